@@ -0,0 +1,112 @@
+//! Embeds the particles in an application of its own: the application owns the window, the device
+//! and the frame, and only hands a view to the renderer after clearing it.
+//!
+//! Run with `cargo run --example embed`.
+
+use std::{sync::Arc, time::Instant};
+
+use particles::{Camera, DepthRange, ParticleRenderer};
+use winit::{
+    event::{Event, WindowEvent},
+    event_loop::{ControlFlow, EventLoop},
+    window::WindowBuilder,
+};
+
+fn main() {
+    let event_loop = EventLoop::new();
+    let window = WindowBuilder::new()
+        .with_title("Embedded particles")
+        .build(&event_loop)
+        .expect("Unable to create the window");
+
+    let instance = wgpu::Instance::default();
+    // # Safety
+    //
+    // The surface is dropped with the event loop's closure, which owns the window too.
+    let surface = unsafe { instance.create_surface(&window) }.expect("Unable to create a surface");
+    let adapter = pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
+        compatible_surface: Some(&surface),
+        ..Default::default()
+    }))
+    .expect("No adapter can present to the window");
+    let (device, queue) = pollster::block_on(adapter.request_device(
+        &wgpu::DeviceDescriptor {
+            label: Some("Embed Device"),
+            features: wgpu::Features::empty(),
+            limits: adapter.limits(),
+        },
+        None,
+    ))
+    .expect("Unable to create a device");
+    let (device, queue) = (Arc::new(device), Arc::new(queue));
+
+    let size = window.inner_size();
+    let mut config = surface
+        .get_default_config(&adapter, size.width, size.height)
+        .expect("The surface isn't supported by the adapter");
+    surface.configure(&device, &config);
+
+    let mut renderer = ParticleRenderer::new(device.clone(), queue.clone(), config.format)
+        .expect("Unable to create the particle renderer");
+    let mut camera = Camera {
+        eye: (0.0, 1.0, 5000.0).into(),
+        target: glam::Vec3::ZERO,
+        up: glam::Vec3::Y,
+        aspect: config.width as f32 / config.height as f32,
+        fovy: 20.0,
+        depth: DepthRange::default(),
+    };
+    let mut last_frame = Instant::now();
+
+    event_loop.run(move |event, _, control_flow| match event {
+        Event::WindowEvent { event, .. } => match event {
+            WindowEvent::CloseRequested => *control_flow = ControlFlow::Exit,
+            WindowEvent::Resized(size) if size.width > 0 && size.height > 0 => {
+                config.width = size.width;
+                config.height = size.height;
+                surface.configure(&device, &config);
+                camera.aspect = size.width as f32 / size.height as f32;
+            }
+            _ => {}
+        },
+        Event::RedrawRequested(_) => {
+            let now = Instant::now();
+            renderer.update((now - last_frame).as_secs_f32());
+            last_frame = now;
+
+            let Ok(frame) = surface.get_current_texture() else {
+                surface.configure(&device, &config);
+                return;
+            };
+            let view = frame
+                .texture
+                .create_view(&wgpu::TextureViewDescriptor::default());
+            let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Embed Encoder"),
+            });
+            // The application's own pass, the particles are drawn over it
+            encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Clear Pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color {
+                            r: 0.02,
+                            g: 0.02,
+                            b: 0.05,
+                            a: 1.0,
+                        }),
+                        store: true,
+                    },
+                })],
+                depth_stencil_attachment: None,
+            });
+            renderer.render(&mut encoder, &view, &camera);
+            queue.submit(Some(encoder.finish()));
+            frame.present();
+        }
+        Event::MainEventsCleared => window.request_redraw(),
+        _ => {}
+    });
+}
@@ -0,0 +1,151 @@
+//! Accessibility options: colors told apart with a color vision deficiency, for the particles and
+//! the heatmap debug view, and a gamma and brightness applied when the frame is blitted to the
+//! surface.
+//!
+//! They're saved with the settings, in an `[accessibility]` table of settings.toml, and changed
+//! from the parameter panel.
+//!
+//! ```toml
+//! [accessibility]
+//! palette = "cividis"
+//! heatmap = "viridis"
+//! gamma = 1.2
+//! brightness = 1.0
+//! ```
+
+use std::fmt::Display;
+
+use bytemuck::{Pod, Zeroable};
+use serde::{Deserialize, Serialize};
+
+use crate::palette::SpawnPalette;
+
+/// Range of the gamma and brightness adjustments
+pub const MIN_ADJUSTMENT: f32 = 0.5;
+pub const MAX_ADJUSTMENT: f32 = 2.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Accessibility {
+    /// Particles spawn with it rather than with the scene's palette
+    pub palette: Option<SafePalette>,
+    pub heatmap: HeatmapColors,
+    #[serde(flatten)]
+    pub display: DisplayAdjustment,
+}
+
+/// Spawn palettes varying in lightness and along blue to yellow, which every form of color vision
+/// deficiency keeps apart.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SafePalette {
+    /// Dark blue to yellow across the box
+    Cividis,
+    /// Blue to orange across the box, the two hues of Okabe and Ito's palette
+    BlueOrange,
+}
+
+impl Display for SafePalette {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SafePalette::Cividis => write!(f, "cividis"),
+            SafePalette::BlueOrange => write!(f, "blue-orange"),
+        }
+    }
+}
+
+impl SafePalette {
+    pub const ALL: [SafePalette; 2] = [SafePalette::Cividis, SafePalette::BlueOrange];
+
+    pub fn spawn_palette(self) -> SpawnPalette {
+        self.to_string()
+            .parse()
+            .expect("safe palettes are built-in palettes")
+    }
+}
+
+/// Color scale of the heatmap debug view.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum HeatmapColors {
+    /// Blue through green and yellow to red, then white
+    #[default]
+    Spectrum,
+    /// Dark purple through blue and green to yellow, even in lightness
+    Viridis,
+    /// Dark blue through gray to yellow, the same with a color vision deficiency
+    Cividis,
+}
+
+impl Display for HeatmapColors {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HeatmapColors::Spectrum => write!(f, "spectrum"),
+            HeatmapColors::Viridis => write!(f, "viridis"),
+            HeatmapColors::Cividis => write!(f, "cividis"),
+        }
+    }
+}
+
+impl HeatmapColors {
+    pub const ALL: [HeatmapColors; 3] = [
+        HeatmapColors::Spectrum,
+        HeatmapColors::Viridis,
+        HeatmapColors::Cividis,
+    ];
+
+    /// Index of the scale in heatmap.wgsl.
+    pub fn index(self) -> u32 {
+        self as u32
+    }
+}
+
+/// Applied to the linear colors of the frame, brightness first.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DisplayAdjustment {
+    /// Above 1 brightens the midtones, below 1 darkens them
+    pub gamma: f32,
+    /// Multiplies the colors
+    pub brightness: f32,
+}
+
+impl Default for DisplayAdjustment {
+    fn default() -> Self {
+        Self {
+            gamma: 1.0,
+            brightness: 1.0,
+        }
+    }
+}
+
+impl DisplayAdjustment {
+    /// Leaves the frame as it is, which then doesn't need a blit.
+    pub fn is_identity(&self) -> bool {
+        *self == Self::default()
+    }
+
+    pub fn clamped(self) -> Self {
+        Self {
+            gamma: self.gamma.clamp(MIN_ADJUSTMENT, MAX_ADJUSTMENT),
+            brightness: self.brightness.clamp(MIN_ADJUSTMENT, MAX_ADJUSTMENT),
+        }
+    }
+
+    pub fn uniform(&self) -> DisplayUniform {
+        DisplayUniform {
+            gamma: self.gamma,
+            brightness: self.brightness,
+            _padding: [0.0; 2],
+        }
+    }
+}
+
+// Must match Display in blit.wgsl
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Pod, Zeroable)]
+pub struct DisplayUniform {
+    gamma: f32,
+    brightness: f32,
+    _padding: [f32; 2],
+}
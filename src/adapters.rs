@@ -0,0 +1,140 @@
+//! Backend and adapter selection, to compare the demo across GPUs and APIs on the same machine.
+
+use std::{fmt::Display, str::FromStr};
+
+use crate::capabilities::GpuCapabilities;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backend {
+    Vulkan,
+    Dx12,
+    Metal,
+    Gl,
+}
+
+impl Backend {
+    pub fn backends(backend: Option<Backend>) -> wgpu::Backends {
+        match backend {
+            None => wgpu::Backends::PRIMARY, // Vulkan, Metal, DX12, WebGPU
+            Some(Backend::Vulkan) => wgpu::Backends::VULKAN,
+            Some(Backend::Dx12) => wgpu::Backends::DX12,
+            Some(Backend::Metal) => wgpu::Backends::METAL,
+            Some(Backend::Gl) => wgpu::Backends::GL,
+        }
+    }
+}
+
+impl Display for Backend {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Backend::Vulkan => write!(f, "vulkan"),
+            Backend::Dx12 => write!(f, "dx12"),
+            Backend::Metal => write!(f, "metal"),
+            Backend::Gl => write!(f, "gl"),
+        }
+    }
+}
+
+impl FromStr for Backend {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "vulkan" => Ok(Backend::Vulkan),
+            "dx12" => Ok(Backend::Dx12),
+            "metal" => Ok(Backend::Metal),
+            "gl" => Ok(Backend::Gl),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Adapter picked with `--adapter`, by its index in `--list-adapters` or by part of its name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AdapterSelector {
+    Index(usize),
+    Name(String),
+}
+
+impl FromStr for AdapterSelector {
+    type Err = std::convert::Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s.parse() {
+            Ok(index) => AdapterSelector::Index(index),
+            Err(_) => AdapterSelector::Name(s.to_lowercase()),
+        })
+    }
+}
+
+impl Display for AdapterSelector {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AdapterSelector::Index(index) => write!(f, "#{index}"),
+            AdapterSelector::Name(name) => write!(f, "`{name}`"),
+        }
+    }
+}
+
+/// Finds the adapter matching `selector` among the adapters of `backends`.
+pub fn select(
+    instance: &wgpu::Instance,
+    backends: wgpu::Backends,
+    selector: &AdapterSelector,
+) -> Option<wgpu::Adapter> {
+    let mut adapters = instance.enumerate_adapters(backends);
+    match selector {
+        AdapterSelector::Index(index) => adapters.nth(*index),
+        AdapterSelector::Name(name) => {
+            adapters.find(|adapter| adapter.get_info().name.to_lowercase().contains(name))
+        }
+    }
+}
+
+/// Prints every adapter of `backends` with the limits that matter for the demo and its features.
+pub fn list(backends: wgpu::Backends) {
+    let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
+        backends,
+        dx12_shader_compiler: Default::default(),
+    });
+    let mut found = false;
+    for (index, adapter) in instance.enumerate_adapters(backends).enumerate() {
+        found = true;
+        let info = adapter.get_info();
+        let limits = adapter.limits();
+        println!(
+            "{index}: {} ({:?}, {:?}, driver: {} {})",
+            info.name, info.backend, info.device_type, info.driver, info.driver_info
+        );
+        println!("    max buffer size: {}", limits.max_buffer_size);
+        println!(
+            "    max storage buffer binding size: {}",
+            limits.max_storage_buffer_binding_size
+        );
+        println!(
+            "    max texture dimension 2d: {}",
+            limits.max_texture_dimension_2d
+        );
+        println!(
+            "    max compute workgroups per dimension: {}",
+            limits.max_compute_workgroups_per_dimension
+        );
+        println!(
+            "    max compute invocations per workgroup: {}",
+            limits.max_compute_invocations_per_workgroup
+        );
+        println!("    features: {:?}", adapter.features());
+        println!(
+            "    downlevel flags: {:?}",
+            adapter.get_downlevel_capabilities().flags
+        );
+        println!("    app features:");
+        for (name, available) in GpuCapabilities::of(&adapter).report() {
+            let status = if available { "on" } else { "off" };
+            println!("        {name}: {status}");
+        }
+    }
+    if !found {
+        println!("No adapters found for {backends:?}");
+    }
+}
@@ -0,0 +1,56 @@
+/// Grows or shrinks the number of simulated particles to hold a target frame time.
+#[derive(Debug)]
+pub struct AdaptiveCount {
+    target_frame_time_ms: f32,
+    active: usize,
+    frames_since_change: usize,
+}
+
+impl AdaptiveCount {
+    // Never go below this many particles
+    const MIN_ACTIVE: usize = 1_000;
+    // Let the frame time average settle on the new count before adjusting again
+    const FRAMES_BETWEEN_CHANGES: usize = 30;
+    // Frame times within this fraction of the target are good enough
+    const TOLERANCE: f32 = 0.05;
+    // Largest change of the particle count in a single step, as a fraction
+    const MAX_STEP: f32 = 0.25;
+
+    pub fn new(target_fps: f32, active: usize) -> Self {
+        Self {
+            target_frame_time_ms: 1000.0 / target_fps,
+            active,
+            frames_since_change: 0,
+        }
+    }
+
+    pub fn active(&self) -> usize {
+        self.active
+    }
+
+    /// Adjusts the particle count from the current frame time average, never going above
+    /// `available`. Returns true if the count changed.
+    pub fn update(&mut self, average_frame_time_ms: f32, available: usize) -> bool {
+        self.frames_since_change += 1;
+        if self.frames_since_change < Self::FRAMES_BETWEEN_CHANGES || average_frame_time_ms <= 0.0 {
+            return false;
+        }
+
+        let ratio = self.target_frame_time_ms / average_frame_time_ms;
+        if (ratio - 1.0).abs() <= Self::TOLERANCE {
+            return false;
+        }
+
+        // Frame time is roughly proportional to the particle count
+        let ratio = ratio.clamp(1.0 - Self::MAX_STEP, 1.0 + Self::MAX_STEP);
+        let active = ((self.active as f32 * ratio) as usize)
+            .clamp(Self::MIN_ACTIVE.min(available), available);
+        if active == self.active {
+            return false;
+        }
+
+        self.active = active;
+        self.frames_since_change = 0;
+        true
+    }
+}
@@ -0,0 +1,118 @@
+//! Custom appearance of the particles: a WGSL function named in the scene's `[shader]` section,
+//! spliced into shader.wgsl in place of the default one.
+//!
+//! The function gets the particle's world position and its appearance, the color after the
+//! gradient and the fade out at the end of its life, and a size of 1, and returns the appearance
+//! it's drawn with. Points ignore the size.
+//!
+//! ```wgsl
+//! fn particle_appearance(position: vec3<f32>, base: Appearance) -> Appearance {
+//!     let size = 1.0 + 0.5 * sin(position.y * 0.01);
+//!     return Appearance(vec4<f32>(base.color.rgb * size, base.color.a), size);
+//! }
+//! ```
+//!
+//! The snippet can use anything shader.wgsl declares before it, like the camera. It is validated
+//! when the particle pipelines are built, the error then names the snippet's file. With `--watch`,
+//! the snippet is reloaded when it changes, and a snippet that doesn't compile leaves the particles
+//! drawn with the previous one.
+
+use std::{
+    path::{Path, PathBuf},
+    time::{Duration, Instant, SystemTime},
+};
+
+// Lines around the default function in shader.wgsl
+const START_MARKER: &str = "// Start of the default appearance";
+const END_MARKER: &str = "// End of the default appearance";
+
+const WATCH_INTERVAL: Duration = Duration::from_millis(500);
+
+#[derive(Debug, thiserror::Error)]
+pub enum AppearanceError {
+    #[error("unable to read the appearance snippet {0}: {1}")]
+    Io(PathBuf, std::io::Error),
+    #[error("invalid appearance snippet {0}:\n{1}")]
+    Invalid(PathBuf, String),
+}
+
+#[derive(Debug, Clone)]
+pub struct Appearance {
+    path: PathBuf,
+    source: String,
+}
+
+impl Appearance {
+    pub fn load(path: &Path) -> Result<Self, AppearanceError> {
+        let source =
+            std::fs::read_to_string(path).map_err(|e| AppearanceError::Io(path.to_owned(), e))?;
+        Ok(Self {
+            path: path.to_owned(),
+            source,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// `shader`, with the default appearance between the markers replaced by the snippet.
+    pub fn splice(&self, shader: &str) -> String {
+        let start = shader
+            .find(START_MARKER)
+            .expect("shader.wgsl has no appearance start marker");
+        let end = shader[start..]
+            .find(END_MARKER)
+            .map(|end| start + end + END_MARKER.len())
+            .expect("shader.wgsl has no appearance end marker");
+        format!("{}{}{}", &shader[..start], self.source, &shader[end..])
+    }
+
+    /// The error of building the shader with the snippet, reported by `message`.
+    pub fn invalid(&self, message: String) -> AppearanceError {
+        AppearanceError::Invalid(self.path.clone(), message)
+    }
+}
+
+/// Polls an appearance snippet for changes.
+pub struct AppearanceWatcher {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+    last_check: Instant,
+}
+
+impl AppearanceWatcher {
+    /// Watches `path`, ignoring its current contents until it changes.
+    pub fn new(path: PathBuf) -> Self {
+        let modified = modified_time(&path);
+        Self {
+            path,
+            modified,
+            last_check: Instant::now(),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns the snippet reloaded from the file if it changed since the last call.
+    pub fn poll(&mut self) -> Option<Result<Appearance, AppearanceError>> {
+        let now = Instant::now();
+        if now - self.last_check < WATCH_INTERVAL {
+            return None;
+        }
+        self.last_check = now;
+
+        let modified = modified_time(&self.path);
+        if modified == self.modified {
+            return None;
+        }
+        self.modified = modified;
+        Some(Appearance::load(&self.path))
+    }
+}
+
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
@@ -0,0 +1,142 @@
+use std::ops::Range;
+
+/// Keeps track of which slots of the instance buffers hold live particles.
+///
+/// Deleting particles leaves holes behind, and every contiguous live range costs one draw call.
+/// [`InstanceArena::defragment`] produces the moves that pack the live ranges back together.
+#[derive(Debug)]
+pub struct InstanceArena {
+    capacity: usize,
+    // Sorted, non-overlapping and non-touching
+    live: Vec<Range<usize>>,
+}
+
+/// A block of `len` instances that has to be moved from `from` to `to` to compact the arena.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Move {
+    pub from: usize,
+    pub to: usize,
+    pub len: usize,
+}
+
+/// The moves needed to compact the arena, in the order they have to be applied.
+#[derive(Debug, Default)]
+pub struct RemapTable {
+    pub moves: Vec<Move>,
+    pub live_count: usize,
+}
+
+impl RemapTable {
+    /// Compacts `data` in place and drops everything past the live instances.
+    pub fn apply<T>(&self, data: &mut Vec<T>) {
+        // Blocks only ever move towards the start, so going in order never overwrites a block
+        // that hasn't been moved yet. The destination only holds dead instances, swapping them
+        // out of the way is enough.
+        for m in &self.moves {
+            for i in 0..m.len {
+                data.swap(m.from + i, m.to + i);
+            }
+        }
+        data.truncate(self.live_count);
+    }
+}
+
+impl InstanceArena {
+    pub fn new(len: usize) -> Self {
+        Self {
+            capacity: len,
+            live: (len > 0).then_some(0..len).into_iter().collect(),
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// End of the last live range, the instances past it are all deleted.
+    pub fn end(&self) -> usize {
+        self.live.last().map_or(0, |range| range.end)
+    }
+
+    pub fn live_ranges(&self) -> &[Range<usize>] {
+        &self.live
+    }
+
+    /// The live ranges holding the first `count` live instances.
+    pub fn first_live(&self, count: usize) -> Vec<Range<usize>> {
+        let mut remaining = count;
+        let mut ranges = vec![];
+        for range in &self.live {
+            if remaining == 0 {
+                break;
+            }
+            let len = range.len().min(remaining);
+            ranges.push(range.start..range.start + len);
+            remaining -= len;
+        }
+        ranges
+    }
+
+    pub fn live_count(&self) -> usize {
+        self.live.iter().map(|range| range.len()).sum()
+    }
+
+    /// Marks the instances in `range` as live, growing the arena to hold them if they're past its
+    /// end.
+    pub fn add(&mut self, range: Range<usize>) {
+        if range.is_empty() {
+            return;
+        }
+        self.capacity = self.capacity.max(range.end);
+        self.live.push(range);
+        self.live.sort_by_key(|range| range.start);
+        let mut live: Vec<Range<usize>> = Vec::with_capacity(self.live.len());
+        for range in self.live.drain(..) {
+            match live.last_mut() {
+                Some(last) if range.start <= last.end => last.end = last.end.max(range.end),
+                _ => live.push(range),
+            }
+        }
+        self.live = live;
+    }
+
+    /// Marks the instances in `range` as deleted.
+    pub fn free(&mut self, range: Range<usize>) {
+        let mut live = Vec::with_capacity(self.live.len() + 1);
+        for existing in self.live.drain(..) {
+            if existing.end <= range.start || existing.start >= range.end {
+                live.push(existing);
+                continue;
+            }
+            if existing.start < range.start {
+                live.push(existing.start..range.start);
+            }
+            if existing.end > range.end {
+                live.push(range.end..existing.end);
+            }
+        }
+        self.live = live;
+    }
+
+    /// Packs every live range at the start of the arena and returns how the data has to move.
+    pub fn defragment(&mut self) -> RemapTable {
+        let mut moves = vec![];
+        let mut next = 0;
+        for range in &self.live {
+            if range.start != next {
+                moves.push(Move {
+                    from: range.start,
+                    to: next,
+                    len: range.len(),
+                });
+            }
+            next += range.len();
+        }
+
+        self.live = (next > 0).then_some(0..next).into_iter().collect();
+        RemapTable {
+            moves,
+            live_count: next,
+        }
+    }
+}
@@ -0,0 +1,181 @@
+//! Audio visualizer mode, from `--audio`: the default input device is listened to, and the energy
+//! of its bass, mids and treble drives the simulation. The bass pulls the particles towards the
+//! attractor, the mids speed them up and the treble brightens them.
+//!
+//! Each band is scaled by its own recent peak, so quiet and loud inputs move the particles as much,
+//! then falls back slowly after each beat instead of flickering.
+
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+};
+
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use rustfft::{num_complex::Complex, Fft, FftPlanner};
+
+use crate::sim_params::SimParams;
+
+// Samples transformed each frame, about 20ms at 48kHz
+const WINDOW: usize = 1024;
+// Frequencies of the bass, mids and treble, in Hz
+const BANDS: [(f32, f32); 3] = [(20.0, 250.0), (250.0, 2000.0), (2000.0, 8000.0)];
+// Fraction of a band's level kept after a second without sound
+const RELEASE: f32 = 0.05;
+// Fraction of a band's peak kept after a second, the gain adapts to the input in a few seconds
+const PEAK_DECAY: f32 = 0.7;
+// Below this energy the input is silent, rather than noise scaled up to full levels
+const NOISE_FLOOR: f32 = 1e-4;
+// Multipliers of the attractor strength, speed and brightness at full levels
+const ATTRACTION_GAIN: f32 = 4.0;
+const SPEED_GAIN: f32 = 2.0;
+const BRIGHTNESS_GAIN: f32 = 1.0;
+
+#[derive(Debug, thiserror::Error)]
+pub enum AudioError {
+    #[error("no audio input device")]
+    NoDevice,
+    #[error("unable to configure the audio input: {0}")]
+    Config(#[from] cpal::DefaultStreamConfigError),
+    #[error("unable to open the audio input: {0}")]
+    Build(#[from] cpal::BuildStreamError),
+    #[error("unable to start the audio input: {0}")]
+    Play(#[from] cpal::PlayStreamError),
+    #[error("unsupported audio sample format {0}")]
+    SampleFormat(cpal::SampleFormat),
+}
+
+/// Bass, mids and treble, from 0 when silent to 1 at their recent peak.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct AudioLevels {
+    pub bass: f32,
+    pub mid: f32,
+    pub treble: f32,
+}
+
+impl AudioLevels {
+    /// Scales the attractor strength and speed of `params` by the levels.
+    pub fn modulate(&self, params: &mut SimParams) {
+        params.attractor_strength *= 1.0 + self.bass * ATTRACTION_GAIN;
+        params.speed_multiplier *= 1.0 + self.mid * SPEED_GAIN;
+    }
+
+    /// Multiplier of the particle colors.
+    pub fn brightness(&self) -> f32 {
+        1.0 + self.treble * BRIGHTNESS_GAIN
+    }
+}
+
+pub struct AudioInput {
+    // Captures until dropped
+    _stream: cpal::Stream,
+    // The last WINDOW mono samples, written by the capture thread
+    samples: Arc<Mutex<VecDeque<f32>>>,
+    sample_rate: f32,
+    fft: Arc<dyn Fft<f32>>,
+    buffer: Vec<Complex<f32>>,
+    peaks: [f32; 3],
+    levels: AudioLevels,
+}
+
+impl AudioInput {
+    /// Starts listening to the default input device.
+    pub fn new() -> Result<Self, AudioError> {
+        let device = cpal::default_host()
+            .default_input_device()
+            .ok_or(AudioError::NoDevice)?;
+        let config = device.default_input_config()?;
+        log::info!(
+            "Listening to {} at {}Hz",
+            device
+                .name()
+                .unwrap_or_else(|_| "the audio input".to_owned()),
+            config.sample_rate().0
+        );
+        let samples = Arc::new(Mutex::new(VecDeque::with_capacity(WINDOW)));
+        let stream = match config.sample_format() {
+            cpal::SampleFormat::F32 => build_stream::<f32>(&device, &config, samples.clone()),
+            cpal::SampleFormat::I16 => build_stream::<i16>(&device, &config, samples.clone()),
+            cpal::SampleFormat::U16 => build_stream::<u16>(&device, &config, samples.clone()),
+            format => return Err(AudioError::SampleFormat(format)),
+        }?;
+        stream.play()?;
+
+        Ok(Self {
+            _stream: stream,
+            samples,
+            sample_rate: config.sample_rate().0 as f32,
+            fft: FftPlanner::new().plan_fft_forward(WINDOW),
+            buffer: vec![Complex::default(); WINDOW],
+            peaks: [NOISE_FLOOR; 3],
+            levels: AudioLevels::default(),
+        })
+    }
+
+    /// Levels of the last samples captured, `dt` seconds after the previous call.
+    pub fn poll(&mut self, dt: f32) -> AudioLevels {
+        {
+            let samples = self.samples.lock().unwrap();
+            let offset = WINDOW - samples.len();
+            for (index, value) in self.buffer.iter_mut().enumerate() {
+                // Hann window, the samples before the first ones captured are silent
+                let sample = index
+                    .checked_sub(offset)
+                    .map_or(0.0, |index| samples[index]);
+                let hann = 0.5 - 0.5 * (std::f32::consts::TAU * index as f32 / WINDOW as f32).cos();
+                *value = Complex::new(sample * hann, 0.0);
+            }
+        }
+        self.fft.process(&mut self.buffer);
+
+        let bin_width = self.sample_rate / WINDOW as f32;
+        let energies = BANDS.map(|(low, high)| {
+            let bins = (low / bin_width).ceil() as usize..(high / bin_width).ceil() as usize;
+            let bins = bins.start.min(WINDOW / 2)..bins.end.min(WINDOW / 2);
+            let count = bins.len().max(1) as f32;
+            self.buffer[bins].iter().map(Complex::norm_sqr).sum::<f32>() / count
+        });
+
+        let release = RELEASE.powf(dt);
+        let peak_decay = PEAK_DECAY.powf(dt);
+        let mut levels = [self.levels.bass, self.levels.mid, self.levels.treble];
+        for ((level, peak), energy) in levels.iter_mut().zip(&mut self.peaks).zip(energies) {
+            *peak = (*peak * peak_decay).max(energy).max(NOISE_FLOOR);
+            *level = (*level * release).max(energy / *peak);
+        }
+        let [bass, mid, treble] = levels;
+        self.levels = AudioLevels { bass, mid, treble };
+        self.levels
+    }
+}
+
+/// Captures `device` into `samples`, mixed down to mono.
+fn build_stream<T>(
+    device: &cpal::Device,
+    config: &cpal::SupportedStreamConfig,
+    samples: Arc<Mutex<VecDeque<f32>>>,
+) -> Result<cpal::Stream, cpal::BuildStreamError>
+where
+    T: cpal::SizedSample,
+    f32: cpal::FromSample<T>,
+{
+    let channels = config.channels().max(1) as usize;
+    device.build_input_stream(
+        &config.config(),
+        move |data: &[T], _: &cpal::InputCallbackInfo| {
+            let mut samples = samples.lock().unwrap();
+            for frame in data.chunks(channels) {
+                let mono = frame
+                    .iter()
+                    .map(|&sample| cpal::Sample::to_sample::<f32>(sample))
+                    .sum::<f32>()
+                    / frame.len() as f32;
+                if samples.len() == WINDOW {
+                    samples.pop_front();
+                }
+                samples.push_back(mono);
+            }
+        },
+        |e| log::warn!("Audio input: {e}"),
+        None,
+    )
+}
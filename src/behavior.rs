@@ -0,0 +1,230 @@
+//! Custom per-particle update logic, applied after every step of the simulation.
+//!
+//! The CPU backend runs [`Behavior`]s on the particles in parallel. The compute kernel can't call
+//! Rust, so the GPU backend runs WGSL functions registered with [`Behaviors::add_wgsl`] instead:
+//!
+//! ```wgsl
+//! fn name(index: u32, position: vec3<f32>, speed: ptr<function, vec3<f32>>, time: f32, dt: f32) -> vec3<f32>
+//! ```
+//!
+//! taking the same arguments as [`Behavior::update`]. Scenes add the built-in ones in
+//! `[behavior]` sections, which register both.
+
+use std::{f32::consts::TAU, fmt::Write, str::FromStr, sync::Arc};
+
+use glam::Vec3;
+
+/// Updates one particle after each step.
+pub trait Behavior: Send + Sync {
+    /// Returns the new position of particle `index`, and may change its `speed`. `time` is the
+    /// simulation time in seconds and `dt` the step that just ran.
+    fn update(&self, index: usize, position: Vec3, speed: &mut Vec3, time: f32, dt: f32) -> Vec3;
+}
+
+/// The behaviors applied to a particle system, in order.
+#[derive(Clone, Default)]
+pub struct Behaviors {
+    cpu: Vec<Arc<dyn Behavior>>,
+    // Name and source of the WGSL functions the compute kernel calls
+    wgsl: Vec<(String, String)>,
+}
+
+impl Behaviors {
+    /// The built-in `behaviors`, on both backends.
+    pub fn new(behaviors: &[BuiltinBehavior]) -> Self {
+        let mut result = Self::default();
+        for behavior in behaviors {
+            let name = format!("behavior_{}", result.wgsl.len());
+            let source = behavior.wgsl(&name);
+            result.add(*behavior);
+            result.add_wgsl(&name, &source);
+        }
+        result
+    }
+
+    /// Applies `behavior` on the CPU backend.
+    pub fn add(&mut self, behavior: impl Behavior + 'static) {
+        self.cpu.push(Arc::new(behavior));
+    }
+
+    /// Calls the WGSL function `name` defined in `source` from the compute kernel. Names must be
+    /// unique and not clash with the kernel's.
+    pub fn add_wgsl(&mut self, name: &str, source: &str) {
+        self.wgsl.push((name.to_owned(), source.to_owned()));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cpu.is_empty()
+    }
+
+    /// Runs every CPU behavior on particle `index`, returning its new position.
+    pub fn update(
+        &self,
+        index: usize,
+        position: Vec3,
+        speed: &mut Vec3,
+        time: f32,
+        dt: f32,
+    ) -> Vec3 {
+        self.cpu.iter().fold(position, |position, behavior| {
+            behavior.update(index, position, speed, time, dt)
+        })
+    }
+
+    /// The registered functions and `apply_behaviors`, which compute_kernel.wgsl calls after
+    /// each step.
+    pub fn wgsl(&self) -> String {
+        let mut source = String::new();
+        for (_, function) in &self.wgsl {
+            source.push_str(function);
+            source.push('\n');
+        }
+        source.push_str(
+            "fn apply_behaviors(index: u32, position: vec3<f32>, speed: ptr<function, vec3<f32>>, \
+             time: f32, dt: f32) -> vec3<f32> {\n    var moved = position;\n",
+        );
+        for (name, _) in &self.wgsl {
+            let _ = writeln!(source, "    moved = {name}(index, moved, speed, time, dt);");
+        }
+        source.push_str("    return moved;\n}\n");
+        source
+    }
+}
+
+/// Behaviors scenes can add without code.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BuiltinBehavior {
+    /// Sways the particles back and forth along `axis`, each with its own phase
+    Oscillation {
+        axis: Vec3,
+        /// Peak speed along the axis
+        amplitude: f32,
+        /// Swings per second
+        frequency: f32,
+    },
+    /// Turns the particles around `axis` through the origin while they climb along it
+    Spiral {
+        axis: Vec3,
+        /// Radians per unit of step
+        angular_speed: f32,
+        /// Distance along the axis per unit of step
+        climb: f32,
+    },
+}
+
+impl FromStr for BuiltinBehavior {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "oscillation" => Ok(BuiltinBehavior::Oscillation {
+                axis: Vec3::Y,
+                amplitude: 1.0,
+                frequency: 0.5,
+            }),
+            "spiral" => Ok(BuiltinBehavior::Spiral {
+                axis: Vec3::Y,
+                angular_speed: 0.01,
+                climb: 0.0,
+            }),
+            _ => Err(()),
+        }
+    }
+}
+
+impl BuiltinBehavior {
+    /// Sets the vector parameter `name`. Returns false if the behavior doesn't have it.
+    pub fn set_vector(&mut self, name: &str, value: Vec3) -> bool {
+        match (self, name) {
+            (
+                BuiltinBehavior::Oscillation { axis, .. } | BuiltinBehavior::Spiral { axis, .. },
+                "axis",
+            ) if value != Vec3::ZERO => *axis = value.normalize(),
+            _ => return false,
+        }
+        true
+    }
+
+    /// Sets the scalar parameter `name`. Returns false if the behavior doesn't have it.
+    pub fn set_scalar(&mut self, name: &str, value: f32) -> bool {
+        match (self, name) {
+            (BuiltinBehavior::Oscillation { amplitude, .. }, "amplitude") => *amplitude = value,
+            (BuiltinBehavior::Oscillation { frequency, .. }, "frequency") => *frequency = value,
+            (BuiltinBehavior::Spiral { angular_speed, .. }, "angular_speed") => {
+                *angular_speed = value
+            }
+            (BuiltinBehavior::Spiral { climb, .. }, "climb") => *climb = value,
+            _ => return false,
+        }
+        true
+    }
+
+    /// The WGSL function `name` doing the same as [`Behavior::update`].
+    fn wgsl(&self, name: &str) -> String {
+        let signature = format!(
+            "fn {name}(index: u32, position: vec3<f32>, speed: ptr<function, vec3<f32>>, \
+             time: f32, dt: f32) -> vec3<f32>"
+        );
+        let vec3 = |v: Vec3| format!("vec3<f32>({:?}, {:?}, {:?})", v.x, v.y, v.z);
+        match *self {
+            BuiltinBehavior::Oscillation {
+                axis,
+                amplitude,
+                frequency,
+            } => format!(
+                "{signature} {{\n    \
+                     let phase = fract(f32(index) * {GOLDEN_RATIO_FRACTION:?}) * {TAU:?};\n    \
+                     let swing = cos(time * {:?} + phase);\n    \
+                     return position + {} * {amplitude:?} * swing * dt;\n\
+                 }}\n",
+                TAU * frequency,
+                vec3(axis),
+            ),
+            BuiltinBehavior::Spiral {
+                axis,
+                angular_speed,
+                climb,
+            } => format!(
+                "{signature} {{\n    \
+                     let axis = {};\n    \
+                     let angle = {angular_speed:?} * dt;\n    \
+                     let turned = position * cos(angle) + cross(axis, position) * sin(angle)\n        \
+                         + axis * dot(axis, position) * (1.0 - cos(angle));\n    \
+                     return turned + axis * {climb:?} * dt;\n\
+                 }}\n",
+                vec3(axis),
+            ),
+        }
+    }
+}
+
+// Spreads the phases of consecutive particles evenly
+const GOLDEN_RATIO_FRACTION: f32 = 0.618_034;
+
+impl Behavior for BuiltinBehavior {
+    fn update(&self, index: usize, position: Vec3, _speed: &mut Vec3, time: f32, dt: f32) -> Vec3 {
+        match *self {
+            BuiltinBehavior::Oscillation {
+                axis,
+                amplitude,
+                frequency,
+            } => {
+                let phase = (index as f32 * GOLDEN_RATIO_FRACTION).fract() * TAU;
+                let swing = (time * TAU * frequency + phase).cos();
+                position + axis * amplitude * swing * dt
+            }
+            BuiltinBehavior::Spiral {
+                axis,
+                angular_speed,
+                climb,
+            } => {
+                // Rodrigues' rotation, as in the WGSL
+                let angle = angular_speed * dt;
+                let turned = position * angle.cos()
+                    + axis.cross(position) * angle.sin()
+                    + axis * axis.dot(position) * (1.0 - angle.cos());
+                turned + axis * climb * dt
+            }
+        }
+    }
+}
@@ -0,0 +1,247 @@
+//! Frame times of `bench` runs, reported once the last frame is rendered, after the time from
+//! startup to the first frame to compare builds such as the minimal profile. With `--cpu-sim`, the
+//! time spent moving the particles on the CPU is reported too, to compare the paths.
+//!
+//! With `--workgroup-size auto`, the frames are timed once per workgroup size of the compute
+//! passes and the fastest one is reported. With `--overlap`, they are timed without the overlap,
+//! then with it, and the GPU timestamps of the metrics feature measure how much shorter the passes
+//! of a frame get once the GPU overlaps them.
+
+use std::time::{Duration, Instant};
+
+// Frames rendered by `bench` without --frames
+pub const DEFAULT_FRAMES: u32 = 600;
+// The first frames include pipeline compilation and uploads, they aren't timed
+const WARMUP_FRAMES: usize = 10;
+// Workgroup sizes timed by `--workgroup-size auto`, in order
+const TUNED_WORKGROUP_SIZES: [u32; 4] = [32, 64, 128, 256];
+
+/// GPU time of the passes of a frame, timed with the `metrics` feature.
+#[derive(Debug, Clone, Copy)]
+pub struct GpuFrameTime {
+    /// Sum of the times of the passes
+    pub busy: Duration,
+    /// From the start of the first pass to the end of the last
+    pub span: Duration,
+}
+
+pub struct Bench {
+    frames: u32,
+    // From startup to the first frame, once rendered
+    startup: Option<Duration>,
+    last_frame: Option<Instant>,
+    frame_times: Vec<Duration>,
+    simulation_times: Vec<Duration>,
+    gpu_times: Vec<GpuFrameTime>,
+    // Sizes left to time after the current one, none unless tuning
+    workgroup_sizes: Vec<u32>,
+    // Average frame time of each size timed so far
+    tuned: Vec<(u32, Duration)>,
+    // Whether the overlap is timed once the current frames are, none unless comparing it
+    overlap_next: Option<bool>,
+    // Average frame and GPU times without the overlap, once timed
+    without_overlap: Option<(Duration, Option<GpuFrameTime>)>,
+}
+
+impl Bench {
+    /// Times `frames` frames after the warmup.
+    pub fn new(frames: u32) -> Self {
+        Self {
+            frames,
+            startup: None,
+            last_frame: None,
+            frame_times: Vec::with_capacity(frames as usize),
+            simulation_times: Vec::with_capacity(frames as usize),
+            gpu_times: Vec::with_capacity(frames as usize),
+            workgroup_sizes: vec![],
+            tuned: vec![],
+            overlap_next: None,
+            without_overlap: None,
+        }
+    }
+
+    /// Times `frames` frames for each workgroup size in turn. Returns the bench and the first size.
+    pub fn tuning(frames: u32) -> (Self, u32) {
+        let [first, rest @ ..] = TUNED_WORKGROUP_SIZES;
+        let mut bench = Self::new(frames);
+        bench.workgroup_sizes = rest.iter().rev().copied().collect();
+        bench.tuned.push((first, Duration::ZERO));
+        (bench, first)
+    }
+
+    /// Times `frames` frames without the overlap, then `frames` with it.
+    pub fn comparing_overlap(frames: u32) -> Self {
+        let mut bench = Self::new(frames);
+        bench.overlap_next = Some(true);
+        bench
+    }
+
+    /// Ends the timing without the overlap once [`Bench::frame`] returned true. Returns true if
+    /// the overlap is timed next, false once both were.
+    pub fn next_overlap(&mut self) -> bool {
+        if self.overlap_next.take() != Some(true) {
+            return false;
+        }
+        let timed = self.timed_frames();
+        let average = timed.iter().sum::<Duration>() / timed.len().max(1) as u32;
+        self.without_overlap = Some((average, self.gpu_average()));
+        // The snapshot is allocated and copied on the next frames, warmed up again
+        self.last_frame = None;
+        self.frame_times.clear();
+        self.simulation_times.clear();
+        self.gpu_times.clear();
+        true
+    }
+
+    /// Ends the timing of the current workgroup size once [`Bench::frame`] returned true. Returns
+    /// the next size to time, none once every size was.
+    pub fn next_workgroup_size(&mut self) -> Option<u32> {
+        let timed = self.timed_frames();
+        if let Some((_, average)) = self.tuned.last_mut() {
+            if !timed.is_empty() {
+                *average = timed.iter().sum::<Duration>() / timed.len() as u32;
+            }
+        }
+        let next = self.workgroup_sizes.pop()?;
+        // The new pipelines are compiled on the next frames, warmed up again
+        self.last_frame = None;
+        self.frame_times.clear();
+        self.simulation_times.clear();
+        self.tuned.push((next, Duration::ZERO));
+        Some(next)
+    }
+
+    /// Records the time from startup to the first frame.
+    pub fn first_frame(&mut self, startup: Duration) {
+        self.startup = Some(startup);
+    }
+
+    /// Records a frame rendered at `now`, after moving the particles on the CPU for
+    /// `simulation_time`, with the GPU times read back during it. Returns true once every frame
+    /// was.
+    pub fn frame(
+        &mut self,
+        now: Instant,
+        simulation_time: Option<Duration>,
+        gpu_time: Option<GpuFrameTime>,
+    ) -> bool {
+        if let Some(last_frame) = self.last_frame.replace(now) {
+            self.frame_times.push(now - last_frame);
+            if self.frame_times.len() > WARMUP_FRAMES {
+                self.simulation_times.extend(simulation_time);
+                self.gpu_times.extend(gpu_time);
+            }
+        }
+        self.frame_times.len() >= WARMUP_FRAMES + self.frames as usize
+    }
+
+    /// Prints the startup time, then the average, median and 99th percentile frame times, or the
+    /// average of each workgroup size when tuning. The GPU times follow, compared to those without
+    /// the overlap if it was.
+    pub fn report(&self) {
+        if let Some(startup) = self.startup {
+            println!("Startup to first frame: {startup:.1?}");
+        }
+        if !self.tuned.is_empty() {
+            self.report_tuning();
+            return;
+        }
+        let mut frame_times = self.timed_frames();
+        if frame_times.is_empty() {
+            println!("No frames rendered");
+            return;
+        }
+        frame_times.sort_unstable();
+        let total = frame_times.iter().sum::<Duration>();
+        let average = total / frame_times.len() as u32;
+        let percentile =
+            |p: f64| frame_times[((frame_times.len() - 1) as f64 * p).round() as usize];
+        println!("Frames: {}", frame_times.len());
+        println!(
+            "Average: {:.2?} ({:.1} fps)",
+            average,
+            frame_times.len() as f64 / total.as_secs_f64()
+        );
+        println!("Median: {:.2?}", percentile(0.5));
+        println!("99th percentile: {:.2?}", percentile(0.99));
+        println!("Worst: {:.2?}", frame_times[frame_times.len() - 1]);
+
+        if !self.simulation_times.is_empty() {
+            let mut simulation_times = self.simulation_times.clone();
+            simulation_times.sort_unstable();
+            println!(
+                "CPU simulation average: {:.2?}",
+                simulation_times.iter().sum::<Duration>() / simulation_times.len() as u32
+            );
+            println!(
+                "CPU simulation median: {:.2?}",
+                simulation_times[simulation_times.len() / 2]
+            );
+        }
+
+        let gpu_average = self.gpu_average();
+        if let Some(gpu_average) = gpu_average {
+            println!("GPU passes average: {:.2?}", gpu_average.busy);
+            println!("GPU frame span average: {:.2?}", gpu_average.span);
+        }
+        if let Some((frame_average, gpu_without)) = self.without_overlap {
+            println!("Without overlap: {frame_average:.2?} average");
+            match (gpu_without, gpu_average) {
+                (Some(without), Some(with)) => {
+                    println!("Without overlap GPU frame span: {:.2?}", without.span);
+                    let saved = without.span.as_secs_f64() - with.span.as_secs_f64();
+                    println!("Overlap saves {:.3} ms of GPU frame span", saved * 1000.0);
+                }
+                _ => println!(
+                    "No GPU times to compare, they need the metrics feature and timestamp queries"
+                ),
+            }
+        }
+    }
+
+    /// Average GPU times of the timed frames, none without any read back.
+    fn gpu_average(&self) -> Option<GpuFrameTime> {
+        if self.gpu_times.is_empty() {
+            return None;
+        }
+        let count = self.gpu_times.len() as u32;
+        Some(GpuFrameTime {
+            busy: self
+                .gpu_times
+                .iter()
+                .map(|time| time.busy)
+                .sum::<Duration>()
+                / count,
+            span: self
+                .gpu_times
+                .iter()
+                .map(|time| time.span)
+                .sum::<Duration>()
+                / count,
+        })
+    }
+
+    fn report_tuning(&self) {
+        // Sizes cut short by closing the window have no average
+        let timed = self
+            .tuned
+            .iter()
+            .filter(|(_, average)| !average.is_zero())
+            .collect::<Vec<_>>();
+        for (workgroup_size, average) in &timed {
+            println!("Workgroup size {workgroup_size}: {average:.2?} average");
+        }
+        match timed.iter().min_by_key(|(_, average)| *average) {
+            Some((workgroup_size, _)) => println!("Fastest workgroup size: {workgroup_size}"),
+            None => println!("No frames rendered"),
+        }
+    }
+
+    fn timed_frames(&self) -> Vec<Duration> {
+        self.frame_times
+            .iter()
+            .skip(WARMUP_FRAMES)
+            .copied()
+            .collect()
+    }
+}
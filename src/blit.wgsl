@@ -0,0 +1,38 @@
+// Stretches the offscreen render target over the whole surface, adjusting its gamma and brightness
+
+@group(0) @binding(0)
+var source: texture_2d<f32>;
+@group(0) @binding(1)
+var source_sampler: sampler;
+
+// Must match DisplayUniform in accessibility.rs
+struct Display {
+    gamma: f32,
+    brightness: f32,
+    _padding: vec2<f32>,
+};
+@group(0) @binding(2)
+var<uniform> display: Display;
+
+fn adjust(color: vec4<f32>) -> vec4<f32> {
+    let rgb = pow(max(color.rgb * display.brightness, vec3<f32>(0.0)), vec3<f32>(1.0 / display.gamma));
+    return vec4<f32>(rgb, color.a);
+}
+
+@fragment
+fn fs_main(in: FullscreenOutput) -> @location(0) vec4<f32> {
+    return adjust(textureSample(source, source_sampler, in.uv));
+}
+
+// Surfaces without an sRGB format take the gamma encoded colors as they are
+fn linear_to_srgb(linear: vec3<f32>) -> vec3<f32> {
+    let low = linear * 12.92;
+    let high = 1.055 * pow(linear, vec3<f32>(1.0 / 2.4)) - 0.055;
+    return select(high, low, linear <= vec3<f32>(0.0031308));
+}
+
+@fragment
+fn fs_encode_srgb(in: FullscreenOutput) -> @location(0) vec4<f32> {
+    let color = adjust(textureSample(source, source_sampler, in.uv));
+    return vec4<f32>(linear_to_srgb(clamp(color.rgb, vec3<f32>(0.0), vec3<f32>(1.0))), color.a);
+}
@@ -0,0 +1,325 @@
+//! Flocking simulation mode: before every step, each boid steers away from its close neighbors,
+//! towards their average speed and towards their center. The step then moves the boids as usual,
+//! so turbulence, bounds and boundaries still apply.
+//!
+//! On the GPU the neighbors come from the [`SpatialHash`], on the CPU from a grid of the same cell
+//! size. The GPU kernel can either read the neighbors of every boid from storage, or steer the boids
+//! of a cell together, loading their neighbors into workgroup memory a tile at a time.
+
+use std::collections::HashMap;
+
+use bytemuck::{Pod, Zeroable};
+use glam::{IVec3, Vec3};
+use rayon::prelude::{IntoParallelIterator, ParallelIterator};
+use wgpu::util::DeviceExt;
+
+use crate::{
+    shader_check,
+    spatial_hash::{SpatialHash, NEIGHBORS_WGSL},
+    speed_layout::SpeedLayout,
+};
+
+// Must match `@workgroup_size` of main in boids.wgsl
+const WORKGROUP_SIZE: u32 = 64;
+/// Boids steered together by the tiled kernel, 0 for the untiled one
+pub const DEFAULT_TILE_SIZE: u32 = 64;
+// Workgroup memory taken by each boid of a tile: index, position and speed
+const TILE_BYTES_PER_BOID: u32 = 4 + 16 + 16;
+// Workgroup memory of the tiled kernel besides the tiles
+const TILE_BYTES_BASE: u32 = 64;
+
+/// Flocking weights and limits, shared with boids.wgsl.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Pod, Zeroable)]
+pub struct BoidsParams {
+    /// Boids only see the others closer than this
+    pub radius: f32,
+    pub separation: f32,
+    pub alignment: f32,
+    pub cohesion: f32,
+    pub min_speed: f32,
+    pub max_speed: f32,
+    /// Length of a simulation step, in frames, set every step
+    pub dt: f32,
+    /// Number of boids, set every step
+    pub count: u32,
+}
+
+impl Default for BoidsParams {
+    fn default() -> Self {
+        Self {
+            radius: 5.0,
+            separation: 0.05,
+            alignment: 0.05,
+            cohesion: 0.005,
+            min_speed: 0.1,
+            max_speed: 0.5,
+            dt: 1.0,
+            count: 0,
+        }
+    }
+}
+
+impl BoidsParams {
+    /// Returns the speeds the boids steer to. Must match `main` in boids.wgsl.
+    pub fn steer(&self, positions: &[Vec3], speeds: &[Vec3]) -> Vec<Vec3> {
+        let cell_of = |position: Vec3| (position / self.radius).floor().as_ivec3();
+        let mut cells = HashMap::<IVec3, Vec<usize>>::new();
+        for (index, &position) in positions.iter().enumerate() {
+            cells.entry(cell_of(position)).or_default().push(index);
+        }
+
+        (0..positions.len())
+            .into_par_iter()
+            .map(|index| {
+                let (position, speed) = (positions[index], speeds[index]);
+                let (mut separation, mut average_speed, mut center) =
+                    (Vec3::ZERO, Vec3::ZERO, Vec3::ZERO);
+                let mut neighbors = 0;
+                let cell = cell_of(position);
+                let offsets = (0..27).map(|i| IVec3::new(i % 3, i / 3 % 3, i / 9) - 1);
+                for other in offsets
+                    .filter_map(|offset| cells.get(&(cell + offset)))
+                    .flatten()
+                    .copied()
+                {
+                    let offset = positions[other] - position;
+                    let distance_squared = offset.length_squared();
+                    if other == index
+                        || distance_squared >= self.radius * self.radius
+                        || distance_squared == 0.0
+                    {
+                        continue;
+                    }
+                    separation -= offset / distance_squared;
+                    average_speed += speeds[other];
+                    center += offset;
+                    neighbors += 1;
+                }
+
+                let mut steered = speed;
+                if neighbors > 0 {
+                    let n = neighbors as f32;
+                    steered += (separation * self.separation
+                        + (average_speed / n - speed) * self.alignment
+                        + center / n * self.cohesion)
+                        * self.dt;
+                }
+                let current = steered.length();
+                if current > 0.0 {
+                    steered *= current.clamp(self.min_speed, self.max_speed) / current;
+                }
+                steered
+            })
+            .collect()
+    }
+}
+
+/// Steers the boids on the GPU, writing their new speeds over the old ones.
+pub struct Boids {
+    capacity: usize,
+    // Of the speeds, and of the steered speeds
+    speed_layout: SpeedLayout,
+    spatial_hash: SpatialHash,
+    params_buffer: wgpu::Buffer,
+    steered_speeds_buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+    pipeline: wgpu::ComputePipeline,
+    tiled: bool,
+}
+
+impl Boids {
+    /// Steers the first of the `capacity` boids of `position_buffer` and `cpu_data_buffer`, laid
+    /// out in `speed_layout`, by tiles of `tile_size` if not 0. Returns `None` if the device can't
+    /// bind them in one piece.
+    pub fn new(
+        device: &wgpu::Device,
+        capacity: usize,
+        params: &BoidsParams,
+        tile_size: u32,
+        position_buffer: &wgpu::Buffer,
+        cpu_data_buffer: &wgpu::Buffer,
+        speed_layout: SpeedLayout,
+    ) -> Option<Self> {
+        let spatial_hash = SpatialHash::new(device, capacity, params.radius, position_buffer)?;
+
+        let params_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Boids Params Buffer"),
+            contents: bytemuck::cast_slice(&[*params]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let steered_speeds_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Steered Speeds Buffer"),
+            size: (capacity.max(1) * speed_layout.stride()) as u64,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+
+        let storage_entry = |binding, read_only| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let bind_group_layout_entries = [
+            wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+            storage_entry(1, true),
+            storage_entry(2, true),
+            storage_entry(3, false),
+        ];
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Boids Bind Group Layout"),
+            entries: &bind_group_layout_entries,
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Boids Bind Group"),
+            layout: &bind_group_layout,
+            entries: &[
+                &params_buffer,
+                position_buffer,
+                cpu_data_buffer,
+                &steered_speeds_buffer,
+            ]
+            .iter()
+            .enumerate()
+            .map(|(binding, buffer)| wgpu::BindGroupEntry {
+                binding: binding as u32,
+                resource: buffer.as_entire_binding(),
+            })
+            .collect::<Vec<_>>(),
+        });
+
+        let tile_size = max_tile_size(device).map_or(0, |max| {
+            if tile_size > max {
+                log::warn!("Tiles of {tile_size} boids don't fit on this device, using {max}");
+            }
+            tile_size.min(max)
+        });
+        let source = shader_source(tile_size, speed_layout);
+        let shader = shader_check::create_module(device, "Boids Shader", "boids.wgsl", &source);
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Boids Pipeline Layout"),
+            bind_group_layouts: &[
+                &bind_group_layout,
+                spatial_hash.neighbors_bind_group_layout(),
+            ],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Boids Pipeline"),
+            layout: Some(&pipeline_layout),
+            module: &shader,
+            entry_point: if tile_size > 0 { "main_tiled" } else { "main" },
+        });
+
+        #[cfg(feature = "guardrails")]
+        {
+            let reflection = crate::guardrails::ShaderReflection::new("boids.wgsl", &source);
+            reflection.check_bind_group_layout(0, &bind_group_layout_entries);
+            reflection.check_struct_size("BoidsParams", std::mem::size_of::<BoidsParams>());
+        }
+
+        Some(Self {
+            capacity,
+            speed_layout,
+            spatial_hash,
+            params_buffer,
+            steered_speeds_buffer,
+            bind_group,
+            pipeline,
+            tiled: tile_size > 0,
+        })
+    }
+
+    /// Hashes the first `params.count` boids and steers them, `cpu_data_buffer` being the one
+    /// the boids were created with.
+    pub fn encode(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        params: &BoidsParams,
+        cpu_data_buffer: &wgpu::Buffer,
+    ) {
+        let count = (params.count as usize).min(self.capacity);
+        queue.write_buffer(&self.params_buffer, 0, bytemuck::cast_slice(&[*params]));
+        self.spatial_hash.set_cell_size(params.radius);
+        self.spatial_hash.encode(device, queue, encoder, count);
+
+        // The tiled kernel runs a workgroup per key
+        let groups = if self.tiled {
+            self.spatial_hash.table_size()
+        } else {
+            (count as u32).div_ceil(WORKGROUP_SIZE).max(1)
+        };
+        let max_groups = device.limits().max_compute_workgroups_per_dimension;
+        let (x, y) = (groups.min(max_groups), groups.div_ceil(max_groups));
+        #[cfg(feature = "guardrails")]
+        if !self.tiled {
+            crate::guardrails::check_dispatch_coverage([x, y, 1], [WORKGROUP_SIZE, 1, 1], count);
+        }
+        {
+            let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("Boids Pass"),
+            });
+            compute_pass.set_pipeline(&self.pipeline);
+            compute_pass.set_bind_group(0, &self.bind_group, &[]);
+            compute_pass.set_bind_group(1, self.spatial_hash.neighbors_bind_group(), &[]);
+            compute_pass.dispatch_workgroups(x, y, 1);
+        }
+        // Steering reads the speeds of the neighbors, they are only replaced once all are steered
+        encoder.copy_buffer_to_buffer(
+            &self.steered_speeds_buffer,
+            0,
+            cpu_data_buffer,
+            0,
+            (count * self.speed_layout.stride()) as u64,
+        );
+    }
+}
+
+/// Largest tile the device runs, `None` if it can't run any.
+fn max_tile_size(device: &wgpu::Device) -> Option<u32> {
+    let limits = device.limits();
+    let max = limits
+        .max_compute_invocations_per_workgroup
+        .min(limits.max_compute_workgroup_size_x)
+        .min(
+            limits
+                .max_compute_workgroup_storage_size
+                .saturating_sub(TILE_BYTES_BASE)
+                / TILE_BYTES_PER_BOID,
+        );
+    (max > 0).then_some(max)
+}
+
+/// The source of the shader, after the neighbor search and `speed_layout`, in tiles of `tile_size`
+/// if not 0.
+pub fn shader_source(tile_size: u32, speed_layout: SpeedLayout) -> String {
+    format!(
+        "{NEIGHBORS_WGSL}\n{}\n{}",
+        speed_layout.wgsl(),
+        include_str!("boids.wgsl")
+            .replace(
+                "const TILE_SIZE: u32 = 64u;",
+                &format!("const TILE_SIZE: u32 = {}u;", tile_size.max(1)),
+            )
+            .replace(
+                "@workgroup_size(64, 1, 1)",
+                &format!("@workgroup_size({}, 1, 1)", tile_size.max(1)),
+            )
+    )
+}
@@ -0,0 +1,192 @@
+// Flocking: each boid steers away from its close neighbors, towards their average speed and
+// towards their center. Included after neighbors.wgsl and the SpeedLayout, see speed_layout.rs.
+
+// Must match BoidsParams in boids.rs
+struct BoidsParams {
+    radius: f32,
+    separation: f32,
+    alignment: f32,
+    cohesion: f32,
+    min_speed: f32,
+    max_speed: f32,
+    dt: f32,
+    count: u32,
+};
+
+@group(0) @binding(0)
+var<uniform> params: BoidsParams;
+
+@group(0) @binding(1)
+var<storage, read> positions: array<vec4<f32>>;
+
+// Speeds of the particles, in CpuData
+@group(0) @binding(2)
+var<storage, read> speeds: array<PackedSpeed>;
+
+@group(0) @binding(3)
+var<storage, read_write> steered_speeds: array<PackedSpeed>;
+
+// Replaced along with the workgroup size of main_tiled, see `Boids::new`
+const TILE_SIZE: u32 = 64u;
+
+// Neighbors of the cell being steered by main_tiled, loaded a tile at a time
+var<workgroup> tile_indices: array<u32, TILE_SIZE>;
+var<workgroup> tile_positions: array<vec4<f32>, TILE_SIZE>;
+var<workgroup> tile_speeds: array<vec4<f32>, TILE_SIZE>;
+var<workgroup> own_range: vec2<u32>;
+var<workgroup> own_cell: vec3<i32>;
+var<workgroup> neighbor_range: vec2<u32>;
+
+struct Flock {
+    separation: vec3<f32>,
+    average_speed: vec3<f32>,
+    center: vec3<f32>,
+    neighbors: u32,
+};
+
+// Must match BoidsParams::steer in boids.rs
+fn add_neighbor(
+    flock: ptr<function, Flock>,
+    position: vec3<f32>,
+    other: vec3<f32>,
+    speed: vec3<f32>,
+) {
+    let offset = other - position;
+    let distance_squared = dot(offset, offset);
+    if distance_squared >= params.radius * params.radius || distance_squared == 0.0 {
+        return;
+    }
+    (*flock).separation -= offset / distance_squared;
+    (*flock).average_speed += speed;
+    (*flock).center += offset;
+    (*flock).neighbors += 1u;
+}
+
+fn steered_speed(flock: Flock, speed: vec3<f32>) -> vec3<f32> {
+    var steered = speed;
+    if flock.neighbors > 0u {
+        let n = f32(flock.neighbors);
+        steered += (flock.separation * params.separation
+            + (flock.average_speed / n - speed) * params.alignment
+            + flock.center / n * params.cohesion) * params.dt;
+    }
+    let current = length(steered);
+    if current > 0.0 {
+        steered *= clamp(current, params.min_speed, params.max_speed) / current;
+    }
+    return steered;
+}
+
+fn steer(index: u32) {
+    let position = positions[index].xyz;
+    var flock = Flock();
+    let cell = hash_cell(position);
+    for (var i = 0u; i < 27u; i++) {
+        let range = cell_range(cell + neighbor_offset(i));
+        for (var j = range.x; j < range.y; j++) {
+            let other = sorted_indices[j];
+            if other != index {
+                let speed = unpack_speed(speeds[other]).xyz;
+                add_neighbor(&flock, position, positions[other].xyz, speed);
+            }
+        }
+    }
+    store_steered(index, flock);
+}
+
+// Keeps the step dt in w
+fn store_steered(index: u32, flock: Flock) {
+    let speed = unpack_speed(speeds[index]);
+    steered_speeds[index] = pack_speed(vec4<f32>(steered_speed(flock, speed.xyz), speed.w));
+}
+
+// One invocation per boid, reading every neighbor from storage
+@compute @workgroup_size(64)
+fn main(
+    @builtin(global_invocation_id) id: vec3<u32>,
+    @builtin(num_workgroups) workgroups: vec3<u32>,
+) {
+    // Large counts are dispatched in rows, a single dimension is limited to 65535 workgroups
+    let index = id.x + id.y * workgroups.x * 64u;
+    if index < params.count {
+        steer(index);
+    }
+}
+
+// One workgroup per key of the hash table, steering the boids of its cell TILE_SIZE at a time.
+// The neighbors are loaded in workgroup memory once for the whole tile, rather than once per boid.
+@compute @workgroup_size(64, 1, 1)
+fn main_tiled(
+    @builtin(workgroup_id) group: vec3<u32>,
+    @builtin(num_workgroups) workgroups: vec3<u32>,
+    @builtin(local_invocation_index) local: u32,
+) {
+    let key = group.x + group.y * workgroups.x;
+    if local == 0u {
+        own_range = vec2<u32>(0u);
+        if key < hash_params.table_size {
+            own_range = vec2<u32>(cell_starts[key], cell_starts[key] + cell_counts[key]);
+        }
+        if own_range.x < own_range.y {
+            own_cell = hash_cell(positions[sorted_indices[own_range.x]].xyz);
+        }
+    }
+    let own = workgroupUniformLoad(&own_range);
+    if own.x == own.y {
+        return;
+    }
+    let cell = workgroupUniformLoad(&own_cell);
+
+    for (var first = own.x; first < own.y; first += TILE_SIZE) {
+        let slot = first + local;
+        let steering = slot < own.y;
+        var index = 0u;
+        var position = vec3<f32>(0.0);
+        if steering {
+            index = sorted_indices[slot];
+            position = positions[index].xyz;
+        }
+        // Boids of another cell sharing the key don't share its neighbors
+        let in_cell = steering && all(hash_cell(position) == cell);
+        var flock = Flock();
+
+        for (var i = 0u; i < 27u; i++) {
+            // The previous range has to be read by every invocation before it's replaced
+            workgroupBarrier();
+            if local == 0u {
+                neighbor_range = cell_range(cell + neighbor_offset(i));
+            }
+            let range = workgroupUniformLoad(&neighbor_range);
+            for (var tile = range.x; tile < range.y; tile += TILE_SIZE) {
+                let load = tile + local;
+                if load < range.y {
+                    let other = sorted_indices[load];
+                    tile_indices[local] = other;
+                    tile_positions[local] = positions[other];
+                    tile_speeds[local] = unpack_speed(speeds[other]);
+                }
+                workgroupBarrier();
+                if in_cell {
+                    let loaded = min(TILE_SIZE, range.y - tile);
+                    for (var k = 0u; k < loaded; k++) {
+                        if tile_indices[k] != index {
+                            add_neighbor(
+                                &flock,
+                                position,
+                                tile_positions[k].xyz,
+                                tile_speeds[k].xyz,
+                            );
+                        }
+                    }
+                }
+                workgroupBarrier();
+            }
+        }
+
+        if in_cell {
+            store_steered(index, flock);
+        } else if steering {
+            steer(index);
+        }
+    }
+}
@@ -1,25 +1,176 @@
 use bytemuck::{Pod, Zeroable};
+use winit::event::MouseScrollDelta;
 
+use crate::groups::GroupMask;
+
+#[derive(Clone)]
 pub struct Camera {
     pub eye: glam::Vec3,
     pub target: glam::Vec3,
     pub up: glam::Vec3,
     pub aspect: f32,
     pub fovy: f32,
-    pub znear: f32,
-    pub zfar: f32,
+    pub depth: DepthRange,
 }
 
 impl Camera {
     pub fn build_view_projection_matrix(&self) -> glam::Mat4 {
-        let view = glam::Mat4::look_at_rh(self.eye, self.target, self.up);
-        let proj = glam::Mat4::perspective_rh_gl(
-            self.fovy * std::f32::consts::PI / 180.0,
-            self.aspect,
-            self.znear,
-            self.zfar,
-        );
-        proj * view
+        self.view_projection().matrix()
+    }
+
+    pub fn view_projection(&self) -> ViewProjection {
+        ViewProjection {
+            view: glam::Mat4::look_at_rh(self.eye, self.target, self.up),
+            proj: self
+                .depth
+                .projection(self.fovy * std::f32::consts::PI / 180.0, self.aspect),
+        }
+    }
+}
+
+/// View and projection matrices kept apart, for the shaders working in view space.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ViewProjection {
+    pub view: glam::Mat4,
+    pub proj: glam::Mat4,
+}
+
+impl ViewProjection {
+    pub fn matrix(&self) -> glam::Mat4 {
+        self.proj * self.view
+    }
+
+    /// The same view, with `clip` applied in clip space after the projection, like the crop of a
+    /// capture tile or the shift of a stereo eye.
+    pub fn then(self, clip: glam::Mat4) -> Self {
+        Self {
+            view: self.view,
+            proj: clip * self.proj,
+        }
+    }
+
+    /// Position of the camera in world space.
+    pub fn eye(&self) -> glam::Vec3 {
+        self.view.inverse().w_axis.truncate()
+    }
+}
+
+/// Near and far planes of the projection, and which way depth goes between them.
+///
+/// Depth normally goes from 0 at the near plane to 1 at the far one. Reversed, it goes from 1 to 0:
+/// floats are most precise near 0, which then makes up for the projection crowding the far depths
+/// together, so particles thousands of units away still sort.
+///
+/// An infinite `zfar` never clips particles, however deep the field goes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DepthRange {
+    pub znear: f32,
+    pub zfar: f32,
+    pub reversed: bool,
+}
+
+impl Default for DepthRange {
+    fn default() -> Self {
+        Self {
+            znear: 1.0,
+            zfar: 10000.0,
+            reversed: false,
+        }
+    }
+}
+
+impl DepthRange {
+    /// Perspective projection of `fovy` radians into wgpu's clip space, depth within the range.
+    pub fn projection(&self, fovy: f32, aspect: f32) -> glam::Mat4 {
+        if self.zfar.is_infinite() {
+            if self.reversed {
+                glam::Mat4::perspective_infinite_reverse_rh(fovy, aspect, self.znear)
+            } else {
+                glam::Mat4::perspective_infinite_rh(fovy, aspect, self.znear)
+            }
+        } else if self.reversed {
+            glam::Mat4::perspective_rh(fovy, aspect, self.zfar, self.znear)
+        } else {
+            glam::Mat4::perspective_rh(fovy, aspect, self.znear, self.zfar)
+        }
+    }
+
+    /// Passes fragments closer than the depth buffer, or as close with `or_equal`.
+    pub fn compare(&self, or_equal: bool) -> wgpu::CompareFunction {
+        match (self.reversed, or_equal) {
+            (false, false) => wgpu::CompareFunction::Less,
+            (false, true) => wgpu::CompareFunction::LessEqual,
+            (true, false) => wgpu::CompareFunction::Greater,
+            (true, true) => wgpu::CompareFunction::GreaterEqual,
+        }
+    }
+
+    /// Depth of the far plane, the depth buffers are cleared to.
+    pub fn far_depth(&self) -> f32 {
+        if self.reversed {
+            0.0
+        } else {
+            1.0
+        }
+    }
+}
+
+/// Moves the camera from the mouse wheel and pinches, easing towards the requested position so
+/// zooming feels the same with line-based wheels, pixel-precise touchpads and any frame rate. The
+/// wheel zooms along the ray under the cursor, pinches along the z axis, and dragging with the
+/// middle button pans.
+#[derive(Debug, Default)]
+pub struct ZoomController {
+    // Still to be travelled by the camera
+    offset: glam::Vec3,
+}
+
+impl ZoomController {
+    // World units travelled per line of a regular mouse wheel
+    const UNITS_PER_LINE: f32 = 200.0;
+    // World units travelled per pixel reported by touchpads
+    const UNITS_PER_PIXEL: f32 = 4.0;
+    // World units travelled per unit of pinch, a relative change in the distance between fingers
+    const UNITS_PER_MAGNIFY: f32 = 4000.0;
+    // How quickly the camera catches up with the target, per second
+    const SMOOTHING: f32 = 10.0;
+
+    /// Moves along `ray`, the unit direction under the cursor: away from what it points at for a
+    /// positive `delta`, towards it for a negative one.
+    pub fn scroll(&mut self, delta: &MouseScrollDelta, ray: glam::Vec3) {
+        let distance = match delta {
+            MouseScrollDelta::LineDelta(_, y) => y * Self::UNITS_PER_LINE,
+            MouseScrollDelta::PixelDelta(pos) => pos.y as f32 * Self::UNITS_PER_PIXEL,
+        };
+        self.offset -= ray * distance;
+    }
+
+    /// Zooms in for a positive `amount` of pinching out, and out for a negative one.
+    pub fn magnify(&mut self, amount: f32) {
+        self.offset.z -= amount * Self::UNITS_PER_MAGNIFY;
+    }
+
+    /// Slides `camera` sideways by `drag` pixels of a view `height` pixels high, right and down,
+    /// so what's at its target follows the cursor.
+    pub fn pan(camera: &mut Camera, drag: glam::Vec2, height: f32) {
+        let forward = camera.target - camera.eye;
+        let right = forward.cross(camera.up).normalize_or_zero();
+        let up = right.cross(forward).normalize_or_zero();
+        let units_per_pixel =
+            2.0 * forward.length() * (camera.fovy.to_radians() / 2.0).tan() / height.max(1.0);
+        let step = (up * drag.y - right * drag.x) * units_per_pixel;
+        camera.eye += step;
+        camera.target += step;
+    }
+
+    pub fn update(&mut self, camera: &mut Camera, dt: f32) {
+        // Exponential smoothing, so the same fraction of the distance is covered for a given
+        // amount of time no matter how it is split into frames
+        let t = 1.0 - (-Self::SMOOTHING * dt).exp();
+        let step = self.offset * t;
+        self.offset -= step;
+        camera.eye += step;
+        camera.target += step;
     }
 }
 
@@ -29,16 +180,79 @@ impl Camera {
 #[derive(Debug, Copy, Clone, Pod, Zeroable)]
 pub struct CameraUniform {
     view_proj: glam::Mat4,
+    // w is unused, the fragment shader needs the eye for specular highlights
+    eye: glam::Vec4,
+    // Turns clip space back into view rays, for the environment
+    inv_view_proj: glam::Mat4,
+    // View projection of the frame before, to reproject the history of the temporal accumulation
+    previous_view_proj: glam::Mat4,
+    // The two halves of view_proj, for the shaders working in view space
+    view: glam::Mat4,
+    proj: glam::Mat4,
+    // Particle groups shown, see groups.rs
+    group_mask: u32,
+    // Non-zero for a reversed DepthRange, depth is written from 1 near the camera to 0 far away
+    reverse_z: u32,
+    _padding: [u32; 2],
 }
 
 impl CameraUniform {
     pub fn new() -> Self {
         Self {
             view_proj: glam::Mat4::IDENTITY,
+            eye: glam::Vec4::W,
+            inv_view_proj: glam::Mat4::IDENTITY,
+            previous_view_proj: glam::Mat4::IDENTITY,
+            view: glam::Mat4::IDENTITY,
+            proj: glam::Mat4::IDENTITY,
+            group_mask: GroupMask::ALL.0,
+            reverse_z: 0,
+            _padding: [0; 2],
+        }
+    }
+
+    /// A still view through `view_projection`, projecting with `depth`.
+    pub fn from_view_projection(view_projection: &ViewProjection, depth: &DepthRange) -> Self {
+        let view_proj = view_projection.matrix();
+        Self {
+            view_proj,
+            eye: view_projection.eye().extend(1.0),
+            inv_view_proj: view_proj.inverse(),
+            previous_view_proj: view_proj,
+            view: view_projection.view,
+            proj: view_projection.proj,
+            group_mask: GroupMask::ALL.0,
+            reverse_z: depth.reversed.into(),
+            _padding: [0; 2],
         }
     }
 
+    /// Turns clip space back into world space.
+    pub fn inv_view_proj(&self) -> glam::Mat4 {
+        self.inv_view_proj
+    }
+
+    /// Unit direction of the view ray through `ndc`, from -1 to 1 with y up.
+    pub fn ray(&self, ndc: glam::Vec2) -> glam::Vec3 {
+        // Halfway into the depth range stays finite with an infinite far plane
+        let near = if self.reverse_z != 0 { 1.0 } else { 0.0 };
+        let unproject = |depth: f32| self.inv_view_proj().project_point3(ndc.extend(depth));
+        (unproject(0.5) - unproject(near)).normalize_or_zero()
+    }
+
+    pub fn set_group_mask(&mut self, mask: GroupMask) {
+        self.group_mask = mask.0;
+    }
+
+    /// Must be called once per frame, the current view projection becomes the previous one.
     pub fn update_view_proj(&mut self, camera: &Camera) {
-        self.view_proj = camera.build_view_projection_matrix();
+        let view_projection = camera.view_projection();
+        self.previous_view_proj = self.view_proj;
+        self.view_proj = view_projection.matrix();
+        self.view = view_projection.view;
+        self.proj = view_projection.proj;
+        self.eye = camera.eye.extend(1.0);
+        self.inv_view_proj = self.view_proj.inverse();
+        self.reverse_z = camera.depth.reversed.into();
     }
 }
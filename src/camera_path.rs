@@ -0,0 +1,325 @@
+//! Keyframed camera flythroughs, so benchmarks and recorded demos see the same thing every run.
+//!
+//! Paths are RON files listing where the camera is and what it looks at over time:
+//!
+//! ```ron
+//! (
+//!     looping: true,
+//!     keyframes: [
+//!         (time: 0.0, position: (0.0, 0.0, 3000.0), target: (0.0, 0.0, 0.0)),
+//!         (time: 8.0, position: (2500.0, 500.0, 1500.0), target: (0.0, 0.0, 0.0)),
+//!     ],
+//! )
+//! ```
+//!
+//! The camera moves along Catmull-Rom splines through the keyframes, then either starts over or
+//! stays on the last one.
+
+use std::path::{Path, PathBuf};
+
+use glam::Vec3;
+
+use crate::camera::Camera;
+
+#[derive(Debug, thiserror::Error)]
+pub enum CameraPathError {
+    #[error("unable to read {0}: {1}")]
+    Io(PathBuf, std::io::Error),
+    #[error("line {0}: {1}")]
+    Syntax(usize, String),
+    #[error("missing `{0}`")]
+    Missing(&'static str),
+    #[error("`{0}` must be {1}")]
+    Invalid(&'static str, &'static str),
+    #[error("keyframe {0}: `{1}` must be {2}")]
+    InvalidKeyframe(usize, &'static str, &'static str),
+    #[error("a camera path needs at least two keyframes")]
+    TooFewKeyframes,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CameraKeyframe {
+    /// Seconds since the start of the path
+    pub time: f32,
+    pub position: Vec3,
+    pub target: Vec3,
+}
+
+pub struct CameraPath {
+    // Sorted by time
+    keyframes: Vec<CameraKeyframe>,
+    looping: bool,
+    elapsed: f32,
+}
+
+impl CameraPath {
+    pub fn new(mut keyframes: Vec<CameraKeyframe>, looping: bool) -> Result<Self, CameraPathError> {
+        if keyframes.len() < 2 {
+            return Err(CameraPathError::TooFewKeyframes);
+        }
+        keyframes.sort_by(|a, b| a.time.total_cmp(&b.time));
+        Ok(Self {
+            keyframes,
+            looping,
+            elapsed: 0.0,
+        })
+    }
+
+    pub fn load(path: &Path) -> Result<Self, CameraPathError> {
+        let contents =
+            std::fs::read_to_string(path).map_err(|e| CameraPathError::Io(path.to_owned(), e))?;
+        Self::parse(&contents)
+    }
+
+    pub fn parse(contents: &str) -> Result<Self, CameraPathError> {
+        let Value::Struct(fields) = Parser::new(contents).parse()? else {
+            return Err(CameraPathError::Invalid("camera path", "a struct"));
+        };
+        let field = |name| fields.iter().find(|(key, _)| key == name).map(|(_, v)| v);
+        let looping = match field("looping") {
+            None => false,
+            Some(Value::Bool(looping)) => *looping,
+            Some(_) => return Err(CameraPathError::Invalid("looping", "true or false")),
+        };
+        let values = match field("keyframes") {
+            None => return Err(CameraPathError::Missing("keyframes")),
+            Some(Value::List(values)) => values,
+            Some(_) => return Err(CameraPathError::Invalid("keyframes", "a list")),
+        };
+        let keyframes = values
+            .iter()
+            .enumerate()
+            .map(|(index, value)| parse_keyframe(index, value))
+            .collect::<Result<_, _>>()?;
+        Self::new(keyframes, looping)
+    }
+
+    /// True once a path that doesn't loop reached its last keyframe.
+    pub fn finished(&self) -> bool {
+        !self.looping && self.elapsed >= self.duration()
+    }
+
+    /// Advances the path by `dt` seconds and moves `camera` along it.
+    pub fn update(&mut self, dt: f32, camera: &mut Camera) {
+        self.elapsed += dt;
+        let time = if self.looping && self.duration() > 0.0 {
+            self.start() + (self.elapsed % self.duration())
+        } else {
+            self.start() + self.elapsed.min(self.duration())
+        };
+        let keyframe = self.sample(time);
+        camera.eye = keyframe.position;
+        camera.target = keyframe.target;
+    }
+
+    fn start(&self) -> f32 {
+        self.keyframes[0].time
+    }
+
+    fn duration(&self) -> f32 {
+        self.keyframes[self.keyframes.len() - 1].time - self.start()
+    }
+
+    fn sample(&self, time: f32) -> CameraKeyframe {
+        let last = self.keyframes.len() - 1;
+        // Segment from keyframe `next - 1` to keyframe `next`
+        let next = self
+            .keyframes
+            .iter()
+            .position(|keyframe| keyframe.time > time)
+            .unwrap_or(last)
+            .max(1);
+        // The ends are duplicated, so the first and last segments have neighbours
+        let [k0, k1, k2, k3] = [next.saturating_sub(2), next - 1, next, (next + 1).min(last)]
+            .map(|index| &self.keyframes[index]);
+        let span = k2.time - k1.time;
+        let t = if span > 0.0 {
+            ((time - k1.time) / span).clamp(0.0, 1.0)
+        } else {
+            1.0
+        };
+        CameraKeyframe {
+            time,
+            position: catmull_rom(k0.position, k1.position, k2.position, k3.position, t),
+            target: catmull_rom(k0.target, k1.target, k2.target, k3.target, t),
+        }
+    }
+}
+
+/// Point at `t` between `p1` and `p2` of the uniform Catmull-Rom spline through the four points.
+fn catmull_rom(p0: Vec3, p1: Vec3, p2: Vec3, p3: Vec3, t: f32) -> Vec3 {
+    let t2 = t * t;
+    let t3 = t2 * t;
+    0.5 * (2.0 * p1
+        + (p2 - p0) * t
+        + (2.0 * p0 - 5.0 * p1 + 4.0 * p2 - p3) * t2
+        + (3.0 * p1 - p0 - 3.0 * p2 + p3) * t3)
+}
+
+fn parse_keyframe(index: usize, value: &Value) -> Result<CameraKeyframe, CameraPathError> {
+    let Value::Struct(fields) = value else {
+        return Err(CameraPathError::InvalidKeyframe(
+            index, "keyframe", "a struct",
+        ));
+    };
+    let field = |name| fields.iter().find(|(key, _)| key == name).map(|(_, v)| v);
+    let time = match field("time") {
+        Some(Value::Number(time)) if *time >= 0.0 => *time,
+        _ => {
+            return Err(CameraPathError::InvalidKeyframe(
+                index,
+                "time",
+                "a positive number",
+            ))
+        }
+    };
+    let vector = |name| match field(name) {
+        Some(Value::Tuple(values)) => match values.as_slice() {
+            [Value::Number(x), Value::Number(y), Value::Number(z)] => Ok(Vec3::new(*x, *y, *z)),
+            _ => Err(CameraPathError::InvalidKeyframe(index, name, "(x, y, z)")),
+        },
+        _ => Err(CameraPathError::InvalidKeyframe(index, name, "(x, y, z)")),
+    };
+    Ok(CameraKeyframe {
+        time,
+        position: vector("position")?,
+        target: vector("target")?,
+    })
+}
+
+/// The part of RON camera paths use.
+enum Value {
+    Number(f32),
+    Bool(bool),
+    Tuple(Vec<Value>),
+    Struct(Vec<(String, Value)>),
+    List(Vec<Value>),
+}
+
+#[derive(Clone, Copy)]
+struct Parser<'a> {
+    rest: &'a str,
+    line: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn new(contents: &'a str) -> Self {
+        Self {
+            rest: contents,
+            line: 1,
+        }
+    }
+
+    fn parse(mut self) -> Result<Value, CameraPathError> {
+        let value = self.value()?;
+        self.skip_whitespace();
+        if !self.rest.is_empty() {
+            return Err(self.error("unexpected content after the path"));
+        }
+        Ok(value)
+    }
+
+    fn error(&self, message: impl Into<String>) -> CameraPathError {
+        CameraPathError::Syntax(self.line, message.into())
+    }
+
+    fn skip_whitespace(&mut self) {
+        loop {
+            let trimmed = self.rest.trim_start();
+            self.line += self.rest[..self.rest.len() - trimmed.len()]
+                .matches('\n')
+                .count();
+            self.rest = trimmed;
+            match self.rest.strip_prefix("//") {
+                Some(comment) => self.rest = comment.find('\n').map_or("", |end| &comment[end..]),
+                None => return,
+            }
+        }
+    }
+
+    fn eat(&mut self, token: char) -> bool {
+        self.skip_whitespace();
+        match self.rest.strip_prefix(token) {
+            Some(rest) => {
+                self.rest = rest;
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn expect(&mut self, token: char) -> Result<(), CameraPathError> {
+        if self.eat(token) {
+            Ok(())
+        } else {
+            Err(self.error(format!("expected `{token}`")))
+        }
+    }
+
+    fn word(&mut self) -> &'a str {
+        self.skip_whitespace();
+        let end = self
+            .rest
+            .find(|c: char| !(c.is_alphanumeric() || matches!(c, '_' | '.' | '-' | '+')))
+            .unwrap_or(self.rest.len());
+        let (word, rest) = self.rest.split_at(end);
+        self.rest = rest;
+        word
+    }
+
+    fn value(&mut self) -> Result<Value, CameraPathError> {
+        if self.eat('[') {
+            return Ok(Value::List(self.items(']', Self::value)?));
+        }
+        if self.eat('(') {
+            return self.parenthesized();
+        }
+        let word = self.word();
+        match word {
+            "" => Err(self.error("expected a value")),
+            "true" => Ok(Value::Bool(true)),
+            "false" => Ok(Value::Bool(false)),
+            _ => match word.parse() {
+                Ok(number) => Ok(Value::Number(number)),
+                // Struct names are optional in RON, `CameraPath(...)` is the same as `(...)`
+                Err(_) if self.eat('(') => self.parenthesized(),
+                Err(_) => Err(self.error(format!("unexpected `{word}`"))),
+            },
+        }
+    }
+
+    /// A tuple or a struct, after its opening parenthesis.
+    fn parenthesized(&mut self) -> Result<Value, CameraPathError> {
+        // Structs start with `name:`
+        let mut lookahead = *self;
+        let is_struct = !lookahead.word().is_empty() && lookahead.eat(':');
+        if !is_struct {
+            return Ok(Value::Tuple(self.items(')', Self::value)?));
+        }
+        let fields = self.items(')', |parser| {
+            let name = parser.word().to_owned();
+            parser.expect(':')?;
+            Ok((name, parser.value()?))
+        })?;
+        Ok(Value::Struct(fields))
+    }
+
+    /// Comma separated items up to `close`, with an optional trailing comma.
+    fn items<T>(
+        &mut self,
+        close: char,
+        mut item: impl FnMut(&mut Self) -> Result<T, CameraPathError>,
+    ) -> Result<Vec<T>, CameraPathError> {
+        let mut items = vec![];
+        loop {
+            if self.eat(close) {
+                return Ok(items);
+            }
+            items.push(item(self)?);
+            if !self.eat(',') {
+                self.expect(close)?;
+                return Ok(items);
+            }
+        }
+    }
+}
@@ -0,0 +1,104 @@
+//! Saved camera viewpoints, so performance comparisons are taken from identical positions.
+//!
+//! Presets are stored as plain text, one `slot eye.x eye.y eye.z target.x target.y target.z up.x
+//! up.y up.z fovy` line per saved slot.
+
+use std::{
+    fmt::Write,
+    path::{Path, PathBuf},
+};
+
+use crate::camera::Camera;
+
+pub const SLOTS: usize = 9;
+
+#[derive(Debug, Clone, Copy)]
+struct Preset {
+    eye: glam::Vec3,
+    target: glam::Vec3,
+    up: glam::Vec3,
+    fovy: f32,
+}
+
+pub struct CameraPresets {
+    path: PathBuf,
+    presets: [Option<Preset>; SLOTS],
+}
+
+impl CameraPresets {
+    /// Loads the presets saved at `path`. Missing files and malformed lines are ignored.
+    pub fn load(path: impl AsRef<Path>) -> Self {
+        let path = path.as_ref().to_owned();
+        let mut presets = [None; SLOTS];
+        if let Ok(contents) = std::fs::read_to_string(&path) {
+            for line in contents.lines() {
+                match parse_line(line) {
+                    Some((slot, preset)) => presets[slot] = Some(preset),
+                    None => log::warn!("Ignoring camera preset `{line}` in {}", path.display()),
+                }
+            }
+        }
+        Self { path, presets }
+    }
+
+    /// Saves `camera` in `slot` (0 based) and writes all presets to disk.
+    pub fn store(&mut self, slot: usize, camera: &Camera) -> std::io::Result<()> {
+        self.presets[slot] = Some(Preset {
+            eye: camera.eye,
+            target: camera.target,
+            up: camera.up,
+            fovy: camera.fovy,
+        });
+
+        let mut contents = String::new();
+        for (slot, preset) in self.presets.iter().enumerate() {
+            if let Some(Preset {
+                eye,
+                target,
+                up,
+                fovy,
+            }) = preset
+            {
+                writeln!(
+                    contents,
+                    "{slot} {} {} {} {} {} {} {} {} {} {fovy}",
+                    eye.x, eye.y, eye.z, target.x, target.y, target.z, up.x, up.y, up.z
+                )
+                .unwrap();
+            }
+        }
+        std::fs::write(&self.path, contents)
+    }
+
+    /// Moves `camera` to the preset in `slot`. Returns false if nothing was saved there.
+    pub fn apply(&self, slot: usize, camera: &mut Camera) -> bool {
+        let Some(preset) = self.presets[slot] else {
+            return false;
+        };
+        camera.eye = preset.eye;
+        camera.target = preset.target;
+        camera.up = preset.up;
+        camera.fovy = preset.fovy;
+        true
+    }
+}
+
+fn parse_line(line: &str) -> Option<(usize, Preset)> {
+    let mut fields = line.split_whitespace();
+    let slot: usize = fields.next()?.parse().ok()?;
+    let values = fields
+        .map(|field| field.parse::<f32>().ok())
+        .collect::<Option<Vec<_>>>()?;
+    let [ex, ey, ez, tx, ty, tz, ux, uy, uz, fovy] = values[..] else {
+        return None;
+    };
+    (slot < SLOTS).then_some((
+        slot,
+        Preset {
+            eye: glam::Vec3::new(ex, ey, ez),
+            target: glam::Vec3::new(tx, ty, tz),
+            up: glam::Vec3::new(ux, uy, uz),
+            fovy,
+        },
+    ))
+}
@@ -0,0 +1,100 @@
+//! Optional GPU features and downlevel capabilities the demo uses when the adapter has them. They
+//! are looked up once per device, and every subsystem asks them rather than the device, so what
+//! `info` reports is what runs.
+
+/// What the adapter supports of what the demo can use.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct GpuCapabilities {
+    /// Line polygons, for the wireframe debug view
+    pub polygon_mode_line: bool,
+    /// Timestamps of the GPU passes, only asked for with the metrics feature
+    pub timestamp_query: bool,
+    /// Vertices and triangles drawn, only asked for with the metrics feature
+    pub pipeline_statistics_query: bool,
+    /// Indirect draws starting past the first instance, for the level of detail and the draws of
+    /// the live ranges
+    pub indirect_first_instance: bool,
+    /// All the live ranges in a single indirect call
+    pub multi_draw_indirect: bool,
+    /// Storage buffers read by vertex shaders, for vertex pulling
+    pub vertex_storage: bool,
+    /// Storage buffers written by fragment shaders, for the overdraw heatmap
+    pub fragment_writable_storage: bool,
+    /// f16 in shaders, for the speeds of the compute passes packed in halves, see speed_layout.rs
+    pub shader_f16: bool,
+    /// Depth textures read with `textureLoad`, for depth of field, half resolution and temporal
+    /// accumulation. Not translated to GLSL
+    pub depth_texture_load: bool,
+}
+
+impl GpuCapabilities {
+    pub fn of(adapter: &wgpu::Adapter) -> Self {
+        let features = adapter.features();
+        let flags = adapter.get_downlevel_capabilities().flags;
+        Self {
+            polygon_mode_line: features.contains(wgpu::Features::POLYGON_MODE_LINE),
+            timestamp_query: cfg!(feature = "metrics")
+                && features.contains(wgpu::Features::TIMESTAMP_QUERY),
+            pipeline_statistics_query: cfg!(feature = "metrics")
+                && features.contains(wgpu::Features::PIPELINE_STATISTICS_QUERY),
+            indirect_first_instance: features.contains(wgpu::Features::INDIRECT_FIRST_INSTANCE),
+            multi_draw_indirect: features.contains(wgpu::Features::MULTI_DRAW_INDIRECT),
+            vertex_storage: flags.contains(wgpu::DownlevelFlags::VERTEX_STORAGE),
+            fragment_writable_storage: flags
+                .contains(wgpu::DownlevelFlags::FRAGMENT_WRITABLE_STORAGE),
+            shader_f16: features.contains(wgpu::Features::SHADER_F16),
+            depth_texture_load: adapter.get_info().backend != wgpu::Backend::Gl,
+        }
+    }
+
+    /// Features the device is created with.
+    pub fn features(&self) -> wgpu::Features {
+        [
+            (self.polygon_mode_line, wgpu::Features::POLYGON_MODE_LINE),
+            (self.timestamp_query, wgpu::Features::TIMESTAMP_QUERY),
+            (
+                self.pipeline_statistics_query,
+                wgpu::Features::PIPELINE_STATISTICS_QUERY,
+            ),
+            (
+                self.indirect_first_instance,
+                wgpu::Features::INDIRECT_FIRST_INSTANCE,
+            ),
+            (
+                self.multi_draw_indirect,
+                wgpu::Features::MULTI_DRAW_INDIRECT,
+            ),
+            (self.shader_f16, wgpu::Features::SHADER_F16),
+        ]
+        .into_iter()
+        .filter(|&(supported, _)| supported)
+        .fold(wgpu::Features::empty(), |features, (_, feature)| {
+            features | feature
+        })
+    }
+
+    /// Each feature of the demo depending on the GPU, and whether it's available on this one.
+    pub fn report(&self) -> Vec<(&'static str, bool)> {
+        vec![
+            ("wireframe debug view", self.polygon_mode_line),
+            ("GPU pass times", self.timestamp_query),
+            ("pipeline statistics", self.pipeline_statistics_query),
+            ("level of detail", self.indirect_first_instance),
+            (
+                "indirect draws of the live ranges",
+                self.indirect_first_instance,
+            ),
+            (
+                "single multi-draw of the live ranges",
+                self.indirect_first_instance && self.multi_draw_indirect,
+            ),
+            ("vertex pulling", self.vertex_storage),
+            ("overdraw heatmap", self.fragment_writable_storage),
+            ("f16 speeds", self.shader_f16),
+            (
+                "depth of field, half resolution and temporal accumulation",
+                self.depth_texture_load,
+            ),
+        ]
+    }
+}
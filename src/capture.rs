@@ -0,0 +1,199 @@
+//! High resolution stills, rendered in tiles so the output size isn't limited by the window or by
+//! the maximum texture size of the adapter.
+
+use std::{fs::File, io::BufWriter, path::Path};
+
+use crate::camera::{DepthRange, ViewProjection};
+
+#[derive(Debug, thiserror::Error)]
+pub enum CaptureError {
+    #[error("unable to write capture: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("unable to encode png: {0}")]
+    Png(#[from] png::EncodingError),
+    #[error("unable to read back the rendered tile: {0}")]
+    Readback(#[from] wgpu::BufferAsyncError),
+    #[error("video encoder failed with {0}")]
+    Encoder(std::process::ExitStatus),
+}
+
+/// A rectangle of the final image, in pixels.
+#[derive(Debug, Clone, Copy)]
+pub struct Tile {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Tile {
+    /// Matrix to apply after the projection so that only this tile of the full image ends up in
+    /// clip space.
+    pub fn crop_matrix(&self, image_width: u32, image_height: u32) -> glam::Mat4 {
+        let x0 = self.x as f32 / image_width as f32 * 2.0 - 1.0;
+        let x1 = (self.x + self.width) as f32 / image_width as f32 * 2.0 - 1.0;
+        // Pixel rows go down while NDC y goes up
+        let y0 = 1.0 - (self.y + self.height) as f32 / image_height as f32 * 2.0;
+        let y1 = 1.0 - self.y as f32 / image_height as f32 * 2.0;
+
+        let scale = glam::Vec3::new(2.0 / (x1 - x0), 2.0 / (y1 - y0), 1.0);
+        let translation = glam::Vec3::new(-(x1 + x0) / (x1 - x0), -(y1 + y0) / (y1 - y0), 0.0);
+        glam::Mat4::from_translation(translation) * glam::Mat4::from_scale(scale)
+    }
+}
+
+/// Splits a `width`x`height` image in tiles of at most `tile_size` pixels per side.
+pub fn tiles(width: u32, height: u32, tile_size: u32) -> impl Iterator<Item = Tile> {
+    (0..height).step_by(tile_size as usize).flat_map(move |y| {
+        (0..width).step_by(tile_size as usize).map(move |x| Tile {
+            x,
+            y,
+            width: tile_size.min(width - x),
+            height: tile_size.min(height - y),
+        })
+    })
+}
+
+/// Tightly packed RGBA8 image that tiles get stitched into.
+pub struct Image {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+}
+
+impl Image {
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            pixels: vec![0; width as usize * height as usize * 4],
+        }
+    }
+
+    /// Copies `rgba` (tightly packed, the size of `tile`) at the position of `tile`.
+    pub fn blit(&mut self, tile: Tile, rgba: &[u8]) {
+        let row_len = tile.width as usize * 4;
+        for (row, src) in rgba.chunks_exact(row_len).enumerate() {
+            let start = ((tile.y as usize + row) * self.width as usize + tile.x as usize) * 4;
+            self.pixels[start..start + row_len].copy_from_slice(src);
+        }
+    }
+
+    pub fn save_png(&self, path: impl AsRef<Path>) -> Result<(), CaptureError> {
+        let writer = BufWriter::new(File::create(path)?);
+        let mut encoder = png::Encoder::new(writer, self.width, self.height);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.write_header()?.write_image_data(&self.pixels)?;
+        Ok(())
+    }
+}
+
+/// Copies a 4 bytes per pixel `texture` back to the CPU as tightly packed RGBA8.
+pub fn read_texture_rgba(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    texture: &wgpu::Texture,
+) -> Result<Vec<u8>, CaptureError> {
+    let width = texture.width();
+    let height = texture.height();
+    let unpadded_bytes_per_row = width * 4;
+    let padded_bytes_per_row = unpadded_bytes_per_row.div_ceil(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT)
+        * wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+
+    let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Capture Readback Buffer"),
+        size: (padded_bytes_per_row * height) as _,
+        usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+        mapped_at_creation: false,
+    });
+
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+        label: Some("Capture Readback Encoder"),
+    });
+    encoder.copy_texture_to_buffer(
+        texture.as_image_copy(),
+        wgpu::ImageCopyBuffer {
+            buffer: &buffer,
+            layout: wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(padded_bytes_per_row),
+                rows_per_image: None,
+            },
+        },
+        texture.size(),
+    );
+    queue.submit(Some(encoder.finish()));
+
+    let (sender, receiver) = futures::channel::oneshot::channel();
+    buffer
+        .slice(..)
+        .map_async(wgpu::MapMode::Read, move |result| {
+            sender.send(result).unwrap();
+        });
+    device.poll(wgpu::Maintain::Wait);
+    futures::executor::block_on(receiver).unwrap()?;
+
+    let bgra = matches!(
+        texture.format(),
+        wgpu::TextureFormat::Bgra8Unorm | wgpu::TextureFormat::Bgra8UnormSrgb
+    );
+    let mut pixels = Vec::with_capacity((unpadded_bytes_per_row * height) as usize);
+    {
+        let mapped = buffer.slice(..).get_mapped_range();
+        for row in mapped.chunks_exact(padded_bytes_per_row as usize) {
+            pixels.extend_from_slice(&row[..unpadded_bytes_per_row as usize]);
+        }
+    }
+    buffer.unmap();
+
+    if bgra {
+        for pixel in pixels.chunks_exact_mut(4) {
+            pixel.swap(0, 2);
+        }
+    }
+    Ok(pixels)
+}
+
+/// One of the six faces of a cubemap, looking out from its center.
+pub struct CubeFace {
+    pub forward: glam::Vec3,
+    pub up: glam::Vec3,
+}
+
+pub const CUBE_FACES: [CubeFace; 6] = [
+    CubeFace {
+        forward: glam::Vec3::X,
+        up: glam::Vec3::Y,
+    },
+    CubeFace {
+        forward: glam::Vec3::NEG_X,
+        up: glam::Vec3::Y,
+    },
+    CubeFace {
+        forward: glam::Vec3::Y,
+        up: glam::Vec3::Z,
+    },
+    CubeFace {
+        forward: glam::Vec3::NEG_Y,
+        up: glam::Vec3::NEG_Z,
+    },
+    CubeFace {
+        forward: glam::Vec3::Z,
+        up: glam::Vec3::Y,
+    },
+    CubeFace {
+        forward: glam::Vec3::NEG_Z,
+        up: glam::Vec3::Y,
+    },
+];
+
+impl CubeFace {
+    /// Square, 90° field of view projection of this face as seen from `eye`.
+    pub fn view_projection(&self, eye: glam::Vec3, depth: &DepthRange) -> ViewProjection {
+        ViewProjection {
+            view: glam::Mat4::look_at_rh(eye, eye + self.forward, self.up),
+            proj: depth.projection(std::f32::consts::FRAC_PI_2, 1.0),
+        }
+    }
+}
@@ -0,0 +1,371 @@
+//! Checkerboard rendering of the particles, halving the pixels they fill every frame.
+//!
+//! The pixels are grouped in 2x2 quads, colored like a checkerboard. Every frame the particles are
+//! only drawn in the quads of one color, alternating between frames, masked by a stencil buffer so
+//! the other quads are rejected before blending. The missing quads are then reconstructed from the
+//! previous frame, kept within the colors drawn around them so moving particles don't smear.
+
+use bytemuck::{Pod, Zeroable};
+
+use crate::render_target::fullscreen_shader;
+
+/// Format of the stencil mask, the particles pipeline drawing into it only tests the stencil.
+pub const STENCIL_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Stencil8;
+
+// Must match CheckerboardParams in checkerboard.wgsl
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Pod, Zeroable)]
+struct CheckerboardParams {
+    parity: u32,
+    history_valid: u32,
+    _padding: [u32; 2],
+}
+
+/// Stencil test of the particles drawn into [`Checkerboard::views`], only passing in the quads
+/// matching the stencil reference.
+pub fn stencil_state() -> wgpu::StencilState {
+    let face = wgpu::StencilFaceState {
+        compare: wgpu::CompareFunction::Equal,
+        fail_op: wgpu::StencilOperation::Keep,
+        depth_fail_op: wgpu::StencilOperation::Keep,
+        pass_op: wgpu::StencilOperation::Keep,
+    };
+    wgpu::StencilState {
+        front: face,
+        back: face,
+        read_mask: 0xff,
+        write_mask: 0,
+    }
+}
+
+struct Targets {
+    size: (u32, u32),
+    // Kept alive for the views
+    _color_texture: wgpu::Texture,
+    color_view: wgpu::TextureView,
+    _stencil_texture: wgpu::Texture,
+    stencil_view: wgpu::TextureView,
+    _history_textures: [wgpu::Texture; 2],
+    history_views: [wgpu::TextureView; 2],
+    // Reading the history of the same index
+    bind_groups: [wgpu::BindGroup; 2],
+    // Frames reconstructed since the targets were created
+    frames: u32,
+}
+
+pub struct Checkerboard {
+    enabled: bool,
+    format: wgpu::TextureFormat,
+    mask_pipeline: wgpu::RenderPipeline,
+    reconstruct_pipeline: wgpu::RenderPipeline,
+    bind_group_layout: wgpu::BindGroupLayout,
+    params_buffer: wgpu::Buffer,
+    targets: Option<Targets>,
+}
+
+impl Checkerboard {
+    pub fn new(device: &wgpu::Device, format: wgpu::TextureFormat, enabled: bool) -> Self {
+        let texture_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                view_dimension: wgpu::TextureViewDimension::D2,
+                multisampled: false,
+            },
+            count: None,
+        };
+        let bind_group_layout_entries = [
+            texture_entry(0),
+            texture_entry(1),
+            wgpu::BindGroupLayoutEntry {
+                binding: 2,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+        ];
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Checkerboard Bind Group Layout"),
+            entries: &bind_group_layout_entries,
+        });
+
+        let source = fullscreen_shader(include_str!("checkerboard.wgsl"));
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Checkerboard Shader"),
+            source: wgpu::ShaderSource::Wgsl(source.as_str().into()),
+        });
+
+        let mask_pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Checkerboard Mask Pipeline Layout"),
+            bind_group_layouts: &[],
+            push_constant_ranges: &[],
+        });
+        let mask_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Checkerboard Mask Pipeline"),
+            layout: Some(&mask_pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_fullscreen",
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_mask",
+                targets: &[],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: STENCIL_FORMAT,
+                depth_write_enabled: false,
+                depth_compare: wgpu::CompareFunction::Always,
+                stencil: wgpu::StencilState {
+                    front: wgpu::StencilFaceState {
+                        compare: wgpu::CompareFunction::Always,
+                        fail_op: wgpu::StencilOperation::Keep,
+                        depth_fail_op: wgpu::StencilOperation::Keep,
+                        pass_op: wgpu::StencilOperation::Replace,
+                    },
+                    back: wgpu::StencilFaceState::IGNORE,
+                    read_mask: 0xff,
+                    write_mask: 0xff,
+                },
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+
+        let reconstruct_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Checkerboard Reconstruct Pipeline Layout"),
+                bind_group_layouts: &[&bind_group_layout],
+                push_constant_ranges: &[],
+            });
+        let color_target = Some(wgpu::ColorTargetState {
+            format,
+            blend: None,
+            write_mask: wgpu::ColorWrites::ALL,
+        });
+        let reconstruct_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Checkerboard Reconstruct Pipeline"),
+            layout: Some(&reconstruct_pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_fullscreen",
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_reconstruct",
+                // The scene, and the history the next frame reconstructs from
+                targets: &[color_target.clone(), color_target],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+
+        #[cfg(feature = "guardrails")]
+        {
+            let reflection = crate::guardrails::ShaderReflection::new("checkerboard.wgsl", &source);
+            reflection.check_bind_group_layout(0, &bind_group_layout_entries);
+            reflection.check_struct_size(
+                "CheckerboardParams",
+                std::mem::size_of::<CheckerboardParams>(),
+            );
+        }
+
+        let params_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Checkerboard Params Buffer"),
+            size: std::mem::size_of::<CheckerboardParams>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        Self {
+            enabled,
+            format,
+            mask_pipeline,
+            reconstruct_pipeline,
+            bind_group_layout,
+            params_buffer,
+            targets: None,
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn toggle(&mut self) {
+        self.enabled = !self.enabled;
+        if !self.enabled {
+            self.targets = None;
+        }
+    }
+
+    /// Makes sure the textures are `size` and moves on to the quads of the next frame. Must be
+    /// called before [`Checkerboard::views`] and [`Checkerboard::reconstruct`] every time the
+    /// scene is drawn.
+    pub fn prepare(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, size: (u32, u32)) {
+        match &mut self.targets {
+            Some(targets) if targets.size == size => targets.frames += 1,
+            _ => self.targets = Some(self.create_targets(device, queue, size)),
+        }
+        let Some(targets) = &self.targets else {
+            return;
+        };
+        let params = CheckerboardParams {
+            parity: targets.frames % 2,
+            history_valid: (targets.frames > 0) as u32,
+            _padding: [0; 2],
+        };
+        queue.write_buffer(&self.params_buffer, 0, bytemuck::cast_slice(&[params]));
+    }
+
+    /// Color and stencil views the particles should be drawn into, once prepared, and the
+    /// stencil reference of this frame's quads.
+    pub fn views(&self) -> Option<(&wgpu::TextureView, &wgpu::TextureView, u32)> {
+        let targets = self.targets.as_ref()?;
+        Some((
+            &targets.color_view,
+            &targets.stencil_view,
+            targets.frames % 2,
+        ))
+    }
+
+    /// Fills in the quads left out of [`Checkerboard::views`] this frame, into `view`.
+    pub fn reconstruct(&self, encoder: &mut wgpu::CommandEncoder, view: &wgpu::TextureView) {
+        let Some(targets) = &self.targets else {
+            return;
+        };
+        // Frames alternate between reading one history and writing the other
+        let read = (targets.frames % 2) as usize;
+
+        let attachment = |view| {
+            Some(wgpu::RenderPassColorAttachment {
+                view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                    store: true,
+                },
+            })
+        };
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Checkerboard Reconstruct Pass"),
+            color_attachments: &[
+                attachment(view),
+                attachment(&targets.history_views[1 - read]),
+            ],
+            depth_stencil_attachment: None,
+        });
+        render_pass.set_pipeline(&self.reconstruct_pipeline);
+        render_pass.set_bind_group(0, &targets.bind_groups[read], &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+
+    fn create_targets(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        size: (u32, u32),
+    ) -> Targets {
+        let extent = wgpu::Extent3d {
+            width: size.0,
+            height: size.1,
+            depth_or_array_layers: 1,
+        };
+        let create_texture = |label, format, usage| {
+            let texture = device.create_texture(&wgpu::TextureDescriptor {
+                label: Some(label),
+                size: extent,
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format,
+                usage,
+                view_formats: &[],
+            });
+            let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+            (texture, view)
+        };
+        let color_usage =
+            wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING;
+        let (color_texture, color_view) =
+            create_texture("Checkerboard Color Texture", self.format, color_usage);
+        let (stencil_texture, stencil_view) = create_texture(
+            "Checkerboard Stencil Texture",
+            STENCIL_FORMAT,
+            wgpu::TextureUsages::RENDER_ATTACHMENT,
+        );
+        let (history_texture_0, history_view_0) =
+            create_texture("Checkerboard History Texture", self.format, color_usage);
+        let (history_texture_1, history_view_1) =
+            create_texture("Checkerboard History Texture", self.format, color_usage);
+        let history_views = [history_view_0, history_view_1];
+
+        let bind_groups = [0, 1].map(|read| {
+            device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("Checkerboard Bind Group"),
+                layout: &self.bind_group_layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::TextureView(&color_view),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::TextureView(&history_views[read]),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: self.params_buffer.as_entire_binding(),
+                    },
+                ],
+            })
+        });
+
+        // The mask never changes, it's written once for the size
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Checkerboard Mask Encoder"),
+        });
+        {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Checkerboard Mask Pass"),
+                color_attachments: &[],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: &stencil_view,
+                    depth_ops: None,
+                    stencil_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(0),
+                        store: true,
+                    }),
+                }),
+            });
+            render_pass.set_pipeline(&self.mask_pipeline);
+            render_pass.set_stencil_reference(1);
+            render_pass.draw(0..3, 0..1);
+        }
+        queue.submit(Some(encoder.finish()));
+
+        Targets {
+            size,
+            _color_texture: color_texture,
+            color_view,
+            _stencil_texture: stencil_texture,
+            stencil_view,
+            _history_textures: [history_texture_0, history_texture_1],
+            history_views,
+            bind_groups,
+            frames: 0,
+        }
+    }
+}
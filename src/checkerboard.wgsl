@@ -0,0 +1,67 @@
+// Checkerboard rendering: every frame only half of the 2x2 pixel quads are drawn, alternating
+// between frames. The missing quads are reconstructed from the previous frame, clamped to the
+// pixels drawn around them so moving particles don't leave ghosts, or from those pixels alone
+// when there is no previous frame.
+
+fn quad_parity(coords: vec2<u32>) -> u32 {
+    return ((coords.x >> 1u) + (coords.y >> 1u)) & 1u;
+}
+
+// Writes the stencil mask, the quads of parity 1 are left for the stencil reference
+@fragment
+fn fs_mask(in: FullscreenOutput) {
+    if quad_parity(vec2<u32>(in.clip_position.xy)) != 1u {
+        discard;
+    }
+}
+
+// Must match CheckerboardParams in checkerboard.rs
+struct CheckerboardParams {
+    parity: u32,
+    history_valid: u32,
+    _padding0: u32,
+    _padding1: u32,
+};
+
+@group(0) @binding(0)
+var current: texture_2d<f32>;
+@group(0) @binding(1)
+var history: texture_2d<f32>;
+@group(0) @binding(2)
+var<uniform> params: CheckerboardParams;
+
+fn load_current(coords: vec2<i32>) -> vec4<f32> {
+    let max_coords = vec2<i32>(textureDimensions(current)) - 1;
+    return textureLoad(current, clamp(coords, vec2<i32>(0), max_coords), 0);
+}
+
+struct Reconstructed {
+    @location(0) color: vec4<f32>,
+    @location(1) history: vec4<f32>,
+};
+
+@fragment
+fn fs_reconstruct(in: FullscreenOutput) -> Reconstructed {
+    let coords = vec2<i32>(in.clip_position.xy);
+    var color = textureLoad(current, coords, 0);
+    if quad_parity(vec2<u32>(coords)) != params.parity {
+        // The closest pixel in each direction is in a quad drawn this frame
+        let in_quad = coords & vec2<i32>(1);
+        let before = coords - 1 - in_quad;
+        let after = coords + 2 - in_quad;
+        let left = load_current(vec2<i32>(before.x, coords.y));
+        let right = load_current(vec2<i32>(after.x, coords.y));
+        let up = load_current(vec2<i32>(coords.x, before.y));
+        let down = load_current(vec2<i32>(coords.x, after.y));
+        color = (left + right + up + down) * 0.25;
+        if params.history_valid != 0u {
+            let low = min(min(left, right), min(up, down));
+            let high = max(max(left, right), max(up, down));
+            color = clamp(textureLoad(history, coords, 0), low, high);
+        }
+    }
+    var out: Reconstructed;
+    out.color = color;
+    out.history = color;
+    return out;
+}
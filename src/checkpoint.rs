@@ -0,0 +1,89 @@
+//! Periodic copies of GPU-only simulation state back to the CPU, so there is something recent to
+//! resume from if the device is lost.
+//!
+//! The copies are mapped asynchronously and picked up on a later frame, so taking a checkpoint
+//! never stalls rendering.
+
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+pub struct Checkpoint {
+    staging_buffer: wgpu::Buffer,
+    sizes: Vec<u64>,
+    ready: Arc<AtomicBool>,
+    pending: bool,
+}
+
+impl Checkpoint {
+    /// Staging space for buffers of the given `sizes`, in bytes.
+    pub fn new(device: &wgpu::Device, sizes: Vec<u64>) -> Self {
+        let staging_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Checkpoint Staging Buffer"),
+            size: sizes.iter().sum(),
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+        Self {
+            staging_buffer,
+            sizes,
+            ready: Arc::new(AtomicBool::new(false)),
+            pending: false,
+        }
+    }
+
+    pub fn pending(&self) -> bool {
+        self.pending
+    }
+
+    /// Copies the start of each of `sources`, as much as the sizes given to [`Checkpoint::new`].
+    pub fn start(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, sources: &[&wgpu::Buffer]) {
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Checkpoint Encoder"),
+        });
+        let mut offset = 0;
+        for (source, &size) in sources.iter().zip(&self.sizes) {
+            encoder.copy_buffer_to_buffer(source, 0, &self.staging_buffer, offset, size);
+            offset += size;
+        }
+        queue.submit(Some(encoder.finish()));
+
+        let ready = self.ready.clone();
+        self.staging_buffer
+            .slice(..)
+            .map_async(wgpu::MapMode::Read, move |result| {
+                if result.is_ok() {
+                    ready.store(true, Ordering::Release);
+                }
+            });
+        self.pending = true;
+    }
+
+    /// Returns the contents of every source once the copy started last is readable.
+    pub fn try_take(&mut self, device: &wgpu::Device) -> Option<Vec<Vec<u8>>> {
+        if !self.pending {
+            return None;
+        }
+        device.poll(wgpu::Maintain::Poll);
+        if !self.ready.swap(false, Ordering::Acquire) {
+            return None;
+        }
+
+        let contents = {
+            let mapped = self.staging_buffer.slice(..).get_mapped_range();
+            let mut offset = 0;
+            self.sizes
+                .iter()
+                .map(|&size| {
+                    let range = offset as usize..(offset + size) as usize;
+                    offset += size;
+                    mapped[range].to_vec()
+                })
+                .collect()
+        };
+        self.staging_buffer.unmap();
+        self.pending = false;
+        Some(contents)
+    }
+}
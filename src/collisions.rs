@@ -0,0 +1,236 @@
+//! Collisions between the particles, enabled by a positive `collision_radius` in [`SimParams`].
+//!
+//! Before every step, particles closing in on each other bounce apart, losing speed according to
+//! the restitution, and overlapping particles are pushed apart so dense clusters spread out
+//! instead of collapsing. The step then moves the particles with their new speeds.
+//!
+//! On the GPU the neighbors come from the [`SpatialHash`], on the CPU from a grid of the same cell
+//! size.
+
+use std::collections::HashMap;
+
+use glam::{IVec3, Vec3};
+use rayon::prelude::{IntoParallelIterator, ParallelIterator};
+
+use crate::{
+    shader_check,
+    sim_params::SimParams,
+    spatial_hash::{SpatialHash, NEIGHBORS_WGSL},
+    speed_layout::SpeedLayout,
+};
+
+// Must match `@workgroup_size` of main in collisions.wgsl
+const WORKGROUP_SIZE: u32 = 64;
+// Fraction of their overlap particles get rid of every frame. Must match RELAXATION in
+// collisions.wgsl
+const RELAXATION: f32 = 0.5;
+
+/// Returns the speeds of the particles after colliding. Must match `main` in collisions.wgsl.
+pub fn collide(params: &SimParams, positions: &[Vec3], speeds: &[Vec3]) -> Vec<Vec3> {
+    let contact = 2.0 * params.collision_radius;
+    let cell_of = |position: Vec3| (position / contact).floor().as_ivec3();
+    let mut cells = HashMap::<IVec3, Vec<usize>>::new();
+    for (index, &position) in positions.iter().enumerate() {
+        cells.entry(cell_of(position)).or_default().push(index);
+    }
+
+    (0..positions.len())
+        .into_par_iter()
+        .map(|index| {
+            let (position, speed) = (positions[index], speeds[index]);
+            let mut response = Vec3::ZERO;
+            let cell = cell_of(position);
+            let offsets = (0..27).map(|i| IVec3::new(i % 3, i / 3 % 3, i / 9) - 1);
+            for other in offsets
+                .filter_map(|offset| cells.get(&(cell + offset)))
+                .flatten()
+                .copied()
+            {
+                let offset = position - positions[other];
+                let distance = offset.length();
+                if other == index || distance >= contact || distance == 0.0 {
+                    continue;
+                }
+                let normal = offset / distance;
+                let separating = (speed - speeds[other]).dot(normal);
+                let target_speed = (-separating * params.restitution)
+                    .max((contact - distance) * RELAXATION / params.dt);
+                if separating < target_speed {
+                    response += normal * (target_speed - separating) * 0.5;
+                }
+            }
+            speed + response
+        })
+        .collect()
+}
+
+/// Collides the particles on the GPU, writing their new speeds over the old ones.
+pub struct Collisions {
+    capacity: usize,
+    // Of the speeds, and of the resolved speeds
+    speed_layout: SpeedLayout,
+    spatial_hash: SpatialHash,
+    resolved_speeds_buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+    pipeline: wgpu::ComputePipeline,
+}
+
+impl Collisions {
+    /// Collides the first of the `capacity` particles of `position_buffer` and `cpu_data_buffer`,
+    /// laid out in `speed_layout`, with the parameters of `sim_params_buffer`. Returns `None` if the
+    /// device can't bind them in one piece.
+    pub fn new(
+        device: &wgpu::Device,
+        capacity: usize,
+        sim_params: &SimParams,
+        sim_params_buffer: &wgpu::Buffer,
+        position_buffer: &wgpu::Buffer,
+        cpu_data_buffer: &wgpu::Buffer,
+        speed_layout: SpeedLayout,
+    ) -> Option<Self> {
+        let spatial_hash = SpatialHash::new(
+            device,
+            capacity,
+            2.0 * sim_params.collision_radius,
+            position_buffer,
+        )?;
+
+        let resolved_speeds_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Resolved Speeds Buffer"),
+            size: (capacity.max(1) * speed_layout.stride()) as u64,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+
+        let storage_entry = |binding, read_only| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let bind_group_layout_entries = [
+            wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+            storage_entry(1, true),
+            storage_entry(2, true),
+            storage_entry(3, false),
+        ];
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Collisions Bind Group Layout"),
+            entries: &bind_group_layout_entries,
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Collisions Bind Group"),
+            layout: &bind_group_layout,
+            entries: &[
+                sim_params_buffer,
+                position_buffer,
+                cpu_data_buffer,
+                &resolved_speeds_buffer,
+            ]
+            .iter()
+            .enumerate()
+            .map(|(binding, buffer)| wgpu::BindGroupEntry {
+                binding: binding as u32,
+                resource: buffer.as_entire_binding(),
+            })
+            .collect::<Vec<_>>(),
+        });
+
+        let source = shader_source(speed_layout);
+        let shader =
+            shader_check::create_module(device, "Collisions Shader", "collisions.wgsl", &source);
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Collisions Pipeline Layout"),
+            bind_group_layouts: &[
+                &bind_group_layout,
+                spatial_hash.neighbors_bind_group_layout(),
+            ],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Collisions Pipeline"),
+            layout: Some(&pipeline_layout),
+            module: &shader,
+            entry_point: "main",
+        });
+
+        #[cfg(feature = "guardrails")]
+        {
+            let reflection = crate::guardrails::ShaderReflection::new("collisions.wgsl", &source);
+            reflection.check_bind_group_layout(0, &bind_group_layout_entries);
+            reflection.check_struct_size("SimParams", std::mem::size_of::<SimParams>());
+        }
+
+        Some(Self {
+            capacity,
+            speed_layout,
+            spatial_hash,
+            resolved_speeds_buffer,
+            bind_group,
+            pipeline,
+        })
+    }
+
+    /// Hashes the first `count` particles and collides them, with `sim_params` as written to the
+    /// buffer the collisions were created with. `cpu_data_buffer` must be the one they were
+    /// created with too.
+    pub fn encode(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        sim_params: &SimParams,
+        count: usize,
+        cpu_data_buffer: &wgpu::Buffer,
+    ) {
+        let count = count.min(self.capacity);
+        self.spatial_hash
+            .set_cell_size(2.0 * sim_params.collision_radius);
+        self.spatial_hash.encode(device, queue, encoder, count);
+
+        let groups = (count as u32).div_ceil(WORKGROUP_SIZE).max(1);
+        let max_groups = device.limits().max_compute_workgroups_per_dimension;
+        let (x, y) = (groups.min(max_groups), groups.div_ceil(max_groups));
+        #[cfg(feature = "guardrails")]
+        crate::guardrails::check_dispatch_coverage([x, y, 1], [WORKGROUP_SIZE, 1, 1], count);
+        {
+            let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("Collisions Pass"),
+            });
+            compute_pass.set_pipeline(&self.pipeline);
+            compute_pass.set_bind_group(0, &self.bind_group, &[]);
+            compute_pass.set_bind_group(1, self.spatial_hash.neighbors_bind_group(), &[]);
+            compute_pass.dispatch_workgroups(x, y, 1);
+        }
+        // Collisions read the speeds of the neighbors, they are only replaced once all collided
+        encoder.copy_buffer_to_buffer(
+            &self.resolved_speeds_buffer,
+            0,
+            cpu_data_buffer,
+            0,
+            (count * self.speed_layout.stride()) as u64,
+        );
+    }
+}
+
+/// The source of the shader, after the neighbor search and `speed_layout`.
+pub fn shader_source(speed_layout: SpeedLayout) -> String {
+    format!(
+        "{NEIGHBORS_WGSL}\n{}\n{}",
+        speed_layout.wgsl(),
+        include_str!("collisions.wgsl")
+    )
+}
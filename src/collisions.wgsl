@@ -0,0 +1,83 @@
+// Collision response between nearby particles: particles closing in on each other bounce apart,
+// and overlapping ones are pushed apart. Included after neighbors.wgsl and the SpeedLayout, see
+// speed_layout.rs.
+
+// Must match SimParams in sim_params.rs
+struct SimParams {
+    bounds_min: vec4<f32>,
+    bounds_max: vec4<f32>,
+    boundary: vec4<u32>,
+    dt: f32,
+    damping: f32,
+    speed_multiplier: f32,
+    attractor_strength: f32,
+    roi_min: vec4<f32>,
+    roi_max: vec4<f32>,
+    roi_substeps: u32,
+    coarse_interval: u32,
+    frame: u32,
+    collision_radius: f32,
+    restitution: f32,
+    obstacle_count: u32,
+    lifetime: f32,
+    impulse_radius: f32,
+    force: vec4<f32>,
+    impulse: vec4<f32>,
+};
+
+@group(0) @binding(0)
+var<uniform> sim: SimParams;
+
+@group(0) @binding(1)
+var<storage, read> positions: array<vec4<f32>>;
+
+// Speeds of the particles, in CpuData
+@group(0) @binding(2)
+var<storage, read> speeds: array<PackedSpeed>;
+
+@group(0) @binding(3)
+var<storage, read_write> resolved_speeds: array<PackedSpeed>;
+
+// Must match RELAXATION in collisions.rs
+const RELAXATION: f32 = 0.5;
+
+// Must match collide in collisions.rs
+@compute @workgroup_size(64)
+fn main(
+    @builtin(global_invocation_id) id: vec3<u32>,
+    @builtin(num_workgroups) workgroups: vec3<u32>,
+) {
+    // Large counts are dispatched in rows, a single dimension is limited to 65535 workgroups
+    let index = id.x + id.y * workgroups.x * 64u;
+    if index >= hash_params.count {
+        return;
+    }
+
+    let position = positions[index].xyz;
+    let own = unpack_speed(speeds[index]);
+    let speed = own.xyz;
+    let contact = 2.0 * sim.collision_radius;
+    var response = vec3<f32>(0.0);
+    let cell = hash_cell(position);
+    for (var i = 0u; i < 27u; i++) {
+        let range = cell_range(cell + neighbor_offset(i));
+        for (var j = range.x; j < range.y; j++) {
+            let other = sorted_indices[j];
+            let offset = position - positions[other].xyz;
+            let distance = length(offset);
+            if other == index || distance >= contact || distance == 0.0 {
+                continue;
+            }
+            let normal = offset / distance;
+            let separating = dot(speed - unpack_speed(speeds[other]).xyz, normal);
+            // Closing particles bounce back, overlapping ones separate within a few frames
+            let target_speed = max(-separating * sim.restitution,
+                (contact - distance) * RELAXATION / sim.dt);
+            if separating < target_speed {
+                // Equal masses, each particle takes half of the change
+                response += normal * (target_speed - separating) * 0.5;
+            }
+        }
+    }
+    resolved_speeds[index] = pack_speed(vec4<f32>(speed + response, own.w));
+}
@@ -0,0 +1,433 @@
+//! Color grading pass, mapping the final colors of the scene through a 3D lookup table.
+//!
+//! The table is loaded from the `.cube` file given with `--lut`, as exported by most grading
+//! tools, so a look tuned there applies as it is here. Without one, when it can't be read or
+//! without the `asset-loaders` feature, the table is neutral and grading changes nothing. While enabled, the scene is drawn into a texture
+//! owned here, then resolved into the render target through the table.
+
+#[cfg(feature = "asset-loaders")]
+use std::path::{Path, PathBuf};
+
+use bytemuck::{Pod, Zeroable};
+use glam::Vec3;
+use wgpu::util::DeviceExt;
+
+use crate::render_target::fullscreen_shader;
+
+// Entries per side the .cube format allows
+const MIN_SIZE: usize = 2;
+#[cfg(feature = "asset-loaders")]
+const MAX_SIZE: usize = 256;
+
+#[cfg(feature = "asset-loaders")]
+#[derive(Debug, thiserror::Error)]
+pub enum LutError {
+    #[error("unable to read {0}: {1}")]
+    Io(PathBuf, std::io::Error),
+    #[error("{0}, line {1}: {2}")]
+    Parse(PathBuf, usize, &'static str),
+    #[error("{0} has {1} entries, its LUT_3D_SIZE needs {2}")]
+    Entries(PathBuf, usize, usize),
+    #[error("{0} has a DOMAIN_MAX that isn't above its DOMAIN_MIN")]
+    Domain(PathBuf),
+}
+
+/// 3D lookup table of a `.cube` file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Lut {
+    /// Entries per side
+    size: usize,
+    domain_min: Vec3,
+    domain_max: Vec3,
+    /// Red changing fastest, then green, then blue
+    entries: Vec<Vec3>,
+}
+
+impl Lut {
+    /// Maps every color to itself. Two entries per side are exact, the texture is filtered
+    /// linearly.
+    pub fn neutral() -> Self {
+        let entries = (0..8)
+            .map(|i| Vec3::new((i & 1) as f32, (i >> 1 & 1) as f32, (i >> 2) as f32))
+            .collect();
+        Self {
+            size: MIN_SIZE,
+            domain_min: Vec3::ZERO,
+            domain_max: Vec3::ONE,
+            entries,
+        }
+    }
+}
+
+#[cfg(feature = "asset-loaders")]
+impl Lut {
+    pub fn load(path: &Path) -> Result<Self, LutError> {
+        let text = std::fs::read_to_string(path).map_err(|e| LutError::Io(path.to_owned(), e))?;
+        Self::parse(path, &text)
+    }
+
+    /// The table of `path`, or a neutral one if there's none or it can't be loaded.
+    pub fn load_or_neutral(path: Option<&Path>) -> Self {
+        let Some(path) = path else {
+            return Self::neutral();
+        };
+        match Self::load(path) {
+            Ok(lut) => {
+                log::info!(
+                    "Color grading with {} ({}³ entries)",
+                    path.display(),
+                    lut.size
+                );
+                lut
+            }
+            Err(e) => {
+                log::error!("Falling back to a neutral LUT: {e}");
+                Self::neutral()
+            }
+        }
+    }
+
+    fn parse(path: &Path, text: &str) -> Result<Self, LutError> {
+        let mut size = None;
+        let mut domain_min = Vec3::ZERO;
+        let mut domain_max = Vec3::ONE;
+        let mut entries = Vec::new();
+        for (index, line) in text.lines().enumerate() {
+            let error = |message| LutError::Parse(path.to_owned(), index + 1, message);
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (keyword, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+            let numbers =
+                |words: &str| vector(words).ok_or_else(|| error("expected three numbers"));
+            match keyword {
+                "TITLE" => {}
+                "LUT_1D_SIZE" => return Err(error("1D LUTs aren't supported")),
+                "LUT_3D_SIZE" => {
+                    let n = rest.trim().parse().ok();
+                    size = Some(
+                        n.filter(|n| (MIN_SIZE..=MAX_SIZE).contains(n))
+                            .ok_or_else(|| error("LUT_3D_SIZE must be from 2 to 256"))?,
+                    );
+                }
+                "DOMAIN_MIN" => domain_min = numbers(rest)?,
+                "DOMAIN_MAX" => domain_max = numbers(rest)?,
+                _ if keyword.starts_with(|c: char| c.is_ascii_alphabetic()) => {
+                    return Err(error("unknown keyword"));
+                }
+                _ => entries.push(numbers(line)?),
+            }
+        }
+        if domain_max.cmple(domain_min).any() {
+            return Err(LutError::Domain(path.to_owned()));
+        }
+
+        let size = size.ok_or_else(|| LutError::Parse(path.to_owned(), 1, "no LUT_3D_SIZE"))?;
+        if entries.len() != size.pow(3) {
+            return Err(LutError::Entries(
+                path.to_owned(),
+                entries.len(),
+                size.pow(3),
+            ));
+        }
+        Ok(Self {
+            size,
+            domain_min,
+            domain_max,
+            entries,
+        })
+    }
+}
+
+/// Three numbers separated by whitespace.
+#[cfg(feature = "asset-loaders")]
+fn vector(words: &str) -> Option<Vec3> {
+    let mut numbers = words.split_whitespace().map(|word| word.parse().ok());
+    let vector = Vec3::new(numbers.next()??, numbers.next()??, numbers.next()??);
+    numbers.next().is_none().then_some(vector)
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Pod, Zeroable)]
+struct GradingParams {
+    domain_min: [f32; 4],
+    domain_max: [f32; 4],
+}
+
+struct Target {
+    size: (u32, u32),
+    // Kept alive for the view
+    _texture: wgpu::Texture,
+    view: wgpu::TextureView,
+    bind_group: wgpu::BindGroup,
+}
+
+pub struct ColorGrading {
+    enabled: bool,
+    lut: Lut,
+    format: wgpu::TextureFormat,
+    pipeline: wgpu::RenderPipeline,
+    bind_group_layout: wgpu::BindGroupLayout,
+    lut_view: wgpu::TextureView,
+    sampler: wgpu::Sampler,
+    params_buffer: wgpu::Buffer,
+    target: Option<Target>,
+}
+
+impl ColorGrading {
+    pub fn new(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        format: wgpu::TextureFormat,
+        lut: Lut,
+        enabled: bool,
+    ) -> Self {
+        let bind_group_layout_entries = [
+            wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 1,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    view_dimension: wgpu::TextureViewDimension::D3,
+                    multisampled: false,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 2,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 3,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+        ];
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Color Grading Bind Group Layout"),
+            entries: &bind_group_layout_entries,
+        });
+
+        let source = fullscreen_shader(include_str!("color_grading.wgsl"));
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Color Grading Shader"),
+            source: wgpu::ShaderSource::Wgsl(source.as_str().into()),
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Color Grading Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Color Grading Pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_fullscreen",
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: None,
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+
+        #[cfg(feature = "guardrails")]
+        crate::guardrails::ShaderReflection::new("color_grading.wgsl", &source)
+            .check_bind_group_layout(0, &bind_group_layout_entries);
+
+        // 8 bits per channel are filterable everywhere, and as precise as the surface
+        let texels: Vec<[u8; 4]> = lut
+            .entries
+            .iter()
+            .map(|entry| {
+                let [r, g, b] = (entry.clamp(Vec3::ZERO, Vec3::ONE) * 255.0)
+                    .round()
+                    .to_array()
+                    .map(|channel| channel as u8);
+                [r, g, b, 255]
+            })
+            .collect();
+        let size = lut.size as u32;
+        let lut_texture = device.create_texture_with_data(
+            queue,
+            &wgpu::TextureDescriptor {
+                label: Some("Color Grading LUT"),
+                size: wgpu::Extent3d {
+                    width: size,
+                    height: size,
+                    depth_or_array_layers: size,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D3,
+                format: wgpu::TextureFormat::Rgba8Unorm,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING,
+                view_formats: &[],
+            },
+            bytemuck::cast_slice(&texels),
+        );
+        let lut_view = lut_texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Color Grading Sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        let params = GradingParams {
+            domain_min: lut.domain_min.extend(0.0).to_array(),
+            domain_max: lut.domain_max.extend(1.0).to_array(),
+        };
+        let params_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Color Grading Params Buffer"),
+            contents: bytemuck::cast_slice(&[params]),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+
+        Self {
+            enabled,
+            lut,
+            format,
+            pipeline,
+            bind_group_layout,
+            lut_view,
+            sampler,
+            params_buffer,
+            target: None,
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn toggle(&mut self) {
+        self.enabled = !self.enabled;
+        if !self.enabled {
+            self.target = None;
+        }
+    }
+
+    pub fn lut(&self) -> &Lut {
+        &self.lut
+    }
+
+    /// Makes sure the scene texture is `size`, if enabled. Must be called before
+    /// [`ColorGrading::view`] and [`ColorGrading::resolve`] every time the scene is drawn.
+    pub fn prepare(&mut self, device: &wgpu::Device, size: (u32, u32)) {
+        if self.enabled && self.target.as_ref().map(|target| target.size) != Some(size) {
+            self.target = Some(self.create_target(device, size));
+        }
+    }
+
+    /// View the scene should be drawn into, once prepared.
+    pub fn view(&self) -> Option<&wgpu::TextureView> {
+        Some(&self.target.as_ref()?.view)
+    }
+
+    /// Grades the scene drawn into [`ColorGrading::view`] into `view`.
+    pub fn resolve(&self, encoder: &mut wgpu::CommandEncoder, view: &wgpu::TextureView) {
+        let Some(target) = &self.target else {
+            return;
+        };
+
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Color Grading Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                    store: true,
+                },
+            })],
+            depth_stencil_attachment: None,
+        });
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &target.bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+
+    fn create_target(&self, device: &wgpu::Device, size: (u32, u32)) -> Target {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Color Grading Texture"),
+            size: wgpu::Extent3d {
+                width: size.0,
+                height: size.1,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: self.format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Color Grading Bind Group"),
+            layout: &self.bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&self.lut_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::Sampler(&self.sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: self.params_buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        Target {
+            size,
+            _texture: texture,
+            view,
+            bind_group,
+        }
+    }
+}
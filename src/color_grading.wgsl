@@ -0,0 +1,41 @@
+// Color grading: maps every pixel of the scene through a 3D lookup table, in gamma encoded sRGB as
+// .cube files expect
+
+struct GradingParams {
+    domain_min: vec4<f32>,
+    domain_max: vec4<f32>,
+};
+
+@group(0) @binding(0)
+var scene: texture_2d<f32>;
+@group(0) @binding(1)
+var lut: texture_3d<f32>;
+@group(0) @binding(2)
+var lut_sampler: sampler;
+@group(0) @binding(3)
+var<uniform> params: GradingParams;
+
+fn linear_to_srgb(linear: vec3<f32>) -> vec3<f32> {
+    let low = linear * 12.92;
+    let high = 1.055 * pow(linear, vec3<f32>(1.0 / 2.4)) - 0.055;
+    return select(high, low, linear <= vec3<f32>(0.0031308));
+}
+
+fn srgb_to_linear(srgb: vec3<f32>) -> vec3<f32> {
+    let low = srgb / 12.92;
+    let high = pow((srgb + 0.055) / 1.055, vec3<f32>(2.4));
+    return select(high, low, srgb <= vec3<f32>(0.04045));
+}
+
+@fragment
+fn fs_main(in: FullscreenOutput) -> @location(0) vec4<f32> {
+    let color = textureLoad(scene, vec2<i32>(in.clip_position.xy), 0);
+    let srgb = linear_to_srgb(clamp(color.rgb, vec3<f32>(0.0), vec3<f32>(1.0)));
+    let domain = params.domain_max.xyz - params.domain_min.xyz;
+    let coords = clamp((srgb - params.domain_min.xyz) / domain, vec3<f32>(0.0), vec3<f32>(1.0));
+    // The first and last entries are at the centers of the edge texels
+    let size = vec3<f32>(textureDimensions(lut));
+    let uvw = coords * (size - 1.0) / size + 0.5 / size;
+    let graded = textureSampleLevel(lut, lut_sampler, uvw, 0.0).rgb;
+    return vec4<f32>(srgb_to_linear(clamp(graded, vec3<f32>(0.0), vec3<f32>(1.0))), color.a);
+}
@@ -0,0 +1,252 @@
+//! Compaction of the alive particles on the GPU, so draw work scales with them only.
+//!
+//! A compute pass copies the alive instances next to each other, counting them with an atomic in
+//! the arguments of an indirect draw. Slots are taken in whatever order the invocations run, so
+//! the blending order of overlapping particles can change from frame to frame.
+
+use std::ops::Range;
+
+use bytemuck::{Pod, Zeroable};
+use wgpu::util::DeviceExt;
+
+use crate::{
+    shader_check,
+    vertex::{
+        DrawnPosition, InstanceColor, InstancePosition, INSTANCE_COLOR_WGSL, INSTANCE_POSITION_WGSL,
+    },
+};
+
+// Must match `@workgroup_size` in compaction.wgsl
+const WORKGROUP_SIZE: u32 = 64;
+const ALIVE_BITS_PER_WORD: usize = 32;
+
+// Must match Params in compaction.wgsl
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Pod, Zeroable)]
+struct CompactionParams {
+    capacity: u32,
+    _padding: [u32; 3],
+}
+
+pub struct Compaction {
+    capacity: usize,
+    // Ranges the alive bits were last uploaded for
+    alive_ranges: Vec<Range<usize>>,
+    alive_buffer: wgpu::Buffer,
+    position_buffer: wgpu::Buffer,
+    color_buffer: wgpu::Buffer,
+    indirect_buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+    pipeline: wgpu::ComputePipeline,
+}
+
+impl Compaction {
+    /// Compacts the `capacity` instances of `position_buffer` and `color_buffer`, drawn with
+    /// `index_count` indices each. Returns `None` if the device can't bind the compacted buffers.
+    pub fn new(
+        device: &wgpu::Device,
+        capacity: usize,
+        index_count: u32,
+        position_buffer: &wgpu::Buffer,
+        color_buffer: &wgpu::Buffer,
+    ) -> Option<Self> {
+        let position_size = (capacity * std::mem::size_of::<InstancePosition>()) as u64;
+        let drawn_size = (capacity * std::mem::size_of::<DrawnPosition>()) as u64;
+        let color_size = (capacity * std::mem::size_of::<InstanceColor>()) as u64;
+        let limits = device.limits();
+        let max_size = (limits.max_storage_buffer_binding_size as u64).min(limits.max_buffer_size);
+        if position_size.max(color_size) > max_size {
+            return None;
+        }
+
+        let alive_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Alive Buffer"),
+            size: (capacity.div_ceil(ALIVE_BITS_PER_WORD).max(1) * std::mem::size_of::<u32>())
+                as u64,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let compacted_buffer = |label, size| {
+            device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some(label),
+                size,
+                usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::STORAGE,
+                mapped_at_creation: false,
+            })
+        };
+        let compacted_position_buffer = compacted_buffer("Compacted Position Buffer", drawn_size);
+        let compacted_color_buffer = compacted_buffer("Compacted Color Buffer", color_size);
+
+        let draw_args = wgpu::util::DrawIndexedIndirect {
+            vertex_count: index_count,
+            instance_count: 0,
+            base_index: 0,
+            vertex_offset: 0,
+            base_instance: 0,
+        };
+        let indirect_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Compaction Indirect Buffer"),
+            contents: draw_args.as_bytes(),
+            usage: wgpu::BufferUsages::INDIRECT
+                | wgpu::BufferUsages::STORAGE
+                | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let params_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Compaction Params Buffer"),
+            contents: bytemuck::cast_slice(&[CompactionParams {
+                capacity: capacity as u32,
+                _padding: [0; 3],
+            }]),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+
+        let storage_entry = |binding, read_only| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let bind_group_layout_entries = [
+            storage_entry(0, true),
+            storage_entry(1, true),
+            storage_entry(2, true),
+            storage_entry(3, false),
+            storage_entry(4, false),
+            storage_entry(5, false),
+            wgpu::BindGroupLayoutEntry {
+                binding: 6,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+        ];
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Compaction Bind Group Layout"),
+            entries: &bind_group_layout_entries,
+        });
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Compaction Bind Group"),
+            layout: &bind_group_layout,
+            entries: &[
+                &alive_buffer,
+                position_buffer,
+                color_buffer,
+                &compacted_position_buffer,
+                &compacted_color_buffer,
+                &indirect_buffer,
+                &params_buffer,
+            ]
+            .iter()
+            .enumerate()
+            .map(|(binding, buffer)| wgpu::BindGroupEntry {
+                binding: binding as u32,
+                resource: buffer.as_entire_binding(),
+            })
+            .collect::<Vec<_>>(),
+        });
+
+        let source = shader_source();
+        let shader =
+            shader_check::create_module(device, "Compaction Shader", "compaction.wgsl", &source);
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Compaction Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Compaction Pipeline"),
+            layout: Some(&pipeline_layout),
+            module: &shader,
+            entry_point: "main",
+        });
+
+        #[cfg(feature = "guardrails")]
+        {
+            let reflection = crate::guardrails::ShaderReflection::new("compaction.wgsl", &source);
+            reflection.check_bind_group_layout(0, &bind_group_layout_entries);
+            reflection.check_struct_size("Params", std::mem::size_of::<CompactionParams>());
+            reflection.check_struct_size("DrawArgs", draw_args.as_bytes().len());
+        }
+
+        Some(Self {
+            capacity,
+            alive_ranges: vec![],
+            alive_buffer,
+            position_buffer: compacted_position_buffer,
+            color_buffer: compacted_color_buffer,
+            indirect_buffer,
+            bind_group,
+            pipeline,
+        })
+    }
+
+    /// Marks the instances of `ranges` as alive and all others as dead.
+    pub fn set_alive(&mut self, queue: &wgpu::Queue, ranges: &[Range<usize>]) {
+        if self.alive_ranges == ranges {
+            return;
+        }
+
+        let mut alive = vec![0u32; self.capacity.div_ceil(ALIVE_BITS_PER_WORD).max(1)];
+        for index in ranges.iter().flat_map(Range::clone) {
+            alive[index / ALIVE_BITS_PER_WORD] |= 1 << (index % ALIVE_BITS_PER_WORD);
+        }
+        queue.write_buffer(&self.alive_buffer, 0, bytemuck::cast_slice(&alive));
+        self.alive_ranges = ranges.to_vec();
+    }
+
+    /// Copies the alive instances to the compacted buffers and counts them.
+    pub fn encode(&self, device: &wgpu::Device, encoder: &mut wgpu::CommandEncoder) {
+        // Only the instance count is reset, the other arguments never change
+        let instance_count_offset = std::mem::size_of::<u32>() as u64;
+        encoder.clear_buffer(
+            &self.indirect_buffer,
+            instance_count_offset,
+            wgpu::BufferSize::new(std::mem::size_of::<u32>() as u64),
+        );
+
+        let groups = (self.capacity as u32).div_ceil(WORKGROUP_SIZE).max(1);
+        let max_groups = device.limits().max_compute_workgroups_per_dimension;
+        let (x, y) = (groups.min(max_groups), groups.div_ceil(max_groups));
+        #[cfg(feature = "guardrails")]
+        crate::guardrails::check_dispatch_coverage(
+            [x, y, 1],
+            [WORKGROUP_SIZE, 1, 1],
+            self.capacity,
+        );
+
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Compaction Pass"),
+        });
+        compute_pass.set_pipeline(&self.pipeline);
+        compute_pass.set_bind_group(0, &self.bind_group, &[]);
+        compute_pass.dispatch_workgroups(x, y, 1);
+    }
+
+    /// Draws the compacted instances, with the quad already bound to vertex slot 0.
+    pub fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        render_pass.set_vertex_buffer(1, self.position_buffer.slice(..));
+        render_pass.set_vertex_buffer(2, self.color_buffer.slice(..));
+        render_pass.draw_indexed_indirect(&self.indirect_buffer, 0);
+    }
+}
+
+/// The source of the shader, after the instance colors and positions.
+pub fn shader_source() -> String {
+    format!(
+        "{INSTANCE_COLOR_WGSL}\n{INSTANCE_POSITION_WGSL}\n{}",
+        include_str!("compaction.wgsl")
+    )
+}
@@ -0,0 +1,57 @@
+// Copies the alive instances next to each other and counts them into the indirect draw arguments
+
+struct Params {
+    capacity: u32,
+    _padding0: u32,
+    _padding1: u32,
+    _padding2: u32,
+}
+
+// Same layout as wgpu::util::DrawIndexedIndirect
+struct DrawArgs {
+    index_count: u32,
+    instance_count: atomic<u32>,
+    first_index: u32,
+    base_vertex: i32,
+    first_instance: u32,
+}
+
+// One bit per instance
+@group(0) @binding(0)
+var<storage, read> alive: array<u32>;
+
+@group(0) @binding(1)
+var<storage, read> positions: array<vec4<f32>>;
+
+// PackedColor is declared by INSTANCE_COLOR_WGSL in vertex.rs
+@group(0) @binding(2)
+var<storage, read> colors: array<PackedColor>;
+
+// PackedPosition is declared by INSTANCE_POSITION_WGSL in vertex.rs
+@group(0) @binding(3)
+var<storage, read_write> compacted_positions: array<PackedPosition>;
+
+@group(0) @binding(4)
+var<storage, read_write> compacted_colors: array<PackedColor>;
+
+@group(0) @binding(5)
+var<storage, read_write> draw_args: DrawArgs;
+
+@group(0) @binding(6)
+var<uniform> params: Params;
+
+@compute @workgroup_size(64)
+fn main(
+    @builtin(global_invocation_id) id: vec3<u32>,
+    @builtin(num_workgroups) workgroups: vec3<u32>,
+) {
+    // Large counts are dispatched in rows, a single dimension is limited to 65535 workgroups
+    let index = id.x + id.y * workgroups.x * 64u;
+    if index >= params.capacity || (alive[index / 32u] & (1u << (index % 32u))) == 0u {
+        return;
+    }
+
+    let slot = atomicAdd(&draw_args.instance_count, 1u);
+    compacted_positions[slot] = pack_position(positions[index]);
+    compacted_colors[slot] = colors[index];
+}
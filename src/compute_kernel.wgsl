@@ -1,31 +1,306 @@
+// Unpacked from PackedSpeed, of the SpeedLayout included before this file, see speed_layout.rs
 struct CpuData {
     speed: vec3<f32>,
+    // Set on the first substep: the dt of every substep of particles in the region of interest,
+    // minus the dt of the single step of the others moving this frame, 0 for the rest
+    step_dt: f32,
 }
 
-struct InstanceInput {
-    model_matrix_0: vec4<f32>,
-    model_matrix_1: vec4<f32>,
-    model_matrix_2: vec4<f32>,
-    model_matrix_3: vec4<f32>,
-    color: vec4<f32>,
+struct InstancePosition {
+    position: vec4<f32>,
+};
+
+// Must match TurbulenceParams in turbulence.rs
+struct TurbulenceParams {
+    time: f32,
+    frequency: f32,
+    amplitude: f32,
+    _padding: f32,
+};
+
+// Must match SimParams in sim_params.rs
+struct SimParams {
+    bounds_min: vec4<f32>,
+    bounds_max: vec4<f32>,
+    boundary: vec4<u32>,
+    dt: f32,
+    damping: f32,
+    speed_multiplier: f32,
+    attractor_strength: f32,
+    roi_min: vec4<f32>,
+    roi_max: vec4<f32>,
+    roi_substeps: u32,
+    coarse_interval: u32,
+    frame: u32,
+    collision_radius: f32,
+    restitution: f32,
+    obstacle_count: u32,
+    lifetime: f32,
+    impulse_radius: f32,
+    force: vec4<f32>,
+    impulse: vec4<f32>,
+};
+
+// Must match Boundary in sim_params.rs
+const BOUNCE: u32 = 0u;
+const CLAMP: u32 = 1u;
+const WRAP: u32 = 2u;
+
+// Must match DispatchParams in state.rs
+struct DispatchParams {
+    first_row: u32,
+    substep: u32,
+    _padding0: u32,
+    _padding1: u32,
 };
 
 @group(0) @binding(0)
-var<storage, read_write> cpu_data: array<CpuData>;
+var<storage, read_write> cpu_data: array<PackedSpeed>;
 
 @group(0) @binding(1)
-var<storage, read_write> instances: array<InstanceInput>;
-
-@compute @workgroup_size(1,1,1)
-fn main(@builtin(global_invocation_id) GlobalInvocationID : vec3<u32>) {
-    let index = GlobalInvocationID.x + (GlobalInvocationID.y * u32(10000));
-    var instance: InstanceInput = instances[index];
-    let model_matrix = mat4x4<f32>(
-        instance.model_matrix_0,
-        instance.model_matrix_1,
-        instance.model_matrix_2,
-        instance.model_matrix_3,
+var<storage, read_write> positions: array<InstancePosition>;
+
+@group(0) @binding(2)
+var<uniform> turbulence: TurbulenceParams;
+
+// Row this dispatch starts at, when a step is split across several dispatches
+@group(0) @binding(3)
+var<uniform> dispatch: DispatchParams;
+
+@group(0) @binding(4)
+var<uniform> sim: SimParams;
+
+// Of the struct in obstacles.wgsl, included before this file like `apply_behaviors`
+@group(0) @binding(5)
+var<storage, read> obstacles: array<Obstacle>;
+
+// Of wind.wgsl, included before this file too
+@group(0) @binding(6)
+var wind_field: texture_3d<f32>;
+
+@group(0) @binding(7)
+var<uniform> wind: WindParams;
+
+// Where particles respawn, of emitter.wgsl included before this file too
+@group(0) @binding(8)
+var<uniform> emitter: EmitterParams;
+
+// Must match the constants of SimParams::age in sim_params.rs and groups.rs
+const GOLDEN_RATIO_FRACTION: f32 = 0.618034;
+const MAX_AGE: f32 = 0.999;
+
+// Curl of (cos(a.y) sin(a.z), cos(a.z) sin(a.x), cos(a.x) sin(a.y)), without the frequency factor
+fn curl_octave(a: vec3<f32>) -> vec3<f32> {
+    let s = sin(a);
+    let c = cos(a);
+    return vec3<f32>(
+        c.x * c.y + s.z * s.x,
+        c.y * c.z + s.x * s.y,
+        c.z * c.x + s.y * s.z,
+    );
+}
+
+// Must match TurbulenceParams::velocity in turbulence.rs
+fn turbulence_velocity(position: vec3<f32>) -> vec3<f32> {
+    let t = turbulence.time;
+    let base = curl_octave(position * turbulence.frequency + vec3<f32>(t * 0.31, t * 0.23, t * 0.17));
+    let detail = curl_octave(
+        position * turbulence.frequency * 2.13
+            + vec3<f32>(1.7 - t * 0.19, 4.1 + t * 0.29, 2.3 + t * 0.37)
+    ) * 0.5;
+    return (base + detail) * turbulence.amplitude;
+}
+
+// Index of the particle of invocation `id` in the bound chunk, past the end of the buffer for the
+// invocations of the last workgroup of a row beyond the row width
+fn particle_index(id: vec3<u32>) -> u32 {
+    if id.x >= 10000u {
+        return 0xffffffffu;
+    }
+    return id.x + ((id.y + dispatch.first_row) * u32(10000));
+}
+
+fn load_cpu_data(index: u32) -> CpuData {
+    let data = unpack_speed(cpu_data[index]);
+    return CpuData(data.xyz, data.w);
+}
+
+fn store_cpu_data(index: u32, data: CpuData) {
+    cpu_data[index] = pack_speed(vec4<f32>(data.speed, data.step_dt));
+}
+
+// dt of the particle's step of `step_dt` on this substep, 0 if it doesn't move on it
+fn substep_dt(step_dt: f32) -> f32 {
+    if step_dt < 0.0 {
+        return select(0.0, -step_dt, dispatch.substep == 0u);
+    }
+    return step_dt;
+}
+
+// Ages the particle at `position` by the frame it moves over, in the fraction of the w of its
+// position, and respawns it at the emitter with a new `speed` at the end of its life. Returns its
+// position.
+// Must match SimParams::age in sim_params.rs and its use in state.rs
+fn age_particle(index: u32, position: vec3<f32>, speed: ptr<function, vec3<f32>>) -> vec3<f32> {
+    let w = positions[index].position.w;
+    if sim.lifetime <= 0.0 {
+        positions[index].position.w = floor(w);
+        return position;
+    }
+    var frame_dt = 0.0;
+    if all(position >= sim.roi_min.xyz) && all(position <= sim.roi_max.xyz) {
+        frame_dt = sim.dt;
+    } else if (index + sim.frame) % sim.coarse_interval == 0u {
+        frame_dt = sim.dt * f32(sim.coarse_interval);
+    }
+    let spread = fract(f32(index) * GOLDEN_RATIO_FRACTION) + 0.5;
+    let age = fract(w) + frame_dt / (sim.lifetime * spread);
+    if age < 1.0 {
+        positions[index].position.w = floor(w) + min(age, MAX_AGE);
+        return position;
+    }
+    rng_state = pcg(index ^ pcg(sim.frame));
+    let respawned = sample_emitter(emitter);
+    *speed = random_speed();
+    positions[index].position = vec4<f32>(respawned, floor(w));
+    return respawned;
+}
+
+// Must match SimParams::impulse_at in sim_params.rs
+fn impulse_at(position: vec3<f32>) -> vec3<f32> {
+    if sim.impulse.w == 0.0 {
+        return vec3<f32>(0.0);
+    }
+    let offset = position - sim.impulse.xyz;
+    let distance_squared = dot(offset, offset);
+    let direction = select(vec3<f32>(0.0), offset / sqrt(distance_squared), distance_squared > 0.0);
+    let falloff = 1.0 / (1.0 + distance_squared / (sim.impulse_radius * sim.impulse_radius));
+    return direction * sim.impulse.w * falloff;
+}
+
+// Every substep runs the three passes below in order, together they must match SimParams::substep
+// in sim_params.rs
+
+// Decides how the particle moves this frame, then updates its speed
+@compute @workgroup_size(64)
+fn accumulate_forces(@builtin(global_invocation_id) id: vec3<u32>) {
+    let index = particle_index(id);
+    if index >= arrayLength(&positions) {
+        return;
+    }
+    var position = positions[index].position.xyz;
+    var data = load_cpu_data(index);
+    // Must match SimParams::step in sim_params.rs
+    if dispatch.substep == 0u {
+        position = age_particle(index, position, &data.speed);
+        var step_dt = 0.0;
+        if all(position >= sim.roi_min.xyz) && all(position <= sim.roi_max.xyz) {
+            step_dt = sim.dt / f32(sim.roi_substeps);
+        } else if (index + sim.frame) % sim.coarse_interval == 0u {
+            step_dt = -sim.dt * f32(sim.coarse_interval);
+        }
+        data.step_dt = step_dt;
+        // Every particle feels the impulse, the ones not moving this frame included
+        data.speed += impulse_at(position);
+    }
+    let dt = substep_dt(data.step_dt);
+    if dt == 0.0 {
+        if dispatch.substep == 0u {
+            store_cpu_data(index, data);
+        }
+        return;
+    }
+
+    var v = data.speed * pow(1.0 - sim.damping, dt);
+    let to_center = select(vec3<f32>(0.0), normalize(position), length(position) > 0.0);
+    v -= to_center * sim.attractor_strength * dt;
+    v += sim.force.xyz * dt;
+    data.speed = v;
+    store_cpu_data(index, data);
+}
+
+// Moves the particle with its speed, the turbulence and the wind, pushing it out of the obstacles
+@compute @workgroup_size(64)
+fn integrate(@builtin(global_invocation_id) id: vec3<u32>) {
+    let index = particle_index(id);
+    if index >= arrayLength(&positions) {
+        return;
+    }
+    var data = load_cpu_data(index);
+    let dt = substep_dt(data.step_dt);
+    if dt == 0.0 {
+        return;
+    }
+
+    let position = positions[index].position.xyz;
+    var v = data.speed;
+    let flow = turbulence_velocity(position) + wind_velocity(position);
+    var moved = position + (v * sim.speed_multiplier + flow) * dt;
+
+    // Must match obstacles::bounce in obstacles.rs
+    for (var i = 0u; i < min(sim.obstacle_count, arrayLength(&obstacles)); i++) {
+        let obstacle = obstacles[i];
+        let distance = obstacle_distance(obstacle, moved);
+        if distance < 0.0 {
+            let normal = obstacle_normal(obstacle, moved);
+            moved -= normal * distance;
+            let into = dot(v, normal);
+            if into < 0.0 {
+                v -= 2.0 * into * normal;
+            }
+        }
+    }
+
+    data.speed = v;
+    store_cpu_data(index, data);
+    positions[index].position = vec4<f32>(moved, positions[index].position.w);
+}
+
+// Bounces, clamps or wraps the particle at the bounds, then runs the behaviors after its last
+// substep
+@compute @workgroup_size(64)
+fn apply_bounds(@builtin(global_invocation_id) id: vec3<u32>) {
+    let index = particle_index(id);
+    if index >= arrayLength(&positions) {
+        return;
+    }
+    var data = load_cpu_data(index);
+    // Declared before this file, see compute_kernel_source in state.rs. Even unmoved, the
+    // position may have been written from the CPU
+    if substep_dt(data.step_dt) == 0.0 {
+        store_drawn_position(index, positions[index].position);
+        return;
+    }
+
+    let moved = positions[index].position.xyz;
+    var v = data.speed;
+    let bounds_min = sim.bounds_min.xyz;
+    let bounds_max = sim.bounds_max.xyz;
+    let below = moved < bounds_min;
+    let above = moved > bounds_max;
+    let bounce = sim.boundary.xyz == vec3<u32>(BOUNCE);
+    v = select(v, abs(v), bounce & below);
+    v = select(v, -abs(v), bounce & above);
+    let clamped = sim.boundary.xyz == vec3<u32>(CLAMP);
+    v = select(v, vec3<f32>(0.0), clamped & (below | above));
+
+    let size = bounds_max - bounds_min;
+    let wrapped = moved - size * floor((moved - bounds_min) / size);
+    var position = select(
+        clamp(moved, bounds_min, bounds_max),
+        wrapped,
+        sim.boundary.xyz == vec3<u32>(WRAP),
     );
-    let speed = cpu_data[index].speed;
-    instances[index].model_matrix_3 = instance.model_matrix_3 + vec4<f32>(speed.x, speed.y, speed.z, 0.0);
+
+    let step_dt = data.step_dt;
+    if step_dt < 0.0 {
+        position = apply_behaviors(index, position, &v, turbulence.time, -step_dt);
+    } else if dispatch.substep + 1u == sim.roi_substeps {
+        position = apply_behaviors(index, position, &v, turbulence.time, sim.dt);
+    }
+
+    data.speed = v;
+    store_cpu_data(index, data);
+    positions[index].position = vec4<f32>(position, positions[index].position.w);
+    store_drawn_position(index, positions[index].position);
 }
@@ -0,0 +1,174 @@
+//! Debug views of the particles, to check instance positions without quad overdraw dominating the
+//! frame: quads drawn as wireframes, or a single point per particle. The heatmap shows that
+//! overdraw instead.
+
+use std::fmt::Display;
+
+use crate::{
+    accessibility::HeatmapColors,
+    capabilities::GpuCapabilities,
+    heatmap::Heatmap,
+    vertex::{DrawnPosition, InstanceColor, Vertex, INSTANCE_COLOR_WGSL},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DebugView {
+    #[default]
+    Off,
+    /// Quad edges, needs `Features::POLYGON_MODE_LINE`
+    Wireframe,
+    Points,
+    /// Fragments shaded per pixel, needs `DownlevelFlags::FRAGMENT_WRITABLE_STORAGE`
+    Heatmap,
+}
+
+impl Display for DebugView {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DebugView::Off => write!(f, "off"),
+            DebugView::Wireframe => write!(f, "wireframe"),
+            DebugView::Points => write!(f, "points"),
+            DebugView::Heatmap => write!(f, "heatmap"),
+        }
+    }
+}
+
+pub struct DebugPipelines {
+    wireframe: Option<wgpu::RenderPipeline>,
+    points: wgpu::RenderPipeline,
+    heatmap: Option<Heatmap>,
+}
+
+impl DebugPipelines {
+    pub fn new(
+        device: &wgpu::Device,
+        capabilities: &GpuCapabilities,
+        format: wgpu::TextureFormat,
+        camera_bind_group_layout: &wgpu::BindGroupLayout,
+    ) -> Self {
+        let source = format!("{INSTANCE_COLOR_WGSL}\n{}", include_str!("shader.wgsl"));
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Debug Shader"),
+            source: wgpu::ShaderSource::Wgsl(source.as_str().into()),
+        });
+
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Debug Pipeline Layout"),
+            bind_group_layouts: &[camera_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let create_pipeline =
+            |label, entry_point, buffers: &[wgpu::VertexBufferLayout], topology, polygon_mode| {
+                device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                    label: Some(label),
+                    layout: Some(&layout),
+                    vertex: wgpu::VertexState {
+                        module: &shader,
+                        entry_point,
+                        buffers,
+                    },
+                    fragment: Some(wgpu::FragmentState {
+                        module: &shader,
+                        entry_point: "fs_flat",
+                        targets: &[Some(wgpu::ColorTargetState {
+                            format,
+                            blend: None,
+                            write_mask: wgpu::ColorWrites::ALL,
+                        })],
+                    }),
+                    primitive: wgpu::PrimitiveState {
+                        topology,
+                        polygon_mode,
+                        ..Default::default()
+                    },
+                    depth_stencil: None,
+                    multisample: wgpu::MultisampleState::default(),
+                    multiview: None,
+                })
+            };
+
+        let wireframe = capabilities.polygon_mode_line.then(|| {
+            create_pipeline(
+                "Wireframe Pipeline",
+                "vs_main",
+                &[
+                    Vertex::descriptor(),
+                    DrawnPosition::descriptor(),
+                    InstanceColor::descriptor(),
+                ],
+                wgpu::PrimitiveTopology::TriangleList,
+                wgpu::PolygonMode::Line,
+            )
+        });
+        let points = create_pipeline(
+            "Point Pipeline",
+            "vs_point",
+            &[DrawnPosition::descriptor(), InstanceColor::descriptor()],
+            wgpu::PrimitiveTopology::PointList,
+            wgpu::PolygonMode::Fill,
+        );
+
+        #[cfg(feature = "guardrails")]
+        crate::guardrails::ShaderReflection::new("shader.wgsl", &source).check_vertex_buffers(
+            "vs_point",
+            &[DrawnPosition::descriptor(), InstanceColor::descriptor()],
+        );
+
+        let heatmap = capabilities
+            .fragment_writable_storage
+            .then(|| Heatmap::new(device, format, camera_bind_group_layout));
+
+        Self {
+            wireframe,
+            points,
+            heatmap,
+        }
+    }
+
+    /// The view after `view`, skipping the ones that aren't supported.
+    pub fn next(&self, view: DebugView) -> DebugView {
+        match view {
+            DebugView::Off if self.wireframe.is_some() => DebugView::Wireframe,
+            DebugView::Off | DebugView::Wireframe => DebugView::Points,
+            DebugView::Points if self.heatmap.is_some() => DebugView::Heatmap,
+            DebugView::Points | DebugView::Heatmap => DebugView::Off,
+        }
+    }
+
+    pub fn supports(&self, view: DebugView) -> bool {
+        match view {
+            DebugView::Off | DebugView::Points => true,
+            DebugView::Wireframe => self.wireframe.is_some(),
+            DebugView::Heatmap => self.heatmap.is_some(),
+        }
+    }
+
+    /// Pipeline drawing the particles for `view`, `None` when off or for the heatmap, which draws
+    /// them itself.
+    pub fn pipeline(&self, view: DebugView) -> Option<&wgpu::RenderPipeline> {
+        match view {
+            DebugView::Off | DebugView::Heatmap => None,
+            DebugView::Wireframe => self.wireframe.as_ref(),
+            DebugView::Points => Some(&self.points),
+        }
+    }
+
+    /// Sizes the heatmap for a view of `size` while it's shown.
+    pub fn prepare(&mut self, device: &wgpu::Device, view: DebugView, size: (u32, u32)) {
+        if let (Some(heatmap), DebugView::Heatmap) = (&mut self.heatmap, view) {
+            heatmap.prepare(device, size);
+        }
+    }
+
+    pub fn set_heatmap_colors(&mut self, colors: HeatmapColors) {
+        if let Some(heatmap) = &mut self.heatmap {
+            heatmap.set_colors(colors);
+        }
+    }
+
+    /// The heatmap, if `view` is it.
+    pub fn heatmap(&self, view: DebugView) -> Option<&Heatmap> {
+        self.heatmap.as_ref().filter(|_| view == DebugView::Heatmap)
+    }
+}
@@ -0,0 +1,351 @@
+//! Depth-of-field post effect, for a photographic look in screenshots.
+//!
+//! While enabled, the scene is drawn into textures owned here, with a depth buffer, then resolved
+//! into the render target by blurring each pixel according to how far it is from the focus
+//! distance. Clicking the scene focuses on whatever is under the cursor.
+
+use bytemuck::{Pod, Zeroable};
+
+use crate::render_target::{fullscreen_shader, DEPTH_FORMAT};
+
+// View-space depths are stored as `depth / (depth + DEPTH_SCALE)`, precise around this distance
+// without needing the camera's far plane, or 1 minus that with reverse-Z. Must match `DEPTH_SCALE`
+// in shader.wgsl
+const DEPTH_SCALE: f32 = 1000.0;
+// Blur radius, in pixels, of the most out-of-focus particles
+const MAX_RADIUS: f32 = 12.0;
+const DEFAULT_APERTURE: f32 = 0.5;
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Pod, Zeroable)]
+struct DofParams {
+    focus_depth: f32,
+    aperture: f32,
+    max_radius: f32,
+    depth_scale: f32,
+    reverse_z: u32,
+    _padding: [u32; 3],
+}
+
+struct Targets {
+    size: (u32, u32),
+    // Kept alive for the views
+    _color_texture: wgpu::Texture,
+    color_view: wgpu::TextureView,
+    depth_texture: wgpu::Texture,
+    depth_view: wgpu::TextureView,
+    bind_group: wgpu::BindGroup,
+}
+
+pub struct DepthOfField {
+    enabled: bool,
+    focus_depth: f32,
+    aperture: f32,
+    // Of the camera's DepthRange
+    reverse_z: bool,
+    format: wgpu::TextureFormat,
+    pipeline: wgpu::RenderPipeline,
+    bind_group_layout: wgpu::BindGroupLayout,
+    params_buffer: wgpu::Buffer,
+    targets: Option<Targets>,
+}
+
+impl DepthOfField {
+    pub fn new(
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+        focus_depth: f32,
+        reverse_z: bool,
+    ) -> Self {
+        let bind_group_layout_entries = [
+            wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 1,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Depth,
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 2,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+        ];
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Depth of Field Bind Group Layout"),
+            entries: &bind_group_layout_entries,
+        });
+
+        let source = fullscreen_shader(include_str!("depth_of_field.wgsl"));
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Depth of Field Shader"),
+            source: wgpu::ShaderSource::Wgsl(source.as_str().into()),
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Depth of Field Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Depth of Field Pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_fullscreen",
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: None,
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+
+        #[cfg(feature = "guardrails")]
+        crate::guardrails::ShaderReflection::new("depth_of_field.wgsl", &source)
+            .check_bind_group_layout(0, &bind_group_layout_entries);
+
+        let params_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Depth of Field Params Buffer"),
+            size: std::mem::size_of::<DofParams>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        Self {
+            enabled: false,
+            focus_depth,
+            aperture: DEFAULT_APERTURE,
+            reverse_z,
+            format,
+            pipeline,
+            bind_group_layout,
+            params_buffer,
+            targets: None,
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn toggle(&mut self) {
+        self.enabled = !self.enabled;
+        if !self.enabled {
+            self.targets = None;
+        }
+    }
+
+    pub fn aperture(&self) -> f32 {
+        self.aperture
+    }
+
+    pub fn scale_aperture(&mut self, factor: f32) {
+        self.aperture *= factor;
+    }
+
+    /// Makes sure the scene textures are `size` and uploads the current parameters. Must be called
+    /// before [`DepthOfField::views`] and [`DepthOfField::resolve`] every time the scene is drawn.
+    pub fn prepare(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, size: (u32, u32)) {
+        if self.targets.as_ref().map(|targets| targets.size) != Some(size) {
+            self.targets = Some(self.create_targets(device, size));
+        }
+        let params = DofParams {
+            focus_depth: self.focus_depth,
+            aperture: self.aperture,
+            max_radius: MAX_RADIUS,
+            depth_scale: DEPTH_SCALE,
+            reverse_z: self.reverse_z.into(),
+            _padding: [0; 3],
+        };
+        queue.write_buffer(&self.params_buffer, 0, bytemuck::cast_slice(&[params]));
+    }
+
+    /// Color and depth views the scene should be drawn into, once prepared.
+    pub fn views(&self) -> Option<(&wgpu::TextureView, &wgpu::TextureView)> {
+        let targets = self.targets.as_ref()?;
+        Some((&targets.color_view, &targets.depth_view))
+    }
+
+    /// Blurs the scene drawn into [`DepthOfField::views`] into `view`.
+    pub fn resolve(&self, encoder: &mut wgpu::CommandEncoder, view: &wgpu::TextureView) {
+        let Some(targets) = &self.targets else {
+            return;
+        };
+
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Depth of Field Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                    store: true,
+                },
+            })],
+            depth_stencil_attachment: None,
+        });
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &targets.bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+
+    /// Focuses on the depth last drawn at `pixel`. Returns the new focus distance, or `None` if
+    /// nothing was drawn there.
+    pub fn focus_at(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        pixel: (u32, u32),
+    ) -> Option<f32> {
+        let targets = self.targets.as_ref()?;
+        if pixel.0 >= targets.size.0 || pixel.1 >= targets.size.1 {
+            return None;
+        }
+
+        let readback_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Focus Readback Buffer"),
+            size: std::mem::size_of::<f32>() as u64,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Focus Encoder"),
+        });
+        encoder.copy_texture_to_buffer(
+            wgpu::ImageCopyTexture {
+                texture: &targets.depth_texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d {
+                    x: pixel.0,
+                    y: pixel.1,
+                    z: 0,
+                },
+                aspect: wgpu::TextureAspect::DepthOnly,
+            },
+            wgpu::ImageCopyBuffer {
+                buffer: &readback_buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: None,
+                    rows_per_image: None,
+                },
+            },
+            wgpu::Extent3d {
+                width: 1,
+                height: 1,
+                depth_or_array_layers: 1,
+            },
+        );
+        queue.submit(Some(encoder.finish()));
+
+        let (sender, receiver) = futures::channel::oneshot::channel();
+        readback_buffer
+            .slice(..)
+            .map_async(wgpu::MapMode::Read, move |result| {
+                let _ = sender.send(result);
+            });
+        device.poll(wgpu::Maintain::Wait);
+        futures::executor::block_on(receiver).ok()?.ok()?;
+
+        let depth =
+            bytemuck::pod_read_unaligned::<f32>(&readback_buffer.slice(..).get_mapped_range());
+        readback_buffer.unmap();
+        // Cleared to the far plane, nothing there
+        let depth = if self.reverse_z { 1.0 - depth } else { depth };
+        if depth >= 1.0 {
+            return None;
+        }
+        self.focus_depth = DEPTH_SCALE * depth / (1.0 - depth);
+        Some(self.focus_depth)
+    }
+
+    fn create_targets(&self, device: &wgpu::Device, size: (u32, u32)) -> Targets {
+        let extent = wgpu::Extent3d {
+            width: size.0,
+            height: size.1,
+            depth_or_array_layers: 1,
+        };
+        let color_texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Depth of Field Color Texture"),
+            size: extent,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: self.format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+        let depth_texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Depth of Field Depth Texture"),
+            size: extent,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: DEPTH_FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                | wgpu::TextureUsages::TEXTURE_BINDING
+                | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
+        let color_view = color_texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let depth_view = depth_texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Depth of Field Bind Group"),
+            layout: &self.bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&color_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&depth_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: self.params_buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        Targets {
+            size,
+            _color_texture: color_texture,
+            color_view,
+            depth_texture,
+            depth_view,
+            bind_group,
+        }
+    }
+}
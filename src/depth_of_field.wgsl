@@ -0,0 +1,61 @@
+// Depth-of-field post effect: blurs every pixel by its circle of confusion, gathered from the
+// pixels around it
+
+struct DofParams {
+    focus_depth: f32,
+    aperture: f32,
+    max_radius: f32,
+    depth_scale: f32,
+    // Non-zero when the depth is reversed, see `remap_depth` in shader.wgsl
+    reverse_z: u32,
+    _padding0: u32,
+    _padding1: u32,
+    _padding2: u32,
+};
+
+@group(0) @binding(0)
+var scene: texture_2d<f32>;
+@group(0) @binding(1)
+var scene_depth: texture_depth_2d;
+@group(0) @binding(2)
+var<uniform> params: DofParams;
+
+const SAMPLES: i32 = 48;
+// Spreads the samples evenly over the disc
+const GOLDEN_ANGLE: f32 = 2.39996323;
+
+// Inverse of the depth written by `fs_depth` in shader.wgsl
+fn view_depth(coords: vec2<i32>) -> f32 {
+    let stored = textureLoad(scene_depth, coords, 0);
+    let depth = min(select(stored, 1.0 - stored, params.reverse_z != 0u), 0.999999);
+    return params.depth_scale * depth / (1.0 - depth);
+}
+
+// Circle of confusion radius in pixels
+fn circle_of_confusion(depth: f32) -> f32 {
+    let blur = params.aperture * abs(depth - params.focus_depth) / depth;
+    return min(blur, 1.0) * params.max_radius;
+}
+
+@fragment
+fn fs_main(in: FullscreenOutput) -> @location(0) vec4<f32> {
+    let max_coords = vec2<i32>(textureDimensions(scene)) - 1;
+    let center = vec2<i32>(in.clip_position.xy);
+    let radius = circle_of_confusion(view_depth(center));
+
+    var sum = textureLoad(scene, center, 0);
+    var weight = 1.0;
+    for (var i = 1; i < SAMPLES; i++) {
+        let distance = radius * sqrt(f32(i) / f32(SAMPLES));
+        let angle = f32(i) * GOLDEN_ANGLE;
+        let offset = vec2<i32>(round(vec2<f32>(cos(angle), sin(angle)) * distance));
+        let coords = clamp(center + offset, vec2<i32>(0), max_coords);
+        // Only samples blurry enough to reach this pixel contribute, so sharp particles don't
+        // bleed into the blurred background around them
+        let reach = circle_of_confusion(view_depth(coords));
+        let sample_weight = clamp(reach - distance + 1.0, 0.0, 1.0);
+        sum += textureLoad(scene, coords, 0) * sample_weight;
+        weight += sample_weight;
+    }
+    return sum / weight;
+}
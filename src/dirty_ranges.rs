@@ -0,0 +1,69 @@
+use std::ops::Range;
+
+/// Keeps track of which contiguous ranges of a buffer changed since the last upload, so only
+/// those regions need to be uploaded.
+///
+/// Ranges are in elements, not bytes. Overlapping or touching ranges are merged together.
+#[derive(Debug, Default)]
+pub struct DirtyRanges {
+    ranges: Vec<Range<usize>>,
+}
+
+impl DirtyRanges {
+    pub fn mark(&mut self, range: Range<usize>) {
+        if range.is_empty() {
+            return;
+        }
+
+        // Fast path: ranges are usually marked in increasing order
+        if let Some(last) = self.ranges.last_mut() {
+            if range.start >= last.start {
+                if range.start <= last.end {
+                    last.end = last.end.max(range.end);
+                } else {
+                    self.ranges.push(range);
+                }
+                return;
+            }
+        }
+
+        let index = self
+            .ranges
+            .partition_point(|existing| existing.start < range.start);
+        self.ranges.insert(index, range);
+        self.coalesce();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+
+    pub fn drain(&mut self) -> impl Iterator<Item = Range<usize>> + '_ {
+        self.ranges.drain(..)
+    }
+
+    fn coalesce(&mut self) {
+        let mut merged: Vec<Range<usize>> = Vec::with_capacity(self.ranges.len());
+        for range in self.ranges.drain(..) {
+            match merged.last_mut() {
+                Some(last) if range.start <= last.end => last.end = last.end.max(range.end),
+                _ => merged.push(range),
+            }
+        }
+        self.ranges = merged;
+    }
+}
+
+/// What was uploaded to the GPU during a frame.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct UploadStats {
+    pub bytes: u64,
+    pub writes: u32,
+}
+
+impl UploadStats {
+    pub fn record(&mut self, bytes: usize) {
+        self.bytes += bytes as u64;
+        self.writes += 1;
+    }
+}
@@ -0,0 +1,173 @@
+//! Built-in initial distributions of the particles, from `--distribution`, instead of spawning them
+//! in the emitter.
+//!
+//! Like meshes, they're turned into a point cloud centered on the origin, drawn from the scene's
+//! seed. The galaxy is a disk of logarithmic spiral arms around a bulge, tilted towards the camera.
+//! Its particles orbit the axis of the disk: with the n-body simulation, as fast as the gravity of
+//! the particles closer to the center keeps them on a circle, otherwise at the speed of spawned
+//! particles.
+
+use std::{f32::consts::TAU, fmt::Display, str::FromStr};
+
+use glam::{Quat, Vec3};
+use rand::Rng;
+
+use crate::{emitter::random_direction, nbody::NbodyParams, point_cloud::PointCloud, state};
+
+// About the size of the point clouds
+const RADIUS: f32 = 450.0;
+// As fast as particles spawned in the emitter, see random_speed in state.rs
+const SPEED: f32 = 0.2;
+
+// Share of the galaxy's particles in the bulge, then of the disk's in the arms
+const BULGE_SHARE: f32 = 0.2;
+const ARM_SHARE: f32 = 0.7;
+const ARMS: u32 = 2;
+// Angle between the arms and the circles around the center
+const ARM_PITCH: f32 = 0.3;
+// Standard deviations of the particles around their arm, in radians, and across the disk
+const ARM_SPREAD: f32 = 0.25;
+const DISK_THICKNESS: f32 = 0.03 * RADIUS;
+const BULGE_RADIUS: f32 = 0.15 * RADIUS;
+// Of the disk towards the camera, around X
+const GALAXY_TILT: f32 = -1.0;
+
+// Radii of the torus, around its axis and of its tube
+const TORUS_RADIUS: f32 = 0.7 * RADIUS;
+const TUBE_RADIUS: f32 = 0.25 * RADIUS;
+// Of the sphere shell, inwards from its radius
+const SHELL_THICKNESS: f32 = 0.05 * RADIUS;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Distribution {
+    /// Spiral arms and a bulge, orbiting
+    Galaxy,
+    /// A thin layer under the surface of a sphere
+    Shell,
+    /// Filling a ring around Z
+    Torus,
+}
+
+impl Display for Distribution {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Distribution::Galaxy => write!(f, "galaxy"),
+            Distribution::Shell => write!(f, "shell"),
+            Distribution::Torus => write!(f, "torus"),
+        }
+    }
+}
+
+impl FromStr for Distribution {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "galaxy" => Ok(Distribution::Galaxy),
+            "shell" => Ok(Distribution::Shell),
+            "torus" => Ok(Distribution::Torus),
+            _ => Err(()),
+        }
+    }
+}
+
+impl Distribution {
+    /// `count` particles, orbiting under `nbody` if given.
+    pub fn generate(
+        self,
+        count: usize,
+        nbody: Option<&NbodyParams>,
+        rng: &mut impl Rng,
+    ) -> PointCloud {
+        let (positions, speeds) = match self {
+            Distribution::Galaxy => galaxy(count, nbody, rng),
+            Distribution::Shell => (0..count)
+                .map(|_| {
+                    let radius = RADIUS - rng.gen::<f32>() * SHELL_THICKNESS;
+                    (random_direction(rng) * radius, state::random_speed(rng))
+                })
+                .unzip(),
+            Distribution::Torus => (0..count)
+                .map(|_| (torus_point(rng), state::random_speed(rng)))
+                .unzip(),
+        };
+        log::info!("Spawning {count} particles in a {self}");
+        PointCloud {
+            positions,
+            colors: None,
+            speeds: Some(speeds),
+        }
+    }
+}
+
+fn galaxy(count: usize, nbody: Option<&NbodyParams>, rng: &mut impl Rng) -> (Vec<Vec3>, Vec<Vec3>) {
+    let tilt = Quat::from_rotation_x(GALAXY_TILT);
+    let axis = tilt * Vec3::Z;
+    let positions = (0..count)
+        .map(|_| {
+            let position = if rng.gen::<f32>() < BULGE_SHARE {
+                random_direction(rng) * (gaussian(rng) * BULGE_RADIUS).abs()
+            } else {
+                // Denser towards the center
+                let radius = RADIUS * rng.gen::<f32>().powf(0.75);
+                let angle = if rng.gen::<f32>() < ARM_SHARE {
+                    let arm = rng.gen_range(0..ARMS) as f32 * TAU / ARMS as f32;
+                    let winding = (radius.max(BULGE_RADIUS) / BULGE_RADIUS).ln() / ARM_PITCH.tan();
+                    arm + winding + gaussian(rng) * ARM_SPREAD
+                } else {
+                    rng.gen::<f32>() * TAU
+                };
+                Vec3::new(
+                    radius * angle.cos(),
+                    radius * angle.sin(),
+                    gaussian(rng) * DISK_THICKNESS,
+                )
+            };
+            tilt * position
+        })
+        .collect::<Vec<_>>();
+
+    // The gravity of a particle pulls like the ones closer to the center all at the center
+    let mut order = (0..count).collect::<Vec<_>>();
+    order.sort_unstable_by(|&a, &b| positions[a].length().total_cmp(&positions[b].length()));
+    let mut speeds = vec![Vec3::ZERO; count];
+    for (inner, index) in order.into_iter().enumerate() {
+        let position = positions[index];
+        let speed = match nbody {
+            Some(nbody) => {
+                let distance_squared = position.length_squared();
+                let softened = distance_squared + nbody.softening * nbody.softening;
+                (nbody.gravity * nbody.time_scale * inner as f32 * distance_squared
+                    / (softened * softened.sqrt()))
+                .sqrt()
+            }
+            None => SPEED,
+        };
+        speeds[index] = axis.cross(position).normalize_or_zero() * speed;
+    }
+    (positions, speeds)
+}
+
+fn torus_point(rng: &mut impl Rng) -> Vec3 {
+    // The outside of the ring is larger, points are kept as often as their circle is long
+    loop {
+        let tube_angle = rng.gen::<f32>() * TAU;
+        let tube_distance = TUBE_RADIUS * rng.gen::<f32>().sqrt();
+        let distance = TORUS_RADIUS + tube_distance * tube_angle.cos();
+        if rng.gen::<f32>() * (TORUS_RADIUS + TUBE_RADIUS) > distance {
+            continue;
+        }
+        let angle = rng.gen::<f32>() * TAU;
+        return Vec3::new(
+            distance * angle.cos(),
+            distance * angle.sin(),
+            tube_distance * tube_angle.sin(),
+        );
+    }
+}
+
+// Standard normal, with the Box-Muller transform
+fn gaussian(rng: &mut impl Rng) -> f32 {
+    let u = 1.0 - rng.gen::<f32>();
+    (-2.0 * u.ln()).sqrt() * (rng.gen::<f32>() * TAU).cos()
+}
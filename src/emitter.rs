@@ -0,0 +1,205 @@
+//! Shapes particles are spawned in, each sampled uniformly.
+
+use std::{f32::consts::TAU, fmt::Display, str::FromStr};
+
+use bytemuck::{Pod, Zeroable};
+use glam::{Vec3, Vec4};
+use rand::Rng;
+
+/// Declares `EmitterParams` and `sample_emitter`, with the random generator they draw from.
+pub const EMITTER_WGSL: &str = include_str!("emitter.wgsl");
+
+// Center of the default shapes, in front of the initial camera
+const DEFAULT_CENTER: Vec3 = Vec3::new(0.0, 0.0, 400.0);
+const DEFAULT_RADIUS: f32 = 450.0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EmitterShape {
+    SphereSurface {
+        center: Vec3,
+        radius: f32,
+    },
+    Sphere {
+        center: Vec3,
+        radius: f32,
+    },
+    /// Flat disk facing `normal`
+    Disk {
+        center: Vec3,
+        radius: f32,
+        normal: Vec3,
+    },
+    Line {
+        start: Vec3,
+        end: Vec3,
+    },
+    Box {
+        min: Vec3,
+        max: Vec3,
+    },
+}
+
+impl Default for EmitterShape {
+    fn default() -> Self {
+        EmitterShape::Box {
+            min: Vec3::new(-425.0, -410.0, -100.0),
+            max: Vec3::new(425.0, 410.0, 900.0),
+        }
+    }
+}
+
+impl EmitterShape {
+    /// A uniformly distributed point of the shape.
+    pub fn sample(&self, rng: &mut impl Rng) -> Vec3 {
+        match *self {
+            EmitterShape::SphereSurface { center, radius } => {
+                center + random_direction(rng) * radius
+            }
+            EmitterShape::Sphere { center, radius } => {
+                // Volume grows with the cube of the radius
+                center + random_direction(rng) * radius * rng.gen::<f32>().cbrt()
+            }
+            EmitterShape::Disk {
+                center,
+                radius,
+                normal,
+            } => {
+                // Area grows with the square of the radius
+                let (u, v) = normal.normalize_or_zero().any_orthonormal_pair();
+                let distance = radius * rng.gen::<f32>().sqrt();
+                let angle = rng.gen::<f32>() * TAU;
+                center + (u * angle.cos() + v * angle.sin()) * distance
+            }
+            EmitterShape::Line { start, end } => start.lerp(end, rng.gen()),
+            EmitterShape::Box { min, max } => {
+                let t = Vec3::new(rng.gen(), rng.gen(), rng.gen());
+                min + (max - min) * t
+            }
+        }
+    }
+
+    /// Sets the point parameter `name` of the shape. Returns false if the shape doesn't have it.
+    pub fn set_point(&mut self, name: &str, value: Vec3) -> bool {
+        let point = match (self, name) {
+            (
+                EmitterShape::SphereSurface { center, .. }
+                | EmitterShape::Sphere { center, .. }
+                | EmitterShape::Disk { center, .. },
+                "center",
+            ) => center,
+            (EmitterShape::Disk { normal, .. }, "normal") => normal,
+            (EmitterShape::Line { start, .. }, "start") => start,
+            (EmitterShape::Line { end, .. }, "end") => end,
+            (EmitterShape::Box { min, .. }, "min") => min,
+            (EmitterShape::Box { max, .. }, "max") => max,
+            _ => return false,
+        };
+        *point = value;
+        true
+    }
+
+    /// Sets the radius of the shape. Returns false if the shape doesn't have one.
+    pub fn set_radius(&mut self, value: f32) -> bool {
+        match self {
+            EmitterShape::SphereSurface { radius, .. }
+            | EmitterShape::Sphere { radius, .. }
+            | EmitterShape::Disk { radius, .. } => {
+                *radius = value;
+                true
+            }
+            EmitterShape::Line { .. } | EmitterShape::Box { .. } => false,
+        }
+    }
+}
+
+/// An [`EmitterShape`] as the shaders sample it.
+// Must match EmitterParams in emitter.wgsl
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Pod, Zeroable)]
+pub struct EmitterParams {
+    a: Vec4,
+    b: Vec4,
+    c: Vec4,
+    shape: u32,
+    radius: f32,
+    _padding: [u32; 2],
+}
+
+impl EmitterParams {
+    pub fn new(emitter: &EmitterShape) -> Self {
+        let mut params = Self::zeroed();
+        // Shapes must match the constants of emitter.wgsl
+        match *emitter {
+            EmitterShape::SphereSurface { center, radius } => {
+                (params.shape, params.a, params.radius) = (0, center.extend(0.0), radius);
+            }
+            EmitterShape::Sphere { center, radius } => {
+                (params.shape, params.a, params.radius) = (1, center.extend(0.0), radius);
+            }
+            EmitterShape::Disk {
+                center,
+                radius,
+                normal,
+            } => {
+                let (u, v) = normal.normalize_or_zero().any_orthonormal_pair();
+                (params.shape, params.a, params.radius) = (2, center.extend(0.0), radius);
+                (params.b, params.c) = (u.extend(0.0), v.extend(0.0));
+            }
+            EmitterShape::Line { start, end } => {
+                (params.shape, params.a, params.b) = (3, start.extend(0.0), end.extend(0.0));
+            }
+            EmitterShape::Box { min, max } => {
+                (params.shape, params.a, params.b) = (4, min.extend(0.0), max.extend(0.0));
+            }
+        }
+        params
+    }
+}
+
+pub fn random_direction(rng: &mut impl Rng) -> Vec3 {
+    let z = rng.gen::<f32>() * 2.0 - 1.0;
+    let angle = rng.gen::<f32>() * TAU;
+    let r = (1.0 - z * z).sqrt();
+    Vec3::new(r * angle.cos(), r * angle.sin(), z)
+}
+
+impl Display for EmitterShape {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EmitterShape::SphereSurface { .. } => write!(f, "sphere-surface"),
+            EmitterShape::Sphere { .. } => write!(f, "sphere"),
+            EmitterShape::Disk { .. } => write!(f, "disk"),
+            EmitterShape::Line { .. } => write!(f, "line"),
+            EmitterShape::Box { .. } => write!(f, "box"),
+        }
+    }
+}
+
+/// Parses the name of a shape into that shape with its default dimensions.
+impl FromStr for EmitterShape {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "sphere-surface" => Ok(EmitterShape::SphereSurface {
+                center: DEFAULT_CENTER,
+                radius: DEFAULT_RADIUS,
+            }),
+            "sphere" => Ok(EmitterShape::Sphere {
+                center: DEFAULT_CENTER,
+                radius: DEFAULT_RADIUS,
+            }),
+            "disk" => Ok(EmitterShape::Disk {
+                center: DEFAULT_CENTER,
+                radius: DEFAULT_RADIUS,
+                normal: Vec3::Z,
+            }),
+            "line" => Ok(EmitterShape::Line {
+                start: DEFAULT_CENTER - Vec3::X * DEFAULT_RADIUS,
+                end: DEFAULT_CENTER + Vec3::X * DEFAULT_RADIUS,
+            }),
+            "box" => Ok(EmitterShape::default()),
+            _ => Err(()),
+        }
+    }
+}
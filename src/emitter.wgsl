@@ -0,0 +1,75 @@
+// Samples the emitter of the scene, with a hash-based random generator. Included before the
+// shaders spawning particles, which seed `rng_state` per particle.
+
+// Must match EmitterShape in emitter.rs, prefixed apart from the obstacle shapes
+const EMITTER_SPHERE_SURFACE: u32 = 0u;
+const EMITTER_SPHERE: u32 = 1u;
+const EMITTER_DISK: u32 = 2u;
+const EMITTER_LINE: u32 = 3u;
+const EMITTER_BOX: u32 = 4u;
+const TAU: f32 = 6.283185307;
+
+// Must match EmitterParams in emitter.rs
+struct EmitterParams {
+    // Center of spheres and disks, start of lines, min of boxes
+    a: vec4<f32>,
+    // End of lines, max of boxes, first axis of disks
+    b: vec4<f32>,
+    // Second axis of disks
+    c: vec4<f32>,
+    shape: u32,
+    radius: f32,
+    _padding0: u32,
+    _padding1: u32,
+}
+
+var<private> rng_state: u32;
+
+// PCG hash, see "Hash Functions for GPU Rendering" by Jarzynski and Olano
+fn pcg(value: u32) -> u32 {
+    let state = value * 747796405u + 2891336453u;
+    let word = ((state >> ((state >> 28u) + 4u)) ^ state) * 277803737u;
+    return (word >> 22u) ^ word;
+}
+
+// Uniform in [0, 1)
+fn random() -> f32 {
+    rng_state = pcg(rng_state);
+    return f32(rng_state >> 8u) / 16777216.0;
+}
+
+fn random_direction() -> vec3<f32> {
+    let z = random() * 2.0 - 1.0;
+    let angle = random() * TAU;
+    let r = sqrt(1.0 - z * z);
+    return vec3<f32>(r * cos(angle), r * sin(angle), z);
+}
+
+// Must match EmitterShape::sample in emitter.rs
+fn sample_emitter(emitter: EmitterParams) -> vec3<f32> {
+    if emitter.shape == EMITTER_SPHERE_SURFACE {
+        return emitter.a.xyz + random_direction() * emitter.radius;
+    }
+    if emitter.shape == EMITTER_SPHERE {
+        // Volume grows with the cube of the radius
+        let direction = random_direction();
+        return emitter.a.xyz + direction * emitter.radius * pow(random(), 1.0 / 3.0);
+    }
+    if emitter.shape == EMITTER_DISK {
+        // Area grows with the square of the radius
+        let distance = emitter.radius * sqrt(random());
+        let angle = random() * TAU;
+        return emitter.a.xyz + (emitter.b.xyz * cos(angle) + emitter.c.xyz * sin(angle)) * distance;
+    }
+    if emitter.shape == EMITTER_LINE {
+        return mix(emitter.a.xyz, emitter.b.xyz, random());
+    }
+    let t = vec3<f32>(random(), random(), random());
+    return emitter.a.xyz + (emitter.b.xyz - emitter.a.xyz) * t;
+}
+
+// Must match random_speed in state.rs
+fn random_speed() -> vec3<f32> {
+    let speed = vec3<f32>(random() - 0.5, random() - 0.5, random() - 0.5);
+    return normalize(speed) / 5.0;
+}
@@ -0,0 +1,47 @@
+//! Encoding of a frame's compute and render passes on two threads.
+//!
+//! wgpu encoders are `Send`, so the compute kernel's dispatches are recorded on the rayon pool
+//! while the main thread records the render passes. Both are submitted in order once joined: the
+//! compute submissions first, then the render encoder. `--serial-encoding` records them one after
+//! the other instead, to compare the encoding times in the frame log.
+
+/// A command buffer, with the buffer writes that must land right before it runs.
+pub struct Submission<'a> {
+    pub writes: Vec<(&'a wgpu::Buffer, Vec<u8>)>,
+    pub command_buffer: wgpu::CommandBuffer,
+}
+
+/// Submits `submissions` one at a time, after their writes.
+pub fn submit(queue: &wgpu::Queue, submissions: Vec<Submission>) {
+    for submission in submissions {
+        // Writes land before the next submission, so every command buffer sees its own
+        for (buffer, data) in &submission.writes {
+            queue.write_buffer(buffer, 0, data);
+        }
+        queue.submit(Some(submission.command_buffer));
+    }
+}
+
+/// Runs `compute` on the rayon pool while `render` runs on this thread, or both on this thread
+/// one after the other unless `parallel`.
+pub fn join<C: Send, R>(
+    parallel: bool,
+    compute: impl FnOnce() -> C + Send,
+    render: impl FnOnce() -> R,
+) -> (C, R) {
+    if !parallel {
+        let compute = tracing::info_span!("encode compute").in_scope(compute);
+        let render = tracing::info_span!("encode render").in_scope(render);
+        return (compute, render);
+    }
+
+    let mut compute_result = None;
+    // Under the frame's span, though on another thread
+    let span = tracing::info_span!("encode compute");
+    let render_result = rayon::in_place_scope(|scope| {
+        scope.spawn(|_| compute_result = Some(span.in_scope(compute)));
+        tracing::info_span!("encode render").in_scope(render)
+    });
+    // The scope waits for its tasks
+    (compute_result.unwrap(), render_result)
+}
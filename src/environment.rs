@@ -0,0 +1,473 @@
+//! Background behind the particles, instead of the flat clear color: a vertical gradient, or a
+//! skybox from six cubemap faces or an equirectangular image.
+//!
+//! Cubemaps are read from `px.png`, `nx.png`, `py.png`, `ny.png`, `pz.png` and `nz.png` in a
+//! directory. Equirectangular images are PNGs or Radiance HDR files. Like the particles, the
+//! environment is tinted by the day cycle.
+
+use std::{
+    fs::File,
+    io::{BufRead, BufReader, Read},
+    path::{Path, PathBuf},
+};
+
+use bytemuck::{Pod, Zeroable};
+use glam::{Vec3, Vec4};
+use wgpu::util::DeviceExt;
+
+use crate::half_float;
+
+// File names of the cubemap faces, in the layer order of wgpu
+const CUBE_FACES: [&str; 6] = ["px.png", "nx.png", "py.png", "ny.png", "pz.png", "nz.png"];
+
+#[derive(Debug, thiserror::Error)]
+pub enum EnvironmentError {
+    #[error("unable to read {0}: {1}")]
+    Io(PathBuf, std::io::Error),
+    #[error("unable to decode {0}: {1}")]
+    Png(PathBuf, png::DecodingError),
+    #[error("{0} isn't a Radiance HDR file with RLE pixels")]
+    Hdr(PathBuf),
+    #[error("cubemap faces must be square and the same size, {0} isn't")]
+    FaceSize(PathBuf),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum EnvironmentSource {
+    /// Blends from `bottom` looking down to `top` looking up
+    Gradient { top: Vec3, bottom: Vec3 },
+    /// Directory of the six faces
+    Cubemap(PathBuf),
+    /// PNG or Radiance HDR file
+    Equirect(PathBuf),
+}
+
+impl EnvironmentSource {
+    pub const DEFAULT_GRADIENT: Self = Self::Gradient {
+        top: Vec3::new(0.05, 0.08, 0.15),
+        bottom: Vec3::ZERO,
+    };
+
+    /// Sets the gradient color `name`, turning the source into a gradient. Returns false for
+    /// unknown colors.
+    pub fn set_color(&mut self, name: &str, color: Vec3) -> bool {
+        if !matches!(self, Self::Gradient { .. }) {
+            *self = Self::DEFAULT_GRADIENT;
+        }
+        let Self::Gradient { top, bottom } = self else {
+            unreachable!();
+        };
+        match name {
+            "top" => *top = color,
+            "bottom" => *bottom = color,
+            _ => return false,
+        }
+        true
+    }
+
+    /// Makes relative paths relative to `dir`.
+    pub fn resolve(&mut self, dir: &Path) {
+        if let Self::Cubemap(path) | Self::Equirect(path) = self {
+            if path.is_relative() {
+                *path = dir.join(&path);
+            }
+        }
+    }
+}
+
+// Must match Gradient in environment.wgsl
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Pod, Zeroable)]
+struct Gradient {
+    top: Vec4,
+    bottom: Vec4,
+}
+
+pub struct Environment {
+    source: EnvironmentSource,
+    bind_group: wgpu::BindGroup,
+    pipeline: wgpu::RenderPipeline,
+}
+
+impl Environment {
+    /// Loads the images of `source`, drawn into targets of `format` with the camera and lighting
+    /// of `camera_bind_group_layout`.
+    pub fn new(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        format: wgpu::TextureFormat,
+        camera_bind_group_layout: &wgpu::BindGroupLayout,
+        source: EnvironmentSource,
+    ) -> Result<Self, EnvironmentError> {
+        let texture_entry = |binding, view_dimension| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                view_dimension,
+                multisampled: false,
+            },
+            count: None,
+        };
+        let sampler_entry = wgpu::BindGroupLayoutEntry {
+            binding: 3,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+            count: None,
+        };
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Environment Sampler"),
+            address_mode_u: wgpu::AddressMode::Repeat,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        let (entry_point, bind_group_layout_entries, resource) = match &source {
+            EnvironmentSource::Gradient { top, bottom } => {
+                let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                    label: Some("Environment Gradient Buffer"),
+                    contents: bytemuck::bytes_of(&Gradient {
+                        top: top.extend(1.0),
+                        bottom: bottom.extend(1.0),
+                    }),
+                    usage: wgpu::BufferUsages::UNIFORM,
+                });
+                let entry = wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                };
+                ("fs_gradient", vec![entry], Resource::Buffer(buffer))
+            }
+            EnvironmentSource::Cubemap(dir) => {
+                let texture = load_cubemap(device, queue, dir)?;
+                let view = texture.create_view(&wgpu::TextureViewDescriptor {
+                    dimension: Some(wgpu::TextureViewDimension::Cube),
+                    ..Default::default()
+                });
+                let entries = vec![
+                    texture_entry(1, wgpu::TextureViewDimension::Cube),
+                    sampler_entry,
+                ];
+                ("fs_cubemap", entries, Resource::Texture(view))
+            }
+            EnvironmentSource::Equirect(path) => {
+                let texture = load_equirect(device, queue, path)?;
+                let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+                let entries = vec![
+                    texture_entry(2, wgpu::TextureViewDimension::D2),
+                    sampler_entry,
+                ];
+                ("fs_equirect", entries, Resource::Texture(view))
+            }
+        };
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Environment Bind Group Layout"),
+            entries: &bind_group_layout_entries,
+        });
+        let bind_group_entries = match &resource {
+            Resource::Buffer(buffer) => vec![wgpu::BindGroupEntry {
+                binding: 0,
+                resource: buffer.as_entire_binding(),
+            }],
+            Resource::Texture(view) => vec![
+                wgpu::BindGroupEntry {
+                    binding: bind_group_layout_entries[0].binding,
+                    resource: wgpu::BindingResource::TextureView(view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::Sampler(&sampler),
+                },
+            ],
+        };
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Environment Bind Group"),
+            layout: &bind_group_layout,
+            entries: &bind_group_entries,
+        });
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Environment Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("environment.wgsl").into()),
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Environment Pipeline Layout"),
+            bind_group_layouts: &[camera_bind_group_layout, &bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Environment Pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point,
+                targets: &[Some(format.into())],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+
+        #[cfg(feature = "guardrails")]
+        {
+            let reflection = crate::guardrails::ShaderReflection::new(
+                "environment.wgsl",
+                include_str!("environment.wgsl"),
+            );
+            reflection.check_struct_size("Gradient", std::mem::size_of::<Gradient>());
+            reflection.check_struct_size(
+                "CameraUniform",
+                std::mem::size_of::<crate::camera::CameraUniform>(),
+            );
+        }
+
+        Ok(Self {
+            source,
+            bind_group,
+            pipeline,
+        })
+    }
+
+    pub fn source(&self) -> &EnvironmentSource {
+        &self.source
+    }
+
+    /// Clears `view` with the environment seen through the camera of `camera_bind_group`.
+    pub fn encode(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        view: &wgpu::TextureView,
+        camera_bind_group: &wgpu::BindGroup,
+    ) {
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Environment Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                    store: true,
+                },
+            })],
+            depth_stencil_attachment: None,
+        });
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, camera_bind_group, &[]);
+        render_pass.set_bind_group(1, &self.bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
+
+enum Resource {
+    Buffer(wgpu::Buffer),
+    Texture(wgpu::TextureView),
+}
+
+/// RGBA8 pixels of the PNG at `path`, with its size.
+fn read_png(path: &Path) -> Result<(Vec<u8>, u32, u32), EnvironmentError> {
+    let file = File::open(path).map_err(|e| EnvironmentError::Io(path.to_owned(), e))?;
+    let mut decoder = png::Decoder::new(file);
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+    let mut reader = decoder
+        .read_info()
+        .map_err(|e| EnvironmentError::Png(path.to_owned(), e))?;
+    let mut pixels = vec![0; reader.output_buffer_size()];
+    let info = reader
+        .next_frame(&mut pixels)
+        .map_err(|e| EnvironmentError::Png(path.to_owned(), e))?;
+    pixels.truncate(info.buffer_size());
+
+    let rgba = match info.color_type {
+        png::ColorType::Rgba => pixels,
+        png::ColorType::Rgb => pixels
+            .chunks_exact(3)
+            .flat_map(|rgb| [rgb[0], rgb[1], rgb[2], 255])
+            .collect(),
+        png::ColorType::GrayscaleAlpha => pixels
+            .chunks_exact(2)
+            .flat_map(|ga| [ga[0], ga[0], ga[0], ga[1]])
+            .collect(),
+        // Palettes are expanded to RGB by the transformations
+        png::ColorType::Grayscale | png::ColorType::Indexed => {
+            pixels.iter().flat_map(|&g| [g, g, g, 255]).collect()
+        }
+    };
+    Ok((rgba, info.width, info.height))
+}
+
+fn load_cubemap(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    dir: &Path,
+) -> Result<wgpu::Texture, EnvironmentError> {
+    let mut size = None;
+    let mut pixels = vec![];
+    for face in CUBE_FACES {
+        let path = dir.join(face);
+        let (rgba, width, height) = read_png(&path)?;
+        if width != height || size.is_some_and(|size| size != width) {
+            return Err(EnvironmentError::FaceSize(path));
+        }
+        size = Some(width);
+        pixels.extend(rgba);
+    }
+    let size = size.unwrap_or_default();
+    Ok(device.create_texture_with_data(
+        queue,
+        &wgpu::TextureDescriptor {
+            label: Some("Environment Cubemap"),
+            size: wgpu::Extent3d {
+                width: size,
+                height: size,
+                depth_or_array_layers: CUBE_FACES.len() as u32,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba8UnormSrgb,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        },
+        &pixels,
+    ))
+}
+
+fn load_equirect(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    path: &Path,
+) -> Result<wgpu::Texture, EnvironmentError> {
+    let is_hdr = path
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("hdr"));
+    let (format, pixels, width, height) = if is_hdr {
+        let (rgb, width, height) = read_hdr(path)?;
+        // Half floats are filterable everywhere, unlike 32-bit floats
+        let pixels = rgb
+            .chunks_exact(3)
+            .flat_map(|rgb| [rgb[0], rgb[1], rgb[2], 1.0])
+            .flat_map(|value| half_float::to_bits(value).to_le_bytes())
+            .collect();
+        (wgpu::TextureFormat::Rgba16Float, pixels, width, height)
+    } else {
+        let (rgba, width, height) = read_png(path)?;
+        (wgpu::TextureFormat::Rgba8UnormSrgb, rgba, width, height)
+    };
+    Ok(device.create_texture_with_data(
+        queue,
+        &wgpu::TextureDescriptor {
+            label: Some("Environment Equirect"),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        },
+        &pixels,
+    ))
+}
+
+/// Linear RGB pixels of the Radiance HDR file at `path`, top row first, with its size. Only
+/// `-Y height +X width` images with run-length encoded scanlines are supported, as written by
+/// most tools.
+fn read_hdr(path: &Path) -> Result<(Vec<f32>, u32, u32), EnvironmentError> {
+    let invalid = || EnvironmentError::Hdr(path.to_owned());
+    let file = File::open(path).map_err(|e| EnvironmentError::Io(path.to_owned(), e))?;
+    let mut reader = BufReader::new(file);
+    let read_line = |reader: &mut BufReader<File>| {
+        let mut line = String::new();
+        reader
+            .read_line(&mut line)
+            .map_err(|e| EnvironmentError::Io(path.to_owned(), e))?;
+        Ok::<_, EnvironmentError>(line.trim_end().to_owned())
+    };
+
+    if !read_line(&mut reader)?.starts_with("#?") {
+        return Err(invalid());
+    }
+    // Header variables until an empty line
+    loop {
+        let line = read_line(&mut reader)?;
+        if line.is_empty() {
+            break;
+        }
+        if line.starts_with("FORMAT=") && line != "FORMAT=32-bit_rle_rgbe" {
+            return Err(invalid());
+        }
+    }
+    let resolution = read_line(&mut reader)?;
+    let (height, width) = match resolution.split_whitespace().collect::<Vec<_>>()[..] {
+        ["-Y", height, "+X", width] => (
+            height.parse::<u32>().map_err(|_| invalid())?,
+            width.parse::<u32>().map_err(|_| invalid())?,
+        ),
+        _ => return Err(invalid()),
+    };
+    if !(8..0x8000).contains(&width) {
+        return Err(invalid());
+    }
+
+    let mut data = vec![];
+    reader
+        .read_to_end(&mut data)
+        .map_err(|e| EnvironmentError::Io(path.to_owned(), e))?;
+    let mut data = data.into_iter();
+    let mut next = || data.next().ok_or_else(invalid);
+
+    let mut rgb = Vec::with_capacity(width as usize * height as usize * 3);
+    let mut scanline = vec![0u8; width as usize * 4];
+    for _ in 0..height {
+        let marker = [next()?, next()?, next()?, next()?];
+        if marker[..2] != [2, 2] || u16::from_be_bytes([marker[2], marker[3]]) as u32 != width {
+            return Err(invalid());
+        }
+        // Each channel of the scanline is encoded separately, as runs and literal spans
+        for channel in 0..4 {
+            let mut x = 0;
+            while x < width as usize {
+                let count = next()? as usize;
+                let (count, run) = match count {
+                    129.. => (count - 128, true),
+                    1.. => (count, false),
+                    0 => return Err(invalid()),
+                };
+                if x + count > width as usize {
+                    return Err(invalid());
+                }
+                let value = if run { next()? } else { 0 };
+                for _ in 0..count {
+                    scanline[x * 4 + channel] = if run { value } else { next()? };
+                    x += 1;
+                }
+            }
+        }
+        for rgbe in scanline.chunks_exact(4) {
+            let scale = match rgbe[3] {
+                0 => 0.0,
+                exponent => 2f32.powi(exponent as i32 - 136),
+            };
+            rgb.extend(rgbe[..3].iter().map(|&value| value as f32 * scale));
+        }
+    }
+    Ok((rgb, width, height))
+}
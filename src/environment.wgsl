@@ -0,0 +1,91 @@
+// Background behind the particles, a fullscreen triangle looking up the color of each view ray
+
+const PI: f32 = 3.14159265;
+const TAU: f32 = 6.283185307;
+
+// Must match CameraUniform in camera.rs
+struct CameraUniform {
+    view_proj: mat4x4<f32>,
+    eye: vec4<f32>,
+    inv_view_proj: mat4x4<f32>,
+    previous_view_proj: mat4x4<f32>,
+    view: mat4x4<f32>,
+    proj: mat4x4<f32>,
+    group_mask: u32,
+    reverse_z: u32,
+};
+@group(0) @binding(0)
+var<uniform> camera: CameraUniform;
+
+struct Lighting {
+    // w is unused
+    tint: vec4<f32>,
+    // Direction towards the light, w is the ambient light
+    light: vec4<f32>,
+};
+@group(0) @binding(1)
+var<uniform> lighting: Lighting;
+
+// Must match Gradient in environment.rs
+struct Gradient {
+    // w is unused
+    top: vec4<f32>,
+    bottom: vec4<f32>,
+};
+
+// Each kind of environment only binds what its fragment shader reads
+@group(1) @binding(0)
+var<uniform> gradient: Gradient;
+@group(1) @binding(1)
+var cubemap: texture_cube<f32>;
+@group(1) @binding(2)
+var equirect: texture_2d<f32>;
+@group(1) @binding(3)
+var environment_sampler: sampler;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    // View ray in homogeneous coordinates, divided by w once interpolated
+    @location(0) ray: vec4<f32>,
+};
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    // Covers the screen with (-1, -1), (3, -1) and (-1, 3)
+    let ndc = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u)) * 2.0 - 1.0;
+    // Through the near plane, the far one may be at infinity
+    let near_depth = select(0.0, 1.0, camera.reverse_z != 0u);
+    let near = camera.inv_view_proj * vec4<f32>(ndc, near_depth, 1.0);
+    var out: VertexOutput;
+    out.clip_position = vec4<f32>(ndc, 0.0, 1.0);
+    out.ray = vec4<f32>(near.xyz - camera.eye.xyz * near.w, near.w);
+    return out;
+}
+
+fn direction(in: VertexOutput) -> vec3<f32> {
+    return normalize(in.ray.xyz / in.ray.w);
+}
+
+@fragment
+fn fs_gradient(in: VertexOutput) -> @location(0) vec4<f32> {
+    let t = direction(in).y * 0.5 + 0.5;
+    let color = mix(gradient.bottom.rgb, gradient.top.rgb, t);
+    return vec4<f32>(color * lighting.tint.rgb, 1.0);
+}
+
+@fragment
+fn fs_cubemap(in: VertexOutput) -> @location(0) vec4<f32> {
+    let color = textureSample(cubemap, environment_sampler, direction(in)).rgb;
+    return vec4<f32>(color * lighting.tint.rgb, 1.0);
+}
+
+@fragment
+fn fs_equirect(in: VertexOutput) -> @location(0) vec4<f32> {
+    let ray = direction(in);
+    let uv = vec2<f32>(
+        atan2(ray.z, ray.x) / TAU + 0.5,
+        0.5 - asin(clamp(ray.y, -1.0, 1.0)) / PI,
+    );
+    let color = textureSample(equirect, environment_sampler, uv).rgb;
+    return vec4<f32>(color * lighting.tint.rgb, 1.0);
+}
@@ -0,0 +1,86 @@
+//! Errors that keep the demo from starting, printed instead of a panic.
+
+use std::path::PathBuf;
+
+#[cfg(feature = "audio")]
+use crate::audio::AudioError;
+#[cfg(feature = "scripting")]
+use crate::scripting::ScriptError;
+use crate::{
+    adapters::{AdapterSelector, Backend},
+    appearance::AppearanceError,
+    camera_path::CameraPathError,
+    capture::CaptureError,
+    instance_pool::PoolFull,
+    scene::SceneError,
+    shader_check::ShaderError,
+};
+#[cfg(feature = "asset-loaders")]
+use crate::{
+    mesh_emitter::MeshError, particle_data::ParticleDataError, point_cloud::PointCloudError,
+};
+
+#[derive(Debug, thiserror::Error)]
+pub enum AppError {
+    #[error("unable to create the window: {0}")]
+    Window(#[from] winit::error::OsError),
+    #[error("unable to create a surface for the window: {0}")]
+    Surface(#[from] wgpu::CreateSurfaceError),
+    #[error(
+        "no compatible adapter for {}, try another --backend or see --list-adapters",
+        backend_name(*.0)
+    )]
+    NoAdapter(Option<Backend>),
+    #[error("no adapter {0}, see --list-adapters")]
+    AdapterNotFound(AdapterSelector),
+    #[error("adapter {0} can't present to the window, pick another one with --adapter")]
+    AdapterCantPresent(AdapterSelector),
+    #[error("unable to open the GPU device: {0}, try another --backend or --adapter")]
+    Device(#[from] wgpu::RequestDeviceError),
+    #[error("the surface has no formats to render to, try another --backend")]
+    NoSurfaceFormat,
+    #[error(transparent)]
+    Scene(#[from] SceneError),
+    #[error(transparent)]
+    Appearance(#[from] AppearanceError),
+    #[error("unable to record to {0}: {1}")]
+    Record(PathBuf, CaptureError),
+    #[error("unable to write to {0}: {1}")]
+    Write(PathBuf, std::io::Error),
+    #[error("{0}: {1}")]
+    CameraPath(PathBuf, CameraPathError),
+    #[error("unable to read the schedule {0}: {1}")]
+    Schedule(PathBuf, std::io::Error),
+    #[cfg(feature = "asset-loaders")]
+    #[error(transparent)]
+    PointCloud(#[from] PointCloudError),
+    #[cfg(feature = "asset-loaders")]
+    #[error(transparent)]
+    ParticleData(#[from] ParticleDataError),
+    #[cfg(feature = "asset-loaders")]
+    #[error(transparent)]
+    Mesh(#[from] MeshError),
+    #[error(transparent)]
+    Pool(#[from] PoolFull),
+    #[error(transparent)]
+    Shader(#[from] ShaderError),
+    #[cfg(feature = "metrics")]
+    #[error("unable to export metrics: {0}")]
+    Metrics(std::io::Error),
+    #[cfg(feature = "metrics")]
+    #[error("unable to serve telemetry: {0}")]
+    Telemetry(std::io::Error),
+    #[cfg(feature = "scripting")]
+    #[error(transparent)]
+    Script(#[from] ScriptError),
+    #[cfg(feature = "audio")]
+    #[error(transparent)]
+    Audio(#[from] AudioError),
+}
+
+fn backend_name(backend: Option<Backend>) -> String {
+    match backend {
+        Some(backend) => format!("backend {backend}"),
+        None => "the primary backends".to_owned(),
+    }
+}
@@ -0,0 +1,146 @@
+//! Explore mode: random walk through the simulation parameters, to discover good-looking
+//! configurations without tuning each parameter by hand.
+
+use std::{io::Write, path::PathBuf};
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::{sim_params::SimParams, turbulence::TurbulenceParams};
+
+// Seconds between two mutations
+const MUTATION_INTERVAL: f32 = 5.0;
+// Largest change of a parameter in a single mutation, as a fraction of its range
+const MUTATION_SCALE: f32 = 0.25;
+
+/// Range a parameter is kept in while exploring.
+#[derive(Debug, Clone, Copy)]
+pub struct ParamRange {
+    pub min: f32,
+    pub max: f32,
+}
+
+impl ParamRange {
+    const fn new(min: f32, max: f32) -> Self {
+        Self { min, max }
+    }
+
+    fn mutate(&self, value: f32, rng: &mut impl Rng) -> f32 {
+        let step = (self.max - self.min) * MUTATION_SCALE;
+        (value + rng.gen_range(-step..=step)).clamp(self.min, self.max)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ExploreRanges {
+    pub turbulence_frequency: ParamRange,
+    pub turbulence_amplitude: ParamRange,
+    pub speed_multiplier: ParamRange,
+    pub damping: ParamRange,
+    pub attractor_strength: ParamRange,
+}
+
+impl ExploreRanges {
+    /// Nudges every explored parameter by a random amount, staying within the ranges.
+    pub fn mutate(
+        &self,
+        rng: &mut impl Rng,
+        turbulence: &mut TurbulenceParams,
+        sim_params: &mut SimParams,
+    ) {
+        turbulence.frequency = self.turbulence_frequency.mutate(turbulence.frequency, rng);
+        turbulence.amplitude = self.turbulence_amplitude.mutate(turbulence.amplitude, rng);
+        sim_params.speed_multiplier = self
+            .speed_multiplier
+            .mutate(sim_params.speed_multiplier, rng);
+        sim_params.damping = self.damping.mutate(sim_params.damping, rng);
+        sim_params.attractor_strength = self
+            .attractor_strength
+            .mutate(sim_params.attractor_strength, rng);
+    }
+}
+
+impl Default for ExploreRanges {
+    fn default() -> Self {
+        Self {
+            turbulence_frequency: ParamRange::new(0.002, 0.05),
+            turbulence_amplitude: ParamRange::new(0.0, 0.6),
+            speed_multiplier: ParamRange::new(0.2, 3.0),
+            damping: ParamRange::new(0.0, 0.01),
+            attractor_strength: ParamRange::new(-0.02, 0.02),
+        }
+    }
+}
+
+pub struct Explorer {
+    ranges: ExploreRanges,
+    enabled: bool,
+    since_mutation: f32,
+    rng: StdRng,
+    bookmarks_path: PathBuf,
+}
+
+impl Explorer {
+    pub fn new(ranges: ExploreRanges, bookmarks_path: impl Into<PathBuf>) -> Self {
+        Self {
+            ranges,
+            enabled: false,
+            since_mutation: 0.0,
+            rng: StdRng::from_entropy(),
+            bookmarks_path: bookmarks_path.into(),
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn toggle(&mut self) {
+        self.enabled = !self.enabled;
+        self.since_mutation = 0.0;
+    }
+
+    /// Mutates the parameters every few seconds while enabled. Returns true if they changed.
+    pub fn update(
+        &mut self,
+        dt: f32,
+        turbulence: &mut TurbulenceParams,
+        sim_params: &mut SimParams,
+    ) -> bool {
+        if !self.enabled {
+            return false;
+        }
+        self.since_mutation += dt;
+        if self.since_mutation < MUTATION_INTERVAL {
+            return false;
+        }
+        self.since_mutation = 0.0;
+
+        self.ranges.mutate(&mut self.rng, turbulence, sim_params);
+        true
+    }
+
+    /// Appends the parameter set to the bookmarks file.
+    pub fn bookmark(
+        &self,
+        turbulence: &TurbulenceParams,
+        sim_params: &SimParams,
+    ) -> std::io::Result<()> {
+        let mut file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.bookmarks_path)?;
+        writeln!(file, "{}", describe(turbulence, sim_params))
+    }
+}
+
+/// One line summary of the explored parameters.
+pub fn describe(turbulence: &TurbulenceParams, sim_params: &SimParams) -> String {
+    format!(
+        "frequency={:.4} amplitude={:.3} speed={:.3} damping={:.4} attractor={:.4}",
+        turbulence.frequency,
+        turbulence.amplitude,
+        sim_params.speed_multiplier,
+        sim_params.damping,
+        sim_params.attractor_strength
+    )
+}
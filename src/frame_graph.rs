@@ -0,0 +1,239 @@
+//! A small frame graph: the passes of a frame declare the resources they read and write, and the
+//! graph records them in an order that respects those accesses rather than the order of the code.
+//!
+//! A pass reading a resource runs after every pass writing it, unless it writes it as well: passes
+//! writing the same resource, like the draws stacking on the scene, keep the order they were added
+//! in. Textures a frame needs only while it's encoded are declared with [`FrameGraph::texture`] and
+//! taken from a [`TransientTextures`] pool, reused by the next frames with the same descriptor.
+
+use std::sync::Arc;
+
+/// Something a pass reads or writes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resource {
+    Positions,
+    Colors,
+    // Of the compute kernel, ages and speeds
+    CpuData,
+    // The positions drawn while the compute passes overlap with the draws
+    Snapshot,
+    // The drawn positions in halves, with the `packed-instances` feature
+    #[cfg(feature = "packed-instances")]
+    PackedPositions,
+    Trails,
+    Spins,
+    // What the scene gets drawn into, the surface at native resolution
+    Scene,
+    Surface,
+    #[cfg(feature = "metrics")]
+    Timestamps,
+    // Copies mapped after the frame, for the inspector, statistics and trajectories
+    Readbacks,
+    Texture(TextureHandle),
+}
+
+/// A texture of the graph, created when it's allocated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TextureHandle(usize);
+
+type Run<'a, C> = Box<dyn FnOnce(&mut C, &mut wgpu::CommandEncoder, &Transients) + 'a>;
+
+struct Pass<'a, C> {
+    name: &'static str,
+    reads: Vec<Resource>,
+    writes: Vec<Resource>,
+    run: Run<'a, C>,
+}
+
+/// Passes recording into an encoder, given a `C` to work with.
+pub struct FrameGraph<'a, C> {
+    passes: Vec<Pass<'a, C>>,
+    textures: Vec<TextureKey>,
+}
+
+impl<'a, C> Default for FrameGraph<'a, C> {
+    fn default() -> Self {
+        Self {
+            passes: vec![],
+            textures: vec![],
+        }
+    }
+}
+
+impl<'a, C> FrameGraph<'a, C> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Declares a texture living for the frame, created or reused on [`Self::allocate`].
+    pub fn texture(&mut self, descriptor: &wgpu::TextureDescriptor) -> TextureHandle {
+        self.textures.push(TextureKey::of(descriptor));
+        TextureHandle(self.textures.len() - 1)
+    }
+
+    /// Adds a pass reading `reads` and writing `writes`.
+    pub fn pass(
+        &mut self,
+        name: &'static str,
+        reads: &[Resource],
+        writes: &[Resource],
+        run: impl FnOnce(&mut C, &mut wgpu::CommandEncoder, &Transients) + 'a,
+    ) {
+        self.passes.push(Pass {
+            name,
+            reads: reads.to_vec(),
+            writes: writes.to_vec(),
+            run: Box::new(run),
+        });
+    }
+
+    /// Takes the textures of the graph from `pool`.
+    pub fn allocate(&self, device: &wgpu::Device, pool: &mut TransientTextures) -> Transients {
+        Transients {
+            textures: self
+                .textures
+                .iter()
+                .map(|key| pool.acquire(device, key))
+                .collect(),
+        }
+    }
+
+    /// Records the passes in order into `encoder`.
+    pub fn execute(
+        self,
+        context: &mut C,
+        encoder: &mut wgpu::CommandEncoder,
+        transients: &Transients,
+    ) {
+        let order = self.order();
+        let mut passes = self.passes.into_iter().map(Some).collect::<Vec<_>>();
+        for index in order {
+            let pass = passes[index].take().unwrap();
+            let _span = tracing::trace_span!("pass", name = pass.name).entered();
+            (pass.run)(context, encoder, transients);
+        }
+    }
+
+    /// Indices of the passes, each after the ones it depends on and otherwise in the order they
+    /// were added.
+    fn order(&self) -> Vec<usize> {
+        let count = self.passes.len();
+        let mut dependencies = vec![vec![]; count];
+        for (index, pass) in self.passes.iter().enumerate() {
+            for (other, earlier) in self.passes.iter().enumerate() {
+                let reads_its_writes = pass
+                    .reads
+                    .iter()
+                    .any(|read| !pass.writes.contains(read) && earlier.writes.contains(read));
+                let writes_after_it = other < index
+                    && pass
+                        .writes
+                        .iter()
+                        .any(|write| earlier.writes.contains(write));
+                if other != index && (reads_its_writes || writes_after_it) {
+                    dependencies[index].push(other);
+                }
+            }
+        }
+
+        let mut order = Vec::with_capacity(count);
+        let mut done = vec![false; count];
+        while order.len() < count {
+            let next = (0..count).find(|&index| {
+                !done[index]
+                    && dependencies[index]
+                        .iter()
+                        .all(|&dependency| done[dependency])
+            });
+            let Some(next) = next else {
+                log::error!(
+                    "Frame graph passes depend on each other, recording them as they were added"
+                );
+                return (0..count).collect();
+            };
+            done[next] = true;
+            order.push(next);
+        }
+        order
+    }
+}
+
+/// The textures of a frame graph, once allocated.
+pub struct Transients {
+    textures: Vec<Arc<wgpu::Texture>>,
+}
+
+impl Transients {
+    pub fn texture(&self, handle: TextureHandle) -> &Arc<wgpu::Texture> {
+        &self.textures[handle.0]
+    }
+}
+
+// Everything of a descriptor but its label, owned
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct TextureKey {
+    size: wgpu::Extent3d,
+    mip_level_count: u32,
+    sample_count: u32,
+    dimension: wgpu::TextureDimension,
+    format: wgpu::TextureFormat,
+    usage: wgpu::TextureUsages,
+}
+
+impl TextureKey {
+    fn of(descriptor: &wgpu::TextureDescriptor) -> Self {
+        Self {
+            size: descriptor.size,
+            mip_level_count: descriptor.mip_level_count,
+            sample_count: descriptor.sample_count,
+            dimension: descriptor.dimension,
+            format: descriptor.format,
+            usage: descriptor.usage,
+        }
+    }
+}
+
+// Frames a texture stays in the pool without being used
+const MAX_IDLE_FRAMES: u64 = 120;
+
+/// Textures of the previous frames, handed out again to the graphs asking for the same ones.
+#[derive(Default)]
+pub struct TransientTextures {
+    textures: Vec<(TextureKey, Arc<wgpu::Texture>, u64)>,
+    frame: u64,
+}
+
+impl TransientTextures {
+    /// A texture matching `key` that nothing holds anymore, or a new one.
+    fn acquire(&mut self, device: &wgpu::Device, key: &TextureKey) -> Arc<wgpu::Texture> {
+        let frame = self.frame;
+        let free = self
+            .textures
+            .iter_mut()
+            .find(|(other, texture, _)| other == key && Arc::strong_count(texture) == 1);
+        if let Some((_, texture, last_used)) = free {
+            *last_used = frame;
+            return texture.clone();
+        }
+        let texture = Arc::new(device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Transient Texture"),
+            size: key.size,
+            mip_level_count: key.mip_level_count,
+            sample_count: key.sample_count,
+            dimension: key.dimension,
+            format: key.format,
+            usage: key.usage,
+            view_formats: &[],
+        }));
+        self.textures.push((key.clone(), texture.clone(), frame));
+        texture
+    }
+
+    /// Drops the textures no frame used for a while.
+    pub fn end_frame(&mut self) {
+        self.frame += 1;
+        let frame = self.frame;
+        self.textures
+            .retain(|(_, _, last_used)| frame - last_used <= MAX_IDLE_FRAMES);
+    }
+}
@@ -0,0 +1,142 @@
+//! Hashes of rendered frames, computed on the GPU so only a few bytes per row are read back.
+//!
+//! Deterministic runs render the same frames, so comparing hashes run-to-run or across commits
+//! catches rendering regressions without storing full images.
+
+// Must match `@workgroup_size` in frame_hash.wgsl
+const WORKGROUP_SIZE: u32 = 64;
+
+pub struct FrameHasher {
+    pipeline: wgpu::ComputePipeline,
+    bind_group_layout: wgpu::BindGroupLayout,
+}
+
+impl FrameHasher {
+    pub fn new(device: &wgpu::Device) -> Self {
+        let bind_group_layout_entries = [
+            wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 1,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Storage { read_only: false },
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+        ];
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Frame Hash Bind Group Layout"),
+            entries: &bind_group_layout_entries,
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Frame Hash Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Frame Hash Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("frame_hash.wgsl").into()),
+        });
+
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Frame Hash Pipeline"),
+            layout: Some(&pipeline_layout),
+            module: &shader,
+            entry_point: "main",
+        });
+
+        #[cfg(feature = "guardrails")]
+        crate::guardrails::ShaderReflection::new(
+            "frame_hash.wgsl",
+            include_str!("frame_hash.wgsl"),
+        )
+        .check_bind_group_layout(0, &bind_group_layout_entries);
+
+        Self {
+            pipeline,
+            bind_group_layout,
+        }
+    }
+
+    /// Hash of the contents of `texture`, which needs the `TEXTURE_BINDING` usage.
+    pub fn hash(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        texture: &wgpu::Texture,
+    ) -> Result<u32, wgpu::BufferAsyncError> {
+        let size = (texture.height() as usize * std::mem::size_of::<u32>()) as u64;
+        let row_hash_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Row Hash Buffer"),
+            size,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let readback_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Row Hash Readback Buffer"),
+            size,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Frame Hash Bind Group"),
+            layout: &self.bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: row_hash_buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Frame Hash Encoder"),
+        });
+        {
+            let mut compute_pass = encoder.begin_compute_pass(&Default::default());
+            compute_pass.set_pipeline(&self.pipeline);
+            compute_pass.set_bind_group(0, &bind_group, &[]);
+            compute_pass.dispatch_workgroups(texture.height().div_ceil(WORKGROUP_SIZE), 1, 1);
+        }
+        encoder.copy_buffer_to_buffer(&row_hash_buffer, 0, &readback_buffer, 0, size);
+        queue.submit(Some(encoder.finish()));
+
+        let (sender, receiver) = futures::channel::oneshot::channel();
+        readback_buffer
+            .slice(..)
+            .map_async(wgpu::MapMode::Read, move |result| {
+                sender.send(result).unwrap();
+            });
+        device.poll(wgpu::Maintain::Wait);
+        futures::executor::block_on(receiver).unwrap()?;
+
+        // FNV-1a over the row hashes
+        let hash = bytemuck::cast_slice::<u8, u32>(&readback_buffer.slice(..).get_mapped_range())
+            .iter()
+            .fold(2_166_136_261u32, |hash, &row| {
+                (hash ^ row).wrapping_mul(16_777_619)
+            });
+        readback_buffer.unmap();
+        Ok(hash)
+    }
+}
@@ -0,0 +1,23 @@
+// One FNV-1a hash per row of the frame, over its pixels packed as RGBA8
+
+@group(0) @binding(0)
+var frame: texture_2d<f32>;
+
+@group(0) @binding(1)
+var<storage, read_write> row_hashes: array<u32>;
+
+@compute @workgroup_size(64)
+fn main(@builtin(global_invocation_id) id: vec3<u32>) {
+    let size = textureDimensions(frame);
+    let y = id.x;
+    if y >= size.y {
+        return;
+    }
+
+    var hash = 2166136261u;
+    for (var x = 0u; x < size.x; x++) {
+        let pixel = pack4x8unorm(textureLoad(frame, vec2<u32>(x, y), 0));
+        hash = (hash ^ pixel) * 16777619u;
+    }
+    row_hashes[y] = hash;
+}
@@ -0,0 +1,87 @@
+//! Per-frame CPU time, GPU time and particle count over a whole run, written to a CSV on exit or
+//! on demand to graph the performance of a session. The CPU time spent recording the passes is
+//! logged too, to compare parallel and `--serial-encoding`.
+//!
+//! GPU times come from the timestamp queries of the `metrics` feature. They are read back
+//! asynchronously, so they lag a few frames behind and are left empty for the frames that
+//! weren't measured.
+
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+const CSV_HEADER: &str = "time_s,frame,cpu_ms,encode_ms,gpu_ms,particles,overlap";
+
+struct FrameRecord {
+    time: Duration,
+    cpu_ms: f32,
+    encode_ms: f32,
+    gpu_ms: Option<f32>,
+    particles: usize,
+    // Whether the rendering overlapped with the compute passes, see overlap.rs
+    overlap: bool,
+}
+
+pub struct FrameLog {
+    path: PathBuf,
+    start: Instant,
+    frames: Vec<FrameRecord>,
+}
+
+impl FrameLog {
+    /// Writes to `path` once [`FrameLog::write`] is called.
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            start: Instant::now(),
+            frames: vec![],
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Adds a frame that ended at `end`.
+    pub fn record(
+        &mut self,
+        end: Instant,
+        cpu_time: Duration,
+        encode_time: Duration,
+        gpu_ms: Option<f32>,
+        particles: usize,
+        overlap: bool,
+    ) {
+        self.frames.push(FrameRecord {
+            time: end.duration_since(self.start),
+            cpu_ms: cpu_time.as_secs_f32() * 1000.0,
+            encode_ms: encode_time.as_secs_f32() * 1000.0,
+            gpu_ms,
+            particles,
+            overlap,
+        });
+    }
+
+    /// Writes every frame recorded so far, replacing the previous CSV.
+    pub fn write(&self) -> io::Result<()> {
+        let mut csv = BufWriter::new(File::create(&self.path)?);
+        writeln!(csv, "{CSV_HEADER}")?;
+        for (index, frame) in self.frames.iter().enumerate() {
+            write!(
+                csv,
+                "{:.4},{index},{:.3},{:.3},",
+                frame.time.as_secs_f64(),
+                frame.cpu_ms,
+                frame.encode_ms
+            )?;
+            if let Some(gpu_ms) = frame.gpu_ms {
+                write!(csv, "{gpu_ms:.3}")?;
+            }
+            writeln!(csv, ",{},{}", frame.particles, frame.overlap as u8)?;
+        }
+        csv.flush()
+    }
+}
@@ -0,0 +1,84 @@
+//! Rolling average of the frame time, shown in the window title and logged every so often.
+//!
+//! Reporting every frame would cost time and flood the terminal, so the title is only refreshed a
+//! few times per second, and the log at `--stats-interval`, or never with `--quiet`.
+
+use std::time::{Duration, Instant};
+
+/// Frames averaged
+const SAMPLES: usize = 25;
+/// The title is updated at most 4 times per second
+const TITLE_INTERVAL: Duration = Duration::from_millis(250);
+/// Seconds between frame stats in the log, without `--stats-interval`
+pub const DEFAULT_LOG_INTERVAL: f32 = 1.0;
+
+pub struct FrameStats {
+    samples: [f32; SAMPLES],
+    index: usize,
+    // Average in the title
+    shown: Option<f32>,
+    title_updated: Option<Instant>,
+    log_interval: Option<Duration>,
+    logged: Option<Instant>,
+}
+
+impl FrameStats {
+    /// Logs at most once every `log_interval`, never if `None`.
+    pub fn new(log_interval: Option<Duration>) -> Self {
+        Self {
+            samples: [0.0; SAMPLES],
+            index: 0,
+            shown: None,
+            title_updated: None,
+            log_interval,
+            logged: None,
+        }
+    }
+
+    /// Adds the time a frame took, returning the average of the last frames in milliseconds.
+    pub fn record(&mut self, frame_time: Duration) -> f32 {
+        self.samples[self.index] = frame_time.as_micros() as f32;
+        self.index = (self.index + 1) % SAMPLES;
+        self.average_ms()
+    }
+
+    pub fn average_ms(&self) -> f32 {
+        self.samples.iter().sum::<f32>() / SAMPLES as f32 / 1000.0
+    }
+
+    /// Returns true if the average in the title is due to be updated, [`FrameStats::describe`]
+    /// then giving the new one.
+    pub fn update_title(&mut self, now: Instant) -> bool {
+        if !due(self.title_updated, TITLE_INTERVAL, now) {
+            return false;
+        }
+        self.title_updated = Some(now);
+        self.shown = Some(self.average_ms());
+        true
+    }
+
+    /// Returns true if the frame stats are due to be logged.
+    pub fn log(&mut self, now: Instant) -> bool {
+        let Some(interval) = self.log_interval else {
+            return false;
+        };
+        if !due(self.logged, interval, now) {
+            return false;
+        }
+        self.logged = Some(now);
+        true
+    }
+
+    /// Frame time and rate shown in the title, once updated.
+    pub fn describe(&self) -> Option<String> {
+        let ms = self.shown?;
+        Some(format!(
+            "{ms:.2} ms ({:.0} fps)",
+            1000.0 / ms.max(f32::EPSILON)
+        ))
+    }
+}
+
+fn due(last: Option<Instant>, interval: Duration, now: Instant) -> bool {
+    last.is_none_or(|last| now.duration_since(last) >= interval)
+}
@@ -0,0 +1,45 @@
+//! Moves the camera back over the whole particle cloud, from the bounding box reduced on the GPU,
+//! so particles drifting away during long simulations can always be found again.
+
+use glam::Vec3;
+
+use crate::{
+    camera::Camera,
+    camera_path::{CameraKeyframe, CameraPath},
+};
+
+// Seconds the camera takes to reach the framing
+const DURATION: f32 = 1.0;
+// Room left around the cloud, relative to its size
+const MARGIN: f32 = 1.1;
+
+/// Path from `camera` to a camera looking at the center of the box from the same direction, just
+/// far enough for the sphere around the box to fit both the vertical and horizontal field of view.
+pub fn fit(camera: &Camera, bounds_min: Vec3, bounds_max: Vec3) -> CameraPath {
+    let center = (bounds_min + bounds_max) / 2.0;
+    let radius = ((bounds_max - bounds_min).length() / 2.0).max(1.0);
+    let half_fovy = camera.fovy.to_radians() / 2.0;
+    let half_fovx = (half_fovy.tan() * camera.aspect).atan();
+    let distance = MARGIN * radius / half_fovy.min(half_fovx).sin();
+    let direction = (camera.eye - camera.target)
+        .try_normalize()
+        .unwrap_or(Vec3::Z);
+    let eye = center + direction * distance;
+    if distance + radius > camera.depth.zfar {
+        log::warn!("The particles are farther than the far plane, some will be clipped");
+    }
+
+    let keyframes = vec![
+        CameraKeyframe {
+            time: 0.0,
+            position: camera.eye,
+            target: camera.target,
+        },
+        CameraKeyframe {
+            time: DURATION,
+            position: eye,
+            target: center,
+        },
+    ];
+    CameraPath::new(keyframes, false).expect("two keyframes")
+}
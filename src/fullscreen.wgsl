@@ -0,0 +1,22 @@
+// One triangle covering the whole screen, no vertex buffer needed. Included before the shaders of
+// the passes going over every pixel.
+
+struct FullscreenOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    // From 0 at the top left corner of the screen to 1 at the bottom right one
+    @location(0) uv: vec2<f32>,
+};
+
+// Normalized device coordinates of `uv`, y up
+fn fullscreen_ndc(uv: vec2<f32>) -> vec2<f32> {
+    return vec2<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0);
+}
+
+@vertex
+fn vs_fullscreen(@builtin(vertex_index) vertex_index: u32) -> FullscreenOutput {
+    let uv = vec2<f32>(f32((vertex_index << 1u) & 2u), f32(vertex_index & 2u));
+    var out: FullscreenOutput;
+    out.clip_position = vec4<f32>(fullscreen_ndc(uv), 0.0, 1.0);
+    out.uv = uv;
+    return out;
+}
@@ -0,0 +1,69 @@
+//! Gamepads through gilrs, polled from the event loop since winit doesn't report them.
+
+use gilrs::{Axis, Button, EventType, GamepadId, Gilrs};
+use glam::Vec2;
+
+use crate::input::GamepadAxes;
+
+pub struct Gamepads {
+    gilrs: Gilrs,
+    // The last gamepad used, the only one read
+    active: Option<GamepadId>,
+}
+
+impl Gamepads {
+    /// Returns `None` if gamepads aren't supported on this system.
+    pub fn new() -> Option<Self> {
+        match Gilrs::new() {
+            Ok(gilrs) => {
+                let active = gilrs.gamepads().next().map(|(id, gamepad)| {
+                    log::info!("Gamepad connected: {}", gamepad.name());
+                    id
+                });
+                Some(Self { gilrs, active })
+            }
+            Err(e) => {
+                log::warn!("Gamepads aren't supported: {e}");
+                None
+            }
+        }
+    }
+
+    /// Handles the pending gamepad events and reads the sticks and triggers of the active one.
+    pub fn poll(&mut self) -> GamepadAxes {
+        while let Some(event) = self.gilrs.next_event() {
+            match event.event {
+                EventType::Connected => {
+                    let gamepad = self.gilrs.gamepad(event.id);
+                    log::info!("Gamepad connected: {}", gamepad.name());
+                }
+                EventType::Disconnected => {
+                    log::info!("Gamepad disconnected");
+                    if self.active == Some(event.id) {
+                        self.active = None;
+                    }
+                    continue;
+                }
+                _ => {}
+            }
+            self.active = Some(event.id);
+        }
+
+        let Some(gamepad) = self.active.and_then(|id| self.gilrs.connected_gamepad(id)) else {
+            return GamepadAxes::default();
+        };
+        let trigger = |button| gamepad.button_data(button).map_or(0.0, |data| data.value());
+        GamepadAxes {
+            left_stick: Vec2::new(
+                gamepad.value(Axis::LeftStickX),
+                gamepad.value(Axis::LeftStickY),
+            ),
+            right_stick: Vec2::new(
+                gamepad.value(Axis::RightStickX),
+                gamepad.value(Axis::RightStickY),
+            ),
+            left_trigger: trigger(Button::LeftTrigger2),
+            right_trigger: trigger(Button::RightTrigger2),
+        }
+    }
+}
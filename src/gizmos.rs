@@ -0,0 +1,251 @@
+//! Orientation aids drawn as lines over the scene: the world axes at the origin, the wireframe of
+//! the shape particles spawn in, and a marker on the attractor while it pulls.
+
+use std::f32::consts::TAU;
+
+use bytemuck::{Pod, Zeroable};
+use glam::{Vec3, Vec4};
+use wgpu::util::DeviceExt;
+
+use crate::emitter::EmitterShape;
+
+// Length of the world axes
+const AXIS_LENGTH: f32 = 200.0;
+// Half the size of the cross marking the attractor
+const MARKER_SIZE: f32 = 25.0;
+// Segments of the circles drawn for round emitters
+const CIRCLE_SEGMENTS: usize = 48;
+const EMITTER_COLOR: Vec4 = Vec4::new(0.6, 0.6, 0.6, 0.6);
+const ATTRACTOR_COLOR: Vec4 = Vec4::new(1.0, 0.9, 0.2, 1.0);
+
+// Must match the inputs of vs_main in gizmos.wgsl
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Pod, Zeroable)]
+struct GizmoVertex {
+    position: [f32; 3],
+    color: [f32; 4],
+}
+
+impl GizmoVertex {
+    fn descriptor() -> wgpu::VertexBufferLayout<'static> {
+        static ATTRIBUTES: &[wgpu::VertexAttribute] = &[
+            wgpu::VertexAttribute {
+                offset: memoffset::offset_of!(GizmoVertex, position) as u64,
+                shader_location: 0,
+                format: wgpu::VertexFormat::Float32x3,
+            },
+            wgpu::VertexAttribute {
+                offset: memoffset::offset_of!(GizmoVertex, color) as u64,
+                shader_location: 1,
+                format: wgpu::VertexFormat::Float32x4,
+            },
+        ];
+
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<GizmoVertex>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: ATTRIBUTES,
+        }
+    }
+}
+
+pub struct Gizmos {
+    pipeline: wgpu::RenderPipeline,
+    vertex_buffer: Option<wgpu::Buffer>,
+    vertex_count: u32,
+    // What the lines were last built for, they're rebuilt when either changes
+    emitter: Option<EmitterShape>,
+    attracting: bool,
+}
+
+impl Gizmos {
+    pub fn new(
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+        camera_bind_group_layout: &wgpu::BindGroupLayout,
+    ) -> Self {
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Gizmo Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("gizmos.wgsl").into()),
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Gizmo Pipeline Layout"),
+            bind_group_layouts: &[camera_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Gizmo Pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[GizmoVertex::descriptor()],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::LineList,
+                ..Default::default()
+            },
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+
+        #[cfg(feature = "guardrails")]
+        {
+            let reflection = crate::guardrails::ShaderReflection::new(
+                "gizmos.wgsl",
+                include_str!("gizmos.wgsl"),
+            );
+            reflection.check_vertex_buffers("vs_main", &[GizmoVertex::descriptor()]);
+        }
+
+        Self {
+            pipeline,
+            vertex_buffer: None,
+            vertex_count: 0,
+            emitter: None,
+            attracting: false,
+        }
+    }
+
+    /// Rebuilds the lines if `emitter` or whether the attractor pulls changed since the last call.
+    pub fn update(&mut self, device: &wgpu::Device, emitter: &EmitterShape, attracting: bool) {
+        if self.emitter.as_ref() == Some(emitter) && self.attracting == attracting {
+            return;
+        }
+        self.emitter = Some(*emitter);
+        self.attracting = attracting;
+
+        let mut lines = Lines::default();
+        for (axis, color) in [
+            (Vec3::X, Vec4::new(1.0, 0.2, 0.2, 1.0)),
+            (Vec3::Y, Vec4::new(0.2, 1.0, 0.2, 1.0)),
+            (Vec3::Z, Vec4::new(0.3, 0.5, 1.0, 1.0)),
+        ] {
+            lines.add(Vec3::ZERO, axis * AXIS_LENGTH, color);
+        }
+        lines.emitter(emitter);
+        if attracting {
+            // The attractor pulls towards the origin, see compute_kernel.wgsl
+            for axis in [
+                Vec3::ONE,
+                Vec3::new(1.0, -1.0, 1.0),
+                Vec3::new(1.0, 1.0, -1.0),
+            ] {
+                let arm = axis.normalize() * MARKER_SIZE;
+                lines.add(-arm, arm, ATTRACTOR_COLOR);
+            }
+        }
+
+        self.vertex_count = lines.0.len() as u32;
+        self.vertex_buffer = Some(
+            device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("Gizmo Vertex Buffer"),
+                contents: bytemuck::cast_slice(&lines.0),
+                usage: wgpu::BufferUsages::VERTEX,
+            }),
+        );
+    }
+
+    /// Draws the lines built by the last [`Gizmos::update`] on top of what `view` holds.
+    pub fn encode(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        view: &wgpu::TextureView,
+        camera_bind_group: &wgpu::BindGroup,
+    ) {
+        let Some(vertex_buffer) = &self.vertex_buffer else {
+            return;
+        };
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Gizmo Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: true,
+                },
+            })],
+            depth_stencil_attachment: None,
+        });
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, camera_bind_group, &[]);
+        render_pass.set_vertex_buffer(0, vertex_buffer.slice(..));
+        render_pass.draw(0..self.vertex_count, 0..1);
+    }
+}
+
+/// Vertices of a line list, two per segment.
+#[derive(Default)]
+struct Lines(Vec<GizmoVertex>);
+
+impl Lines {
+    fn add(&mut self, start: Vec3, end: Vec3, color: Vec4) {
+        for position in [start, end] {
+            self.0.push(GizmoVertex {
+                position: position.to_array(),
+                color: color.to_array(),
+            });
+        }
+    }
+
+    /// Circle around `center` in the plane of `u` and `v`, of the length of `u` and `v`.
+    fn circle(&mut self, center: Vec3, u: Vec3, v: Vec3) {
+        let point = |segment: usize| {
+            let angle = segment as f32 / CIRCLE_SEGMENTS as f32 * TAU;
+            center + u * angle.cos() + v * angle.sin()
+        };
+        for segment in 0..CIRCLE_SEGMENTS {
+            self.add(point(segment), point(segment + 1), EMITTER_COLOR);
+        }
+    }
+
+    /// Wireframe of `emitter`: the edges of boxes, and a circle in each plane of spheres.
+    fn emitter(&mut self, emitter: &EmitterShape) {
+        match *emitter {
+            EmitterShape::SphereSurface { center, radius }
+            | EmitterShape::Sphere { center, radius } => {
+                let (x, y, z) = (Vec3::X * radius, Vec3::Y * radius, Vec3::Z * radius);
+                self.circle(center, x, y);
+                self.circle(center, y, z);
+                self.circle(center, z, x);
+            }
+            EmitterShape::Disk {
+                center,
+                radius,
+                normal,
+            } => {
+                let (u, v) = normal.normalize_or_zero().any_orthonormal_pair();
+                self.circle(center, u * radius, v * radius);
+            }
+            EmitterShape::Line { start, end } => self.add(start, end, EMITTER_COLOR),
+            EmitterShape::Box { min, max } => {
+                let corner = |index: usize| {
+                    Vec3::select(
+                        glam::BVec3::new(index & 1 != 0, index & 2 != 0, index & 4 != 0),
+                        max,
+                        min,
+                    )
+                };
+                // Corners one bit apart share an edge
+                for index in 0..8 {
+                    for bit in [1, 2, 4] {
+                        if index & bit == 0 {
+                            self.add(corner(index), corner(index | bit), EMITTER_COLOR);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
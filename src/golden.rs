@@ -0,0 +1,164 @@
+//! Golden image comparisons, so shader and pipeline refactors can be checked automatically.
+//!
+//! `headless --compare golden.png` renders the last frame at the size of the reference and fails
+//! if more than `--threshold` of its pixels differ. The rendered frame and a diff highlighting the
+//! mismatches are written next to the reference.
+
+use std::{
+    fs::File,
+    io::BufReader,
+    path::{Path, PathBuf},
+};
+
+use crate::capture::{CaptureError, Image};
+
+/// Fraction of the pixels allowed to differ, unless `--threshold` is given
+pub const DEFAULT_THRESHOLD: f32 = 0.01;
+
+// Channels may differ by this much without the pixel counting as a mismatch, for rounding
+// differences between drivers
+const CHANNEL_TOLERANCE: u8 = 2;
+
+#[derive(Debug, thiserror::Error)]
+pub enum GoldenError {
+    #[error("unable to read {0}: {1}")]
+    Io(PathBuf, std::io::Error),
+    #[error("unable to decode {0}: {1}")]
+    Decode(PathBuf, png::DecodingError),
+    #[error("{0} is {1:?}, only RGB and RGBA are supported")]
+    Color(PathBuf, png::ColorType),
+    #[error(transparent)]
+    Capture(#[from] CaptureError),
+}
+
+/// How much a rendered frame differs from the reference.
+pub struct Difference {
+    pub mismatched: usize,
+    pub total: usize,
+    /// Reference dimmed, with the mismatched pixels in red
+    pub image: Image,
+}
+
+impl Difference {
+    pub fn fraction(&self) -> f32 {
+        self.mismatched as f32 / self.total as f32
+    }
+}
+
+/// Decodes the RGBA8 reference at `path`.
+pub fn load(path: &Path) -> Result<Image, GoldenError> {
+    let file = File::open(path).map_err(|e| GoldenError::Io(path.to_owned(), e))?;
+    let mut decoder = png::Decoder::new(BufReader::new(file));
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+    let mut reader = decoder
+        .read_info()
+        .map_err(|e| GoldenError::Decode(path.to_owned(), e))?;
+    let mut buffer = vec![0; reader.output_buffer_size()];
+    let info = reader
+        .next_frame(&mut buffer)
+        .map_err(|e| GoldenError::Decode(path.to_owned(), e))?;
+    buffer.truncate(info.buffer_size());
+
+    let pixels = match info.color_type {
+        png::ColorType::Rgba => buffer,
+        png::ColorType::Rgb => buffer
+            .chunks_exact(3)
+            .flat_map(|rgb| [rgb[0], rgb[1], rgb[2], u8::MAX])
+            .collect(),
+        color => return Err(GoldenError::Color(path.to_owned(), color)),
+    };
+    Ok(Image {
+        width: info.width,
+        height: info.height,
+        pixels,
+    })
+}
+
+/// Compares two images of the same size pixel by pixel.
+pub fn compare(reference: &Image, actual: &Image) -> Difference {
+    let mut image = Image::new(reference.width, reference.height);
+    let mut mismatched = 0;
+    for ((expected, actual), diff) in reference
+        .pixels
+        .chunks_exact(4)
+        .zip(actual.pixels.chunks_exact(4))
+        .zip(image.pixels.chunks_exact_mut(4))
+    {
+        let mismatch = expected
+            .iter()
+            .zip(actual)
+            .any(|(a, b)| a.abs_diff(*b) > CHANNEL_TOLERANCE);
+        if mismatch {
+            mismatched += 1;
+            diff.copy_from_slice(&[u8::MAX, 0, 0, u8::MAX]);
+        } else {
+            diff.copy_from_slice(&[expected[0] / 4, expected[1] / 4, expected[2] / 4, u8::MAX]);
+        }
+    }
+    Difference {
+        mismatched,
+        total: reference.width as usize * reference.height as usize,
+        image,
+    }
+}
+
+/// `golden.png` becomes `golden.<suffix>.png`.
+fn sibling(path: &Path, suffix: &str) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    path.with_file_name(format!("{stem}.{suffix}.png"))
+}
+
+/// Renders a frame with `render` at the size of the reference at `path`, or at `default_size`
+/// if there is none yet, and returns true if it matches within `threshold`.
+pub fn check(
+    path: &Path,
+    threshold: f32,
+    default_size: (u32, u32),
+    render: impl FnOnce(u32, u32) -> Result<Image, CaptureError>,
+) -> Result<bool, GoldenError> {
+    let reference = match load(path) {
+        Ok(reference) => Some(reference),
+        Err(GoldenError::Io(_, e)) if e.kind() == std::io::ErrorKind::NotFound => None,
+        Err(e) => return Err(e),
+    };
+    let (width, height) = reference.as_ref().map_or(default_size, |reference| {
+        (reference.width, reference.height)
+    });
+    let actual = render(width, height)?;
+    let actual_path = sibling(path, "actual");
+
+    let Some(reference) = reference else {
+        actual.save_png(&actual_path)?;
+        log::error!(
+            "No golden image at {}, the frame was written to {}",
+            path.display(),
+            actual_path.display()
+        );
+        return Ok(false);
+    };
+
+    let difference = compare(&reference, &actual);
+    let fraction = difference.fraction();
+    if fraction <= threshold {
+        log::info!(
+            "Frame matches {} ({:.3}% of the pixels differ)",
+            path.display(),
+            fraction * 100.0
+        );
+        return Ok(true);
+    }
+    let diff_path = sibling(path, "diff");
+    actual.save_png(&actual_path)?;
+    difference.image.save_png(&diff_path)?;
+    log::error!(
+        "Frame differs from {} in {} of {} pixels ({:.3}%, threshold {:.3}%), see {} and {}",
+        path.display(),
+        difference.mismatched,
+        difference.total,
+        fraction * 100.0,
+        threshold * 100.0,
+        actual_path.display(),
+        diff_path.display()
+    );
+    Ok(false)
+}
@@ -0,0 +1,189 @@
+//! GPU time spent in the compute and render passes, measured with timestamp queries, and the work
+//! of the main particle pass, counted with pipeline statistics queries where supported.
+//!
+//! Like checkpoints, the results are read back asynchronously through a [`QueryPool`], so they lag
+//! a couple of frames behind and some frames aren't measured at all.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::{capabilities::GpuCapabilities, query_pool::QueryPool};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Pass {
+    Compute = 0,
+    Render = 1,
+    /// Boid steering and collisions, with the spatial hashes they need
+    Neighbors = 2,
+}
+
+const PASSES: u32 = 3;
+// A timestamp at the start and one at the end of every pass
+const QUERY_COUNT: u32 = PASSES * 2;
+// Read back in the order of their bits
+const STATISTICS: wgpu::PipelineStatisticsTypes =
+    wgpu::PipelineStatisticsTypes::VERTEX_SHADER_INVOCATIONS
+        .union(wgpu::PipelineStatisticsTypes::CLIPPER_INVOCATIONS)
+        .union(wgpu::PipelineStatisticsTypes::CLIPPER_PRIMITIVES_OUT)
+        .union(wgpu::PipelineStatisticsTypes::FRAGMENT_SHADER_INVOCATIONS);
+
+/// Milliseconds spent in each pass.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct PassTimes {
+    pub compute_ms: f64,
+    pub render_ms: f64,
+    pub neighbors_ms: f64,
+    /// From the start of the first pass to the end of the last, shorter than the sum of the passes
+    /// when the GPU overlaps them
+    pub span_ms: f64,
+}
+
+/// Work done drawing the particles into the main view.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PipelineStats {
+    pub vertex_invocations: u64,
+    /// Triangles reaching the clipper
+    pub clipper_invocations: u64,
+    /// Triangles left after clipping
+    pub primitives: u64,
+    pub fragment_invocations: u64,
+}
+
+impl PipelineStats {
+    /// Fragments shaded per pixel of a view of `pixels`, 1 without overdraw if every pixel is
+    /// covered.
+    pub fn overdraw(&self, pixels: u64) -> f64 {
+        self.fragment_invocations as f64 / pixels.max(1) as f64
+    }
+
+    /// Share of the triangles clipped or culled away.
+    pub fn culled(&self) -> f64 {
+        1.0 - self.primitives as f64 / self.clipper_invocations.max(1) as f64
+    }
+}
+
+pub struct GpuTimer {
+    timestamps: QueryPool,
+    statistics: Option<QueryPool>,
+    // Whether the statistics query was written this frame, resolving it otherwise reads garbage.
+    // Atomic for the compute passes to be timed from the encoding thread
+    statistics_written: AtomicBool,
+    // Nanoseconds per timestamp tick
+    period: f64,
+}
+
+impl GpuTimer {
+    /// Returns `None` without timestamp queries. The pipeline statistics are only counted with
+    /// pipeline statistics queries.
+    pub fn new(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        capabilities: &GpuCapabilities,
+    ) -> Option<Self> {
+        if !capabilities.timestamp_query {
+            return None;
+        }
+
+        let statistics = capabilities.pipeline_statistics_query.then(|| {
+            QueryPool::new(
+                device,
+                "Pipeline Statistics",
+                wgpu::QueryType::PipelineStatistics(STATISTICS),
+                1,
+            )
+        });
+        Some(Self {
+            timestamps: QueryPool::new(
+                device,
+                "GPU Timer",
+                wgpu::QueryType::Timestamp,
+                QUERY_COUNT,
+            ),
+            statistics,
+            statistics_written: AtomicBool::new(false),
+            period: queue.get_timestamp_period() as f64,
+        })
+    }
+
+    pub fn begin(&self, encoder: &mut wgpu::CommandEncoder, pass: Pass) {
+        encoder.write_timestamp(self.timestamps.query_set(), pass as u32 * 2);
+    }
+
+    pub fn end(&self, encoder: &mut wgpu::CommandEncoder, pass: Pass) {
+        encoder.write_timestamp(self.timestamps.query_set(), pass as u32 * 2 + 1);
+    }
+
+    /// Starts counting the work of `render_pass`, to be ended with [`GpuTimer::end_statistics`]
+    /// before the pass is.
+    pub fn begin_statistics(&self, render_pass: &mut wgpu::RenderPass) {
+        if let Some(statistics) = &self.statistics {
+            render_pass.begin_pipeline_statistics_query(statistics.query_set(), 0);
+            self.statistics_written.store(true, Ordering::Relaxed);
+        }
+    }
+
+    pub fn end_statistics(&self, render_pass: &mut wgpu::RenderPass) {
+        if self.statistics.is_some() {
+            render_pass.end_pipeline_statistics_query();
+        }
+    }
+
+    /// Copies this frame's results for reading. Must be encoded after every pass was timed, and
+    /// followed by [`GpuTimer::map`] once submitted.
+    pub fn resolve(&mut self, encoder: &mut wgpu::CommandEncoder) {
+        self.timestamps.resolve(encoder);
+        if let Some(statistics) = &mut self.statistics {
+            if self.statistics_written.swap(false, Ordering::Relaxed) {
+                statistics.resolve(encoder);
+            }
+        }
+    }
+
+    pub fn map(&mut self) {
+        self.timestamps.map();
+        if let Some(statistics) = &mut self.statistics {
+            statistics.map();
+        }
+    }
+
+    /// Returns the pass times of the latest frame read back, if they arrived since the last call.
+    pub fn try_take(&mut self, device: &wgpu::Device) -> Option<PassTimes> {
+        let timestamps = self.timestamps.try_take(device)?;
+        let to_ms = |ticks: u64| ticks as f64 * self.period / 1_000_000.0;
+        let elapsed_ms = |pass: Pass| {
+            let start = timestamps[pass as usize * 2];
+            let end = timestamps[pass as usize * 2 + 1];
+            to_ms(end.saturating_sub(start))
+        };
+        let first_start = timestamps
+            .iter()
+            .step_by(2)
+            .min()
+            .copied()
+            .unwrap_or_default();
+        let last_end = timestamps
+            .iter()
+            .skip(1)
+            .step_by(2)
+            .max()
+            .copied()
+            .unwrap_or_default();
+        Some(PassTimes {
+            compute_ms: elapsed_ms(Pass::Compute),
+            render_ms: elapsed_ms(Pass::Render),
+            neighbors_ms: elapsed_ms(Pass::Neighbors),
+            span_ms: to_ms(last_end.saturating_sub(first_start)),
+        })
+    }
+
+    /// Returns the pipeline statistics of the latest frame read back, if they arrived since the
+    /// last call.
+    pub fn try_take_statistics(&mut self, device: &wgpu::Device) -> Option<PipelineStats> {
+        let values = self.statistics.as_mut()?.try_take(device)?;
+        Some(PipelineStats {
+            vertex_invocations: values[0],
+            clipper_invocations: values[1],
+            primitives: values[2],
+            fragment_invocations: values[3],
+        })
+    }
+}
@@ -0,0 +1,103 @@
+//! Color over lifetime, for fire and smoke looks.
+//!
+//! Particles live forever, so their age loops over a lifetime of a few seconds, each particle
+//! starting at its own point of it. Scenes give the gradient as stops in a `[gradient]` section,
+//! uploaded to a uniform buffer bound with the camera. shader.wgsl replaces the particle colors
+//! with the gradient at their age, keeping their alpha.
+
+use bytemuck::{Pod, Zeroable};
+use glam::Vec4;
+
+/// Most stops a gradient can have, the uniform is sized for this many.
+pub const MAX_STOPS: usize = 8;
+const DEFAULT_LIFETIME: f32 = 4.0;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColorGradient {
+    /// Seconds for a particle to go through the whole gradient
+    pub lifetime: f32,
+    // Position between 0 and 1 of each stop and its color, sorted by position
+    stops: Vec<(f32, Vec4)>,
+}
+
+impl Default for ColorGradient {
+    fn default() -> Self {
+        Self {
+            lifetime: DEFAULT_LIFETIME,
+            stops: vec![],
+        }
+    }
+}
+
+impl ColorGradient {
+    /// Adds a stop at `position`, between 0 and 1. Returns false if the gradient already has
+    /// [`MAX_STOPS`] stops.
+    pub fn add_stop(&mut self, position: f32, color: Vec4) -> bool {
+        if self.stops.len() == MAX_STOPS {
+            return false;
+        }
+        let index = self.stops.partition_point(|&(p, _)| p <= position);
+        self.stops.insert(index, (position, color));
+        true
+    }
+}
+
+// Must match GradientStop in shader.wgsl
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Pod, Zeroable)]
+struct GradientStop {
+    color: Vec4,
+    position: f32,
+    _padding: [u32; 3],
+}
+
+// Must match Gradient in shader.wgsl. No stops leaves the particle colors as they are.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Pod, Zeroable)]
+pub struct GradientUniform {
+    stops: [GradientStop; MAX_STOPS],
+    time: f32,
+    lifetime: f32,
+    count: u32,
+    _padding: u32,
+}
+
+impl GradientUniform {
+    pub fn new(gradient: Option<&ColorGradient>, time: f32) -> Self {
+        let mut uniform = Self::zeroed();
+        if let Some(gradient) = gradient {
+            for (stop, &(position, color)) in uniform.stops.iter_mut().zip(&gradient.stops) {
+                stop.color = color;
+                stop.position = position;
+            }
+            uniform.time = time;
+            uniform.lifetime = gradient.lifetime;
+            uniform.count = gradient.stops.len() as u32;
+        }
+        uniform
+    }
+}
+
+/// Uniform buffer for a gradient, without stops until [`write_buffer`].
+pub fn create_buffer(device: &wgpu::Device) -> wgpu::Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Gradient Buffer"),
+        size: std::mem::size_of::<GradientUniform>() as u64,
+        usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    })
+}
+
+/// Uploads `gradient` with the ages of the particles at `time`, or clears the gradient.
+pub fn write_buffer(
+    queue: &wgpu::Queue,
+    buffer: &wgpu::Buffer,
+    gradient: Option<&ColorGradient>,
+    time: f32,
+) {
+    queue.write_buffer(
+        buffer,
+        0,
+        bytemuck::bytes_of(&GradientUniform::new(gradient, time)),
+    );
+}
@@ -0,0 +1,64 @@
+//! Layout of the grid view, where several independent particle systems are tiled on one surface to
+//! compare scenes at a glance.
+
+/// Columns and rows of the grid, as square as possible.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GridLayout {
+    columns: u32,
+    rows: u32,
+}
+
+/// Area of the target a cell is drawn in, in pixels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CellRect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl CellRect {
+    pub fn aspect(&self) -> f32 {
+        self.width as f32 / self.height as f32
+    }
+
+    pub fn contains(&self, x: u32, y: u32) -> bool {
+        (self.x..self.x + self.width).contains(&x) && (self.y..self.y + self.height).contains(&y)
+    }
+}
+
+impl GridLayout {
+    pub fn new(cells: usize) -> Self {
+        let cells = cells.max(1) as u32;
+        let columns = (cells as f32).sqrt().ceil() as u32;
+        Self {
+            columns,
+            rows: cells.div_ceil(columns),
+        }
+    }
+
+    /// Area of cell `index` in a target of `size`, filled row by row from the top left.
+    pub fn cell_rect(&self, index: usize, (width, height): (u32, u32)) -> CellRect {
+        let (column, row) = (index as u32 % self.columns, index as u32 / self.columns);
+        // Remainders go to the last column and row, so cells cover the whole target
+        let (cell_width, cell_height) = (width / self.columns, height / self.rows);
+        let x = column * cell_width;
+        let y = row * cell_height;
+        CellRect {
+            x,
+            y,
+            width: if column + 1 == self.columns {
+                width - x
+            } else {
+                cell_width
+            }
+            .max(1),
+            height: if row + 1 == self.rows {
+                height - y
+            } else {
+                cell_height
+            }
+            .max(1),
+        }
+    }
+}
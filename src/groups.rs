@@ -0,0 +1,69 @@
+//! Particle groups, shown or hidden at runtime for A/B comparisons within one run.
+//!
+//! Particles are spread over [`GROUPS`] groups by their index when spawned, the group is stored in
+//! the whole part of the w of their position, and their age in its fraction. The particle shaders collapse the particles of the groups left out of
+//! the mask in the camera uniform, so each window shows its own groups. The passes reading the
+//! positions for other purposes, like picking, the heatmap or the motion blur, still see every
+//! group.
+
+use std::fmt::Display;
+
+/// Groups the particles are spread over, each toggled by a numpad key.
+pub const GROUPS: usize = 4;
+
+/// Group of the particle spawned at `index`.
+pub fn of(index: usize) -> u32 {
+    (index % GROUPS) as u32
+}
+
+// Ages are kept below the next group
+const MAX_AGE: f32 = 0.999;
+
+/// Age of the particle with `w` in its position, see [`SimParams::age`](crate::sim_params::SimParams::age).
+pub fn age_of(w: f32) -> f32 {
+    w.fract()
+}
+
+/// `w` of the particle of the group in `w`, at `age`.
+pub fn with_age(w: f32, age: f32) -> f32 {
+    w.floor() + age.min(MAX_AGE)
+}
+
+/// Groups shown, one bit per group.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GroupMask(pub u32);
+
+impl Default for GroupMask {
+    fn default() -> Self {
+        Self::ALL
+    }
+}
+
+impl GroupMask {
+    pub const ALL: Self = Self((1 << GROUPS) - 1);
+
+    pub fn toggle(&mut self, group: usize) {
+        self.0 ^= 1 << group;
+    }
+
+    pub fn shows(self, group: usize) -> bool {
+        self.0 & (1 << group) != 0
+    }
+}
+
+/// The groups shown, numbered from 1 like their keys, with a dash for the hidden ones.
+impl Display for GroupMask {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for group in 0..GROUPS {
+            if group > 0 {
+                write!(f, " ")?;
+            }
+            if self.shows(group) {
+                write!(f, "{}", group + 1)?;
+            } else {
+                write!(f, "-")?;
+            }
+        }
+        Ok(())
+    }
+}
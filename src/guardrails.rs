@@ -0,0 +1,229 @@
+//! Consistency checks between what the CPU side sets up and what the shaders expect.
+//!
+//! Only compiled with the `guardrails` feature. Every check panics with a message describing the
+//! mismatch instead of letting the GPU silently read garbage.
+
+use std::collections::HashMap;
+
+use naga::{AddressSpace, Binding, StorageAccess, TypeInner};
+
+/// Parsed WGSL module, used to reflect on structs, entry points and bindings.
+pub struct ShaderReflection {
+    label: &'static str,
+    module: naga::Module,
+}
+
+impl ShaderReflection {
+    pub fn new(label: &'static str, source: &str) -> Self {
+        let module = naga::front::wgsl::parse_str(source)
+            .unwrap_or_else(|e| panic!("[guardrails] {label}: {}", e.emit_to_string(source)));
+        Self { label, module }
+    }
+
+    /// Checks that the WGSL struct named `name` is exactly `expected_size` bytes.
+    pub fn check_struct_size(&self, name: &str, expected_size: usize) {
+        let mut layouter = naga::proc::Layouter::default();
+        layouter
+            .update(self.module.to_ctx())
+            .unwrap_or_else(|e| panic!("[guardrails] {}: layout error: {e}", self.label));
+
+        let (handle, _) = self
+            .module
+            .types
+            .iter()
+            .find(|(_, ty)| ty.name.as_deref() == Some(name))
+            .unwrap_or_else(|| panic!("[guardrails] {}: no struct named `{name}`", self.label));
+
+        let size = layouter[handle].size as usize;
+        assert_eq!(
+            size, expected_size,
+            "[guardrails] {}: WGSL struct `{name}` is {size} bytes but the Rust side uses {expected_size} bytes",
+            self.label
+        );
+    }
+
+    /// Checks that every attribute of `buffers` feeds a location of the same size in the inputs of
+    /// `entry_point`, that every location is fed, and that attributes fit in their stride.
+    pub fn check_vertex_buffers(&self, entry_point: &str, buffers: &[wgpu::VertexBufferLayout]) {
+        let locations = self.vertex_input_locations(entry_point);
+
+        let mut fed = HashMap::new();
+        for (buffer_index, buffer) in buffers.iter().enumerate() {
+            for attribute in buffer.attributes {
+                let size = attribute.format.size();
+                assert!(
+                    attribute.offset + size <= buffer.array_stride,
+                    "[guardrails] {}: attribute @location({}) of vertex buffer {buffer_index} ends at byte {} but the stride is {}",
+                    self.label,
+                    attribute.shader_location,
+                    attribute.offset + size,
+                    buffer.array_stride
+                );
+
+                let decoded = decoded_size(attribute.format);
+                let expected = locations.get(&attribute.shader_location).unwrap_or_else(|| {
+                    panic!(
+                        "[guardrails] {}: vertex buffer {buffer_index} provides @location({}) which `{entry_point}` does not read",
+                        self.label, attribute.shader_location
+                    )
+                });
+                assert_eq!(
+                    decoded, *expected,
+                    "[guardrails] {}: @location({}) is {expected} bytes in `{entry_point}` but vertex buffer {buffer_index} provides {decoded} bytes",
+                    self.label, attribute.shader_location
+                );
+
+                if let Some(previous) = fed.insert(attribute.shader_location, buffer_index) {
+                    panic!(
+                        "[guardrails] {}: @location({}) is provided by both vertex buffer {previous} and {buffer_index}",
+                        self.label, attribute.shader_location
+                    );
+                }
+            }
+        }
+
+        for location in locations.keys() {
+            assert!(
+                fed.contains_key(location),
+                "[guardrails] {}: `{entry_point}` reads @location({location}) but no vertex buffer provides it",
+                self.label
+            );
+        }
+    }
+
+    /// Checks that every resource the shader declares in `group` has a compatible entry in the
+    /// bind group layout.
+    pub fn check_bind_group_layout(&self, group: u32, entries: &[wgpu::BindGroupLayoutEntry]) {
+        for (_, global) in self.module.global_variables.iter() {
+            let Some(binding) = &global.binding else {
+                continue;
+            };
+            if binding.group != group {
+                continue;
+            }
+
+            let name = global.name.as_deref().unwrap_or("<unnamed>");
+            let entry = entries
+                .iter()
+                .find(|entry| entry.binding == binding.binding)
+                .unwrap_or_else(|| {
+                    panic!(
+                        "[guardrails] {}: `{name}` is bound at @group({group}) @binding({}) but the layout has no such entry",
+                        self.label, binding.binding
+                    )
+                });
+
+            let compatible = match (global.space, entry.ty) {
+                (
+                    AddressSpace::Uniform,
+                    wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        ..
+                    },
+                ) => true,
+                (
+                    AddressSpace::Storage { access },
+                    wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only },
+                        ..
+                    },
+                ) => read_only != access.contains(StorageAccess::STORE),
+                (AddressSpace::Handle, _) => true,
+                _ => false,
+            };
+            assert!(
+                compatible,
+                "[guardrails] {}: `{name}` is declared as {:?} but the layout entry at @binding({}) is {:?}",
+                self.label, global.space, binding.binding, entry.ty
+            );
+        }
+    }
+
+    fn vertex_input_locations(&self, entry_point: &str) -> HashMap<u32, u64> {
+        let entry_point = self
+            .module
+            .entry_points
+            .iter()
+            .find(|ep| ep.name == entry_point)
+            .unwrap_or_else(|| {
+                panic!(
+                    "[guardrails] {}: no entry point `{entry_point}`",
+                    self.label
+                )
+            });
+
+        let mut locations = HashMap::new();
+        let mut add = |binding: &Option<Binding>, ty: naga::Handle<naga::Type>| {
+            if let Some(Binding::Location { location, .. }) = binding {
+                locations.insert(*location, self.type_size(ty));
+            }
+        };
+
+        for argument in &entry_point.function.arguments {
+            match &self.module.types[argument.ty].inner {
+                TypeInner::Struct { members, .. } => {
+                    for member in members {
+                        add(&member.binding, member.ty);
+                    }
+                }
+                _ => add(&argument.binding, argument.ty),
+            }
+        }
+        locations
+    }
+
+    fn type_size(&self, ty: naga::Handle<naga::Type>) -> u64 {
+        match self.module.types[ty].inner {
+            TypeInner::Scalar { width, .. } => width as u64,
+            TypeInner::Vector { size, width, .. } => size as u64 * width as u64,
+            ref other => panic!(
+                "[guardrails] {}: unsupported vertex input type {other:?}",
+                self.label
+            ),
+        }
+    }
+}
+
+/// Size of an attribute of `format` once fetched, normalized and half precision formats being
+/// widened to f32.
+fn decoded_size(format: wgpu::VertexFormat) -> u64 {
+    use wgpu::VertexFormat::*;
+    match format {
+        Unorm8x2 | Snorm8x2 | Unorm16x2 | Snorm16x2 | Float16x2 => 8,
+        Unorm8x4 | Snorm8x4 | Unorm16x4 | Snorm16x4 | Float16x4 => 16,
+        format => format.size(),
+    }
+}
+
+/// Checks that a write of `len` bytes at `offset` stays inside `buffer`.
+pub fn check_buffer_write(label: &str, buffer: &wgpu::Buffer, offset: u64, len: usize) {
+    assert!(
+        offset + len as u64 <= buffer.size(),
+        "[guardrails] writing {len} bytes at offset {offset} overflows `{label}` ({} bytes)",
+        buffer.size()
+    );
+}
+
+/// Checks that `buffer` holds exactly `count` elements of `element_size` bytes.
+pub fn check_buffer_size(label: &str, buffer: &wgpu::Buffer, count: usize, element_size: usize) {
+    let expected = (count * element_size) as u64;
+    assert_eq!(
+        buffer.size(),
+        expected,
+        "[guardrails] `{label}` is {} bytes but should hold {count} elements of {element_size} bytes",
+        buffer.size()
+    );
+}
+
+/// Checks that a dispatch launches at least one invocation per particle.
+pub fn check_dispatch_coverage(workgroups: [u32; 3], workgroup_size: [u32; 3], particles: usize) {
+    let invocations = workgroups
+        .iter()
+        .zip(workgroup_size)
+        .map(|(&count, size)| count as u64 * size as u64)
+        .product::<u64>();
+    assert!(
+        invocations >= particles as u64,
+        "[guardrails] dispatching {workgroups:?} workgroups of {workgroup_size:?} only covers {invocations} of {particles} particles"
+    );
+}
@@ -0,0 +1,48 @@
+//! Conversions between f32 and the bits of half floats, for the data uploaded or read back in
+//! 16-bit float formats. They round like `pack2x16float` and `unpack2x16float` in WGSL.
+
+/// Bits of `value` as a half float, rounded to the nearest, flushing values too small for it to
+/// zero.
+pub fn to_bits(value: f32) -> u16 {
+    let bits = value.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    // Half of the lowest kept bit, ties to even, may carry into the exponent
+    let rounded = (bits & 0x7fff_ffff) + 0x0fff + ((bits >> 13) & 1);
+    let exponent = (rounded >> 23) as i32 - 127 + 15;
+    let mantissa = ((rounded >> 13) & 0x3ff) as u16;
+    match exponent {
+        ..=0 => sign,
+        31.. => sign | 0x7c00,
+        exponent => sign | (exponent as u16) << 10 | mantissa,
+    }
+}
+
+/// The value of the half float `bits`.
+pub fn from_bits(bits: u16) -> f32 {
+    let sign = if bits & 0x8000 != 0 { -1.0 } else { 1.0 };
+    let exponent = ((bits >> 10) & 0x1f) as i32;
+    let mantissa = (bits & 0x3ff) as f32 / 1024.0;
+    sign * match exponent {
+        0 => mantissa * 2f32.powi(-14),
+        31 => f32::INFINITY,
+        exponent => (1.0 + mantissa) * 2f32.powi(exponent - 15),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_within_half_precision() {
+        for value in [0.0, 1.0, -2.5, 0.2, -0.016_666, 1234.5, 65504.0] {
+            let back = from_bits(to_bits(value));
+            assert!(
+                (back - value).abs() <= value.abs() / 2048.0,
+                "{value} came back as {back}"
+            );
+        }
+        assert_eq!(from_bits(to_bits(1e9)), f32::INFINITY);
+        assert_eq!(from_bits(to_bits(1e-9)), 0.0);
+    }
+}
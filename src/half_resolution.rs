@@ -0,0 +1,205 @@
+//! Draws the particles at half the resolution of the scene, a quarter of the pixels to fill, then
+//! upsamples them guided by their depth. Particles lose some sharpness, but dense scenes on large
+//! displays are mostly limited by the fill rate of the overlapping particles.
+
+use crate::render_target::{fullscreen_shader, DEPTH_FORMAT};
+
+struct Targets {
+    size: (u32, u32),
+    // Kept alive for the views
+    _color_texture: wgpu::Texture,
+    color_view: wgpu::TextureView,
+    _depth_texture: wgpu::Texture,
+    depth_view: wgpu::TextureView,
+    bind_group: wgpu::BindGroup,
+}
+
+pub struct HalfResolution {
+    enabled: bool,
+    format: wgpu::TextureFormat,
+    pipeline: wgpu::RenderPipeline,
+    bind_group_layout: wgpu::BindGroupLayout,
+    targets: Option<Targets>,
+}
+
+impl HalfResolution {
+    pub fn new(device: &wgpu::Device, format: wgpu::TextureFormat, enabled: bool) -> Self {
+        let bind_group_layout_entries = [
+            wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 1,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Depth,
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                count: None,
+            },
+        ];
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Half Resolution Bind Group Layout"),
+            entries: &bind_group_layout_entries,
+        });
+
+        let source = fullscreen_shader(include_str!("half_resolution.wgsl"));
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Half Resolution Shader"),
+            source: wgpu::ShaderSource::Wgsl(source.as_str().into()),
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Half Resolution Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Half Resolution Pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_fullscreen",
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: None,
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+
+        #[cfg(feature = "guardrails")]
+        crate::guardrails::ShaderReflection::new("half_resolution.wgsl", &source)
+            .check_bind_group_layout(0, &bind_group_layout_entries);
+
+        Self {
+            enabled,
+            format,
+            pipeline,
+            bind_group_layout,
+            targets: None,
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn toggle(&mut self) {
+        self.enabled = !self.enabled;
+        if !self.enabled {
+            self.targets = None;
+        }
+    }
+
+    /// Makes sure the half resolution textures match a scene of `size`. Must be called before
+    /// [`HalfResolution::views`] and [`HalfResolution::upsample`] every time the scene is drawn.
+    pub fn prepare(&mut self, device: &wgpu::Device, size: (u32, u32)) {
+        let size = (size.0.div_ceil(2), size.1.div_ceil(2));
+        if self.targets.as_ref().map(|targets| targets.size) != Some(size) {
+            self.targets = Some(self.create_targets(device, size));
+        }
+    }
+
+    /// Color and depth views the particles should be drawn into, once prepared.
+    pub fn views(&self) -> Option<(&wgpu::TextureView, &wgpu::TextureView)> {
+        let targets = self.targets.as_ref()?;
+        Some((&targets.color_view, &targets.depth_view))
+    }
+
+    /// Upsamples the particles drawn into [`HalfResolution::views`] into `view`.
+    pub fn upsample(&self, encoder: &mut wgpu::CommandEncoder, view: &wgpu::TextureView) {
+        let Some(targets) = &self.targets else {
+            return;
+        };
+
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Half Resolution Upsample Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                    store: true,
+                },
+            })],
+            depth_stencil_attachment: None,
+        });
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &targets.bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+
+    fn create_targets(&self, device: &wgpu::Device, size: (u32, u32)) -> Targets {
+        let extent = wgpu::Extent3d {
+            width: size.0,
+            height: size.1,
+            depth_or_array_layers: 1,
+        };
+        let color_texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Half Resolution Color Texture"),
+            size: extent,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: self.format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+        let depth_texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Half Resolution Depth Texture"),
+            size: extent,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: DEPTH_FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+        let color_view = color_texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let depth_view = depth_texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Half Resolution Bind Group"),
+            layout: &self.bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&color_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&depth_view),
+                },
+            ],
+        });
+
+        Targets {
+            size,
+            _color_texture: color_texture,
+            color_view,
+            _depth_texture: depth_texture,
+            depth_view,
+            bind_group,
+        }
+    }
+}
@@ -0,0 +1,38 @@
+// Upsamples the particles drawn at half resolution: each pixel blends the 4 nearest half
+// resolution texels bilinearly, weighted by how close their depth is to the texel under the
+// pixel, so near particles don't bleed over the far ones or the background around them
+
+@group(0) @binding(0)
+var scene: texture_2d<f32>;
+@group(0) @binding(1)
+var scene_depth: texture_depth_2d;
+
+// Depth difference at which a texel weighs half as much as one at the same depth, about 4 units
+// at 1000 units from the camera with the depth written by `fs_depth` in shader.wgsl
+const DEPTH_TOLERANCE: f32 = 0.001;
+
+@fragment
+fn fs_main(in: FullscreenOutput) -> @location(0) vec4<f32> {
+    let max_coords = vec2<i32>(textureDimensions(scene)) - 1;
+    let position = in.clip_position.xy * 0.5;
+    let under = clamp(vec2<i32>(position), vec2<i32>(0), max_coords);
+    let reference = textureLoad(scene_depth, under, 0);
+
+    // Texel centers are at half coordinates
+    let corner = position - 0.5;
+    let base = vec2<i32>(floor(corner));
+    let fraction = corner - floor(corner);
+    var sum = vec4<f32>(0.0);
+    var weight = 0.0;
+    for (var i = 0; i < 4; i++) {
+        let offset = vec2<i32>(i & 1, i >> 1u);
+        let coords = clamp(base + offset, vec2<i32>(0), max_coords);
+        let bilinear = mix(1.0 - fraction, fraction, vec2<f32>(offset));
+        let depth = textureLoad(scene_depth, coords, 0);
+        let sample_weight = bilinear.x * bilinear.y
+            * DEPTH_TOLERANCE / (DEPTH_TOLERANCE + abs(depth - reference));
+        sum += textureLoad(scene, coords, 0) * sample_weight;
+        weight += sample_weight;
+    }
+    return sum / max(weight, 1e-6);
+}
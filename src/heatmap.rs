@@ -0,0 +1,267 @@
+//! Heatmap debug view: how many particle fragments each pixel shades, in false colors, to find the
+//! overdraw hotspots of the instanced draw.
+//!
+//! The quads are drawn into a buffer of per-pixel counts with atomics in the fragment shader, then
+//! a full-screen pass maps each count to a color, on a log scale up to the highest count of the
+//! frame. Black pixels have no particle.
+
+use std::ops::Range;
+
+use bytemuck::{Pod, Zeroable};
+use wgpu::util::DeviceExt;
+
+use crate::{
+    accessibility::HeatmapColors,
+    picking::ParticleBuffers,
+    render_target::fullscreen_shader,
+    vertex::{InstancePosition, Vertex},
+};
+
+// Must match Params in heatmap.wgsl
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Pod, Zeroable)]
+struct HeatmapParams {
+    width: u32,
+    height: u32,
+    color_map: u32,
+    _padding: u32,
+}
+
+struct Counts {
+    size: (u32, u32),
+    colors: HeatmapColors,
+    buffer: wgpu::Buffer,
+    // Kept alive for the bind group
+    _params_buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+}
+
+pub struct Heatmap {
+    bind_group_layout: wgpu::BindGroupLayout,
+    count_pipeline: wgpu::RenderPipeline,
+    heatmap_pipeline: wgpu::RenderPipeline,
+    // Created for the size of the view and the colors
+    counts: Option<Counts>,
+    colors: HeatmapColors,
+}
+
+impl Heatmap {
+    pub fn new(
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+        camera_bind_group_layout: &wgpu::BindGroupLayout,
+    ) -> Self {
+        let bind_group_layout_entries = [
+            wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Storage { read_only: false },
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 1,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+        ];
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Heatmap Bind Group Layout"),
+            entries: &bind_group_layout_entries,
+        });
+
+        let source = fullscreen_shader(include_str!("heatmap.wgsl"));
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Heatmap Shader"),
+            source: wgpu::ShaderSource::Wgsl(source.as_str().into()),
+        });
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Heatmap Pipeline Layout"),
+            bind_group_layouts: &[camera_bind_group_layout, &bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let quad_buffers = [Vertex::descriptor(), InstancePosition::descriptor()];
+        let create_pipeline = |label,
+                               (vertex_entry_point, fragment_entry_point),
+                               buffers: &[wgpu::VertexBufferLayout],
+                               write_mask| {
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some(label),
+                layout: Some(&layout),
+                vertex: wgpu::VertexState {
+                    module: &shader,
+                    entry_point: vertex_entry_point,
+                    buffers,
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader,
+                    entry_point: fragment_entry_point,
+                    targets: &[Some(wgpu::ColorTargetState {
+                        format,
+                        blend: None,
+                        write_mask,
+                    })],
+                }),
+                primitive: wgpu::PrimitiveState::default(),
+                depth_stencil: None,
+                multisample: wgpu::MultisampleState::default(),
+                multiview: None,
+            })
+        };
+        let count_pipeline = create_pipeline(
+            "Heatmap Count Pipeline",
+            ("vs_count", "fs_count"),
+            &quad_buffers,
+            wgpu::ColorWrites::empty(),
+        );
+        let heatmap_pipeline = create_pipeline(
+            "Heatmap Pipeline",
+            ("vs_fullscreen", "fs_heatmap"),
+            &[],
+            wgpu::ColorWrites::ALL,
+        );
+
+        #[cfg(feature = "guardrails")]
+        {
+            let reflection = crate::guardrails::ShaderReflection::new("heatmap.wgsl", &source);
+            reflection.check_bind_group_layout(1, &bind_group_layout_entries);
+            reflection.check_vertex_buffers("vs_count", &quad_buffers);
+            reflection.check_struct_size("Params", std::mem::size_of::<HeatmapParams>());
+        }
+
+        Self {
+            bind_group_layout,
+            count_pipeline,
+            heatmap_pipeline,
+            counts: None,
+            colors: HeatmapColors::default(),
+        }
+    }
+
+    /// Shows the counts with `colors` from the next frame prepared.
+    pub fn set_colors(&mut self, colors: HeatmapColors) {
+        self.colors = colors;
+    }
+
+    /// Sizes the counts for a view of `size`.
+    pub fn prepare(&mut self, device: &wgpu::Device, size: (u32, u32)) {
+        if self
+            .counts
+            .as_ref()
+            .is_some_and(|counts| counts.size == size && counts.colors == self.colors)
+        {
+            return;
+        }
+
+        // The maximum, then one count per pixel
+        let len = 1 + size.0 as u64 * size.1 as u64;
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Heatmap Counts Buffer"),
+            size: len * std::mem::size_of::<u32>() as u64,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let params = HeatmapParams {
+            width: size.0,
+            height: size.1,
+            color_map: self.colors.index(),
+            _padding: 0,
+        };
+        let params_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Heatmap Params Buffer"),
+            contents: bytemuck::bytes_of(&params),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Heatmap Bind Group"),
+            layout: &self.bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: params_buffer.as_entire_binding(),
+                },
+            ],
+        });
+        self.counts = Some(Counts {
+            size,
+            colors: self.colors,
+            buffer,
+            _params_buffer: params_buffer,
+            bind_group,
+        });
+    }
+
+    /// Counts the fragments of the quads of `ranges` and draws the heatmap over `view`, which must
+    /// be of the size last prepared.
+    pub fn encode(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        view: &wgpu::TextureView,
+        buffers: &ParticleBuffers,
+        ranges: &[Range<usize>],
+    ) {
+        let Some(counts) = &self.counts else {
+            return;
+        };
+        encoder.clear_buffer(&counts.buffer, 0, None);
+
+        {
+            // The target is only there for the rasterizer, the counts go to the buffer
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Heatmap Count Pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: false,
+                    },
+                })],
+                depth_stencil_attachment: None,
+            });
+            render_pass.set_pipeline(&self.count_pipeline);
+            render_pass.set_bind_group(0, buffers.camera_bind_group, &[]);
+            render_pass.set_bind_group(1, &counts.bind_group, &[]);
+            render_pass.set_vertex_buffer(0, buffers.vertex_buffer.slice(..));
+            render_pass.set_vertex_buffer(1, buffers.position_buffer.slice(..));
+            render_pass.set_index_buffer(buffers.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
+            for range in ranges {
+                render_pass.draw_indexed(
+                    0..buffers.index_count,
+                    0,
+                    range.start as u32..range.end as u32,
+                );
+            }
+        }
+
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Heatmap Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                    store: true,
+                },
+            })],
+            depth_stencil_attachment: None,
+        });
+        render_pass.set_pipeline(&self.heatmap_pipeline);
+        render_pass.set_bind_group(0, buffers.camera_bind_group, &[]);
+        render_pass.set_bind_group(1, &counts.bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
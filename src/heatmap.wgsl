@@ -0,0 +1,110 @@
+// Particle counts per pixel for the heatmap debug view: the quads are drawn counting their
+// fragments with atomics, then the counts are shown in false colors
+
+struct CameraUniform {
+    view_proj: mat4x4<f32>,
+};
+@group(0) @binding(0)
+var<uniform> camera: CameraUniform;
+
+struct Counts {
+    // Highest count of the frame, the top of the color scale
+    max: atomic<u32>,
+    pixels: array<atomic<u32>>,
+};
+@group(1) @binding(0)
+var<storage, read_write> counts: Counts;
+
+// Must match HeatmapParams in heatmap.rs
+struct Params {
+    width: u32,
+    height: u32,
+    // 0 for the spectrum, 1 for viridis and 2 for cividis, see HeatmapColors in accessibility.rs
+    color_map: u32,
+    _padding: u32,
+};
+@group(1) @binding(1)
+var<uniform> params: Params;
+
+struct VertexInput {
+    @location(0) position: vec3<f32>,
+    @location(1) vertex_position: vec2<f32>,
+    @location(4) normal: vec3<f32>,
+};
+
+struct InstanceInput {
+    @location(2) position: vec4<f32>,
+};
+
+@vertex
+fn vs_count(model: VertexInput, instance: InstanceInput) -> @builtin(position) vec4<f32> {
+    return camera.view_proj * vec4<f32>(model.position + instance.position.xyz, 1.0);
+}
+
+// Every fragment of the quads is counted, the ones the particle shader discards outside the circle
+// cost about as much. Nothing is written to the target
+@fragment
+fn fs_count(@builtin(position) position: vec4<f32>) -> @location(0) vec4<f32> {
+    let pixel = vec2<u32>(position.xy);
+    let count = atomicAdd(&counts.pixels[pixel.y * params.width + pixel.x], 1u) + 1u;
+    // Most fragments don't raise the maximum, reading it first spares them the contention
+    if count > atomicLoad(&counts.max) {
+        atomicMax(&counts.max, count);
+    }
+    return vec4<f32>(0.0);
+}
+
+// Dark blue through cyan, green and yellow to red, then white for the hottest pixels. Viridis and
+// cividis rise steadily in lightness instead, and can be read with a color vision deficiency
+fn false_color(t: f32) -> vec3<f32> {
+    // A var, arrays can only be indexed dynamically through a pointer
+    var stops: array<vec3<f32>, 6>;
+    switch params.color_map {
+        case 1u {
+            stops = array<vec3<f32>, 6>(
+                vec3<f32>(0.267, 0.005, 0.329),
+                vec3<f32>(0.255, 0.267, 0.529),
+                vec3<f32>(0.165, 0.471, 0.557),
+                vec3<f32>(0.133, 0.659, 0.518),
+                vec3<f32>(0.478, 0.820, 0.318),
+                vec3<f32>(0.992, 0.906, 0.145),
+            );
+        }
+        case 2u {
+            stops = array<vec3<f32>, 6>(
+                vec3<f32>(0.0, 0.133, 0.306),
+                vec3<f32>(0.208, 0.271, 0.424),
+                vec3<f32>(0.4, 0.412, 0.439),
+                vec3<f32>(0.580, 0.557, 0.467),
+                vec3<f32>(0.784, 0.722, 0.4),
+                vec3<f32>(0.996, 0.910, 0.220),
+            );
+        }
+        default {
+            stops = array<vec3<f32>, 6>(
+                vec3<f32>(0.0, 0.0, 0.3),
+                vec3<f32>(0.0, 0.6, 1.0),
+                vec3<f32>(0.0, 0.9, 0.2),
+                vec3<f32>(1.0, 0.9, 0.0),
+                vec3<f32>(1.0, 0.1, 0.0),
+                vec3<f32>(1.0, 1.0, 1.0),
+            );
+        }
+    }
+    let scaled = clamp(t, 0.0, 1.0) * 5.0;
+    let index = min(u32(scaled), 4u);
+    return mix(stops[index], stops[index + 1u], scaled - f32(index));
+}
+
+// Log scale, a few pixels drawn thousands of times don't leave everything else dark
+@fragment
+fn fs_heatmap(in: FullscreenOutput) -> @location(0) vec4<f32> {
+    let pixel = vec2<u32>(in.clip_position.xy);
+    let count = atomicLoad(&counts.pixels[pixel.y * params.width + pixel.x]);
+    if count == 0u {
+        return vec4<f32>(0.0, 0.0, 0.0, 1.0);
+    }
+    let max_count = max(atomicLoad(&counts.max), 2u);
+    let t = log2(f32(count)) / log2(f32(max_count));
+    return vec4<f32>(false_color(t), 1.0);
+}
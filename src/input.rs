@@ -0,0 +1,151 @@
+//! Camera flying controls held down on the keyboard or a gamepad, merged into one state.
+//!
+//! W, A, S and D move and the arrow keys look around, Shift flies faster, unless remapped in the
+//! keymap. On a gamepad the left
+//! stick moves, the right stick looks around, and the right and left triggers speed up and slow
+//! down.
+
+use crate::{camera::Camera, keymap::Action};
+use glam::{Quat, Vec2, Vec3};
+
+// World units per second at full stick or with a key held
+const MOVE_SPEED: f32 = 1000.0;
+// Radians per second at full stick or with a key held
+const LOOK_SPEED: f32 = 1.0;
+// Speed multiplier of Shift and of a fully pressed right trigger
+const BOOST: f32 = 4.0;
+// Speed multiplier of a fully pressed left trigger
+const BRAKE: f32 = 0.25;
+// Sticks report a little travel at rest
+const STICK_DEADZONE: f32 = 0.15;
+// Looking closer than this to straight up or down would flip the camera, in cosine of the angle
+const MAX_PITCH_COS: f32 = 0.99;
+
+/// Sticks and triggers of a gamepad, each stick axis from -1 to 1 and each trigger from 0 to 1.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct GamepadAxes {
+    pub left_stick: Vec2,
+    pub right_stick: Vec2,
+    pub left_trigger: f32,
+    pub right_trigger: f32,
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+struct HeldKeys {
+    forward: bool,
+    back: bool,
+    left: bool,
+    right: bool,
+    look_up: bool,
+    look_down: bool,
+    look_left: bool,
+    look_right: bool,
+    boost: bool,
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct InputState {
+    keys: HeldKeys,
+    gamepad: GamepadAxes,
+}
+
+impl InputState {
+    /// Updates the held keys. Returns true if `action` is a flying control.
+    pub fn action(&mut self, action: Action, pressed: bool) -> bool {
+        let held = match action {
+            Action::MoveForward => &mut self.keys.forward,
+            Action::MoveBack => &mut self.keys.back,
+            Action::MoveLeft => &mut self.keys.left,
+            Action::MoveRight => &mut self.keys.right,
+            Action::LookUp => &mut self.keys.look_up,
+            Action::LookDown => &mut self.keys.look_down,
+            Action::LookLeft => &mut self.keys.look_left,
+            Action::LookRight => &mut self.keys.look_right,
+            Action::Boost => &mut self.keys.boost,
+            _ => return false,
+        };
+        *held = pressed;
+        true
+    }
+
+    /// Releases every key, their releases don't reach an unfocused window.
+    pub fn release_keys(&mut self) {
+        self.keys = HeldKeys::default();
+    }
+
+    #[cfg(feature = "gamepad")]
+    pub fn set_gamepad(&mut self, axes: GamepadAxes) {
+        self.gamepad = axes;
+    }
+
+    /// Sideways and forward movement, at most 1 long.
+    fn movement(&self) -> Vec2 {
+        let keys = Vec2::new(
+            axis(self.keys.right, self.keys.left),
+            axis(self.keys.forward, self.keys.back),
+        );
+        (keys + deadzone(self.gamepad.left_stick)).clamp_length_max(1.0)
+    }
+
+    /// Yaw to the right and pitch up, at most 1 long.
+    fn look(&self) -> Vec2 {
+        let keys = Vec2::new(
+            axis(self.keys.look_right, self.keys.look_left),
+            axis(self.keys.look_up, self.keys.look_down),
+        );
+        (keys + deadzone(self.gamepad.right_stick)).clamp_length_max(1.0)
+    }
+
+    fn speed_factor(&self) -> f32 {
+        let boost = if self.keys.boost {
+            1.0
+        } else {
+            self.gamepad.right_trigger
+        };
+        let brake = self.gamepad.left_trigger;
+        (1.0 + (BOOST - 1.0) * boost) * (1.0 + (BRAKE - 1.0) * brake)
+    }
+
+    /// Flies `camera` for `dt` seconds. Returns true if it moved.
+    pub fn fly(&self, camera: &mut Camera, dt: f32) -> bool {
+        let (movement, look) = (self.movement(), self.look());
+        if movement == Vec2::ZERO && look == Vec2::ZERO {
+            return false;
+        }
+
+        let up = camera.up.try_normalize().unwrap_or(Vec3::Y);
+        let mut direction = camera.target - camera.eye;
+        let Some(forward) = direction.try_normalize() else {
+            return false;
+        };
+        let right = forward.cross(up).normalize_or_zero();
+
+        let yaw = Quat::from_axis_angle(up, -look.x * LOOK_SPEED * dt);
+        let pitch = Quat::from_axis_angle(right, look.y * LOOK_SPEED * dt);
+        let turned = yaw * pitch * direction;
+        // Stops pitching short of the up axis rather than flipping over it
+        if turned.normalize().dot(up).abs() < MAX_PITCH_COS {
+            direction = turned;
+        } else {
+            direction = yaw * direction;
+        }
+
+        let step =
+            (right * movement.x + forward * movement.y) * MOVE_SPEED * self.speed_factor() * dt;
+        camera.eye += step;
+        camera.target = camera.eye + direction;
+        true
+    }
+}
+
+fn axis(positive: bool, negative: bool) -> f32 {
+    positive as i32 as f32 - negative as i32 as f32
+}
+
+fn deadzone(stick: Vec2) -> Vec2 {
+    if stick.length() < STICK_DEADZONE {
+        Vec2::ZERO
+    } else {
+        stick
+    }
+}
@@ -0,0 +1,236 @@
+//! Input sessions, recorded with `--record-input` and replayed with `--replay` to reproduce what
+//! was seen interactively.
+//!
+//! The keyboard, mouse, touch and focus events of the main window are saved with the frame they
+//! arrived before, and the time since the start of the session. Both runs are deterministic, the
+//! particles start from the same seed and frames advance by a fixed time, so replaying the events
+//! before the same frames ends in the same state. The window should be the same size for the
+//! cursor positions to land on the same particles.
+//!
+//! Sessions are TOML, one `[[events]]` table per event appended as it arrives, so a session is
+//! kept up to the event before a crash.
+
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    iter::Peekable,
+    path::{Path, PathBuf},
+    time::Instant,
+};
+
+use serde::{Deserialize, Serialize};
+use winit::{
+    dpi::PhysicalPosition,
+    event::{
+        DeviceId, ElementState, KeyboardInput, ModifiersState, MouseButton, MouseScrollDelta,
+        Touch, TouchPhase, WindowEvent,
+    },
+};
+
+#[derive(Debug, thiserror::Error)]
+pub enum InputSessionError {
+    #[error("unable to write the input session {0}: {1}")]
+    Write(PathBuf, io::Error),
+    #[error("unable to read the input session {0}: {1}")]
+    Read(PathBuf, io::Error),
+    #[error("invalid input session {0}: {1}")]
+    Parse(PathBuf, toml::de::Error),
+}
+
+/// The events of a session that make it reproducible.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+enum InputEvent {
+    Keyboard(KeyboardInput),
+    Character(char),
+    Modifiers(ModifiersState),
+    MouseButton {
+        state: ElementState,
+        button: MouseButton,
+    },
+    MouseWheel(MouseScrollDelta),
+    CursorMoved(PhysicalPosition<f64>),
+    CursorLeft,
+    Touch {
+        phase: TouchPhase,
+        location: PhysicalPosition<f64>,
+        id: u64,
+    },
+    Focused(bool),
+}
+
+impl InputEvent {
+    fn from_window_event(event: &WindowEvent) -> Option<Self> {
+        Some(match *event {
+            WindowEvent::KeyboardInput { input, .. } => InputEvent::Keyboard(input),
+            WindowEvent::ReceivedCharacter(character) => InputEvent::Character(character),
+            WindowEvent::ModifiersChanged(modifiers) => InputEvent::Modifiers(modifiers),
+            WindowEvent::MouseInput { state, button, .. } => {
+                InputEvent::MouseButton { state, button }
+            }
+            WindowEvent::MouseWheel { delta, .. } => InputEvent::MouseWheel(delta),
+            WindowEvent::CursorMoved { position, .. } => InputEvent::CursorMoved(position),
+            WindowEvent::CursorLeft { .. } => InputEvent::CursorLeft,
+            WindowEvent::Touch(Touch {
+                phase,
+                location,
+                id,
+                ..
+            }) => InputEvent::Touch {
+                phase,
+                location,
+                id,
+            },
+            WindowEvent::Focused(focused) => InputEvent::Focused(focused),
+            _ => return None,
+        })
+    }
+
+    #[allow(deprecated)]
+    fn to_window_event(self) -> WindowEvent<'static> {
+        // # Safety
+        //
+        // The dummy device is only compared with other devices, never passed to the platform.
+        let device_id = unsafe { DeviceId::dummy() };
+        match self {
+            InputEvent::Keyboard(input) => WindowEvent::KeyboardInput {
+                device_id,
+                input,
+                is_synthetic: false,
+            },
+            InputEvent::Character(character) => WindowEvent::ReceivedCharacter(character),
+            InputEvent::Modifiers(modifiers) => WindowEvent::ModifiersChanged(modifiers),
+            InputEvent::MouseButton { state, button } => WindowEvent::MouseInput {
+                device_id,
+                state,
+                button,
+                modifiers: ModifiersState::empty(),
+            },
+            InputEvent::MouseWheel(delta) => WindowEvent::MouseWheel {
+                device_id,
+                delta,
+                phase: TouchPhase::Moved,
+                modifiers: ModifiersState::empty(),
+            },
+            InputEvent::CursorMoved(position) => WindowEvent::CursorMoved {
+                device_id,
+                position,
+                modifiers: ModifiersState::empty(),
+            },
+            InputEvent::CursorLeft => WindowEvent::CursorLeft { device_id },
+            InputEvent::Touch {
+                phase,
+                location,
+                id,
+            } => WindowEvent::Touch(Touch {
+                device_id,
+                phase,
+                location,
+                force: None,
+                id,
+            }),
+            InputEvent::Focused(focused) => WindowEvent::Focused(focused),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct RecordedEvent {
+    /// Frames rendered before the event arrived
+    frame: u64,
+    /// Since the start of the session, in seconds, only for reading the session
+    time: f64,
+    event: InputEvent,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+struct Session {
+    events: Vec<RecordedEvent>,
+}
+
+/// Appends the input events of the main window to a session file.
+pub struct InputRecorder {
+    path: PathBuf,
+    writer: Option<BufWriter<File>>,
+    started: Instant,
+    frame: u64,
+}
+
+impl InputRecorder {
+    pub fn new(path: &Path) -> Result<Self, InputSessionError> {
+        let file = File::create(path).map_err(|e| InputSessionError::Write(path.to_owned(), e))?;
+        log::info!("Recording input to {}", path.display());
+        Ok(Self {
+            path: path.to_owned(),
+            writer: Some(BufWriter::new(file)),
+            started: Instant::now(),
+            frame: 0,
+        })
+    }
+
+    /// Saves `event` if it's an input event, before the next frame.
+    pub fn record(&mut self, event: &WindowEvent) {
+        let (Some(writer), Some(event)) = (&mut self.writer, InputEvent::from_window_event(event))
+        else {
+            return;
+        };
+        let session = Session {
+            events: vec![RecordedEvent {
+                frame: self.frame,
+                time: self.started.elapsed().as_secs_f64(),
+                event,
+            }],
+        };
+        let written = toml::to_string(&session)
+            .map_err(io::Error::other)
+            .and_then(|text| writeln!(writer, "{text}"))
+            .and_then(|()| writer.flush());
+        if let Err(e) = written {
+            log::error!("{}", InputSessionError::Write(self.path.clone(), e));
+            self.writer = None;
+        }
+    }
+
+    pub fn frame_rendered(&mut self) {
+        self.frame += 1;
+    }
+}
+
+/// Events of a recorded session, handed back before the frames they arrived before.
+pub struct InputReplay {
+    events: Peekable<std::vec::IntoIter<RecordedEvent>>,
+    frame: u64,
+}
+
+impl InputReplay {
+    pub fn load(path: &Path) -> Result<Self, InputSessionError> {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| InputSessionError::Read(path.to_owned(), e))?;
+        let session: Session =
+            toml::from_str(&contents).map_err(|e| InputSessionError::Parse(path.to_owned(), e))?;
+        log::info!(
+            "Replaying {} input events from {}",
+            session.events.len(),
+            path.display()
+        );
+        Ok(Self {
+            events: session.events.into_iter().peekable(),
+            frame: 0,
+        })
+    }
+
+    /// Events to handle before rendering the next frame, in the order they arrived.
+    pub fn next_frame(&mut self) -> Vec<WindowEvent<'static>> {
+        let mut events = vec![];
+        while let Some(recorded) = self.events.next_if(|recorded| recorded.frame <= self.frame) {
+            events.push(recorded.event.to_window_event());
+        }
+        self.frame += 1;
+        events
+    }
+
+    /// Whether `event` is one the session replays, so the live one is ignored.
+    pub fn replays(event: &WindowEvent) -> bool {
+        InputEvent::from_window_event(event).is_some()
+    }
+}
@@ -0,0 +1,125 @@
+//! Inspector of a single particle, showing its live GPU values in the window title.
+//!
+//! Only the bytes of the inspected particle are copied back each frame, through a readback ring of a
+//! single buffer, so the values shown lag a frame or two behind.
+
+use crate::{
+    readback::{ParticleValues, ReadbackRing},
+    speed_layout::SpeedLayout,
+};
+
+pub struct Inspector {
+    index: Option<usize>,
+    // Digits of the index being typed, if any
+    typed: Option<String>,
+    values: Option<ParticleValues>,
+    ring: ReadbackRing,
+}
+
+impl Inspector {
+    /// Inspects the particle at `index`, or waits for one to be typed.
+    pub fn new(index: Option<usize>) -> Self {
+        Self {
+            index,
+            typed: index.is_none().then(String::new),
+            values: None,
+            ring: ReadbackRing::new(1, index.map(|index| index..index + 1)),
+        }
+    }
+
+    pub fn index(&self) -> Option<usize> {
+        self.index
+    }
+
+    pub fn typing(&self) -> bool {
+        self.typed.is_some()
+    }
+
+    pub fn select(&mut self, index: usize) {
+        self.index = Some(index);
+        self.typed = None;
+        self.values = None;
+        self.ring.set_ranges(Some(index..index + 1));
+    }
+
+    /// Starts typing the index of another particle.
+    pub fn start_typing(&mut self) {
+        self.typed = Some(String::new());
+    }
+
+    /// Adds `c` to the typed index if it is a digit.
+    pub fn type_char(&mut self, c: char) {
+        if let Some(typed) = self.typed.as_mut().filter(|_| c.is_ascii_digit()) {
+            typed.push(c);
+        }
+    }
+
+    pub fn backspace(&mut self) {
+        if let Some(typed) = &mut self.typed {
+            typed.pop();
+        }
+    }
+
+    /// Inspects the typed particle if it is below `count`.
+    pub fn confirm(&mut self, count: usize) {
+        let Some(typed) = &self.typed else {
+            return;
+        };
+        match typed.parse::<usize>() {
+            Ok(index) if index < count => self.select(index),
+            _ => log::warn!("No particle {typed}, there are {count}"),
+        }
+    }
+
+    /// Copies the values of the inspected particle for reading, unless the previous ones are still
+    /// being read. Must be followed by [`Inspector::map`] once submitted.
+    pub fn copy(
+        &mut self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        position_buffer: &wgpu::Buffer,
+        color_buffer: &wgpu::Buffer,
+        cpu_data_buffer: Option<(&wgpu::Buffer, SpeedLayout)>,
+    ) {
+        self.ring.copy(
+            device,
+            encoder,
+            position_buffer,
+            color_buffer,
+            cpu_data_buffer,
+        );
+    }
+
+    pub fn map(&mut self) {
+        self.ring.map();
+    }
+
+    /// Picks up values read back since the last call. Returns true if there were any.
+    pub fn try_take(&mut self, device: &wgpu::Device) -> bool {
+        let Some(readback) = self.ring.try_take(device) else {
+            return false;
+        };
+        let inspected = readback
+            .iter()
+            .next()
+            .map(|(index, values)| (index, *values));
+        match inspected {
+            // Values of a particle inspected before the last selection are dropped
+            Some((index, values)) if self.index == Some(index) => {
+                self.values = Some(values);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// What the window title shows.
+    pub fn describe(&self) -> String {
+        match (&self.typed, self.index, &self.values) {
+            (Some(typed), _, _) => format!("inspect particle #{typed}_"),
+            (None, Some(index), Some(values)) => format!("particle #{index} | {values}"),
+            (None, Some(index), None) => format!("particle #{index}"),
+            (None, None, _) => String::new(),
+        }
+    }
+}
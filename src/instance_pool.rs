@@ -0,0 +1,157 @@
+//! One pair of instance buffers shared by the particle systems of the grid view.
+//!
+//! Each system gets a slot of the buffers, starting at a storage binding offset so its compute
+//! kernel can bind its own positions. The draws of the grid bind the buffers once and pick each
+//! system's instances with the instance range. Freed slots go back to a free-list and get handed
+//! out again, so systems come and go without creating buffers or binding others.
+
+use std::ops::Range;
+
+use crate::vertex::{self, DrawnPosition, InstanceColor, InstancePosition};
+
+#[derive(Debug, thiserror::Error)]
+#[error("no room left for {0} particles in the instance pool")]
+pub struct PoolFull(pub usize);
+
+pub struct InstancePool {
+    position_buffer: wgpu::Buffer,
+    color_buffer: wgpu::Buffer,
+    // Written by the compute kernels along with the positions, with the `packed-instances` feature
+    drawn_position_buffer: Option<wgpu::Buffer>,
+    capacity: usize,
+    // Sorted, non-overlapping and non-touching
+    free: Vec<Range<usize>>,
+    // Slots start at multiples of it, in instances
+    alignment: usize,
+}
+
+impl InstancePool {
+    /// Buffers for `capacity` instances.
+    pub fn new(device: &wgpu::Device, capacity: usize) -> Self {
+        let buffer = |label, element_size: usize| {
+            device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some(label),
+                size: (capacity.max(1) * element_size) as u64,
+                usage: wgpu::BufferUsages::VERTEX
+                    | wgpu::BufferUsages::COPY_DST
+                    | wgpu::BufferUsages::COPY_SRC
+                    | wgpu::BufferUsages::STORAGE,
+                mapped_at_creation: false,
+            })
+        };
+        Self {
+            position_buffer: buffer(
+                "Pool Position Buffer",
+                std::mem::size_of::<InstancePosition>(),
+            ),
+            color_buffer: buffer("Pool Color Buffer", std::mem::size_of::<InstanceColor>()),
+            drawn_position_buffer: vertex::create_drawn_position_buffer(device, capacity),
+            capacity,
+            free: (capacity > 0).then_some(0..capacity).into_iter().collect(),
+            alignment: Self::alignment(device),
+        }
+    }
+
+    /// Instances a slot of `len` instances takes up at most, with its alignment.
+    pub fn slot_len(device: &wgpu::Device, len: usize) -> usize {
+        len.next_multiple_of(Self::alignment(device))
+    }
+
+    // Of the drawn positions too, which may be smaller
+    fn alignment(device: &wgpu::Device) -> usize {
+        (device.limits().min_storage_buffer_offset_alignment as usize)
+            .div_ceil(std::mem::size_of::<DrawnPosition>())
+            .max(1)
+    }
+
+    pub fn position_buffer(&self) -> &wgpu::Buffer {
+        &self.position_buffer
+    }
+
+    pub fn color_buffer(&self) -> &wgpu::Buffer {
+        &self.color_buffer
+    }
+
+    /// The positions in halves, with the `packed-instances` feature.
+    pub fn drawn_position_buffer(&self) -> Option<&wgpu::Buffer> {
+        self.drawn_position_buffer.as_ref()
+    }
+
+    /// Binds the drawn positions and the colors to the vertex buffer slots of the particle
+    /// pipelines.
+    pub fn set_vertex_buffers<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        let drawn_position_buffer = self
+            .drawn_position_buffer
+            .as_ref()
+            .unwrap_or(&self.position_buffer);
+        render_pass.set_vertex_buffer(1, drawn_position_buffer.slice(..));
+        render_pass.set_vertex_buffer(2, self.color_buffer.slice(..));
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Instances not handed out.
+    pub fn free_count(&self) -> usize {
+        self.free.iter().map(|range| range.len()).sum()
+    }
+
+    /// The first free slot of `len` instances, written with `positions` and `colors`.
+    pub fn allocate(
+        &mut self,
+        queue: &wgpu::Queue,
+        positions: &[InstancePosition],
+        colors: &[InstanceColor],
+    ) -> Result<Range<usize>, PoolFull> {
+        let len = positions.len();
+        let (index, start) = self
+            .free
+            .iter()
+            .enumerate()
+            .find_map(|(index, range)| {
+                let start = range.start.next_multiple_of(self.alignment);
+                (start + len <= range.end).then_some((index, start))
+            })
+            .ok_or(PoolFull(len))?;
+        let range = self.free.remove(index);
+        let slot = start..start + len;
+        // What's left on both sides of the slot stays free
+        let rest = [range.start..slot.start, slot.end..range.end];
+        for (offset, rest) in rest.into_iter().filter(|rest| !rest.is_empty()).enumerate() {
+            self.free.insert(index + offset, rest);
+        }
+
+        queue.write_buffer(
+            &self.position_buffer,
+            (slot.start * std::mem::size_of::<InstancePosition>()) as u64,
+            bytemuck::cast_slice(positions),
+        );
+        if let Some(drawn_position_buffer) = &self.drawn_position_buffer {
+            vertex::write_drawn_positions(queue, drawn_position_buffer, slot.start, positions);
+        }
+        queue.write_buffer(
+            &self.color_buffer,
+            (slot.start * std::mem::size_of::<InstanceColor>()) as u64,
+            bytemuck::cast_slice(colors),
+        );
+        Ok(slot)
+    }
+
+    /// Hands `slot` out again, merged with the free slots it touches.
+    pub fn free(&mut self, slot: Range<usize>) {
+        if slot.is_empty() {
+            return;
+        }
+        let index = self.free.partition_point(|range| range.end <= slot.start);
+        self.free.insert(index, slot);
+        if index + 1 < self.free.len() && self.free[index].end == self.free[index + 1].start {
+            let next = self.free.remove(index + 1);
+            self.free[index].end = next.end;
+        }
+        if index > 0 && self.free[index - 1].end == self.free[index].start {
+            let merged = self.free.remove(index);
+            self.free[index - 1].end = merged.end;
+        }
+    }
+}
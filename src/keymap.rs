@@ -0,0 +1,440 @@
+//! Keys bound to each action, so the controls can be remapped.
+//!
+//! The default keymap is overridden by `keymap.toml` in the platform config directory, e.g.
+//! `~/.config/particles/keymap.toml` on Linux, next to the settings. Each line binds an action to
+//! a key or a list of keys, named after winit's `VirtualKeyCode`, optionally with `Ctrl+`:
+//!
+//! ```toml
+//! pause = "P"
+//! capture = "Ctrl+P"
+//! boost = ["LShift", "RShift"]
+//! # Unbinds the action
+//! toggle_fullscreen = []
+//! ```
+//!
+//! An action given in the file loses its default keys. Keys bound with Ctrl held trigger the action
+//! bound without Ctrl if they have none of their own.
+
+use std::{collections::HashMap, fmt::Display, path::PathBuf, str::FromStr};
+
+use serde::{de::IntoDeserializer, Deserialize};
+use winit::event::{ModifiersState, VirtualKeyCode};
+
+use crate::{camera_presets, groups};
+
+#[derive(Debug, thiserror::Error)]
+pub enum KeymapError {
+    #[error("no config directory on this platform")]
+    NoConfigDir,
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Parse(#[from] toml::de::Error),
+    #[error("unknown action `{0}`")]
+    UnknownAction(String),
+    #[error("invalid key `{key}` for `{action}`")]
+    InvalidKey { action: String, key: String },
+}
+
+/// Everything the keyboard does.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Action {
+    // Held down, see input.rs
+    MoveForward,
+    MoveBack,
+    MoveLeft,
+    MoveRight,
+    LookUp,
+    LookDown,
+    LookLeft,
+    LookRight,
+    Boost,
+    /// Jumps to the camera preset in the slot, 0 based, or stores it with Ctrl held
+    CameraPreset(usize),
+    /// Shows or hides the particle group, 0 based, in the window
+    ToggleGroup(usize),
+    TurbulenceFrequencyDown,
+    TurbulenceFrequencyUp,
+    TurbulenceAmplitudeDown,
+    TurbulenceAmplitudeUp,
+    ParticleCountDown,
+    ParticleCountUp,
+    SpeedDown,
+    SpeedUp,
+    StepDown,
+    StepUp,
+    TimeScaleDown,
+    TimeScaleUp,
+    DampingDown,
+    DampingUp,
+    AttractorDown,
+    AttractorUp,
+    /// Pushes the particles away from the point under the cursor, once
+    Explode,
+    /// Pulls the particles towards the point under the cursor, once
+    Implode,
+    EyeSeparationDown,
+    EyeSeparationUp,
+    ConvergenceDown,
+    ConvergenceUp,
+    #[cfg(feature = "post-processing")]
+    ApertureDown,
+    #[cfg(feature = "post-processing")]
+    ApertureUp,
+    RenderResolutionDown,
+    RenderResolutionUp,
+    Pause,
+    ResetParticles,
+    SimulateOnCpu,
+    DeleteBlock,
+    NewWindow,
+    CloseCell,
+    ToggleBounds,
+    ToggleExplore,
+    Bookmark,
+    ToggleTrails,
+    ToggleBlendMode,
+    ToggleColorsByAge,
+    #[cfg(feature = "ui")]
+    ToggleUi,
+    ToggleLod,
+    ToggleCompaction,
+    ToggleStretched,
+    ToggleSplats,
+    ToggleSpinning,
+    ToggleGizmos,
+    ToggleSpatialHash,
+    ToggleOverlap,
+    ToggleVolume,
+    CycleStereoView,
+    ToggleParticleStats,
+    CycleDebugView,
+    Inspect,
+    ToggleFullscreen,
+    TogglePacing,
+    #[cfg(feature = "post-processing")]
+    ToggleDepthOfField,
+    #[cfg(feature = "post-processing")]
+    ToggleHalfResolution,
+    #[cfg(feature = "post-processing")]
+    ToggleMotionBlur,
+    #[cfg(feature = "post-processing")]
+    ToggleTemporal,
+    #[cfg(feature = "post-processing")]
+    ToggleCheckerboard,
+    #[cfg(feature = "post-processing")]
+    ToggleObstacleView,
+    #[cfg(feature = "post-processing")]
+    ToggleColorGrading,
+    Capture,
+    CapturePanorama,
+    StereoSideBySide,
+    StereoAnaglyph,
+    WriteFrameLog,
+    FrameParticles,
+}
+
+// Name in the keymap file and default keys of every action but the camera presets and the group
+// toggles
+const ACTIONS: &[(Action, &str, &[&str])] = &[
+    (Action::MoveForward, "move_forward", &["W"]),
+    (Action::MoveBack, "move_back", &["S"]),
+    (Action::MoveLeft, "move_left", &["A"]),
+    (Action::MoveRight, "move_right", &["D"]),
+    (Action::LookUp, "look_up", &["Up"]),
+    (Action::LookDown, "look_down", &["Down"]),
+    (Action::LookLeft, "look_left", &["Left"]),
+    (Action::LookRight, "look_right", &["Right"]),
+    (Action::Boost, "boost", &["LShift", "RShift"]),
+    (
+        Action::TurbulenceFrequencyDown,
+        "turbulence_frequency_down",
+        &["LBracket"],
+    ),
+    (
+        Action::TurbulenceFrequencyUp,
+        "turbulence_frequency_up",
+        &["RBracket"],
+    ),
+    (
+        Action::TurbulenceAmplitudeDown,
+        "turbulence_amplitude_down",
+        &["Minus"],
+    ),
+    (
+        Action::TurbulenceAmplitudeUp,
+        "turbulence_amplitude_up",
+        &["Equals"],
+    ),
+    (
+        Action::ParticleCountDown,
+        "particle_count_down",
+        &["Ctrl+Minus"],
+    ),
+    (
+        Action::ParticleCountUp,
+        "particle_count_up",
+        &["Ctrl+Equals"],
+    ),
+    (Action::SpeedDown, "speed_down", &["N"]),
+    (Action::SpeedUp, "speed_up", &["M"]),
+    (Action::StepDown, "step_down", &["PageDown"]),
+    (Action::StepUp, "step_up", &["PageUp"]),
+    (Action::TimeScaleDown, "time_scale_down", &["Ctrl+Comma"]),
+    (Action::TimeScaleUp, "time_scale_up", &["Ctrl+Period"]),
+    (Action::DampingDown, "damping_down", &["J"]),
+    (Action::DampingUp, "damping_up", &["K"]),
+    (Action::AttractorDown, "attractor_down", &["G"]),
+    (Action::AttractorUp, "attractor_up", &["H"]),
+    (Action::Explode, "explode", &["Ctrl+E"]),
+    (Action::Implode, "implode", &["Ctrl+I"]),
+    (Action::EyeSeparationDown, "eye_separation_down", &["Comma"]),
+    (Action::EyeSeparationUp, "eye_separation_up", &["Period"]),
+    (Action::ConvergenceDown, "convergence_down", &["Semicolon"]),
+    (Action::ConvergenceUp, "convergence_up", &["Apostrophe"]),
+    #[cfg(feature = "post-processing")]
+    (Action::ApertureDown, "aperture_down", &["U"]),
+    #[cfg(feature = "post-processing")]
+    (Action::ApertureUp, "aperture_up", &["I"]),
+    (
+        Action::RenderResolutionDown,
+        "render_resolution_down",
+        &["F9"],
+    ),
+    (Action::RenderResolutionUp, "render_resolution_up", &["F10"]),
+    (Action::Pause, "pause", &["Space"]),
+    (Action::ResetParticles, "reset_particles", &["Ctrl+R"]),
+    (Action::SimulateOnCpu, "simulate_on_cpu", &["R"]),
+    (Action::DeleteBlock, "delete_block", &["Delete"]),
+    (Action::NewWindow, "new_window", &["Ctrl+N"]),
+    (Action::CloseCell, "close_cell", &["Ctrl+W"]),
+    (Action::ToggleBounds, "toggle_bounds", &["X"]),
+    (Action::ToggleExplore, "toggle_explore", &["E"]),
+    (Action::Bookmark, "bookmark", &["Q"]),
+    (Action::ToggleTrails, "toggle_trails", &["T"]),
+    (Action::ToggleBlendMode, "toggle_blend_mode", &["Y"]),
+    (Action::ToggleColorsByAge, "toggle_colors_by_age", &["Z"]),
+    #[cfg(feature = "ui")]
+    (Action::ToggleUi, "toggle_ui", &["F1"]),
+    (Action::ToggleLod, "toggle_lod", &["L"]),
+    (Action::ToggleCompaction, "toggle_compaction", &["F6"]),
+    (Action::ToggleStretched, "toggle_stretched", &["F12"]),
+    (Action::ToggleSplats, "toggle_splats", &["Ctrl+G"]),
+    (Action::ToggleSpinning, "toggle_spinning", &["Ctrl+T"]),
+    (Action::ToggleGizmos, "toggle_gizmos", &["Ctrl+O"]),
+    (Action::ToggleSpatialHash, "toggle_spatial_hash", &["F3"]),
+    (Action::ToggleOverlap, "toggle_overlap", &["Home"]),
+    (Action::ToggleVolume, "toggle_volume", &["End"]),
+    (Action::CycleStereoView, "cycle_stereo_view", &["Tab"]),
+    (
+        Action::ToggleParticleStats,
+        "toggle_particle_stats",
+        &["Grave"],
+    ),
+    (Action::CycleDebugView, "cycle_debug_view", &["F7"]),
+    (Action::Inspect, "inspect", &["F4"]),
+    (Action::ToggleFullscreen, "toggle_fullscreen", &["F11"]),
+    (Action::TogglePacing, "toggle_pacing", &["F8"]),
+    #[cfg(feature = "post-processing")]
+    (Action::ToggleDepthOfField, "toggle_depth_of_field", &["F"]),
+    #[cfg(feature = "post-processing")]
+    (
+        Action::ToggleHalfResolution,
+        "toggle_half_resolution",
+        &["F2"],
+    ),
+    #[cfg(feature = "post-processing")]
+    (
+        Action::ToggleMotionBlur,
+        "toggle_motion_blur",
+        &["Backslash"],
+    ),
+    #[cfg(feature = "post-processing")]
+    (Action::ToggleTemporal, "toggle_temporal", &["Slash"]),
+    #[cfg(feature = "post-processing")]
+    (Action::ToggleCheckerboard, "toggle_checkerboard", &["F5"]),
+    #[cfg(feature = "post-processing")]
+    (Action::ToggleObstacleView, "toggle_obstacle_view", &["O"]),
+    #[cfg(feature = "post-processing")]
+    (
+        Action::ToggleColorGrading,
+        "toggle_color_grading",
+        &["Key0"],
+    ),
+    (Action::Capture, "capture", &["P"]),
+    (Action::CapturePanorama, "capture_panorama", &["C"]),
+    (Action::StereoSideBySide, "stereo_side_by_side", &["V"]),
+    (Action::StereoAnaglyph, "stereo_anaglyph", &["B"]),
+    (Action::WriteFrameLog, "write_frame_log", &["Insert"]),
+    (Action::FrameParticles, "frame_particles", &["Back"]),
+];
+const CAMERA_PRESET_PREFIX: &str = "camera_preset_";
+const CAMERA_PRESET_KEYS: [&str; camera_presets::SLOTS] = [
+    "Key1", "Key2", "Key3", "Key4", "Key5", "Key6", "Key7", "Key8", "Key9",
+];
+const TOGGLE_GROUP_PREFIX: &str = "toggle_group_";
+const TOGGLE_GROUP_KEYS: [&str; groups::GROUPS] = ["Numpad1", "Numpad2", "Numpad3", "Numpad4"];
+const CTRL_PREFIX: &str = "Ctrl+";
+
+impl Display for Action {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Action::CameraPreset(slot) = self {
+            return write!(f, "{CAMERA_PRESET_PREFIX}{}", slot + 1);
+        }
+        if let Action::ToggleGroup(group) = self {
+            return write!(f, "{TOGGLE_GROUP_PREFIX}{}", group + 1);
+        }
+        let (_, name, _) = ACTIONS.iter().find(|(action, ..)| action == self).unwrap();
+        write!(f, "{name}")
+    }
+}
+
+impl FromStr for Action {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(slot) = s.strip_prefix(CAMERA_PRESET_PREFIX) {
+            return match slot.parse::<usize>() {
+                Ok(slot @ 1..=camera_presets::SLOTS) => Ok(Action::CameraPreset(slot - 1)),
+                _ => Err(()),
+            };
+        }
+        if let Some(group) = s.strip_prefix(TOGGLE_GROUP_PREFIX) {
+            return match group.parse::<usize>() {
+                Ok(group @ 1..=groups::GROUPS) => Ok(Action::ToggleGroup(group - 1)),
+                _ => Err(()),
+            };
+        }
+        ACTIONS
+            .iter()
+            .find(|(_, name, _)| *name == s)
+            .map(|&(action, ..)| action)
+            .ok_or(())
+    }
+}
+
+/// A key, pressed with or without Ctrl.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct KeyBinding {
+    key: VirtualKeyCode,
+    ctrl: bool,
+}
+
+impl FromStr for KeyBinding {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, ctrl) = match s.strip_prefix(CTRL_PREFIX) {
+            Some(name) => (name, true),
+            None => (s, false),
+        };
+        let key: Result<VirtualKeyCode, serde::de::value::Error> =
+            VirtualKeyCode::deserialize(name.into_deserializer());
+        Ok(Self {
+            key: key.map_err(|_| ())?,
+            ctrl,
+        })
+    }
+}
+
+/// A key or a list of keys in the keymap file.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Keys {
+    One(String),
+    Many(Vec<String>),
+}
+
+pub struct Keymap {
+    bindings: HashMap<KeyBinding, Action>,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        let mut keymap = Self {
+            bindings: HashMap::new(),
+        };
+        for &(action, _, keys) in ACTIONS {
+            keymap.bind(action, keys);
+        }
+        for (slot, key) in CAMERA_PRESET_KEYS.into_iter().enumerate() {
+            keymap.bind(Action::CameraPreset(slot), &[key]);
+        }
+        for (group, key) in TOGGLE_GROUP_KEYS.into_iter().enumerate() {
+            keymap.bind(Action::ToggleGroup(group), &[key]);
+        }
+        keymap
+    }
+}
+
+impl Keymap {
+    fn path() -> Option<PathBuf> {
+        dirs::config_dir().map(|dir| dir.join(env!("CARGO_PKG_NAME")).join("keymap.toml"))
+    }
+
+    /// The default keymap with the overrides of the keymap file, or without them if there is none
+    /// or it can't be read.
+    pub fn load() -> Self {
+        match Self::try_load() {
+            Ok(keymap) => keymap,
+            Err(e) => {
+                log::warn!("Ignoring the keymap file: {e}");
+                Self::default()
+            }
+        }
+    }
+
+    fn try_load() -> Result<Self, KeymapError> {
+        let path = Self::path().ok_or(KeymapError::NoConfigDir)?;
+        match std::fs::read_to_string(&path) {
+            Ok(contents) => Self::parse(&contents),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// The default keymap with the overrides of `contents`, a keymap file.
+    pub fn parse(contents: &str) -> Result<Self, KeymapError> {
+        let overrides: HashMap<String, Keys> = toml::from_str(contents)?;
+        let mut keymap = Self::default();
+        for (name, keys) in overrides {
+            let action = name
+                .parse()
+                .map_err(|_| KeymapError::UnknownAction(name.clone()))?;
+            let keys = match keys {
+                Keys::One(key) => vec![key],
+                Keys::Many(keys) => keys,
+            };
+            keymap.bindings.retain(|_, bound| *bound != action);
+            for key in keys {
+                let binding = key.parse().map_err(|_| KeymapError::InvalidKey {
+                    action: name.clone(),
+                    key,
+                })?;
+                keymap.bindings.insert(binding, action);
+            }
+        }
+        Ok(keymap)
+    }
+
+    // Only called with the keys of the tables above, which are valid
+    fn bind(&mut self, action: Action, keys: &[&str]) {
+        for key in keys {
+            self.bindings.insert(key.parse().unwrap(), action);
+        }
+    }
+
+    /// Action of `key` pressed with `modifiers`, the action bound without Ctrl if there is none
+    /// with it.
+    pub fn action(&self, key: VirtualKeyCode, modifiers: ModifiersState) -> Option<Action> {
+        let ctrl = KeyBinding {
+            key,
+            ctrl: modifiers.ctrl(),
+        };
+        let plain = KeyBinding { key, ctrl: false };
+        self.bindings
+            .get(&ctrl)
+            .or_else(|| self.bindings.get(&plain))
+            .copied()
+    }
+}
@@ -0,0 +1,192 @@
+//! Instanced particles simulated in compute shaders, with the renderer of the `particles` binary.
+//!
+//! [`ParticleRenderer`] embeds a particle system into another wgpu application: it simulates the
+//! particles of the default scene on the GPU and draws them into a view of the application's frame
+//! with its own camera. The other modules make up the binary, which reaches them through a hidden
+//! `internal` module that isn't part of the library's API.
+
+mod state;
+mod vertex;
+mod camera;
+mod accessibility;
+mod adapters;
+mod adaptive;
+mod appearance;
+mod arena;
+mod behavior;
+mod bench;
+mod boids;
+mod camera_path;
+mod camera_presets;
+mod capabilities;
+mod capture;
+mod checkpoint;
+mod collisions;
+mod compaction;
+mod debug_view;
+mod dirty_ranges;
+mod distribution;
+mod emitter;
+mod encoding;
+mod environment;
+mod error;
+mod explore;
+mod frame_graph;
+mod frame_hash;
+mod frame_log;
+mod frame_stats;
+mod framing;
+mod gizmos;
+mod golden;
+mod gradient;
+mod grid;
+mod groups;
+mod half_float;
+mod heatmap;
+mod input;
+mod input_session;
+mod inspector;
+mod instance_pool;
+mod keymap;
+mod lights;
+mod lod;
+mod memory_budget;
+mod multi_draw;
+mod nbody;
+mod obstacles;
+mod options;
+mod overlap;
+mod pacing;
+mod palette;
+mod panorama;
+mod particle_init;
+mod picking;
+mod pipeline_cache;
+mod point_cache;
+mod point_cloud;
+// Not used by the renderer yet, building blocks for compaction, sorting and grids
+#[allow(dead_code)]
+mod prefix_sum;
+#[allow(dead_code)]
+mod radix_sort;
+mod readback;
+mod recording;
+mod reduction;
+mod render_target;
+mod renderer;
+mod scene;
+mod schedule;
+mod screensaver;
+mod search;
+mod settings;
+mod shader_check;
+mod sim_params;
+mod spatial_hash;
+mod speed_layout;
+mod spinning;
+mod stereo;
+mod stereo_view;
+mod stretched;
+mod surface_format;
+mod surface_recovery;
+mod touch;
+mod trace;
+mod trails;
+mod trajectories;
+mod turbulence;
+mod vertex_pulling;
+mod viewport;
+mod volume;
+mod watchdog;
+mod wind;
+#[cfg(feature = "audio")]
+mod audio;
+#[cfg(feature = "post-processing")]
+mod checkerboard;
+#[cfg(feature = "post-processing")]
+mod color_grading;
+#[cfg(feature = "post-processing")]
+mod depth_of_field;
+#[cfg(feature = "gamepad")]
+mod gamepad;
+#[cfg(feature = "metrics")]
+mod gpu_timer;
+#[cfg(feature = "guardrails")]
+mod guardrails;
+#[cfg(feature = "post-processing")]
+mod half_resolution;
+#[cfg(feature = "asset-loaders")]
+mod mesh_emitter;
+#[cfg(feature = "metrics")]
+mod metrics;
+#[cfg(feature = "post-processing")]
+mod motion_blur;
+#[cfg(feature = "asset-loaders")]
+mod particle_data;
+#[cfg(feature = "packed-instances")]
+mod position_packing;
+#[cfg(feature = "metrics")]
+mod query_pool;
+#[cfg(feature = "scripting")]
+mod scripting;
+#[cfg(feature = "post-processing")]
+mod soft_particles;
+#[cfg(feature = "metrics")]
+mod telemetry;
+#[cfg(feature = "post-processing")]
+mod temporal;
+#[cfg(test)]
+mod test_device;
+#[cfg(feature = "ui")]
+mod ui;
+
+pub use crate::{
+    camera::{Camera, DepthRange},
+    error::AppError,
+    renderer::ParticleRenderer,
+};
+
+/// The modules of the `particles` binary, public for it only: they may change in any release.
+#[doc(hidden)]
+pub mod internal {
+    pub mod adapters {
+        pub use crate::adapters::*;
+    }
+    pub mod bench {
+        pub use crate::bench::*;
+    }
+    pub mod error {
+        pub use crate::error::*;
+    }
+    #[cfg(feature = "gamepad")]
+    pub mod gamepad {
+        pub use crate::gamepad::*;
+    }
+    pub mod golden {
+        pub use crate::golden::*;
+    }
+    pub mod input_session {
+        pub use crate::input_session::*;
+    }
+    pub mod options {
+        pub use crate::options::*;
+    }
+    pub mod screensaver {
+        pub use crate::screensaver::*;
+    }
+    pub mod search {
+        pub use crate::search::*;
+    }
+    pub mod settings {
+        pub use crate::settings::*;
+    }
+    pub mod state {
+        pub use crate::state::*;
+    }
+    pub mod trace {
+        pub use crate::trace::*;
+    }
+    pub mod viewport {
+        pub use crate::viewport::*;
+    }
+}
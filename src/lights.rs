@@ -0,0 +1,129 @@
+//! Point and directional lights of the scene, on top of the light of the day cycle.
+//!
+//! Scenes list them in `[light]` sections, uploaded to a storage buffer bound with the camera.
+//! shader.wgsl shades the particles with Lambert diffuse and Blinn-Phong highlights from each.
+
+use std::str::FromStr;
+
+use bytemuck::{Pod, Zeroable};
+use glam::{Vec3, Vec4};
+
+/// Most lights a scene can have, the buffer is allocated for this many.
+pub const MAX_LIGHTS: usize = 16;
+// Distance at which point lights fade out, unless the scene sets one
+const DEFAULT_RANGE: f32 = 1000.0;
+const DEFAULT_SPECULAR: f32 = 0.5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LightKind {
+    /// Lights everything from the same direction
+    Directional,
+    /// Lights the particles around a position, fading out with the distance
+    Point,
+}
+
+impl FromStr for LightKind {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "directional" => Ok(LightKind::Directional),
+            "point" => Ok(LightKind::Point),
+            _ => Err(()),
+        }
+    }
+}
+
+/// One light, shared with shader.wgsl.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq, Pod, Zeroable)]
+pub struct Light {
+    /// Position of point lights with w at 1, direction towards directional lights with w at 0
+    pub position: Vec4,
+    /// w is the intensity
+    pub color: Vec4,
+    /// Distance at which point lights fade out
+    pub range: f32,
+    /// Strength of the highlights, 0 for diffuse only
+    pub specular: f32,
+    pub _padding: [u32; 2],
+}
+
+// Must match Lights in shader.wgsl, followed by the lights
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Pod, Zeroable)]
+struct LightsHeader {
+    count: u32,
+    _padding: [u32; 3],
+}
+
+impl Light {
+    /// A white light of `kind` at the origin, or shining from above.
+    pub fn new(kind: LightKind) -> Self {
+        let position = match kind {
+            LightKind::Directional => Vec4::Y,
+            LightKind::Point => Vec4::W,
+        };
+        Self {
+            position,
+            color: Vec4::ONE,
+            range: DEFAULT_RANGE,
+            specular: DEFAULT_SPECULAR,
+            _padding: [0; 2],
+        }
+    }
+
+    fn is_point(&self) -> bool {
+        self.position.w > 0.0
+    }
+
+    /// Sets the vector parameter `name` of the light. Returns false if the light doesn't have it.
+    pub fn set_vector(&mut self, name: &str, value: Vec3) -> bool {
+        match name {
+            "position" if self.is_point() => self.position = value.extend(1.0),
+            "direction" if !self.is_point() && value != Vec3::ZERO => {
+                self.position = value.normalize().extend(0.0)
+            }
+            "color" => self.color = value.extend(self.color.w),
+            _ => return false,
+        }
+        true
+    }
+
+    /// Sets the scalar parameter `name` of the light. Returns false if the light doesn't have it.
+    pub fn set_scalar(&mut self, name: &str, value: f32) -> bool {
+        match name {
+            "intensity" => self.color.w = value,
+            "range" if self.is_point() => self.range = value,
+            "specular" => self.specular = value,
+            _ => return false,
+        }
+        true
+    }
+}
+
+/// Storage buffer for up to [`MAX_LIGHTS`] lights, filled by [`write_buffer`].
+pub fn create_buffer(device: &wgpu::Device) -> wgpu::Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Light Buffer"),
+        size: (std::mem::size_of::<LightsHeader>() + MAX_LIGHTS * std::mem::size_of::<Light>())
+            as u64,
+        usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    })
+}
+
+/// Replaces the lights of `buffer` with the first [`MAX_LIGHTS`] of `lights`.
+pub fn write_buffer(queue: &wgpu::Queue, buffer: &wgpu::Buffer, lights: &[Light]) {
+    let lights = &lights[..lights.len().min(MAX_LIGHTS)];
+    let header = LightsHeader {
+        count: lights.len() as u32,
+        _padding: [0; 3],
+    };
+    queue.write_buffer(buffer, 0, bytemuck::bytes_of(&header));
+    queue.write_buffer(
+        buffer,
+        std::mem::size_of::<LightsHeader>() as u64,
+        bytemuck::cast_slice(lights),
+    );
+}
@@ -0,0 +1,365 @@
+//! Level of detail of the particles: the further from the camera, the cheaper the mesh.
+//!
+//! Like compaction, a compute pass copies the alive instances next to each other, sorted into
+//! distance buckets with a counting pass first. Near particles are drawn as quads, the middle ones
+//! as a single triangle around the particle and far ones as points, through indirect draws.
+//! Drawing a bucket from its first instance requires `Features::INDIRECT_FIRST_INSTANCE`.
+
+use std::{ops::Range, sync::Arc};
+
+use bytemuck::{Pod, Zeroable};
+use wgpu::util::DeviceExt;
+
+use crate::{
+    capabilities::GpuCapabilities,
+    shader_check,
+    vertex::{
+        DrawnPosition, InstanceColor, InstancePosition, Vertex, INSTANCE_COLOR_WGSL,
+        INSTANCE_POSITION_WGSL,
+    },
+};
+
+// Must match `@workgroup_size` in lod.wgsl
+const WORKGROUP_SIZE: u32 = 64;
+const ALIVE_BITS_PER_WORD: usize = 32;
+const BUCKETS: usize = 3;
+// Distances from the camera where particles switch to the triangle, then to points
+const NEAR_DISTANCE: f32 = 1500.0;
+const FAR_DISTANCE: f32 = 4000.0;
+
+// Equilateral triangle around the particle's disk, with the same corner coordinates as the quad
+const TRIANGLE_VERTICES: &[Vertex] = &[
+    Vertex {
+        position: [0.0, 1.0, 0.0],
+        vertex_position: [0.5, 1.5],
+        normal: [0.0, 0.0, 1.0],
+    },
+    Vertex {
+        position: [-0.866, -0.5, 0.0],
+        vertex_position: [-0.366, 0.0],
+        normal: [0.0, 0.0, 1.0],
+    },
+    Vertex {
+        position: [0.866, -0.5, 0.0],
+        vertex_position: [1.366, 0.0],
+        normal: [0.0, 0.0, 1.0],
+    },
+];
+const TRIANGLE_INDICES: &[u16] = &[0, 1, 2];
+
+// Must match Params in lod.wgsl
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq, Pod, Zeroable)]
+struct LodParams {
+    eye: glam::Vec4,
+    near_distance: f32,
+    far_distance: f32,
+    capacity: u32,
+    _padding: u32,
+}
+
+// Must match Draws in lod.wgsl
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Pod, Zeroable)]
+struct LodDraws {
+    near: [u32; 5],
+    middle: [u32; 5],
+    far: [u32; 4],
+}
+
+pub struct Lod {
+    capacity: usize,
+    // Ranges the alive bits were last uploaded for
+    alive_ranges: Vec<Range<usize>>,
+    params: LodParams,
+    alive_buffer: wgpu::Buffer,
+    params_buffer: wgpu::Buffer,
+    position_buffer: wgpu::Buffer,
+    color_buffer: wgpu::Buffer,
+    counters_buffer: wgpu::Buffer,
+    indirect_buffer: wgpu::Buffer,
+    triangle_vertex_buffer: wgpu::Buffer,
+    triangle_index_buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+    count_pipeline: wgpu::ComputePipeline,
+    scatter_pipeline: wgpu::ComputePipeline,
+    // Far particles, for each depth-stencil format of the particles pass
+    point_pipelines: Vec<(Option<wgpu::TextureFormat>, Arc<wgpu::RenderPipeline>)>,
+}
+
+impl Lod {
+    /// Sorts the `capacity` instances of `position_buffer` and `color_buffer`, the near ones drawn
+    /// with `index_count` indices each. Far ones are drawn with the point pipeline of the pass'
+    /// depth-stencil format. Returns `None` if the device can't bind the sorted buffers or draw
+    /// the buckets.
+    pub fn new(
+        device: &wgpu::Device,
+        capabilities: &GpuCapabilities,
+        capacity: usize,
+        index_count: u32,
+        position_buffer: &wgpu::Buffer,
+        color_buffer: &wgpu::Buffer,
+        point_pipelines: Vec<(Option<wgpu::TextureFormat>, Arc<wgpu::RenderPipeline>)>,
+    ) -> Option<Self> {
+        if !capabilities.indirect_first_instance {
+            return None;
+        }
+        let position_size = (capacity * std::mem::size_of::<InstancePosition>()) as u64;
+        let drawn_size = (capacity * std::mem::size_of::<DrawnPosition>()) as u64;
+        let color_size = (capacity * std::mem::size_of::<InstanceColor>()) as u64;
+        let limits = device.limits();
+        let max_size = (limits.max_storage_buffer_binding_size as u64).min(limits.max_buffer_size);
+        if position_size.max(color_size) > max_size {
+            return None;
+        }
+
+        let alive_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("LOD Alive Buffer"),
+            size: (capacity.div_ceil(ALIVE_BITS_PER_WORD).max(1) * std::mem::size_of::<u32>())
+                as u64,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let sorted_buffer = |label, size| {
+            device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some(label),
+                size,
+                usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::STORAGE,
+                mapped_at_creation: false,
+            })
+        };
+        let sorted_position_buffer = sorted_buffer("LOD Position Buffer", drawn_size);
+        let sorted_color_buffer = sorted_buffer("LOD Color Buffer", color_size);
+        let counters_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("LOD Counters Buffer"),
+            size: (2 * BUCKETS * std::mem::size_of::<u32>()) as u64,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        // Only the instance counts and first instances are written by the shader
+        let draws = LodDraws {
+            near: [index_count, 0, 0, 0, 0],
+            middle: [TRIANGLE_INDICES.len() as u32, 0, 0, 0, 0],
+            far: [1, 0, 0, 0],
+        };
+        let indirect_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("LOD Indirect Buffer"),
+            contents: bytemuck::bytes_of(&draws),
+            usage: wgpu::BufferUsages::INDIRECT | wgpu::BufferUsages::STORAGE,
+        });
+
+        let params = LodParams {
+            eye: glam::Vec4::W,
+            near_distance: NEAR_DISTANCE,
+            far_distance: FAR_DISTANCE,
+            capacity: capacity as u32,
+            _padding: 0,
+        };
+        let params_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("LOD Params Buffer"),
+            contents: bytemuck::bytes_of(&params),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let triangle_vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("LOD Triangle Vertex Buffer"),
+            contents: bytemuck::cast_slice(TRIANGLE_VERTICES),
+            usage: wgpu::BufferUsages::VERTEX,
+        });
+        let triangle_index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("LOD Triangle Index Buffer"),
+            contents: bytemuck::cast_slice(TRIANGLE_INDICES),
+            usage: wgpu::BufferUsages::INDEX,
+        });
+
+        let storage_entry = |binding, read_only| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let bind_group_layout_entries = [
+            storage_entry(0, true),
+            storage_entry(1, true),
+            storage_entry(2, true),
+            storage_entry(3, false),
+            storage_entry(4, false),
+            storage_entry(5, false),
+            storage_entry(6, false),
+            wgpu::BindGroupLayoutEntry {
+                binding: 7,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+        ];
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("LOD Bind Group Layout"),
+            entries: &bind_group_layout_entries,
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("LOD Bind Group"),
+            layout: &bind_group_layout,
+            entries: &[
+                &alive_buffer,
+                position_buffer,
+                color_buffer,
+                &sorted_position_buffer,
+                &sorted_color_buffer,
+                &counters_buffer,
+                &indirect_buffer,
+                &params_buffer,
+            ]
+            .iter()
+            .enumerate()
+            .map(|(binding, buffer)| wgpu::BindGroupEntry {
+                binding: binding as u32,
+                resource: buffer.as_entire_binding(),
+            })
+            .collect::<Vec<_>>(),
+        });
+
+        let source = shader_source();
+        let shader = shader_check::create_module(device, "LOD Shader", "lod.wgsl", &source);
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("LOD Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let create_pipeline = |label, entry_point| {
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some(label),
+                layout: Some(&pipeline_layout),
+                module: &shader,
+                entry_point,
+            })
+        };
+        let count_pipeline = create_pipeline("LOD Count Pipeline", "count");
+        let scatter_pipeline = create_pipeline("LOD Scatter Pipeline", "scatter");
+
+        #[cfg(feature = "guardrails")]
+        {
+            let reflection = crate::guardrails::ShaderReflection::new("lod.wgsl", &source);
+            reflection.check_bind_group_layout(0, &bind_group_layout_entries);
+            reflection.check_struct_size("Params", std::mem::size_of::<LodParams>());
+            reflection.check_struct_size("Draws", std::mem::size_of::<LodDraws>());
+        }
+
+        Some(Self {
+            capacity,
+            alive_ranges: vec![],
+            params,
+            alive_buffer,
+            params_buffer,
+            position_buffer: sorted_position_buffer,
+            color_buffer: sorted_color_buffer,
+            counters_buffer,
+            indirect_buffer,
+            triangle_vertex_buffer,
+            triangle_index_buffer,
+            bind_group,
+            count_pipeline,
+            scatter_pipeline,
+            point_pipelines,
+        })
+    }
+
+    /// Marks the instances of `ranges` as alive and all others as dead.
+    pub fn set_alive(&mut self, queue: &wgpu::Queue, ranges: &[Range<usize>]) {
+        if self.alive_ranges == ranges {
+            return;
+        }
+
+        let mut alive = vec![0u32; self.capacity.div_ceil(ALIVE_BITS_PER_WORD).max(1)];
+        for index in ranges.iter().flat_map(Range::clone) {
+            alive[index / ALIVE_BITS_PER_WORD] |= 1 << (index % ALIVE_BITS_PER_WORD);
+        }
+        queue.write_buffer(&self.alive_buffer, 0, bytemuck::cast_slice(&alive));
+        self.alive_ranges = ranges.to_vec();
+    }
+
+    /// Sorts the particles by their distance to `eye`.
+    pub fn set_eye(&mut self, queue: &wgpu::Queue, eye: glam::Vec3) {
+        let params = LodParams {
+            eye: eye.extend(1.0),
+            ..self.params
+        };
+        if params != self.params {
+            queue.write_buffer(&self.params_buffer, 0, bytemuck::bytes_of(&params));
+            self.params = params;
+        }
+    }
+
+    /// Sorts the alive instances into their buckets and builds the draw arguments.
+    pub fn encode(&self, device: &wgpu::Device, encoder: &mut wgpu::CommandEncoder) {
+        encoder.clear_buffer(&self.counters_buffer, 0, None);
+
+        let groups = (self.capacity as u32).div_ceil(WORKGROUP_SIZE).max(1);
+        let max_groups = device.limits().max_compute_workgroups_per_dimension;
+        let (x, y) = (groups.min(max_groups), groups.div_ceil(max_groups));
+        #[cfg(feature = "guardrails")]
+        crate::guardrails::check_dispatch_coverage(
+            [x, y, 1],
+            [WORKGROUP_SIZE, 1, 1],
+            self.capacity,
+        );
+
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("LOD Pass"),
+        });
+        compute_pass.set_bind_group(0, &self.bind_group, &[]);
+        compute_pass.set_pipeline(&self.count_pipeline);
+        compute_pass.dispatch_workgroups(x, y, 1);
+        compute_pass.set_pipeline(&self.scatter_pipeline);
+        compute_pass.dispatch_workgroups(x, y, 1);
+    }
+
+    /// Draws the sorted instances, with the quad and its index buffer already bound to vertex slot
+    /// 0 and the quad pipeline set. `depth_format` picks the point pipeline.
+    pub fn draw<'a>(
+        &'a self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        depth_format: Option<wgpu::TextureFormat>,
+    ) {
+        let args_size = std::mem::size_of::<[u32; 5]>() as u64;
+        render_pass.set_vertex_buffer(1, self.position_buffer.slice(..));
+        render_pass.set_vertex_buffer(2, self.color_buffer.slice(..));
+        render_pass.draw_indexed_indirect(&self.indirect_buffer, 0);
+
+        render_pass.set_vertex_buffer(0, self.triangle_vertex_buffer.slice(..));
+        render_pass.set_index_buffer(
+            self.triangle_index_buffer.slice(..),
+            wgpu::IndexFormat::Uint16,
+        );
+        render_pass.draw_indexed_indirect(&self.indirect_buffer, args_size);
+
+        let Some((_, point_pipeline)) = self
+            .point_pipelines
+            .iter()
+            .find(|(format, _)| *format == depth_format)
+        else {
+            return;
+        };
+        render_pass.set_pipeline(point_pipeline);
+        render_pass.set_vertex_buffer(0, self.position_buffer.slice(..));
+        render_pass.set_vertex_buffer(1, self.color_buffer.slice(..));
+        render_pass.draw_indirect(&self.indirect_buffer, 2 * args_size);
+    }
+}
+
+/// The source of the shader, after the instance colors and positions.
+pub fn shader_source() -> String {
+    format!(
+        "{INSTANCE_COLOR_WGSL}\n{INSTANCE_POSITION_WGSL}\n{}",
+        include_str!("lod.wgsl")
+    )
+}
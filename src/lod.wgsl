@@ -0,0 +1,139 @@
+// Sorts the alive instances into distance buckets, each drawn with a cheaper mesh than the last:
+// `count` counts the instances of each bucket, then `scatter` copies them next to each other,
+// bucket after bucket, and writes the indirect draw arguments
+
+// Must match the buckets of lod.rs
+const NEAR: u32 = 0u;
+const MIDDLE: u32 = 1u;
+const FAR: u32 = 2u;
+
+struct Params {
+    // w is unused
+    eye: vec4<f32>,
+    // Instances closer than this are near, further than `far_distance` far
+    near_distance: f32,
+    far_distance: f32,
+    capacity: u32,
+    _padding: u32,
+}
+
+struct Counters {
+    counts: array<atomic<u32>, 3>,
+    // Slots taken in each bucket by `scatter`
+    cursors: array<atomic<u32>, 3>,
+}
+
+// Same layout as wgpu::util::DrawIndexedIndirect
+struct DrawIndexedArgs {
+    index_count: u32,
+    instance_count: u32,
+    first_index: u32,
+    base_vertex: i32,
+    first_instance: u32,
+}
+
+// Same layout as wgpu::util::DrawIndirect
+struct DrawArgs {
+    vertex_count: u32,
+    instance_count: u32,
+    first_vertex: u32,
+    first_instance: u32,
+}
+
+struct Draws {
+    // The quad, then the triangle
+    near: DrawIndexedArgs,
+    middle: DrawIndexedArgs,
+    // A point per instance
+    far: DrawArgs,
+}
+
+// One bit per instance
+@group(0) @binding(0)
+var<storage, read> alive: array<u32>;
+
+@group(0) @binding(1)
+var<storage, read> positions: array<vec4<f32>>;
+
+// PackedColor is declared by INSTANCE_COLOR_WGSL in vertex.rs
+@group(0) @binding(2)
+var<storage, read> colors: array<PackedColor>;
+
+@group(0) @binding(3)
+var<storage, read_write> sorted_positions: array<PackedPosition>;
+
+@group(0) @binding(4)
+var<storage, read_write> sorted_colors: array<PackedColor>;
+
+@group(0) @binding(5)
+var<storage, read_write> counters: Counters;
+
+@group(0) @binding(6)
+var<storage, read_write> draws: Draws;
+
+@group(0) @binding(7)
+var<uniform> params: Params;
+
+// Large counts are dispatched in rows, a single dimension is limited to 65535 workgroups
+fn instance_index(id: vec3<u32>, workgroups: vec3<u32>) -> u32 {
+    return id.x + id.y * workgroups.x * 64u;
+}
+
+fn is_alive(index: u32) -> bool {
+    return index < params.capacity && (alive[index / 32u] & (1u << (index % 32u))) != 0u;
+}
+
+fn bucket(index: u32) -> u32 {
+    let distance = distance(positions[index].xyz, params.eye.xyz);
+    if distance < params.near_distance {
+        return NEAR;
+    }
+    if distance < params.far_distance {
+        return MIDDLE;
+    }
+    return FAR;
+}
+
+@compute @workgroup_size(64)
+fn count(
+    @builtin(global_invocation_id) id: vec3<u32>,
+    @builtin(num_workgroups) workgroups: vec3<u32>,
+) {
+    let index = instance_index(id, workgroups);
+    if !is_alive(index) {
+        return;
+    }
+    atomicAdd(&counters.counts[bucket(index)], 1u);
+}
+
+@compute @workgroup_size(64)
+fn scatter(
+    @builtin(global_invocation_id) id: vec3<u32>,
+    @builtin(num_workgroups) workgroups: vec3<u32>,
+) {
+    let near = atomicLoad(&counters.counts[NEAR]);
+    let middle = atomicLoad(&counters.counts[MIDDLE]);
+    let index = instance_index(id, workgroups);
+    if index == 0u {
+        draws.near.instance_count = near;
+        draws.near.first_instance = 0u;
+        draws.middle.instance_count = middle;
+        draws.middle.first_instance = near;
+        draws.far.instance_count = atomicLoad(&counters.counts[FAR]);
+        draws.far.first_instance = near + middle;
+    }
+    if !is_alive(index) {
+        return;
+    }
+
+    let instance_bucket = bucket(index);
+    var start = 0u;
+    if instance_bucket == MIDDLE {
+        start = near;
+    } else if instance_bucket == FAR {
+        start = near + middle;
+    }
+    let slot = start + atomicAdd(&counters.cursors[instance_bucket], 1u);
+    sorted_positions[slot] = pack_position(positions[index]);
+    sorted_colors[slot] = colors[index];
+}
@@ -1,9 +1,18 @@
-mod state;
-mod vertex;
-mod camera;
-
-use crate::state::State;
-use log::warn;
+#[cfg(feature = "gamepad")]
+use particles::internal::gamepad;
+use particles::internal::{
+    adapters,
+    bench::{self, Bench},
+    error::AppError,
+    golden,
+    input_session::{InputRecorder, InputReplay},
+    options::{Command, Options},
+    screensaver::ScrCommand,
+    search,
+    settings::Settings,
+    state::{self, State},
+    trace, viewport,
+};
 use winit::{
     event::{Event, WindowEvent},
     event_loop::{ControlFlow, EventLoop},
@@ -11,41 +20,146 @@ use winit::{
 };
 
 fn main() {
-    env_logger::init();
-    let event_loop = EventLoop::new();
-    let window = WindowBuilder::new()
-        .with_inner_size(winit::dpi::LogicalSize::new(1500, 900))
-        .with_title("Particles!")
-        .build(&event_loop)
-        .expect("Unable to create Window");
+    // Startup time, up to the first frame, logged to compare builds
+    let mut started = Some(std::time::Instant::now());
+    let options = match Options::from_args(std::env::args().skip(1)) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("{e}");
+            std::process::exit(2);
+        }
+    };
+    let mut trace_guard = trace::init(options.trace_json.as_deref());
+
+    if options.command == Command::Info {
+        adapters::list(adapters::Backend::backends(options.backend));
+        return;
+    }
 
-    let mut state = State::new(window);
+    if let Some(generations) = options.search {
+        let settings = Settings::default();
+        if let Err(e) = search::run(generations, &options, &settings) {
+            eprintln!("{e}");
+            std::process::exit(1);
+        }
+        return;
+    }
 
-    event_loop.run(move |event, _, control_fow| match event {
+    match options.scr {
+        Some(ScrCommand::Configure) => {
+            println!("This screensaver has no settings");
+            return;
+        }
+        // Previews in the screensaver settings dialog aren't supported
+        Some(ScrCommand::Preview) => return,
+        Some(ScrCommand::Run) | None => {}
+    }
+
+    let mut settings = options
+        .remembers_settings()
+        .then(Settings::load)
+        .unwrap_or_default();
+    if options.command == Command::Headless {
+        let succeeded = run_headless(&options, &settings);
+        drop(trace_guard.take());
+        if !succeeded {
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    let event_loop = EventLoop::new();
+    let mut state = match build_state(&event_loop, &options, &settings) {
+        Ok(state) => state,
+        Err(e) => {
+            eprintln!("{e}");
+            std::process::exit(1);
+        }
+    };
+    let bench_frames = options.frames.unwrap_or(bench::DEFAULT_FRAMES);
+    let mut bench = (options.command == Command::Bench).then(|| {
+        if options.tune_workgroup_size {
+            let (bench, workgroup_size) = Bench::tuning(bench_frames);
+            state.set_workgroup_size(workgroup_size);
+            bench
+        } else if options.overlap {
+            // Timed without first, then with it
+            state.set_overlap(false);
+            Bench::comparing_overlap(bench_frames)
+        } else {
+            Bench::new(bench_frames)
+        }
+    });
+    let input_recorder = options.record_input.as_deref().map(InputRecorder::new).transpose();
+    let input_replay = options.replay.as_deref().map(InputReplay::load).transpose();
+    let (mut input_recorder, mut input_replay) = match (input_recorder, input_replay) {
+        (Ok(recorder), Ok(replay)) => (recorder, replay),
+        (Err(e), _) | (_, Err(e)) => {
+            eprintln!("{e}");
+            std::process::exit(1);
+        }
+    };
+    #[cfg(feature = "gamepad")]
+    let mut gamepads = gamepad::Gamepads::new();
+
+    event_loop.run(move |event, target, control_fow| match event {
         // Only process the event if the ID is correct
-        Event::WindowEvent { event, window_id }
-            if state.window().id() == window_id && !state.input(&event) =>
-        {
+        Event::WindowEvent { event, window_id } if state.has_window(window_id) => {
+            if state.window().is_some_and(|window| window.id() == window_id) {
+                if let Some(recorder) = &mut input_recorder {
+                    recorder.record(&event);
+                }
+                // Only the replayed input reaches the main window
+                if input_replay.is_some() && InputReplay::replays(&event) {
+                    return;
+                }
+            }
+            if state.input(window_id, &event) {
+                return;
+            }
             match event {
-                WindowEvent::CloseRequested => {
+                WindowEvent::CloseRequested if state.close_window(window_id) => {
                     *control_fow = ControlFlow::Exit;
                 }
                 WindowEvent::Resized(size) => {
-                    state.resize(size);
+                    state.resize(window_id, size);
                 }
-                WindowEvent::ScaleFactorChanged { new_inner_size, .. } => {
-                    state.resize(*new_inner_size);
+                WindowEvent::ScaleFactorChanged {
+                    scale_factor,
+                    new_inner_size,
+                } => {
+                    state.rescale(window_id, scale_factor, *new_inner_size);
+                    state.update_monitor();
+                }
+                WindowEvent::Moved(_) => {
+                    state.update_monitor();
                 }
                 _ => {}
             }
         },
-        Event::RedrawRequested(window_id) if state.window().id() == window_id => {
-            if let Err(e) =  state.render() {
+        // Minimized windows have nothing to present to
+        Event::RedrawRequested(window_id)
+            if state.window().is_some_and(|window| window.id() == window_id)
+                && !state.minimized() =>
+        {
+            if let Some(replay) = &mut input_replay {
+                for event in replay.next_frame() {
+                    state.replay_input(&event);
+                }
+            }
+            let rendered = state.render();
+            if let Some(recorder) = &mut input_recorder {
+                recorder.frame_rendered();
+            }
+            if let Some(started) = started.take() {
+                let startup = started.elapsed();
+                log::info!("First frame {startup:.1?} after startup");
+                if let Some(bench) = &mut bench {
+                    bench.first_frame(startup);
+                }
+            }
+            if let Err(e) = rendered {
                 match e {
-                    wgpu::SurfaceError::Lost => {
-                        warn!("Surface lost, reconfiguring.");
-                        state.resize(*state.size());
-                    },
                     wgpu::SurfaceError::OutOfMemory => {
                         log::error!("OOM. Exiting.");
                         *control_fow = ControlFlow::Exit;
@@ -55,10 +169,155 @@ fn main() {
                     }
                 }
             }
+            if state.recording_failed() {
+                *control_fow = ControlFlow::Exit;
+            } else if state.recording_done() {
+                log::info!("Recording done. Exiting.");
+                *control_fow = ControlFlow::Exit;
+            }
+            if bench
+                .as_mut()
+                .is_some_and(|bench| {
+                    bench.frame(
+                        std::time::Instant::now(),
+                        state.cpu_simulation_time(),
+                        state.gpu_frame_time(),
+                    )
+                })
+            {
+                if let Some(workgroup_size) = bench.as_mut().and_then(Bench::next_workgroup_size) {
+                    state.set_workgroup_size(workgroup_size);
+                } else if bench.as_mut().is_some_and(Bench::next_overlap) {
+                    state.set_overlap(true);
+                } else {
+                    *control_fow = ControlFlow::Exit;
+                }
+            }
         }
         Event::MainEventsCleared => {
-            state.window().request_redraw();
+            if state.screensaver_dismissed() {
+                *control_fow = ControlFlow::Exit;
+                return;
+            }
+            #[cfg(feature = "gamepad")]
+            if let Some(gamepads) = &mut gamepads {
+                state.set_gamepad(gamepads.poll());
+            }
+            if state.take_window_request() {
+                match WindowBuilder::new()
+                    .with_inner_size(winit::dpi::LogicalSize::new(800, 600))
+                    .with_min_inner_size(viewport::MIN_WINDOW_SIZE)
+                    .with_title(state::WINDOW_TITLE)
+                    .build(target)
+                {
+                    Ok(window) => state.add_window(window),
+                    Err(e) => log::error!("Unable to create Window: {e}"),
+                }
+            }
+            // Woken up by the resize restoring the window
+            if state.minimized() {
+                *control_fow = ControlFlow::Wait;
+                return;
+            }
+            match state.pacer().poll(std::time::Instant::now()) {
+                Some(next_frame) => *control_fow = ControlFlow::WaitUntil(next_frame),
+                None => {
+                    *control_fow = ControlFlow::Poll;
+                    if let Some(window) = state.window() {
+                        window.request_redraw();
+                    }
+                }
+            }
+        }
+        Event::Suspended => state.suspend(),
+        Event::Resumed => state.resume(),
+        Event::LoopDestroyed => {
+            if options.remembers_settings() {
+                state.store_settings(&mut settings);
+                if let Err(e) = settings.save() {
+                    log::error!("Unable to save settings: {e}");
+                }
+            }
+            state.shutdown();
+            // Finishes the trace, the event loop exits the process without dropping the guard
+            drop(trace_guard.take());
+            if let Some(bench) = &bench {
+                bench.report();
+            }
+            if state.recording_failed() {
+                std::process::exit(1);
+            }
         }
         _ => {}
     });
 }
+
+/// Renders the `--frames` of the headless command offscreen, without a window or an event loop, then
+/// compares the last one with the golden image if asked. Returns false if any of it failed.
+fn run_headless(options: &Options, settings: &Settings) -> bool {
+    let mut state = match State::new(None, options, settings) {
+        Ok(state) => state,
+        Err(e) => {
+            eprintln!("{e}");
+            return false;
+        }
+    };
+    let mut input_replay = match options.replay.as_deref().map(InputReplay::load).transpose() {
+        Ok(replay) => replay,
+        Err(e) => {
+            eprintln!("{e}");
+            return false;
+        }
+    };
+    for _ in 0..options.frames.unwrap_or_default() {
+        if let Some(replay) = &mut input_replay {
+            for event in replay.next_frame() {
+                state.replay_input(&event);
+            }
+        }
+        // Without a surface, there's no surface error to get
+        if let Err(e) = state.render() {
+            log::error!("{e}");
+        }
+        if state.recording_failed() {
+            break;
+        }
+    }
+    let matched = match &options.compare {
+        Some(path) => {
+            let size = *state.size();
+            golden::check(
+                path,
+                options.threshold,
+                (size.width, size.height),
+                |width, height| state.render_frame(width, height),
+            )
+            .unwrap_or_else(|e| {
+                log::error!("{e}");
+                false
+            })
+        }
+        None => true,
+    };
+    state.shutdown();
+    matched && !state.recording_failed()
+}
+
+/// Opens the window where `settings` left it, and sets up the simulation and renderer in it, the
+/// same way for every windowed command.
+fn build_state(
+    event_loop: &EventLoop<()>,
+    options: &Options,
+    settings: &Settings,
+) -> Result<State, AppError> {
+    let builder = WindowBuilder::new()
+        .with_inner_size(winit::dpi::LogicalSize::new(1500, 900))
+        .with_min_inner_size(viewport::MIN_WINDOW_SIZE)
+        .with_title(state::WINDOW_TITLE);
+    let window = settings.apply_window(builder).build(event_loop)?;
+    if options.scr == Some(ScrCommand::Run) {
+        window.set_fullscreen(Some(winit::window::Fullscreen::Borderless(None)));
+        window.set_cursor_visible(false);
+    }
+    State::new(Some(window), options, settings)
+}
@@ -0,0 +1,80 @@
+//! Device memory taken by the particles, estimated before their buffers are created.
+//!
+//! Only the buffers sized by the particle count are counted: the instances, their velocities and
+//! the staging buffers they're uploaded and read back through. The estimate is reported at
+//! startup, and the particle count scaled down to stay under `--max-memory`.
+
+use std::{fmt::Display, str::FromStr};
+
+const MIB: u64 = 1024 * 1024;
+
+/// Bytes of device memory of each kind of particle buffer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryEstimate {
+    /// Positions and colors
+    pub instances: u64,
+    pub velocities: u64,
+    pub staging: u64,
+}
+
+impl MemoryEstimate {
+    pub fn total(&self) -> u64 {
+        self.instances + self.velocities + self.staging
+    }
+}
+
+impl Display for MemoryEstimate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} ({} instances, {} velocities, {} staging)",
+            ByteSize(self.total()),
+            ByteSize(self.instances),
+            ByteSize(self.velocities),
+            ByteSize(self.staging)
+        )
+    }
+}
+
+/// Most particles whose `estimate` fits in `budget` bytes, at most `requested`.
+pub fn particles_within(
+    budget: u64,
+    requested: usize,
+    estimate: impl Fn(usize) -> MemoryEstimate,
+) -> usize {
+    // Every buffer grows linearly with the particle count
+    let fixed = estimate(0).total();
+    let per_particle = estimate(1).total() - fixed;
+    let fitting = (budget.saturating_sub(fixed) / per_particle.max(1)) as usize;
+    fitting.min(requested)
+}
+
+/// A number of bytes, given with a `K`, `M` or `G` suffix for powers of 1024, in mebibytes without
+/// one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ByteSize(pub u64);
+
+impl FromStr for ByteSize {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (number, unit) = match s.char_indices().find(|(_, c)| c.is_ascii_alphabetic()) {
+            Some((index, _)) => s.split_at(index),
+            None => (s, "M"),
+        };
+        let unit = match unit.to_ascii_uppercase().trim_end_matches(['B', 'I']) {
+            "K" => 1024,
+            "M" => MIB,
+            "G" => 1024 * MIB,
+            _ => return Err(()),
+        };
+        let number: u64 = number.trim().parse().map_err(|_| ())?;
+        number.checked_mul(unit).map(ByteSize).ok_or(())
+    }
+}
+
+impl Display for ByteSize {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:.1} MiB", self.0 as f64 / MIB as f64)
+    }
+}
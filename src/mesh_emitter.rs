@@ -0,0 +1,173 @@
+//! Particles spawned over the surface of a triangle mesh, from `--emit-mesh`, for statues that
+//! dissolve into the turbulence.
+//!
+//! Meshes are read from Wavefront OBJ files, only their vertices and faces: polygons are split into
+//! triangle fans, and the texture coordinates and normals of the faces are ignored. Like point
+//! clouds, meshes are centered and scaled to fit the view.
+//!
+//! Points are spread uniformly over the surface, each triangle drawing as many as its area, and
+//! start moving along the normal of their triangle.
+
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
+
+use glam::{DVec3, Vec3};
+use rand::Rng;
+
+use crate::point_cloud::{self, PointCloud};
+
+// As fast as particles spawned in the emitter, see random_speed in state.rs
+const SPEED: f32 = 0.2;
+
+#[derive(Debug, thiserror::Error)]
+pub enum MeshError {
+    #[error("unable to read the mesh {0}: {1}")]
+    Io(PathBuf, io::Error),
+    #[error("invalid mesh {0}: {1}")]
+    Invalid(PathBuf, String),
+    #[error("{0} isn't an .obj mesh")]
+    UnknownFormat(PathBuf),
+}
+
+pub struct TriangleMesh {
+    triangles: Vec<[Vec3; 3]>,
+    // Sum of the areas of the triangles up to and including each one
+    cumulative_areas: Vec<f32>,
+}
+
+impl TriangleMesh {
+    pub fn load(path: &Path) -> Result<Self, MeshError> {
+        let extension = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map(str::to_ascii_lowercase);
+        if extension.as_deref() != Some("obj") {
+            return Err(MeshError::UnknownFormat(path.to_owned()));
+        }
+        let contents =
+            std::fs::read_to_string(path).map_err(|e| MeshError::Io(path.to_owned(), e))?;
+        let (vertices, faces) =
+            read_obj(&contents).map_err(|e| MeshError::Invalid(path.to_owned(), e))?;
+        let vertices = point_cloud::fit(&vertices);
+        let triangles = faces
+            .iter()
+            .map(|face| face.map(|index| vertices[index]))
+            .collect::<Vec<_>>();
+        let cumulative_areas = triangles
+            .iter()
+            .scan(0.0, |sum, &[a, b, c]| {
+                *sum += (b - a).cross(c - a).length() / 2.0;
+                Some(*sum)
+            })
+            .collect::<Vec<f32>>();
+        if cumulative_areas.last().is_none_or(|&area| area <= 0.0) {
+            return Err(MeshError::Invalid(
+                path.to_owned(),
+                "no surface to spawn on".to_owned(),
+            ));
+        }
+        log::info!(
+            "Loaded {} vertices and {} triangles from {}",
+            vertices.len(),
+            triangles.len(),
+            path.display()
+        );
+        Ok(Self {
+            triangles,
+            cumulative_areas,
+        })
+    }
+
+    /// `count` points spread uniformly over the surface, moving away from it.
+    pub fn sample(&self, count: usize, rng: &mut impl Rng) -> PointCloud {
+        let area = *self.cumulative_areas.last().unwrap();
+        let (positions, speeds) = (0..count)
+            .map(|_| {
+                let target = rng.gen::<f32>() * area;
+                let index = self
+                    .cumulative_areas
+                    .partition_point(|&sum| sum < target)
+                    .min(self.triangles.len() - 1);
+                let [a, b, c] = self.triangles[index];
+                // Folds the unit square onto the triangle, uniformly
+                let (mut u, mut v) = (rng.gen::<f32>(), rng.gen::<f32>());
+                if u + v > 1.0 {
+                    (u, v) = (1.0 - u, 1.0 - v);
+                }
+                let position = a + (b - a) * u + (c - a) * v;
+                let normal = (b - a).cross(c - a).normalize_or_zero();
+                (position, normal * SPEED)
+            })
+            .unzip();
+        log::info!("Spawning {count} particles over the mesh");
+        PointCloud {
+            positions,
+            colors: None,
+            speeds: Some(speeds),
+        }
+    }
+}
+
+/// Index of `field` in `vertex_count` vertices, OBJ indices starting at 1 and negative ones
+/// counting from the last vertex.
+fn vertex_index(field: &str, vertex_count: usize) -> Result<usize, String> {
+    let position = field.split('/').next().unwrap_or_default();
+    let index = position
+        .parse::<i64>()
+        .map_err(|e| format!("face vertex {field}: {e}"))?;
+    let resolved = match index {
+        1.. => index - 1,
+        ..=-1 => vertex_count as i64 + index,
+        0 => return Err("face vertex 0".to_owned()),
+    };
+    usize::try_from(resolved)
+        .ok()
+        .filter(|&resolved| resolved < vertex_count)
+        .ok_or_else(|| format!("face vertex {index} out of the {vertex_count} vertices"))
+}
+
+fn read_obj(contents: &str) -> Result<(Vec<DVec3>, Vec<[usize; 3]>), String> {
+    let mut vertices = vec![];
+    let mut faces = vec![];
+    for (line_number, line) in contents.lines().enumerate() {
+        let mut fields = line.split_whitespace();
+        match fields.next() {
+            Some("v") => {
+                let coordinates = fields
+                    .take(3)
+                    .map(str::parse::<f64>)
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(|e| format!("line {}: {e}", line_number + 1))?;
+                let [x, y, z] = coordinates[..] else {
+                    return Err(format!(
+                        "line {}: a vertex needs x, y and z",
+                        line_number + 1
+                    ));
+                };
+                vertices.push(DVec3::new(x, y, z));
+            }
+            Some("f") => {
+                let indices = fields
+                    .map(|field| vertex_index(field, vertices.len()))
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(|e| format!("line {}: {e}", line_number + 1))?;
+                if indices.len() < 3 {
+                    return Err(format!("line {}: a face needs 3 vertices", line_number + 1));
+                }
+                faces.extend(
+                    indices
+                        .windows(2)
+                        .skip(1)
+                        .map(|pair| [indices[0], pair[0], pair[1]]),
+                );
+            }
+            _ => {}
+        }
+    }
+    if faces.is_empty() {
+        return Err("no faces".to_owned());
+    }
+    Ok((vertices, faces))
+}
@@ -0,0 +1,205 @@
+//! Export of runtime metrics, to monitor long-running installations from the outside.
+//!
+//! The latest metrics are served in the Prometheus text format over HTTP, and can also be appended
+//! to a CSV file once per second.
+
+use std::{
+    fmt::Write as _,
+    fs::{File, OpenOptions},
+    io::{self, BufRead, BufReader, BufWriter, Write},
+    net::{SocketAddr, TcpListener, TcpStream},
+    path::Path,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant, SystemTime},
+};
+
+// Time between two rows of the CSV file
+const CSV_INTERVAL: Duration = Duration::from_secs(1);
+// How long a scraper gets to send its request, so a stalled one doesn't block the others.
+const READ_TIMEOUT: Duration = Duration::from_secs(5);
+const CSV_HEADER: &str = "timestamp,frame_time_ms,compute_pass_ms,render_pass_ms,\
+    neighbors_pass_ms,active_particles,total_particles,gpu_buffer_bytes,resident_bytes";
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Metrics {
+    pub frame_time_ms: f32,
+    /// GPU time of the passes, if the device supports timestamp queries
+    pub compute_pass_ms: Option<f64>,
+    pub render_pass_ms: Option<f64>,
+    pub neighbors_pass_ms: Option<f64>,
+    pub active_particles: usize,
+    pub total_particles: usize,
+    /// Size of the particle buffers on the GPU
+    pub gpu_buffer_bytes: u64,
+    /// Resident memory of the process, where the OS reports it
+    pub resident_bytes: Option<u64>,
+}
+
+pub struct MetricsExporter {
+    latest: Arc<Mutex<Metrics>>,
+    csv: Option<BufWriter<File>>,
+    last_csv_row: Option<Instant>,
+}
+
+impl MetricsExporter {
+    /// Serves metrics on `listen` and appends them to `csv`, whichever are given.
+    pub fn new(listen: Option<SocketAddr>, csv: Option<&Path>) -> io::Result<Self> {
+        let latest = Arc::new(Mutex::new(Metrics::default()));
+
+        if let Some(address) = listen {
+            let listener = TcpListener::bind(address)?;
+            log::info!("Serving metrics on http://{address}/metrics");
+            let latest = latest.clone();
+            std::thread::spawn(move || {
+                for stream in listener.incoming().flatten() {
+                    let metrics = *latest.lock().unwrap();
+                    if let Err(e) = respond(stream, &metrics) {
+                        log::warn!("Unable to serve metrics: {e}");
+                    }
+                }
+            });
+        }
+
+        let csv = match csv {
+            Some(path) => {
+                let file = OpenOptions::new().create(true).append(true).open(path)?;
+                let empty = file.metadata()?.len() == 0;
+                let mut writer = BufWriter::new(file);
+                if empty {
+                    writeln!(writer, "{CSV_HEADER}")?;
+                }
+                Some(writer)
+            }
+            None => None,
+        };
+
+        Ok(Self {
+            latest,
+            csv,
+            last_csv_row: None,
+        })
+    }
+
+    pub fn publish(&mut self, metrics: Metrics) {
+        *self.latest.lock().unwrap() = metrics;
+
+        let Some(csv) = &mut self.csv else {
+            return;
+        };
+        let now = Instant::now();
+        if self
+            .last_csv_row
+            .is_some_and(|last| now - last < CSV_INTERVAL)
+        {
+            return;
+        }
+        self.last_csv_row = Some(now);
+        if let Err(e) = write_csv_row(csv, &metrics) {
+            log::error!("Unable to write metrics: {e}");
+            self.csv = None;
+        }
+    }
+}
+
+/// Resident memory of the current process, in bytes.
+pub fn resident_bytes() -> Option<u64> {
+    // Sizes in pages: total, then resident
+    let statm = std::fs::read_to_string("/proc/self/statm").ok()?;
+    let pages: u64 = statm.split_whitespace().nth(1)?.parse().ok()?;
+    // The page size isn't exposed by std, 4 KiB is by far the most common
+    Some(pages * 4096)
+}
+
+fn write_csv_row(csv: &mut BufWriter<File>, metrics: &Metrics) -> io::Result<()> {
+    let timestamp = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs_f64();
+    let optional = |value: Option<String>| value.unwrap_or_default();
+    writeln!(
+        csv,
+        "{timestamp:.3},{},{},{},{},{},{},{},{}",
+        metrics.frame_time_ms,
+        optional(metrics.compute_pass_ms.map(|ms| ms.to_string())),
+        optional(metrics.render_pass_ms.map(|ms| ms.to_string())),
+        optional(metrics.neighbors_pass_ms.map(|ms| ms.to_string())),
+        metrics.active_particles,
+        metrics.total_particles,
+        metrics.gpu_buffer_bytes,
+        optional(metrics.resident_bytes.map(|bytes| bytes.to_string())),
+    )?;
+    csv.flush()
+}
+
+fn respond(stream: TcpStream, metrics: &Metrics) -> io::Result<()> {
+    stream.set_read_timeout(Some(READ_TIMEOUT))?;
+    // Every path gets the metrics, the request only has to be read up to its blank line
+    let mut reader = BufReader::new(&stream);
+    let mut line = String::new();
+    while reader.read_line(&mut line)? > 0 && line.trim_end() != "" {
+        line.clear();
+    }
+
+    let body = prometheus_text(metrics);
+    let mut stream = &stream;
+    write!(
+        stream,
+        "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\n\
+        Connection: close\r\n\r\n{body}",
+        body.len()
+    )
+}
+
+fn prometheus_text(metrics: &Metrics) -> String {
+    let mut text = String::new();
+    let mut gauge = |name: &str, help: &str, value: Option<f64>| {
+        if let Some(value) = value {
+            let _ = write!(
+                text,
+                "# HELP particles_{name} {help}\n# TYPE particles_{name} gauge\n\
+                particles_{name} {value}\n"
+            );
+        }
+    };
+    gauge(
+        "frame_time_seconds",
+        "Average CPU time per frame.",
+        Some(metrics.frame_time_ms as f64 / 1000.0),
+    );
+    gauge(
+        "compute_pass_seconds",
+        "GPU time of the simulation pass.",
+        metrics.compute_pass_ms.map(|ms| ms / 1000.0),
+    );
+    gauge(
+        "render_pass_seconds",
+        "GPU time of the render passes.",
+        metrics.render_pass_ms.map(|ms| ms / 1000.0),
+    );
+    gauge(
+        "neighbors_pass_seconds",
+        "GPU time of the boid steering and collision passes, spatial hashes included.",
+        metrics.neighbors_pass_ms.map(|ms| ms / 1000.0),
+    );
+    gauge(
+        "active",
+        "Particles simulated and drawn.",
+        Some(metrics.active_particles as f64),
+    );
+    gauge(
+        "total",
+        "Particles allocated.",
+        Some(metrics.total_particles as f64),
+    );
+    gauge(
+        "gpu_buffer_bytes",
+        "Size of the particle buffers on the GPU.",
+        Some(metrics.gpu_buffer_bytes as f64),
+    );
+    gauge(
+        "resident_bytes",
+        "Resident memory of the process.",
+        metrics.resident_bytes.map(|bytes| bytes as f64),
+    );
+    text
+}
@@ -0,0 +1,493 @@
+//! Motion blur post effect, smearing fast particles along their motion on screen.
+//!
+//! While enabled, the scene is drawn into textures owned here, then the particles are drawn again
+//! into a velocity target: how far each pixel moved on screen since the previous frame, from the
+//! previous positions kept in a second buffer and the previous camera. The resolve pass averages
+//! the scene along those velocities, scaled by the shutter.
+
+use std::ops::Range;
+
+use bytemuck::{Pod, Zeroable};
+use wgpu::util::DeviceExt;
+
+use crate::{
+    camera::DepthRange,
+    picking::ParticleBuffers,
+    render_target::{fullscreen_shader, DEPTH_FORMAT},
+    vertex::{InstancePosition, Vertex},
+};
+
+/// Fraction of the frame the shutter stays open, half like a 180° film camera
+pub const DEFAULT_SHUTTER: f32 = 0.5;
+const VELOCITY_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rg16Float;
+// Longest streak, in pixels
+const MAX_LENGTH: f32 = 32.0;
+// Distance in pixels of the neighbors whose streaks reach over a pixel, see motion_blur.wgsl
+const DILATION: f32 = 8.0;
+
+// Must match Motion in motion_blur_velocity.wgsl
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Pod, Zeroable)]
+struct Motion {
+    previous_view_proj: glam::Mat4,
+}
+
+// Must match ResolveParams in motion_blur.wgsl
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Pod, Zeroable)]
+struct ResolveParams {
+    shutter: f32,
+    max_length: f32,
+    dilation: f32,
+    _padding: f32,
+}
+
+struct Targets {
+    size: (u32, u32),
+    // Kept alive for the views
+    _color_texture: wgpu::Texture,
+    color_view: wgpu::TextureView,
+    _depth_texture: wgpu::Texture,
+    depth_view: wgpu::TextureView,
+    _velocity_texture: wgpu::Texture,
+    velocity_view: wgpu::TextureView,
+    bind_group: wgpu::BindGroup,
+}
+
+pub struct MotionBlur {
+    enabled: bool,
+    shutter: f32,
+    format: wgpu::TextureFormat,
+    velocity_pipeline: wgpu::RenderPipeline,
+    resolve_pipeline: wgpu::RenderPipeline,
+    resolve_bind_group_layout: wgpu::BindGroupLayout,
+    motion_buffer: wgpu::Buffer,
+    motion_bind_group: wgpu::BindGroup,
+    params_buffer: wgpu::Buffer,
+    // Camera of the last prepared frame
+    view_proj: Option<glam::Mat4>,
+    // Positions of the previous frame, copied from the position buffer after every frame
+    history: Option<wgpu::Buffer>,
+    targets: Option<Targets>,
+}
+
+impl MotionBlur {
+    /// Enabled from the start if given a `shutter`.
+    pub fn new(
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+        camera_bind_group_layout: &wgpu::BindGroupLayout,
+        depth: &DepthRange,
+        shutter: Option<f32>,
+    ) -> Self {
+        let motion_bind_group_layout_entries = [wgpu::BindGroupLayoutEntry {
+            binding: 0,
+            visibility: wgpu::ShaderStages::VERTEX,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        }];
+        let motion_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("Motion Bind Group Layout"),
+                entries: &motion_bind_group_layout_entries,
+            });
+        let motion_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Motion Buffer"),
+            contents: bytemuck::cast_slice(&[Motion {
+                previous_view_proj: glam::Mat4::IDENTITY,
+            }]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let motion_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Motion Bind Group"),
+            layout: &motion_bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: motion_buffer.as_entire_binding(),
+            }],
+        });
+
+        let velocity_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Motion Blur Velocity Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("motion_blur_velocity.wgsl").into()),
+        });
+        let velocity_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Motion Blur Velocity Pipeline Layout"),
+                bind_group_layouts: &[camera_bind_group_layout, &motion_bind_group_layout],
+                push_constant_ranges: &[],
+            });
+        let velocity_buffers = [
+            Vertex::descriptor(),
+            InstancePosition::descriptor(),
+            Self::previous_position_descriptor(),
+        ];
+        let velocity_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Motion Blur Velocity Pipeline"),
+            layout: Some(&velocity_pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &velocity_shader,
+                entry_point: "vs_main",
+                buffers: &velocity_buffers,
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &velocity_shader,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: VELOCITY_FORMAT,
+                    blend: None,
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState {
+                cull_mode: Some(wgpu::Face::Back),
+                ..Default::default()
+            },
+            // Tested against the depth of the scene, the same particles win
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: DEPTH_FORMAT,
+                depth_write_enabled: false,
+                depth_compare: depth.compare(true),
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+
+        let resolve_bind_group_layout_entries = [
+            wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 1,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 2,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+        ];
+        let resolve_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("Motion Blur Bind Group Layout"),
+                entries: &resolve_bind_group_layout_entries,
+            });
+        let resolve_source = fullscreen_shader(include_str!("motion_blur.wgsl"));
+        let resolve_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Motion Blur Shader"),
+            source: wgpu::ShaderSource::Wgsl(resolve_source.as_str().into()),
+        });
+        let resolve_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Motion Blur Pipeline Layout"),
+                bind_group_layouts: &[&resolve_bind_group_layout],
+                push_constant_ranges: &[],
+            });
+        let resolve_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Motion Blur Pipeline"),
+            layout: Some(&resolve_pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &resolve_shader,
+                entry_point: "vs_fullscreen",
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &resolve_shader,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: None,
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+
+        #[cfg(feature = "guardrails")]
+        {
+            let reflection = crate::guardrails::ShaderReflection::new(
+                "motion_blur_velocity.wgsl",
+                include_str!("motion_blur_velocity.wgsl"),
+            );
+            reflection.check_vertex_buffers("vs_main", &velocity_buffers);
+            reflection.check_bind_group_layout(1, &motion_bind_group_layout_entries);
+            reflection.check_struct_size("Motion", std::mem::size_of::<Motion>());
+            let reflection =
+                crate::guardrails::ShaderReflection::new("motion_blur.wgsl", &resolve_source);
+            reflection.check_bind_group_layout(0, &resolve_bind_group_layout_entries);
+            reflection.check_struct_size("ResolveParams", std::mem::size_of::<ResolveParams>());
+        }
+
+        let params_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Motion Blur Params Buffer"),
+            size: std::mem::size_of::<ResolveParams>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        Self {
+            enabled: shutter.is_some(),
+            shutter: shutter.unwrap_or(DEFAULT_SHUTTER),
+            format,
+            velocity_pipeline,
+            resolve_pipeline,
+            resolve_bind_group_layout,
+            motion_buffer,
+            motion_bind_group,
+            params_buffer,
+            view_proj: None,
+            history: None,
+            targets: None,
+        }
+    }
+
+    /// Previous positions at location 3, laid out like the positions.
+    fn previous_position_descriptor() -> wgpu::VertexBufferLayout<'static> {
+        static ATTRIBUTES: &[wgpu::VertexAttribute] = &[wgpu::VertexAttribute {
+            offset: 0,
+            shader_location: 3,
+            format: wgpu::VertexFormat::Float32x4,
+        }];
+
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<InstancePosition>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Instance,
+            attributes: ATTRIBUTES,
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Shutter given when enabled, to recreate it the same way.
+    pub fn shutter(&self) -> Option<f32> {
+        self.enabled.then_some(self.shutter)
+    }
+
+    /// Disabling forgets the previous frame, the first frame once enabled again isn't blurred.
+    pub fn toggle(&mut self) {
+        self.enabled = !self.enabled;
+        if !self.enabled {
+            self.targets = None;
+            self.reset();
+        }
+    }
+
+    /// Forgets the previous positions, for particles that jumped or were moved to other slots.
+    pub fn reset(&mut self) {
+        self.history = None;
+        self.view_proj = None;
+    }
+
+    /// Makes sure the scene textures are `size` and the previous positions as large as
+    /// `position_buffer`, and remembers the camera for the next frame. Must be called before
+    /// [`MotionBlur::views`] and [`MotionBlur::resolve`] every time the scene is drawn.
+    pub fn prepare(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        size: (u32, u32),
+        view_proj: glam::Mat4,
+        position_buffer: &wgpu::Buffer,
+    ) {
+        if self.targets.as_ref().map(|targets| targets.size) != Some(size) {
+            self.targets = Some(self.create_targets(device, size));
+        }
+        if self.history.as_ref().map(wgpu::Buffer::size) != Some(position_buffer.size()) {
+            self.history = Some(Self::create_history(device, queue, position_buffer));
+        }
+
+        let motion = Motion {
+            previous_view_proj: self.view_proj.unwrap_or(view_proj),
+        };
+        queue.write_buffer(&self.motion_buffer, 0, bytemuck::cast_slice(&[motion]));
+        self.view_proj = Some(view_proj);
+        let params = ResolveParams {
+            shutter: self.shutter,
+            max_length: MAX_LENGTH,
+            dilation: DILATION,
+            _padding: 0.0,
+        };
+        queue.write_buffer(&self.params_buffer, 0, bytemuck::cast_slice(&[params]));
+    }
+
+    /// Color and depth views the scene should be drawn into, once prepared.
+    pub fn views(&self) -> Option<(&wgpu::TextureView, &wgpu::TextureView)> {
+        let targets = self.targets.as_ref()?;
+        Some((&targets.color_view, &targets.depth_view))
+    }
+
+    /// Draws the velocities of the `ranges` of particles, blurs the scene drawn into
+    /// [`MotionBlur::views`] along them into `view`, then keeps the positions for the next frame.
+    pub fn resolve(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        view: &wgpu::TextureView,
+        buffers: &ParticleBuffers,
+        ranges: &[Range<usize>],
+    ) {
+        let (Some(targets), Some(history)) = (&self.targets, &self.history) else {
+            return;
+        };
+
+        {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Motion Blur Velocity Pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &targets.velocity_view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                        store: true,
+                    },
+                })],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: &targets.depth_view,
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: false,
+                    }),
+                    stencil_ops: None,
+                }),
+            });
+            render_pass.set_pipeline(&self.velocity_pipeline);
+            render_pass.set_bind_group(0, buffers.camera_bind_group, &[]);
+            render_pass.set_bind_group(1, &self.motion_bind_group, &[]);
+            render_pass.set_vertex_buffer(0, buffers.vertex_buffer.slice(..));
+            render_pass.set_vertex_buffer(1, buffers.position_buffer.slice(..));
+            render_pass.set_vertex_buffer(2, history.slice(..));
+            render_pass.set_index_buffer(buffers.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
+            for range in ranges {
+                render_pass.draw_indexed(
+                    0..buffers.index_count,
+                    0,
+                    range.start as u32..range.end as u32,
+                );
+            }
+        }
+
+        {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Motion Blur Pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                        store: true,
+                    },
+                })],
+                depth_stencil_attachment: None,
+            });
+            render_pass.set_pipeline(&self.resolve_pipeline);
+            render_pass.set_bind_group(0, &targets.bind_group, &[]);
+            render_pass.draw(0..3, 0..1);
+        }
+
+        encoder.copy_buffer_to_buffer(buffers.position_buffer, 0, history, 0, history.size());
+    }
+
+    /// Starts the previous positions from the current ones, nothing moves on the first frame.
+    fn create_history(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        position_buffer: &wgpu::Buffer,
+    ) -> wgpu::Buffer {
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Previous Position Buffer"),
+            size: position_buffer.size(),
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Motion Blur History Encoder"),
+        });
+        encoder.copy_buffer_to_buffer(position_buffer, 0, &buffer, 0, position_buffer.size());
+        queue.submit(Some(encoder.finish()));
+        buffer
+    }
+
+    fn create_targets(&self, device: &wgpu::Device, size: (u32, u32)) -> Targets {
+        let create_texture = |label, format| {
+            device.create_texture(&wgpu::TextureDescriptor {
+                label: Some(label),
+                size: wgpu::Extent3d {
+                    width: size.0,
+                    height: size.1,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format,
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                    | wgpu::TextureUsages::TEXTURE_BINDING,
+                view_formats: &[],
+            })
+        };
+        let color_texture = create_texture("Motion Blur Color Texture", self.format);
+        let depth_texture = create_texture("Motion Blur Depth Texture", DEPTH_FORMAT);
+        let velocity_texture = create_texture("Motion Blur Velocity Texture", VELOCITY_FORMAT);
+        let color_view = color_texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let depth_view = depth_texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let velocity_view = velocity_texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Motion Blur Bind Group"),
+            layout: &self.resolve_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&color_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&velocity_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: self.params_buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        Targets {
+            size,
+            _color_texture: color_texture,
+            color_view,
+            _depth_texture: depth_texture,
+            depth_view,
+            _velocity_texture: velocity_texture,
+            velocity_view,
+            bind_group,
+        }
+    }
+}
@@ -0,0 +1,59 @@
+// Motion blur post effect: averages the scene along the distance every pixel moved on screen while
+// the shutter was open
+
+// Must match ResolveParams in motion_blur.rs
+struct ResolveParams {
+    shutter: f32,
+    max_length: f32,
+    dilation: f32,
+    _padding: f32,
+};
+
+@group(0) @binding(0)
+var scene: texture_2d<f32>;
+// Texture coordinates moved since the previous frame, see motion_blur_velocity.wgsl
+@group(0) @binding(1)
+var velocities: texture_2d<f32>;
+@group(0) @binding(2)
+var<uniform> params: ResolveParams;
+
+const SAMPLES: i32 = 16;
+
+// Distance moved on screen while the shutter was open, in pixels
+fn pixel_streak(coords: vec2<i32>) -> vec2<f32> {
+    let size = vec2<f32>(textureDimensions(velocities));
+    let streak = textureLoad(velocities, coords, 0).xy * size * params.shutter;
+    let streak_length = length(streak);
+    if streak_length > params.max_length {
+        return streak * params.max_length / streak_length;
+    }
+    return streak;
+}
+
+@fragment
+fn fs_main(in: FullscreenOutput) -> @location(0) vec4<f32> {
+    let max_coords = vec2<i32>(textureDimensions(scene)) - 1;
+    let center = vec2<i32>(in.clip_position.xy);
+
+    // Velocities are only written where particles are drawn, the pixels around fast particles take
+    // theirs so the streaks reach past them
+    var streak = pixel_streak(center);
+    let reach = i32(params.dilation);
+    for (var y = -1; y <= 1; y++) {
+        for (var x = -1; x <= 1; x++) {
+            let coords = clamp(center + vec2<i32>(x, y) * reach, vec2<i32>(0), max_coords);
+            let neighbor = pixel_streak(coords);
+            if dot(neighbor, neighbor) > dot(streak, streak) {
+                streak = neighbor;
+            }
+        }
+    }
+
+    var sum = vec4<f32>(0.0);
+    for (var i = 0; i < SAMPLES; i++) {
+        let t = (f32(i) + 0.5) / f32(SAMPLES) - 0.5;
+        let coords = clamp(center + vec2<i32>(round(streak * t)), vec2<i32>(0), max_coords);
+        sum += textureLoad(scene, coords, 0);
+    }
+    return sum / f32(SAMPLES);
+}
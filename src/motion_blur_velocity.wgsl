@@ -0,0 +1,83 @@
+// Velocities of the particles on screen for the motion blur, from their positions and the camera
+// of the previous frame
+
+struct CameraUniform {
+    view_proj: mat4x4<f32>,
+    eye: vec4<f32>,
+    inv_view_proj: mat4x4<f32>,
+    previous_view_proj: mat4x4<f32>,
+    view: mat4x4<f32>,
+    proj: mat4x4<f32>,
+    group_mask: u32,
+    reverse_z: u32,
+};
+@group(0) @binding(0)
+var<uniform> camera: CameraUniform;
+
+// Must match Motion in motion_blur.rs
+struct Motion {
+    previous_view_proj: mat4x4<f32>,
+};
+@group(1) @binding(0)
+var<uniform> motion: Motion;
+
+struct VertexInput {
+    @location(0) position: vec3<f32>,
+    @location(1) vertex_position: vec2<f32>,
+    @location(4) normal: vec3<f32>,
+};
+
+struct InstanceInput {
+    @location(2) position: vec4<f32>,
+    @location(3) previous_position: vec4<f32>,
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) vertex_position: vec2<f32>,
+    // Clip positions of the vertex this frame and the previous one
+    @location(1) current: vec4<f32>,
+    @location(2) previous: vec4<f32>,
+};
+
+// Must match `DEPTH_SCALE` in depth_of_field.rs
+const DEPTH_SCALE: f32 = 1000.0;
+
+@vertex
+fn vs_main(model: VertexInput, instance: InstanceInput) -> VertexOutput {
+    var out: VertexOutput;
+    out.clip_position = camera.view_proj * vec4<f32>(model.position + instance.position.xyz, 1.0);
+    out.vertex_position = model.vertex_position;
+    out.current = out.clip_position;
+    out.previous = motion.previous_view_proj
+        * vec4<f32>(model.position + instance.previous_position.xyz, 1.0);
+    return out;
+}
+
+struct VelocityOutput {
+    @location(0) velocity: vec2<f32>,
+    @builtin(frag_depth) depth: f32,
+};
+
+// Same coverage and depth as `fs_depth` in shader.wgsl, so only the particle in front writes its
+// velocity
+@fragment
+fn fs_main(in: VertexOutput) -> VelocityOutput {
+    if length(in.vertex_position - 0.5) * 2.0 >= 1.0 {
+        discard;
+    }
+    var out: VelocityOutput;
+    // Particles that were behind the camera don't move on screen
+    if in.previous.w > 0.0 {
+        let moved = in.current.xy / in.current.w - in.previous.xy / in.previous.w;
+        // Normalized device coordinates to texture coordinates, y pointing down
+        out.velocity = moved * vec2<f32>(0.5, -0.5);
+    }
+    // `remap_depth` in shader.wgsl
+    if camera.reverse_z != 0u {
+        out.depth = DEPTH_SCALE / (in.current.w + DEPTH_SCALE);
+    } else {
+        out.depth = in.current.w / (in.current.w + DEPTH_SCALE);
+    }
+    return out;
+}
@@ -0,0 +1,221 @@
+//! Draws every live range of the instance buffer through indirect draws, with arguments built on
+//! the GPU from the visible particle count.
+//!
+//! The draws are issued with a single `multi_draw_indexed_indirect` when the device supports
+//! `Features::MULTI_DRAW_INDIRECT`, and one indirect draw per range otherwise.
+
+use std::ops::Range;
+
+use bytemuck::{Pod, Zeroable};
+use wgpu::util::DeviceExt;
+
+use crate::capabilities::GpuCapabilities;
+
+/// Most ranges drawn at once, one workgroup of multi_draw.wgsl covers them.
+const MAX_DRAWS: usize = 64;
+// Size of wgpu::util::DrawIndexedIndirect
+const DRAW_ARGS_SIZE: u64 = 20;
+
+// Must match Params in multi_draw.wgsl
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq, Pod, Zeroable)]
+struct MultiDrawParams {
+    range_count: u32,
+    visible: u32,
+    index_count: u32,
+    _padding: u32,
+}
+
+pub struct MultiDraw {
+    // Ranges last uploaded, drawn by `draw`
+    ranges: Vec<Range<usize>>,
+    // Whether the ranges of the last `prepare` fit
+    ready: bool,
+    params: MultiDrawParams,
+    ranges_buffer: wgpu::Buffer,
+    params_buffer: wgpu::Buffer,
+    indirect_buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+    pipeline: wgpu::ComputePipeline,
+    multi_draw: bool,
+}
+
+impl MultiDraw {
+    /// Draws `index_count` indices per instance. Returns `None` without indirect draws starting
+    /// past the first instance, the ranges don't start at the first instance.
+    pub fn new(
+        device: &wgpu::Device,
+        capabilities: &GpuCapabilities,
+        index_count: u32,
+    ) -> Option<Self> {
+        if !capabilities.indirect_first_instance {
+            return None;
+        }
+
+        let ranges_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Multi-Draw Ranges Buffer"),
+            size: (MAX_DRAWS * 2 * std::mem::size_of::<u32>()) as u64,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let params = MultiDrawParams {
+            range_count: 0,
+            visible: 0,
+            index_count,
+            _padding: 0,
+        };
+        let params_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Multi-Draw Params Buffer"),
+            contents: bytemuck::bytes_of(&params),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let indirect_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Multi-Draw Indirect Buffer"),
+            size: MAX_DRAWS as u64 * DRAW_ARGS_SIZE,
+            usage: wgpu::BufferUsages::INDIRECT | wgpu::BufferUsages::STORAGE,
+            mapped_at_creation: false,
+        });
+
+        let storage_entry = |binding, read_only| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let bind_group_layout_entries = [
+            storage_entry(0, true),
+            storage_entry(1, false),
+            wgpu::BindGroupLayoutEntry {
+                binding: 2,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+        ];
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Multi-Draw Bind Group Layout"),
+            entries: &bind_group_layout_entries,
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Multi-Draw Bind Group"),
+            layout: &bind_group_layout,
+            entries: &[&ranges_buffer, &indirect_buffer, &params_buffer]
+                .iter()
+                .enumerate()
+                .map(|(binding, buffer)| wgpu::BindGroupEntry {
+                    binding: binding as u32,
+                    resource: buffer.as_entire_binding(),
+                })
+                .collect::<Vec<_>>(),
+        });
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Multi-Draw Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("multi_draw.wgsl").into()),
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Multi-Draw Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Multi-Draw Pipeline"),
+            layout: Some(&pipeline_layout),
+            module: &shader,
+            entry_point: "main",
+        });
+
+        #[cfg(feature = "guardrails")]
+        {
+            let reflection = crate::guardrails::ShaderReflection::new(
+                "multi_draw.wgsl",
+                include_str!("multi_draw.wgsl"),
+            );
+            reflection.check_bind_group_layout(0, &bind_group_layout_entries);
+            reflection.check_struct_size("Params", std::mem::size_of::<MultiDrawParams>());
+            reflection.check_struct_size("DrawArgs", DRAW_ARGS_SIZE as usize);
+        }
+
+        Some(Self {
+            ranges: vec![],
+            ready: false,
+            params,
+            ranges_buffer,
+            params_buffer,
+            indirect_buffer,
+            bind_group,
+            pipeline,
+            multi_draw: capabilities.multi_draw_indirect,
+        })
+    }
+
+    /// Draws the first `visible` instances of `ranges`, unless there are too many ranges.
+    pub fn prepare(&mut self, queue: &wgpu::Queue, ranges: &[Range<usize>], visible: usize) {
+        self.ready = ranges.len() <= MAX_DRAWS;
+        if !self.ready {
+            return;
+        }
+        if self.ranges != ranges {
+            let bounds = ranges
+                .iter()
+                .flat_map(|range| [range.start as u32, range.end as u32])
+                .collect::<Vec<_>>();
+            queue.write_buffer(&self.ranges_buffer, 0, bytemuck::cast_slice(&bounds));
+            self.ranges = ranges.to_vec();
+        }
+        let params = MultiDrawParams {
+            range_count: ranges.len() as u32,
+            visible: visible as u32,
+            ..self.params
+        };
+        if params != self.params {
+            queue.write_buffer(&self.params_buffer, 0, bytemuck::bytes_of(&params));
+            self.params = params;
+        }
+    }
+
+    /// Builds the draw arguments of the prepared ranges.
+    pub fn encode(&self, encoder: &mut wgpu::CommandEncoder) {
+        if !self.ready {
+            return;
+        }
+        #[cfg(feature = "guardrails")]
+        crate::guardrails::check_dispatch_coverage(
+            [1, 1, 1],
+            [MAX_DRAWS as u32, 1, 1],
+            self.ranges.len(),
+        );
+
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Multi-Draw Pass"),
+        });
+        compute_pass.set_pipeline(&self.pipeline);
+        compute_pass.set_bind_group(0, &self.bind_group, &[]);
+        compute_pass.dispatch_workgroups(1, 1, 1);
+    }
+
+    /// Draws the prepared ranges, with the quad and instance buffers already bound. Returns false
+    /// if there were too many, they have to be drawn directly.
+    pub fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) -> bool {
+        if !self.ready {
+            return false;
+        }
+        let count = self.ranges.len() as u32;
+        if self.multi_draw {
+            render_pass.multi_draw_indexed_indirect(&self.indirect_buffer, 0, count);
+            return true;
+        }
+        for index in 0..count as u64 {
+            render_pass.draw_indexed_indirect(&self.indirect_buffer, index * DRAW_ARGS_SIZE);
+        }
+        true
+    }
+}
@@ -0,0 +1,53 @@
+// Builds the indirect draw arguments of each live range, clipped to the visible particle count
+
+struct Params {
+    range_count: u32,
+    // Particles drawn across all ranges, in order
+    visible: u32,
+    index_count: u32,
+    _padding: u32,
+}
+
+// Same layout as wgpu::util::DrawIndexedIndirect
+struct DrawArgs {
+    index_count: u32,
+    instance_count: u32,
+    first_index: u32,
+    base_vertex: i32,
+    first_instance: u32,
+}
+
+// Start and end of each live range
+@group(0) @binding(0)
+var<storage, read> ranges: array<vec2<u32>>;
+
+@group(0) @binding(1)
+var<storage, read_write> draws: array<DrawArgs>;
+
+@group(0) @binding(2)
+var<uniform> params: Params;
+
+// A single workgroup covers every range, must match MAX_DRAWS in multi_draw.rs
+@compute @workgroup_size(64)
+fn main(@builtin(global_invocation_id) id: vec3<u32>) {
+    let index = id.x;
+    if index >= params.range_count {
+        return;
+    }
+
+    // There are few ranges, each invocation adds up the ones before it
+    var before = 0u;
+    for (var i = 0u; i < index; i++) {
+        before += ranges[i].y - ranges[i].x;
+    }
+    let range = ranges[index];
+    let remaining = params.visible - min(before, params.visible);
+
+    var args: DrawArgs;
+    args.index_count = params.index_count;
+    args.instance_count = min(range.y - range.x, remaining);
+    args.first_index = 0u;
+    args.base_vertex = 0;
+    args.first_instance = range.x;
+    draws[index] = args;
+}
@@ -0,0 +1,535 @@
+//! N-body gravity simulation mode: before every step, every particle attracts every other one with
+//! a softened inverse square force. The step then moves the particles as usual.
+//!
+//! Up to [`EXACT_MAX_PARTICLES`], each pair is visited, so a frame costs `count²` interactions.
+//! The GPU kernel loads the positions a tile at a time into workgroup memory, where every
+//! invocation of the workgroup reads them, instead of each invocation reading all of them from
+//! storage. `--nbody-tile-size 0` runs the naive kernel instead, to compare them with `bench`.
+//!
+//! Larger counts are binned in a grid of `grid_size³` cells over their bounding box. Cells that
+//! aren't neighbors attract each other through their centers of mass, and particles feel the
+//! centers of mass of the 27 cells around them directly, so a frame costs `count * 27 +
+//! grid_size⁶` interactions.
+
+use bytemuck::{Pod, Zeroable};
+use glam::{DVec3, IVec3, Vec3, Vec4};
+use rayon::prelude::{
+    IndexedParallelIterator, IntoParallelIterator, IntoParallelRefIterator, ParallelIterator,
+};
+use wgpu::util::DeviceExt;
+
+use crate::{shader_check, speed_layout::SpeedLayout};
+
+// Must match `@workgroup_size` of main in nbody.wgsl, and WORKGROUP_SIZE in nbody_grid.wgsl
+const WORKGROUP_SIZE: u32 = 64;
+/// Particles attracted together by the tiled kernel, 0 for the naive one
+pub const DEFAULT_TILE_SIZE: u32 = 64;
+// Workgroup memory taken by each position of a tile, a vec3 aligned to 16 bytes
+const TILE_BYTES_PER_PARTICLE: u32 = 16;
+// Size of a position, and of the cells of the grid
+const ELEMENT_SIZE: u64 = 16;
+/// Particles attracted pair by pair, above this they're binned in the grid
+pub const EXACT_MAX_PARTICLES: usize = 16_384;
+/// Cells per side of the grid, unless `--nbody-grid` is given
+pub const DEFAULT_GRID_SIZE: u32 = 32;
+/// Finer grids take too long, the cells attract each other pair by pair
+pub const MAX_GRID_SIZE: u32 = 64;
+
+/// Entry points of nbody_grid.wgsl in the order they run, and whether they run once per cell
+/// rather than once per particle.
+const GRID_PASSES: [(&str, bool); 6] = [
+    ("clear", true),
+    ("find_bounds", false),
+    ("bin", false),
+    ("find_centers", true),
+    ("far_field", true),
+    ("apply", false),
+];
+
+/// Strength of the gravity, shared with nbody.wgsl.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Pod, Zeroable)]
+pub struct NbodyParams {
+    /// Gravitational constant, every particle having a unit mass
+    pub gravity: f32,
+    /// Added to the distances so close encounters don't fling particles away
+    pub softening: f32,
+    /// Time the gravity acts for, per frame of step
+    pub time_scale: f32,
+    /// Length of a simulation step, in frames, set every step
+    pub dt: f32,
+    /// Number of particles, set every step
+    pub count: u32,
+    /// Cells per side of the grid large counts are binned in, 0 to always attract pair by pair.
+    /// Set every step
+    pub grid_size: u32,
+    pub _padding: [u32; 2],
+}
+
+impl Default for NbodyParams {
+    fn default() -> Self {
+        Self {
+            gravity: 0.01,
+            softening: 20.0,
+            time_scale: 1.0,
+            dt: 1.0,
+            count: 0,
+            grid_size: 0,
+            _padding: [0; 2],
+        }
+    }
+}
+
+impl NbodyParams {
+    fn uses_grid(&self, count: usize) -> bool {
+        self.grid_size > 0 && count > EXACT_MAX_PARTICLES
+    }
+
+    /// Returns the speeds after the gravity of all the particles. Must match `main` and
+    /// `main_tiled` in nbody.wgsl, or nbody_grid.wgsl for large counts.
+    pub fn accelerate(&self, positions: &[Vec3], speeds: &[Vec3]) -> Vec<Vec3> {
+        let step = self.gravity * self.time_scale * self.dt;
+        if self.uses_grid(positions.len()) {
+            return self.accelerate_grid(positions, speeds, step);
+        }
+        (0..positions.len())
+            .into_par_iter()
+            .map(|index| {
+                let position = positions[index];
+                // The particle itself is at distance 0 and adds nothing
+                let acceleration = positions.iter().fold(Vec3::ZERO, |acceleration, &other| {
+                    acceleration + self.attraction(other - position, 1.0)
+                });
+                speeds[index] + acceleration * step
+            })
+            .collect()
+    }
+
+    /// Acceleration towards `mass` at `offset`, without the gravitational constant.
+    fn attraction(&self, offset: Vec3, mass: f32) -> Vec3 {
+        let distance_squared = offset.length_squared() + self.softening * self.softening;
+        offset * mass / (distance_squared * distance_squared.sqrt())
+    }
+
+    fn accelerate_grid(&self, positions: &[Vec3], speeds: &[Vec3], step: f32) -> Vec<Vec3> {
+        let n = self.grid_size as i32;
+        let (low, high) = positions
+            .par_iter()
+            .fold(
+                || (Vec3::MAX, Vec3::MIN),
+                |(low, high), &position| (low.min(position), high.max(position)),
+            )
+            .reduce(
+                || (Vec3::MAX, Vec3::MIN),
+                |(low_a, high_a), (low_b, high_b)| (low_a.min(low_b), high_a.max(high_b)),
+            );
+        let cell_size = (high - low).max(Vec3::splat(1e-3)) / n as f32;
+        let cell_of = |position: Vec3| {
+            ((position - low) / cell_size)
+                .floor()
+                .as_ivec3()
+                .clamp(IVec3::ZERO, IVec3::splat(n - 1))
+        };
+        let index_of = |cell: IVec3| (cell.x + (cell.y + cell.z * n) * n) as usize;
+        let coordinates_of =
+            |index: usize| IVec3::new(index as i32 % n, index as i32 / n % n, index as i32 / n / n);
+
+        let mut sums = vec![(DVec3::ZERO, 0u32); (n * n * n) as usize];
+        for &position in positions {
+            let (sum, count) = &mut sums[index_of(cell_of(position))];
+            *sum += position.as_dvec3();
+            *count += 1;
+        }
+        let centers = sums
+            .iter()
+            .map(|&(sum, count)| match count {
+                0 => Vec4::ZERO,
+                _ => (sum / count as f64).as_vec3().extend(count as f32),
+            })
+            .collect::<Vec<_>>();
+        let occupied = (0..centers.len())
+            .filter(|&index| centers[index].w > 0.0)
+            .collect::<Vec<_>>();
+
+        let far_fields = (0..centers.len())
+            .into_par_iter()
+            .map(|index| {
+                let cell = coordinates_of(index);
+                occupied
+                    .iter()
+                    .filter(|&&other| (coordinates_of(other) - cell).abs().max_element() > 1)
+                    .fold(Vec3::ZERO, |acceleration, &other| {
+                        let offset = centers[other].truncate() - centers[index].truncate();
+                        acceleration + self.attraction(offset, centers[other].w)
+                    })
+            })
+            .collect::<Vec<_>>();
+
+        positions
+            .par_iter()
+            .zip(speeds)
+            .map(|(&position, &speed)| {
+                let cell = cell_of(position);
+                let own = index_of(cell);
+                let mut acceleration = far_fields[own];
+                for i in 0..27 {
+                    let neighbor = cell + IVec3::new(i % 3, i / 3 % 3, i / 9) - 1;
+                    if neighbor.min_element() < 0 || neighbor.max_element() >= n {
+                        continue;
+                    }
+                    let neighbor_index = index_of(neighbor);
+                    let (mut center, mut mass) = (
+                        centers[neighbor_index].truncate(),
+                        centers[neighbor_index].w,
+                    );
+                    if neighbor_index == own {
+                        // The particle doesn't attract itself
+                        center = (center * mass - position) / (mass - 1.0).max(1.0);
+                        mass -= 1.0;
+                    }
+                    acceleration += self.attraction(center - position, mass);
+                }
+                speed + acceleration * step
+            })
+            .collect()
+    }
+}
+
+/// Applies the gravity on the GPU, updating the speeds in place.
+pub struct Nbody {
+    capacity: usize,
+    params_buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+    pipeline: wgpu::ComputePipeline,
+    // Invocations per workgroup of the tiled kernel, 0 when running the naive one
+    tile_size: u32,
+    grid: Option<Grid>,
+}
+
+/// The passes of nbody_grid.wgsl, in the order of [`GRID_PASSES`].
+struct Grid {
+    size: u32,
+    bind_group: wgpu::BindGroup,
+    passes: Vec<wgpu::ComputePipeline>,
+}
+
+impl Nbody {
+    /// Attracts the first of the `capacity` particles of `position_buffer` and `cpu_data_buffer`,
+    /// laid out in `speed_layout`, to each other, through a grid of `grid_size³` cells past
+    /// [`EXACT_MAX_PARTICLES`] if not 0, in tiles of `tile_size` if not 0. Returns `None` if the
+    /// device can't bind them in one piece.
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        device: &wgpu::Device,
+        capacity: usize,
+        params: &NbodyParams,
+        grid_size: u32,
+        tile_size: u32,
+        position_buffer: &wgpu::Buffer,
+        cpu_data_buffer: &wgpu::Buffer,
+        speed_layout: SpeedLayout,
+    ) -> Option<Self> {
+        if capacity as u64 * ELEMENT_SIZE > device.limits().max_storage_buffer_binding_size as u64 {
+            return None;
+        }
+
+        let params_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Nbody Params Buffer"),
+            contents: bytemuck::cast_slice(&[*params]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let storage_entry = |binding, read_only| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let bind_group_layout_entries = [
+            wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+            storage_entry(1, true),
+            storage_entry(2, false),
+        ];
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Nbody Bind Group Layout"),
+            entries: &bind_group_layout_entries,
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Nbody Bind Group"),
+            layout: &bind_group_layout,
+            entries: &[&params_buffer, position_buffer, cpu_data_buffer]
+                .iter()
+                .enumerate()
+                .map(|(binding, buffer)| wgpu::BindGroupEntry {
+                    binding: binding as u32,
+                    resource: buffer.as_entire_binding(),
+                })
+                .collect::<Vec<_>>(),
+        });
+
+        let tile_size = max_tile_size(device).map_or(0, |max| {
+            if tile_size > max {
+                log::warn!("Tiles of {tile_size} particles don't fit on this device, using {max}");
+            }
+            tile_size.min(max)
+        });
+        let source = shader_source(tile_size, speed_layout);
+        let shader = shader_check::create_module(device, "Nbody Shader", "nbody.wgsl", &source);
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Nbody Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Nbody Pipeline"),
+            layout: Some(&pipeline_layout),
+            module: &shader,
+            entry_point: if tile_size > 0 { "main_tiled" } else { "main" },
+        });
+
+        #[cfg(feature = "guardrails")]
+        {
+            let reflection = crate::guardrails::ShaderReflection::new("nbody.wgsl", &source);
+            reflection.check_bind_group_layout(0, &bind_group_layout_entries);
+            reflection.check_struct_size("NbodyParams", std::mem::size_of::<NbodyParams>());
+        }
+
+        let grid = (grid_size > 0).then(|| {
+            Grid::new(
+                device,
+                grid_size,
+                &params_buffer,
+                position_buffer,
+                cpu_data_buffer,
+                speed_layout,
+            )
+        });
+
+        Some(Self {
+            capacity,
+            params_buffer,
+            bind_group,
+            pipeline,
+            tile_size,
+            grid,
+        })
+    }
+
+    /// Applies the gravity between the first `params.count` particles.
+    pub fn encode(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        params: &NbodyParams,
+    ) {
+        let count = (params.count as usize).min(self.capacity);
+        // The grid buffers are sized for the grid the passes were created with
+        let params = NbodyParams {
+            count: count as u32,
+            grid_size: self.grid.as_ref().map_or(0, |grid| grid.size),
+            ..*params
+        };
+        queue.write_buffer(&self.params_buffer, 0, bytemuck::cast_slice(&[params]));
+
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Nbody Pass"),
+        });
+        match &self.grid {
+            Some(grid) if params.uses_grid(count) => {
+                let cells = grid.size.pow(3) as usize;
+                compute_pass.set_bind_group(0, &grid.bind_group, &[]);
+                for (pipeline, (_, per_cell)) in grid.passes.iter().zip(GRID_PASSES) {
+                    compute_pass.set_pipeline(pipeline);
+                    let invocations = if per_cell { cells } else { count };
+                    let (x, y) = workgroups(device, invocations, WORKGROUP_SIZE);
+                    compute_pass.dispatch_workgroups(x, y, 1);
+                }
+            }
+            _ => {
+                compute_pass.set_pipeline(&self.pipeline);
+                compute_pass.set_bind_group(0, &self.bind_group, &[]);
+                let size = match self.tile_size {
+                    0 => WORKGROUP_SIZE,
+                    tile_size => tile_size,
+                };
+                let (x, y) = workgroups(device, count, size);
+                compute_pass.dispatch_workgroups(x, y, 1);
+            }
+        }
+    }
+}
+
+impl Grid {
+    fn new(
+        device: &wgpu::Device,
+        size: u32,
+        params_buffer: &wgpu::Buffer,
+        position_buffer: &wgpu::Buffer,
+        cpu_data_buffer: &wgpu::Buffer,
+        speed_layout: SpeedLayout,
+    ) -> Self {
+        let cell_buffer = |label| {
+            device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some(label),
+                size: size.pow(3) as u64 * ELEMENT_SIZE,
+                usage: wgpu::BufferUsages::STORAGE,
+                mapped_at_creation: false,
+            })
+        };
+        let bounds_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Nbody Bounds Buffer"),
+            size: 6 * 4,
+            usage: wgpu::BufferUsages::STORAGE,
+            mapped_at_creation: false,
+        });
+        let cells_buffer = cell_buffer("Nbody Cells Buffer");
+        let centers_buffer = cell_buffer("Nbody Centers Buffer");
+        let far_fields_buffer = cell_buffer("Nbody Far Fields Buffer");
+
+        let storage_entry = |binding, read_only| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let bind_group_layout_entries = [
+            wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+            storage_entry(1, true),
+            storage_entry(2, false),
+            storage_entry(3, false),
+            storage_entry(4, false),
+            storage_entry(5, false),
+            storage_entry(6, false),
+        ];
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Nbody Grid Bind Group Layout"),
+            entries: &bind_group_layout_entries,
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Nbody Grid Bind Group"),
+            layout: &bind_group_layout,
+            entries: &[
+                params_buffer,
+                position_buffer,
+                cpu_data_buffer,
+                &bounds_buffer,
+                &cells_buffer,
+                &centers_buffer,
+                &far_fields_buffer,
+            ]
+            .iter()
+            .enumerate()
+            .map(|(binding, buffer)| wgpu::BindGroupEntry {
+                binding: binding as u32,
+                resource: buffer.as_entire_binding(),
+            })
+            .collect::<Vec<_>>(),
+        });
+
+        let source = grid_shader_source(speed_layout);
+        let shader =
+            shader_check::create_module(device, "Nbody Grid Shader", "nbody_grid.wgsl", &source);
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Nbody Grid Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let passes = GRID_PASSES
+            .iter()
+            .map(|(entry_point, _)| {
+                device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                    label: Some("Nbody Grid Pipeline"),
+                    layout: Some(&pipeline_layout),
+                    module: &shader,
+                    entry_point,
+                })
+            })
+            .collect();
+
+        #[cfg(feature = "guardrails")]
+        {
+            let reflection = crate::guardrails::ShaderReflection::new("nbody_grid.wgsl", &source);
+            reflection.check_bind_group_layout(0, &bind_group_layout_entries);
+            reflection.check_struct_size("NbodyParams", std::mem::size_of::<NbodyParams>());
+        }
+
+        Self {
+            size,
+            bind_group,
+            passes,
+        }
+    }
+}
+
+/// Workgroups of `size` invocations covering `invocations`, in rows past the dimension limit.
+fn workgroups(device: &wgpu::Device, invocations: usize, size: u32) -> (u32, u32) {
+    let groups = (invocations as u32).div_ceil(size).max(1);
+    let max_groups = device.limits().max_compute_workgroups_per_dimension;
+    let (x, y) = (groups.min(max_groups), groups.div_ceil(max_groups));
+    #[cfg(feature = "guardrails")]
+    crate::guardrails::check_dispatch_coverage([x, y, 1], [size, 1, 1], invocations);
+    (x, y)
+}
+
+/// Largest tile the device runs, `None` if it can't run any.
+fn max_tile_size(device: &wgpu::Device) -> Option<u32> {
+    let limits = device.limits();
+    let max = limits
+        .max_compute_invocations_per_workgroup
+        .min(limits.max_compute_workgroup_size_x)
+        .min(limits.max_compute_workgroup_storage_size / TILE_BYTES_PER_PARTICLE);
+    (max > 0).then_some(max)
+}
+
+/// The source of the pair by pair shader, after `speed_layout`, in tiles of `tile_size` if not 0.
+pub fn shader_source(tile_size: u32, speed_layout: SpeedLayout) -> String {
+    format!(
+        "{}\n{}",
+        speed_layout.wgsl(),
+        include_str!("nbody.wgsl")
+            .replace(
+                "const TILE_SIZE: u32 = 64u;",
+                &format!("const TILE_SIZE: u32 = {}u;", tile_size.max(1)),
+            )
+            .replace(
+                "@workgroup_size(64, 1, 1)",
+                &format!("@workgroup_size({}, 1, 1)", tile_size.max(1)),
+            )
+    )
+}
+
+/// The source of the grid's shader, after `speed_layout`.
+pub fn grid_shader_source(speed_layout: SpeedLayout) -> String {
+    format!(
+        "{}\n{}",
+        speed_layout.wgsl(),
+        include_str!("nbody_grid.wgsl")
+    )
+}
@@ -0,0 +1,98 @@
+// N-body gravity: every particle attracts every other one with a softened inverse square force.
+// Included after the SpeedLayout, see speed_layout.rs.
+
+// Must match NbodyParams in nbody.rs
+struct NbodyParams {
+    gravity: f32,
+    softening: f32,
+    time_scale: f32,
+    dt: f32,
+    count: u32,
+    grid_size: u32,
+    _padding0: u32,
+    _padding1: u32,
+};
+
+@group(0) @binding(0)
+var<uniform> params: NbodyParams;
+
+@group(0) @binding(1)
+var<storage, read> positions: array<vec4<f32>>;
+
+// Speeds of the particles, in CpuData. Only positions are read, so they're updated in place
+@group(0) @binding(2)
+var<storage, read_write> speeds: array<PackedSpeed>;
+
+// Replaced along with the workgroup size of main_tiled, see `Nbody::new`
+const TILE_SIZE: u32 = 64u;
+
+// Positions shared by the workgroup of main_tiled, loaded a tile at a time
+var<workgroup> tile_positions: array<vec3<f32>, TILE_SIZE>;
+
+// Acceleration towards a unit mass at `offset`, without the gravitational constant. The particle
+// itself is at distance 0 and adds nothing
+fn attraction(offset: vec3<f32>) -> vec3<f32> {
+    let distance_squared = dot(offset, offset) + params.softening * params.softening;
+    return offset / (distance_squared * sqrt(distance_squared));
+}
+
+fn accelerate(index: u32, acceleration: vec3<f32>) {
+    let speed = unpack_speed(speeds[index]);
+    let step = params.gravity * params.time_scale * params.dt;
+    speeds[index] = pack_speed(vec4<f32>(speed.xyz + acceleration * step, speed.w));
+}
+
+// One invocation per particle, each reading every position from storage.
+// Must match NbodyParams::accelerate in nbody.rs
+@compute @workgroup_size(64)
+fn main(
+    @builtin(global_invocation_id) id: vec3<u32>,
+    @builtin(num_workgroups) workgroups: vec3<u32>,
+) {
+    // Large counts are dispatched in rows, a single dimension is limited to 65535 workgroups
+    let index = id.x + id.y * workgroups.x * 64u;
+    if index >= params.count {
+        return;
+    }
+    let position = positions[index].xyz;
+    var acceleration = vec3<f32>(0.0);
+    for (var k = 0u; k < params.count; k++) {
+        acceleration += attraction(positions[k].xyz - position);
+    }
+    accelerate(index, acceleration);
+}
+
+// One invocation per particle, all of the workgroup reading each tile of positions together.
+// Must match NbodyParams::accelerate in nbody.rs
+@compute @workgroup_size(64, 1, 1)
+fn main_tiled(
+    @builtin(global_invocation_id) id: vec3<u32>,
+    @builtin(num_workgroups) workgroups: vec3<u32>,
+    @builtin(local_invocation_index) local: u32,
+) {
+    let index = id.x + id.y * workgroups.x * TILE_SIZE;
+    let in_range = index < params.count;
+    var position = vec3<f32>(0.0);
+    if in_range {
+        position = positions[index].xyz;
+    }
+
+    // Invocations past the end still load tiles and reach the barriers
+    var acceleration = vec3<f32>(0.0);
+    for (var tile = 0u; tile < params.count; tile += TILE_SIZE) {
+        let load = tile + local;
+        if load < params.count {
+            tile_positions[local] = positions[load].xyz;
+        }
+        workgroupBarrier();
+        let loaded = min(TILE_SIZE, params.count - tile);
+        for (var k = 0u; k < loaded; k++) {
+            acceleration += attraction(tile_positions[k] - position);
+        }
+        workgroupBarrier();
+    }
+
+    if in_range {
+        accelerate(index, acceleration);
+    }
+}
@@ -0,0 +1,297 @@
+// Approximate n-body gravity for large counts. The particles are binned in a grid over their
+// bounding box, cells that aren't neighbors attract each other through their centers of mass, and
+// each particle feels the cells around its own directly. The passes run in the order of the entry
+// points below, see `GRID_PASSES` in nbody.rs. Included after the SpeedLayout, see speed_layout.rs.
+
+// Must match NbodyParams in nbody.rs
+struct NbodyParams {
+    gravity: f32,
+    softening: f32,
+    time_scale: f32,
+    dt: f32,
+    count: u32,
+    grid_size: u32,
+    _padding0: u32,
+    _padding1: u32,
+};
+
+// Particles binned in a cell, and the sums of their fixed point positions inside it
+struct Cell {
+    count: atomic<u32>,
+    sum_x: atomic<u32>,
+    sum_y: atomic<u32>,
+    sum_z: atomic<u32>,
+};
+
+@group(0) @binding(0)
+var<uniform> params: NbodyParams;
+
+@group(0) @binding(1)
+var<storage, read> positions: array<vec4<f32>>;
+
+// Speeds of the particles, in CpuData
+@group(0) @binding(2)
+var<storage, read_write> speeds: array<PackedSpeed>;
+
+// Bounding box of the particles as order preserving integers, min then max
+@group(0) @binding(3)
+var<storage, read_write> bounds: array<atomic<i32>, 6>;
+
+@group(0) @binding(4)
+var<storage, read_write> cells: array<Cell>;
+
+// Center of mass of every cell, and its mass in w
+@group(0) @binding(5)
+var<storage, read_write> centers: array<vec4<f32>>;
+
+// Acceleration at the center of mass of every cell from the cells that aren't its neighbors
+@group(0) @binding(6)
+var<storage, read_write> far_fields: array<vec4<f32>>;
+
+// Must match the workgroup size of every pass, and WORKGROUP_SIZE in nbody.rs
+const WORKGROUP_SIZE: u32 = 64u;
+// Fixed point steps per cell width, up to 2^32 / 1024 particles fit in a cell
+const FIXED_POINT_SCALE: f32 = 1024.0;
+const LARGEST_F32: f32 = 3.4e38;
+
+var<workgroup> group_bounds: array<atomic<i32>, 6>;
+var<workgroup> tile_centers: array<vec4<f32>, WORKGROUP_SIZE>;
+
+struct Grid {
+    origin: vec3<f32>,
+    cell_size: vec3<f32>,
+};
+
+// Floats compare like these integers, so the bounds can be found with integer atomics
+fn to_ordered(x: f32) -> i32 {
+    let bits = bitcast<i32>(x);
+    return select(bits, bits ^ 0x7fffffff, bits < 0);
+}
+
+fn from_ordered(ordered: i32) -> f32 {
+    return bitcast<f32>(select(ordered, ordered ^ 0x7fffffff, ordered < 0));
+}
+
+// Large counts are dispatched in rows, a single dimension is limited to 65535 workgroups
+fn flat_index(id: vec3<u32>, workgroups: vec3<u32>) -> u32 {
+    return id.x + id.y * workgroups.x * WORKGROUP_SIZE;
+}
+
+fn cell_count() -> u32 {
+    return params.grid_size * params.grid_size * params.grid_size;
+}
+
+fn grid() -> Grid {
+    let low = vec3<f32>(
+        from_ordered(atomicLoad(&bounds[0])),
+        from_ordered(atomicLoad(&bounds[1])),
+        from_ordered(atomicLoad(&bounds[2])),
+    );
+    let high = vec3<f32>(
+        from_ordered(atomicLoad(&bounds[3])),
+        from_ordered(atomicLoad(&bounds[4])),
+        from_ordered(atomicLoad(&bounds[5])),
+    );
+    return Grid(low, max(high - low, vec3<f32>(1e-3)) / f32(params.grid_size));
+}
+
+fn cell_of(grid: Grid, position: vec3<f32>) -> vec3<i32> {
+    let cell = vec3<i32>(floor((position - grid.origin) / grid.cell_size));
+    return clamp(cell, vec3<i32>(0), vec3<i32>(i32(params.grid_size) - 1));
+}
+
+fn cell_index(cell: vec3<i32>) -> u32 {
+    let n = params.grid_size;
+    return u32(cell.x) + (u32(cell.y) + u32(cell.z) * n) * n;
+}
+
+fn cell_coordinates(index: u32) -> vec3<i32> {
+    let n = params.grid_size;
+    return vec3<i32>(vec3<u32>(index % n, index / n % n, index / (n * n)));
+}
+
+// Acceleration towards `mass` at `offset`, without the gravitational constant
+fn attraction(offset: vec3<f32>, mass: f32) -> vec3<f32> {
+    let distance_squared = dot(offset, offset) + params.softening * params.softening;
+    return offset * mass / (distance_squared * sqrt(distance_squared));
+}
+
+// Empties the cells and the bounds, one invocation per cell
+@compute @workgroup_size(64)
+fn clear(
+    @builtin(global_invocation_id) id: vec3<u32>,
+    @builtin(num_workgroups) workgroups: vec3<u32>,
+) {
+    let index = flat_index(id, workgroups);
+    if index == 0u {
+        for (var axis = 0u; axis < 3u; axis++) {
+            atomicStore(&bounds[axis], to_ordered(LARGEST_F32));
+            atomicStore(&bounds[axis + 3u], to_ordered(-LARGEST_F32));
+        }
+    }
+    if index < cell_count() {
+        atomicStore(&cells[index].count, 0u);
+        atomicStore(&cells[index].sum_x, 0u);
+        atomicStore(&cells[index].sum_y, 0u);
+        atomicStore(&cells[index].sum_z, 0u);
+    }
+}
+
+// Bounding box of the particles, reduced in workgroup memory before the global atomics
+@compute @workgroup_size(64)
+fn find_bounds(
+    @builtin(global_invocation_id) id: vec3<u32>,
+    @builtin(num_workgroups) workgroups: vec3<u32>,
+    @builtin(local_invocation_index) local: u32,
+) {
+    if local < 6u {
+        let start = select(-LARGEST_F32, LARGEST_F32, local < 3u);
+        atomicStore(&group_bounds[local], to_ordered(start));
+    }
+    workgroupBarrier();
+    let index = flat_index(id, workgroups);
+    if index < params.count {
+        let position = positions[index].xyz;
+        for (var axis = 0u; axis < 3u; axis++) {
+            let ordered = to_ordered(position[axis]);
+            atomicMin(&group_bounds[axis], ordered);
+            atomicMax(&group_bounds[axis + 3u], ordered);
+        }
+    }
+    workgroupBarrier();
+    if local < 6u {
+        let ordered = atomicLoad(&group_bounds[local]);
+        if local < 3u {
+            atomicMin(&bounds[local], ordered);
+        } else {
+            atomicMax(&bounds[local], ordered);
+        }
+    }
+}
+
+// Adds every particle to its cell
+@compute @workgroup_size(64)
+fn bin(
+    @builtin(global_invocation_id) id: vec3<u32>,
+    @builtin(num_workgroups) workgroups: vec3<u32>,
+) {
+    let index = flat_index(id, workgroups);
+    if index >= params.count {
+        return;
+    }
+    let grid = grid();
+    let position = positions[index].xyz;
+    let cell = cell_of(grid, position);
+    let inside = clamp(
+        (position - grid.origin) / grid.cell_size - vec3<f32>(cell),
+        vec3<f32>(0.0),
+        vec3<f32>(1.0),
+    );
+    let fixed = vec3<u32>(inside * FIXED_POINT_SCALE);
+    let binned = cell_index(cell);
+    atomicAdd(&cells[binned].count, 1u);
+    atomicAdd(&cells[binned].sum_x, fixed.x);
+    atomicAdd(&cells[binned].sum_y, fixed.y);
+    atomicAdd(&cells[binned].sum_z, fixed.z);
+}
+
+// Center of mass of every cell, one invocation per cell
+@compute @workgroup_size(64)
+fn find_centers(
+    @builtin(global_invocation_id) id: vec3<u32>,
+    @builtin(num_workgroups) workgroups: vec3<u32>,
+) {
+    let index = flat_index(id, workgroups);
+    if index >= cell_count() {
+        return;
+    }
+    let count = atomicLoad(&cells[index].count);
+    if count == 0u {
+        centers[index] = vec4<f32>(0.0);
+        return;
+    }
+    let grid = grid();
+    let sum = vec3<f32>(vec3<u32>(
+        atomicLoad(&cells[index].sum_x),
+        atomicLoad(&cells[index].sum_y),
+        atomicLoad(&cells[index].sum_z),
+    ));
+    let inside = sum / (f32(count) * FIXED_POINT_SCALE);
+    let center = grid.origin + (vec3<f32>(cell_coordinates(index)) + inside) * grid.cell_size;
+    centers[index] = vec4<f32>(center, f32(count));
+}
+
+// Gravity of the cells that aren't neighbors at the center of every cell, one invocation per
+// cell reading the others a tile at a time from workgroup memory
+@compute @workgroup_size(64)
+fn far_field(
+    @builtin(global_invocation_id) id: vec3<u32>,
+    @builtin(num_workgroups) workgroups: vec3<u32>,
+    @builtin(local_invocation_index) local: u32,
+) {
+    let index = flat_index(id, workgroups);
+    let total = cell_count();
+    let in_range = index < total;
+    var center = vec3<f32>(0.0);
+    var cell = vec3<i32>(0);
+    if in_range {
+        center = centers[index].xyz;
+        cell = cell_coordinates(index);
+    }
+
+    // Invocations past the end still load tiles and reach the barriers
+    var acceleration = vec3<f32>(0.0);
+    for (var tile = 0u; tile < total; tile += WORKGROUP_SIZE) {
+        let load = tile + local;
+        if load < total {
+            tile_centers[local] = centers[load];
+        }
+        workgroupBarrier();
+        let loaded = min(WORKGROUP_SIZE, total - tile);
+        for (var k = 0u; k < loaded; k++) {
+            // Neighbors are applied particle by particle, empty cells have no mass
+            let other = cell_coordinates(tile + k);
+            if any(abs(other - cell) > vec3<i32>(1)) {
+                acceleration += attraction(tile_centers[k].xyz - center, tile_centers[k].w);
+            }
+        }
+        workgroupBarrier();
+    }
+    if in_range {
+        far_fields[index] = vec4<f32>(acceleration, 0.0);
+    }
+}
+
+// Accelerates every particle with the far field of its cell and the centers of mass around it
+@compute @workgroup_size(64)
+fn apply(
+    @builtin(global_invocation_id) id: vec3<u32>,
+    @builtin(num_workgroups) workgroups: vec3<u32>,
+) {
+    let index = flat_index(id, workgroups);
+    if index >= params.count {
+        return;
+    }
+    let grid = grid();
+    let position = positions[index].xyz;
+    let cell = cell_of(grid, position);
+    let own = cell_index(cell);
+    var acceleration = far_fields[own].xyz;
+    for (var i = 0u; i < 27u; i++) {
+        let neighbor = cell + vec3<i32>(vec3<u32>(i % 3u, i / 3u % 3u, i / 9u)) - 1;
+        if any(neighbor < vec3<i32>(0)) || any(neighbor >= vec3<i32>(i32(params.grid_size))) {
+            continue;
+        }
+        let neighbor_index = cell_index(neighbor);
+        var center = centers[neighbor_index];
+        if neighbor_index == own {
+            // The particle doesn't attract itself
+            let mass = center.w - 1.0;
+            center = vec4<f32>((center.xyz * center.w - position) / max(mass, 1.0), mass);
+        }
+        acceleration += attraction(center.xyz - position, center.w);
+    }
+    let speed = unpack_speed(speeds[index]);
+    let step = params.gravity * params.time_scale * params.dt;
+    speeds[index] = pack_speed(vec4<f32>(speed.xyz + acceleration * step, speed.w));
+}
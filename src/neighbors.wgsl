@@ -0,0 +1,57 @@
+// Neighbor queries in the spatial hash built by spatial_hash.rs, included at the start of the
+// kernels that iterate neighbors, which bind `SpatialHash::neighbors_bind_group` to group 1:
+//
+//     let cell = hash_cell(position);
+//     for (var i = 0u; i < 27u; i++) {
+//         let range = cell_range(cell + neighbor_offset(i));
+//         for (var j = range.x; j < range.y; j++) {
+//             let other = sorted_indices[j];
+//         }
+//     }
+//
+// Cells sharing a key share their range, so a range may hold particles of far away cells, or be
+// visited twice within the same 27 cells.
+
+// Must match HashParams in spatial_hash.rs
+struct HashParams {
+    cell_size: f32,
+    table_size: u32,
+    count: u32,
+    _padding: u32,
+};
+
+@group(1) @binding(0)
+var<uniform> hash_params: HashParams;
+
+@group(1) @binding(1)
+var<storage, read> cell_starts: array<u32>;
+
+@group(1) @binding(2)
+var<storage, read> cell_counts: array<u32>;
+
+// Particle indices, sorted by key
+@group(1) @binding(3)
+var<storage, read> sorted_indices: array<u32>;
+
+// Must match hash_key in spatial_hash.wgsl
+fn hash_key(cell: vec3<i32>) -> u32 {
+    let c = bitcast<vec3<u32>>(cell);
+    return ((c.x * 73856093u) ^ (c.y * 19349663u) ^ (c.z * 83492791u))
+        & (hash_params.table_size - 1u);
+}
+
+fn hash_cell(position: vec3<f32>) -> vec3<i32> {
+    return vec3<i32>(floor(position / hash_params.cell_size));
+}
+
+// Offset of the `i`th of the 27 cells around and including a cell
+fn neighbor_offset(i: u32) -> vec3<i32> {
+    return vec3<i32>(vec3<u32>(i % 3u, i / 3u % 3u, i / 9u)) - 1;
+}
+
+// Start and end in `sorted_indices` of the particles in `cell`
+fn cell_range(cell: vec3<i32>) -> vec2<u32> {
+    let key = hash_key(cell);
+    let start = cell_starts[key];
+    return vec2<u32>(start, start + cell_counts[key]);
+}
@@ -0,0 +1,79 @@
+// Debug view of the obstacles, raymarched over the scene. Included after fullscreen.wgsl and
+// obstacles.wgsl.
+
+// Must match ViewParams in obstacles.rs
+struct ViewParams {
+    eye: vec4<f32>,
+    forward: vec4<f32>,
+    // Scaled to the edges of the screen
+    right: vec4<f32>,
+    up: vec4<f32>,
+    max_distance: f32,
+    count: u32,
+    _padding0: u32,
+    _padding1: u32,
+};
+
+@group(0) @binding(0)
+var<uniform> view: ViewParams;
+
+@group(0) @binding(1)
+var<storage, read> obstacles: array<Obstacle>;
+
+const MAX_STEPS: u32 = 128u;
+// Distance at which a ray hits an obstacle
+const HIT_DISTANCE: f32 = 0.5;
+const OPACITY: f32 = 0.4;
+
+fn scene_distance(position: vec3<f32>) -> vec2<f32> {
+    var closest = vec2<f32>(view.max_distance, 0.0);
+    for (var i = 0u; i < min(view.count, arrayLength(&obstacles)); i++) {
+        let distance = obstacle_distance(obstacles[i], position);
+        if distance < closest.x {
+            closest = vec2<f32>(distance, f32(i));
+        }
+    }
+    return closest;
+}
+
+fn ray_direction(ndc: vec2<f32>) -> vec3<f32> {
+    return normalize(view.forward.xyz + view.right.xyz * ndc.x + view.up.xyz * ndc.y);
+}
+
+// Distance along `direction` to the obstacle it hits and the obstacle's index, a negative
+// distance if it hits none
+fn march(direction: vec3<f32>) -> vec2<f32> {
+    var travelled = 0.0;
+    for (var step = 0u; step < MAX_STEPS && travelled < view.max_distance; step++) {
+        let closest = scene_distance(view.eye.xyz + direction * travelled);
+        if closest.x < HIT_DISTANCE {
+            return vec2<f32>(travelled, closest.y);
+        }
+        travelled += closest.x;
+    }
+    return vec2<f32>(-1.0, 0.0);
+}
+
+@fragment
+fn fs_main(in: FullscreenOutput) -> @location(0) vec4<f32> {
+    let direction = ray_direction(fullscreen_ndc(in.uv));
+    let hit = march(direction);
+    if hit.x < 0.0 {
+        discard;
+    }
+    let normal = obstacle_normal(obstacles[u32(hit.y)], view.eye.xyz + direction * hit.x);
+    // Lit from the camera, so every visible face shows
+    let light = 0.3 + 0.7 * abs(dot(normal, direction));
+    return vec4<f32>(vec3<f32>(0.9, 0.6, 0.3) * light, OPACITY);
+}
+
+// View-space depth of the obstacles, the far plane where there's none, for the soft particles
+@fragment
+fn fs_depth(in: FullscreenOutput) -> @location(0) vec4<f32> {
+    let direction = ray_direction(fullscreen_ndc(in.uv));
+    let hit = march(direction);
+    if hit.x < 0.0 {
+        return vec4<f32>(view.max_distance);
+    }
+    return vec4<f32>(hit.x * dot(direction, normalize(view.forward.xyz)));
+}
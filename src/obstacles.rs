@@ -0,0 +1,360 @@
+//! Static obstacles particles bounce off, described by signed distance functions.
+//!
+//! Scenes list them in `[obstacle]` sections, uploaded to the compute kernel as a storage buffer.
+//! Every substep, particles found inside an obstacle are pushed back to its surface and their speed
+//! reflected if still heading in. [`ObstacleView`] raymarches them over the scene to check where
+//! they are.
+
+use std::str::FromStr;
+
+use bytemuck::{Pod, Zeroable};
+use glam::{Vec3, Vec4};
+use wgpu::util::DeviceExt;
+
+#[cfg(feature = "post-processing")]
+use crate::{camera::Camera, shader_check, soft_particles};
+
+/// Obstacle functions and struct, for shaders binding their own array of [`Obstacle`].
+pub const OBSTACLES_WGSL: &str = include_str!("obstacles.wgsl");
+
+// Step of the central differences giving the normals. Must match NORMAL_EPSILON in obstacles.wgsl
+const NORMAL_EPSILON: f32 = 0.1;
+const DEFAULT_RADIUS: f32 = 100.0;
+
+#[repr(u32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ObstacleShape {
+    Sphere = 0,
+    /// Half space below the plane, which faces its normal
+    Plane = 1,
+    Box = 2,
+}
+
+impl FromStr for ObstacleShape {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "sphere" => Ok(ObstacleShape::Sphere),
+            "plane" => Ok(ObstacleShape::Plane),
+            "box" => Ok(ObstacleShape::Box),
+            _ => Err(()),
+        }
+    }
+}
+
+/// One obstacle, shared with obstacles.wgsl.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq, Pod, Zeroable)]
+pub struct Obstacle {
+    /// Center of spheres and boxes, a point of planes. w is unused
+    pub center: Vec4,
+    /// Radius of spheres in x, normal of planes, half size of boxes. w is unused
+    pub extent: Vec4,
+    /// [`ObstacleShape`]
+    pub shape: u32,
+    pub _padding: [u32; 3],
+}
+
+impl Obstacle {
+    /// An obstacle of `shape` at the origin, of the default size.
+    pub fn new(shape: ObstacleShape) -> Self {
+        let extent = match shape {
+            ObstacleShape::Sphere => Vec4::new(DEFAULT_RADIUS, 0.0, 0.0, 0.0),
+            ObstacleShape::Plane => Vec4::Y,
+            ObstacleShape::Box => Vec4::new(DEFAULT_RADIUS, DEFAULT_RADIUS, DEFAULT_RADIUS, 0.0),
+        };
+        Self {
+            center: Vec4::ZERO,
+            extent,
+            shape: shape as u32,
+            _padding: [0; 3],
+        }
+    }
+
+    /// Sets the radius of a sphere. Returns false if the obstacle isn't one.
+    pub fn set_radius(&mut self, radius: f32) -> bool {
+        if self.shape != ObstacleShape::Sphere as u32 {
+            return false;
+        }
+        self.extent.x = radius;
+        true
+    }
+
+    /// Sets the point parameter `name` of the shape. Returns false if the shape doesn't have it.
+    pub fn set_point(&mut self, name: &str, value: Vec3) -> bool {
+        let plane = self.shape == ObstacleShape::Plane as u32;
+        let sized = self.shape == ObstacleShape::Box as u32;
+        match name {
+            "center" if !plane => self.center = value.extend(0.0),
+            "point" if plane => self.center = value.extend(0.0),
+            "normal" if plane && value != Vec3::ZERO => self.extent = value.normalize().extend(0.0),
+            "half_size" if sized => self.extent = value.extend(0.0),
+            _ => return false,
+        }
+        true
+    }
+
+    /// Signed distance from `position` to the surface, negative inside. Must match
+    /// `obstacle_distance` in obstacles.wgsl.
+    pub fn distance(&self, position: Vec3) -> f32 {
+        let offset = position - self.center.truncate();
+        match self.shape {
+            shape if shape == ObstacleShape::Sphere as u32 => offset.length() - self.extent.x,
+            shape if shape == ObstacleShape::Plane as u32 => offset.dot(self.extent.truncate()),
+            _ => {
+                let q = offset.abs() - self.extent.truncate();
+                q.max(Vec3::ZERO).length() + q.max_element().min(0.0)
+            }
+        }
+    }
+
+    /// Direction out of the obstacle at `position`. Must match `obstacle_normal` in obstacles.wgsl.
+    pub fn normal(&self, position: Vec3) -> Vec3 {
+        let axis = |axis: Vec3| {
+            self.distance(position + axis * NORMAL_EPSILON)
+                - self.distance(position - axis * NORMAL_EPSILON)
+        };
+        Vec3::new(axis(Vec3::X), axis(Vec3::Y), axis(Vec3::Z)).normalize_or_zero()
+    }
+}
+
+/// Pushes `position` out of the `obstacles` it's inside of, reflecting `speed` off their
+/// surface. Must match the obstacles loop of `integrate` in compute_kernel.wgsl.
+pub fn bounce(obstacles: &[Obstacle], mut position: Vec3, speed: &mut Vec3) -> Vec3 {
+    for obstacle in obstacles {
+        let distance = obstacle.distance(position);
+        if distance < 0.0 {
+            let normal = obstacle.normal(position);
+            position -= normal * distance;
+            let into = speed.dot(normal);
+            if into < 0.0 {
+                *speed -= 2.0 * into * normal;
+            }
+        }
+    }
+    position
+}
+
+/// Storage buffer of `obstacles`, never empty so it can always be bound.
+pub fn create_buffer(device: &wgpu::Device, obstacles: &[Obstacle]) -> wgpu::Buffer {
+    let padding = [Obstacle::new(ObstacleShape::Sphere)];
+    device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some("Obstacle Buffer"),
+        contents: bytemuck::cast_slice(if obstacles.is_empty() {
+            &padding
+        } else {
+            obstacles
+        }),
+        usage: wgpu::BufferUsages::STORAGE,
+    })
+}
+
+// Must match ViewParams in obstacle_view.wgsl
+#[cfg(feature = "post-processing")]
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Pod, Zeroable)]
+struct ViewParams {
+    eye: Vec4,
+    forward: Vec4,
+    right: Vec4,
+    up: Vec4,
+    max_distance: f32,
+    count: u32,
+    _padding: [u32; 2],
+}
+
+/// Debug pass drawing the obstacles over the scene, seen from the camera.
+#[cfg(feature = "post-processing")]
+pub struct ObstacleView {
+    count: u32,
+    params_buffer: wgpu::Buffer,
+    // Kept alive for the bind group
+    _obstacle_buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+    pipeline: wgpu::RenderPipeline,
+    depth_pipeline: wgpu::RenderPipeline,
+}
+
+#[cfg(feature = "post-processing")]
+impl ObstacleView {
+    pub fn new(device: &wgpu::Device, format: wgpu::TextureFormat, obstacles: &[Obstacle]) -> Self {
+        let params_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Obstacle View Params Buffer"),
+            size: std::mem::size_of::<ViewParams>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let obstacle_buffer = create_buffer(device, obstacles);
+
+        let bind_group_layout_entries = [
+            wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 1,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Storage { read_only: true },
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+        ];
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Obstacle View Bind Group Layout"),
+            entries: &bind_group_layout_entries,
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Obstacle View Bind Group"),
+            layout: &bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: params_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: obstacle_buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        let source = view_shader_source();
+        let shader = shader_check::create_module(
+            device,
+            "Obstacle View Shader",
+            "obstacle_view.wgsl",
+            &source,
+        );
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Obstacle View Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let create_pipeline = |entry_point, target| {
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some("Obstacle View Pipeline"),
+                layout: Some(&pipeline_layout),
+                vertex: wgpu::VertexState {
+                    module: &shader,
+                    entry_point: "vs_fullscreen",
+                    buffers: &[],
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader,
+                    entry_point,
+                    targets: &[Some(target)],
+                }),
+                primitive: wgpu::PrimitiveState::default(),
+                depth_stencil: None,
+                multisample: wgpu::MultisampleState::default(),
+                multiview: None,
+            })
+        };
+        let pipeline = create_pipeline(
+            "fs_main",
+            wgpu::ColorTargetState {
+                format,
+                blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                write_mask: wgpu::ColorWrites::ALL,
+            },
+        );
+        let depth_pipeline =
+            create_pipeline("fs_depth", soft_particles::OBSTACLE_DEPTH_FORMAT.into());
+
+        #[cfg(feature = "guardrails")]
+        {
+            let reflection =
+                crate::guardrails::ShaderReflection::new("obstacle_view.wgsl", &source);
+            reflection.check_bind_group_layout(0, &bind_group_layout_entries);
+            reflection.check_struct_size("ViewParams", std::mem::size_of::<ViewParams>());
+            reflection.check_struct_size("Obstacle", std::mem::size_of::<Obstacle>());
+        }
+
+        Self {
+            count: obstacles.len() as u32,
+            params_buffer,
+            _obstacle_buffer: obstacle_buffer,
+            bind_group,
+            pipeline,
+            depth_pipeline,
+        }
+    }
+
+    /// Looks at the obstacles through `camera` from now on.
+    pub fn prepare(&self, queue: &wgpu::Queue, camera: &Camera) {
+        let forward = (camera.target - camera.eye).normalize_or_zero();
+        let half_height = (camera.fovy.to_radians() / 2.0).tan();
+        let right = forward.cross(camera.up).normalize_or_zero() * half_height * camera.aspect;
+        let up = right.cross(forward).normalize_or_zero() * half_height;
+        let params = ViewParams {
+            eye: camera.eye.extend(0.0),
+            forward: forward.extend(0.0),
+            right: right.extend(0.0),
+            up: up.extend(0.0),
+            // Shaders may assume there are no infinities, the largest float stands in for them
+            max_distance: camera.depth.zfar.min(f32::MAX),
+            count: self.count,
+            _padding: [0; 2],
+        };
+        queue.write_buffer(&self.params_buffer, 0, bytemuck::cast_slice(&[params]));
+    }
+
+    /// Draws the obstacles over `view`.
+    pub fn encode(&self, encoder: &mut wgpu::CommandEncoder, view: &wgpu::TextureView) {
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Obstacle View Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: true,
+                },
+            })],
+            depth_stencil_attachment: None,
+        });
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &self.bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+
+    /// Writes the view-space depth of the obstacles into `view`, of
+    /// [`soft_particles::OBSTACLE_DEPTH_FORMAT`].
+    pub fn encode_depth(&self, encoder: &mut wgpu::CommandEncoder, view: &wgpu::TextureView) {
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Obstacle Depth Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                    store: true,
+                },
+            })],
+            depth_stencil_attachment: None,
+        });
+        render_pass.set_pipeline(&self.depth_pipeline);
+        render_pass.set_bind_group(0, &self.bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
+
+/// The source of the shader drawing the obstacles, after the fullscreen triangle and their shared
+/// WGSL.
+#[cfg_attr(not(feature = "post-processing"), allow(dead_code))]
+pub fn view_shader_source() -> String {
+    crate::render_target::fullscreen_shader(&format!(
+        "{OBSTACLES_WGSL}\n{}",
+        include_str!("obstacle_view.wgsl")
+    ))
+}
@@ -0,0 +1,44 @@
+// Signed distances to the static obstacles of obstacles.rs, included at the start of the shaders
+// using them, which bind their own array of obstacles
+
+// Must match Obstacle in obstacles.rs
+struct Obstacle {
+    center: vec4<f32>,
+    extent: vec4<f32>,
+    shape: u32,
+    _padding0: u32,
+    _padding1: u32,
+    _padding2: u32,
+};
+
+// Must match ObstacleShape in obstacles.rs
+const SPHERE: u32 = 0u;
+const PLANE: u32 = 1u;
+const BOX: u32 = 2u;
+
+// Must match NORMAL_EPSILON in obstacles.rs
+const NORMAL_EPSILON: f32 = 0.1;
+
+// Must match Obstacle::distance in obstacles.rs
+fn obstacle_distance(obstacle: Obstacle, position: vec3<f32>) -> f32 {
+    let offset = position - obstacle.center.xyz;
+    if obstacle.shape == SPHERE {
+        return length(offset) - obstacle.extent.x;
+    }
+    if obstacle.shape == PLANE {
+        return dot(offset, obstacle.extent.xyz);
+    }
+    let q = abs(offset) - obstacle.extent.xyz;
+    return length(max(q, vec3<f32>(0.0))) + min(max(q.x, max(q.y, q.z)), 0.0);
+}
+
+// Must match Obstacle::normal in obstacles.rs
+fn obstacle_normal(obstacle: Obstacle, position: vec3<f32>) -> vec3<f32> {
+    let e = vec2<f32>(NORMAL_EPSILON, 0.0);
+    let gradient = vec3<f32>(
+        obstacle_distance(obstacle, position + e.xyy) - obstacle_distance(obstacle, position - e.xyy),
+        obstacle_distance(obstacle, position + e.yxy) - obstacle_distance(obstacle, position - e.yxy),
+        obstacle_distance(obstacle, position + e.yyx) - obstacle_distance(obstacle, position - e.yyx),
+    );
+    return select(vec3<f32>(0.0), normalize(gradient), length(gradient) > 0.0);
+}
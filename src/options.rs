@@ -0,0 +1,784 @@
+use std::path::PathBuf;
+
+use crate::{
+    adapters::{AdapterSelector, Backend},
+    boids,
+    distribution::Distribution,
+    emitter::EmitterShape,
+    frame_stats, golden,
+    memory_budget::ByteSize,
+    nbody,
+    pacing::PresentMode,
+    palette::SpawnPalette,
+    render_target,
+    schedule::Clock,
+    screensaver::ScrCommand,
+    search::Score,
+    sim_params::{CpuPath, SimMode},
+    state::{COMPUTE_ROW_WIDTH, MAX_WORKGROUP_SIZE},
+    surface_format::SurfaceFormat,
+};
+
+/// What to do, given as the first argument. Defaults to [`Command::Run`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Command {
+    /// Show the particles in a window
+    #[default]
+    Run,
+    /// Render `--frames` frames as fast as possible and print how long they took
+    Bench,
+    /// Render `--frames` frames offscreen, without a window or a display, to record, hash or
+    /// compare them
+    Headless,
+    /// Print the adapters and what they support
+    Info,
+}
+
+impl std::str::FromStr for Command {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "run" => Ok(Command::Run),
+            "bench" => Ok(Command::Bench),
+            "headless" => Ok(Command::Headless),
+            "info" => Ok(Command::Info),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Command line options.
+#[derive(Debug, Clone)]
+pub struct Options {
+    pub command: Command,
+    /// Grow or shrink the number of simulated particles to hold this frame rate
+    pub target_fps: Option<f32>,
+    /// Start frames at most this many times per second, pacing them on a fixed schedule
+    pub max_fps: Option<f32>,
+    /// How frames are presented, immediately unless given
+    pub present_mode: PresentMode,
+    /// Write depth from 1 at the near plane to 0 at the far one, for more precision far away
+    pub reverse_z: bool,
+    /// Record frames to this directory, or to this video file through ffmpeg
+    pub record: Option<PathBuf>,
+    /// Number of frames to record, benchmark or render headless before exiting
+    pub frames: Option<u32>,
+    /// Split each compute step in submissions of at most this many invocations, at least one row
+    /// of the kernel
+    pub max_invocations_per_submit: Option<u32>,
+    /// Run this many generations of the headless parameter search instead of opening a window
+    pub search: Option<u32>,
+    /// What the parameter search maximizes, set with --score or --score-command
+    pub score: Score,
+    /// Graphics API to use, instead of the best one available
+    pub backend: Option<Backend>,
+    /// Adapter to use, instead of the high performance one
+    pub adapter: Option<AdapterSelector>,
+    /// Surface format to use if supported, instead of the first sRGB one
+    pub surface_format: Option<SurfaceFormat>,
+    /// Print a hash of every rendered frame, with a fixed time step
+    pub frame_hash: bool,
+    /// Compare the last headless frame with this golden image, and fail if they differ
+    pub compare: Option<PathBuf>,
+    /// Fraction of the pixels allowed to differ from the golden image
+    pub threshold: f32,
+    /// Save the input events to this session file, with a fixed time step
+    pub record_input: Option<PathBuf>,
+    /// Replay the input events of this session file instead of the live ones, with a fixed time
+    /// step
+    pub replay: Option<PathBuf>,
+    /// Start from this scene file, and reload it and its appearance snippet whenever they change
+    pub watch: Option<PathBuf>,
+    /// Tile one independent particle system per scene file, instead of the main one. More scenes
+    /// can be dropped on the window, and Ctrl+W closes the one under the cursor
+    pub grid: Vec<PathBuf>,
+    /// Number of particles, instead of the scene's. Counts too large for a single storage binding
+    /// are simulated in chunks.
+    pub particles: Option<usize>,
+    /// Shape particles are spawned in, instead of the scene's
+    pub emitter: Option<EmitterShape>,
+    /// Built-in palette particles are spawned with, instead of the scene's
+    pub palette: Option<SpawnPalette>,
+    /// Show the points of this PLY or LAS file, centered and scaled to fit, instead of spawning
+    /// particles
+    #[cfg(feature = "asset-loaders")]
+    pub points: Option<PathBuf>,
+    /// Start from the particles of this CSV or NumPy file, see particle_data.rs
+    #[cfg(feature = "asset-loaders")]
+    pub import: Option<PathBuf>,
+    /// Spawn the particles over the surface of this OBJ mesh, see mesh_emitter.rs
+    #[cfg(feature = "asset-loaders")]
+    pub emit_mesh: Option<PathBuf>,
+    /// Spawn the particles in this built-in distribution, see distribution.rs
+    pub distribution: Option<Distribution>,
+    /// Write the particles to this CSV or NumPy file when exiting
+    #[cfg(feature = "asset-loaders")]
+    pub export: Option<PathBuf>,
+    /// Change the palette, background and lighting over a day of this clock
+    pub schedule: Option<Clock>,
+    /// Keyframes of the day cycle, instead of the built-in ones
+    pub schedule_file: Option<PathBuf>,
+    /// Fly the camera along the keyframes of this RON file
+    pub camera_path: Option<PathBuf>,
+    /// Orbit the camera and cycle through the presets after this many seconds without input
+    pub idle: Option<f32>,
+    /// Write spans around the phases of every frame to this Chrome trace file
+    pub trace_json: Option<PathBuf>,
+    /// Write the CPU time, GPU time and particle count of every frame to this CSV file on exit
+    pub frame_log: Option<PathBuf>,
+    /// Stream this percentage of the particles back from the GPU every frame
+    pub sample: Option<f32>,
+    /// Particles whose trajectories are written to `track_csv`
+    pub track: Vec<usize>,
+    /// Write the position and speed of the tracked particles to this CSV file every frame
+    pub track_csv: Option<PathBuf>,
+    /// Write the positions of the particles to this PC2 point cache, see point_cache.rs
+    pub point_cache: Option<PathBuf>,
+    /// Frames between the samples of the point cache
+    pub point_cache_interval: u32,
+    /// What moves the particles
+    pub sim: SimMode,
+    /// Start on the CPU backend, moving the particles along this path
+    pub cpu_sim: Option<CpuPath>,
+    /// Boids steered together in workgroup memory, 0 to steer each on its own
+    pub boids_tile_size: u32,
+    /// Cells per side of the grid large n-body runs are approximated with, 0 for exact gravity
+    pub nbody_grid: u32,
+    /// Particles attracted together in workgroup memory, 0 to attract each on its own
+    pub nbody_tile_size: u32,
+    /// Invocations per workgroup of the compute passes, a power of two
+    pub workgroup_size: Option<u32>,
+    /// Time the bench with each workgroup size in turn, given as `--workgroup-size auto`
+    pub tune_workgroup_size: bool,
+    /// Device memory the particle buffers may take, in bytes, fewer particles are spawned past it
+    pub max_memory: Option<u64>,
+    /// Draw the particles at this scale of the window's resolution, upsampled or downsampled to it
+    pub render_scale: f32,
+    /// Draw the particles at half resolution and upsample them, for dense scenes on large displays
+    #[cfg(feature = "post-processing")]
+    pub half_res: bool,
+    /// Draw the particles in alternating quads of a checkerboard every frame, reconstructing the
+    /// others from the previous frame
+    #[cfg(feature = "post-processing")]
+    pub checkerboard: bool,
+    /// Blur the particles along their motion, with the shutter open this fraction of each frame
+    #[cfg(feature = "post-processing")]
+    pub motion_blur: Option<f32>,
+    /// Blend every frame with the previous ones, smoothing the shimmer of small particles
+    #[cfg(feature = "post-processing")]
+    pub taa: bool,
+    /// Grade the final colors through the 3D LUT of this .cube file
+    #[cfg(all(feature = "post-processing", feature = "asset-loaders"))]
+    pub lut: Option<PathBuf>,
+    /// Fade the particles over this distance in front of the obstacles while they're shown
+    #[cfg(feature = "post-processing")]
+    pub soft_particles: Option<f32>,
+    /// Seconds between frame stats in the log, `None` to never log them
+    pub stats_interval: Option<f32>,
+    /// Draw the particles a frame behind, letting the GPU overlap the drawing with the compute
+    /// passes
+    pub overlap: bool,
+    /// Record the compute kernel and the render passes one after the other, instead of on two
+    /// threads
+    pub serial_encoding: bool,
+    /// Generate the quads in the vertex shader and read the instances from storage buffers, instead
+    /// of binding them as vertex buffers
+    pub vertex_pulling: bool,
+    /// Command Windows passes to `.scr` screensavers
+    pub scr: Option<ScrCommand>,
+    /// Serve metrics for Prometheus on this address
+    #[cfg(feature = "metrics")]
+    pub metrics_listen: Option<std::net::SocketAddr>,
+    /// Append metrics to this CSV file
+    #[cfg(feature = "metrics")]
+    pub metrics_csv: Option<PathBuf>,
+    /// Serve JSON stats and take commands on this address, see telemetry.rs. `--telemetry-port`
+    /// only listens on the loopback interface
+    #[cfg(feature = "metrics")]
+    pub telemetry_listen: Option<std::net::SocketAddr>,
+    /// Take the emission and global forces from this Rhai script, reloaded whenever it changes
+    #[cfg(feature = "scripting")]
+    pub script: Option<PathBuf>,
+    /// Drive the simulation from the default audio input, see audio.rs
+    #[cfg(feature = "audio")]
+    pub audio: bool,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum OptionsError {
+    #[error("missing value for {0}")]
+    MissingValue(String),
+    #[error("invalid value `{value}` for {option}")]
+    InvalidValue { option: String, value: String },
+    #[error("unknown option {0}")]
+    Unknown(String),
+    #[error("{0} requires {1}")]
+    Requires(&'static str, &'static str),
+    #[error("{0} can't be used with {1}")]
+    Conflicts(&'static str, &'static str),
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            command: Command::default(),
+            target_fps: None,
+            max_fps: None,
+            present_mode: PresentMode::default(),
+            reverse_z: false,
+            record: None,
+            frames: None,
+            max_invocations_per_submit: None,
+            search: None,
+            score: Score::Combined,
+            backend: None,
+            adapter: None,
+            surface_format: None,
+            frame_hash: false,
+            compare: None,
+            record_input: None,
+            replay: None,
+            threshold: golden::DEFAULT_THRESHOLD,
+            watch: None,
+            grid: vec![],
+            particles: None,
+            emitter: None,
+            palette: None,
+            #[cfg(feature = "asset-loaders")]
+            points: None,
+            #[cfg(feature = "asset-loaders")]
+            import: None,
+            #[cfg(feature = "asset-loaders")]
+            emit_mesh: None,
+            distribution: None,
+            #[cfg(feature = "asset-loaders")]
+            export: None,
+            schedule: None,
+            schedule_file: None,
+            camera_path: None,
+            idle: None,
+            trace_json: None,
+            frame_log: None,
+            sample: None,
+            track: vec![],
+            track_csv: None,
+            point_cache: None,
+            point_cache_interval: 1,
+            sim: SimMode::default(),
+            cpu_sim: None,
+            boids_tile_size: boids::DEFAULT_TILE_SIZE,
+            nbody_grid: nbody::DEFAULT_GRID_SIZE,
+            nbody_tile_size: nbody::DEFAULT_TILE_SIZE,
+            workgroup_size: None,
+            tune_workgroup_size: false,
+            max_memory: None,
+            render_scale: 1.0,
+            #[cfg(feature = "post-processing")]
+            half_res: false,
+            #[cfg(feature = "post-processing")]
+            checkerboard: false,
+            #[cfg(feature = "post-processing")]
+            motion_blur: None,
+            #[cfg(feature = "post-processing")]
+            taa: false,
+            #[cfg(all(feature = "post-processing", feature = "asset-loaders"))]
+            lut: None,
+            #[cfg(feature = "post-processing")]
+            soft_particles: None,
+            stats_interval: Some(frame_stats::DEFAULT_LOG_INTERVAL),
+            overlap: false,
+            serial_encoding: false,
+            vertex_pulling: false,
+            scr: None,
+            #[cfg(feature = "metrics")]
+            metrics_listen: None,
+            #[cfg(feature = "metrics")]
+            metrics_csv: None,
+            #[cfg(feature = "metrics")]
+            telemetry_listen: None,
+            #[cfg(feature = "scripting")]
+            script: None,
+            #[cfg(feature = "audio")]
+            audio: false,
+        }
+    }
+}
+
+impl Options {
+    pub fn from_args(args: impl Iterator<Item = String>) -> Result<Self, OptionsError> {
+        let mut options = Options::default();
+        let mut args = args.peekable();
+        if let Some(command) = args.peek().and_then(|arg| arg.parse().ok()) {
+            options.command = command;
+            args.next();
+        }
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--target-fps" => {
+                    let fps: f32 = parse_value(&arg, args.next())?;
+                    if fps <= 0.0 {
+                        return Err(OptionsError::InvalidValue {
+                            option: arg,
+                            value: fps.to_string(),
+                        });
+                    }
+                    options.target_fps = Some(fps);
+                }
+                "--max-fps" => {
+                    let fps: f32 = parse_value(&arg, args.next())?;
+                    if !(fps > 0.0 && fps.is_finite()) {
+                        return Err(OptionsError::InvalidValue {
+                            option: arg,
+                            value: fps.to_string(),
+                        });
+                    }
+                    options.max_fps = Some(fps);
+                }
+                "--present-mode" => {
+                    options.present_mode = parse_value(&arg, args.next())?;
+                }
+                "--record" => {
+                    options.record = Some(parse_value(&arg, args.next())?);
+                }
+                "--frames" => {
+                    let frames: u32 = parse_value(&arg, args.next())?;
+                    if frames == 0 {
+                        return Err(OptionsError::InvalidValue {
+                            option: arg,
+                            value: frames.to_string(),
+                        });
+                    }
+                    options.frames = Some(frames);
+                }
+                "--max-invocations-per-submit" => {
+                    let max: u32 = parse_value(&arg, args.next())?;
+                    // Submissions hold whole rows of the kernel
+                    if max < COMPUTE_ROW_WIDTH {
+                        return Err(OptionsError::InvalidValue {
+                            option: arg,
+                            value: max.to_string(),
+                        });
+                    }
+                    options.max_invocations_per_submit = Some(max);
+                }
+                "--search" => {
+                    options.search = Some(parse_value(&arg, args.next())?);
+                }
+                "--score" => {
+                    options.score = parse_value(&arg, args.next())?;
+                }
+                "--score-command" => {
+                    options.score = Score::Command(parse_value(&arg, args.next())?);
+                }
+                "--backend" => {
+                    options.backend = Some(parse_value(&arg, args.next())?);
+                }
+                "--adapter" => {
+                    options.adapter = Some(parse_value(&arg, args.next())?);
+                }
+                "--surface-format" => {
+                    options.surface_format = Some(parse_value(&arg, args.next())?);
+                }
+                // Same as `info`, from before there were commands
+                "--list-adapters" => options.command = Command::Info,
+                "--frame-hash" => options.frame_hash = true,
+                "--compare" => {
+                    options.compare = Some(parse_value(&arg, args.next())?);
+                }
+                "--record-input" => {
+                    options.record_input = Some(parse_value(&arg, args.next())?);
+                }
+                "--replay" => {
+                    options.replay = Some(parse_value(&arg, args.next())?);
+                }
+                "--threshold" => {
+                    let threshold: f32 = parse_value(&arg, args.next())?;
+                    if !(0.0..=1.0).contains(&threshold) {
+                        return Err(OptionsError::InvalidValue {
+                            option: arg,
+                            value: threshold.to_string(),
+                        });
+                    }
+                    options.threshold = threshold;
+                }
+                "--render-scale" => {
+                    let scale: f32 = parse_value(&arg, args.next())?;
+                    if !(render_target::MIN_SCALE..=render_target::MAX_SCALE).contains(&scale) {
+                        return Err(OptionsError::InvalidValue {
+                            option: arg,
+                            value: scale.to_string(),
+                        });
+                    }
+                    options.render_scale = scale;
+                }
+                #[cfg(feature = "post-processing")]
+                "--half-res" => options.half_res = true,
+                #[cfg(feature = "post-processing")]
+                "--checkerboard" => options.checkerboard = true,
+                #[cfg(feature = "post-processing")]
+                "--motion-blur" => {
+                    let shutter: f32 = parse_value(&arg, args.next())?;
+                    if !(shutter > 0.0 && shutter <= 1.0) {
+                        return Err(OptionsError::InvalidValue {
+                            option: arg,
+                            value: shutter.to_string(),
+                        });
+                    }
+                    options.motion_blur = Some(shutter);
+                }
+                #[cfg(feature = "post-processing")]
+                "--taa" => options.taa = true,
+                #[cfg(all(feature = "post-processing", feature = "asset-loaders"))]
+                "--lut" => options.lut = Some(parse_value(&arg, args.next())?),
+                #[cfg(feature = "post-processing")]
+                "--soft-particles" => {
+                    let fade_distance: f32 = parse_value(&arg, args.next())?;
+                    if !(fade_distance > 0.0 && fade_distance.is_finite()) {
+                        return Err(OptionsError::InvalidValue {
+                            option: arg,
+                            value: fade_distance.to_string(),
+                        });
+                    }
+                    options.soft_particles = Some(fade_distance);
+                }
+                "--overlap" => options.overlap = true,
+                "--reverse-z" => options.reverse_z = true,
+                "--serial-encoding" => options.serial_encoding = true,
+                "--vertex-pulling" => options.vertex_pulling = true,
+                "--quiet" => options.stats_interval = None,
+                "--stats-interval" => {
+                    let interval: f32 = parse_value(&arg, args.next())?;
+                    if !(interval >= 0.0 && interval.is_finite()) {
+                        return Err(OptionsError::InvalidValue {
+                            option: arg,
+                            value: interval.to_string(),
+                        });
+                    }
+                    options.stats_interval = Some(interval);
+                }
+                "--particles" => {
+                    let particles: usize = parse_value(&arg, args.next())?;
+                    if particles == 0 {
+                        return Err(OptionsError::InvalidValue {
+                            option: arg,
+                            value: particles.to_string(),
+                        });
+                    }
+                    options.particles = Some(particles);
+                }
+                "--emitter" => {
+                    options.emitter = Some(parse_value(&arg, args.next())?);
+                }
+                "--palette" => {
+                    options.palette = Some(parse_value(&arg, args.next())?);
+                }
+                "--schedule" => {
+                    options.schedule = Some(parse_value(&arg, args.next())?);
+                }
+                "--schedule-file" => {
+                    options.schedule_file = Some(parse_value(&arg, args.next())?);
+                }
+                #[cfg(feature = "asset-loaders")]
+                "--points" => {
+                    options.points = Some(parse_value(&arg, args.next())?);
+                }
+                #[cfg(feature = "asset-loaders")]
+                "--import" => {
+                    options.import = Some(parse_value(&arg, args.next())?);
+                }
+                #[cfg(feature = "asset-loaders")]
+                "--emit-mesh" => {
+                    options.emit_mesh = Some(parse_value(&arg, args.next())?);
+                }
+                "--distribution" => {
+                    options.distribution = Some(parse_value(&arg, args.next())?);
+                }
+                #[cfg(feature = "asset-loaders")]
+                "--export" => {
+                    options.export = Some(parse_value(&arg, args.next())?);
+                }
+                "--camera-path" => {
+                    options.camera_path = Some(parse_value(&arg, args.next())?);
+                }
+                "--idle" => {
+                    let idle: f32 = parse_value(&arg, args.next())?;
+                    if idle < 0.0 {
+                        return Err(OptionsError::InvalidValue {
+                            option: arg,
+                            value: idle.to_string(),
+                        });
+                    }
+                    options.idle = Some(idle);
+                }
+                "--sample" => {
+                    let percent: f32 = parse_value(&arg, args.next())?;
+                    if percent <= 0.0 || percent > 100.0 {
+                        return Err(OptionsError::InvalidValue {
+                            option: arg,
+                            value: percent.to_string(),
+                        });
+                    }
+                    options.sample = Some(percent);
+                }
+                "--track" => {
+                    let particles: String = parse_value(&arg, args.next())?;
+                    options.track = particles
+                        .split(',')
+                        .map(|index| parse_value(&arg, Some(index.to_owned())))
+                        .collect::<Result<_, _>>()?;
+                }
+                "--trace-json" => {
+                    options.trace_json = Some(parse_value(&arg, args.next())?);
+                }
+                "--frame-log" => {
+                    options.frame_log = Some(parse_value(&arg, args.next())?);
+                }
+                "--track-csv" => {
+                    options.track_csv = Some(parse_value(&arg, args.next())?);
+                }
+                "--point-cache" => {
+                    options.point_cache = Some(parse_value(&arg, args.next())?);
+                }
+                "--point-cache-interval" => {
+                    let interval: u32 = parse_value(&arg, args.next())?;
+                    if interval == 0 {
+                        return Err(OptionsError::InvalidValue {
+                            option: arg,
+                            value: interval.to_string(),
+                        });
+                    }
+                    options.point_cache_interval = interval;
+                }
+                "--sim" => {
+                    options.sim = parse_value(&arg, args.next())?;
+                }
+                "--cpu-sim" => {
+                    options.cpu_sim = Some(parse_value(&arg, args.next())?);
+                }
+                "--boids-tile-size" => {
+                    options.boids_tile_size = parse_value(&arg, args.next())?;
+                }
+                "--nbody-grid" => {
+                    let grid: u32 = parse_value(&arg, args.next())?;
+                    if grid > nbody::MAX_GRID_SIZE {
+                        return Err(OptionsError::InvalidValue {
+                            option: arg,
+                            value: grid.to_string(),
+                        });
+                    }
+                    options.nbody_grid = grid;
+                }
+                "--nbody-tile-size" => {
+                    options.nbody_tile_size = parse_value(&arg, args.next())?;
+                }
+                "--workgroup-size" => {
+                    let value: String = parse_value(&arg, args.next())?;
+                    if value == "auto" {
+                        options.tune_workgroup_size = true;
+                    } else {
+                        let size: u32 = parse_value(&arg, Some(value))?;
+                        if !size.is_power_of_two() || size > MAX_WORKGROUP_SIZE {
+                            return Err(OptionsError::InvalidValue {
+                                option: arg,
+                                value: size.to_string(),
+                            });
+                        }
+                        options.workgroup_size = Some(size);
+                    }
+                }
+                "--max-memory" => {
+                    let ByteSize(max_memory) = parse_value(&arg, args.next())?;
+                    options.max_memory = Some(max_memory);
+                }
+                "--grid" => {
+                    let scenes: String = parse_value(&arg, args.next())?;
+                    options.grid = scenes.split(',').map(PathBuf::from).collect();
+                }
+                "--watch" => {
+                    options.watch = Some(parse_value(&arg, args.next())?);
+                }
+                #[cfg(feature = "metrics")]
+                "--metrics-listen" => {
+                    options.metrics_listen = Some(parse_value(&arg, args.next())?);
+                }
+                #[cfg(feature = "metrics")]
+                "--metrics-csv" => {
+                    options.metrics_csv = Some(parse_value(&arg, args.next())?);
+                }
+                #[cfg(feature = "metrics")]
+                "--telemetry-port" => {
+                    let port: u16 = parse_value(&arg, args.next())?;
+                    options.telemetry_listen = Some((std::net::Ipv4Addr::LOCALHOST, port).into());
+                }
+                #[cfg(feature = "metrics")]
+                "--telemetry-listen" => {
+                    options.telemetry_listen = Some(parse_value(&arg, args.next())?);
+                }
+                #[cfg(feature = "scripting")]
+                "--script" => {
+                    options.script = Some(parse_value(&arg, args.next())?);
+                }
+                #[cfg(feature = "audio")]
+                "--audio" => options.audio = true,
+                _ => match ScrCommand::parse(&arg) {
+                    Some(command) => {
+                        // The preview window handle comes as a separate argument
+                        if command == ScrCommand::Preview && !arg.contains(':') {
+                            args.next();
+                        }
+                        options.scr = Some(command);
+                    }
+                    None => return Err(OptionsError::Unknown(arg)),
+                },
+            }
+        }
+
+        // The grid replaces the main particle system, reloading it wouldn't show
+        if !options.grid.is_empty() && options.watch.is_some() {
+            return Err(OptionsError::Conflicts("--grid", "--watch"));
+        }
+        #[cfg(feature = "scripting")]
+        if !options.grid.is_empty() && options.script.is_some() {
+            return Err(OptionsError::Conflicts("--script", "--grid"));
+        }
+        if !options.grid.is_empty() && options.sample.is_some() {
+            return Err(OptionsError::Conflicts("--sample", "--grid"));
+        }
+        if !options.grid.is_empty() && options.sim != SimMode::default() {
+            return Err(OptionsError::Conflicts("--sim", "--grid"));
+        }
+        if !options.grid.is_empty() && options.cpu_sim.is_some() {
+            return Err(OptionsError::Conflicts("--cpu-sim", "--grid"));
+        }
+        if !options.grid.is_empty() && options.track_csv.is_some() {
+            return Err(OptionsError::Conflicts("--track-csv", "--grid"));
+        }
+        if !options.grid.is_empty() && options.point_cache.is_some() {
+            return Err(OptionsError::Conflicts("--point-cache", "--grid"));
+        }
+        if options.point_cache_interval != 1 && options.point_cache.is_none() {
+            return Err(OptionsError::Requires(
+                "--point-cache-interval",
+                "--point-cache",
+            ));
+        }
+        // The particle count would shrink forever trying to reach it
+        if let (Some(target_fps), Some(max_fps)) = (options.target_fps, options.max_fps) {
+            if target_fps > max_fps {
+                return Err(OptionsError::Conflicts("--target-fps", "a lower --max-fps"));
+            }
+        }
+        if options.boids_tile_size != boids::DEFAULT_TILE_SIZE && options.sim != SimMode::Boids {
+            return Err(OptionsError::Requires("--boids-tile-size", "--sim boids"));
+        }
+        if options.nbody_grid != nbody::DEFAULT_GRID_SIZE && options.sim != SimMode::Nbody {
+            return Err(OptionsError::Requires("--nbody-grid", "--sim nbody"));
+        }
+        if options.nbody_tile_size != nbody::DEFAULT_TILE_SIZE && options.sim != SimMode::Nbody {
+            return Err(OptionsError::Requires("--nbody-tile-size", "--sim nbody"));
+        }
+        if options.tune_workgroup_size && options.command != Command::Bench {
+            return Err(OptionsError::Requires("--workgroup-size auto", "bench"));
+        }
+        // Only the GPU simulation has workgroups to time
+        if options.tune_workgroup_size && options.cpu_sim.is_some() {
+            return Err(OptionsError::Conflicts(
+                "--workgroup-size auto",
+                "--cpu-sim",
+            ));
+        }
+        match (options.track.is_empty(), options.track_csv.is_some()) {
+            (false, false) => return Err(OptionsError::Requires("--track", "--track-csv")),
+            (true, true) => return Err(OptionsError::Requires("--track-csv", "--track")),
+            _ => {}
+        }
+
+        if options.schedule_file.is_some() && options.schedule.is_none() {
+            return Err(OptionsError::Requires("--schedule-file", "--schedule"));
+        }
+
+        if options.compare.is_some() && options.command != Command::Headless {
+            return Err(OptionsError::Requires("--compare", "headless"));
+        }
+        if options.record_input.is_some() && options.replay.is_some() {
+            return Err(OptionsError::Conflicts("--record-input", "--replay"));
+        }
+        #[cfg(feature = "asset-loaders")]
+        {
+            if options.points.is_some() && options.import.is_some() {
+                return Err(OptionsError::Conflicts("--points", "--import"));
+            }
+            if options.emit_mesh.is_some() {
+                if options.points.is_some() {
+                    return Err(OptionsError::Conflicts("--emit-mesh", "--points"));
+                }
+                if options.import.is_some() {
+                    return Err(OptionsError::Conflicts("--emit-mesh", "--import"));
+                }
+            }
+        }
+        if options.distribution.is_some() {
+            let sources = [
+                #[cfg(feature = "asset-loaders")]
+                ("--points", options.points.is_some()),
+                #[cfg(feature = "asset-loaders")]
+                ("--import", options.import.is_some()),
+                #[cfg(feature = "asset-loaders")]
+                ("--emit-mesh", options.emit_mesh.is_some()),
+                ("--grid", !options.grid.is_empty()),
+            ];
+            if let Some((other, _)) = sources.into_iter().find(|(_, given)| *given) {
+                return Err(OptionsError::Conflicts("--distribution", other));
+            }
+        }
+        if options.threshold != golden::DEFAULT_THRESHOLD && options.compare.is_none() {
+            return Err(OptionsError::Requires("--threshold", "--compare"));
+        }
+        if options.command == Command::Headless {
+            // Without a window, there's no input to record
+            if options.record_input.is_some() {
+                return Err(OptionsError::Conflicts("--record-input", "headless"));
+            }
+            if options.record.is_none() && !options.frame_hash && options.compare.is_none() {
+                return Err(OptionsError::Requires(
+                    "headless",
+                    "--record, --frame-hash or --compare",
+                ));
+            }
+            if options.frames.is_none() {
+                return Err(OptionsError::Requires("headless", "--frames"));
+            }
+        }
+
+        match (&options.record, options.frames) {
+            (Some(_), None) => Err(OptionsError::Requires("--record", "--frames")),
+            (None, Some(_)) if options.command == Command::Run => {
+                Err(OptionsError::Requires("--frames", "--record"))
+            }
+            _ => Ok(options),
+        }
+    }
+
+    /// Interactive runs restore and save the [`Settings`](crate::settings::Settings), recordings,
+    /// frame hashes, benchmarks and screensavers always start the same way.
+    pub fn remembers_settings(&self) -> bool {
+        self.command == Command::Run
+            && self.record.is_none()
+            && self.record_input.is_none()
+            && self.replay.is_none()
+            && !self.frame_hash
+            && self.scr.is_none()
+    }
+}
+
+fn parse_value<T: std::str::FromStr>(
+    option: &str,
+    value: Option<String>,
+) -> Result<T, OptionsError> {
+    let value = value.ok_or_else(|| OptionsError::MissingValue(option.to_owned()))?;
+    value.parse().map_err(|_| OptionsError::InvalidValue {
+        option: option.to_owned(),
+        value,
+    })
+}
@@ -0,0 +1,95 @@
+//! Lets the compute passes of a frame overlap with the drawing of the previous one.
+//!
+//! The compute passes move the particles in place, so drawing them has to wait until they are
+//! done. While enabled, the particles are drawn from a snapshot of their positions instead, copied
+//! at the end of every frame: the draws no longer touch the buffer the next compute passes write,
+//! and the GPU is free to run both at once, at the cost of showing positions one frame late.
+//!
+//! `bench --overlap` measures the win: it times the frames without the overlap, then with it, and
+//! compares how long the GPU timestamps of the passes span a frame in both.
+
+pub struct RenderSnapshot {
+    buffer: wgpu::Buffer,
+    // Of the positions as drawn, when they are a buffer of their own, see vertex.rs
+    drawn_buffer: Option<wgpu::Buffer>,
+    // Until the first copy the snapshot holds nothing to draw
+    copied: bool,
+}
+
+impl RenderSnapshot {
+    pub fn new(device: &wgpu::Device, size: wgpu::BufferAddress) -> Self {
+        Self {
+            buffer: Self::create_buffer(device, size),
+            drawn_buffer: None,
+            copied: false,
+        }
+    }
+
+    /// Positions to draw from, once [`RenderSnapshot::prepare`]d.
+    pub fn buffer(&self) -> &wgpu::Buffer {
+        &self.buffer
+    }
+
+    /// Positions the particle pipelines fetch as vertices, if the snapshot holds drawn positions.
+    pub fn drawn_buffer(&self) -> Option<&wgpu::Buffer> {
+        self.drawn_buffer.as_ref()
+    }
+
+    /// Makes sure the snapshot holds every position of `position_buffer`, and of
+    /// `drawn_position_buffer` if given, copying them right away if it just got created or
+    /// resized. Must be called before the draws.
+    pub fn prepare(
+        &mut self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        position_buffer: &wgpu::Buffer,
+        drawn_position_buffer: Option<&wgpu::Buffer>,
+    ) {
+        if self.buffer.size() != position_buffer.size() {
+            self.buffer = Self::create_buffer(device, position_buffer.size());
+            self.copied = false;
+        }
+        let drawn_size = drawn_position_buffer.map(wgpu::Buffer::size);
+        if self.drawn_buffer.as_ref().map(wgpu::Buffer::size) != drawn_size {
+            self.drawn_buffer = drawn_size.map(|size| Self::create_buffer(device, size));
+            self.copied = false;
+        }
+        if !self.copied {
+            self.copy(encoder, position_buffer, drawn_position_buffer);
+        }
+    }
+
+    /// Copies the positions drawn next frame. Must be encoded after the draws.
+    pub fn copy(
+        &mut self,
+        encoder: &mut wgpu::CommandEncoder,
+        position_buffer: &wgpu::Buffer,
+        drawn_position_buffer: Option<&wgpu::Buffer>,
+    ) {
+        encoder.copy_buffer_to_buffer(position_buffer, 0, &self.buffer, 0, self.buffer.size());
+        if let (Some(drawn_position_buffer), Some(drawn_buffer)) =
+            (drawn_position_buffer, &self.drawn_buffer)
+        {
+            encoder.copy_buffer_to_buffer(
+                drawn_position_buffer,
+                0,
+                drawn_buffer,
+                0,
+                drawn_buffer.size(),
+            );
+        }
+        self.copied = true;
+    }
+
+    fn create_buffer(device: &wgpu::Device, size: wgpu::BufferAddress) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Render Snapshot Buffer"),
+            size,
+            usage: wgpu::BufferUsages::VERTEX
+                | wgpu::BufferUsages::COPY_DST
+                | wgpu::BufferUsages::COPY_SRC
+                | wgpu::BufferUsages::STORAGE,
+            mapped_at_creation: false,
+        })
+    }
+}
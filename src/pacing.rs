@@ -0,0 +1,155 @@
+//! Frame pacing for the "vsync off but paced" mode, and the frame rate cap of `--max-fps`.
+//!
+//! The surface keeps presenting immediately, or in the mode of `--present-mode`, but frames are
+//! only started once per refresh of the monitor the window is on, which avoids both tearing-heavy
+//! uncapped rendering and the latency of a vsync queue. With a cap, frames are started at most at
+//! that rate, and at the slower of the two when both apply.
+//!
+//! Frames are scheduled at fixed intervals from the first rather than from the last, so late
+//! wake-ups don't add up. The event loop sleeps until shortly before the next frame and the rest
+//! is spun, timers being too coarse for high frame rates.
+
+use std::{
+    fmt::Display,
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+use winit::monitor::MonitorHandle;
+
+// Left to spin before each frame, about the resolution of the OS timers
+const SPIN: Duration = Duration::from_millis(2);
+
+/// Present mode given with `--present-mode`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PresentMode(pub wgpu::PresentMode);
+
+impl Default for PresentMode {
+    /// Uncapped, paced by [`FramePacer`] if at all.
+    fn default() -> Self {
+        Self(wgpu::PresentMode::Immediate)
+    }
+}
+
+const NAMES: &[(&str, wgpu::PresentMode)] = &[
+    ("immediate", wgpu::PresentMode::Immediate),
+    ("mailbox", wgpu::PresentMode::Mailbox),
+    ("fifo", wgpu::PresentMode::Fifo),
+    ("fifo-relaxed", wgpu::PresentMode::FifoRelaxed),
+];
+
+impl FromStr for PresentMode {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        NAMES
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(s))
+            .map(|&(_, mode)| PresentMode(mode))
+            .ok_or(())
+    }
+}
+
+impl Display for PresentMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match NAMES.iter().find(|(_, mode)| *mode == self.0) {
+            Some((name, _)) => write!(f, "{name}"),
+            None => write!(f, "{:?}", self.0),
+        }
+    }
+}
+
+/// `requested` if the surface supports it, else FIFO, which every surface does.
+pub fn choose_present_mode(
+    supported: &[wgpu::PresentMode],
+    requested: PresentMode,
+) -> wgpu::PresentMode {
+    if supported.contains(&requested.0) {
+        return requested.0;
+    }
+    log::warn!("The surface doesn't present in {requested} mode, supported: {supported:?}");
+    wgpu::PresentMode::Fifo
+}
+
+pub struct FramePacer {
+    enabled: bool,
+    monitor: Option<MonitorHandle>,
+    refresh_rate_millihertz: Option<u32>,
+    // Frames per second never exceeded, paced or not
+    max_fps: Option<f32>,
+    next_frame: Instant,
+}
+
+impl FramePacer {
+    pub fn new(monitor: Option<MonitorHandle>, max_fps: Option<f32>) -> Self {
+        let mut pacer = Self {
+            enabled: false,
+            monitor: None,
+            refresh_rate_millihertz: None,
+            max_fps,
+            next_frame: Instant::now(),
+        };
+        pacer.set_monitor(monitor);
+        pacer
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        self.next_frame = Instant::now();
+    }
+
+    /// Refresh rate of the current monitor in Hz, if the platform reports it.
+    pub fn refresh_rate(&self) -> Option<f32> {
+        self.refresh_rate_millihertz.map(|mhz| mhz as f32 / 1000.0)
+    }
+
+    /// Must be called whenever the window may have moved to another monitor. Returns true if the
+    /// monitor changed.
+    pub fn set_monitor(&mut self, monitor: Option<MonitorHandle>) -> bool {
+        if monitor == self.monitor {
+            return false;
+        }
+        self.refresh_rate_millihertz = monitor
+            .as_ref()
+            .and_then(|monitor| monitor.refresh_rate_millihertz())
+            .filter(|&mhz| mhz > 0);
+        self.monitor = monitor;
+        true
+    }
+
+    /// Time between frames, `None` if they're neither paced nor capped.
+    fn interval(&self) -> Option<Duration> {
+        let refresh = self
+            .refresh_rate_millihertz
+            .filter(|_| self.enabled)
+            .map(|mhz| Duration::from_secs_f64(1000.0 / mhz as f64));
+        let cap = self
+            .max_fps
+            .map(|fps| Duration::from_secs_f64(1.0 / fps as f64));
+        refresh.max(cap)
+    }
+
+    /// Returns `None` if a frame should be rendered now, or the time to wait until otherwise.
+    /// Spins until the frame is due once it's close.
+    pub fn poll(&mut self, now: Instant) -> Option<Instant> {
+        // Unpaced frames are rendered as soon as possible
+        let interval = self.interval()?;
+        if now + SPIN < self.next_frame {
+            return Some(self.next_frame - SPIN);
+        }
+        while Instant::now() < self.next_frame {
+            std::hint::spin_loop();
+        }
+
+        self.next_frame += interval;
+        // Don't try to catch up on missed refreshes, that would render a burst of frames
+        if self.next_frame <= now {
+            self.next_frame = now + interval;
+        }
+        None
+    }
+}
@@ -0,0 +1,98 @@
+//! Colors particles are spawned with: a base color, a random amount of each channel on top of it,
+//! and a gradient added from left to right across the default box.
+
+use std::str::FromStr;
+
+use glam::{Vec3, Vec4};
+
+// Width the gradient spans, that of the default box, centered on x = 0
+const GRADIENT_WIDTH: f32 = 850.0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SpawnPalette {
+    pub base: Vec3,
+    /// Largest random amount added to each channel
+    pub variance: Vec3,
+    /// Added in full on the right of the gradient, not at all on the left
+    pub gradient: Vec3,
+}
+
+impl Default for SpawnPalette {
+    /// Greens, with red growing to the right and random blues.
+    fn default() -> Self {
+        Self {
+            base: Vec3::new(0.12, 0.75, 0.0),
+            variance: Vec3::new(0.25, 0.2, 1.0),
+            gradient: Vec3::new(0.5, 0.0, 0.0),
+        }
+    }
+}
+
+impl SpawnPalette {
+    /// Color of a particle spawned at `position`, `random` holding a number between 0 and 1 per
+    /// channel. Must match `spawn_color` in particle_init.wgsl.
+    pub fn color(&self, position: Vec3, random: Vec3) -> Vec4 {
+        let gradient = (position.x / GRADIENT_WIDTH + 0.5).clamp(0.0, 1.0);
+        (self.base + self.variance * random + self.gradient * gradient).extend(1.0)
+    }
+
+    /// Sets `base`, `variance` or `gradient`. Returns false for any other name.
+    pub fn set_vector(&mut self, name: &str, value: Vec3) -> bool {
+        match name {
+            "base" => self.base = value,
+            "variance" => self.variance = value,
+            "gradient" => self.gradient = value,
+            _ => return false,
+        }
+        true
+    }
+}
+
+/// Parses the name of a built-in palette.
+impl FromStr for SpawnPalette {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (base, variance, gradient) = match s {
+            "default" => return Ok(Self::default()),
+            "fire" => (
+                Vec3::new(0.75, 0.1, 0.0),
+                Vec3::new(0.25, 0.35, 0.05),
+                Vec3::new(0.0, 0.3, 0.0),
+            ),
+            "ocean" => (
+                Vec3::new(0.0, 0.25, 0.55),
+                Vec3::new(0.1, 0.3, 0.3),
+                Vec3::new(0.0, 0.2, 0.15),
+            ),
+            "forest" => (
+                Vec3::new(0.1, 0.35, 0.05),
+                Vec3::new(0.15, 0.35, 0.1),
+                Vec3::new(0.25, 0.1, 0.0),
+            ),
+            "neon" => (
+                Vec3::new(0.6, 0.0, 0.6),
+                Vec3::new(0.4, 0.2, 0.4),
+                Vec3::new(-0.5, 0.8, 0.0),
+            ),
+            "pastel" => (Vec3::splat(0.6), Vec3::splat(0.4), Vec3::ZERO),
+            // Kept apart with a color vision deficiency, see accessibility.rs
+            "cividis" => (
+                Vec3::new(0.0, 0.13, 0.3),
+                Vec3::splat(0.08),
+                Vec3::new(0.9, 0.75, -0.2),
+            ),
+            "blue-orange" => (
+                Vec3::new(0.0, 0.45, 0.7),
+                Vec3::splat(0.08),
+                Vec3::new(0.9, 0.15, -0.7),
+            ),
+            _ => return Err(()),
+        };
+        Ok(Self {
+            base,
+            variance,
+            gradient,
+        })
+    }
+}
@@ -0,0 +1,150 @@
+//! Equirectangular panoramas, converted from the six faces of a cubemap rendered around the camera
+//! by a compute pass, so only the panorama is read back.
+
+// Must match `@workgroup_size` of main in panorama.wgsl
+const WORKGROUP_SIZE: u32 = 8;
+
+/// Converts `faces`, square textures rendered with [`CUBE_FACES`](crate::capture::CUBE_FACES) in
+/// the same order, into a new `width`x`height` RGBA8 panorama usable as a copy source.
+pub fn from_cube(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    faces: &[&wgpu::Texture; 6],
+    width: u32,
+    height: u32,
+) -> wgpu::Texture {
+    let face_size = faces[0].width();
+    // Loaded without the sRGB conversion, the panorama stores the bytes of the faces as they are
+    let format = faces[0].format();
+    let view_format = format.remove_srgb_suffix();
+    let cube = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("Panorama Cube Texture"),
+        size: wgpu::Extent3d {
+            width: face_size,
+            height: face_size,
+            depth_or_array_layers: 6,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format,
+        usage: wgpu::TextureUsages::COPY_DST | wgpu::TextureUsages::TEXTURE_BINDING,
+        view_formats: &[view_format],
+    });
+    let cube_view = cube.create_view(&wgpu::TextureViewDescriptor {
+        format: Some(view_format),
+        dimension: Some(wgpu::TextureViewDimension::D2Array),
+        ..Default::default()
+    });
+
+    let panorama = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("Panorama Texture"),
+        size: wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: wgpu::TextureFormat::Rgba8Unorm,
+        usage: wgpu::TextureUsages::STORAGE_BINDING | wgpu::TextureUsages::COPY_SRC,
+        view_formats: &[],
+    });
+    let panorama_view = panorama.create_view(&wgpu::TextureViewDescriptor::default());
+
+    let bind_group_layout_entries = [
+        wgpu::BindGroupLayoutEntry {
+            binding: 0,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                view_dimension: wgpu::TextureViewDimension::D2Array,
+                multisampled: false,
+            },
+            count: None,
+        },
+        wgpu::BindGroupLayoutEntry {
+            binding: 1,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::StorageTexture {
+                access: wgpu::StorageTextureAccess::WriteOnly,
+                format: wgpu::TextureFormat::Rgba8Unorm,
+                view_dimension: wgpu::TextureViewDimension::D2,
+            },
+            count: None,
+        },
+    ];
+    let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        label: Some("Panorama Bind Group Layout"),
+        entries: &bind_group_layout_entries,
+    });
+    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("Panorama Bind Group"),
+        layout: &bind_group_layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(&cube_view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::TextureView(&panorama_view),
+            },
+        ],
+    });
+
+    let source = include_str!("panorama.wgsl");
+    #[cfg(feature = "guardrails")]
+    crate::guardrails::ShaderReflection::new("panorama.wgsl", source)
+        .check_bind_group_layout(0, &bind_group_layout_entries);
+    let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+        label: Some("Panorama Shader"),
+        source: wgpu::ShaderSource::Wgsl(source.into()),
+    });
+    let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: Some("Panorama Pipeline Layout"),
+        bind_group_layouts: &[&bind_group_layout],
+        push_constant_ranges: &[],
+    });
+    let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+        label: Some("Panorama Pipeline"),
+        layout: Some(&pipeline_layout),
+        module: &shader,
+        entry_point: "main",
+    });
+
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+        label: Some("Panorama Encoder"),
+    });
+    for (layer, face) in (0..).zip(faces) {
+        encoder.copy_texture_to_texture(
+            face.as_image_copy(),
+            wgpu::ImageCopyTexture {
+                texture: &cube,
+                mip_level: 0,
+                origin: wgpu::Origin3d {
+                    x: 0,
+                    y: 0,
+                    z: layer,
+                },
+                aspect: wgpu::TextureAspect::All,
+            },
+            face.size(),
+        );
+    }
+    {
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Panorama Pass"),
+        });
+        compute_pass.set_pipeline(&pipeline);
+        compute_pass.set_bind_group(0, &bind_group, &[]);
+        compute_pass.dispatch_workgroups(
+            width.div_ceil(WORKGROUP_SIZE),
+            height.div_ceil(WORKGROUP_SIZE),
+            1,
+        );
+    }
+    queue.submit(Some(encoder.finish()));
+    panorama
+}
@@ -0,0 +1,64 @@
+// Equirectangular panorama from the six faces of a cubemap, one invocation per panorama pixel
+
+const PI: f32 = 3.14159265;
+const TAU: f32 = 6.28318531;
+
+// Must match CUBE_FACES in capture.rs, in the same order as the layers of `faces`
+var<private> FORWARD: array<vec3<f32>, 6> = array<vec3<f32>, 6>(
+    vec3<f32>(1.0, 0.0, 0.0),
+    vec3<f32>(-1.0, 0.0, 0.0),
+    vec3<f32>(0.0, 1.0, 0.0),
+    vec3<f32>(0.0, -1.0, 0.0),
+    vec3<f32>(0.0, 0.0, 1.0),
+    vec3<f32>(0.0, 0.0, -1.0),
+);
+var<private> UP: array<vec3<f32>, 6> = array<vec3<f32>, 6>(
+    vec3<f32>(0.0, 1.0, 0.0),
+    vec3<f32>(0.0, 1.0, 0.0),
+    vec3<f32>(0.0, 0.0, 1.0),
+    vec3<f32>(0.0, 0.0, -1.0),
+    vec3<f32>(0.0, 1.0, 0.0),
+    vec3<f32>(0.0, 1.0, 0.0),
+);
+
+@group(0) @binding(0)
+var faces: texture_2d_array<f32>;
+
+@group(0) @binding(1)
+var panorama: texture_storage_2d<rgba8unorm, write>;
+
+// The center of the panorama looks down -z
+@compute @workgroup_size(8, 8)
+fn main(@builtin(global_invocation_id) id: vec3<u32>) {
+    let size = textureDimensions(panorama);
+    if any(id.xy >= size) {
+        return;
+    }
+    let latitude = PI / 2.0 - (f32(id.y) + 0.5) / f32(size.y) * PI;
+    let longitude = (f32(id.x) + 0.5) / f32(size.x) * TAU - PI;
+    let direction = vec3<f32>(
+        cos(latitude) * sin(longitude),
+        sin(latitude),
+        -cos(latitude) * cos(longitude),
+    );
+
+    // The face the direction points the most towards
+    var face = 0u;
+    var depth = dot(direction, FORWARD[0]);
+    for (var i = 1u; i < 6u; i++) {
+        let face_depth = dot(direction, FORWARD[i]);
+        if face_depth > depth {
+            face = i;
+            depth = face_depth;
+        }
+    }
+
+    // Project on the face plane, giving NDC coordinates in [-1, 1]
+    let forward = FORWARD[face];
+    let up = UP[face];
+    let ndc = vec2<f32>(dot(direction, cross(forward, up)), dot(direction, up)) / depth;
+    let face_size = textureDimensions(faces).x;
+    let texel = vec2<f32>(ndc.x + 1.0, 1.0 - ndc.y) / 2.0 * f32(face_size);
+    let pixel = min(vec2<u32>(texel), vec2<u32>(face_size - 1u));
+    textureStore(panorama, id.xy, textureLoad(faces, pixel, face, 0));
+}
@@ -0,0 +1,288 @@
+//! Particles exchanged with analysis scripts: read with `--import` instead of spawning them, and
+//! written with `--export` when the app exits, as CSV or NumPy `.npy` files.
+//!
+//! Each particle is a row of `x, y, z, r, g, b, a, vx, vy, vz`, colors between 0 and 1. CSV files
+//! may name their columns in a header, in any order, and leave out the colors or the speeds.
+//! Without a header, and in `.npy` files of 32 or 64-bit floats, rows of 3 columns are positions,
+//! 6 add the speeds, 7 the colors and 10 both.
+//!
+//! ```python
+//! particles = numpy.load("particles.npy")
+//! speeds = numpy.linalg.norm(particles[:, 7:10], axis=1)
+//! ```
+
+use std::{
+    fmt::Write as _,
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use glam::{Vec3, Vec4};
+
+use crate::point_cloud::PointCloud;
+
+const COLUMNS: [&str; 10] = ["x", "y", "z", "r", "g", "b", "a", "vx", "vy", "vz"];
+const NPY_MAGIC: &[u8] = b"\x93NUMPY";
+
+#[derive(Debug, thiserror::Error)]
+pub enum ParticleDataError {
+    #[error("unable to read the particles {0}: {1}")]
+    Read(PathBuf, io::Error),
+    #[error("unable to write the particles {0}: {1}")]
+    Write(PathBuf, io::Error),
+    #[error("invalid particles {0}: {1}")]
+    Invalid(PathBuf, String),
+    #[error("{0} isn't a .csv or .npy file")]
+    UnknownFormat(PathBuf),
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Format {
+    Csv,
+    Npy,
+}
+
+impl Format {
+    fn of(path: &Path) -> Result<Self, ParticleDataError> {
+        let extension = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map(str::to_ascii_lowercase);
+        match extension.as_deref() {
+            Some("csv") => Ok(Format::Csv),
+            Some("npy") => Ok(Format::Npy),
+            _ => Err(ParticleDataError::UnknownFormat(path.to_owned())),
+        }
+    }
+}
+
+/// Where each of `COLUMNS` is in a row, if it is.
+type Layout = [Option<usize>; 10];
+
+/// Layout of rows of `count` columns without names.
+fn layout_of(count: usize) -> Result<Layout, String> {
+    let mut layout = [None; 10];
+    let columns: &[usize] = match count {
+        3 => &[0, 1, 2],
+        6 => &[0, 1, 2, 7, 8, 9],
+        7 => &[0, 1, 2, 3, 4, 5, 6],
+        10 => &[0, 1, 2, 3, 4, 5, 6, 7, 8, 9],
+        _ => return Err(format!("rows of {count} columns, instead of 3, 6, 7 or 10")),
+    };
+    for (index, &column) in columns.iter().enumerate() {
+        layout[column] = Some(index);
+    }
+    Ok(layout)
+}
+
+/// Reads the particles of `path`, as they are: unlike point clouds, they're neither centered nor
+/// scaled.
+pub fn import(path: &Path) -> Result<PointCloud, ParticleDataError> {
+    let format = Format::of(path)?;
+    let contents = std::fs::read(path).map_err(|e| ParticleDataError::Read(path.to_owned(), e))?;
+    let (layout, rows) = match format {
+        Format::Csv => read_csv(&contents),
+        Format::Npy => read_npy(&contents),
+    }
+    .map_err(|e| ParticleDataError::Invalid(path.to_owned(), e))?;
+    if rows.is_empty() {
+        return Err(ParticleDataError::Invalid(
+            path.to_owned(),
+            "no particles".to_owned(),
+        ));
+    }
+    let [Some(x), Some(y), Some(z), ..] = layout else {
+        return Err(ParticleDataError::Invalid(
+            path.to_owned(),
+            "missing x, y or z".to_owned(),
+        ));
+    };
+    let column = |name: usize| layout[name];
+    let colors = column(3).map(|r| {
+        let (g, b, a) = (column(4), column(5), column(6));
+        rows.iter()
+            .map(|row| {
+                let channel = |index: Option<usize>| index.map_or(1.0, |index| row[index]);
+                Vec4::new(row[r], channel(g), channel(b), channel(a))
+            })
+            .collect()
+    });
+    let speeds = column(7).map(|vx| {
+        let (vy, vz) = (column(8), column(9));
+        rows.iter()
+            .map(|row| {
+                let axis = |index: Option<usize>| index.map_or(0.0, |index| row[index]);
+                Vec3::new(row[vx], axis(vy), axis(vz))
+            })
+            .collect()
+    });
+    log::info!("Imported {} particles from {}", rows.len(), path.display());
+    Ok(PointCloud {
+        positions: rows
+            .iter()
+            .map(|row| Vec3::new(row[x], row[y], row[z]))
+            .collect(),
+        colors,
+        speeds,
+    })
+}
+
+fn split(line: &str) -> Vec<&str> {
+    line.split(',').map(str::trim).collect()
+}
+
+fn read_csv(contents: &[u8]) -> Result<(Layout, Vec<Vec<f32>>), String> {
+    let text = std::str::from_utf8(contents).map_err(|e| e.to_string())?;
+    let mut lines = text
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .peekable();
+    let first = split(lines.peek().ok_or("empty file")?);
+    let named = first.iter().any(|field| field.parse::<f32>().is_err());
+    let (layout, width) = if named {
+        lines.next();
+        let mut layout = [None; 10];
+        for (index, name) in first.iter().enumerate() {
+            if let Some(column) = COLUMNS.iter().position(|column| column == name) {
+                layout[column] = Some(index);
+            }
+        }
+        (layout, first.len())
+    } else {
+        (layout_of(first.len())?, first.len())
+    };
+
+    let rows = lines
+        .enumerate()
+        .map(|(index, line)| {
+            let fields = split(line);
+            if fields.len() != width {
+                return Err(format!("row {} has {} columns", index + 1, fields.len()));
+            }
+            fields
+                .iter()
+                .map(|field| field.parse::<f32>())
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|e| format!("row {}: {e}", index + 1))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok((layout, rows))
+}
+
+fn read_npy(contents: &[u8]) -> Result<(Layout, Vec<Vec<f32>>), String> {
+    if !contents.starts_with(NPY_MAGIC) || contents.len() < 10 {
+        return Err("missing the NumPy magic string".to_owned());
+    }
+    // Version 1 has a 16-bit header length, the later ones 32 bits
+    let (header_length, header_start) = match contents[6] {
+        1 => (u16::from_le_bytes([contents[8], contents[9]]) as usize, 10),
+        2 | 3 => {
+            let bytes = contents.get(8..12).ok_or("truncated header")?;
+            (u32::from_le_bytes(bytes.try_into().unwrap()) as usize, 12)
+        }
+        version => return Err(format!("unsupported .npy version {version}")),
+    };
+    let header = contents
+        .get(header_start..header_start + header_length)
+        .ok_or("truncated header")?;
+    let header = std::str::from_utf8(header).map_err(|e| e.to_string())?;
+    let value = |key: &str| {
+        let start = header.find(&format!("'{key}'"))? + key.len() + 2;
+        let rest = header[start..].trim_start().strip_prefix(':')?.trim_start();
+        Some(rest)
+    };
+    let descr = value("descr")
+        .and_then(|rest| rest.strip_prefix('\''))
+        .and_then(|rest| rest.split('\'').next())
+        .ok_or("missing descr")?;
+    if value("fortran_order").is_some_and(|rest| rest.starts_with("True")) {
+        return Err("Fortran-ordered arrays aren't supported".to_owned());
+    }
+    let shape = value("shape")
+        .and_then(|rest| rest.strip_prefix('('))
+        .and_then(|rest| rest.split(')').next())
+        .ok_or("missing shape")?
+        .split(',')
+        .map(str::trim)
+        .filter(|dimension| !dimension.is_empty())
+        .map(|dimension| dimension.parse::<usize>().map_err(|e| e.to_string()))
+        .collect::<Result<Vec<_>, _>>()?;
+    let [count, width] = shape[..] else {
+        return Err(format!("a {}-dimensional array instead of 2", shape.len()));
+    };
+    let layout = layout_of(width)?;
+
+    let size = match descr {
+        "<f4" => 4,
+        "<f8" => 8,
+        _ => return Err(format!("{descr} values instead of little-endian floats")),
+    };
+    let data = &contents[header_start + header_length..];
+    if data.len() < count * width * size {
+        return Err("fewer values than the shape".to_owned());
+    }
+    let values = data[..count * width * size]
+        .chunks_exact(size)
+        .map(|bytes| match size {
+            4 => f32::from_le_bytes(bytes.try_into().unwrap()),
+            _ => f64::from_le_bytes(bytes.try_into().unwrap()) as f32,
+        })
+        .collect::<Vec<_>>();
+    let rows = values.chunks_exact(width).map(<[f32]>::to_vec).collect();
+    Ok((layout, rows))
+}
+
+/// Writes every particle to `path`, in all the columns.
+pub fn export(
+    path: &Path,
+    particles: impl ExactSizeIterator<Item = (Vec3, Vec4, Vec3)>,
+) -> Result<(), ParticleDataError> {
+    let format = Format::of(path)?;
+    let count = particles.len();
+    let write = || -> io::Result<()> {
+        let mut writer = BufWriter::new(std::fs::File::create(path)?);
+        let rows = particles.map(|(position, color, speed)| {
+            let mut row = [0.0; 10];
+            row[..3].copy_from_slice(&position.to_array());
+            row[3..7].copy_from_slice(&color.to_array());
+            row[7..].copy_from_slice(&speed.to_array());
+            row
+        });
+        match format {
+            Format::Csv => {
+                writeln!(writer, "{}", COLUMNS.join(","))?;
+                let mut line = String::new();
+                for row in rows {
+                    line.clear();
+                    for (index, value) in row.iter().enumerate() {
+                        let separator = if index == 0 { "" } else { "," };
+                        let _ = write!(line, "{separator}{value}");
+                    }
+                    writeln!(writer, "{line}")?;
+                }
+            }
+            Format::Npy => {
+                let mut header = format!(
+                    "{{'descr': '<f4', 'fortran_order': False, 'shape': ({count}, {}), }}",
+                    COLUMNS.len()
+                );
+                // The data starts on a multiple of 64 bytes, after a newline
+                let unpadded = NPY_MAGIC.len() + 4 + header.len() + 1;
+                header.push_str(&" ".repeat(unpadded.next_multiple_of(64) - unpadded));
+                header.push('\n');
+                writer.write_all(NPY_MAGIC)?;
+                writer.write_all(&[1, 0])?;
+                writer.write_all(&(header.len() as u16).to_le_bytes())?;
+                writer.write_all(header.as_bytes())?;
+                for value in rows.flatten() {
+                    writer.write_all(&value.to_le_bytes())?;
+                }
+            }
+        }
+        writer.flush()
+    };
+    write().map_err(|e| ParticleDataError::Write(path.to_owned(), e))?;
+    log::info!("Exported {count} particles to {}", path.display());
+    Ok(())
+}
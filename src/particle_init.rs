@@ -0,0 +1,190 @@
+//! Spawns particles straight into their GPU buffers, with a hash-based random generator per
+//! particle, so startup doesn't generate and upload millions of particles from the CPU.
+//!
+//! The particles follow the same distributions as on the CPU but not the same random sequence, a
+//! seed spawns the same particles on the GPU every time but different ones than on the CPU.
+
+use bytemuck::{Pod, Zeroable};
+use glam::Vec4;
+use wgpu::util::DeviceExt;
+
+use crate::{
+    emitter::{EmitterParams, EmitterShape, EMITTER_WGSL},
+    palette::SpawnPalette,
+    shader_check,
+    speed_layout::SpeedLayout,
+    vertex::{InstanceColor, INSTANCE_COLOR_WGSL},
+};
+
+// Must match `@workgroup_size` of main in particle_init.wgsl
+const WORKGROUP_SIZE: u32 = 64;
+// Size of each particle in the position buffer, and at most in the speed buffer
+const ELEMENT_SIZE: u64 = 16;
+
+// Must match InitParams in particle_init.wgsl
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Pod, Zeroable)]
+struct InitParams {
+    emitter: EmitterParams,
+    seed: u32,
+    count: u32,
+    _padding: [u32; 2],
+    palette_base: Vec4,
+    palette_variance: Vec4,
+    palette_gradient: Vec4,
+}
+
+impl InitParams {
+    fn new(emitter: &EmitterShape, palette: &SpawnPalette, seed: u64, count: u32) -> Self {
+        Self {
+            emitter: EmitterParams::new(emitter),
+            seed: (seed ^ (seed >> 32)) as u32,
+            count,
+            _padding: [0; 2],
+            palette_base: palette.base.extend(0.0),
+            palette_variance: palette.variance.extend(0.0),
+            palette_gradient: palette.gradient.extend(0.0),
+        }
+    }
+}
+
+/// Whether `count` particles can be spawned on `device`, each buffer has to fit in a single binding.
+pub fn fits(device: &wgpu::Device, count: usize) -> bool {
+    let size = count as u64 * ELEMENT_SIZE;
+    count > 0 && size <= device.limits().max_storage_buffer_binding_size as u64
+}
+
+/// Spawns `count` particles of `emitter` colored from `palette` into the start of the buffers,
+/// speeds in `cpu_data_buffer` laid out in `speed_layout`. They must [`fits`].
+#[allow(clippy::too_many_arguments)]
+pub fn spawn(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    emitter: &EmitterShape,
+    palette: &SpawnPalette,
+    seed: u64,
+    count: usize,
+    position_buffer: &wgpu::Buffer,
+    color_buffer: &wgpu::Buffer,
+    cpu_data_buffer: &wgpu::Buffer,
+    speed_layout: SpeedLayout,
+) {
+    let size = count as u64 * ELEMENT_SIZE;
+
+    let params_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some("Particle Init Params Buffer"),
+        contents: bytemuck::bytes_of(&InitParams::new(emitter, palette, seed, count as u32)),
+        usage: wgpu::BufferUsages::UNIFORM,
+    });
+
+    let storage_entry = |binding| wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility: wgpu::ShaderStages::COMPUTE,
+        ty: wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Storage { read_only: false },
+            has_dynamic_offset: false,
+            min_binding_size: None,
+        },
+        count: None,
+    };
+    let bind_group_layout_entries = [
+        wgpu::BindGroupLayoutEntry {
+            binding: 0,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        },
+        storage_entry(1),
+        storage_entry(2),
+        storage_entry(3),
+    ];
+    let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        label: Some("Particle Init Bind Group Layout"),
+        entries: &bind_group_layout_entries,
+    });
+    fn binding(binding: u32, buffer: &wgpu::Buffer, size: u64) -> wgpu::BindGroupEntry<'_> {
+        wgpu::BindGroupEntry {
+            binding,
+            resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                buffer,
+                offset: 0,
+                size: wgpu::BufferSize::new(size),
+            }),
+        }
+    }
+    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("Particle Init Bind Group"),
+        layout: &bind_group_layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: params_buffer.as_entire_binding(),
+            },
+            binding(1, position_buffer, size),
+            binding(
+                2,
+                color_buffer,
+                (count * std::mem::size_of::<InstanceColor>()) as u64,
+            ),
+            binding(3, cpu_data_buffer, (count * speed_layout.stride()) as u64),
+        ],
+    });
+
+    let source = shader_source(speed_layout);
+    let shader = shader_check::create_module(
+        device,
+        "Particle Init Shader",
+        "particle_init.wgsl",
+        &source,
+    );
+    let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: Some("Particle Init Pipeline Layout"),
+        bind_group_layouts: &[&bind_group_layout],
+        push_constant_ranges: &[],
+    });
+    let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+        label: Some("Particle Init Pipeline"),
+        layout: Some(&pipeline_layout),
+        module: &shader,
+        entry_point: "main",
+    });
+
+    #[cfg(feature = "guardrails")]
+    {
+        let reflection = crate::guardrails::ShaderReflection::new("particle_init.wgsl", &source);
+        reflection.check_bind_group_layout(0, &bind_group_layout_entries);
+        reflection.check_struct_size("InitParams", std::mem::size_of::<InitParams>());
+    }
+
+    let groups = (count as u32).div_ceil(WORKGROUP_SIZE);
+    let max_groups = device.limits().max_compute_workgroups_per_dimension;
+    let (x, y) = (groups.min(max_groups), groups.div_ceil(max_groups));
+    #[cfg(feature = "guardrails")]
+    crate::guardrails::check_dispatch_coverage([x, y, 1], [WORKGROUP_SIZE, 1, 1], count);
+
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+        label: Some("Particle Init Encoder"),
+    });
+    {
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Particle Init Pass"),
+        });
+        compute_pass.set_pipeline(&pipeline);
+        compute_pass.set_bind_group(0, &bind_group, &[]);
+        compute_pass.dispatch_workgroups(x, y, 1);
+    }
+    queue.submit(Some(encoder.finish()));
+}
+
+/// The source of the spawning shader, after the pieces it uses and `speed_layout`.
+pub fn shader_source(speed_layout: SpeedLayout) -> String {
+    format!(
+        "{INSTANCE_COLOR_WGSL}\n{EMITTER_WGSL}\n{}\n{}",
+        speed_layout.wgsl(),
+        include_str!("particle_init.wgsl")
+    )
+}
@@ -0,0 +1,54 @@
+// Must match GROUPS in groups.rs
+const GROUPS: u32 = 4u;
+
+// Must match InitParams in particle_init.rs
+struct InitParams {
+    emitter: EmitterParams,
+    seed: u32,
+    count: u32,
+    _padding0: u32,
+    _padding1: u32,
+    // SpawnPalette in palette.rs
+    palette_base: vec4<f32>,
+    palette_variance: vec4<f32>,
+    palette_gradient: vec4<f32>,
+}
+
+@group(0) @binding(0) var<uniform> params: InitParams;
+@group(0) @binding(1) var<storage, read_write> positions: array<vec4<f32>>;
+// PackedColor and pack_color are declared by INSTANCE_COLOR_WGSL in vertex.rs
+@group(0) @binding(2) var<storage, read_write> colors: array<PackedColor>;
+// CpuData in state.rs, the speed and the step dt of the compute passes, PackedSpeed is declared by
+// the SpeedLayout in speed_layout.rs
+@group(0) @binding(3) var<storage, read_write> speeds: array<PackedSpeed>;
+
+// Must match SpawnPalette::color in palette.rs
+fn spawn_color(position: vec3<f32>) -> vec4<f32> {
+    let gradient = clamp(position.x / 850.0 + 0.5, 0.0, 1.0);
+    let amount = vec3<f32>(random(), random(), random());
+    let color = params.palette_base.xyz + params.palette_variance.xyz * amount
+        + params.palette_gradient.xyz * gradient;
+    return vec4<f32>(color, 1.0);
+}
+
+// Must match random_instance and random_speed in state.rs
+@compute @workgroup_size(64)
+fn main(
+    @builtin(global_invocation_id) id: vec3<u32>,
+    @builtin(num_workgroups) workgroups: vec3<u32>,
+) {
+    // Large counts are dispatched in rows, a single dimension is limited to 65535 workgroups
+    let index = id.x + id.y * workgroups.x * 64u;
+    if index >= params.count {
+        return;
+    }
+    rng_state = pcg(index ^ pcg(params.seed));
+
+    let position = sample_emitter(params.emitter);
+    // w is the group, newborn particles have an age of 0 in its fraction
+    positions[index] = vec4<f32>(position, f32(index % GROUPS));
+
+    colors[index] = pack_color(spawn_color(position));
+
+    speeds[index] = pack_speed(vec4<f32>(random_speed(), 0.0));
+}
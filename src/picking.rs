@@ -0,0 +1,351 @@
+//! Picking of the particle under the cursor on the GPU: the particles are drawn with their instance
+//! ids into an integer target, around the cursor only, and the ids there are read back.
+//!
+//! This picks from the positions the GPU currently holds, unlike a projection of the positions on
+//! the CPU, which lag behind while the compute kernel moves the particles.
+
+use std::ops::Range;
+
+use crate::{
+    camera::DepthRange,
+    render_target::DEPTH_FORMAT,
+    vertex::{InstancePosition, Vertex},
+};
+
+// Particles further than this many pixels from the cursor can't be picked
+const PICK_RADIUS: u32 = 20;
+const ID_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::R32Uint;
+const ID_SIZE: u32 = 4;
+
+/// Buffers and camera the particles are drawn with.
+pub struct ParticleBuffers<'a> {
+    pub camera_bind_group: &'a wgpu::BindGroup,
+    pub vertex_buffer: &'a wgpu::Buffer,
+    pub index_buffer: &'a wgpu::Buffer,
+    pub index_count: u32,
+    pub position_buffer: &'a wgpu::Buffer,
+}
+
+struct Targets {
+    size: (u32, u32),
+    id_texture: wgpu::Texture,
+    id_view: wgpu::TextureView,
+    // Kept alive for the view
+    _depth_texture: wgpu::Texture,
+    depth_view: wgpu::TextureView,
+}
+
+pub struct Picker {
+    quad_pipeline: wgpu::RenderPipeline,
+    point_pipeline: wgpu::RenderPipeline,
+    highlight_pipeline: wgpu::RenderPipeline,
+    // Of the camera's DepthRange, the depth is cleared to
+    far_depth: f32,
+    // Created on the first pick, for the size of the window
+    targets: Option<Targets>,
+}
+
+impl Picker {
+    pub fn new(
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+        camera_bind_group_layout: &wgpu::BindGroupLayout,
+        depth_range: &DepthRange,
+    ) -> Self {
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Picking Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("picking.wgsl").into()),
+        });
+
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Picking Pipeline Layout"),
+            bind_group_layouts: &[camera_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let quad_buffers = [Vertex::descriptor(), InstancePosition::descriptor()];
+        let point_buffers = [InstancePosition::descriptor()];
+        let create_pipeline = |label,
+                               (vertex_entry_point, fragment_entry_point),
+                               buffers: &[wgpu::VertexBufferLayout],
+                               topology,
+                               target: wgpu::TextureFormat,
+                               depth: bool| {
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some(label),
+                layout: Some(&layout),
+                vertex: wgpu::VertexState {
+                    module: &shader,
+                    entry_point: vertex_entry_point,
+                    buffers,
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader,
+                    entry_point: fragment_entry_point,
+                    targets: &[Some(wgpu::ColorTargetState {
+                        format: target,
+                        blend: None,
+                        write_mask: wgpu::ColorWrites::ALL,
+                    })],
+                }),
+                primitive: wgpu::PrimitiveState {
+                    topology,
+                    ..Default::default()
+                },
+                depth_stencil: depth.then_some(wgpu::DepthStencilState {
+                    format: DEPTH_FORMAT,
+                    depth_write_enabled: true,
+                    depth_compare: depth_range.compare(false),
+                    stencil: wgpu::StencilState::default(),
+                    bias: wgpu::DepthBiasState::default(),
+                }),
+                multisample: wgpu::MultisampleState::default(),
+                multiview: None,
+            })
+        };
+
+        let quad_pipeline = create_pipeline(
+            "Picking Quad Pipeline",
+            ("vs_pick", "fs_pick"),
+            &quad_buffers,
+            wgpu::PrimitiveTopology::TriangleList,
+            ID_FORMAT,
+            true,
+        );
+        let point_pipeline = create_pipeline(
+            "Picking Point Pipeline",
+            ("vs_pick_point", "fs_pick"),
+            &point_buffers,
+            wgpu::PrimitiveTopology::PointList,
+            ID_FORMAT,
+            true,
+        );
+        let highlight_pipeline = create_pipeline(
+            "Highlight Pipeline",
+            ("vs_highlight", "fs_highlight"),
+            &quad_buffers,
+            wgpu::PrimitiveTopology::TriangleList,
+            format,
+            false,
+        );
+
+        #[cfg(feature = "guardrails")]
+        {
+            let reflection = crate::guardrails::ShaderReflection::new(
+                "picking.wgsl",
+                include_str!("picking.wgsl"),
+            );
+            reflection.check_vertex_buffers("vs_pick", &quad_buffers);
+            reflection.check_vertex_buffers("vs_pick_point", &point_buffers);
+            reflection.check_vertex_buffers("vs_highlight", &quad_buffers);
+        }
+
+        Self {
+            quad_pipeline,
+            point_pipeline,
+            highlight_pipeline,
+            far_depth: depth_range.far_depth(),
+            targets: None,
+        }
+    }
+
+    /// Index of the closest particle of `ranges` to `cursor` in a window of `size`, if any is close
+    /// enough. Waits for the GPU.
+    pub fn pick(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        buffers: &ParticleBuffers,
+        ranges: &[Range<usize>],
+        size: (u32, u32),
+        cursor: (u32, u32),
+    ) -> Option<usize> {
+        if cursor.0 >= size.0 || cursor.1 >= size.1 {
+            return None;
+        }
+        if self
+            .targets
+            .as_ref()
+            .is_none_or(|targets| targets.size != size)
+        {
+            self.targets = Some(Self::create_targets(device, size));
+        }
+        let targets = self.targets.as_ref().unwrap();
+
+        // Only the pixels around the cursor are drawn and read
+        let x = cursor.0.saturating_sub(PICK_RADIUS);
+        let y = cursor.1.saturating_sub(PICK_RADIUS);
+        let width = (cursor.0 + PICK_RADIUS + 1).min(size.0) - x;
+        let height = (cursor.1 + PICK_RADIUS + 1).min(size.1) - y;
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Picking Encoder"),
+        });
+        {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Picking Pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &targets.id_view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                        store: true,
+                    },
+                })],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: &targets.depth_view,
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(self.far_depth),
+                        store: false,
+                    }),
+                    stencil_ops: None,
+                }),
+            });
+            render_pass.set_scissor_rect(x, y, width, height);
+            render_pass.set_bind_group(0, buffers.camera_bind_group, &[]);
+            render_pass.set_vertex_buffer(1, buffers.position_buffer.slice(..));
+
+            render_pass.set_pipeline(&self.quad_pipeline);
+            render_pass.set_vertex_buffer(0, buffers.vertex_buffer.slice(..));
+            render_pass.set_index_buffer(buffers.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
+            for range in ranges {
+                render_pass.draw_indexed(
+                    0..buffers.index_count,
+                    0,
+                    range.start as u32..range.end as u32,
+                );
+            }
+
+            render_pass.set_pipeline(&self.point_pipeline);
+            render_pass.set_vertex_buffer(0, buffers.position_buffer.slice(..));
+            for range in ranges {
+                render_pass.draw(0..1, range.start as u32..range.end as u32);
+            }
+        }
+
+        let bytes_per_row = (width * ID_SIZE).next_multiple_of(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT);
+        let readback_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Picking Readback Buffer"),
+            size: (bytes_per_row * height) as u64,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+        encoder.copy_texture_to_buffer(
+            wgpu::ImageCopyTexture {
+                texture: &targets.id_texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d { x, y, z: 0 },
+                aspect: wgpu::TextureAspect::All,
+            },
+            wgpu::ImageCopyBuffer {
+                buffer: &readback_buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(bytes_per_row),
+                    rows_per_image: None,
+                },
+            },
+            wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+        );
+        queue.submit(Some(encoder.finish()));
+
+        let (sender, receiver) = futures::channel::oneshot::channel();
+        readback_buffer
+            .slice(..)
+            .map_async(wgpu::MapMode::Read, move |result| {
+                let _ = sender.send(result);
+            });
+        device.poll(wgpu::Maintain::Wait);
+        futures::executor::block_on(receiver).ok()?.ok()?;
+
+        let closest = {
+            let data = readback_buffer.slice(..).get_mapped_range();
+            let radius_squared = PICK_RADIUS * PICK_RADIUS;
+            (0..height)
+                .flat_map(|row| (0..width).map(move |column| (column, row)))
+                .filter_map(|(column, row)| {
+                    let offset = (row * bytes_per_row + column * ID_SIZE) as usize;
+                    let id: u32 = bytemuck::pod_read_unaligned(&data[offset..offset + 4]);
+                    let (dx, dy) = (
+                        (x + column).abs_diff(cursor.0),
+                        (y + row).abs_diff(cursor.1),
+                    );
+                    let distance_squared = dx * dx + dy * dy;
+                    (id != 0 && distance_squared <= radius_squared)
+                        .then_some((distance_squared, id - 1))
+                })
+                .min()
+        };
+        readback_buffer.unmap();
+        closest.map(|(_, index)| index as usize)
+    }
+
+    /// Draws a ring around the particle at `index`, on top of what `view` holds.
+    pub fn highlight(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        view: &wgpu::TextureView,
+        buffers: &ParticleBuffers,
+        index: usize,
+    ) {
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Highlight Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: true,
+                },
+            })],
+            depth_stencil_attachment: None,
+        });
+        render_pass.set_pipeline(&self.highlight_pipeline);
+        render_pass.set_bind_group(0, buffers.camera_bind_group, &[]);
+        render_pass.set_vertex_buffer(0, buffers.vertex_buffer.slice(..));
+        render_pass.set_vertex_buffer(1, buffers.position_buffer.slice(..));
+        render_pass.set_index_buffer(buffers.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
+        let index = index as u32;
+        render_pass.draw_indexed(0..buffers.index_count, 0, index..index + 1);
+    }
+
+    fn create_targets(device: &wgpu::Device, size: (u32, u32)) -> Targets {
+        let create_texture = |label, format, usage| {
+            device.create_texture(&wgpu::TextureDescriptor {
+                label: Some(label),
+                size: wgpu::Extent3d {
+                    width: size.0,
+                    height: size.1,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format,
+                usage,
+                view_formats: &[],
+            })
+        };
+        let id_texture = create_texture(
+            "Picking Id Texture",
+            ID_FORMAT,
+            wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+        );
+        let depth_texture = create_texture(
+            "Picking Depth Texture",
+            DEPTH_FORMAT,
+            wgpu::TextureUsages::RENDER_ATTACHMENT,
+        );
+        Targets {
+            size,
+            id_view: id_texture.create_view(&wgpu::TextureViewDescriptor::default()),
+            depth_view: depth_texture.create_view(&wgpu::TextureViewDescriptor::default()),
+            id_texture,
+            _depth_texture: depth_texture,
+        }
+    }
+}
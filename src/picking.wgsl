@@ -0,0 +1,106 @@
+// Instance ids of the particles, to pick the one under the cursor, and the highlight around the
+// picked particle
+
+struct CameraUniform {
+    view_proj: mat4x4<f32>,
+};
+@group(0) @binding(0)
+var<uniform> camera: CameraUniform;
+
+struct VertexInput {
+    @location(0) position: vec3<f32>,
+    @location(1) vertex_position: vec2<f32>,
+    @location(4) normal: vec3<f32>,
+};
+
+struct InstanceInput {
+    @location(2) position: vec4<f32>,
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) vertex_position: vec2<f32>,
+    // Instance index + 1, 0 is left for the background
+    @location(1) @interpolate(flat) id: u32,
+    @location(2) view_depth: f32,
+};
+
+// Must match `DEPTH_SCALE` in depth_of_field.rs
+const DEPTH_SCALE: f32 = 1000.0;
+// Height of the highlight on screen, in normalized device coordinates
+const HIGHLIGHT_SIZE: f32 = 0.06;
+
+fn particle_vertex(corner: vec3<f32>, instance: InstanceInput, index: u32) -> VertexOutput {
+    var out: VertexOutput;
+    out.clip_position = camera.view_proj * vec4<f32>(corner + instance.position.xyz, 1.0);
+    out.vertex_position = vec2<f32>(0.5, 0.5);
+    out.id = index + 1u;
+    out.view_depth = out.clip_position.w;
+    return out;
+}
+
+@vertex
+fn vs_pick(
+    model: VertexInput,
+    instance: InstanceInput,
+    @builtin(instance_index) index: u32,
+) -> VertexOutput {
+    var out = particle_vertex(model.position, instance, index);
+    out.vertex_position = model.vertex_position;
+    return out;
+}
+
+// Far away particles are smaller than a pixel and may not cover any, their point always does
+@vertex
+fn vs_pick_point(instance: InstanceInput, @builtin(instance_index) index: u32) -> VertexOutput {
+    return particle_vertex(vec3<f32>(0.0), instance, index);
+}
+
+struct PickOutput {
+    @location(0) id: u32,
+    @builtin(frag_depth) depth: f32,
+};
+
+// The closest particle wins, with the same depth as the depth-of-field pass
+@fragment
+fn fs_pick(in: VertexOutput) -> PickOutput {
+    // Same round shape as the particles
+    if length(in.vertex_position - 0.5) > 0.5 {
+        discard;
+    }
+    var out: PickOutput;
+    out.id = in.id;
+    out.depth = in.view_depth / (in.view_depth + DEPTH_SCALE);
+    return out;
+}
+
+// Same size on screen wherever the particle is, far away particles are smaller than a pixel
+@vertex
+fn vs_highlight(
+    model: VertexInput,
+    instance: InstanceInput,
+    @builtin(instance_index) index: u32,
+) -> VertexOutput {
+    var out = particle_vertex(vec3<f32>(0.0), instance, index);
+    out.vertex_position = model.vertex_position;
+    // The view only rotates, so the first two rows of the projection keep their length, which is
+    // scaled by the aspect ratio for the first
+    let m = camera.view_proj;
+    let row0 = vec3<f32>(m[0][0], m[1][0], m[2][0]);
+    let row1 = vec3<f32>(m[0][1], m[1][1], m[2][1]);
+    let aspect = length(row1) / length(row0);
+    let offset = model.position.xy * vec2<f32>(HIGHLIGHT_SIZE / aspect, HIGHLIGHT_SIZE);
+    let clip = out.clip_position;
+    out.clip_position = vec4<f32>(clip.xy + offset * clip.w, clip.zw);
+    return out;
+}
+
+// A ring around the particle
+@fragment
+fn fs_highlight(in: VertexOutput) -> @location(0) vec4<f32> {
+    let radius = length(in.vertex_position - 0.5) * 2.0;
+    if radius < 0.8 || radius > 1.0 {
+        discard;
+    }
+    return vec4<f32>(1.0, 0.85, 0.2, 1.0);
+}
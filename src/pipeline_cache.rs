@@ -0,0 +1,442 @@
+//! Variants of the particle pipeline, keyed by the render options they are built for.
+//!
+//! The shape, blend mode and depth-stencil format of the particles pass each need their own
+//! pipeline. They are built from the one shader module the first time they're asked for and kept,
+//! so switching back to options used before doesn't build anything. The shapes are entry points of
+//! shader.wgsl rather than preprocessed variants of it, as are the soft particles, which also bind
+//! the obstacles' depth, and the quads pulling their instances from storage buffers. A scene's
+//! custom appearance is spliced into the module, see appearance.rs.
+
+use std::{
+    collections::HashMap,
+    fmt::Display,
+    sync::{Arc, Mutex},
+};
+
+use crate::{
+    appearance::{Appearance, AppearanceError},
+    camera::DepthRange,
+    shader_check,
+    speed_layout::SpeedLayout,
+    spinning::Spinning,
+    stretched::Stretched,
+    vertex::{DrawnPosition, InstanceColor, Vertex, INSTANCE_COLOR_WGSL},
+    vertex_pulling,
+};
+#[cfg(feature = "post-processing")]
+use crate::{checkerboard, render_target::DEPTH_FORMAT, soft_particles};
+
+/// How the particles blend with what's behind them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum BlendMode {
+    #[default]
+    Alpha,
+    /// Adds up, overlapping particles glow
+    Additive,
+}
+
+impl Display for BlendMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BlendMode::Alpha => write!(f, "alpha"),
+            BlendMode::Additive => write!(f, "additive"),
+        }
+    }
+}
+
+impl BlendMode {
+    fn state(self) -> wgpu::BlendState {
+        match self {
+            BlendMode::Alpha => wgpu::BlendState::ALPHA_BLENDING,
+            BlendMode::Additive => wgpu::BlendState {
+                color: wgpu::BlendComponent {
+                    src_factor: wgpu::BlendFactor::SrcAlpha,
+                    dst_factor: wgpu::BlendFactor::One,
+                    operation: wgpu::BlendOperation::Add,
+                },
+                alpha: wgpu::BlendComponent::OVER,
+            },
+        }
+    }
+}
+
+/// Mesh each particle is drawn with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ParticleShape {
+    Quad,
+    /// A single point, without the quad
+    Point,
+    /// A camera-facing quad elongated along the velocity, see stretched.rs
+    Stretched,
+    /// The quad turned by each particle's orientation, see spinning.rs
+    Spinning,
+    /// A screen-space Gaussian elongated along the velocity, see stretched.rs
+    Splat,
+    /// The quad, generated in the shader from instances in storage buffers, see vertex_pulling.rs
+    Pulled,
+}
+
+/// Everything a particle pipeline differs by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct RenderOptions {
+    pub shape: ParticleShape,
+    pub blend_mode: BlendMode,
+    /// Of the particles pass, none when it has no depth-stencil attachment
+    pub depth_format: Option<wgpu::TextureFormat>,
+    /// Fades the particles near the obstacles, see soft_particles.rs
+    pub soft: bool,
+}
+
+pub struct PipelineCache {
+    format: wgpu::TextureFormat,
+    depth_compare: wgpu::CompareFunction,
+    shader: wgpu::ShaderModule,
+    layout: wgpu::PipelineLayout,
+    #[cfg(feature = "post-processing")]
+    soft_bind_group_layout: wgpu::BindGroupLayout,
+    #[cfg(feature = "post-processing")]
+    soft_layout: wgpu::PipelineLayout,
+    pulled_bind_group_layout: wgpu::BindGroupLayout,
+    pulled_layout: wgpu::PipelineLayout,
+    // Bound at index 1 of the pulled pipelines without soft particles
+    empty_bind_group: wgpu::BindGroup,
+    #[cfg(feature = "post-processing")]
+    soft_pulled_layout: wgpu::PipelineLayout,
+    // Of the velocities of the stretched particles, see stretched.rs
+    speed_layout: SpeedLayout,
+    pipelines: Mutex<HashMap<RenderOptions, Arc<wgpu::RenderPipeline>>>,
+}
+
+impl PipelineCache {
+    /// Builds the pipelines drawing into `format` with the camera bind group of
+    /// `camera_bind_group_layout`, testing depth the way of `depth`, and drawing the particles with
+    /// `appearance` if any. Fails if the appearance doesn't compile.
+    pub fn new(
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+        camera_bind_group_layout: &wgpu::BindGroupLayout,
+        depth: &DepthRange,
+        appearance: Option<&Appearance>,
+    ) -> Result<Self, AppearanceError> {
+        let shader_source = include_str!("shader.wgsl");
+        let source = match appearance {
+            Some(appearance) => {
+                format!(
+                    "{INSTANCE_COLOR_WGSL}\n{}",
+                    appearance.splice(shader_source)
+                )
+            }
+            None => format!("{INSTANCE_COLOR_WGSL}\n{shader_source}"),
+        };
+        // Checked first for an error pointing at the lines of the source
+        let name = appearance.map_or("shader.wgsl".to_owned(), |appearance| {
+            appearance.path().display().to_string()
+        });
+        if let Err(error) = shader_check::validate(&name, &source) {
+            match appearance {
+                Some(appearance) => return Err(appearance.invalid(error.to_string())),
+                None => panic!("{error}"),
+            }
+        }
+        // Caught rather than left to the device's error handler, which panics
+        device.push_error_scope(wgpu::ErrorFilter::Validation);
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Shader"),
+            source: wgpu::ShaderSource::Wgsl(source.as_str().into()),
+        });
+        if let Some(error) = pollster::block_on(device.pop_error_scope()) {
+            match appearance {
+                Some(appearance) => return Err(appearance.invalid(error.to_string())),
+                None => panic!("{error}"),
+            }
+        }
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Render Pipeline Layout"),
+            bind_group_layouts: &[camera_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        #[cfg(feature = "post-processing")]
+        let soft_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("Soft Particles Bind Group Layout"),
+                entries: &soft_particles::BIND_GROUP_LAYOUT_ENTRIES,
+            });
+        #[cfg(feature = "post-processing")]
+        let soft_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Soft Particles Pipeline Layout"),
+            bind_group_layouts: &[camera_bind_group_layout, &soft_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pulled_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("Vertex Pulling Bind Group Layout"),
+                entries: &vertex_pulling::BIND_GROUP_LAYOUT_ENTRIES,
+            });
+        // The instances are bound after the soft particles' depth, which the others leave empty
+        let empty_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("Empty Bind Group Layout"),
+                entries: &[],
+            });
+        let pulled_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Vertex Pulling Pipeline Layout"),
+            bind_group_layouts: &[
+                camera_bind_group_layout,
+                &empty_bind_group_layout,
+                &pulled_bind_group_layout,
+            ],
+            push_constant_ranges: &[],
+        });
+        let empty_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Empty Bind Group"),
+            layout: &empty_bind_group_layout,
+            entries: &[],
+        });
+        #[cfg(feature = "post-processing")]
+        let soft_pulled_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Soft Vertex Pulling Pipeline Layout"),
+            bind_group_layouts: &[
+                camera_bind_group_layout,
+                &soft_bind_group_layout,
+                &pulled_bind_group_layout,
+            ],
+            push_constant_ranges: &[],
+        });
+
+        #[cfg(feature = "guardrails")]
+        {
+            use crate::{camera::CameraUniform, gradient, lights::Light, state};
+
+            let speed_layout = SpeedLayout::of(device);
+            let reflection = crate::guardrails::ShaderReflection::new("shader.wgsl", &source);
+            let [quad_buffers, point_buffers, stretched_buffers, spinning_buffers, splat_buffers] =
+                [
+                    ParticleShape::Quad,
+                    ParticleShape::Point,
+                    ParticleShape::Stretched,
+                    ParticleShape::Spinning,
+                    ParticleShape::Splat,
+                ]
+                .map(|shape| vertex_buffers(shape, speed_layout));
+            reflection.check_vertex_buffers("vs_main", &quad_buffers);
+            reflection.check_vertex_buffers("vs_point", &point_buffers);
+            reflection.check_vertex_buffers("vs_stretched", &stretched_buffers);
+            reflection.check_vertex_buffers("vs_spinning", &spinning_buffers);
+            reflection.check_vertex_buffers("vs_splat", &splat_buffers);
+            reflection.check_vertex_buffers("vs_pulled", &[]);
+            reflection.check_bind_group_layout(0, &state::CAMERA_BIND_GROUP_LAYOUT_ENTRIES);
+            reflection.check_struct_size("CameraUniform", std::mem::size_of::<CameraUniform>());
+            reflection.check_struct_size("Light", std::mem::size_of::<Light>());
+            reflection
+                .check_struct_size("Gradient", std::mem::size_of::<gradient::GradientUniform>());
+            #[cfg(feature = "post-processing")]
+            reflection.check_bind_group_layout(1, &soft_particles::BIND_GROUP_LAYOUT_ENTRIES);
+            reflection.check_bind_group_layout(2, &vertex_pulling::BIND_GROUP_LAYOUT_ENTRIES);
+        }
+
+        Ok(Self {
+            format,
+            depth_compare: depth.compare(false),
+            shader,
+            layout,
+            #[cfg(feature = "post-processing")]
+            soft_bind_group_layout,
+            #[cfg(feature = "post-processing")]
+            soft_layout,
+            pulled_bind_group_layout,
+            pulled_layout,
+            empty_bind_group,
+            #[cfg(feature = "post-processing")]
+            soft_pulled_layout,
+            speed_layout: SpeedLayout::of(device),
+            pipelines: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// The pipeline of `options`, built on first use.
+    pub fn get(&self, device: &wgpu::Device, options: RenderOptions) -> Arc<wgpu::RenderPipeline> {
+        let mut pipelines = self.pipelines.lock().unwrap();
+        pipelines
+            .entry(options)
+            .or_insert_with(|| {
+                log::debug!("Building the particle pipeline for {options:?}");
+                Arc::new(self.create_pipeline(device, options))
+            })
+            .clone()
+    }
+
+    /// Layout of the bind group the soft particle pipelines take at index 1.
+    #[cfg(feature = "post-processing")]
+    pub fn soft_bind_group_layout(&self) -> &wgpu::BindGroupLayout {
+        &self.soft_bind_group_layout
+    }
+
+    /// Layout of the bind group the [`ParticleShape::Pulled`] pipelines take at index 2.
+    pub fn pulled_bind_group_layout(&self) -> &wgpu::BindGroupLayout {
+        &self.pulled_bind_group_layout
+    }
+
+    /// Bind group the [`ParticleShape::Pulled`] pipelines take at index 1 without soft particles.
+    pub fn empty_bind_group(&self) -> &wgpu::BindGroup {
+        &self.empty_bind_group
+    }
+
+    /// Pipelines drawing `shape` for each depth-stencil format of the particles pass, for the far
+    /// particles of the level of detail or the stretched particles.
+    pub fn shape_pipelines(
+        &self,
+        device: &wgpu::Device,
+        shape: ParticleShape,
+        blend_mode: BlendMode,
+    ) -> Vec<(Option<wgpu::TextureFormat>, Arc<wgpu::RenderPipeline>)> {
+        let depth_formats = [
+            None,
+            #[cfg(feature = "post-processing")]
+            Some(DEPTH_FORMAT),
+            #[cfg(feature = "post-processing")]
+            Some(checkerboard::STENCIL_FORMAT),
+        ];
+        depth_formats
+            .into_iter()
+            .map(|depth_format| {
+                let options = RenderOptions {
+                    shape,
+                    blend_mode,
+                    depth_format,
+                    soft: false,
+                };
+                (depth_format, self.get(device, options))
+            })
+            .collect()
+    }
+
+    fn create_pipeline(
+        &self,
+        device: &wgpu::Device,
+        options: RenderOptions,
+    ) -> wgpu::RenderPipeline {
+        let RenderOptions {
+            shape,
+            blend_mode,
+            depth_format,
+            soft,
+        } = options;
+        let buffers = vertex_buffers(shape, self.speed_layout);
+        let has_depth = depth_format.is_some_and(|format| format.has_depth_aspect());
+        let layout = match (shape, soft) {
+            #[cfg(feature = "post-processing")]
+            (ParticleShape::Pulled, true) => &self.soft_pulled_layout,
+            #[cfg(feature = "post-processing")]
+            (_, true) => &self.soft_layout,
+            (ParticleShape::Pulled, _) => &self.pulled_layout,
+            _ => &self.layout,
+        };
+        let fragment_entry_point = match (soft, has_depth) {
+            // Splats add up through the depth test without writing it
+            _ if shape == ParticleShape::Splat => "fs_splat",
+            (true, true) => "fs_soft_depth",
+            (true, false) => "fs_soft",
+            (false, true) => "fs_depth",
+            (false, false) => "fs_main",
+        };
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Render Pipeline"),
+            layout: Some(layout),
+            vertex: wgpu::VertexState {
+                module: &self.shader,
+                entry_point: match shape {
+                    ParticleShape::Quad => "vs_main",
+                    ParticleShape::Point => "vs_point",
+                    ParticleShape::Stretched => "vs_stretched",
+                    ParticleShape::Spinning => "vs_spinning",
+                    ParticleShape::Splat => "vs_splat",
+                    ParticleShape::Pulled => "vs_pulled",
+                },
+                buffers: &buffers,
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &self.shader,
+                entry_point: fragment_entry_point,
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: self.format,
+                    blend: Some(blend_mode.state()),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: if shape == ParticleShape::Point {
+                    wgpu::PrimitiveTopology::PointList
+                } else {
+                    wgpu::PrimitiveTopology::TriangleList
+                },
+                strip_index_format: None,
+                front_face: wgpu::FrontFace::Ccw,
+                // Stretched, spinning and splat quads turn and can face either way
+                cull_mode: if matches!(
+                    shape,
+                    ParticleShape::Stretched | ParticleShape::Spinning | ParticleShape::Splat
+                ) {
+                    None
+                } else {
+                    Some(wgpu::Face::Back)
+                },
+                // Setting this to Line requires Features::POLYGON_MODE_LINE, see debug_view.rs
+                polygon_mode: wgpu::PolygonMode::Fill,
+                // Requires Features::DEPTH_CLIP_CONTROL
+                unclipped_depth: false,
+                // Requires Features::CONSERVATIVE_RASTERIZATION
+                conservative: false,
+            },
+            // Stencil-only formats mask the particles instead, see checkerboard.rs
+            depth_stencil: depth_format.map(|format| wgpu::DepthStencilState {
+                format,
+                depth_write_enabled: format.has_depth_aspect() && shape != ParticleShape::Splat,
+                depth_compare: if format.has_depth_aspect() {
+                    self.depth_compare
+                } else {
+                    wgpu::CompareFunction::Always
+                },
+                stencil: match format.has_stencil_aspect() {
+                    #[cfg(feature = "post-processing")]
+                    true => checkerboard::stencil_state(),
+                    _ => wgpu::StencilState::default(),
+                },
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState {
+                count: 1,
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+            multiview: None,
+        })
+    }
+}
+
+/// Vertex buffers bound to draw `shape`, the velocities in `speed_layout`.
+fn vertex_buffers(
+    shape: ParticleShape,
+    speed_layout: SpeedLayout,
+) -> Vec<wgpu::VertexBufferLayout<'static>> {
+    match shape {
+        ParticleShape::Quad => vec![
+            Vertex::descriptor(),
+            DrawnPosition::descriptor(),
+            InstanceColor::descriptor(),
+        ],
+        ParticleShape::Point => vec![DrawnPosition::descriptor(), InstanceColor::descriptor()],
+        ParticleShape::Pulled => vec![],
+        ParticleShape::Stretched | ParticleShape::Splat => vec![
+            Vertex::descriptor(),
+            DrawnPosition::descriptor(),
+            InstanceColor::descriptor(),
+            Stretched::descriptor(speed_layout),
+        ],
+        ParticleShape::Spinning => vec![
+            Vertex::descriptor(),
+            DrawnPosition::descriptor(),
+            InstanceColor::descriptor(),
+            Spinning::descriptor(),
+        ],
+    }
+}
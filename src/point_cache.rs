@@ -0,0 +1,203 @@
+//! Export of the particle positions as a PC2 point cache, to bring a simulation into Blender, with
+//! the Mesh Cache modifier, or Houdini for offline rendering.
+//!
+//! A PC2 file is a header followed by the positions of every point on each sample, as three
+//! little-endian f32. The header holds, all little-endian:
+//!
+//! | Offset | Type     | Value                                     |
+//! |--------|----------|-------------------------------------------|
+//! | 0      | 12 bytes | `POINTCACHE2` and a NUL                   |
+//! | 12     | i32      | version, 1                                |
+//! | 16     | i32      | points per sample                         |
+//! | 20     | f32      | frame of the first sample, 0              |
+//! | 24     | f32      | frames between samples                    |
+//! | 28     | i32      | samples, written when the cache is closed |
+//!
+//! The points are the particles in their order in the instance buffer, as many as when the export
+//! started. Particles added later aren't exported, the positions of removed ones are zeros. Every
+//! sample is kept: when the GPU falls behind, the frame waits for it rather than skipping one.
+
+use std::{
+    collections::VecDeque,
+    fs::File,
+    io::{self, BufWriter, Seek, SeekFrom, Write},
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+
+const MAGIC: &[u8; 12] = b"POINTCACHE2\0";
+const VERSION: i32 = 1;
+// Offset of the number of samples in the header
+const SAMPLE_COUNT_OFFSET: u64 = 28;
+// Of a position in the instance buffer, w holding the group and age
+const POSITION_SIZE: u64 = 16;
+// Copies waiting to be read before the frame waits for the GPU
+const MAX_IN_FLIGHT: usize = 4;
+
+struct Sample {
+    buffer: wgpu::Buffer,
+    ready: Arc<AtomicBool>,
+    mapped: bool,
+}
+
+pub struct PointCache {
+    file: BufWriter<File>,
+    point_count: usize,
+    interval: u32,
+    // Frames seen since the last sample
+    frame: u32,
+    samples: u32,
+    // Copied, oldest first
+    pending: VecDeque<Sample>,
+    // Read, to copy into again
+    free: Vec<wgpu::Buffer>,
+}
+
+impl PointCache {
+    /// Writes `point_count` points every `interval` frames to `path`, replacing any previous
+    /// cache.
+    pub fn new(path: &Path, point_count: usize, interval: u32) -> io::Result<Self> {
+        let interval = interval.max(1);
+        let mut file = BufWriter::new(File::create(path)?);
+        file.write_all(MAGIC)?;
+        file.write_all(&VERSION.to_le_bytes())?;
+        file.write_all(&(point_count as i32).to_le_bytes())?;
+        file.write_all(&0.0f32.to_le_bytes())?;
+        file.write_all(&(interval as f32).to_le_bytes())?;
+        file.write_all(&0i32.to_le_bytes())?;
+        Ok(Self {
+            file,
+            point_count,
+            interval,
+            frame: 0,
+            samples: 0,
+            pending: VecDeque::new(),
+            free: vec![],
+        })
+    }
+
+    /// Copies the positions on every `interval`th call. Must be followed by
+    /// [`PointCache::map`] once submitted.
+    pub fn copy(
+        &mut self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        position_buffer: &wgpu::Buffer,
+    ) {
+        let sample = self.frame == 0;
+        self.frame = (self.frame + 1) % self.interval;
+        if !sample || self.point_count == 0 {
+            return;
+        }
+
+        let size = self.point_count as u64 * POSITION_SIZE;
+        let buffer = self.free.pop().unwrap_or_else(|| {
+            device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("Point Cache Buffer"),
+                size,
+                usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+                mapped_at_creation: false,
+            })
+        });
+        // The particles removed since the export started are left at the origin
+        let copied = size.min(position_buffer.size());
+        if copied < size {
+            encoder.clear_buffer(&buffer, copied, None);
+        }
+        encoder.copy_buffer_to_buffer(position_buffer, 0, &buffer, 0, copied);
+        self.pending.push_back(Sample {
+            buffer,
+            ready: Arc::new(AtomicBool::new(false)),
+            mapped: false,
+        });
+    }
+
+    /// Starts reading the samples copied since the last call.
+    pub fn map(&mut self) {
+        for sample in self.pending.iter_mut().filter(|sample| !sample.mapped) {
+            let ready = sample.ready.clone();
+            sample
+                .buffer
+                .slice(..)
+                .map_async(wgpu::MapMode::Read, move |result| {
+                    if result.is_ok() {
+                        ready.store(true, Ordering::Release);
+                    }
+                });
+            sample.mapped = true;
+        }
+    }
+
+    /// Writes the samples read back so far, in order, waiting for the GPU if too many are left.
+    pub fn write_ready(&mut self, device: &wgpu::Device) -> io::Result<()> {
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+        let wait = self.pending.len() > MAX_IN_FLIGHT;
+        device.poll(if wait {
+            wgpu::Maintain::Wait
+        } else {
+            wgpu::Maintain::Poll
+        });
+        while self
+            .pending
+            .front()
+            .is_some_and(|sample| sample.ready.load(Ordering::Acquire))
+        {
+            let sample = self.pending.pop_front().unwrap();
+            {
+                let data = sample.buffer.slice(..).get_mapped_range();
+                let positions: &[[f32; 4]] = bytemuck::cast_slice(&data);
+                for position in positions {
+                    for value in &position[..3] {
+                        self.file.write_all(&value.to_le_bytes())?;
+                    }
+                }
+            }
+            sample.buffer.unmap();
+            self.free.push(sample.buffer);
+            self.samples += 1;
+        }
+        Ok(())
+    }
+
+    /// Waits for the samples in flight, writes them and the number of samples. Returns it.
+    pub fn finish(mut self, device: &wgpu::Device) -> io::Result<u32> {
+        self.map();
+        device.poll(wgpu::Maintain::Wait);
+        self.write_ready(device)?;
+        self.file.seek(SeekFrom::Start(SAMPLE_COUNT_OFFSET))?;
+        self.file.write_all(&(self.samples as i32).to_le_bytes())?;
+        self.file.flush()?;
+        Ok(self.samples)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn header_holds_the_clamped_interval() {
+        let path =
+            std::env::temp_dir().join(format!("particles-header-{}.pc2", std::process::id()));
+        drop(PointCache::new(&path, 3, 0).unwrap());
+        let header = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let mut expected = MAGIC.to_vec();
+        for value in [
+            VERSION.to_le_bytes(),
+            3i32.to_le_bytes(),
+            0.0f32.to_le_bytes(),
+            1.0f32.to_le_bytes(),
+            0i32.to_le_bytes(),
+        ] {
+            expected.extend(value);
+        }
+        assert_eq!(header, expected);
+    }
+}
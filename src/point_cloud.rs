@@ -0,0 +1,347 @@
+//! Point clouds shown as the particles, from `--points`, instead of spawning them in the emitter.
+//!
+//! PLY files are read in ASCII or binary, from the `x`, `y` and `z` properties of their `vertex`
+//! element and its `red`, `green` and `blue` ones if any, as bytes or floats. LAS files are read up
+//! to version 1.4, with the colors of the point formats that have them. Points without colors take
+//! the palette's.
+//!
+//! Survey coordinates are far from the origin and precise to the millimeter, so the points are
+//! centered in double precision, then scaled to fit the view, before being turned into floats.
+//!
+//! The files are only read with the `asset-loaders` feature, [`PointCloud`] itself also holds the
+//! built-in distributions.
+
+#[cfg(feature = "asset-loaders")]
+use std::{
+    io::{self, BufRead, Cursor, Read},
+    path::{Path, PathBuf},
+};
+
+#[cfg(feature = "asset-loaders")]
+use glam::DVec3;
+use glam::{Vec3, Vec4};
+
+// Radius of the sphere the cloud is scaled to fit in, about the size of the default emitter
+#[cfg(feature = "asset-loaders")]
+const FIT_RADIUS: f64 = 500.0;
+
+#[cfg(feature = "asset-loaders")]
+#[derive(Debug, thiserror::Error)]
+pub enum PointCloudError {
+    #[error("unable to read the point cloud {0}: {1}")]
+    Io(PathBuf, io::Error),
+    #[error("invalid point cloud {0}: {1}")]
+    Invalid(PathBuf, String),
+    #[error("{0} isn't a .ply or .las point cloud")]
+    UnknownFormat(PathBuf),
+}
+
+/// Particles read from a file instead of spawned, see also particle_data.rs.
+pub struct PointCloud {
+    pub positions: Vec<Vec3>,
+    /// One per position, if the file has colors
+    pub colors: Option<Vec<Vec4>>,
+    /// One per position, if the file has speeds, the particles stand still otherwise
+    pub speeds: Option<Vec<Vec3>>,
+}
+
+impl PointCloud {
+    #[cfg(feature = "asset-loaders")]
+    pub fn load(path: &Path) -> Result<Self, PointCloudError> {
+        let extension = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map(str::to_ascii_lowercase);
+        let contents = std::fs::read(path).map_err(|e| PointCloudError::Io(path.to_owned(), e))?;
+        let (positions, colors) = match extension.as_deref() {
+            Some("ply") => read_ply(&contents),
+            Some("las") => read_las(&contents),
+            _ => return Err(PointCloudError::UnknownFormat(path.to_owned())),
+        }
+        .map_err(|e| PointCloudError::Invalid(path.to_owned(), e))?;
+        if positions.is_empty() {
+            return Err(PointCloudError::Invalid(
+                path.to_owned(),
+                "no points".to_owned(),
+            ));
+        }
+        log::info!("Loaded {} points from {}", positions.len(), path.display());
+        Ok(Self {
+            positions: fit(&positions),
+            colors,
+            speeds: None,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.positions.len()
+    }
+}
+
+/// `positions` centered on their bounding box and scaled to fit in `FIT_RADIUS`.
+#[cfg(feature = "asset-loaders")]
+pub fn fit(positions: &[DVec3]) -> Vec<Vec3> {
+    let (min, max) = positions.iter().fold(
+        (DVec3::splat(f64::MAX), DVec3::splat(f64::MIN)),
+        |(min, max), &position| (min.min(position), max.max(position)),
+    );
+    let center = (min + max) / 2.0;
+    let radius = ((max - min).length() / 2.0).max(f64::EPSILON);
+    let scale = FIT_RADIUS / radius;
+    log::info!(
+        "Points centered on ({:.3}, {:.3}, {:.3}) and scaled by {scale:.3}",
+        center.x,
+        center.y,
+        center.z
+    );
+    positions
+        .iter()
+        .map(|&position| ((position - center) * scale).as_vec3())
+        .collect()
+}
+
+#[cfg(feature = "asset-loaders")]
+type Points = (Vec<DVec3>, Option<Vec<Vec4>>);
+
+#[cfg(feature = "asset-loaders")]
+#[derive(Clone, Copy, PartialEq)]
+enum PlyFormat {
+    Ascii,
+    LittleEndian,
+    BigEndian,
+}
+
+#[cfg(feature = "asset-loaders")]
+struct PlyProperty {
+    name: String,
+    // Bytes of the binary value, and whether it's an integer
+    size: usize,
+    integer: bool,
+    signed: bool,
+}
+
+#[cfg(feature = "asset-loaders")]
+fn ply_property(kind: &str, name: &str) -> Result<PlyProperty, String> {
+    let (size, integer, signed) = match kind {
+        "char" | "int8" => (1, true, true),
+        "uchar" | "uint8" => (1, true, false),
+        "short" | "int16" => (2, true, true),
+        "ushort" | "uint16" => (2, true, false),
+        "int" | "int32" => (4, true, true),
+        "uint" | "uint32" => (4, true, false),
+        "float" | "float32" => (4, false, true),
+        "double" | "float64" => (8, false, true),
+        _ => return Err(format!("unknown property type `{kind}`")),
+    };
+    Ok(PlyProperty {
+        name: name.to_owned(),
+        size,
+        integer,
+        signed,
+    })
+}
+
+#[cfg(feature = "asset-loaders")]
+fn read_ply(contents: &[u8]) -> Result<Points, String> {
+    let mut reader = Cursor::new(contents);
+    let mut line = String::new();
+    let mut next_line = |reader: &mut Cursor<&[u8]>| -> Result<String, String> {
+        line.clear();
+        match reader.read_line(&mut line) {
+            Ok(0) => Err("unexpected end of the header".to_owned()),
+            Ok(_) => Ok(line.trim().to_owned()),
+            Err(e) => Err(e.to_string()),
+        }
+    };
+    if next_line(&mut reader)? != "ply" {
+        return Err("missing the `ply` magic number".to_owned());
+    }
+
+    let mut format = None;
+    // Vertex count and properties, which must be the first element to be read
+    let mut vertices: Option<(usize, Vec<PlyProperty>)> = None;
+    let mut other_element = false;
+    loop {
+        let line = next_line(&mut reader)?;
+        let words = line.split_whitespace().collect::<Vec<_>>();
+        match words.as_slice() {
+            ["end_header"] => break,
+            ["format", "ascii", _] => format = Some(PlyFormat::Ascii),
+            ["format", "binary_little_endian", _] => format = Some(PlyFormat::LittleEndian),
+            ["format", "binary_big_endian", _] => format = Some(PlyFormat::BigEndian),
+            ["element", "vertex", count] => {
+                if other_element {
+                    return Err("the vertex element must come first".to_owned());
+                }
+                let count = count.parse().map_err(|_| "invalid vertex count")?;
+                vertices = Some((count, vec![]));
+            }
+            ["element", ..] => other_element = true,
+            ["property", "list", ..] if !other_element => {
+                return Err("vertices can't have list properties".to_owned());
+            }
+            ["property", kind, name] if !other_element => {
+                let (_, properties) = vertices.as_mut().ok_or("property outside an element")?;
+                properties.push(ply_property(kind, name)?);
+            }
+            _ => {}
+        }
+    }
+    let format = format.ok_or("missing the format")?;
+    let (count, properties) = vertices.ok_or("missing the vertex element")?;
+    let index_of = |name: &str| properties.iter().position(|property| property.name == name);
+    let [x, y, z] = ["x", "y", "z"].map(index_of);
+    let (Some(x), Some(y), Some(z)) = (x, y, z) else {
+        return Err("vertices need x, y and z".to_owned());
+    };
+    let color_indices = match ["red", "green", "blue"].map(index_of) {
+        [Some(red), Some(green), Some(blue)] => Some([red, green, blue]),
+        _ => None,
+    };
+
+    let mut text = String::new();
+    if format == PlyFormat::Ascii {
+        reader
+            .read_to_string(&mut text)
+            .map_err(|e| e.to_string())?;
+    }
+    let mut words = text.split_whitespace();
+    let mut values = vec![0.0; properties.len()];
+    let mut positions = Vec::with_capacity(count);
+    let mut colors = color_indices.map(|_| Vec::with_capacity(count));
+    for _ in 0..count {
+        for (value, property) in values.iter_mut().zip(&properties) {
+            *value = match format {
+                PlyFormat::Ascii => words
+                    .next()
+                    .ok_or("fewer vertices than declared")?
+                    .parse()
+                    .map_err(|_| "invalid vertex value")?,
+                _ => read_binary(&mut reader, property, format == PlyFormat::BigEndian)
+                    .map_err(|_| "fewer vertices than declared")?,
+            };
+        }
+        positions.push(DVec3::new(values[x], values[y], values[z]));
+        if let (Some(colors), Some(indices)) = (&mut colors, color_indices) {
+            // Byte colors go up to 255, float ones to 1
+            let color = indices.map(|index| {
+                let value = values[index] as f32;
+                if properties[index].integer {
+                    value / 255.0
+                } else {
+                    value
+                }
+            });
+            colors.push(Vec3::from(color).extend(1.0));
+        }
+    }
+    Ok((positions, colors))
+}
+
+#[cfg(feature = "asset-loaders")]
+fn read_binary(
+    reader: &mut impl Read,
+    property: &PlyProperty,
+    big_endian: bool,
+) -> io::Result<f64> {
+    let mut bytes = [0; 8];
+    let bytes = &mut bytes[..property.size];
+    reader.read_exact(bytes)?;
+    if big_endian {
+        bytes.reverse();
+    }
+    let mut padded = [0; 8];
+    padded[..property.size].copy_from_slice(bytes);
+    // Sign-extended from the highest byte read
+    if property.integer && property.signed && bytes[property.size - 1] & 0x80 != 0 {
+        padded[property.size..].fill(0xff);
+    }
+    let bits = u64::from_le_bytes(padded);
+    Ok(match (property.integer, property.signed, property.size) {
+        (true, true, _) => bits as i64 as f64,
+        (true, false, _) => bits as f64,
+        (false, _, 4) => f32::from_bits(bits as u32) as f64,
+        _ => f64::from_bits(bits),
+    })
+}
+
+#[cfg(feature = "asset-loaders")]
+fn read_las(contents: &[u8]) -> Result<Points, String> {
+    let too_short = || "truncated file".to_owned();
+    let bytes =
+        |offset: usize, size: usize| contents.get(offset..offset + size).ok_or_else(too_short);
+    let u8_at = |offset| bytes(offset, 1).map(|bytes| bytes[0]);
+    let u16_at = |offset| bytes(offset, 2).map(|bytes| u16::from_le_bytes([bytes[0], bytes[1]]));
+    let u32_at =
+        |offset| bytes(offset, 4).map(|bytes| u32::from_le_bytes(bytes.try_into().unwrap()));
+    let u64_at =
+        |offset| bytes(offset, 8).map(|bytes| u64::from_le_bytes(bytes.try_into().unwrap()));
+    let f64_at =
+        |offset| bytes(offset, 8).map(|bytes| f64::from_le_bytes(bytes.try_into().unwrap()));
+    let dvec3_at = |offset| -> Result<DVec3, String> {
+        Ok(DVec3::new(
+            f64_at(offset)?,
+            f64_at(offset + 8)?,
+            f64_at(offset + 16)?,
+        ))
+    };
+
+    if bytes(0, 4)? != b"LASF" {
+        return Err("missing the `LASF` signature".to_owned());
+    }
+    let (major, minor) = (u8_at(24)?, u8_at(25)?);
+    if major != 1 || minor > 4 {
+        return Err(format!("unsupported LAS version {major}.{minor}"));
+    }
+    let point_offset = u32_at(96)? as usize;
+    // The high bits flag compressed points
+    let point_format = u8_at(104)? & 0x3f;
+    let record_length = u16_at(105)? as usize;
+    let mut count = u32_at(107)? as u64;
+    if count == 0 && minor >= 4 {
+        count = u64_at(247)?;
+    }
+    let scale = dvec3_at(131)?;
+    let offset = dvec3_at(155)?;
+    // Start of the red, green and blue of a point record
+    let color_offset = match point_format {
+        2 => Some(20),
+        3 | 5 => Some(28),
+        7 | 8 | 10 => Some(30),
+        0 | 1 | 4 | 6 | 9 => None,
+        _ => return Err(format!("unsupported point format {point_format}")),
+    };
+    if record_length < color_offset.map_or(12, |offset| offset + 6) {
+        return Err(format!(
+            "point records of {record_length} bytes are too short"
+        ));
+    }
+
+    let count = count as usize;
+    let mut positions = Vec::with_capacity(count);
+    let mut colors = color_offset.map(|_| Vec::with_capacity(count));
+    for index in 0..count {
+        let record = bytes(point_offset + index * record_length, record_length)?;
+        let coordinate = |offset: usize| {
+            i32::from_le_bytes(record[offset..offset + 4].try_into().unwrap()) as f64
+        };
+        positions.push(DVec3::new(coordinate(0), coordinate(4), coordinate(8)) * scale + offset);
+        if let (Some(colors), Some(color_offset)) = (&mut colors, color_offset) {
+            let channel = |channel: usize| {
+                let start = color_offset + channel * 2;
+                u16::from_le_bytes([record[start], record[start + 1]])
+            };
+            colors.push([channel(0), channel(1), channel(2)]);
+        }
+    }
+    // Colors are meant to span 16 bits, but many writers leave them on 8
+    let colors = colors.map(|colors| {
+        let max = colors.iter().flatten().copied().max().unwrap_or(0);
+        let range = if max <= 255 { 255.0 } else { 65535.0 };
+        colors
+            .iter()
+            .map(|color| (Vec3::from(color.map(f32::from)) / range).extend(1.0))
+            .collect()
+    });
+    Ok((positions, colors))
+}
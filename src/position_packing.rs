@@ -0,0 +1,223 @@
+//! Packs the positions drawn by the particle pipelines in half floats, on the frames the compute
+//! kernel doesn't, with the `packed-instances` feature.
+//!
+//! The compute kernel integrates the positions in f32, a half float loses the small steps of a
+//! frame, and writes them in halves to the drawn position buffer as it goes. The draws fetch half
+//! the bytes through `Float16x4` attributes. While the kernel doesn't run, paused or simulating on
+//! the CPU, this pass packs them instead. Counts too large for a single storage binding are packed
+//! in chunks.
+
+use crate::{
+    shader_check,
+    vertex::{InstancePosition, PackedPosition, INSTANCE_POSITION_WGSL},
+};
+
+// Must match `@workgroup_size` in position_packing.wgsl
+const WORKGROUP_SIZE: u32 = 64;
+
+pub struct PositionPacker {
+    bind_group_layout: wgpu::BindGroupLayout,
+    pipeline: wgpu::ComputePipeline,
+}
+
+impl PositionPacker {
+    pub fn new(device: &wgpu::Device) -> Self {
+        let storage_entry = |binding, read_only| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let bind_group_layout_entries = [storage_entry(0, true), storage_entry(1, false)];
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Position Packing Bind Group Layout"),
+            entries: &bind_group_layout_entries,
+        });
+
+        let source = shader_source();
+        let shader = shader_check::create_module(
+            device,
+            "Position Packing Shader",
+            "position_packing.wgsl",
+            &source,
+        );
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Position Packing Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Position Packing Pipeline"),
+            layout: Some(&pipeline_layout),
+            module: &shader,
+            entry_point: "main",
+        });
+
+        #[cfg(feature = "guardrails")]
+        {
+            let reflection =
+                crate::guardrails::ShaderReflection::new("position_packing.wgsl", &source);
+            reflection.check_bind_group_layout(0, &bind_group_layout_entries);
+        }
+
+        Self {
+            bind_group_layout,
+            pipeline,
+        }
+    }
+
+    /// Packs every position of `position_buffer` into `packed_buffer`, of as many positions, see
+    /// [`crate::vertex::create_drawn_position_buffer`].
+    pub fn encode(
+        &self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        position_buffer: &wgpu::Buffer,
+        packed_buffer: &wgpu::Buffer,
+    ) {
+        let count = position_buffer.size() as usize / std::mem::size_of::<InstancePosition>();
+        #[cfg(feature = "guardrails")]
+        crate::guardrails::check_buffer_size(
+            "Drawn Position Buffer",
+            packed_buffer,
+            count.max(1),
+            std::mem::size_of::<PackedPosition>(),
+        );
+
+        let binding = |buffer, first: usize, len: usize, element_size: usize| {
+            wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                buffer,
+                offset: (first * element_size) as u64,
+                size: wgpu::BufferSize::new((len * element_size) as u64),
+            })
+        };
+        let per_binding = Self::per_binding(device);
+        let chunks = (0..count)
+            .step_by(per_binding)
+            .map(|first| {
+                let len = per_binding.min(count - first);
+                let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                    label: Some("Position Packing Bind Group"),
+                    layout: &self.bind_group_layout,
+                    entries: &[
+                        wgpu::BindGroupEntry {
+                            binding: 0,
+                            resource: binding(
+                                position_buffer,
+                                first,
+                                len,
+                                std::mem::size_of::<InstancePosition>(),
+                            ),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 1,
+                            resource: binding(
+                                packed_buffer,
+                                first,
+                                len,
+                                std::mem::size_of::<PackedPosition>(),
+                            ),
+                        },
+                    ],
+                });
+                (bind_group, len)
+            })
+            .collect::<Vec<_>>();
+
+        let max_groups = device.limits().max_compute_workgroups_per_dimension;
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Position Packing Pass"),
+        });
+        compute_pass.set_pipeline(&self.pipeline);
+        for (bind_group, len) in &chunks {
+            let groups = (*len as u32).div_ceil(WORKGROUP_SIZE);
+            let (x, y) = (groups.min(max_groups), groups.div_ceil(max_groups));
+            #[cfg(feature = "guardrails")]
+            crate::guardrails::check_dispatch_coverage([x, y, 1], [WORKGROUP_SIZE, 1, 1], *len);
+            compute_pass.set_bind_group(0, bind_group, &[]);
+            compute_pass.dispatch_workgroups(x, y, 1);
+        }
+    }
+
+    /// Positions packed per binding, so both bindings of a chunk start at a storage offset
+    /// alignment and fit in a storage binding.
+    fn per_binding(device: &wgpu::Device) -> usize {
+        let limits = device.limits();
+        let alignment = (limits.min_storage_buffer_offset_alignment as usize)
+            .div_ceil(std::mem::size_of::<PackedPosition>())
+            .max(1);
+        let max = limits.max_storage_buffer_binding_size as usize
+            / std::mem::size_of::<InstancePosition>();
+        (max / alignment * alignment).max(alignment)
+    }
+}
+
+/// The source of the shader, after the instance positions.
+pub fn shader_source() -> String {
+    format!(
+        "{INSTANCE_POSITION_WGSL}\n{}",
+        include_str!("position_packing.wgsl")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        test_device::device,
+        vertex::{create_drawn_position_buffer, Instance},
+    };
+    use wgpu::util::DeviceExt;
+
+    #[test]
+    fn packs_like_the_cpu() {
+        let Some((device, queue)) = device() else {
+            return;
+        };
+        let instances: Vec<_> = (0..1000)
+            .map(|i| Instance {
+                position: glam::vec3(i as f32 * 0.37 - 150.0, (i * i) as f32 * 1e-3, -2.5),
+                color: glam::Vec4::ONE,
+                group: i % 3,
+            })
+            .collect();
+        let positions: Vec<_> = instances.iter().map(Instance::to_position).collect();
+        let position_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: None,
+            contents: bytemuck::cast_slice(&positions),
+            usage: wgpu::BufferUsages::STORAGE,
+        });
+        let packer = PositionPacker::new(&device);
+        let packed_buffer = create_drawn_position_buffer(&device, instances.len()).unwrap();
+        let size = (instances.len() * std::mem::size_of::<PackedPosition>()) as u64;
+        let readback_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
+        packer.encode(&device, &mut encoder, &position_buffer, &packed_buffer);
+        encoder.copy_buffer_to_buffer(&packed_buffer, 0, &readback_buffer, 0, size);
+        queue.submit(Some(encoder.finish()));
+
+        readback_buffer
+            .slice(..)
+            .map_async(wgpu::MapMode::Read, |result| result.unwrap());
+        device.poll(wgpu::Maintain::Wait);
+        let data = readback_buffer.slice(..).get_mapped_range();
+        let packed: &[PackedPosition] = bytemuck::cast_slice(&data);
+        for (instance, packed) in instances.iter().zip(packed) {
+            let (gpu, cpu) = (packed.position(), instance.to_drawn_position().position());
+            // Within the last bit of a half, whichever way the GPU rounds
+            assert!(
+                (gpu - cpu).abs().cmple(cpu.abs() / 1024.0).all(),
+                "packed {gpu} on the GPU, {cpu} on the CPU"
+            );
+        }
+    }
+}
@@ -0,0 +1,21 @@
+// Copies the positions the compute passes integrate into the buffer the particles are drawn from
+
+@group(0) @binding(0)
+var<storage, read> positions: array<vec4<f32>>;
+
+// PackedPosition and pack_position are declared by INSTANCE_POSITION_WGSL in vertex.rs
+@group(0) @binding(1)
+var<storage, read_write> packed_positions: array<PackedPosition>;
+
+@compute @workgroup_size(64)
+fn main(
+    @builtin(global_invocation_id) id: vec3<u32>,
+    @builtin(num_workgroups) workgroups: vec3<u32>,
+) {
+    // Large counts are dispatched in rows, a single dimension is limited to 65535 workgroups
+    let index = id.x + id.y * workgroups.x * 64u;
+    if index >= arrayLength(&positions) {
+        return;
+    }
+    packed_positions[index] = pack_position(positions[index]);
+}
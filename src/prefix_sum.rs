@@ -0,0 +1,310 @@
+//! Exclusive prefix sum of a buffer of `u32`s on the GPU, a building block for compaction, sorting
+//! and grid construction.
+//!
+//! Each block of [`BLOCK_SIZE`] values is scanned in workgroup memory and its total written to a
+//! buffer of block sums, which is scanned the same way, recursively, until it fits in one block.
+//! The scanned sums are then added back to their blocks, from the coarsest level down. Sums wrap
+//! around on overflow.
+
+use bytemuck::{Pod, Zeroable};
+use wgpu::util::DeviceExt;
+
+/// Values scanned by each workgroup, two per invocation. Must match BLOCK_SIZE in prefix_sum.wgsl
+pub const BLOCK_SIZE: usize = 512;
+
+// Must match Params in prefix_sum.wgsl
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Pod, Zeroable)]
+struct ScanParams {
+    len: u32,
+    _padding: [u32; 3],
+}
+
+pub struct PrefixSum {
+    max_len: usize,
+    // Block sums of each level, the last one a single block
+    sums_buffers: Vec<wgpu::Buffer>,
+    bind_group_layout: wgpu::BindGroupLayout,
+    scan_pipeline: wgpu::ComputePipeline,
+    add_pipeline: wgpu::ComputePipeline,
+}
+
+impl PrefixSum {
+    /// Scans buffers of up to `max_len` values.
+    pub fn new(device: &wgpu::Device, max_len: usize) -> Self {
+        let storage_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only: false },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let bind_group_layout_entries = [
+            storage_entry(0),
+            storage_entry(1),
+            wgpu::BindGroupLayoutEntry {
+                binding: 2,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+        ];
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Prefix Sum Bind Group Layout"),
+            entries: &bind_group_layout_entries,
+        });
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Prefix Sum Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("prefix_sum.wgsl").into()),
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Prefix Sum Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let create_pipeline = |entry_point| {
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some("Prefix Sum Pipeline"),
+                layout: Some(&pipeline_layout),
+                module: &shader,
+                entry_point,
+            })
+        };
+
+        #[cfg(feature = "guardrails")]
+        {
+            let reflection = crate::guardrails::ShaderReflection::new(
+                "prefix_sum.wgsl",
+                include_str!("prefix_sum.wgsl"),
+            );
+            reflection.check_bind_group_layout(0, &bind_group_layout_entries);
+            reflection.check_struct_size("Params", std::mem::size_of::<ScanParams>());
+        }
+
+        let sums_buffers = level_lens(max_len)
+            .map(|len| {
+                device.create_buffer(&wgpu::BufferDescriptor {
+                    label: Some("Prefix Sum Block Sums Buffer"),
+                    size: (len.div_ceil(BLOCK_SIZE) * std::mem::size_of::<u32>()) as u64,
+                    usage: wgpu::BufferUsages::STORAGE,
+                    mapped_at_creation: false,
+                })
+            })
+            .collect();
+
+        Self {
+            max_len,
+            sums_buffers,
+            bind_group_layout,
+            scan_pipeline: create_pipeline("scan_blocks"),
+            add_pipeline: create_pipeline("add_block_sums"),
+        }
+    }
+
+    /// Replaces the first `len` values of `buffer`, a `STORAGE` buffer of `u32`s, by the sum of
+    /// the values before them.
+    pub fn scan(
+        &self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        buffer: &wgpu::Buffer,
+        len: usize,
+    ) {
+        assert!(
+            len <= self.max_len,
+            "scanning {len} values, at most {} fit",
+            self.max_len
+        );
+        if len == 0 {
+            return;
+        }
+
+        // The values of each level are the block sums of the one before
+        let levels: Vec<_> = level_lens(len)
+            .zip(std::iter::once(buffer).chain(&self.sums_buffers))
+            .zip(&self.sums_buffers)
+            .map(|((len, values), sums)| {
+                let params = ScanParams {
+                    len: len as u32,
+                    _padding: [0; 3],
+                };
+                let params_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                    label: Some("Prefix Sum Params Buffer"),
+                    contents: bytemuck::bytes_of(&params),
+                    usage: wgpu::BufferUsages::UNIFORM,
+                });
+                let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                    label: Some("Prefix Sum Bind Group"),
+                    layout: &self.bind_group_layout,
+                    entries: &[
+                        wgpu::BindGroupEntry {
+                            binding: 0,
+                            resource: values.as_entire_binding(),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 1,
+                            resource: sums.as_entire_binding(),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 2,
+                            resource: params_buffer.as_entire_binding(),
+                        },
+                    ],
+                });
+                (len, bind_group)
+            })
+            .collect();
+
+        let max_groups = device.limits().max_compute_workgroups_per_dimension;
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Prefix Sum Pass"),
+        });
+        // Every level is scanned from the finest, then offset by its scanned block sums from the
+        // coarsest. The last level is a single block, already offset
+        let scans = levels.iter().map(|level| (&self.scan_pipeline, level));
+        let adds = levels
+            .iter()
+            .rev()
+            .skip(1)
+            .map(|level| (&self.add_pipeline, level));
+        for (pipeline, (len, bind_group)) in scans.chain(adds) {
+            let groups = len.div_ceil(BLOCK_SIZE) as u32;
+            let (x, y) = (groups.min(max_groups), groups.div_ceil(max_groups));
+            #[cfg(feature = "guardrails")]
+            crate::guardrails::check_dispatch_coverage([x, y, 1], [BLOCK_SIZE as u32, 1, 1], *len);
+            compute_pass.set_pipeline(pipeline);
+            compute_pass.set_bind_group(0, bind_group, &[]);
+            compute_pass.dispatch_workgroups(x, y, 1);
+        }
+    }
+}
+
+/// Number of values of each level scanning `len`, down to the one fitting in a block.
+fn level_lens(len: usize) -> impl Iterator<Item = usize> {
+    let mut next = Some(len);
+    std::iter::from_fn(move || {
+        let len = next?;
+        next = (len > BLOCK_SIZE).then(|| len.div_ceil(BLOCK_SIZE));
+        Some(len)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_device::device;
+
+    /// What the GPU should compute.
+    fn exclusive_scan(values: &[u32]) -> Vec<u32> {
+        values
+            .iter()
+            .scan(0u32, |sum, &value| {
+                let before = *sum;
+                *sum = sum.wrapping_add(value);
+                Some(before)
+            })
+            .collect()
+    }
+
+    /// Scans the first `len` of `values` on the GPU, returning all of them.
+    fn gpu_scan(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        values: &[u32],
+        len: usize,
+    ) -> Vec<u32> {
+        let prefix_sum = PrefixSum::new(device, values.len());
+        let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: None,
+            contents: bytemuck::cast_slice(values),
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+        });
+        let readback_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size: buffer.size(),
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
+        prefix_sum.scan(device, &mut encoder, &buffer, len);
+        encoder.copy_buffer_to_buffer(&buffer, 0, &readback_buffer, 0, buffer.size());
+        queue.submit(Some(encoder.finish()));
+
+        readback_buffer
+            .slice(..)
+            .map_async(wgpu::MapMode::Read, |result| result.unwrap());
+        device.poll(wgpu::Maintain::Wait);
+        let scanned = bytemuck::cast_slice(&readback_buffer.slice(..).get_mapped_range()).to_vec();
+        scanned
+    }
+
+    #[test]
+    fn levels_shrink_to_one_block() {
+        assert_eq!(level_lens(1).collect::<Vec<_>>(), [1]);
+        assert_eq!(level_lens(BLOCK_SIZE).collect::<Vec<_>>(), [BLOCK_SIZE]);
+        assert_eq!(
+            level_lens(BLOCK_SIZE + 1).collect::<Vec<_>>(),
+            [BLOCK_SIZE + 1, 2]
+        );
+        let len = BLOCK_SIZE * BLOCK_SIZE + 7;
+        assert_eq!(
+            level_lens(len).collect::<Vec<_>>(),
+            [len, BLOCK_SIZE + 1, 2]
+        );
+    }
+
+    #[test]
+    fn matches_the_cpu_scan() {
+        let Some((device, queue)) = device() else {
+            eprintln!("No adapter, skipping the GPU prefix sum");
+            return;
+        };
+        let mut seed = 1u32;
+        for len in [
+            1,
+            7,
+            BLOCK_SIZE - 1,
+            BLOCK_SIZE,
+            BLOCK_SIZE + 1,
+            100_000,
+            300_007,
+        ] {
+            let values: Vec<u32> = (0..len)
+                .map(|_| {
+                    // xorshift, large enough values to wrap around
+                    seed ^= seed << 13;
+                    seed ^= seed >> 17;
+                    seed ^= seed << 5;
+                    seed % 100_000
+                })
+                .collect();
+            assert_eq!(
+                gpu_scan(&device, &queue, &values, len),
+                exclusive_scan(&values),
+                "{len} values"
+            );
+        }
+    }
+
+    #[test]
+    fn scans_part_of_the_buffer() {
+        let Some((device, queue)) = device() else {
+            eprintln!("No adapter, skipping the GPU prefix sum");
+            return;
+        };
+        let values = vec![1u32; 2000];
+        let scanned = gpu_scan(&device, &queue, &values, 1500);
+
+        // The values past `len` are left as they were
+        assert_eq!(scanned[..1500], exclusive_scan(&values[..1500]));
+        assert!(scanned[1500..].iter().all(|&value| value == 1));
+    }
+}
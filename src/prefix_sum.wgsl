@@ -0,0 +1,107 @@
+// Exclusive prefix sum of u32s, in blocks of 512 scanned in workgroup memory (Blelloch). The sums
+// of the blocks are scanned the same way, then added back to every element of their block
+
+struct Params {
+    len: u32,
+    _padding0: u32,
+    _padding1: u32,
+    _padding2: u32,
+}
+
+@group(0) @binding(0)
+var<storage, read_write> values: array<u32>;
+
+// One per block of `values`, its total after `scan_blocks` and its offset for `add_block_sums`
+@group(0) @binding(1)
+var<storage, read_write> block_sums: array<u32>;
+
+@group(0) @binding(2)
+var<uniform> params: Params;
+
+// Must match BLOCK_SIZE in prefix_sum.rs, two values per invocation
+const BLOCK_SIZE: u32 = 512u;
+
+var<workgroup> block: array<u32, 512>;
+
+// Large counts are dispatched in rows, a single dimension is limited to 65535 workgroups
+fn block_index(workgroup: vec3<u32>, workgroups: vec3<u32>) -> u32 {
+    return workgroup.x + workgroup.y * workgroups.x;
+}
+
+@compute @workgroup_size(256)
+fn scan_blocks(
+    @builtin(local_invocation_index) local: u32,
+    @builtin(workgroup_id) workgroup: vec3<u32>,
+    @builtin(num_workgroups) workgroups: vec3<u32>,
+) {
+    let start = block_index(workgroup, workgroups) * BLOCK_SIZE;
+    let first = start + local * 2u;
+    // Every invocation reaches the barriers, the ones past the end scan zeroes
+    block[local * 2u] = 0u;
+    block[local * 2u + 1u] = 0u;
+    if first < params.len {
+        block[local * 2u] = values[first];
+    }
+    if first + 1u < params.len {
+        block[local * 2u + 1u] = values[first + 1u];
+    }
+
+    // Up-sweep: sums of ever larger spans, the whole block's in the last element
+    var stride = 1u;
+    for (var span_count = BLOCK_SIZE / 2u; span_count > 0u; span_count /= 2u) {
+        workgroupBarrier();
+        if local < span_count {
+            let left = stride * (local * 2u + 1u) - 1u;
+            let right = stride * (local * 2u + 2u) - 1u;
+            block[right] += block[left];
+        }
+        stride *= 2u;
+    }
+
+    if local == 0u {
+        if start < params.len {
+            block_sums[start / BLOCK_SIZE] = block[BLOCK_SIZE - 1u];
+        }
+        block[BLOCK_SIZE - 1u] = 0u;
+    }
+
+    // Down-sweep: each span passes its offset to its left half, and adds the left half's sum to
+    // its right half
+    for (var span_count = 1u; span_count < BLOCK_SIZE; span_count *= 2u) {
+        stride /= 2u;
+        workgroupBarrier();
+        if local < span_count {
+            let left = stride * (local * 2u + 1u) - 1u;
+            let right = stride * (local * 2u + 2u) - 1u;
+            let sum = block[left];
+            block[left] = block[right];
+            block[right] += sum;
+        }
+    }
+    workgroupBarrier();
+
+    if first < params.len {
+        values[first] = block[local * 2u];
+    }
+    if first + 1u < params.len {
+        values[first + 1u] = block[local * 2u + 1u];
+    }
+}
+
+@compute @workgroup_size(256)
+fn add_block_sums(
+    @builtin(local_invocation_index) local: u32,
+    @builtin(workgroup_id) workgroup: vec3<u32>,
+    @builtin(num_workgroups) workgroups: vec3<u32>,
+) {
+    let index = block_index(workgroup, workgroups);
+    let first = index * BLOCK_SIZE + local * 2u;
+    if first >= params.len {
+        return;
+    }
+    let offset = block_sums[index];
+    values[first] += offset;
+    if first + 1u < params.len {
+        values[first + 1u] += offset;
+    }
+}
@@ -0,0 +1,153 @@
+//! Query set with the buffers its results are resolved into and read back from, so measuring never
+//! stalls a frame.
+//!
+//! Each frame resolves into the next of [`FRAMES_IN_FLIGHT`] pairs of buffers and maps its readback
+//! buffer once submitted. Results arrive about two frames late; a frame finding its buffers still
+//! mapped isn't measured.
+
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+/// Frames whose results can be read back at the same time.
+pub const FRAMES_IN_FLIGHT: usize = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SlotState {
+    Free,
+    // Resolved and copied by the frame being encoded, to be mapped once submitted
+    Copied,
+    Mapping,
+}
+
+struct Slot {
+    resolve_buffer: wgpu::Buffer,
+    readback_buffer: wgpu::Buffer,
+    state: SlotState,
+    // Frame the results are from, to hand out the latest
+    frame: u64,
+    ready: Arc<AtomicBool>,
+}
+
+pub struct QueryPool {
+    query_set: wgpu::QuerySet,
+    count: u32,
+    slots: Vec<Slot>,
+    next: usize,
+    frame: u64,
+}
+
+impl QueryPool {
+    /// `count` queries of type `ty`, which the device must support.
+    pub fn new(device: &wgpu::Device, label: &str, ty: wgpu::QueryType, count: u32) -> Self {
+        let values_per_query = match ty {
+            wgpu::QueryType::PipelineStatistics(types) => types.bits().count_ones(),
+            wgpu::QueryType::Timestamp | wgpu::QueryType::Occlusion => 1,
+        };
+        let size = (count * values_per_query) as u64 * std::mem::size_of::<u64>() as u64;
+        let query_set = device.create_query_set(&wgpu::QuerySetDescriptor {
+            label: Some(&format!("{label} Query Set")),
+            ty,
+            count,
+        });
+        let slots = (0..FRAMES_IN_FLIGHT)
+            .map(|_| Slot {
+                resolve_buffer: device.create_buffer(&wgpu::BufferDescriptor {
+                    label: Some(&format!("{label} Resolve Buffer")),
+                    size,
+                    usage: wgpu::BufferUsages::QUERY_RESOLVE | wgpu::BufferUsages::COPY_SRC,
+                    mapped_at_creation: false,
+                }),
+                readback_buffer: device.create_buffer(&wgpu::BufferDescriptor {
+                    label: Some(&format!("{label} Readback Buffer")),
+                    size,
+                    usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+                    mapped_at_creation: false,
+                }),
+                state: SlotState::Free,
+                frame: 0,
+                ready: Arc::new(AtomicBool::new(false)),
+            })
+            .collect();
+
+        Self {
+            query_set,
+            count,
+            slots,
+            next: 0,
+            frame: 0,
+        }
+    }
+
+    pub fn query_set(&self) -> &wgpu::QuerySet {
+        &self.query_set
+    }
+
+    /// Copies this frame's results for reading, unless the buffers they'd go in are still being
+    /// read. Must be encoded after every query was written, and followed by [`QueryPool::map`]
+    /// once submitted.
+    pub fn resolve(&mut self, encoder: &mut wgpu::CommandEncoder) {
+        self.frame += 1;
+        let slot = &mut self.slots[self.next];
+        if slot.state != SlotState::Free {
+            return;
+        }
+        encoder.resolve_query_set(&self.query_set, 0..self.count, &slot.resolve_buffer, 0);
+        encoder.copy_buffer_to_buffer(
+            &slot.resolve_buffer,
+            0,
+            &slot.readback_buffer,
+            0,
+            slot.resolve_buffer.size(),
+        );
+        slot.state = SlotState::Copied;
+        slot.frame = self.frame;
+        self.next = (self.next + 1) % self.slots.len();
+    }
+
+    pub fn map(&mut self) {
+        for slot in &mut self.slots {
+            if slot.state != SlotState::Copied {
+                continue;
+            }
+            let ready = slot.ready.clone();
+            slot.readback_buffer
+                .slice(..)
+                .map_async(wgpu::MapMode::Read, move |result| {
+                    if result.is_ok() {
+                        ready.store(true, Ordering::Release);
+                    }
+                });
+            slot.state = SlotState::Mapping;
+        }
+    }
+
+    /// Returns the results of the latest frame read back since the last call, if any, without
+    /// waiting for the ones still in flight.
+    pub fn try_take(&mut self, device: &wgpu::Device) -> Option<Vec<u64>> {
+        if self
+            .slots
+            .iter()
+            .all(|slot| slot.state != SlotState::Mapping)
+        {
+            return None;
+        }
+        device.poll(wgpu::Maintain::Poll);
+
+        let mut latest: Option<(u64, Vec<u64>)> = None;
+        for slot in &mut self.slots {
+            if slot.state != SlotState::Mapping || !slot.ready.swap(false, Ordering::Acquire) {
+                continue;
+            }
+            let values =
+                bytemuck::cast_slice(&slot.readback_buffer.slice(..).get_mapped_range()).to_vec();
+            slot.readback_buffer.unmap();
+            slot.state = SlotState::Free;
+            if latest.as_ref().is_none_or(|(frame, _)| slot.frame > *frame) {
+                latest = Some((slot.frame, values));
+            }
+        }
+        latest.map(|(_, values)| values)
+    }
+}
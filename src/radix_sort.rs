@@ -0,0 +1,362 @@
+//! Radix sort of `u32` keys and values on the GPU, such as particle depths or cell ids and the
+//! indices of their instances, for depth sorting and grid construction.
+//!
+//! Least significant digit first, [`RADIX_BITS`] bits per pass: the digits of each block of keys
+//! are counted, the counts scanned with [`PrefixSum`] into where each block's keys go, and the
+//! keys scattered there. The sort is stable. Non-negative floats sort by their bits, so depths can
+//! be sorted with `f32::to_bits` keys.
+
+use bytemuck::{Pod, Zeroable};
+use wgpu::util::DeviceExt;
+
+use crate::prefix_sum::PrefixSum;
+
+/// Bits of the keys sorted by each pass.
+pub const RADIX_BITS: u32 = 4;
+// Must match RADIX in radix_sort.wgsl
+const RADIX: usize = 1 << RADIX_BITS;
+// Must match BLOCK_SIZE and `@workgroup_size` in radix_sort.wgsl
+const BLOCK_SIZE: usize = 256;
+
+// Must match Params in radix_sort.wgsl
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Pod, Zeroable)]
+struct SortParams {
+    len: u32,
+    shift: u32,
+    blocks: u32,
+    _padding: u32,
+}
+
+pub struct RadixSort {
+    max_len: usize,
+    // The keys and values are sorted back and forth between the caller's buffers and these
+    keys_buffer: wgpu::Buffer,
+    values_buffer: wgpu::Buffer,
+    histograms_buffer: wgpu::Buffer,
+    prefix_sum: PrefixSum,
+    bind_group_layout: wgpu::BindGroupLayout,
+    count_pipeline: wgpu::ComputePipeline,
+    scatter_pipeline: wgpu::ComputePipeline,
+}
+
+impl RadixSort {
+    /// Sorts buffers of up to `max_len` keys and values.
+    pub fn new(device: &wgpu::Device, max_len: usize) -> Self {
+        let buffer_entry = |binding, ty| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let read_only = wgpu::BufferBindingType::Storage { read_only: true };
+        let read_write = wgpu::BufferBindingType::Storage { read_only: false };
+        let bind_group_layout_entries = [
+            buffer_entry(0, read_only),
+            buffer_entry(1, read_only),
+            buffer_entry(2, read_write),
+            buffer_entry(3, read_write),
+            buffer_entry(4, read_write),
+            buffer_entry(5, wgpu::BufferBindingType::Uniform),
+        ];
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Radix Sort Bind Group Layout"),
+            entries: &bind_group_layout_entries,
+        });
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Radix Sort Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("radix_sort.wgsl").into()),
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Radix Sort Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let create_pipeline = |entry_point| {
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some("Radix Sort Pipeline"),
+                layout: Some(&pipeline_layout),
+                module: &shader,
+                entry_point,
+            })
+        };
+
+        #[cfg(feature = "guardrails")]
+        {
+            let reflection = crate::guardrails::ShaderReflection::new(
+                "radix_sort.wgsl",
+                include_str!("radix_sort.wgsl"),
+            );
+            reflection.check_bind_group_layout(0, &bind_group_layout_entries);
+            reflection.check_struct_size("Params", std::mem::size_of::<SortParams>());
+        }
+
+        let u32_buffer = |label, len: usize, usage| {
+            device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some(label),
+                size: (len.max(1) * std::mem::size_of::<u32>()) as u64,
+                usage,
+                mapped_at_creation: false,
+            })
+        };
+        let histograms_len = RADIX * max_len.div_ceil(BLOCK_SIZE);
+
+        Self {
+            max_len,
+            keys_buffer: u32_buffer(
+                "Radix Sort Keys Buffer",
+                max_len,
+                wgpu::BufferUsages::STORAGE,
+            ),
+            values_buffer: u32_buffer(
+                "Radix Sort Values Buffer",
+                max_len,
+                wgpu::BufferUsages::STORAGE,
+            ),
+            histograms_buffer: u32_buffer(
+                "Radix Sort Histograms Buffer",
+                histograms_len,
+                wgpu::BufferUsages::STORAGE,
+            ),
+            prefix_sum: PrefixSum::new(device, histograms_len),
+            bind_group_layout,
+            count_pipeline: create_pipeline("count"),
+            scatter_pipeline: create_pipeline("scatter"),
+        }
+    }
+
+    /// Sorts the first `len` of `keys` and moves the `values` along, both `STORAGE` buffers of
+    /// `u32`s. The keys must fit in their lowest `key_bits` bits, the fewer the faster.
+    pub fn sort(
+        &self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        keys: &wgpu::Buffer,
+        values: &wgpu::Buffer,
+        len: usize,
+        key_bits: u32,
+    ) {
+        assert!(
+            len <= self.max_len,
+            "sorting {len} keys, at most {} fit",
+            self.max_len
+        );
+        assert!(
+            key_bits <= u32::BITS,
+            "sorting on {key_bits} bits of u32 keys"
+        );
+        if len == 0 {
+            return;
+        }
+
+        let blocks = len.div_ceil(BLOCK_SIZE);
+        let groups = blocks as u32;
+        let max_groups = device.limits().max_compute_workgroups_per_dimension;
+        let (x, y) = (groups.min(max_groups), groups.div_ceil(max_groups));
+        #[cfg(feature = "guardrails")]
+        crate::guardrails::check_dispatch_coverage([x, y, 1], [BLOCK_SIZE as u32, 1, 1], len);
+
+        // An even number of passes, so the sorted keys end up back in the caller's buffers. An
+        // extra pass on the zero bits above `key_bits` leaves them in order
+        let passes = key_bits.div_ceil(RADIX_BITS).next_multiple_of(2);
+        for pass in 0..passes {
+            let ((src_keys, src_values), (dst_keys, dst_values)) = if pass % 2 == 0 {
+                ((keys, values), (&self.keys_buffer, &self.values_buffer))
+            } else {
+                ((&self.keys_buffer, &self.values_buffer), (keys, values))
+            };
+            let params = SortParams {
+                len: len as u32,
+                shift: pass * RADIX_BITS,
+                blocks: groups,
+                _padding: 0,
+            };
+            let params_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("Radix Sort Params Buffer"),
+                contents: bytemuck::bytes_of(&params),
+                usage: wgpu::BufferUsages::UNIFORM,
+            });
+            let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("Radix Sort Bind Group"),
+                layout: &self.bind_group_layout,
+                entries: &[
+                    src_keys,
+                    src_values,
+                    dst_keys,
+                    dst_values,
+                    &self.histograms_buffer,
+                    &params_buffer,
+                ]
+                .iter()
+                .enumerate()
+                .map(|(binding, buffer)| wgpu::BindGroupEntry {
+                    binding: binding as u32,
+                    resource: buffer.as_entire_binding(),
+                })
+                .collect::<Vec<_>>(),
+            });
+
+            {
+                let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                    label: Some("Radix Sort Count Pass"),
+                });
+                compute_pass.set_pipeline(&self.count_pipeline);
+                compute_pass.set_bind_group(0, &bind_group, &[]);
+                compute_pass.dispatch_workgroups(x, y, 1);
+            }
+            self.prefix_sum
+                .scan(device, encoder, &self.histograms_buffer, RADIX * blocks);
+            {
+                let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                    label: Some("Radix Sort Scatter Pass"),
+                });
+                compute_pass.set_pipeline(&self.scatter_pipeline);
+                compute_pass.set_bind_group(0, &bind_group, &[]);
+                compute_pass.dispatch_workgroups(x, y, 1);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_device::device;
+
+    /// Sorts `keys` on the GPU with their indices as values, returning both.
+    fn gpu_sort(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        keys: &[u32],
+        key_bits: u32,
+    ) -> Vec<(u32, u32)> {
+        let radix_sort = RadixSort::new(device, keys.len());
+        let indices: Vec<u32> = (0..keys.len() as u32).collect();
+        let buffer = |contents| {
+            device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: None,
+                contents: bytemuck::cast_slice(contents),
+                usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+            })
+        };
+        let (keys_buffer, values_buffer) = (buffer(keys), buffer(&indices));
+        let size = keys_buffer.size();
+        let readback_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size: size * 2,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
+        radix_sort.sort(
+            device,
+            &mut encoder,
+            &keys_buffer,
+            &values_buffer,
+            keys.len(),
+            key_bits,
+        );
+        encoder.copy_buffer_to_buffer(&keys_buffer, 0, &readback_buffer, 0, size);
+        encoder.copy_buffer_to_buffer(&values_buffer, 0, &readback_buffer, size, size);
+        queue.submit(Some(encoder.finish()));
+
+        readback_buffer
+            .slice(..)
+            .map_async(wgpu::MapMode::Read, |result| result.unwrap());
+        device.poll(wgpu::Maintain::Wait);
+        let data = readback_buffer.slice(..).get_mapped_range();
+        let (sorted_keys, sorted_values) =
+            bytemuck::cast_slice::<_, u32>(&data).split_at(keys.len());
+        sorted_keys
+            .iter()
+            .copied()
+            .zip(sorted_values.iter().copied())
+            .collect()
+    }
+
+    /// What the GPU should compute: the sort is stable, so keys that are equal keep their indices
+    /// in order.
+    fn cpu_sort(keys: &[u32]) -> Vec<(u32, u32)> {
+        let mut pairs: Vec<_> = keys.iter().copied().zip(0..).collect();
+        pairs.sort_unstable();
+        pairs
+    }
+
+    fn random_keys(len: usize, seed: &mut u32) -> Vec<u32> {
+        (0..len)
+            .map(|_| {
+                // xorshift
+                *seed ^= *seed << 13;
+                *seed ^= *seed >> 17;
+                *seed ^= *seed << 5;
+                *seed
+            })
+            .collect()
+    }
+
+    #[test]
+    fn matches_the_cpu_sort() {
+        let Some((device, queue)) = device() else {
+            eprintln!("No adapter, skipping the GPU radix sort");
+            return;
+        };
+        let mut seed = 1u32;
+        for len in [
+            1,
+            7,
+            BLOCK_SIZE - 1,
+            BLOCK_SIZE,
+            BLOCK_SIZE + 1,
+            10_000,
+            100_003,
+        ] {
+            let keys = random_keys(len, &mut seed);
+            assert_eq!(
+                gpu_sort(&device, &queue, &keys, u32::BITS),
+                cpu_sort(&keys),
+                "{len} keys"
+            );
+        }
+    }
+
+    #[test]
+    fn sorts_depths() {
+        let Some((device, queue)) = device() else {
+            eprintln!("No adapter, skipping the GPU radix sort");
+            return;
+        };
+        let depths = [3.5f32, 0.0, 1e-3, 250.0, 0.5, 3.5, 1e6, 0.25];
+        let keys: Vec<u32> = depths.iter().map(|depth| depth.to_bits()).collect();
+        let sorted: Vec<f32> = gpu_sort(&device, &queue, &keys, u32::BITS)
+            .into_iter()
+            .map(|(key, _)| f32::from_bits(key))
+            .collect();
+        assert_eq!(sorted, [0.0, 1e-3, 0.25, 0.5, 3.5, 3.5, 250.0, 1e6]);
+    }
+
+    #[test]
+    fn sorts_the_low_bits_only() {
+        let Some((device, queue)) = device() else {
+            eprintln!("No adapter, skipping the GPU radix sort");
+            return;
+        };
+        // Like cell ids, many particles per cell, 12 bits for a 16³ grid
+        let mut seed = 7u32;
+        for key_bits in [1, 4, 12] {
+            let keys: Vec<u32> = random_keys(5000, &mut seed)
+                .into_iter()
+                .map(|key| key % (1 << key_bits))
+                .collect();
+            assert_eq!(
+                gpu_sort(&device, &queue, &keys, key_bits),
+                cpu_sort(&keys),
+                "{key_bits} bits"
+            );
+        }
+    }
+}
@@ -0,0 +1,99 @@
+// Least significant digit radix sort of u32 keys and their values, 4 bits per pass. Each block of
+// 256 keys counts its digits, the counts are scanned digit-major into where each block's keys of a
+// digit go, and the keys scattered there in their order within the block, keeping the sort stable
+
+struct Params {
+    len: u32,
+    // Of the digit sorted by this pass
+    shift: u32,
+    blocks: u32,
+    _padding: u32,
+}
+
+@group(0) @binding(0)
+var<storage, read> src_keys: array<u32>;
+
+@group(0) @binding(1)
+var<storage, read> src_values: array<u32>;
+
+@group(0) @binding(2)
+var<storage, read_write> dst_keys: array<u32>;
+
+@group(0) @binding(3)
+var<storage, read_write> dst_values: array<u32>;
+
+// Count of each digit in each block, `digit * blocks + block`, then scanned into offsets
+@group(0) @binding(4)
+var<storage, read_write> histograms: array<u32>;
+
+@group(0) @binding(5)
+var<uniform> params: Params;
+
+// Must match RADIX in radix_sort.rs
+const RADIX: u32 = 16u;
+// Must match BLOCK_SIZE in radix_sort.rs
+const BLOCK_SIZE: u32 = 256u;
+
+var<workgroup> counts: array<atomic<u32>, 16>;
+var<workgroup> digits: array<u32, 256>;
+
+// Large counts are dispatched in rows, a single dimension is limited to 65535 workgroups
+fn block_index(workgroup: vec3<u32>, workgroups: vec3<u32>) -> u32 {
+    return workgroup.x + workgroup.y * workgroups.x;
+}
+
+fn digit(key: u32) -> u32 {
+    return (key >> params.shift) & (RADIX - 1u);
+}
+
+@compute @workgroup_size(256)
+fn count(
+    @builtin(local_invocation_index) local: u32,
+    @builtin(workgroup_id) workgroup: vec3<u32>,
+    @builtin(num_workgroups) workgroups: vec3<u32>,
+) {
+    let block = block_index(workgroup, workgroups);
+    if local < RADIX {
+        atomicStore(&counts[local], 0u);
+    }
+    workgroupBarrier();
+
+    let index = block * BLOCK_SIZE + local;
+    if index < params.len {
+        atomicAdd(&counts[digit(src_keys[index])], 1u);
+    }
+    workgroupBarrier();
+
+    if local < RADIX && block < params.blocks {
+        histograms[local * params.blocks + block] = atomicLoad(&counts[local]);
+    }
+}
+
+@compute @workgroup_size(256)
+fn scatter(
+    @builtin(local_invocation_index) local: u32,
+    @builtin(workgroup_id) workgroup: vec3<u32>,
+    @builtin(num_workgroups) workgroups: vec3<u32>,
+) {
+    let block = block_index(workgroup, workgroups);
+    let index = block * BLOCK_SIZE + local;
+    // Past the end, a digit no key has
+    var key_digit = RADIX;
+    if index < params.len {
+        key_digit = digit(src_keys[index]);
+    }
+    digits[local] = key_digit;
+    workgroupBarrier();
+
+    if index >= params.len {
+        return;
+    }
+    // The keys of the same digit before this one in the block go first
+    var rank = 0u;
+    for (var i = 0u; i < local; i++) {
+        rank += u32(digits[i] == key_digit);
+    }
+    let destination = histograms[key_digit * params.blocks + block] + rank;
+    dst_keys[destination] = src_keys[index];
+    dst_values[destination] = src_values[index];
+}
@@ -0,0 +1,304 @@
+//! Streaming readback of particles from the GPU, through a ring of mappable buffers.
+//!
+//! Each frame the sampled particles are copied into a free buffer of the ring, which is read once
+//! the GPU is done with it, a frame or two later. When every buffer is still in flight the frame is
+//! skipped rather than waited for, so the bandwidth stays bounded by the size of the sample.
+
+use std::{
+    fmt::Display,
+    ops::Range,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Instant,
+};
+
+use glam::{Vec3, Vec4, Vec4Swizzles};
+
+use crate::{speed_layout::SpeedLayout, vertex::InstanceColor};
+
+// Position of a particle, and at most its speed. Each section of the buffers is this size per
+// particle, colors and packed speeds may take less.
+const ELEMENT_SIZE: u64 = 16;
+const COLOR_SIZE: u64 = std::mem::size_of::<InstanceColor>() as u64;
+// Sampled particles are read in this many contiguous blocks, spread over the buffers. Particles are
+// spawned at random, so blocks are as representative as single particles and far fewer copies.
+const SAMPLE_BLOCKS: usize = 64;
+
+#[derive(Debug, Clone, Copy)]
+pub struct ParticleValues {
+    pub position: Vec3,
+    pub color: Vec4,
+    /// Only on the GPU when simulating there
+    pub velocity: Option<Vec3>,
+}
+
+impl Display for ParticleValues {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let Vec3 { x, y, z } = self.position;
+        write!(f, "position ({x:.1}, {y:.1}, {z:.1})")?;
+        if let Some(Vec3 { x, y, z }) = self.velocity {
+            write!(f, " | velocity ({x:.3}, {y:.3}, {z:.3})")?;
+        }
+        let [r, g, b, a] = self.color.to_array();
+        write!(f, " | color ({r:.2}, {g:.2}, {b:.2}, {a:.2})")
+    }
+}
+
+/// Values of the sampled particles on one frame.
+pub struct Readback {
+    /// Number of the copy the values come from, counted by the ring
+    pub frame: u64,
+    /// When the copy was encoded, the values are those of the frame rendered then
+    pub copied_at: Instant,
+    ranges: Arc<[Range<usize>]>,
+    values: Vec<ParticleValues>,
+}
+
+impl Readback {
+    /// Index and values of each sampled particle.
+    pub fn iter(&self) -> impl Iterator<Item = (usize, &ParticleValues)> {
+        self.ranges.iter().flat_map(Range::clone).zip(&self.values)
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+}
+
+/// What was copied into a buffer of the ring.
+struct Copied {
+    frame: u64,
+    copied_at: Instant,
+    ranges: Arc<[Range<usize>]>,
+    count: usize,
+    // Of the speeds, if they were copied
+    speed_layout: Option<SpeedLayout>,
+}
+
+struct Slot {
+    buffer: Option<wgpu::Buffer>,
+    ready: Arc<AtomicBool>,
+    copied: Option<Copied>,
+    mapped: bool,
+}
+
+pub struct ReadbackRing {
+    ranges: Arc<[Range<usize>]>,
+    count: usize,
+    slots: Vec<Slot>,
+    frame: u64,
+}
+
+impl ReadbackRing {
+    /// Reads the particles of `ranges` through `slots` buffers, the number of frames that can be
+    /// in flight before one is skipped.
+    pub fn new(slots: usize, ranges: impl IntoIterator<Item = Range<usize>>) -> Self {
+        let mut ring = Self {
+            ranges: Arc::from([]),
+            count: 0,
+            slots: (0..slots.max(1))
+                .map(|_| Slot {
+                    buffer: None,
+                    ready: Arc::new(AtomicBool::new(false)),
+                    copied: None,
+                    mapped: false,
+                })
+                .collect(),
+            frame: 0,
+        };
+        ring.set_ranges(ranges);
+        ring
+    }
+
+    /// Evenly spread blocks of `fraction` of `capacity` particles.
+    pub fn sample(capacity: usize, fraction: f32) -> Vec<Range<usize>> {
+        let count = ((capacity as f64 * fraction as f64).ceil() as usize).min(capacity);
+        let blocks = SAMPLE_BLOCKS.min(count);
+        if blocks == 0 {
+            return vec![];
+        }
+        let stride = capacity / blocks;
+        (0..blocks)
+            .map(|block| {
+                // The last block takes the remainder
+                let len = if block + 1 == blocks {
+                    count - count / blocks * (blocks - 1)
+                } else {
+                    count / blocks
+                };
+                let start = (block * stride).min(capacity - len);
+                start..start + len
+            })
+            .collect()
+    }
+
+    /// Reads the particles of `ranges` from the next copy on. Copies in flight still report the
+    /// previous ones.
+    pub fn set_ranges(&mut self, ranges: impl IntoIterator<Item = Range<usize>>) {
+        self.ranges = ranges.into_iter().collect();
+        self.count = self.ranges.iter().map(Range::len).sum();
+    }
+
+    /// Copies the sampled particles into a free buffer, unless all of them are still being read,
+    /// with their speeds if given the buffer they're laid out in. Must be followed by
+    /// [`ReadbackRing::map`] once submitted.
+    pub fn copy(
+        &mut self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        position_buffer: &wgpu::Buffer,
+        color_buffer: &wgpu::Buffer,
+        cpu_data_buffer: Option<(&wgpu::Buffer, SpeedLayout)>,
+    ) {
+        if self.count == 0 {
+            return;
+        }
+        let Some(slot) = self
+            .slots
+            .iter_mut()
+            .find(|slot| slot.copied.is_none() && !slot.mapped)
+        else {
+            return;
+        };
+
+        let section_size = self.count as u64 * ELEMENT_SIZE;
+        if slot
+            .buffer
+            .as_ref()
+            .is_none_or(|buffer| buffer.size() < section_size * 3)
+        {
+            slot.buffer = Some(device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("Readback Ring Buffer"),
+                size: section_size * 3,
+                usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+                mapped_at_creation: false,
+            }));
+        }
+        let buffer = slot.buffer.as_ref().unwrap();
+
+        // Positions, colors and speeds each get a section of the buffer
+        let sources = [
+            (Some(position_buffer), ELEMENT_SIZE),
+            (Some(color_buffer), COLOR_SIZE),
+            cpu_data_buffer.map_or((None, 0), |(buffer, speed_layout)| {
+                (Some(buffer), speed_layout.stride() as u64)
+            }),
+        ];
+        for (section, (source, element_size)) in sources.into_iter().enumerate() {
+            let Some(source) = source else {
+                continue;
+            };
+            let mut offset = section as u64 * section_size;
+            for range in self.ranges.iter() {
+                let size = range.len() as u64 * element_size;
+                encoder.copy_buffer_to_buffer(
+                    source,
+                    range.start as u64 * element_size,
+                    buffer,
+                    offset,
+                    size,
+                );
+                offset += size;
+            }
+        }
+
+        slot.copied = Some(Copied {
+            frame: self.frame,
+            copied_at: Instant::now(),
+            ranges: self.ranges.clone(),
+            count: self.count,
+            speed_layout: cpu_data_buffer.map(|(_, speed_layout)| speed_layout),
+        });
+        self.frame += 1;
+    }
+
+    /// Starts reading the buffers copied into since the last call.
+    pub fn map(&mut self) {
+        for slot in &mut self.slots {
+            if slot.copied.is_none() || slot.mapped {
+                continue;
+            }
+            let ready = slot.ready.clone();
+            slot.buffer
+                .as_ref()
+                .unwrap()
+                .slice(..)
+                .map_async(wgpu::MapMode::Read, move |result| {
+                    if result.is_ok() {
+                        ready.store(true, Ordering::Release);
+                    }
+                });
+            slot.mapped = true;
+        }
+    }
+
+    /// Picks up the newest values read back since the last call, freeing every buffer read.
+    pub fn try_take(&mut self, device: &wgpu::Device) -> Option<Readback> {
+        if !self.slots.iter().any(|slot| slot.mapped) {
+            return None;
+        }
+        device.poll(wgpu::Maintain::Poll);
+
+        let mut newest: Option<Readback> = None;
+        for slot in &mut self.slots {
+            if !slot.mapped || !slot.ready.swap(false, Ordering::Acquire) {
+                continue;
+            }
+            let copy = slot.copied.take().unwrap();
+            slot.mapped = false;
+            let buffer = slot.buffer.as_ref().unwrap();
+            if newest
+                .as_ref()
+                .is_some_and(|newest| newest.frame > copy.frame)
+            {
+                buffer.unmap();
+                continue;
+            }
+
+            let section_size = copy.count * ELEMENT_SIZE as usize;
+            let values = {
+                let data = buffer.slice(..).get_mapped_range();
+                // Mapped ranges are only guaranteed to be aligned for f32
+                let section = |index: usize| -> &[[f32; 4]] {
+                    bytemuck::cast_slice(&data[index * section_size..(index + 1) * section_size])
+                };
+                let positions = section(0);
+                let colors = &data[section_size..];
+                let color_size = COLOR_SIZE as usize;
+                let speeds = copy.speed_layout.map(|speed_layout| {
+                    let mut speeds = vec![[0.0; 4]; copy.count];
+                    let size = copy.count * speed_layout.stride();
+                    let start = 2 * section_size;
+                    speed_layout.decode(&data[start..start + size], &mut speeds);
+                    speeds
+                });
+                (0..copy.count)
+                    .map(|i| ParticleValues {
+                        position: Vec4::from_array(positions[i]).xyz(),
+                        color: bytemuck::pod_read_unaligned::<InstanceColor>(
+                            &colors[i * color_size..(i + 1) * color_size],
+                        )
+                        .color(),
+                        velocity: speeds
+                            .as_ref()
+                            .map(|speeds| Vec4::from_array(speeds[i]).xyz()),
+                    })
+                    .collect()
+            };
+            buffer.unmap();
+            newest = Some(Readback {
+                frame: copy.frame,
+                copied_at: copy.copied_at,
+                ranges: copy.ranges,
+                values,
+            });
+        }
+        newest
+    }
+}
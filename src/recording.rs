@@ -0,0 +1,113 @@
+//! Offline recording of the simulation, either as numbered PNGs or piped to ffmpeg.
+
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+    process::{Child, ChildStdin, Command, Stdio},
+};
+
+use crate::capture::{CaptureError, Image};
+
+/// Simulated time between two recorded frames, independent of how long rendering them takes.
+pub const FRAME_TIME: f32 = 1.0 / 60.0;
+
+// Outputs with one of these extensions are encoded by ffmpeg instead of saved as PNGs
+const VIDEO_EXTENSIONS: &[&str] = &["mp4", "mkv", "webm", "mov"];
+
+enum Sink {
+    Pngs(PathBuf),
+    Ffmpeg { child: Child, stdin: ChildStdin },
+    Done,
+}
+
+pub struct Recorder {
+    sink: Sink,
+    width: u32,
+    height: u32,
+    frame: u32,
+    frames: u32,
+}
+
+impl Recorder {
+    /// Starts recording `frames` frames of `width`x`height` to `path`, a directory for PNGs or a
+    /// video file.
+    pub fn new(path: &Path, frames: u32, width: u32, height: u32) -> Result<Self, CaptureError> {
+        let is_video = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .is_some_and(|extension| VIDEO_EXTENSIONS.contains(&extension));
+
+        let sink = if is_video {
+            let mut child = Command::new("ffmpeg")
+                .args(["-y", "-loglevel", "error", "-f", "rawvideo"])
+                .args(["-pixel_format", "rgba"])
+                .args(["-video_size", &format!("{width}x{height}")])
+                .args(["-framerate", &(1.0 / FRAME_TIME).round().to_string()])
+                .args(["-i", "-", "-pix_fmt", "yuv420p"])
+                .arg(path)
+                .stdin(Stdio::piped())
+                .spawn()?;
+            let stdin = child.stdin.take().expect("ffmpeg stdin is piped");
+            Sink::Ffmpeg { child, stdin }
+        } else {
+            std::fs::create_dir_all(path)?;
+            Sink::Pngs(path.to_owned())
+        };
+
+        Ok(Self {
+            sink,
+            width,
+            height,
+            frame: 0,
+            frames,
+        })
+    }
+
+    pub fn size(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    /// Frames written so far, out of the ones to record.
+    pub fn progress(&self) -> (u32, u32) {
+        (self.frame, self.frames)
+    }
+
+    pub fn is_done(&self) -> bool {
+        matches!(self.sink, Sink::Done)
+    }
+
+    /// Writes the next RGBA8 frame, and finishes the output after the last one.
+    pub fn write_frame(&mut self, rgba: Vec<u8>) -> Result<(), CaptureError> {
+        match &mut self.sink {
+            Sink::Pngs(dir) => {
+                let image = Image {
+                    width: self.width,
+                    height: self.height,
+                    pixels: rgba,
+                };
+                image.save_png(dir.join(format!("frame_{:05}.png", self.frame)))?;
+            }
+            Sink::Ffmpeg { stdin, .. } => stdin.write_all(&rgba)?,
+            Sink::Done => return Ok(()),
+        }
+
+        self.frame += 1;
+        if self.frame >= self.frames {
+            self.finish()?;
+        }
+        Ok(())
+    }
+
+    /// Finishes the output, early if frames are left. Nothing is written afterwards.
+    pub fn finish(&mut self) -> Result<(), CaptureError> {
+        if let Sink::Ffmpeg { mut child, stdin } = std::mem::replace(&mut self.sink, Sink::Done) {
+            // Closing stdin tells ffmpeg there are no more frames
+            drop(stdin);
+            let status = child.wait()?;
+            if !status.success() {
+                return Err(CaptureError::Encoder(status));
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,304 @@
+//! Summary of the particles reduced on the GPU every frame: the bounding box of their positions
+//! and their lowest, highest and average speed.
+//!
+//! Only the few bytes of the summary are read back, asynchronously, so the health of the
+//! simulation can be watched in the title and the panel without reading back the particles. The
+//! summary lags a few frames behind, and frames are skipped while one is still being read.
+
+use std::{
+    fmt::Display,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+
+use bytemuck::{Pod, Zeroable};
+use glam::Vec3;
+
+use crate::{shader_check, speed_layout::SpeedLayout};
+
+// Must match `WORKGROUP_SIZE` in reduction.wgsl
+const WORKGROUP_SIZE: u32 = 256;
+// Workgroups of the first pass, the second one reduces a partial summary per invocation
+const PARTIAL_GROUPS: u32 = WORKGROUP_SIZE;
+const SUMMARY_SIZE: u64 = std::mem::size_of::<Summary>() as u64;
+
+// Must match ReductionParams in reduction.wgsl
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Pod, Zeroable)]
+struct ReductionParams {
+    count: u32,
+    _padding: [u32; 3],
+}
+
+// Must match Summary in reduction.wgsl
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Pod, Zeroable)]
+struct Summary {
+    bounds_min: Vec3,
+    min_speed: f32,
+    bounds_max: Vec3,
+    max_speed: f32,
+    speed_sum: f32,
+    count: u32,
+    _padding: [u32; 2],
+}
+
+/// Summary of the particles as of a few frames ago.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ParticleStats {
+    pub bounds_min: Vec3,
+    pub bounds_max: Vec3,
+    pub min_speed: f32,
+    pub max_speed: f32,
+    pub average_speed: f32,
+}
+
+impl Display for ParticleStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let (min, max) = (self.bounds_min, self.bounds_max);
+        write!(
+            f,
+            "speed {:.3}..{:.3} (avg {:.3}), bounds ({:.0}, {:.0}, {:.0})..({:.0}, {:.0}, {:.0})",
+            self.min_speed,
+            self.max_speed,
+            self.average_speed,
+            min.x,
+            min.y,
+            min.z,
+            max.x,
+            max.y,
+            max.z
+        )
+    }
+}
+
+pub struct Reduction {
+    params: ReductionParams,
+    params_buffer: wgpu::Buffer,
+    partials_buffer: wgpu::Buffer,
+    result_buffer: wgpu::Buffer,
+    staging_buffer: wgpu::Buffer,
+    bind_group_layout: wgpu::BindGroupLayout,
+    partial_pipeline: wgpu::ComputePipeline,
+    combine_pipeline: wgpu::ComputePipeline,
+    ready: Arc<AtomicBool>,
+    // The staging buffer holds a summary not mapped yet
+    copied: bool,
+    mapped: bool,
+    latest: Option<ParticleStats>,
+}
+
+impl Reduction {
+    /// Reduces the speeds laid out in `speed_layout`.
+    pub fn new(device: &wgpu::Device, speed_layout: SpeedLayout) -> Self {
+        let params = ReductionParams::zeroed();
+        let params_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Reduction Params Buffer"),
+            size: std::mem::size_of::<ReductionParams>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let partials_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Reduction Partials Buffer"),
+            size: PARTIAL_GROUPS as u64 * SUMMARY_SIZE,
+            usage: wgpu::BufferUsages::STORAGE,
+            mapped_at_creation: false,
+        });
+        let result_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Reduction Result Buffer"),
+            size: SUMMARY_SIZE,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let staging_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Reduction Staging Buffer"),
+            size: SUMMARY_SIZE,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        let buffer_entry = |binding, ty| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let bind_group_layout_entries = [
+            buffer_entry(0, wgpu::BufferBindingType::Uniform),
+            buffer_entry(1, wgpu::BufferBindingType::Storage { read_only: true }),
+            buffer_entry(2, wgpu::BufferBindingType::Storage { read_only: true }),
+            buffer_entry(3, wgpu::BufferBindingType::Storage { read_only: false }),
+            buffer_entry(4, wgpu::BufferBindingType::Storage { read_only: false }),
+        ];
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Reduction Bind Group Layout"),
+            entries: &bind_group_layout_entries,
+        });
+
+        let source = shader_source(speed_layout);
+        let shader =
+            shader_check::create_module(device, "Reduction Shader", "reduction.wgsl", &source);
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Reduction Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let create_pipeline = |label, entry_point| {
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some(label),
+                layout: Some(&pipeline_layout),
+                module: &shader,
+                entry_point,
+            })
+        };
+
+        #[cfg(feature = "guardrails")]
+        {
+            let reflection = crate::guardrails::ShaderReflection::new("reduction.wgsl", &source);
+            reflection.check_bind_group_layout(0, &bind_group_layout_entries);
+            reflection.check_struct_size("ReductionParams", std::mem::size_of::<ReductionParams>());
+            reflection.check_struct_size("Summary", SUMMARY_SIZE as usize);
+        }
+
+        Self {
+            params,
+            params_buffer,
+            partials_buffer,
+            result_buffer,
+            staging_buffer,
+            bind_group_layout,
+            partial_pipeline: create_pipeline("Reduction Partial Pipeline", "partial"),
+            combine_pipeline: create_pipeline("Reduction Combine Pipeline", "combine"),
+            ready: Arc::new(AtomicBool::new(false)),
+            copied: false,
+            mapped: false,
+            latest: None,
+        }
+    }
+
+    /// The newest summary read back, none until the first one arrives or without particles.
+    pub fn latest(&self) -> Option<ParticleStats> {
+        self.latest
+    }
+
+    /// Summarizes the first `count` particles, from their positions and the speeds of
+    /// `cpu_data_buffer` once `encoder` runs. Must be followed by [`Reduction::map`] once
+    /// submitted.
+    pub fn encode(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        position_buffer: &wgpu::Buffer,
+        cpu_data_buffer: &wgpu::Buffer,
+        count: usize,
+    ) {
+        // Still reading the last summary, this frame is skipped
+        if self.copied || self.mapped {
+            return;
+        }
+        let count = count as u32;
+        if self.params.count != count {
+            self.params.count = count;
+            queue.write_buffer(&self.params_buffer, 0, bytemuck::cast_slice(&[self.params]));
+        }
+
+        // The particle buffers change with the particle count, bound every frame
+        let buffers = [
+            &self.params_buffer,
+            position_buffer,
+            cpu_data_buffer,
+            &self.partials_buffer,
+            &self.result_buffer,
+        ];
+        let entries = buffers
+            .iter()
+            .enumerate()
+            .map(|(binding, buffer)| wgpu::BindGroupEntry {
+                binding: binding as u32,
+                resource: buffer.as_entire_binding(),
+            })
+            .collect::<Vec<_>>();
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Reduction Bind Group"),
+            layout: &self.bind_group_layout,
+            entries: &entries,
+        });
+
+        {
+            let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("Reduction Pass"),
+            });
+            compute_pass.set_bind_group(0, &bind_group, &[]);
+            compute_pass.set_pipeline(&self.partial_pipeline);
+            compute_pass.dispatch_workgroups(PARTIAL_GROUPS, 1, 1);
+            compute_pass.set_pipeline(&self.combine_pipeline);
+            compute_pass.dispatch_workgroups(1, 1, 1);
+        }
+        encoder.copy_buffer_to_buffer(
+            &self.result_buffer,
+            0,
+            &self.staging_buffer,
+            0,
+            SUMMARY_SIZE,
+        );
+        self.copied = true;
+    }
+
+    /// Starts reading the summary copied since the last call, if any.
+    pub fn map(&mut self) {
+        if !self.copied {
+            return;
+        }
+        let ready = self.ready.clone();
+        self.staging_buffer
+            .slice(..)
+            .map_async(wgpu::MapMode::Read, move |result| {
+                if result.is_ok() {
+                    ready.store(true, Ordering::Release);
+                }
+            });
+        self.copied = false;
+        self.mapped = true;
+    }
+
+    /// Picks up the summary into [`Reduction::latest`] once readable.
+    pub fn try_take(&mut self, device: &wgpu::Device) {
+        if !self.mapped {
+            return;
+        }
+        device.poll(wgpu::Maintain::Poll);
+        if !self.ready.swap(false, Ordering::Acquire) {
+            return;
+        }
+
+        let summary = {
+            let data = self.staging_buffer.slice(..).get_mapped_range();
+            bytemuck::pod_read_unaligned::<Summary>(&data)
+        };
+        self.staging_buffer.unmap();
+        self.mapped = false;
+        self.latest = (summary.count > 0).then(|| ParticleStats {
+            bounds_min: summary.bounds_min,
+            bounds_max: summary.bounds_max,
+            min_speed: summary.min_speed,
+            max_speed: summary.max_speed,
+            average_speed: summary.speed_sum / summary.count as f32,
+        });
+    }
+}
+
+/// The source of the shader, after `speed_layout`.
+pub fn shader_source(speed_layout: SpeedLayout) -> String {
+    format!(
+        "{}\n{}",
+        speed_layout.wgsl(),
+        include_str!("reduction.wgsl")
+    )
+}
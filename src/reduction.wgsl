@@ -0,0 +1,94 @@
+// Reduces the particles to their bounding box and lowest, highest and total speed in two passes:
+// every workgroup of `partial` strides over the particles into a partial summary, then `combine`
+// reduces the partial summaries into the result
+
+// Must match `WORKGROUP_SIZE` and `PARTIAL_GROUPS` in reduction.rs
+const WORKGROUP_SIZE: u32 = 256u;
+const LARGEST: f32 = 3.4e38;
+
+// Must match ReductionParams in reduction.rs
+struct ReductionParams {
+    count: u32,
+    _padding0: u32,
+    _padding1: u32,
+    _padding2: u32,
+}
+
+// Must match Summary in reduction.rs
+struct Summary {
+    bounds_min: vec3<f32>,
+    min_speed: f32,
+    bounds_max: vec3<f32>,
+    max_speed: f32,
+    speed_sum: f32,
+    count: u32,
+    _padding: vec2<u32>,
+}
+
+@group(0) @binding(0) var<uniform> params: ReductionParams;
+@group(0) @binding(1) var<storage, read> positions: array<vec4<f32>>;
+// CpuData in state.rs, the speed and the step dt of the compute passes, PackedSpeed is declared by
+// the SpeedLayout in speed_layout.rs
+@group(0) @binding(2) var<storage, read> speeds: array<PackedSpeed>;
+// One per workgroup of `partial`
+@group(0) @binding(3) var<storage, read_write> partials: array<Summary>;
+@group(0) @binding(4) var<storage, read_write> result: Summary;
+
+var<workgroup> scratch: array<Summary, WORKGROUP_SIZE>;
+
+fn empty() -> Summary {
+    return Summary(vec3<f32>(LARGEST), LARGEST, vec3<f32>(-LARGEST), -LARGEST, 0.0, 0u, vec2<u32>(0u));
+}
+
+fn merge(a: Summary, b: Summary) -> Summary {
+    return Summary(
+        min(a.bounds_min, b.bounds_min),
+        min(a.min_speed, b.min_speed),
+        max(a.bounds_max, b.bounds_max),
+        max(a.max_speed, b.max_speed),
+        a.speed_sum + b.speed_sum,
+        a.count + b.count,
+        vec2<u32>(0u),
+    );
+}
+
+// Leaves the summary of the whole scratch in its first element
+fn reduce_scratch(local: u32) {
+    for (var stride = WORKGROUP_SIZE / 2u; stride > 0u; stride /= 2u) {
+        workgroupBarrier();
+        if local < stride {
+            scratch[local] = merge(scratch[local], scratch[local + stride]);
+        }
+    }
+    workgroupBarrier();
+}
+
+@compute @workgroup_size(256)
+fn partial(
+    @builtin(local_invocation_index) local: u32,
+    @builtin(workgroup_id) group: vec3<u32>,
+    @builtin(num_workgroups) groups: vec3<u32>,
+) {
+    var summary = empty();
+    let stride = groups.x * WORKGROUP_SIZE;
+    for (var i = group.x * WORKGROUP_SIZE + local; i < params.count; i += stride) {
+        let position = positions[i].xyz;
+        let speed = length(unpack_speed(speeds[i]).xyz);
+        summary = merge(summary, Summary(position, speed, position, speed, speed, 1u, vec2<u32>(0u)));
+    }
+    scratch[local] = summary;
+    reduce_scratch(local);
+    if local == 0u {
+        partials[group.x] = scratch[0];
+    }
+}
+
+// Dispatched as a single workgroup, one invocation per partial summary
+@compute @workgroup_size(256)
+fn combine(@builtin(local_invocation_index) local: u32) {
+    scratch[local] = partials[local];
+    reduce_scratch(local);
+    if local == 0u {
+        result = scratch[0];
+    }
+}
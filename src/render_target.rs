@@ -0,0 +1,363 @@
+//! Internal render resolution, independent of the window size.
+//!
+//! At [`RenderResolution::Native`] the scene is drawn straight into the surface. Any other
+//! resolution draws into an offscreen texture that then gets stretched over the surface, as do
+//! surfaces without an sRGB format, the blit encoding the gamma. The native resolution can be
+//! scaled by `--render-scale`, below 1 to test the fill rate and above to supersample. The blit also
+//! applies the gamma and brightness of the accessibility options, drawing offscreen while they
+//! change the frame.
+
+use std::fmt::Display;
+
+use wgpu::util::DeviceExt;
+
+use crate::{accessibility::DisplayAdjustment, surface_format};
+
+/// Depth buffer of the passes drawing the particles with depth, picking included.
+pub const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
+/// Range of `--render-scale`
+pub const MIN_SCALE: f32 = 0.5;
+pub const MAX_SCALE: f32 = 2.0;
+
+/// The triangle covering the screen, `vs_fullscreen`, shared by the passes going over every pixel.
+pub const FULLSCREEN_WGSL: &str = include_str!("fullscreen.wgsl");
+
+/// The source of a fullscreen pass, after the shared triangle.
+pub fn fullscreen_shader(source: &str) -> String {
+    format!("{FULLSCREEN_WGSL}\n{source}")
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RenderResolution {
+    Native,
+    Fixed { width: u32, height: u32 },
+}
+
+impl Display for RenderResolution {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RenderResolution::Native => write!(f, "native"),
+            RenderResolution::Fixed { width, height } => write!(f, "{width}x{height}"),
+        }
+    }
+}
+
+const RESOLUTIONS: &[RenderResolution] = &[
+    RenderResolution::Native,
+    RenderResolution::Fixed {
+        width: 1280,
+        height: 720,
+    },
+    RenderResolution::Fixed {
+        width: 1920,
+        height: 1080,
+    },
+    RenderResolution::Fixed {
+        width: 2560,
+        height: 1440,
+    },
+    RenderResolution::Fixed {
+        width: 3840,
+        height: 2160,
+    },
+];
+
+struct Offscreen {
+    // Kept alive for the view and bind group
+    _texture: wgpu::Texture,
+    view: wgpu::TextureView,
+    bind_group: wgpu::BindGroup,
+}
+
+pub struct RenderTarget {
+    resolution_index: usize,
+    surface_size: winit::dpi::PhysicalSize<u32>,
+    // Of the native resolution
+    scale: f32,
+    format: wgpu::TextureFormat,
+    // The scene is drawn in another format than the surface's, even at native resolution
+    always_offscreen: bool,
+    offscreen: Option<Offscreen>,
+    blit_pipeline: wgpu::RenderPipeline,
+    bind_group_layout: wgpu::BindGroupLayout,
+    sampler: wgpu::Sampler,
+    display: DisplayAdjustment,
+    display_buffer: wgpu::Buffer,
+}
+
+impl RenderTarget {
+    /// Draws the scene in `format`, blitted to a surface of `surface_format` when they differ, at
+    /// `scale` times the size of the surface at native resolution.
+    pub fn new(
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+        surface_format: wgpu::TextureFormat,
+        surface_size: winit::dpi::PhysicalSize<u32>,
+        scale: f32,
+    ) -> Self {
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Blit Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Blit Sampler"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        let display = DisplayAdjustment::default();
+        let display_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Display Buffer"),
+            contents: bytemuck::bytes_of(&display.uniform()),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let source = fullscreen_shader(include_str!("blit.wgsl"));
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Blit Shader"),
+            source: wgpu::ShaderSource::Wgsl(source.as_str().into()),
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Blit Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let blit_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Blit Pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_fullscreen",
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: if surface_format::encodes_gamma(surface_format) {
+                    "fs_encode_srgb"
+                } else {
+                    "fs_main"
+                },
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: surface_format,
+                    blend: None,
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+
+        let mut render_target = Self {
+            resolution_index: 0,
+            surface_size,
+            scale,
+            format,
+            always_offscreen: format != surface_format,
+            offscreen: None,
+            blit_pipeline,
+            bind_group_layout,
+            sampler,
+            display,
+            display_buffer,
+        };
+        render_target.create_offscreen_for_resolution(device);
+        render_target
+    }
+
+    pub fn resolution(&self) -> RenderResolution {
+        RESOLUTIONS[self.resolution_index]
+    }
+
+    pub fn scale(&self) -> f32 {
+        self.scale
+    }
+
+    /// Size the scene is rendered at.
+    pub fn size(&self) -> (u32, u32) {
+        match self.resolution() {
+            RenderResolution::Native => self.native_size(),
+            RenderResolution::Fixed { width, height } => (width, height),
+        }
+    }
+
+    /// Size of the surface, scaled.
+    fn native_size(&self) -> (u32, u32) {
+        let scale = |length| ((length as f32 * self.scale).round() as u32).max(1);
+        (
+            scale(self.surface_size.width),
+            scale(self.surface_size.height),
+        )
+    }
+
+    /// Native resolution is drawn offscreen, sized after the surface.
+    fn native_offscreen(&self) -> bool {
+        self.always_offscreen || self.scale != 1.0 || !self.display.is_identity()
+    }
+
+    /// Adjusts the frame with `display` when it's blitted.
+    pub fn set_display(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        display: DisplayAdjustment,
+    ) {
+        let was_offscreen = self.native_offscreen();
+        self.display = display;
+        queue.write_buffer(
+            &self.display_buffer,
+            0,
+            bytemuck::bytes_of(&display.uniform()),
+        );
+        if self.native_offscreen() != was_offscreen {
+            self.create_offscreen_for_resolution(device);
+        }
+    }
+
+    /// Must be called whenever the surface is reconfigured.
+    pub fn resize(&mut self, device: &wgpu::Device, surface_size: winit::dpi::PhysicalSize<u32>) {
+        self.surface_size = surface_size;
+        if self.native_offscreen() && self.resolution() == RenderResolution::Native {
+            self.create_offscreen_for_resolution(device);
+        }
+    }
+
+    /// Switches to the next (or previous) internal resolution.
+    pub fn cycle(&mut self, device: &wgpu::Device, forward: bool) {
+        let count = RESOLUTIONS.len();
+        self.resolution_index = if forward {
+            (self.resolution_index + 1) % count
+        } else {
+            (self.resolution_index + count - 1) % count
+        };
+        self.create_offscreen_for_resolution(device);
+    }
+
+    /// Switches to `resolution`, if it is one of the resolutions cycled through.
+    pub fn set_resolution(&mut self, device: &wgpu::Device, resolution: RenderResolution) {
+        if let Some(index) = RESOLUTIONS.iter().position(|&r| r == resolution) {
+            self.resolution_index = index;
+            self.create_offscreen_for_resolution(device);
+        }
+    }
+
+    fn create_offscreen_for_resolution(&mut self, device: &wgpu::Device) {
+        let max = device.limits().max_texture_dimension_2d;
+        self.offscreen = match self.resolution() {
+            RenderResolution::Native if self.native_offscreen() => {
+                let (width, height) = self.native_size();
+                Some(self.create_offscreen(device, width.min(max), height.min(max)))
+            }
+            RenderResolution::Native => None,
+            RenderResolution::Fixed { width, height } => {
+                Some(self.create_offscreen(device, width.min(max), height.min(max)))
+            }
+        };
+    }
+
+    /// The view the scene should be drawn into this frame.
+    pub fn view<'a>(&'a self, surface_view: &'a wgpu::TextureView) -> &'a wgpu::TextureView {
+        match &self.offscreen {
+            Some(offscreen) => &offscreen.view,
+            None => surface_view,
+        }
+    }
+
+    /// Stretches the offscreen target over the surface. Does nothing when drawing straight into
+    /// the surface.
+    pub fn blit(&self, encoder: &mut wgpu::CommandEncoder, surface_view: &wgpu::TextureView) {
+        let Some(offscreen) = &self.offscreen else {
+            return;
+        };
+
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Blit Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: surface_view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                    store: true,
+                },
+            })],
+            depth_stencil_attachment: None,
+        });
+        render_pass.set_pipeline(&self.blit_pipeline);
+        render_pass.set_bind_group(0, &offscreen.bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+
+    fn create_offscreen(&self, device: &wgpu::Device, width: u32, height: u32) -> Offscreen {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Offscreen Render Target"),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: self.format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Blit Bind Group"),
+            layout: &self.bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&self.sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: self.display_buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        Offscreen {
+            _texture: texture,
+            view,
+            bind_group,
+        }
+    }
+}
@@ -0,0 +1,153 @@
+//! The particles as a renderer of its own, for other wgpu applications to embed in their frames.
+//!
+//! It is a cell of the grid view without the grid: the particles of the default scene, simulated
+//! on the GPU by the compute kernel and drawn as instanced quads. There is no depth buffer and no
+//! post-processing, the particles are blended over whatever the view already holds.
+
+use std::sync::Arc;
+
+use crate::{
+    camera::{Camera, CameraUniform},
+    encoding,
+    error::AppError,
+    gradient,
+    instance_pool::InstancePool,
+    lights,
+    pipeline_cache::{BlendMode, ParticleShape, PipelineCache, RenderOptions},
+    scene::Scene,
+    schedule::Keyframe,
+    shader_check,
+    state::{self, GridCell, State},
+};
+
+pub struct ParticleRenderer {
+    device: Arc<wgpu::Device>,
+    queue: Arc<wgpu::Queue>,
+    cell: GridCell,
+    // Holding the cell alone
+    pool: InstancePool,
+    pipeline_cache: PipelineCache,
+    vertex_buffer: wgpu::Buffer,
+    index_buffer: wgpu::Buffer,
+    index_count: u32,
+    // Kept alive for the camera bind group
+    _lights_buffer: wgpu::Buffer,
+    _gradient_buffer: wgpu::Buffer,
+}
+
+impl ParticleRenderer {
+    /// Spawns the particles of the default scene, to be drawn into views of `surface_format`.
+    /// Fails if the shaders don't compile or the device can't hold the scene.
+    pub fn new(
+        device: Arc<wgpu::Device>,
+        queue: Arc<wgpu::Queue>,
+        surface_format: wgpu::TextureFormat,
+    ) -> Result<Self, AppError> {
+        let scene = Scene::default();
+        let camera = state::initial_camera(1.0, false);
+
+        let lights_buffer = lights::create_buffer(&device);
+        lights::write_buffer(&queue, &lights_buffer, &scene.lights);
+        let gradient_buffer = gradient::create_buffer(&device);
+        let (_, _, camera_bind_group_layout, _) = State::create_camera_bindings(
+            &device,
+            &CameraUniform::new(),
+            &Keyframe::NEUTRAL,
+            &lights_buffer,
+            &gradient_buffer,
+        );
+        shader_check::validate_builtin()?;
+        let pipeline_cache = PipelineCache::new(
+            &device,
+            surface_format,
+            &camera_bind_group_layout,
+            &camera.depth,
+            None,
+        )?;
+
+        let mut pool = InstancePool::new(
+            &device,
+            State::scene_particle_count(&device, &scene, None, None),
+        );
+        let cell = State::create_scene_cell(
+            &device,
+            &queue,
+            &mut pool,
+            &camera_bind_group_layout,
+            &lights_buffer,
+            &gradient_buffer,
+            &scene,
+            false,
+            state::DEFAULT_WORKGROUP_SIZE,
+            None,
+            false,
+        )?;
+        let (vertex_buffer, index_buffer) = State::create_mesh_buffers(&device);
+
+        Ok(Self {
+            device,
+            queue,
+            cell,
+            pool,
+            pipeline_cache,
+            vertex_buffer,
+            index_buffer,
+            index_count: state::INDICES.len() as u32,
+            _lights_buffer: lights_buffer,
+            _gradient_buffer: gradient_buffer,
+        })
+    }
+
+    /// Steps the simulation by `dt` seconds, submitting the compute kernel right away.
+    pub fn update(&mut self, dt: f32) {
+        self.cell.advance(&self.queue, dt);
+        let submissions =
+            State::encode_grid_compute(&self.device, std::slice::from_ref(&self.cell));
+        encoding::submit(&self.queue, submissions);
+    }
+
+    /// Reads the positions of the particles back from the GPU, waiting for it. Slow, for tests and
+    /// tools rather than every frame.
+    pub fn positions(&self) -> Vec<glam::Vec3> {
+        self.cell
+            .read_positions(&self.device, &self.queue, &self.pool)
+    }
+
+    /// Records the draw of the particles seen through `camera` over `view`. The camera is written
+    /// to the queue, so only the last render before a submission is seen through its own camera.
+    pub fn render(
+        &mut self,
+        encoder: &mut wgpu::CommandEncoder,
+        view: &wgpu::TextureView,
+        camera: &Camera,
+    ) {
+        self.cell
+            .set_camera(&self.queue, camera, &Keyframe::NEUTRAL);
+        let pipeline = self.pipeline_cache.get(
+            &self.device,
+            RenderOptions {
+                shape: ParticleShape::Quad,
+                blend_mode: BlendMode::default(),
+                depth_format: None,
+                soft: false,
+            },
+        );
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Particle Renderer Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: true,
+                },
+            })],
+            depth_stencil_attachment: None,
+        });
+        render_pass.set_pipeline(&pipeline);
+        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        self.pool.set_vertex_buffers(&mut render_pass);
+        render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
+        self.cell.draw(&mut render_pass, self.index_count);
+    }
+}
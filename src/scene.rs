@@ -0,0 +1,706 @@
+//! Scene files, describing the particles and parameters a run starts from.
+//!
+//! Scenes use a small subset of TOML: `key = value` lines grouped under `[section]` headers, with
+//! numbers, booleans, arrays of numbers and `#` comments. Missing keys keep their default value.
+//!
+//! ```toml
+//! particles = 500000
+//! seed = 42
+//!
+//! [turbulence]
+//! frequency = 0.02
+//! amplitude = 0.3
+//!
+//! [simulation]
+//! dt = 1.0
+//! damping = 0.01
+//! speed_multiplier = 1.0
+//! attractor_strength = 0.05
+//! bounded = true
+//! # "bounce", "clamp" or "wrap" on every axis, or on one with boundary_x, boundary_y or boundary_z
+//! boundary = "bounce"
+//! boundary_y = "wrap"
+//! # Substeps particles in this box, and only moves the others every `coarse_interval` frames
+//! roi_min = [-200.0, -200.0, -200.0]
+//! roi_max = [200.0, 200.0, 200.0]
+//! roi_substeps = 4
+//! coarse_interval = 4
+//! # Particles closer than twice the radius collide, bouncing back with this fraction of their speed
+//! collision_radius = 2.0
+//! restitution = 0.8
+//! # Particles fade out and respawn at the emitter after about this many frames, 0 keeps them
+//! # forever
+//! lifetime = 600.0
+//!
+//! # Only used with `--sim boids`
+//! [boids]
+//! radius = 5.0
+//! separation = 0.05
+//! alignment = 0.05
+//! cohesion = 0.005
+//! min_speed = 0.1
+//! max_speed = 0.5
+//!
+//! # Only used with `--sim nbody`
+//! [nbody]
+//! gravity = 0.01
+//! softening = 20.0
+//! time_scale = 1.0
+//!
+//! [camera]
+//! eye = [0.0, 0.0, 2500.0]
+//! target = [0.0, 0.0, 0.0]
+//! fovy = 45.0
+//! # Clip planes, the near one must be closer than the far one. The far one can also be
+//! # "infinite", for very deep particle fields
+//! near = 1.0
+//! far = 10000.0
+//!
+//! # `shape` comes first, the other keys depend on it
+//! [emitter]
+//! shape = "disk"
+//! center = [0.0, 0.0, 400.0]
+//! radius = 600.0
+//! normal = [0.0, 1.0, 0.0]
+//!
+//! # Spawn colors: `name` is a built-in palette, "default", "fire", "ocean", "forest", "neon",
+//! # "pastel", or "cividis" and "blue-orange" which stay apart with a color vision deficiency, and
+//! # comes first, the other keys change it. Each channel is
+//! # `base + variance * random + gradient * x`, x going from 0 to 1 left to right
+//! [palette]
+//! name = "fire"
+//! base = [0.75, 0.1, 0.0]
+//! variance = [0.25, 0.35, 0.05]
+//! gradient = [0.0, 0.3, 0.0]
+//!
+//! # Every `[obstacle]` section adds one, `shape` comes first: "sphere" with `center` and
+//! # `radius`, "plane" with `point` and `normal`, or "box" with `center` and `half_size`
+//! [obstacle]
+//! shape = "sphere"
+//! center = [0.0, 0.0, 400.0]
+//! radius = 150.0
+//!
+//! [obstacle]
+//! shape = "plane"
+//! point = [0.0, -400.0, 0.0]
+//! normal = [0.0, 1.0, 0.0]
+//!
+//! # Every `[light]` section adds one, `kind` comes first: "point" with `position` and `range`, or
+//! # "directional" with the `direction` it shines towards. Both take `color`, `intensity` and
+//! # `specular`, the strength of their highlights
+//! [light]
+//! kind = "point"
+//! position = [0.0, 300.0, 600.0]
+//! color = [1.0, 0.8, 0.6]
+//! intensity = 1.5
+//! range = 1200.0
+//! specular = 0.5
+//!
+//! # Drawn behind the particles instead of the clear color: a gradient from `bottom` to `top`, a
+//! # `cubemap` directory of px.png, nx.png, py.png, ny.png, pz.png and nz.png, or an `equirect`
+//! # PNG or HDR image. Paths are relative to the scene file
+//! [environment]
+//! top = [0.05, 0.08, 0.15]
+//! bottom = [0.0, 0.0, 0.0]
+//!
+//! # Blows the particles along a velocity field over the box from `min` to `max`, interpolated
+//! # between `size` samples along each axis: a `file` of raw little-endian f32 x, y, z triples, x
+//! # varying fastest then y then z, such as a grid exported from a VDB file, or a `procedural`
+//! # "vortex". Velocities are scaled by `strength`. Paths are relative to the scene file
+//! [wind]
+//! file = "wind.raw"
+//! size = [64, 64, 64]
+//! min = [-1000.0, -1000.0, -1000.0]
+//! max = [1000.0, 1000.0, 1000.0]
+//! strength = 2.0
+//!
+//! # Colors the particles by age over a looping `lifetime` in seconds. Every `stop` is a position
+//! # between 0 and 1 followed by a color with alpha
+//! [gradient]
+//! lifetime = 3.0
+//! stop = [0.0, 1.0, 1.0, 1.0, 1.0]
+//! stop = [0.3, 1.0, 0.5, 0.1, 1.0]
+//! stop = [1.0, 0.2, 0.2, 0.2, 0.0]
+//!
+//! # WGSL function drawing the particles with a custom color and size, see appearance.rs. The path
+//! # is relative to the scene file
+//! [shader]
+//! appearance = "appearance.wgsl"
+//!
+//! # Every `[behavior]` section adds one, run after each step, `kind` comes first: "oscillation"
+//! # swaying along `axis` at up to `amplitude` speed `frequency` times per second, or "spiral"
+//! # turning `angular_speed` radians around `axis` and moving `climb` along it per unit of `dt`
+//! [behavior]
+//! kind = "spiral"
+//! axis = [0.0, 1.0, 0.0]
+//! angular_speed = 0.005
+//! climb = 0.1
+//! ```
+//!
+//! With `--watch`, the scene is reloaded and the particles regenerated whenever the file changes.
+
+use std::{
+    path::{Path, PathBuf},
+    time::{Duration, Instant, SystemTime},
+};
+
+use crate::{
+    behavior::BuiltinBehavior,
+    boids::BoidsParams,
+    camera::{Camera, DepthRange},
+    emitter::EmitterShape,
+    environment::EnvironmentSource,
+    gradient::{ColorGradient, MAX_STOPS},
+    lights::{Light, LightKind, MAX_LIGHTS},
+    nbody::NbodyParams,
+    obstacles::{Obstacle, ObstacleShape},
+    palette::SpawnPalette,
+    sim_params::SimParams,
+    turbulence::TurbulenceParams,
+    wind::{WindData, WindSource},
+};
+
+// Time between two checks of the watched file
+const WATCH_INTERVAL: Duration = Duration::from_millis(500);
+
+#[derive(Debug, thiserror::Error)]
+pub enum SceneError {
+    #[error("unable to read {0}: {1}")]
+    Io(PathBuf, std::io::Error),
+    #[error("line {0}: expected `key = value` or `[section]`")]
+    Syntax(usize),
+    #[error("line {0}: unknown key `{1}`")]
+    UnknownKey(usize, String),
+    #[error("line {line}: invalid value `{value}` for `{key}`")]
+    InvalidValue {
+        line: usize,
+        key: String,
+        value: String,
+    },
+    #[error("line {0}: more than {} lights", MAX_LIGHTS)]
+    TooManyLights(usize),
+    #[error("line {0}: more than {} gradient stops", MAX_STOPS)]
+    TooManyStops(usize),
+    #[error("the camera's near plane at {0} isn't closer than its far plane at {1}")]
+    ClipPlanes(f32, f32),
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Scene {
+    /// Number of particles, or the default count if the scene doesn't ask for one
+    pub particles: Option<usize>,
+    /// Particles are generated from this seed, or randomly without one
+    pub seed: Option<u64>,
+    pub turbulence: TurbulenceParams,
+    pub sim_params: SimParams,
+    pub boids: BoidsParams,
+    pub nbody: NbodyParams,
+    pub eye: Option<glam::Vec3>,
+    pub target: Option<glam::Vec3>,
+    pub fovy: Option<f32>,
+    pub near: Option<f32>,
+    pub far: Option<f32>,
+    pub emitter: EmitterShape,
+    pub palette: SpawnPalette,
+    pub obstacles: Vec<Obstacle>,
+    pub lights: Vec<Light>,
+    pub environment: Option<EnvironmentSource>,
+    pub wind: Option<WindSource>,
+    pub gradient: Option<ColorGradient>,
+    pub behaviors: Vec<BuiltinBehavior>,
+    /// WGSL file of the particles' appearance, see appearance.rs
+    pub appearance: Option<PathBuf>,
+}
+
+impl Scene {
+    pub fn load(path: &Path) -> Result<Self, SceneError> {
+        let contents =
+            std::fs::read_to_string(path).map_err(|e| SceneError::Io(path.to_owned(), e))?;
+        let mut scene = Self::parse(&contents)?;
+        if let (Some(environment), Some(dir)) = (&mut scene.environment, path.parent()) {
+            environment.resolve(dir);
+        }
+        if let (Some(wind), Some(dir)) = (&mut scene.wind, path.parent()) {
+            wind.resolve(dir);
+        }
+        if let (Some(appearance), Some(dir)) = (&mut scene.appearance, path.parent()) {
+            if appearance.is_relative() {
+                *appearance = dir.join(&appearance);
+            }
+        }
+        Ok(scene)
+    }
+
+    pub fn parse(contents: &str) -> Result<Self, SceneError> {
+        let mut scene = Scene::default();
+        let mut section = String::new();
+        for (index, line) in contents.lines().enumerate() {
+            let line_number = index + 1;
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+            if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+                section = name.trim().to_owned();
+                if section == "obstacle" {
+                    scene.obstacles.push(Obstacle::new(ObstacleShape::Sphere));
+                } else if section == "light" {
+                    if scene.lights.len() == MAX_LIGHTS {
+                        return Err(SceneError::TooManyLights(line_number));
+                    }
+                    scene.lights.push(Light::new(LightKind::Point));
+                } else if section == "behavior" {
+                    scene.behaviors.push("oscillation".parse().unwrap());
+                }
+                continue;
+            }
+            let (key, value) = line
+                .split_once('=')
+                .ok_or(SceneError::Syntax(line_number))?;
+            let (key, value) = (key.trim(), value.trim());
+            let invalid = || SceneError::InvalidValue {
+                line: line_number,
+                key: key.to_owned(),
+                value: value.to_owned(),
+            };
+
+            match (section.as_str(), key) {
+                ("", "particles") => {
+                    scene.particles = Some(
+                        value
+                            .parse()
+                            .ok()
+                            .filter(|&count| count > 0)
+                            .ok_or_else(invalid)?,
+                    )
+                }
+                ("", "seed") => scene.seed = Some(value.parse().map_err(|_| invalid())?),
+                ("turbulence", "frequency") => {
+                    scene.turbulence.frequency = value.parse().map_err(|_| invalid())?
+                }
+                ("turbulence", "amplitude") => {
+                    scene.turbulence.amplitude = value.parse().map_err(|_| invalid())?
+                }
+                ("simulation", "dt") => {
+                    scene.sim_params.dt = value.parse().map_err(|_| invalid())?
+                }
+                ("simulation", "damping") => {
+                    scene.sim_params.damping = value.parse().map_err(|_| invalid())?
+                }
+                ("simulation", "speed_multiplier") => {
+                    scene.sim_params.speed_multiplier = value.parse().map_err(|_| invalid())?
+                }
+                ("simulation", "attractor_strength") => {
+                    scene.sim_params.attractor_strength = value.parse().map_err(|_| invalid())?
+                }
+                ("simulation", "bounded") => {
+                    let bounded: bool = value.parse().map_err(|_| invalid())?;
+                    if bounded != scene.sim_params.bounded() {
+                        scene.sim_params.toggle_bounds();
+                    }
+                }
+                ("simulation", "boundary") => {
+                    let boundary = value.trim_matches('"').parse().map_err(|_| invalid())?;
+                    for axis in 0..3 {
+                        scene.sim_params.set_boundary(axis, boundary);
+                    }
+                }
+                ("simulation", "boundary_x" | "boundary_y" | "boundary_z") => {
+                    let axis = match key {
+                        "boundary_x" => 0,
+                        "boundary_y" => 1,
+                        _ => 2,
+                    };
+                    let boundary = value.trim_matches('"').parse().map_err(|_| invalid())?;
+                    scene.sim_params.set_boundary(axis, boundary);
+                }
+                ("simulation", "roi_min") => {
+                    scene.sim_params.roi_min = parse_vec3(value).ok_or_else(invalid)?.extend(0.0)
+                }
+                ("simulation", "roi_max") => {
+                    scene.sim_params.roi_max = parse_vec3(value).ok_or_else(invalid)?.extend(0.0)
+                }
+                ("simulation", "roi_substeps") => {
+                    scene.sim_params.roi_substeps = parse_positive(value).ok_or_else(invalid)?
+                }
+                ("simulation", "coarse_interval") => {
+                    scene.sim_params.coarse_interval = parse_positive(value).ok_or_else(invalid)?
+                }
+                ("simulation", "collision_radius") => {
+                    scene.sim_params.collision_radius = value
+                        .parse()
+                        .ok()
+                        .filter(|&radius| radius >= 0.0)
+                        .ok_or_else(invalid)?
+                }
+                ("simulation", "restitution") => {
+                    scene.sim_params.restitution = value
+                        .parse()
+                        .ok()
+                        .filter(|restitution| (0.0..=1.0).contains(restitution))
+                        .ok_or_else(invalid)?
+                }
+                ("simulation", "lifetime") => {
+                    scene.sim_params.lifetime = value
+                        .parse()
+                        .ok()
+                        .filter(|&lifetime| lifetime >= 0.0)
+                        .ok_or_else(invalid)?
+                }
+                ("boids", "radius") => {
+                    scene.boids.radius = value
+                        .parse()
+                        .ok()
+                        .filter(|&radius| radius > 0.0)
+                        .ok_or_else(invalid)?
+                }
+                ("boids", "separation") => {
+                    scene.boids.separation = value.parse().map_err(|_| invalid())?
+                }
+                ("boids", "alignment") => {
+                    scene.boids.alignment = value.parse().map_err(|_| invalid())?
+                }
+                ("boids", "cohesion") => {
+                    scene.boids.cohesion = value.parse().map_err(|_| invalid())?
+                }
+                ("boids", "min_speed") => {
+                    scene.boids.min_speed = value.parse().map_err(|_| invalid())?
+                }
+                ("boids", "max_speed") => {
+                    scene.boids.max_speed = value.parse().map_err(|_| invalid())?
+                }
+                ("nbody", "gravity") => {
+                    scene.nbody.gravity = value.parse().map_err(|_| invalid())?
+                }
+                ("nbody", "softening") => {
+                    scene.nbody.softening = value
+                        .parse()
+                        .ok()
+                        .filter(|&softening| softening > 0.0)
+                        .ok_or_else(invalid)?
+                }
+                ("nbody", "time_scale") => {
+                    scene.nbody.time_scale = value.parse().map_err(|_| invalid())?
+                }
+                ("camera", "eye") => scene.eye = Some(parse_vec3(value).ok_or_else(invalid)?),
+                ("camera", "target") => scene.target = Some(parse_vec3(value).ok_or_else(invalid)?),
+                ("camera", "fovy") => scene.fovy = Some(value.parse().map_err(|_| invalid())?),
+                ("camera", "near") => scene.near = Some(parse_distance(value).ok_or_else(invalid)?),
+                ("camera", "far") => {
+                    scene.far = Some(match value.trim_matches('"') {
+                        "infinite" => f32::INFINITY,
+                        value => parse_distance(value).ok_or_else(invalid)?,
+                    })
+                }
+                ("emitter", "shape") => {
+                    scene.emitter = value.trim_matches('"').parse().map_err(|_| invalid())?
+                }
+                ("emitter", "radius") => {
+                    let radius = value.parse().map_err(|_| invalid())?;
+                    if !scene.emitter.set_radius(radius) {
+                        return Err(SceneError::UnknownKey(line_number, "emitter.radius".into()));
+                    }
+                }
+                ("emitter", name) => {
+                    let point = parse_vec3(value).ok_or_else(invalid)?;
+                    if !scene.emitter.set_point(name, point) {
+                        return Err(SceneError::UnknownKey(
+                            line_number,
+                            format!("emitter.{name}"),
+                        ));
+                    }
+                }
+                ("palette", "name") => {
+                    scene.palette = value.trim_matches('"').parse().map_err(|_| invalid())?
+                }
+                ("palette", name) => {
+                    let vector = parse_vec3(value).ok_or_else(invalid)?;
+                    if !scene.palette.set_vector(name, vector) {
+                        return Err(SceneError::UnknownKey(
+                            line_number,
+                            format!("palette.{name}"),
+                        ));
+                    }
+                }
+                ("obstacle", "shape") => {
+                    let shape = value.trim_matches('"').parse().map_err(|_| invalid())?;
+                    if let Some(obstacle) = scene.obstacles.last_mut() {
+                        *obstacle = Obstacle::new(shape);
+                    }
+                }
+                ("obstacle", "radius") => {
+                    let radius = value
+                        .parse()
+                        .ok()
+                        .filter(|&radius| radius > 0.0)
+                        .ok_or_else(invalid)?;
+                    if !scene
+                        .obstacles
+                        .last_mut()
+                        .is_some_and(|obstacle| obstacle.set_radius(radius))
+                    {
+                        return Err(SceneError::UnknownKey(
+                            line_number,
+                            "obstacle.radius".into(),
+                        ));
+                    }
+                }
+                ("obstacle", name) => {
+                    let point = parse_vec3(value).ok_or_else(invalid)?;
+                    if !scene
+                        .obstacles
+                        .last_mut()
+                        .is_some_and(|obstacle| obstacle.set_point(name, point))
+                    {
+                        return Err(SceneError::UnknownKey(
+                            line_number,
+                            format!("obstacle.{name}"),
+                        ));
+                    }
+                }
+                ("light", "kind") => {
+                    let kind = value.trim_matches('"').parse().map_err(|_| invalid())?;
+                    if let Some(light) = scene.lights.last_mut() {
+                        *light = Light::new(kind);
+                    }
+                }
+                ("light", "intensity" | "range" | "specular") => {
+                    let value = value
+                        .parse()
+                        .ok()
+                        .filter(|&value| value >= 0.0)
+                        .ok_or_else(invalid)?;
+                    if !scene
+                        .lights
+                        .last_mut()
+                        .is_some_and(|light| light.set_scalar(key, value))
+                    {
+                        return Err(SceneError::UnknownKey(line_number, format!("light.{key}")));
+                    }
+                }
+                ("light", name) => {
+                    let vector = parse_vec3(value).ok_or_else(invalid)?;
+                    if !scene
+                        .lights
+                        .last_mut()
+                        .is_some_and(|light| light.set_vector(name, vector))
+                    {
+                        return Err(SceneError::UnknownKey(line_number, format!("light.{name}")));
+                    }
+                }
+                ("behavior", "kind") => {
+                    let behavior = value.trim_matches('"').parse().map_err(|_| invalid())?;
+                    if let Some(last) = scene.behaviors.last_mut() {
+                        *last = behavior;
+                    }
+                }
+                ("behavior", "axis") => {
+                    let axis = parse_vec3(value).ok_or_else(invalid)?;
+                    if !scene
+                        .behaviors
+                        .last_mut()
+                        .is_some_and(|behavior| behavior.set_vector(key, axis))
+                    {
+                        return Err(invalid());
+                    }
+                }
+                ("behavior", name) => {
+                    let value = value.parse().map_err(|_| invalid())?;
+                    if !scene
+                        .behaviors
+                        .last_mut()
+                        .is_some_and(|behavior| behavior.set_scalar(name, value))
+                    {
+                        return Err(SceneError::UnknownKey(
+                            line_number,
+                            format!("behavior.{name}"),
+                        ));
+                    }
+                }
+                ("shader", "appearance") => {
+                    scene.appearance = Some(value.trim_matches('"').into());
+                }
+                ("environment", "cubemap") => {
+                    let path = value.trim_matches('"');
+                    scene.environment = Some(EnvironmentSource::Cubemap(path.into()));
+                }
+                ("environment", "equirect") => {
+                    let path = value.trim_matches('"');
+                    scene.environment = Some(EnvironmentSource::Equirect(path.into()));
+                }
+                ("environment", name) => {
+                    let color = parse_vec3(value).ok_or_else(invalid)?;
+                    if !scene
+                        .environment
+                        .get_or_insert(EnvironmentSource::DEFAULT_GRADIENT)
+                        .set_color(name, color)
+                    {
+                        return Err(SceneError::UnknownKey(
+                            line_number,
+                            format!("environment.{name}"),
+                        ));
+                    }
+                }
+                ("wind", "file") => {
+                    let path = value.trim_matches('"');
+                    scene.wind.get_or_insert_with(WindSource::default).data =
+                        WindData::File(path.into());
+                }
+                ("wind", "procedural") => {
+                    if value.trim_matches('"') != "vortex" {
+                        return Err(invalid());
+                    }
+                    scene.wind.get_or_insert_with(WindSource::default).data = WindData::Vortex;
+                }
+                ("wind", "size") => {
+                    let [x, y, z] = parse_floats(value).ok_or_else(invalid)?;
+                    let size = glam::Vec3::new(x, y, z);
+                    if size.cmplt(glam::Vec3::ONE).any() || size.fract() != glam::Vec3::ZERO {
+                        return Err(invalid());
+                    }
+                    scene.wind.get_or_insert_with(WindSource::default).size = size.as_uvec3();
+                }
+                ("wind", "min") => {
+                    scene.wind.get_or_insert_with(WindSource::default).min =
+                        parse_vec3(value).ok_or_else(invalid)?
+                }
+                ("wind", "max") => {
+                    scene.wind.get_or_insert_with(WindSource::default).max =
+                        parse_vec3(value).ok_or_else(invalid)?
+                }
+                ("wind", "strength") => {
+                    scene.wind.get_or_insert_with(WindSource::default).strength =
+                        value.parse().map_err(|_| invalid())?
+                }
+                ("gradient", "lifetime") => {
+                    scene
+                        .gradient
+                        .get_or_insert_with(ColorGradient::default)
+                        .lifetime = value
+                        .parse()
+                        .ok()
+                        .filter(|&lifetime| lifetime > 0.0)
+                        .ok_or_else(invalid)?
+                }
+                ("gradient", "stop") => {
+                    let [position, r, g, b, a] = parse_floats(value).ok_or_else(invalid)?;
+                    if !(0.0..=1.0).contains(&position) {
+                        return Err(invalid());
+                    }
+                    if !scene
+                        .gradient
+                        .get_or_insert_with(ColorGradient::default)
+                        .add_stop(position, glam::Vec4::new(r, g, b, a))
+                    {
+                        return Err(SceneError::TooManyStops(line_number));
+                    }
+                }
+                _ => {
+                    let key = match section.as_str() {
+                        "" => key.to_owned(),
+                        section => format!("{section}.{key}"),
+                    };
+                    return Err(SceneError::UnknownKey(line_number, key));
+                }
+            }
+        }
+        scene.sim_params.obstacle_count = scene.obstacles.len() as u32;
+        let depth = DepthRange::default();
+        let (near, far) = (
+            scene.near.unwrap_or(depth.znear),
+            scene.far.unwrap_or(depth.zfar),
+        );
+        if near >= far {
+            return Err(SceneError::ClipPlanes(near, far));
+        }
+        Ok(scene)
+    }
+
+    /// Moves `camera` to where the scene puts it, keeping what the scene doesn't set.
+    pub fn apply_camera(&self, camera: &mut Camera) {
+        if let Some(eye) = self.eye {
+            camera.eye = eye;
+        }
+        if let Some(target) = self.target {
+            camera.target = target;
+        }
+        if let Some(fovy) = self.fovy {
+            camera.fovy = fovy;
+        }
+        if let Some(near) = self.near {
+            camera.depth.znear = near;
+        }
+        if let Some(far) = self.far {
+            camera.depth.zfar = far;
+        }
+    }
+}
+
+fn parse_positive(value: &str) -> Option<u32> {
+    value.parse().ok().filter(|&value| value > 0)
+}
+
+// Positive and finite
+fn parse_distance(value: &str) -> Option<f32> {
+    value
+        .parse()
+        .ok()
+        .filter(|&distance: &f32| distance > 0.0 && distance.is_finite())
+}
+
+fn parse_vec3(value: &str) -> Option<glam::Vec3> {
+    parse_floats(value).map(glam::Vec3::from_array)
+}
+
+fn parse_floats<const N: usize>(value: &str) -> Option<[f32; N]> {
+    let values = value
+        .strip_prefix('[')?
+        .strip_suffix(']')?
+        .split(',')
+        .map(|v| v.trim().parse::<f32>().ok())
+        .collect::<Option<Vec<_>>>()?;
+    values.try_into().ok()
+}
+
+/// Polls a scene file for changes.
+pub struct SceneWatcher {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+    last_check: Instant,
+}
+
+impl SceneWatcher {
+    /// Watches `path`, ignoring its current contents until it changes.
+    pub fn new(path: PathBuf) -> Self {
+        let modified = modified_time(&path);
+        Self {
+            path,
+            modified,
+            last_check: Instant::now(),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns the scene reloaded from the file if it changed since the last call.
+    pub fn poll(&mut self) -> Option<Result<Scene, SceneError>> {
+        let now = Instant::now();
+        if now - self.last_check < WATCH_INTERVAL {
+            return None;
+        }
+        self.last_check = now;
+
+        let modified = modified_time(&self.path);
+        if modified == self.modified {
+            return None;
+        }
+        self.modified = modified;
+        Some(Scene::load(&self.path))
+    }
+}
+
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
@@ -0,0 +1,228 @@
+//! Day cycle of palette tint, background and light direction, for installations running all day.
+//!
+//! The look is interpolated between keyframes placed at hours of the day. The day either lasts a
+//! configurable number of seconds or follows the wall clock. Keyframes can be loaded from a text
+//! file, one `hour tint.r tint.g tint.b background.r background.g background.b light.x light.y
+//! light.z ambient` line per keyframe, with `#` comments.
+
+use std::{path::Path, str::FromStr, time::SystemTime};
+
+use bytemuck::{Pod, Zeroable};
+use glam::Vec3;
+
+const HOURS_PER_DAY: f32 = 24.0;
+
+/// What drives the time of day.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Clock {
+    /// A whole day passes in this many seconds of simulation
+    Cycle { day_length: f32 },
+    /// Follows the system clock, shifted from UTC by this many hours
+    WallClock { utc_offset: f32 },
+}
+
+/// Parses `wall-clock`, `wall-clock+2`, `wall-clock-5.5` or a day length in seconds.
+impl FromStr for Clock {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(offset) = s.strip_prefix("wall-clock") {
+            let utc_offset = match offset {
+                "" => 0.0,
+                offset => offset.parse().map_err(|_| ())?,
+            };
+            return Ok(Clock::WallClock { utc_offset });
+        }
+        match s.parse() {
+            Ok(day_length) if day_length > 0.0 => Ok(Clock::Cycle { day_length }),
+            _ => Err(()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Keyframe {
+    pub hour: f32,
+    /// Multiplies the particle colors
+    pub tint: Vec3,
+    pub background: Vec3,
+    /// Towards the light
+    pub light_direction: Vec3,
+    /// Fraction of the light reaching the sides of particles facing away from it
+    pub ambient: f32,
+}
+
+impl Keyframe {
+    /// Unchanged particle colors on black, what the scene looks like without a schedule.
+    pub const NEUTRAL: Keyframe = Keyframe {
+        hour: 0.0,
+        tint: Vec3::ONE,
+        background: Vec3::ZERO,
+        light_direction: Vec3::Z,
+        ambient: 1.0,
+    };
+
+    fn lerp(&self, other: &Keyframe, t: f32) -> Keyframe {
+        Keyframe {
+            hour: self.hour + (other.hour - self.hour) * t,
+            tint: self.tint.lerp(other.tint, t),
+            background: self.background.lerp(other.background, t),
+            light_direction: self
+                .light_direction
+                .lerp(other.light_direction, t)
+                .try_normalize()
+                .unwrap_or(Vec3::Z),
+            ambient: self.ambient + (other.ambient - self.ambient) * t,
+        }
+    }
+}
+
+/// Lighting of the particles, shared with shader.wgsl.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Pod, Zeroable)]
+pub struct LightingUniform {
+    // w is unused
+    tint: glam::Vec4,
+    // w is the ambient light
+    light: glam::Vec4,
+}
+
+impl From<&Keyframe> for LightingUniform {
+    fn from(keyframe: &Keyframe) -> Self {
+        Self {
+            tint: keyframe.tint.extend(1.0),
+            light: keyframe
+                .light_direction
+                .normalize_or_zero()
+                .extend(keyframe.ambient),
+        }
+    }
+}
+
+pub struct Schedule {
+    clock: Clock,
+    // Sorted by hour
+    keyframes: Vec<Keyframe>,
+    elapsed: f32,
+}
+
+impl Schedule {
+    pub fn new(clock: Clock, mut keyframes: Vec<Keyframe>) -> Self {
+        if keyframes.is_empty() {
+            keyframes = default_keyframes();
+        }
+        keyframes.sort_by(|a, b| a.hour.total_cmp(&b.hour));
+        Self {
+            clock,
+            keyframes,
+            elapsed: 0.0,
+        }
+    }
+
+    /// Loads keyframes from `path`. Malformed lines are skipped.
+    pub fn load_keyframes(path: &Path) -> std::io::Result<Vec<Keyframe>> {
+        let contents = std::fs::read_to_string(path)?;
+        Ok(contents
+            .lines()
+            .map(|line| line.split('#').next().unwrap_or_default().trim())
+            .filter(|line| !line.is_empty())
+            .filter_map(|line| {
+                let keyframe = parse_keyframe(line);
+                if keyframe.is_none() {
+                    log::warn!("Ignoring keyframe `{line}` in {}", path.display());
+                }
+                keyframe
+            })
+            .collect())
+    }
+
+    /// Advances the schedule by `dt` seconds and returns the current look.
+    pub fn update(&mut self, dt: f32) -> Keyframe {
+        self.elapsed += dt;
+        let hour = match self.clock {
+            Clock::Cycle { day_length } => self.elapsed / day_length * HOURS_PER_DAY,
+            Clock::WallClock { utc_offset } => {
+                let seconds = SystemTime::now()
+                    .duration_since(SystemTime::UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_secs_f64();
+                // Wrapped before converting, f32 hours since 1970 would be off by minutes
+                ((seconds / 3600.0 + utc_offset as f64) % HOURS_PER_DAY as f64) as f32
+            }
+        };
+        self.at(hour.rem_euclid(HOURS_PER_DAY))
+    }
+
+    /// Look at `hour`, wrapping around midnight between the last and first keyframes.
+    fn at(&self, hour: f32) -> Keyframe {
+        let next_index = self
+            .keyframes
+            .iter()
+            .position(|keyframe| keyframe.hour > hour)
+            .unwrap_or(0);
+        let next = &self.keyframes[next_index];
+        let previous =
+            &self.keyframes[(next_index + self.keyframes.len() - 1) % self.keyframes.len()];
+
+        let span = (next.hour - previous.hour).rem_euclid(HOURS_PER_DAY);
+        if span == 0.0 {
+            return *previous;
+        }
+        let t = (hour - previous.hour).rem_euclid(HOURS_PER_DAY) / span;
+        previous.lerp(next, t)
+    }
+}
+
+fn default_keyframes() -> Vec<Keyframe> {
+    vec![
+        // Night: dim and blue, lit from above
+        Keyframe {
+            hour: 0.0,
+            tint: Vec3::new(0.35, 0.45, 0.8),
+            background: Vec3::new(0.0, 0.0, 0.02),
+            light_direction: Vec3::new(0.0, 1.0, 0.3),
+            ambient: 0.5,
+        },
+        // Dawn: warm, low light from the left
+        Keyframe {
+            hour: 6.0,
+            tint: Vec3::new(1.0, 0.7, 0.5),
+            background: Vec3::new(0.08, 0.03, 0.05),
+            light_direction: Vec3::new(-1.0, 0.2, 0.5),
+            ambient: 0.35,
+        },
+        // Noon: unchanged colors, light from the front
+        Keyframe {
+            hour: 12.0,
+            tint: Vec3::ONE,
+            background: Vec3::new(0.02, 0.03, 0.05),
+            light_direction: Vec3::new(0.0, 0.5, 1.0),
+            ambient: 0.6,
+        },
+        // Dusk: orange, low light from the right
+        Keyframe {
+            hour: 18.0,
+            tint: Vec3::new(1.0, 0.55, 0.35),
+            background: Vec3::new(0.06, 0.02, 0.02),
+            light_direction: Vec3::new(1.0, 0.2, 0.5),
+            ambient: 0.35,
+        },
+    ]
+}
+
+fn parse_keyframe(line: &str) -> Option<Keyframe> {
+    let values = line
+        .split_whitespace()
+        .map(|field| field.parse::<f32>().ok())
+        .collect::<Option<Vec<_>>>()?;
+    let [hour, tr, tg, tb, br, bg, bb, lx, ly, lz, ambient] = values[..] else {
+        return None;
+    };
+    Some(Keyframe {
+        hour: hour.rem_euclid(HOURS_PER_DAY),
+        tint: Vec3::new(tr, tg, tb),
+        background: Vec3::new(br, bg, bb),
+        light_direction: Vec3::new(lx, ly, lz),
+        ambient,
+    })
+}
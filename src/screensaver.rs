@@ -0,0 +1,147 @@
+//! Screensaver mode for kiosks: after some time without input the camera slowly orbits the
+//! particles and cycles through the saved camera presets, until any input.
+//!
+//! Windows runs `.scr` files with `/s` to start the screensaver, `/c` to configure it and
+//! `/p <hwnd>` to preview it in the settings dialog.
+
+use winit::{dpi::PhysicalPosition, event::WindowEvent};
+
+use crate::{
+    camera::Camera,
+    camera_presets::{self, CameraPresets},
+};
+
+// Radians per second around the target
+const ORBIT_SPEED: f32 = 0.05;
+// Seconds spent on each camera preset
+const PRESET_INTERVAL: f32 = 45.0;
+// Cursor moves shorter than this aren't input, some mice report jitter while lying still
+const CURSOR_DEADZONE: f64 = 8.0;
+
+/// What Windows launched the `.scr` file for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScrCommand {
+    Run,
+    Configure,
+    Preview,
+}
+
+impl ScrCommand {
+    /// Parses `/s`, `/c`, `/c:<hwnd>`, `/p` and their `-` and uppercase variants.
+    pub fn parse(arg: &str) -> Option<Self> {
+        let command = arg.strip_prefix(['/', '-'])?;
+        let (command, _hwnd) = command.split_once(':').unwrap_or((command, ""));
+        match command.to_ascii_lowercase().as_str() {
+            "s" => Some(ScrCommand::Run),
+            "c" => Some(ScrCommand::Configure),
+            "p" => Some(ScrCommand::Preview),
+            _ => None,
+        }
+    }
+}
+
+pub struct Screensaver {
+    idle_timeout: f32,
+    exit_on_input: bool,
+    idle: f32,
+    dismissed: bool,
+    // Camera to go back to when input resumes, kept while the screensaver runs
+    saved_camera: Option<Camera>,
+    since_preset: f32,
+    preset_slot: Option<usize>,
+    cursor_anchor: Option<PhysicalPosition<f64>>,
+}
+
+impl Screensaver {
+    /// Starts after `idle_timeout` seconds without input. With `exit_on_input`, input ends the
+    /// whole app instead of only the screensaver.
+    pub fn new(idle_timeout: f32, exit_on_input: bool) -> Self {
+        Self {
+            idle_timeout,
+            exit_on_input,
+            idle: 0.0,
+            dismissed: false,
+            saved_camera: None,
+            since_preset: 0.0,
+            preset_slot: None,
+            cursor_anchor: None,
+        }
+    }
+
+    pub fn active(&self) -> bool {
+        self.saved_camera.is_some()
+    }
+
+    /// True once input ended a screensaver that exits on input.
+    pub fn dismissed(&self) -> bool {
+        self.dismissed
+    }
+
+    /// Resets the idle time on input. Returns true if `event` ended the screensaver, in which case
+    /// it shouldn't be handled any further.
+    pub fn input(&mut self, event: &WindowEvent, camera: &mut Camera) -> bool {
+        let is_input = match event {
+            WindowEvent::KeyboardInput { .. }
+            | WindowEvent::MouseInput { .. }
+            | WindowEvent::MouseWheel { .. }
+            | WindowEvent::Touch(_)
+            | WindowEvent::TouchpadMagnify { .. } => true,
+            WindowEvent::CursorMoved { position, .. } => {
+                let anchor = *self.cursor_anchor.get_or_insert(*position);
+                let (dx, dy) = (position.x - anchor.x, position.y - anchor.y);
+                dx.hypot(dy) > CURSOR_DEADZONE
+            }
+            _ => false,
+        };
+        if !is_input {
+            return false;
+        }
+
+        self.idle = 0.0;
+        self.cursor_anchor = None;
+        let Some(saved) = self.saved_camera.take() else {
+            return false;
+        };
+        if self.exit_on_input {
+            self.dismissed = true;
+        }
+        camera.eye = saved.eye;
+        camera.target = saved.target;
+        camera.up = saved.up;
+        camera.fovy = saved.fovy;
+        log::info!("Screensaver stopped");
+        true
+    }
+
+    /// Moves `camera` if the screensaver is running. Returns true if it did.
+    pub fn update(&mut self, dt: f32, camera: &mut Camera, presets: &CameraPresets) -> bool {
+        self.idle += dt;
+        if !self.active() {
+            if self.idle < self.idle_timeout {
+                return false;
+            }
+            log::info!("Screensaver started");
+            self.saved_camera = Some(camera.clone());
+            self.since_preset = PRESET_INTERVAL;
+        }
+
+        self.since_preset += dt;
+        if self.since_preset >= PRESET_INTERVAL {
+            self.since_preset = 0.0;
+            self.next_preset(camera, presets);
+        }
+
+        let axis = camera.up.try_normalize().unwrap_or(glam::Vec3::Y);
+        let rotation = glam::Quat::from_axis_angle(axis, ORBIT_SPEED * dt);
+        camera.eye = camera.target + rotation * (camera.eye - camera.target);
+        true
+    }
+
+    /// Moves to the next saved preset, if any were saved.
+    fn next_preset(&mut self, camera: &mut Camera, presets: &CameraPresets) {
+        let start = self.preset_slot.map_or(0, |slot| slot + 1);
+        self.preset_slot = (start..start + camera_presets::SLOTS)
+            .map(|slot| slot % camera_presets::SLOTS)
+            .find(|&slot| presets.apply(slot, camera));
+    }
+}
@@ -0,0 +1,267 @@
+//! Emission parameters and global forces from a Rhai script given with `--script`, to tune them
+//! without touching Rust or WGSL.
+//!
+//! The script can define either function:
+//!
+//! ```rhai
+//! // Called on load and on every reload, the particles respawn when the result changes
+//! fn emission() {
+//!     #{ particles: 200000, shape: "sphere", center: [0.0, 0.0, 400.0], radius: 300.0 }
+//! }
+//!
+//! // Called every frame with the simulation time, in seconds
+//! fn forces(time) {
+//!     #{ force: [0.0, -0.05 * time.sin(), 0.0], attractor: 0.01, damping: 0.001 }
+//! }
+//! ```
+//!
+//! Both run on the CPU, the forces replace the simulation parameters of the same name before they
+//! are uploaded. The file is reloaded when it changes, a script that doesn't compile keeps the
+//! previous one.
+
+use std::{
+    path::{Path, PathBuf},
+    time::{Duration, Instant, SystemTime},
+};
+
+use glam::Vec3;
+use rhai::{Dynamic, Engine, FuncArgs, Map, Scope, AST, FLOAT};
+
+use crate::{emitter::EmitterShape, sim_params::SimParams};
+
+// Time between two checks of the script file
+const WATCH_INTERVAL: Duration = Duration::from_millis(500);
+// A runaway loop in the script would hang the frame
+const MAX_OPERATIONS: u64 = 1_000_000;
+
+#[derive(Debug, thiserror::Error)]
+pub enum ScriptError {
+    #[error("unable to load {0}: {1}")]
+    Load(PathBuf, Box<rhai::EvalAltResult>),
+    #[error("`{0}` failed: {1}")]
+    Call(&'static str, Box<rhai::EvalAltResult>),
+    #[error("`{0}` must return a map")]
+    NotAMap(&'static str),
+    #[error("`{0}` returned an invalid `{1}`")]
+    InvalidValue(&'static str, String),
+    #[error("`{0}` returned an unknown key `{1}`")]
+    UnknownKey(&'static str, String),
+    #[error("`emission` sets `{0}` without a `shape`")]
+    MissingShape(String),
+}
+
+/// What `emission` returned, replacing `--particles` and `--emitter`.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Emission {
+    pub particles: Option<usize>,
+    pub emitter: Option<EmitterShape>,
+}
+
+impl Emission {
+    fn from_map(map: &Map) -> Result<Self, ScriptError> {
+        let invalid = |key: &str| ScriptError::InvalidValue("emission", key.to_owned());
+        let mut emission = Emission::default();
+        // The shape first, the other keys set its dimensions
+        if let Some(shape) = map.get("shape") {
+            let shape = shape
+                .clone()
+                .into_string()
+                .ok()
+                .and_then(|s| s.parse().ok());
+            emission.emitter = Some(shape.ok_or_else(|| invalid("shape"))?);
+        }
+        for (key, value) in map {
+            match key.as_str() {
+                "shape" => {}
+                "particles" => {
+                    let particles = value.as_int().ok().and_then(|n| usize::try_from(n).ok());
+                    emission.particles = Some(particles.ok_or_else(|| invalid(key))?);
+                }
+                name => {
+                    let emitter = emission
+                        .emitter
+                        .as_mut()
+                        .ok_or_else(|| ScriptError::MissingShape(name.to_owned()))?;
+                    let known = if name == "radius" {
+                        emitter.set_radius(number(value).ok_or_else(|| invalid(name))? as f32)
+                    } else {
+                        emitter.set_point(name, vector(value).ok_or_else(|| invalid(name))?)
+                    };
+                    if !known {
+                        return Err(ScriptError::UnknownKey("emission", name.to_owned()));
+                    }
+                }
+            }
+        }
+        Ok(emission)
+    }
+
+    /// `particles` and `emitter`, replaced where the script sets them.
+    pub fn apply(
+        &self,
+        particles: Option<usize>,
+        emitter: Option<EmitterShape>,
+    ) -> (Option<usize>, Option<EmitterShape>) {
+        (self.particles.or(particles), self.emitter.or(emitter))
+    }
+}
+
+/// What `forces` returned, replacing the simulation parameters of the same name.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Forces {
+    pub force: Option<Vec3>,
+    pub attractor: Option<f32>,
+    pub damping: Option<f32>,
+}
+
+impl Forces {
+    fn from_map(map: &Map) -> Result<Self, ScriptError> {
+        let invalid = |key: &str| ScriptError::InvalidValue("forces", key.to_owned());
+        let mut forces = Forces::default();
+        for (key, value) in map {
+            match key.as_str() {
+                "force" => forces.force = Some(vector(value).ok_or_else(|| invalid(key))?),
+                "attractor" => {
+                    forces.attractor = Some(number(value).ok_or_else(|| invalid(key))? as f32);
+                }
+                "damping" => {
+                    forces.damping = Some(number(value).ok_or_else(|| invalid(key))? as f32)
+                }
+                _ => return Err(ScriptError::UnknownKey("forces", key.to_string())),
+            }
+        }
+        Ok(forces)
+    }
+
+    pub fn apply(&self, sim_params: &mut SimParams) {
+        if let Some(force) = self.force {
+            sim_params.force = force.extend(0.0);
+        }
+        if let Some(attractor) = self.attractor {
+            sim_params.attractor_strength = attractor;
+        }
+        if let Some(damping) = self.damping {
+            sim_params.damping = damping;
+        }
+    }
+}
+
+pub struct Script {
+    path: PathBuf,
+    engine: Engine,
+    ast: AST,
+    modified: Option<SystemTime>,
+    last_check: Instant,
+    // `forces` isn't called again after failing, until the script is reloaded
+    forces_failed: bool,
+}
+
+impl Script {
+    pub fn load(path: &Path) -> Result<Self, ScriptError> {
+        let mut engine = Engine::new();
+        engine.set_max_operations(MAX_OPERATIONS);
+        let ast = engine
+            .compile_file(path.to_owned())
+            .map_err(|e| ScriptError::Load(path.to_owned(), e))?;
+        log::info!("Running {}", path.display());
+        Ok(Self {
+            path: path.to_owned(),
+            engine,
+            ast,
+            modified: modified_time(path),
+            last_check: Instant::now(),
+            forces_failed: false,
+        })
+    }
+
+    /// Compiles the script again if the file changed since the last call. Returns true if it did,
+    /// a script that doesn't compile is logged and the previous one kept.
+    pub fn reload(&mut self) -> bool {
+        let now = Instant::now();
+        if now - self.last_check < WATCH_INTERVAL {
+            return false;
+        }
+        self.last_check = now;
+
+        let modified = modified_time(&self.path);
+        if modified == self.modified {
+            return false;
+        }
+        self.modified = modified;
+        match self.engine.compile_file(self.path.clone()) {
+            Ok(ast) => {
+                log::info!("Reloaded {}", self.path.display());
+                self.ast = ast;
+                self.forces_failed = false;
+                true
+            }
+            Err(e) => {
+                let e = ScriptError::Load(self.path.clone(), e);
+                log::error!("Keeping the current script: {e}");
+                false
+            }
+        }
+    }
+
+    /// What `emission` returns, none if the script doesn't define it.
+    pub fn emission(&self) -> Result<Option<Emission>, ScriptError> {
+        self.call("emission", ())?
+            .map(|map| Emission::from_map(&map))
+            .transpose()
+    }
+
+    /// What `forces` returns at `time`, none if the script doesn't define it or it failed before.
+    pub fn forces(&mut self, time: f32) -> Result<Option<Forces>, ScriptError> {
+        if self.forces_failed {
+            return Ok(None);
+        }
+        let forces = self
+            .call("forces", (time as FLOAT,))
+            .and_then(|map| map.map(|map| Forces::from_map(&map)).transpose());
+        self.forces_failed = forces.is_err();
+        forces
+    }
+
+    fn call(&self, name: &'static str, args: impl FuncArgs) -> Result<Option<Map>, ScriptError> {
+        if !self
+            .ast
+            .iter_functions()
+            .any(|function| function.name == name)
+        {
+            return Ok(None);
+        }
+        let result = self
+            .engine
+            .call_fn::<Dynamic>(&mut Scope::new(), &self.ast, name, args)
+            .map_err(|e| ScriptError::Call(name, e))?;
+        result
+            .try_cast::<Map>()
+            .map(Some)
+            .ok_or(ScriptError::NotAMap(name))
+    }
+}
+
+/// An integer or a float.
+fn number(value: &Dynamic) -> Option<f64> {
+    value
+        .as_float()
+        .ok()
+        .or_else(|| value.as_int().ok().map(|n| n as FLOAT))
+}
+
+/// An array of three numbers.
+fn vector(value: &Dynamic) -> Option<Vec3> {
+    let array = value.clone().try_cast::<rhai::Array>()?;
+    let [x, y, z] = array.as_slice() else {
+        return None;
+    };
+    Some(Vec3::new(
+        number(x)? as f32,
+        number(y)? as f32,
+        number(z)? as f32,
+    ))
+}
+
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
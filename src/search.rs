@@ -0,0 +1,249 @@
+//! Headless search for good-looking simulation parameters.
+//!
+//! A small genetic algorithm evolves the parameters explored by [`crate::explore`]. Each candidate
+//! runs a few seconds of the scene in a headless [`State`], on the GPU with every force and
+//! behavior of the real run. It is then scored from the positions read back, as spread on screen
+//! by the scene's camera, or by a command given its last frame.
+
+use std::{fmt::Display, path::PathBuf, process::Command};
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::{
+    capture::CaptureError,
+    error::AppError,
+    explore::{self, ExploreRanges},
+    options::Options,
+    settings::Settings,
+    sim_params::SimParams,
+    state::State,
+    turbulence::TurbulenceParams,
+};
+
+// Frames simulated per candidate
+const STEPS: usize = 300;
+const POPULATION: usize = 16;
+// Number of candidates kept unchanged in the next generation
+const ELITES: usize = POPULATION / 4;
+// Screen cells used to measure how particles are spread
+const GRID_SIZE: (usize, usize) = (64, 36);
+// Of the frames handed to score commands
+const FRAME_SIZE: (u32, u32) = (640, 360);
+
+/// What the search maximizes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Score {
+    /// Fraction of the screen covered by particles
+    Coverage,
+    /// How evenly particles are spread over the covered part of the screen
+    Uniformity,
+    /// Both at once
+    Combined,
+    /// The number printed by a command, run with the path of the candidate's last frame as a PNG
+    /// appended to its arguments
+    Command(String),
+}
+
+impl Display for Score {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Score::Coverage => write!(f, "coverage"),
+            Score::Uniformity => write!(f, "uniformity"),
+            Score::Combined => write!(f, "combined"),
+            Score::Command(command) => write!(f, "score of `{command}`"),
+        }
+    }
+}
+
+impl std::str::FromStr for Score {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "coverage" => Ok(Score::Coverage),
+            "uniformity" => Ok(Score::Uniformity),
+            "combined" => Ok(Score::Combined),
+            _ => Err(()),
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum SearchError {
+    #[error(transparent)]
+    State(#[from] AppError),
+    #[error("unable to render the frame to score: {0}")]
+    Frame(#[from] CaptureError),
+    #[error("unable to run the score command: {0}")]
+    Spawn(#[from] std::io::Error),
+    #[error("the score command failed with {0}")]
+    Failed(std::process::ExitStatus),
+    #[error("the score command printed {0:?} instead of a number")]
+    NotANumber(String),
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Candidate {
+    turbulence: TurbulenceParams,
+    sim_params: SimParams,
+}
+
+/// Runs `generations` generations of the search on the scene of `options` and prints the best
+/// parameters found.
+pub fn run(generations: u32, options: &Options, settings: &Settings) -> Result<(), SearchError> {
+    let mut state = State::new(None, options, settings)?;
+    let mut rng = StdRng::seed_from_u64(0);
+    let ranges = ExploreRanges::default();
+    let score = &options.score;
+
+    let (turbulence, sim_params) = state.explored_params();
+    let mut population = vec![
+        Candidate {
+            turbulence,
+            sim_params,
+        };
+        POPULATION
+    ];
+    for candidate in &mut population[1..] {
+        ranges.mutate(
+            &mut rng,
+            &mut candidate.turbulence,
+            &mut candidate.sim_params,
+        );
+    }
+
+    let mut best: Option<(f32, Candidate)> = None;
+    for generation in 0..generations {
+        let mut scored = population
+            .iter()
+            .map(|candidate| Ok((evaluate(&mut state, candidate, score)?, *candidate)))
+            .collect::<Result<Vec<_>, SearchError>>()?;
+        scored.sort_by(|a, b| b.0.total_cmp(&a.0));
+
+        let (value, candidate) = scored[0];
+        if best.is_none_or(|(best_value, _)| value > best_value) {
+            best = Some((value, candidate));
+        }
+        log::info!(
+            "Generation {generation}: best {score} {value:.4} with {}",
+            explore::describe(&candidate.turbulence, &candidate.sim_params)
+        );
+
+        // Keep the elites, fill the rest with mutated crossovers of them
+        population = scored[..ELITES].iter().map(|(_, c)| *c).collect();
+        while population.len() < POPULATION {
+            let a = scored[rng.gen_range(0..ELITES)].1;
+            let b = scored[rng.gen_range(0..ELITES)].1;
+            let mut child = crossover(&a, &b, &mut rng);
+            ranges.mutate(&mut rng, &mut child.turbulence, &mut child.sim_params);
+            population.push(child);
+        }
+    }
+    state.shutdown();
+
+    if let Some((value, candidate)) = best {
+        println!(
+            "Best {score} {value:.4}: {}",
+            explore::describe(&candidate.turbulence, &candidate.sim_params)
+        );
+    }
+    Ok(())
+}
+
+fn crossover(a: &Candidate, b: &Candidate, rng: &mut impl Rng) -> Candidate {
+    let mut pick = |x: f32, y: f32| if rng.gen() { x } else { y };
+    let mut child = *a;
+    child.turbulence.frequency = pick(a.turbulence.frequency, b.turbulence.frequency);
+    child.turbulence.amplitude = pick(a.turbulence.amplitude, b.turbulence.amplitude);
+    child.sim_params.speed_multiplier =
+        pick(a.sim_params.speed_multiplier, b.sim_params.speed_multiplier);
+    child.sim_params.damping = pick(a.sim_params.damping, b.sim_params.damping);
+    child.sim_params.attractor_strength = pick(
+        a.sim_params.attractor_strength,
+        b.sim_params.attractor_strength,
+    );
+    child
+}
+
+/// Simulates `candidate` from the spawned particles for [`STEPS`] frames and scores the result.
+fn evaluate(state: &mut State, candidate: &Candidate, score: &Score) -> Result<f32, SearchError> {
+    state.restart_with(candidate.turbulence, candidate.sim_params);
+    for _ in 0..STEPS {
+        // Without a surface, there's no surface error to get
+        if let Err(e) = state.render() {
+            log::error!("{e}");
+        }
+    }
+
+    let Score::Command(command) = score else {
+        return Ok(spread(state, score));
+    };
+    let path = std::env::temp_dir().join(format!("particles-search-{}.png", std::process::id()));
+    let (width, height) = FRAME_SIZE;
+    state.render_frame(width, height)?.save_png(&path)?;
+    let value = run_score_command(command, path.clone());
+    // The frame is only needed by the command
+    let _ = std::fs::remove_file(&path);
+    value
+}
+
+/// Runs `command`, split on whitespace, with `frame` as its last argument and parses what it
+/// prints.
+fn run_score_command(command: &str, frame: PathBuf) -> Result<f32, SearchError> {
+    let mut words = command.split_whitespace();
+    let program = words.next().unwrap_or_default();
+    let output = Command::new(program).args(words).arg(frame).output()?;
+    if !output.status.success() {
+        return Err(SearchError::Failed(output.status));
+    }
+    let printed = String::from_utf8_lossy(&output.stdout).trim().to_owned();
+    printed
+        .parse()
+        .map_err(|_| SearchError::NotANumber(printed))
+}
+
+/// How the simulated particles are spread on screen, as seen by the scene's camera, by the
+/// built-in `score`.
+fn spread(state: &State, score: &Score) -> f32 {
+    let (width, height) = GRID_SIZE;
+    let mut camera = state.camera().clone();
+    camera.aspect = width as f32 / height as f32;
+    let view_proj = camera.build_view_projection_matrix();
+
+    let mut cells = vec![0u32; width * height];
+    for position in state.particle_positions() {
+        let clip = view_proj * position.extend(1.0);
+        if clip.w <= 0.0 {
+            continue;
+        }
+        let ndc = clip.truncate() / clip.w;
+        if ndc.x.abs() >= 1.0 || ndc.y.abs() >= 1.0 {
+            continue;
+        }
+        let x = ((ndc.x + 1.0) / 2.0 * width as f32) as usize;
+        let y = ((ndc.y + 1.0) / 2.0 * height as f32) as usize;
+        cells[y.min(height - 1) * width + x.min(width - 1)] += 1;
+    }
+
+    let occupied = cells.iter().filter(|&&count| count > 0).collect::<Vec<_>>();
+    if occupied.is_empty() {
+        return 0.0;
+    }
+    let coverage = occupied.len() as f32 / cells.len() as f32;
+
+    // 1 for perfectly even densities, towards 0 as the coefficient of variation grows
+    let mean = occupied.iter().map(|&&c| c as f32).sum::<f32>() / occupied.len() as f32;
+    let variance = occupied
+        .iter()
+        .map(|&&c| (c as f32 - mean).powi(2))
+        .sum::<f32>()
+        / occupied.len() as f32;
+    let uniformity = 1.0 / (1.0 + variance.sqrt() / mean);
+
+    match score {
+        Score::Coverage => coverage,
+        Score::Uniformity => uniformity,
+        Score::Combined => coverage * uniformity,
+        Score::Command(_) => unreachable!("scored by its command"),
+    }
+}
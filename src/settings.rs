@@ -0,0 +1,143 @@
+//! Window geometry, camera and toggles remembered between runs.
+//!
+//! Saved as TOML in the platform config directory, e.g. `~/.config/particles/settings.toml` on
+//! Linux, when the app exits and restored when it starts again.
+
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+use winit::{
+    dpi::{PhysicalPosition, PhysicalSize},
+    window::{Window, WindowBuilder},
+};
+
+use crate::{accessibility::Accessibility, camera::Camera};
+
+#[derive(Debug, thiserror::Error)]
+pub enum SettingsError {
+    #[error("no config directory on this platform")]
+    NoConfigDir,
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Parse(#[from] toml::de::Error),
+    #[error(transparent)]
+    Serialize(#[from] toml::ser::Error),
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub window: Option<WindowGeometry>,
+    pub camera: Option<CameraPose>,
+    /// Frames paced to the monitor refresh rate, toggled with F8
+    pub paced: bool,
+    /// Parameter panel open
+    pub ui_open: bool,
+    pub accessibility: Accessibility,
+}
+
+/// Outer position and inner size of the main window, in physical pixels.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct WindowGeometry {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+    pub maximized: bool,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct CameraPose {
+    pub eye: glam::Vec3,
+    pub target: glam::Vec3,
+    pub up: glam::Vec3,
+    pub fovy: f32,
+}
+
+impl Settings {
+    fn path() -> Option<PathBuf> {
+        dirs::config_dir().map(|dir| dir.join(env!("CARGO_PKG_NAME")).join("settings.toml"))
+    }
+
+    /// The saved settings, or the defaults if there are none or they can't be read.
+    pub fn load() -> Self {
+        match Self::try_load() {
+            Ok(settings) => settings,
+            Err(e) => {
+                log::warn!("Ignoring saved settings: {e}");
+                Self::default()
+            }
+        }
+    }
+
+    fn try_load() -> Result<Self, SettingsError> {
+        let path = Self::path().ok_or(SettingsError::NoConfigDir)?;
+        match std::fs::read_to_string(&path) {
+            Ok(contents) => Ok(toml::from_str(&contents)?),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub fn save(&self) -> Result<(), SettingsError> {
+        let path = Self::path().ok_or(SettingsError::NoConfigDir)?;
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::write(&path, toml::to_string(self)?)?;
+        log::info!("Settings saved to {}", path.display());
+        Ok(())
+    }
+
+    /// Opens the window where it was left.
+    pub fn apply_window(&self, builder: WindowBuilder) -> WindowBuilder {
+        match self.window {
+            Some(geometry) => builder
+                .with_position(PhysicalPosition::new(geometry.x, geometry.y))
+                .with_inner_size(PhysicalSize::new(geometry.width, geometry.height))
+                .with_maximized(geometry.maximized),
+            None => builder,
+        }
+    }
+
+    /// Remembers where `window` is. Minimized windows keep the previous geometry, their size is
+    /// meaningless.
+    pub fn store_window(&mut self, window: &Window) {
+        let size = window.inner_size();
+        if size.width == 0 || size.height == 0 {
+            return;
+        }
+        // Not every platform reports the position, keep the previous one then
+        let (x, y) = match (window.outer_position(), self.window) {
+            (Ok(position), _) => (position.x, position.y),
+            (Err(_), Some(previous)) => (previous.x, previous.y),
+            (Err(_), None) => (0, 0),
+        };
+        self.window = Some(WindowGeometry {
+            x,
+            y,
+            width: size.width,
+            height: size.height,
+            maximized: window.is_maximized(),
+        });
+    }
+
+    pub fn apply_camera(&self, camera: &mut Camera) {
+        if let Some(pose) = self.camera {
+            camera.eye = pose.eye;
+            camera.target = pose.target;
+            camera.up = pose.up;
+            camera.fovy = pose.fovy;
+        }
+    }
+
+    pub fn store_camera(&mut self, camera: &Camera) {
+        self.camera = Some(CameraPose {
+            eye: camera.eye,
+            target: camera.target,
+            up: camera.up,
+            fovy: camera.fovy,
+        });
+    }
+}
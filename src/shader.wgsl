@@ -2,53 +2,513 @@
 
 struct CameraUniform {
     view_proj: mat4x4<f32>,
+    // w is unused
+    eye: vec4<f32>,
+    inv_view_proj: mat4x4<f32>,
+    previous_view_proj: mat4x4<f32>,
+    // The two halves of view_proj
+    view: mat4x4<f32>,
+    proj: mat4x4<f32>,
+    // Particle groups shown, one bit per group, see groups.rs
+    group_mask: u32,
+    // Non-zero when depth goes from 1 near the camera to 0 far away
+    reverse_z: u32,
 };
 @group(0) @binding(0)
 var<uniform> camera: CameraUniform;
 
+struct Lighting {
+    // w is unused
+    tint: vec4<f32>,
+    // Direction towards the light, w is the ambient light
+    light: vec4<f32>,
+};
+@group(0) @binding(1)
+var<uniform> lighting: Lighting;
+
+// Must match Light in lights.rs
+struct Light {
+    // Position of point lights with w at 1, direction towards directional lights with w at 0
+    position: vec4<f32>,
+    // w is the intensity
+    color: vec4<f32>,
+    range: f32,
+    specular: f32,
+};
+struct Lights {
+    count: u32,
+    lights: array<Light>,
+};
+@group(0) @binding(2)
+var<storage, read> scene_lights: Lights;
+
+// Must match GradientStop in gradient.rs
+struct GradientStop {
+    color: vec4<f32>,
+    // Between 0 and 1, along the lifetime
+    position: f32,
+};
+// Must match GradientUniform in gradient.rs
+struct Gradient {
+    stops: array<GradientStop, 8>,
+    time: f32,
+    lifetime: f32,
+    // No stops leaves the particle colors as they are
+    count: u32,
+};
+@group(0) @binding(3)
+var<uniform> gradient: Gradient;
+
+// Must match SoftParams in soft_particles.rs
+struct SoftParams {
+    fade_distance: f32,
+};
+// Only bound to the soft particle pipelines, see soft_particles.rs
+@group(1) @binding(0)
+var obstacle_depth: texture_2d<f32>;
+@group(1) @binding(1)
+var<uniform> soft: SoftParams;
+
+// Instances of `vs_pulled`, the same buffers the other entry points take as vertex buffers
+@group(2) @binding(0)
+var<storage, read> pulled_positions: array<vec4<f32>>;
+@group(2) @binding(1)
+var<storage, read> pulled_colors: array<PackedColor>;
+
 struct VertexInput {
     @location(0) position: vec3<f32>,
     @location(1) vertex_position: vec2<f32>,
+    @location(4) normal: vec3<f32>,
 };
 
 struct InstanceInput {
-    @location(2) model_matrix_0: vec4<f32>,
-    @location(3) model_matrix_1: vec4<f32>,
-    @location(4) model_matrix_2: vec4<f32>,
-    @location(5) model_matrix_3: vec4<f32>,
-    @location(6) color: vec4<f32>,
+    // w is the particle's group
+    @location(2) position: vec4<f32>,
+    @location(3) color: vec4<f32>,
 };
 
 struct VertexOutput {
     @builtin(position) clip_position: vec4<f32>,
     @location(0) vertex_position: vec2<f32>,
     @location(1) vertex_color: vec4<f32>,
+    // Distance from the camera plane, for the depth-of-field pass
+    @location(2) view_depth: f32,
+    @location(3) world_position: vec3<f32>,
+    @location(4) normal: vec3<f32>,
+    // From the fragment to the camera, the camera uniform is only visible to the vertex stage
+    @location(5) to_eye: vec3<f32>,
 };
 
+// PCG hash, see "Hash Functions for GPU Rendering" by Jarzynski and Olano
+fn pcg(value: u32) -> u32 {
+    let state = value * 747796405u + 2891336453u;
+    let word = ((state >> ((state >> 28u) + 4u)) ^ state) * 277803737u;
+    return (word >> 22u) ^ word;
+}
+
+// Color of the gradient at the particle's age. Particles are reordered by compaction and the
+// level of detail, so each starts at a point of the lifetime hashed from its color
+fn gradient_color(color: vec4<f32>) -> vec4<f32> {
+    if gradient.count == 0u {
+        return color;
+    }
+    let seed = bitcast<vec4<u32>>(color);
+    let phase = f32(pcg(seed.x ^ pcg(seed.y ^ pcg(seed.z)))) / 4294967295.0;
+    let age = fract(gradient.time / gradient.lifetime + phase);
+    var result = gradient.stops[0].color;
+    for (var i = 1u; i < gradient.count; i++) {
+        let previous = gradient.stops[i - 1u];
+        let next = gradient.stops[i];
+        if age >= previous.position {
+            let span = max(next.position - previous.position, 0.00001);
+            let t = clamp((age - previous.position) / span, 0.0, 1.0);
+            result = mix(previous.color, next.color, t);
+        }
+    }
+    return vec4<f32>(result.rgb, result.a * color.a);
+}
+
 @vertex
 fn vs_main(
     model: VertexInput,
     instance: InstanceInput,
 ) -> VertexOutput {
+    return quad_vertex(model, instance);
+}
+
+// Same as `vs_main` without any vertex buffer: the corners of the quad come from the vertex index,
+// in the order of the index buffer, and the instance from the storage buffers
+@vertex
+fn vs_pulled(
+    @builtin(vertex_index) vertex_index: u32,
+    @builtin(instance_index) instance_index: u32,
+) -> VertexOutput {
+    // Indices 0, 1, 2, 3, 2, 1
+    let corner = select(vertex_index, 6u - vertex_index, vertex_index > 3u);
+    let vertex_position = vec2<f32>(f32(corner & 1u), f32(corner >> 1u));
+    let model = VertexInput(
+        vec3<f32>(vertex_position - 0.5, 0.0),
+        vertex_position,
+        vec3<f32>(0.0, 0.0, 1.0),
+    );
+    let instance = InstanceInput(
+        pulled_positions[instance_index],
+        unpack_color(pulled_colors[instance_index]),
+    );
+    return quad_vertex(model, instance);
+}
+
+// What a particle is drawn with, see appearance.rs
+struct Appearance {
+    color: vec4<f32>,
+    // Of the quad, 1 for the default size
+    size: f32,
+};
+
+// Start of the default appearance, replaced by the scene's snippet
+fn particle_appearance(position: vec3<f32>, base: Appearance) -> Appearance {
+    return base;
+}
+// End of the default appearance
+
+// Beyond the far plane, where the vertices of the hidden groups are collapsed to be clipped
+const HIDDEN: vec4<f32> = vec4<f32>(0.0, 0.0, 2.0, 1.0);
+
+fn hidden(instance: InstanceInput) -> bool {
+    return (camera.group_mask & (1u << u32(instance.position.w))) == 0u;
+}
+
+// Fraction of their life particles fade out over, see SimParams::age in sim_params.rs
+const FADE_OUT: f32 = 0.2;
+
+// The gradient color of the particle, faded out near the end of its life, before the scene's
+// appearance. Its age is the fraction of the w of its position, 0 for particles living forever
+fn base_appearance(instance: InstanceInput) -> Appearance {
+    var color = gradient_color(instance.color);
+    color.a *= 1.0 - smoothstep(1.0 - FADE_OUT, 1.0, fract(instance.position.w));
+    return Appearance(color, 1.0);
+}
+
+fn quad_vertex(model: VertexInput, instance: InstanceInput) -> VertexOutput {
+    // Spinning particles are turned before, so the model matrix is a translation
     let model_matrix = mat4x4<f32>(
-        instance.model_matrix_0,
-        instance.model_matrix_1,
-        instance.model_matrix_2,
-        instance.model_matrix_3,
+        vec4<f32>(1.0, 0.0, 0.0, 0.0),
+        vec4<f32>(0.0, 1.0, 0.0, 0.0),
+        vec4<f32>(0.0, 0.0, 1.0, 0.0),
+        vec4<f32>(instance.position.xyz, 1.0),
     );
+    let appearance = particle_appearance(instance.position.xyz, base_appearance(instance));
+    var out: VertexOutput;
+    let world_position = model_matrix * vec4<f32>(model.position * appearance.size, 1.0);
+    out.vertex_position = model.vertex_position;
+    let view_position = camera.view * world_position;
+    out.clip_position = camera.proj * view_position;
+    out.vertex_color = appearance.color;
+    out.world_position = world_position.xyz;
+    out.normal = model.normal;
+    out.to_eye = camera.eye.xyz - world_position.xyz;
+    // The camera looks down -z in view space
+    out.view_depth = -view_position.z;
+    if hidden(instance) {
+        out.clip_position = HIDDEN;
+    }
+    return out;
+}
+
+// One point per particle, for the point debug view
+@vertex
+fn vs_point(instance: InstanceInput) -> VertexOutput {
+    var out: VertexOutput;
+    out.vertex_position = vec2<f32>(0.5, 0.5);
+    let view_position = camera.view * vec4<f32>(instance.position.xyz, 1.0);
+    out.clip_position = camera.proj * view_position;
+    out.vertex_color = particle_appearance(instance.position.xyz, base_appearance(instance)).color;
+    out.world_position = instance.position.xyz;
+    out.normal = vec3<f32>(0.0, 0.0, 1.0);
+    out.to_eye = camera.eye.xyz - instance.position.xyz;
+    out.view_depth = -view_position.z;
+    if hidden(instance) {
+        out.clip_position = HIDDEN;
+    }
+    return out;
+}
+
+// Stretched quads cover the distance travelled in this many frames
+const STREAK_FRAMES: f32 = 4.0;
+
+// Camera-facing quad elongated along the velocity as seen from the camera, for the stretched
+// particles. Slow particles, or ones moving towards the camera, stay a round quad
+@vertex
+fn vs_stretched(
+    model: VertexInput,
+    instance: InstanceInput,
+    // The step dt of the compute passes in w
+    @location(5) speed_dt: vec4<f32>,
+) -> VertexOutput {
+    let velocity = speed_dt.xyz;
+    let center = instance.position.xyz;
+    let appearance = particle_appearance(center, base_appearance(instance));
+    let to_eye = normalize(camera.eye.xyz - center);
+    let across_view = velocity - to_eye * dot(velocity, to_eye);
+    let streak = length(across_view) * STREAK_FRAMES;
+    var along = cross(vec3<f32>(0.0, 1.0, 0.0), to_eye);
+    if streak > 0.0001 {
+        along = across_view / length(across_view);
+    } else if dot(along, along) < 0.0001 {
+        // Looking straight up or down
+        along = vec3<f32>(1.0, 0.0, 0.0);
+    }
+    along = normalize(along);
+    let side = cross(to_eye, along);
+    let world_position = center
+        + along * model.position.x * max(streak, appearance.size)
+        + side * model.position.y * appearance.size;
+
     var out: VertexOutput;
     out.vertex_position = model.vertex_position;
-    out.clip_position = camera.view_proj * model_matrix * vec4<f32>(model.position, 1.0);
-    out.vertex_color = instance.color;
+    let view_position = camera.view * vec4<f32>(world_position, 1.0);
+    out.clip_position = camera.proj * view_position;
+    out.vertex_color = appearance.color;
+    out.world_position = world_position;
+    out.normal = to_eye;
+    out.to_eye = camera.eye.xyz - world_position;
+    out.view_depth = -view_position.z;
+    if hidden(instance) {
+        out.clip_position = HIDDEN;
+    }
     return out;
 }
 
+// Standard deviations a splat covers, the Gaussian is under 1% past them
+const SPLAT_EXTENT: f32 = 3.0;
+
+struct SplatOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    // From the center of the splat, in normalized device coordinates
+    @location(0) offset: vec2<f32>,
+    @location(1) color: vec4<f32>,
+    // Inverse of the screen-space covariance: xx, xy and yy
+    @location(2) @interpolate(flat) conic: vec3<f32>,
+};
+
+// Screen-space Gaussian of the particle: a 3D Gaussian as wide as the quad, elongated along the
+// velocity like the stretched quads, projected with the Jacobian of the perspective at its center
+@vertex
+fn vs_splat(
+    model: VertexInput,
+    instance: InstanceInput,
+    @location(5) speed_dt: vec4<f32>,
+) -> SplatOutput {
+    let velocity = speed_dt.xyz;
+    var out: SplatOutput;
+    let center = instance.position.xyz;
+    let appearance = particle_appearance(center, base_appearance(instance));
+    let view_center = camera.view * vec4<f32>(center, 1.0);
+    let depth = -view_center.z;
+    if depth <= 0.0001 || hidden(instance) {
+        out.clip_position = HIDDEN;
+        return out;
+    }
+
+    let deviation = appearance.size / (2.0 * SPLAT_EXTENT);
+    let speed = length(velocity);
+    var covariance = mat3x3<f32>(
+        vec3<f32>(deviation * deviation, 0.0, 0.0),
+        vec3<f32>(0.0, deviation * deviation, 0.0),
+        vec3<f32>(0.0, 0.0, deviation * deviation),
+    );
+    if speed > 0.0001 {
+        let along = velocity / speed;
+        let streak = max(speed * STREAK_FRAMES / (2.0 * SPLAT_EXTENT), deviation);
+        let extra = streak * streak - deviation * deviation;
+        covariance += mat3x3<f32>(along * along.x, along * along.y, along * along.z) * extra;
+    }
+    let view = mat3x3<f32>(camera.view[0].xyz, camera.view[1].xyz, camera.view[2].xyz);
+    let view_covariance = view * covariance * transpose(view);
+    // Derivatives of the normalized device coordinates, by view-space x, y and z
+    let fx = camera.proj[0][0];
+    let fy = camera.proj[1][1];
+    let jacobian = mat3x2<f32>(
+        vec2<f32>(fx / depth, 0.0),
+        vec2<f32>(0.0, fy / depth),
+        vec2<f32>(fx * view_center.x, fy * view_center.y) / (depth * depth),
+    );
+    let screen = jacobian * view_covariance * transpose(jacobian);
+    // Slightly blurred, so splats smaller than a pixel don't vanish between samples
+    let a = screen[0][0] + 0.000001;
+    let b = screen[0][1];
+    let c = screen[1][1] + 0.000001;
+    let determinant = a * c - b * b;
+    if determinant <= 0.0 {
+        out.clip_position = HIDDEN;
+        return out;
+    }
+    let middle = (a + c) / 2.0;
+    let largest = middle + sqrt(max(middle * middle - determinant, 0.0));
+    let radius = SPLAT_EXTENT * sqrt(largest);
+
+    let clip_center = camera.proj * view_center;
+    out.offset = model.position.xy * 2.0 * radius;
+    out.clip_position = clip_center + vec4<f32>(out.offset * clip_center.w, 0.0, 0.0);
+    out.color = appearance.color;
+    out.conic = vec3<f32>(c, -b, a) / determinant;
+    return out;
+}
+
+// `v` turned by the quaternion `q`
+fn rotate(q: vec4<f32>, v: vec3<f32>) -> vec3<f32> {
+    return v + 2.0 * cross(q.xyz, cross(q.xyz, v) + q.w * v);
+}
+
+// Quad turned by the particle's orientation, for the spinning particles
+@vertex
+fn vs_spinning(
+    model: VertexInput,
+    instance: InstanceInput,
+    @location(5) orientation: vec4<f32>,
+) -> VertexOutput {
+    let turned = VertexInput(
+        rotate(orientation, model.position),
+        model.vertex_position,
+        rotate(orientation, model.normal),
+    );
+    return quad_vertex(turned, instance);
+}
+
 // Fragment shader
 
+// Sharpness of the Blinn-Phong highlights
+const SHININESS: f32 = 32.0;
+
+struct Shading {
+    diffuse: vec3<f32>,
+    specular: vec3<f32>,
+};
+
+// Lambert diffuse and Blinn-Phong highlights of the scene lights
+fn scene_lighting(normal: vec3<f32>, position: vec3<f32>, to_eye: vec3<f32>) -> Shading {
+    var shading: Shading;
+    let view = normalize(to_eye);
+    for (var i = 0u; i < scene_lights.count; i++) {
+        let light = scene_lights.lights[i];
+        var direction = light.position.xyz;
+        var attenuation = 1.0;
+        if light.position.w > 0.0 {
+            let to_light = light.position.xyz - position;
+            let distance = length(to_light);
+            direction = to_light / max(distance, 0.0001);
+            let falloff = clamp(1.0 - distance / max(light.range, 0.0001), 0.0, 1.0);
+            attenuation = falloff * falloff;
+        }
+        let radiance = light.color.rgb * light.color.w * attenuation;
+        let lambert = max(dot(normal, direction), 0.0);
+        shading.diffuse += radiance * lambert;
+        if light.specular > 0.0 && lambert > 0.0 {
+            let halfway = normalize(direction + view);
+            let highlight = pow(max(dot(normal, halfway), 0.0), SHININESS);
+            shading.specular += radiance * light.specular * highlight;
+        }
+    }
+    return shading;
+}
+
+fn particle_color(in: VertexOutput) -> vec4<f32> {
+    let alpha = 1.0 - (length(in.vertex_position - 0.5) * 2.0);
+    // Shaded like a sphere bulging out of the quad
+    let offset = (in.vertex_position - 0.5) * 2.0;
+    let bulge = sqrt(max(1.0 - dot(offset, offset), 0.0));
+    let normal = normalize(vec3<f32>(offset, 0.0) + in.normal * bulge);
+    let ambient = lighting.light.w;
+    let diffuse = ambient + (1.0 - ambient) * max(dot(normal, lighting.light.xyz), 0.0);
+    let shading = scene_lighting(normal, in.world_position, in.to_eye);
+    let color = in.vertex_color.rgb * lighting.tint.rgb * (diffuse + shading.diffuse);
+    return vec4<f32>(color + shading.specular, in.vertex_color.a * alpha);
+}
+
 @fragment
 fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
-    let alpha = 1.0 - (length(in.vertex_position - 0.5) * 2.0);
-    var out_color: vec4<f32> = in.vertex_color;
-    out_color.a *= alpha;
-    return out_color;
+    return particle_color(in);
+}
+
+// Must match `DEPTH_SCALE` in depth_of_field.rs
+const DEPTH_SCALE: f32 = 1000.0;
+
+struct DepthOutput {
+    @location(0) color: vec4<f32>,
+    @builtin(frag_depth) depth: f32,
+};
+
+// View-space depth remapped to [0, 1), from the camera towards infinity or the other way around
+// with reverse-Z. The post effects turn it back into view-space depth without the clip planes
+fn remap_depth(view_depth: f32) -> f32 {
+    if camera.reverse_z != 0u {
+        return DEPTH_SCALE / (view_depth + DEPTH_SCALE);
+    }
+    return view_depth / (view_depth + DEPTH_SCALE);
+}
+
+// Same as `fs_main`, also writing depth for the depth-of-field pass
+@fragment
+fn fs_depth(in: VertexOutput) -> DepthOutput {
+    let color = particle_color(in);
+    // The corners of the quad are transparent and must not hide the particles behind them
+    if color.a <= 0.0 {
+        discard;
+    }
+    var out: DepthOutput;
+    out.color = color;
+    out.depth = remap_depth(in.view_depth);
+    return out;
+}
+
+// How much of the particle shows in front of the obstacles, fading to nothing where it meets them
+fn soft_fade(in: VertexOutput) -> f32 {
+    // The depth texture may not be the size of the target, it's looked up at the same place on
+    // screen
+    let clip = camera.view_proj * vec4<f32>(in.world_position, 1.0);
+    let uv = clip.xy / clip.w * vec2<f32>(0.5, -0.5) + 0.5;
+    let size = textureDimensions(obstacle_depth);
+    let texel = min(vec2<u32>(max(uv, vec2<f32>(0.0)) * vec2<f32>(size)), size - 1u);
+    let depth = textureLoad(obstacle_depth, texel, 0).r;
+    return clamp((depth - in.view_depth) / soft.fade_distance, 0.0, 1.0);
+}
+
+// Same as `fs_main`, faded near the obstacles
+@fragment
+fn fs_soft(in: VertexOutput) -> @location(0) vec4<f32> {
+    let color = particle_color(in);
+    return vec4<f32>(color.rgb, color.a * soft_fade(in));
+}
+
+// Same as `fs_depth`, faded near the obstacles
+@fragment
+fn fs_soft_depth(in: VertexOutput) -> DepthOutput {
+    let color = particle_color(in);
+    let alpha = color.a * soft_fade(in);
+    if alpha <= 0.0 {
+        discard;
+    }
+    var out: DepthOutput;
+    out.color = vec4<f32>(color.rgb, alpha);
+    out.depth = remap_depth(in.view_depth);
+    return out;
+}
+
+// Gaussian falloff of the splat, lit only by the tint since it has no surface
+@fragment
+fn fs_splat(in: SplatOutput) -> @location(0) vec4<f32> {
+    let d = in.offset;
+    let distance = in.conic.x * d.x * d.x + 2.0 * in.conic.y * d.x * d.y + in.conic.z * d.y * d.y;
+    let power = -0.5 * distance;
+    let alpha = in.color.a * exp(power);
+    if alpha < 1.0 / 255.0 {
+        discard;
+    }
+    return vec4<f32>(in.color.rgb * lighting.tint.rgb, alpha);
+}
+
+// Opaque particle color, for the debug views: the fading of `fs_main` would hide wireframe edges
+@fragment
+fn fs_flat(in: VertexOutput) -> @location(0) vec4<f32> {
+    return vec4<f32>(in.vertex_color.rgb, 1.0);
 }
@@ -0,0 +1,222 @@
+//! Validation of the WGSL sources with naga before they reach the device.
+//!
+//! wgpu panics on a shader that doesn't compile. Running the source through naga first gives the
+//! error as text annotated with the lines it spans, which the particle shader's hot-reloaded
+//! appearance shows in the overlay instead. The shaders that compile on their own are all checked
+//! when the demo starts, the ones put together from pieces when they're built, with
+//! [`create_module`].
+
+use crate::{render_target::fullscreen_shader, vertex::INSTANCE_COLOR_WGSL};
+
+/// A shader that doesn't parse or validate, with the annotated source lines.
+#[derive(Debug, thiserror::Error)]
+#[error("{0}")]
+pub struct ShaderError(String);
+
+/// Parses and validates `source`, naming it `name` in the error.
+pub fn validate(name: &str, source: &str) -> Result<(), ShaderError> {
+    let module = naga::front::wgsl::parse_str(source)
+        .map_err(|e| ShaderError(e.emit_to_string_with_path(source, name)))?;
+    naga::valid::Validator::new(
+        naga::valid::ValidationFlags::all(),
+        naga::valid::Capabilities::all(),
+    )
+    .validate(&module)
+    .map_err(|e| ShaderError(e.emit_to_string_with_path(source, name)))?;
+    Ok(())
+}
+
+/// Validates `source`, put together from pieces, before creating its module. The pieces are the
+/// demo's own, an invalid shader is a bug: it panics with the annotated lines of `name` rather than
+/// with wgpu's message.
+pub fn create_module(
+    device: &wgpu::Device,
+    label: &str,
+    name: &str,
+    source: &str,
+) -> wgpu::ShaderModule {
+    if let Err(e) = validate(name, source) {
+        panic!("{e}");
+    }
+    device.create_shader_module(wgpu::ShaderModuleDescriptor {
+        label: Some(label),
+        source: wgpu::ShaderSource::Wgsl(source.into()),
+    })
+}
+
+// The shaders complete as they are
+const SOURCES: [(&str, &str); 17] = [
+    ("emitter.wgsl", include_str!("emitter.wgsl")),
+    ("environment.wgsl", include_str!("environment.wgsl")),
+    ("frame_hash.wgsl", include_str!("frame_hash.wgsl")),
+    ("gizmos.wgsl", include_str!("gizmos.wgsl")),
+    (
+        "motion_blur_velocity.wgsl",
+        include_str!("motion_blur_velocity.wgsl"),
+    ),
+    ("multi_draw.wgsl", include_str!("multi_draw.wgsl")),
+    ("neighbors.wgsl", include_str!("neighbors.wgsl")),
+    ("obstacles.wgsl", include_str!("obstacles.wgsl")),
+    ("panorama.wgsl", include_str!("panorama.wgsl")),
+    ("picking.wgsl", include_str!("picking.wgsl")),
+    ("prefix_sum.wgsl", include_str!("prefix_sum.wgsl")),
+    ("radix_sort.wgsl", include_str!("radix_sort.wgsl")),
+    ("spatial_hash.wgsl", include_str!("spatial_hash.wgsl")),
+    ("trails.wgsl", include_str!("trails.wgsl")),
+    ("ui.wgsl", include_str!("ui.wgsl")),
+    ("volume.wgsl", include_str!("volume.wgsl")),
+    ("volume_splat.wgsl", include_str!("volume_splat.wgsl")),
+];
+
+// The fullscreen passes, complete after the triangle they share
+const FULLSCREEN_SOURCES: [(&str, &str); 9] = [
+    ("blit.wgsl", include_str!("blit.wgsl")),
+    ("checkerboard.wgsl", include_str!("checkerboard.wgsl")),
+    ("color_grading.wgsl", include_str!("color_grading.wgsl")),
+    ("depth_of_field.wgsl", include_str!("depth_of_field.wgsl")),
+    ("half_resolution.wgsl", include_str!("half_resolution.wgsl")),
+    ("heatmap.wgsl", include_str!("heatmap.wgsl")),
+    ("motion_blur.wgsl", include_str!("motion_blur.wgsl")),
+    ("stereo_view.wgsl", include_str!("stereo_view.wgsl")),
+    ("temporal.wgsl", include_str!("temporal.wgsl")),
+];
+
+/// Validates the shaders that compile on their own or after the fullscreen triangle, and the
+/// particle shader without appearance.
+pub fn validate_builtin() -> Result<(), ShaderError> {
+    let start = std::time::Instant::now();
+    for (name, source) in SOURCES {
+        validate(name, source)?;
+    }
+    for (name, source) in FULLSCREEN_SOURCES {
+        validate(name, &fullscreen_shader(source))?;
+    }
+    validate(
+        "shader.wgsl",
+        &format!("{INSTANCE_COLOR_WGSL}\n{}", include_str!("shader.wgsl")),
+    )?;
+    log::debug!("Validated the shaders in {:?}", start.elapsed());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        behavior::{Behaviors, BuiltinBehavior},
+        boids, collisions, compaction, lod, nbody, obstacles, particle_init, reduction,
+        speed_layout::SpeedLayout,
+        spinning, state,
+        vertex::INSTANCE_POSITION_WGSL,
+    };
+
+    /// The shaders put together from pieces, with the files of the pieces. The ones binding the
+    /// speeds are assembled for each of their layouts.
+    fn assembled() -> Vec<(&'static [&'static str], String)> {
+        let behaviors = Behaviors::new(&[
+            "oscillation".parse::<BuiltinBehavior>().unwrap(),
+            "spiral".parse().unwrap(),
+        ]);
+        let mut assembled = [SpeedLayout::F32, SpeedLayout::F16]
+            .into_iter()
+            .flat_map(|speed_layout| -> [(&'static [&'static str], String); 7] {
+                [
+                    (
+                        &[
+                            "obstacles.wgsl",
+                            "wind.wgsl",
+                            "emitter.wgsl",
+                            "compute_kernel.wgsl",
+                        ],
+                        state::compute_kernel_source(&behaviors, speed_layout),
+                    ),
+                    (
+                        &["emitter.wgsl", "particle_init.wgsl"],
+                        particle_init::shader_source(speed_layout),
+                    ),
+                    (&["nbody.wgsl"], nbody::shader_source(64, speed_layout)),
+                    (
+                        &["nbody_grid.wgsl"],
+                        nbody::grid_shader_source(speed_layout),
+                    ),
+                    (
+                        &["neighbors.wgsl", "boids.wgsl"],
+                        boids::shader_source(64, speed_layout),
+                    ),
+                    (
+                        &["neighbors.wgsl", "collisions.wgsl"],
+                        collisions::shader_source(speed_layout),
+                    ),
+                    (&["reduction.wgsl"], reduction::shader_source(speed_layout)),
+                ]
+            })
+            .collect::<Vec<_>>();
+        assembled.extend::<[(&[&str], String); 5]>([
+            (
+                &["fullscreen.wgsl", "obstacles.wgsl", "obstacle_view.wgsl"],
+                obstacles::view_shader_source(),
+            ),
+            (&["compaction.wgsl"], compaction::shader_source()),
+            (&["lod.wgsl"], lod::shader_source()),
+            (&["spinning.wgsl"], spinning::shader_source()),
+            // Like position_packing::shader_source(), only compiled with the `packed-instances`
+            // feature
+            (
+                &["position_packing.wgsl"],
+                format!(
+                    "{INSTANCE_POSITION_WGSL}\n{}",
+                    include_str!("position_packing.wgsl")
+                ),
+            ),
+        ]);
+        assembled
+    }
+
+    #[test]
+    fn builtin_shaders_validate() {
+        if let Err(e) = validate_builtin() {
+            panic!("{e}");
+        }
+    }
+
+    #[test]
+    fn assembled_shaders_validate() {
+        for (files, source) in assembled() {
+            if let Err(e) = validate(files.last().unwrap(), &source) {
+                panic!("{e}");
+            }
+        }
+    }
+
+    #[test]
+    fn every_shader_is_validated() {
+        let assembled = assembled();
+        let validated = SOURCES
+            .iter()
+            .chain(&FULLSCREEN_SOURCES)
+            .map(|(name, _)| *name)
+            .chain(["fullscreen.wgsl", "shader.wgsl"])
+            .chain(
+                assembled
+                    .iter()
+                    .flat_map(|(files, _)| files.iter().copied()),
+            )
+            .collect::<Vec<_>>();
+        let directory = concat!(env!("CARGO_MANIFEST_DIR"), "/src");
+        for entry in std::fs::read_dir(directory).unwrap() {
+            let name = entry.unwrap().file_name().into_string().unwrap();
+            assert!(
+                !name.ends_with(".wgsl") || validated.contains(&name.as_str()),
+                "{name} isn't validated, add it to SOURCES, FULLSCREEN_SOURCES or to the assembled shaders"
+            );
+        }
+    }
+
+    #[test]
+    fn errors_point_at_the_source() {
+        let source = "fn f() -> f32 {\n    return 1u;\n}\n";
+        let error = validate("broken.wgsl", source).unwrap_err().to_string();
+        assert!(error.contains("broken.wgsl:1"), "{error}");
+        assert!(error.contains("return 1u;"), "{error}");
+    }
+}
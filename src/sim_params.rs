@@ -0,0 +1,389 @@
+use std::str::FromStr;
+
+use bytemuck::{Pod, Zeroable};
+use glam::Vec3A;
+
+use crate::{
+    behavior::Behaviors,
+    obstacles::{self, Obstacle},
+    wind::Flow,
+};
+
+// Bounds far enough that particles never reach them
+const UNBOUNDED: f32 = 1e30;
+// Half size of the box particles stay in when bounds are enabled
+const BOX_HALF_SIZE: f32 = 1000.0;
+// Spreads the lifetimes of the particles, so they don't all respawn at once
+const GOLDEN_RATIO_FRACTION: f32 = 0.618_034;
+
+/// What moves the particles, on top of the turbulence and forces of [`SimParams`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SimMode {
+    #[default]
+    Turbulence,
+    /// Particles flock, see boids.rs
+    Boids,
+    /// Particles attract each other, see nbody.rs
+    Nbody,
+}
+
+impl FromStr for SimMode {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "turbulence" => Ok(SimMode::Turbulence),
+            "boids" => Ok(SimMode::Boids),
+            "nbody" => Ok(SimMode::Nbody),
+            _ => Err(()),
+        }
+    }
+}
+
+/// How the CPU backend moves the particles.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CpuPath {
+    /// [`SimParams::step`], on 12-byte vectors
+    Scalar,
+    /// [`SimParams::step_simd`], on 16-byte SIMD vectors updated in place in the instance buffer
+    #[default]
+    Simd,
+}
+
+impl FromStr for CpuPath {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "scalar" => Ok(CpuPath::Scalar),
+            "simd" => Ok(CpuPath::Simd),
+            _ => Err(()),
+        }
+    }
+}
+
+/// What happens to particles leaving the bounds along one axis.
+#[repr(u32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Boundary {
+    /// Reflected back in
+    Bounce = 0,
+    /// Stopped on the face, losing their speed along the axis
+    Clamp = 1,
+    /// Brought back in through the opposite face, so flows never run out of particles
+    Wrap = 2,
+}
+
+impl FromStr for Boundary {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "bounce" => Ok(Boundary::Bounce),
+            "clamp" => Ok(Boundary::Clamp),
+            "wrap" => Ok(Boundary::Wrap),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Simulation parameters tunable at runtime, shared with compute_kernel.wgsl.
+///
+/// [`SimParams::step`] must stay in sync with the passes of the compute kernel so both backends
+/// move particles the same way.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Pod, Zeroable)]
+pub struct SimParams {
+    /// Particles stay in this box, w is unused
+    pub bounds_min: glam::Vec4,
+    pub bounds_max: glam::Vec4,
+    /// [`Boundary`] of the x, y and z axes, w is unused
+    pub boundary: [u32; 4],
+    /// Length of a simulation step, in frames
+    pub dt: f32,
+    /// Fraction of its speed a particle loses every frame
+    pub damping: f32,
+    pub speed_multiplier: f32,
+    /// Acceleration towards the origin, negative values push particles away
+    pub attractor_strength: f32,
+    /// Particles inside this box are simulated in `roi_substeps` steps per frame, w is unused.
+    /// Empty by default.
+    pub roi_min: glam::Vec4,
+    pub roi_max: glam::Vec4,
+    pub roi_substeps: u32,
+    /// Particles outside of the region of interest only move every this many frames, by as many
+    /// frames at once
+    pub coarse_interval: u32,
+    /// Frames simulated so far, spreads the coarse updates over the frames
+    pub frame: u32,
+    /// Particles closer than twice this collide, see collisions.rs. 0 disables collisions.
+    pub collision_radius: f32,
+    /// Fraction of their closing speed colliding particles bounce back with, 1 being elastic
+    pub restitution: f32,
+    /// Obstacles the compute kernel reads from its obstacle buffer, see obstacles.rs
+    pub obstacle_count: u32,
+    /// Average length of a particle's life, in frames, see [`SimParams::age`]. 0 keeps particles
+    /// forever.
+    pub lifetime: f32,
+    /// Distance from the impulse's center at which it's half as strong
+    pub impulse_radius: f32,
+    /// Acceleration of every particle, like gravity or wind, w is unused
+    pub force: glam::Vec4,
+    /// Center of a change of speed applied once, in xyz, and its strength at the center in w:
+    /// positive pushes the particles away, negative pulls them in. See [`SimParams::impulse_at`].
+    pub impulse: glam::Vec4,
+}
+
+impl Default for SimParams {
+    fn default() -> Self {
+        Self {
+            bounds_min: glam::Vec4::splat(-UNBOUNDED),
+            bounds_max: glam::Vec4::splat(UNBOUNDED),
+            boundary: [Boundary::Bounce as u32; 4],
+            dt: 1.0,
+            damping: 0.0,
+            speed_multiplier: 1.0,
+            attractor_strength: 0.0,
+            roi_min: glam::Vec4::splat(UNBOUNDED),
+            roi_max: glam::Vec4::splat(-UNBOUNDED),
+            roi_substeps: 1,
+            coarse_interval: 1,
+            frame: 0,
+            collision_radius: 0.0,
+            restitution: 1.0,
+            obstacle_count: 0,
+            lifetime: 0.0,
+            impulse_radius: 1.0,
+            force: glam::Vec4::ZERO,
+            impulse: glam::Vec4::ZERO,
+        }
+    }
+}
+
+impl SimParams {
+    pub fn bounded(&self) -> bool {
+        self.bounds_max.x < UNBOUNDED
+    }
+
+    pub fn toggle_bounds(&mut self) {
+        let half_size = if self.bounded() {
+            UNBOUNDED
+        } else {
+            BOX_HALF_SIZE
+        };
+        self.bounds_min = glam::Vec4::splat(-half_size);
+        self.bounds_max = glam::Vec4::splat(half_size);
+    }
+
+    pub fn collisions(&self) -> bool {
+        self.collision_radius > 0.0
+    }
+
+    pub fn set_boundary(&mut self, axis: usize, boundary: Boundary) {
+        self.boundary[axis] = boundary as u32;
+    }
+
+    fn axes_with(&self, boundary: Boundary) -> glam::BVec3 {
+        let [x, y, z, _] = self.boundary.map(|axis| axis == boundary as u32);
+        glam::BVec3::new(x, y, z)
+    }
+
+    fn in_roi(&self, position: glam::Vec3) -> bool {
+        position.cmpge(self.roi_min.truncate()).all()
+            && position.cmple(self.roi_max.truncate()).all()
+    }
+
+    /// Length of the frame particle `index` at `position` moves by, 0 if it doesn't move this
+    /// frame.
+    fn frame_dt(&self, index: usize, position: glam::Vec3) -> f32 {
+        if self.in_roi(position) {
+            self.dt
+        } else if (index as u32)
+            .wrapping_add(self.frame)
+            .is_multiple_of(self.coarse_interval)
+        {
+            self.dt * self.coarse_interval as f32
+        } else {
+            0.0
+        }
+    }
+
+    /// Age of particle `index` at `position` after this frame, from its `age` before it, as a
+    /// fraction of its life. Particles live between half and one and a half `lifetime`, the ones
+    /// reaching 1 respawn. Must match `age_particle` in compute_kernel.wgsl.
+    pub fn age(&self, index: usize, position: glam::Vec3, age: f32) -> f32 {
+        if self.lifetime <= 0.0 {
+            return 0.0;
+        }
+        let spread = (index as f32 * GOLDEN_RATIO_FRACTION).fract() + 0.5;
+        age + self.frame_dt(index, position) / (self.lifetime * spread)
+    }
+
+    /// Change of speed of a particle at `position` from the impulse, along the line from its center
+    /// and fading with the square of the distance to it. Must match `impulse_at` in
+    /// compute_kernel.wgsl.
+    pub fn impulse_at(&self, position: glam::Vec3) -> glam::Vec3 {
+        if self.impulse.w == 0.0 {
+            return glam::Vec3::ZERO;
+        }
+        let offset = position - self.impulse.truncate();
+        let falloff = 1.0 / (1.0 + offset.length_squared() / self.impulse_radius.powi(2));
+        offset.normalize_or_zero() * self.impulse.w * falloff
+    }
+
+    /// Advances particle `index` by one frame, updating its `speed` and returning its new
+    /// position. The compute kernel reads `obstacle_count` obstacles from its buffer instead of
+    /// `obstacles`, and calls the WGSL of `behaviors`.
+    pub fn step(
+        &self,
+        index: usize,
+        position: glam::Vec3,
+        speed: &mut glam::Vec3,
+        flow: &Flow,
+        obstacles: &[Obstacle],
+        behaviors: &Behaviors,
+    ) -> glam::Vec3 {
+        // Every particle feels the impulse, the ones not moving this frame included
+        *speed += self.impulse_at(position);
+        if self.in_roi(position) {
+            let dt = self.dt / self.roi_substeps as f32;
+            let position = (0..self.roi_substeps).fold(position, |position, _| {
+                self.substep(position, speed, flow.velocity(position), obstacles, dt)
+            });
+            behaviors.update(index, position, speed, flow.turbulence.time, self.dt)
+        } else if (index as u32)
+            .wrapping_add(self.frame)
+            .is_multiple_of(self.coarse_interval)
+        {
+            let dt = self.dt * self.coarse_interval as f32;
+            let position = self.substep(position, speed, flow.velocity(position), obstacles, dt);
+            behaviors.update(index, position, speed, flow.turbulence.time, dt)
+        } else {
+            position
+        }
+    }
+
+    /// Same as [`SimParams::step`] on SIMD vectors, must stay in sync with it.
+    pub fn step_simd(
+        &self,
+        index: usize,
+        position: Vec3A,
+        speed: &mut Vec3A,
+        flow: &Flow,
+        obstacles: &[Obstacle],
+        behaviors: &Behaviors,
+    ) -> Vec3A {
+        *speed += Vec3A::from(self.impulse_at(position.into()));
+        let in_roi =
+            position.cmpge(self.roi_min.into()).all() && position.cmple(self.roi_max.into()).all();
+        if in_roi {
+            let dt = self.dt / self.roi_substeps as f32;
+            let position = (0..self.roi_substeps).fold(position, |position, _| {
+                self.substep_simd(position, speed, flow.velocity_simd(position), obstacles, dt)
+            });
+            Self::behave_simd(
+                behaviors,
+                index,
+                position,
+                speed,
+                flow.turbulence.time,
+                self.dt,
+            )
+        } else if (index as u32)
+            .wrapping_add(self.frame)
+            .is_multiple_of(self.coarse_interval)
+        {
+            let dt = self.dt * self.coarse_interval as f32;
+            let position =
+                self.substep_simd(position, speed, flow.velocity_simd(position), obstacles, dt);
+            Self::behave_simd(behaviors, index, position, speed, flow.turbulence.time, dt)
+        } else {
+            position
+        }
+    }
+
+    // Behaviors work on 12-byte vectors, converted to only when there are some
+    fn behave_simd(
+        behaviors: &Behaviors,
+        index: usize,
+        position: Vec3A,
+        speed: &mut Vec3A,
+        time: f32,
+        dt: f32,
+    ) -> Vec3A {
+        if behaviors.is_empty() {
+            return position;
+        }
+        let mut scalar_speed = glam::Vec3::from(*speed);
+        let position = behaviors.update(index, position.into(), &mut scalar_speed, time, dt);
+        *speed = scalar_speed.into();
+        position.into()
+    }
+
+    fn substep_simd(
+        &self,
+        position: Vec3A,
+        speed: &mut Vec3A,
+        flow_velocity: Vec3A,
+        obstacles: &[Obstacle],
+        dt: f32,
+    ) -> Vec3A {
+        *speed *= (1.0 - self.damping).powf(dt);
+        *speed -= position.normalize_or_zero() * self.attractor_strength * dt;
+        *speed += Vec3A::from(self.force) * dt;
+        let mut position = position + (*speed * self.speed_multiplier + flow_velocity) * dt;
+        if !obstacles.is_empty() {
+            let mut scalar_speed = glam::Vec3::from(*speed);
+            position = obstacles::bounce(obstacles, position.into(), &mut scalar_speed).into();
+            *speed = scalar_speed.into();
+        }
+
+        let (min, max) = (Vec3A::from(self.bounds_min), Vec3A::from(self.bounds_max));
+        let (below, above) = (position.cmplt(min), position.cmpgt(max));
+        let [bounce, clamp, wrap] =
+            [Boundary::Bounce, Boundary::Clamp, Boundary::Wrap].map(|b| self.axes_with_simd(b));
+        *speed = Vec3A::select(bounce & below, speed.abs(), *speed);
+        *speed = Vec3A::select(bounce & above, -speed.abs(), *speed);
+        *speed = Vec3A::select(clamp & (below | above), Vec3A::ZERO, *speed);
+
+        let size = max - min;
+        let wrapped = position - size * ((position - min) / size).floor();
+        Vec3A::select(wrap, wrapped, position.clamp(min, max))
+    }
+
+    fn axes_with_simd(&self, boundary: Boundary) -> glam::BVec3A {
+        let [x, y, z, _] = self.boundary.map(|axis| axis == boundary as u32);
+        glam::BVec3A::new(x, y, z)
+    }
+
+    fn substep(
+        &self,
+        position: glam::Vec3,
+        speed: &mut glam::Vec3,
+        flow_velocity: glam::Vec3,
+        obstacles: &[Obstacle],
+        dt: f32,
+    ) -> glam::Vec3 {
+        *speed *= (1.0 - self.damping).powf(dt);
+        *speed -= position.normalize_or_zero() * self.attractor_strength * dt;
+        *speed += self.force.truncate() * dt;
+        let position = position + (*speed * self.speed_multiplier + flow_velocity) * dt;
+        let position = obstacles::bounce(obstacles, position, speed);
+
+        let (min, max) = (self.bounds_min.truncate(), self.bounds_max.truncate());
+        let (below, above) = (position.cmplt(min), position.cmpgt(max));
+        let bounce = self.axes_with(Boundary::Bounce);
+        *speed = glam::Vec3::select(bounce & below, speed.abs(), *speed);
+        *speed = glam::Vec3::select(bounce & above, -speed.abs(), *speed);
+        let clamp = self.axes_with(Boundary::Clamp);
+        *speed = glam::Vec3::select(clamp & (below | above), glam::Vec3::ZERO, *speed);
+
+        let size = max - min;
+        let wrapped = position - size * ((position - min) / size).floor();
+        glam::Vec3::select(
+            self.axes_with(Boundary::Wrap),
+            wrapped,
+            position.clamp(min, max),
+        )
+    }
+}
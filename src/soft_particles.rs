@@ -0,0 +1,151 @@
+//! Soft particles, fading out where they meet the obstacles instead of being cut by them.
+//!
+//! While the obstacle view shows the obstacles, their view-space depth is raymarched into a
+//! texture owned here before the particles are drawn. The particles then fade by how far in front
+//! of that depth they are, over `--soft-particles` units, and the ones behind the obstacles are
+//! hidden.
+
+use bytemuck::{Pod, Zeroable};
+use wgpu::util::DeviceExt;
+
+/// Of the obstacles' depth, in view space rather than remapped like the particles' depth buffer.
+pub const OBSTACLE_DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::R32Float;
+
+/// Layout of the bind group at index 1 of the soft particle pipelines.
+pub const BIND_GROUP_LAYOUT_ENTRIES: [wgpu::BindGroupLayoutEntry; 2] = [
+    wgpu::BindGroupLayoutEntry {
+        binding: 0,
+        visibility: wgpu::ShaderStages::FRAGMENT,
+        ty: wgpu::BindingType::Texture {
+            sample_type: wgpu::TextureSampleType::Float { filterable: false },
+            view_dimension: wgpu::TextureViewDimension::D2,
+            multisampled: false,
+        },
+        count: None,
+    },
+    wgpu::BindGroupLayoutEntry {
+        binding: 1,
+        visibility: wgpu::ShaderStages::FRAGMENT,
+        ty: wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Uniform,
+            has_dynamic_offset: false,
+            min_binding_size: None,
+        },
+        count: None,
+    },
+];
+
+// Must match SoftParams in shader.wgsl
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Pod, Zeroable)]
+struct SoftParams {
+    fade_distance: f32,
+    _padding: [f32; 3],
+}
+
+struct Target {
+    size: (u32, u32),
+    // Kept alive for the view
+    _texture: wgpu::Texture,
+    view: wgpu::TextureView,
+    bind_group: wgpu::BindGroup,
+}
+
+pub struct SoftParticles {
+    fade_distance: f32,
+    params_buffer: wgpu::Buffer,
+    target: Option<Target>,
+}
+
+impl SoftParticles {
+    /// Fades the particles over `fade_distance` in front of the obstacles.
+    pub fn new(device: &wgpu::Device, fade_distance: f32) -> Self {
+        let params = SoftParams {
+            fade_distance,
+            _padding: [0.0; 3],
+        };
+        let params_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Soft Particles Params Buffer"),
+            contents: bytemuck::cast_slice(&[params]),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+        Self {
+            fade_distance,
+            params_buffer,
+            target: None,
+        }
+    }
+
+    pub fn fade_distance(&self) -> f32 {
+        self.fade_distance
+    }
+
+    /// Makes sure the depth texture is `size`, bound with `layout`, of
+    /// [`BIND_GROUP_LAYOUT_ENTRIES`]. Must be called before [`SoftParticles::depth_view`] and
+    /// [`SoftParticles::bind_group`] every time the scene is drawn.
+    pub fn prepare(
+        &mut self,
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        size: (u32, u32),
+    ) {
+        if self.target.as_ref().map(|target| target.size) != Some(size) {
+            self.target = Some(self.create_target(device, layout, size));
+        }
+    }
+
+    /// View the obstacles' depth should be drawn into, once prepared.
+    pub fn depth_view(&self) -> Option<&wgpu::TextureView> {
+        Some(&self.target.as_ref()?.view)
+    }
+
+    /// Bind group of the soft particle pipelines, once prepared.
+    pub fn bind_group(&self) -> Option<&wgpu::BindGroup> {
+        Some(&self.target.as_ref()?.bind_group)
+    }
+
+    fn create_target(
+        &self,
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        size: (u32, u32),
+    ) -> Target {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Obstacle Depth Texture"),
+            size: wgpu::Extent3d {
+                width: size.0,
+                height: size.1,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: OBSTACLE_DEPTH_FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Soft Particles Bind Group"),
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: self.params_buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        Target {
+            size,
+            _texture: texture,
+            view,
+            bind_group,
+        }
+    }
+}
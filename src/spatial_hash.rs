@@ -0,0 +1,356 @@
+//! Uniform grid spatial hash of the particles, rebuilt on the GPU every frame, for the kernels
+//! acting on nearby particles.
+//!
+//! The positions are hashed into a table of cells by counting sort: the particles of each cell
+//! are counted, the counts scanned into the start of each cell, and the particle indices
+//! scattered to their cell. Kernels include [`NEIGHBORS_WGSL`] to iterate the particles of the
+//! cells around a position. Indices within a cell come in whatever order the invocations ran.
+
+use std::fmt::Display;
+
+use bytemuck::{Pod, Zeroable};
+use wgpu::util::DeviceExt;
+
+use crate::vertex::InstancePosition;
+
+/// Neighbor queries for kernels binding [`SpatialHash::neighbors_bind_group`] to group 1.
+pub const NEIGHBORS_WGSL: &str = include_str!("neighbors.wgsl");
+
+// Must match `@workgroup_size` of count and scatter in spatial_hash.wgsl
+const WORKGROUP_SIZE: u32 = 64;
+// Must match `SCAN_THREADS` in spatial_hash.wgsl
+const SCAN_THREADS: u32 = 256;
+// Each scan thread goes through table_size / SCAN_THREADS cells in turn
+const MAX_TABLE_SIZE: u32 = 1 << 20;
+
+// Must match HashParams in spatial_hash.wgsl and neighbors.wgsl
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Pod, Zeroable)]
+struct HashParams {
+    cell_size: f32,
+    table_size: u32,
+    count: u32,
+    _padding: u32,
+}
+
+/// How the particles spread over the cells, to tune the cell size.
+#[derive(Debug, Clone, Copy)]
+pub struct HashStats {
+    pub particles: usize,
+    pub occupied_cells: usize,
+    pub max_per_cell: u32,
+}
+
+impl Display for HashStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} particles in {} cells, at most {} per cell",
+            self.particles, self.occupied_cells, self.max_per_cell
+        )
+    }
+}
+
+pub struct SpatialHash {
+    capacity: usize,
+    cell_size: f32,
+    // As last written to the params buffer
+    params: HashParams,
+    params_buffer: wgpu::Buffer,
+    cell_counts_buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+    count_pipeline: wgpu::ComputePipeline,
+    scan_pipeline: wgpu::ComputePipeline,
+    scatter_pipeline: wgpu::ComputePipeline,
+    neighbors_bind_group_layout: wgpu::BindGroupLayout,
+    neighbors_bind_group: wgpu::BindGroup,
+}
+
+impl SpatialHash {
+    /// Hashes the first particles of the `capacity` in `position_buffer` into cells `cell_size`
+    /// wide. Returns `None` if the device can't bind the buffers.
+    pub fn new(
+        device: &wgpu::Device,
+        capacity: usize,
+        cell_size: f32,
+        position_buffer: &wgpu::Buffer,
+    ) -> Option<Self> {
+        let position_size = (capacity * std::mem::size_of::<InstancePosition>()) as u64;
+        let limits = device.limits();
+        let max_size = (limits.max_storage_buffer_binding_size as u64).min(limits.max_buffer_size);
+        if position_size > max_size {
+            return None;
+        }
+
+        let table_size = (capacity as u32)
+            .next_power_of_two()
+            .clamp(SCAN_THREADS, MAX_TABLE_SIZE);
+        let params = HashParams {
+            cell_size,
+            table_size,
+            count: 0,
+            _padding: 0,
+        };
+        let params_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Spatial Hash Params Buffer"),
+            contents: bytemuck::cast_slice(&[params]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let u32_buffer = |label, len: usize, usage| {
+            device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some(label),
+                size: (len.max(1) * std::mem::size_of::<u32>()) as u64,
+                usage: wgpu::BufferUsages::STORAGE | usage,
+                mapped_at_creation: false,
+            })
+        };
+        let keys_buffer = u32_buffer(
+            "Spatial Hash Keys Buffer",
+            capacity,
+            wgpu::BufferUsages::empty(),
+        );
+        let cell_counts_buffer = u32_buffer(
+            "Spatial Hash Cell Counts Buffer",
+            table_size as usize,
+            wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::COPY_SRC,
+        );
+        let cell_starts_buffer = u32_buffer(
+            "Spatial Hash Cell Starts Buffer",
+            table_size as usize,
+            wgpu::BufferUsages::empty(),
+        );
+        let cell_offsets_buffer = u32_buffer(
+            "Spatial Hash Cell Offsets Buffer",
+            table_size as usize,
+            wgpu::BufferUsages::empty(),
+        );
+        let sorted_indices_buffer = u32_buffer(
+            "Spatial Hash Sorted Indices Buffer",
+            capacity,
+            wgpu::BufferUsages::empty(),
+        );
+
+        let uniform_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let storage_entry = |binding, read_only| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let bind_group_layout_entries = [
+            uniform_entry(0),
+            storage_entry(1, true),
+            storage_entry(2, false),
+            storage_entry(3, false),
+            storage_entry(4, false),
+            storage_entry(5, false),
+            storage_entry(6, false),
+        ];
+        let neighbors_bind_group_layout_entries = [
+            uniform_entry(0),
+            storage_entry(1, true),
+            storage_entry(2, true),
+            storage_entry(3, true),
+        ];
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Spatial Hash Bind Group Layout"),
+            entries: &bind_group_layout_entries,
+        });
+        let neighbors_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("Neighbors Bind Group Layout"),
+                entries: &neighbors_bind_group_layout_entries,
+            });
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Spatial Hash Bind Group"),
+            layout: &bind_group_layout,
+            entries: &bind_group_entries(&[
+                &params_buffer,
+                position_buffer,
+                &keys_buffer,
+                &cell_counts_buffer,
+                &cell_starts_buffer,
+                &cell_offsets_buffer,
+                &sorted_indices_buffer,
+            ]),
+        });
+        let neighbors_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Neighbors Bind Group"),
+            layout: &neighbors_bind_group_layout,
+            entries: &bind_group_entries(&[
+                &params_buffer,
+                &cell_starts_buffer,
+                &cell_counts_buffer,
+                &sorted_indices_buffer,
+            ]),
+        });
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Spatial Hash Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("spatial_hash.wgsl").into()),
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Spatial Hash Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let create_pipeline = |label, entry_point| {
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some(label),
+                layout: Some(&pipeline_layout),
+                module: &shader,
+                entry_point,
+            })
+        };
+
+        #[cfg(feature = "guardrails")]
+        {
+            let reflection = crate::guardrails::ShaderReflection::new(
+                "spatial_hash.wgsl",
+                include_str!("spatial_hash.wgsl"),
+            );
+            reflection.check_bind_group_layout(0, &bind_group_layout_entries);
+            reflection.check_struct_size("HashParams", std::mem::size_of::<HashParams>());
+            let reflection =
+                crate::guardrails::ShaderReflection::new("neighbors.wgsl", NEIGHBORS_WGSL);
+            reflection.check_bind_group_layout(1, &neighbors_bind_group_layout_entries);
+            reflection.check_struct_size("HashParams", std::mem::size_of::<HashParams>());
+        }
+
+        Some(Self {
+            capacity,
+            cell_size,
+            params,
+            params_buffer,
+            cell_counts_buffer,
+            bind_group,
+            count_pipeline: create_pipeline("Spatial Hash Count Pipeline", "count"),
+            scan_pipeline: create_pipeline("Spatial Hash Scan Pipeline", "scan"),
+            scatter_pipeline: create_pipeline("Spatial Hash Scatter Pipeline", "scatter"),
+            neighbors_bind_group_layout,
+            neighbors_bind_group,
+        })
+    }
+
+    /// Layout of [`SpatialHash::neighbors_bind_group`], for the pipelines of the kernels
+    /// including [`NEIGHBORS_WGSL`].
+    pub fn neighbors_bind_group_layout(&self) -> &wgpu::BindGroupLayout {
+        &self.neighbors_bind_group_layout
+    }
+
+    pub fn neighbors_bind_group(&self) -> &wgpu::BindGroup {
+        &self.neighbors_bind_group
+    }
+
+    /// Number of keys, cells sharing a key share their range.
+    pub fn table_size(&self) -> u32 {
+        self.params.table_size
+    }
+
+    pub fn set_cell_size(&mut self, cell_size: f32) {
+        self.cell_size = cell_size;
+    }
+
+    /// Hashes the first `count` particles, from their positions once `encoder` runs.
+    pub fn encode(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        count: usize,
+    ) {
+        let count = count.min(self.capacity) as u32;
+        if self.params.count != count || self.params.cell_size != self.cell_size {
+            self.params.count = count;
+            self.params.cell_size = self.cell_size;
+            queue.write_buffer(&self.params_buffer, 0, bytemuck::cast_slice(&[self.params]));
+        }
+        encoder.clear_buffer(&self.cell_counts_buffer, 0, None);
+
+        let groups = count.div_ceil(WORKGROUP_SIZE).max(1);
+        let max_groups = device.limits().max_compute_workgroups_per_dimension;
+        let (x, y) = (groups.min(max_groups), groups.div_ceil(max_groups));
+        #[cfg(feature = "guardrails")]
+        crate::guardrails::check_dispatch_coverage(
+            [x, y, 1],
+            [WORKGROUP_SIZE, 1, 1],
+            count as usize,
+        );
+
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Spatial Hash Pass"),
+        });
+        compute_pass.set_bind_group(0, &self.bind_group, &[]);
+        compute_pass.set_pipeline(&self.count_pipeline);
+        compute_pass.dispatch_workgroups(x, y, 1);
+        compute_pass.set_pipeline(&self.scan_pipeline);
+        compute_pass.dispatch_workgroups(1, 1, 1);
+        compute_pass.set_pipeline(&self.scatter_pipeline);
+        compute_pass.dispatch_workgroups(x, y, 1);
+    }
+
+    /// Reads back how full the cells are, waiting for the GPU.
+    pub fn stats(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> Option<HashStats> {
+        let readback_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Spatial Hash Readback Buffer"),
+            size: self.cell_counts_buffer.size(),
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Spatial Hash Readback Encoder"),
+        });
+        encoder.copy_buffer_to_buffer(
+            &self.cell_counts_buffer,
+            0,
+            &readback_buffer,
+            0,
+            readback_buffer.size(),
+        );
+        queue.submit(Some(encoder.finish()));
+
+        let (sender, receiver) = futures::channel::oneshot::channel();
+        readback_buffer
+            .slice(..)
+            .map_async(wgpu::MapMode::Read, move |result| {
+                let _ = sender.send(result);
+            });
+        device.poll(wgpu::Maintain::Wait);
+        futures::executor::block_on(receiver).ok()?.ok()?;
+
+        let data = readback_buffer.slice(..).get_mapped_range();
+        let counts: &[u32] = bytemuck::cast_slice(&data);
+        Some(HashStats {
+            particles: self.params.count as usize,
+            occupied_cells: counts.iter().filter(|&&count| count > 0).count(),
+            max_per_cell: counts.iter().copied().max().unwrap_or(0),
+        })
+    }
+}
+
+/// Binds each of `buffers` entirely, in order.
+fn bind_group_entries<'a>(buffers: &[&'a wgpu::Buffer]) -> Vec<wgpu::BindGroupEntry<'a>> {
+    buffers
+        .iter()
+        .enumerate()
+        .map(|(binding, buffer)| wgpu::BindGroupEntry {
+            binding: binding as u32,
+            resource: buffer.as_entire_binding(),
+        })
+        .collect()
+}
@@ -0,0 +1,108 @@
+// Uniform grid spatial hash of the particles: each particle is counted in the cell of its key, the
+// counts are scanned into the start of each cell, and the particle indices are scattered sorted by
+// key
+
+// Must match HashParams in spatial_hash.rs
+struct HashParams {
+    cell_size: f32,
+    table_size: u32,
+    count: u32,
+    _padding: u32,
+};
+
+@group(0) @binding(0)
+var<uniform> params: HashParams;
+
+@group(0) @binding(1)
+var<storage, read> positions: array<vec4<f32>>;
+
+// Key of each particle, kept between the count and scatter passes
+@group(0) @binding(2)
+var<storage, read_write> keys: array<u32>;
+
+@group(0) @binding(3)
+var<storage, read_write> cell_counts: array<atomic<u32>>;
+
+@group(0) @binding(4)
+var<storage, read_write> cell_starts: array<u32>;
+
+// Next free slot of each cell while scattering
+@group(0) @binding(5)
+var<storage, read_write> cell_offsets: array<atomic<u32>>;
+
+@group(0) @binding(6)
+var<storage, read_write> sorted_indices: array<u32>;
+
+// Must match `SCAN_THREADS` in spatial_hash.rs
+const SCAN_THREADS: u32 = 256u;
+
+var<workgroup> partial_sums: array<u32, SCAN_THREADS>;
+
+// Must match hash_key in neighbors.wgsl
+fn hash_key(cell: vec3<i32>) -> u32 {
+    let c = bitcast<vec3<u32>>(cell);
+    return ((c.x * 73856093u) ^ (c.y * 19349663u) ^ (c.z * 83492791u)) & (params.table_size - 1u);
+}
+
+// Large counts are dispatched in rows, a single dimension is limited to 65535 workgroups
+fn particle_index(id: vec3<u32>, workgroups: vec3<u32>) -> u32 {
+    return id.x + id.y * workgroups.x * 64u;
+}
+
+@compute @workgroup_size(64)
+fn count(
+    @builtin(global_invocation_id) id: vec3<u32>,
+    @builtin(num_workgroups) workgroups: vec3<u32>,
+) {
+    let index = particle_index(id, workgroups);
+    if index >= params.count {
+        return;
+    }
+    let cell = vec3<i32>(floor(positions[index].xyz / params.cell_size));
+    let key = hash_key(cell);
+    keys[index] = key;
+    atomicAdd(&cell_counts[key], 1u);
+}
+
+// A single workgroup, each thread sums a run of cells before the runs are scanned together
+@compute @workgroup_size(256)
+fn scan(@builtin(local_invocation_index) thread: u32) {
+    let per_thread = params.table_size / SCAN_THREADS;
+    let first = thread * per_thread;
+    var sum = 0u;
+    for (var i = 0u; i < per_thread; i++) {
+        sum += atomicLoad(&cell_counts[first + i]);
+    }
+    partial_sums[thread] = sum;
+    workgroupBarrier();
+
+    for (var stride = 1u; stride < SCAN_THREADS; stride *= 2u) {
+        var value = partial_sums[thread];
+        if thread >= stride {
+            value += partial_sums[thread - stride];
+        }
+        workgroupBarrier();
+        partial_sums[thread] = value;
+        workgroupBarrier();
+    }
+
+    var start = partial_sums[thread] - sum;
+    for (var i = 0u; i < per_thread; i++) {
+        cell_starts[first + i] = start;
+        atomicStore(&cell_offsets[first + i], start);
+        start += atomicLoad(&cell_counts[first + i]);
+    }
+}
+
+@compute @workgroup_size(64)
+fn scatter(
+    @builtin(global_invocation_id) id: vec3<u32>,
+    @builtin(num_workgroups) workgroups: vec3<u32>,
+) {
+    let index = particle_index(id, workgroups);
+    if index >= params.count {
+        return;
+    }
+    let slot = atomicAdd(&cell_offsets[keys[index]], 1u);
+    sorted_indices[slot] = index;
+}
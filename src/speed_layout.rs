@@ -0,0 +1,122 @@
+//! Layout of the speeds in the storage buffer every compute pass shares, `cpu_data` in
+//! compute_kernel.wgsl: each particle's speed in xyz and the step dt of the compute kernel in w.
+//!
+//! On devices with f16 in shaders the four values are packed in halves, two to a word, halving the
+//! bandwidth every compute pass spends on them. Elsewhere they stay four f32, the layout of
+//! `ParticleCpuData`, which the CPU keeps either way: speeds are converted when uploaded and read
+//! back. The shaders binding the speeds are built after [`SpeedLayout::wgsl`], declaring
+//! `PackedSpeed` with `pack_speed(vec4<f32>) -> PackedSpeed` and `unpack_speed(PackedSpeed) ->
+//! vec4<f32>`.
+
+use std::borrow::Cow;
+
+use crate::half_float;
+
+const F32_WGSL: &str = "
+alias PackedSpeed = vec4<f32>;
+fn pack_speed(speed: vec4<f32>) -> PackedSpeed { return speed; }
+fn unpack_speed(speed: PackedSpeed) -> vec4<f32> { return speed; }
+";
+const F16_WGSL: &str = "
+alias PackedSpeed = vec2<u32>;
+fn pack_speed(speed: vec4<f32>) -> PackedSpeed {
+    return vec2<u32>(pack2x16float(speed.xy), pack2x16float(speed.zw));
+}
+fn unpack_speed(speed: PackedSpeed) -> vec4<f32> {
+    return vec4<f32>(unpack2x16float(speed.x), unpack2x16float(speed.y));
+}
+";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpeedLayout {
+    /// Four f32, 16 bytes
+    F32,
+    /// Four f16, 8 bytes
+    F16,
+}
+
+impl SpeedLayout {
+    /// Halves on devices created with f16 in shaders, see [`GpuCapabilities::features`].
+    ///
+    /// [`GpuCapabilities::features`]: crate::capabilities::GpuCapabilities::features
+    pub fn of(device: &wgpu::Device) -> Self {
+        if device.features().contains(wgpu::Features::SHADER_F16) {
+            SpeedLayout::F16
+        } else {
+            SpeedLayout::F32
+        }
+    }
+
+    /// Bytes per particle.
+    pub fn stride(self) -> usize {
+        match self {
+            SpeedLayout::F32 => 16,
+            SpeedLayout::F16 => 8,
+        }
+    }
+
+    /// `PackedSpeed`, `pack_speed` and `unpack_speed`, included before the shaders binding the
+    /// speeds.
+    pub fn wgsl(self) -> &'static str {
+        match self {
+            SpeedLayout::F32 => F32_WGSL,
+            SpeedLayout::F16 => F16_WGSL,
+        }
+    }
+
+    /// Format of the speeds bound as a vertex buffer, read as a `vec4<f32>`.
+    pub fn vertex_format(self) -> wgpu::VertexFormat {
+        match self {
+            SpeedLayout::F32 => wgpu::VertexFormat::Float32x4,
+            SpeedLayout::F16 => wgpu::VertexFormat::Float16x4,
+        }
+    }
+
+    /// Bytes of `speeds` in this layout.
+    pub fn encode(self, speeds: &[[f32; 4]]) -> Cow<'_, [u8]> {
+        match self {
+            SpeedLayout::F32 => Cow::Borrowed(bytemuck::cast_slice(speeds)),
+            SpeedLayout::F16 => Cow::Owned(
+                speeds
+                    .iter()
+                    .flatten()
+                    .flat_map(|&value| half_float::to_bits(value).to_le_bytes())
+                    .collect(),
+            ),
+        }
+    }
+
+    /// Reads the `speeds` from their `bytes` in this layout.
+    pub fn decode(self, bytes: &[u8], speeds: &mut [[f32; 4]]) {
+        match self {
+            SpeedLayout::F32 => bytemuck::cast_slice_mut(speeds).copy_from_slice(bytes),
+            SpeedLayout::F16 => {
+                for (value, bits) in speeds.iter_mut().flatten().zip(bytes.chunks_exact(2)) {
+                    *value = half_float::from_bits(u16::from_le_bytes([bits[0], bits[1]]));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn speeds_round_trip() {
+        let speeds = [[0.2, -0.1, 0.05, 0.25], [0.0, 1.5, -3.0, -0.5]];
+        for layout in [SpeedLayout::F32, SpeedLayout::F16] {
+            let bytes = layout.encode(&speeds);
+            assert_eq!(bytes.len(), speeds.len() * layout.stride());
+            let mut decoded = [[0.0; 4]; 2];
+            layout.decode(&bytes, &mut decoded);
+            for (decoded, speed) in decoded.iter().flatten().zip(speeds.iter().flatten()) {
+                assert!(
+                    (decoded - speed).abs() < 1e-3,
+                    "{layout:?}: {speed} is {decoded}"
+                );
+            }
+        }
+    }
+}
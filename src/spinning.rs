@@ -0,0 +1,372 @@
+//! Spinning particles: each quad turns around its own axis at its own angular velocity, its
+//! orientation integrated every frame by a compute pass when the particles move on the GPU, or on
+//! the CPU along with them.
+//!
+//! Every compute pass depends on the layout of the speeds, so the orientations and angular
+//! velocities are a buffer of their own, bound as an extra per-instance vertex buffer. Compaction
+//! and the level of detail reorder the instances, so they can't be combined with it. With the
+//! `packed-instances` feature the buffer holds them in 16 bits per component, half the size.
+
+use std::{ops::Range, sync::Arc};
+
+use bytemuck::{Pod, Zeroable};
+use glam::{Quat, Vec3};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+#[cfg(feature = "packed-instances")]
+use crate::half_float;
+use crate::shader_check;
+
+// Must match the workgroup size of main in spinning.wgsl
+const WORKGROUP_SIZE: u32 = 64;
+// Radians per second of the fastest particles
+const MAX_ANGULAR_SPEED: f32 = 4.0;
+const SPIN_SIZE: u64 = std::mem::size_of::<PackedSpin>() as u64;
+
+// As integrated on the CPU
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Pod, Zeroable)]
+struct Spin {
+    orientation: Quat,
+    angular_velocity: Vec3,
+    _padding: f32,
+}
+
+impl Spin {
+    /// Random orientation and angular velocity of particle `index`, the same every run.
+    fn new(index: usize) -> Self {
+        let mut rng = StdRng::seed_from_u64(index as u64);
+        let mut random_axis = || {
+            let z: f32 = rng.gen_range(-1.0..1.0);
+            let angle: f32 = rng.gen_range(0.0..std::f32::consts::TAU);
+            let radius = (1.0 - z * z).sqrt();
+            Vec3::new(radius * angle.cos(), radius * angle.sin(), z)
+        };
+        let (axis, spin_axis) = (random_axis(), random_axis());
+        let angle = rng.gen_range(0.0..std::f32::consts::TAU);
+        let speed = rng.gen_range(-MAX_ANGULAR_SPEED..MAX_ANGULAR_SPEED);
+        Self {
+            orientation: Quat::from_axis_angle(axis, angle),
+            angular_velocity: spin_axis * speed,
+            _padding: 0.0,
+        }
+    }
+
+    /// Same integration as main in spinning.wgsl.
+    fn integrate(&mut self, dt: f32) {
+        let w = self.angular_velocity;
+        let dq = Quat::from_xyzw(w.x, w.y, w.z, 0.0) * self.orientation * 0.5;
+        self.orientation = (self.orientation + dq * dt).normalize();
+    }
+}
+
+// As uploaded, must match Spin in SPIN_WGSL. With the `packed-instances` feature the orientations
+// are 16 bit normalized integers, the angular velocities half floats.
+#[cfg(not(feature = "packed-instances"))]
+type PackedSpin = Spin;
+#[cfg(feature = "packed-instances")]
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Pod, Zeroable)]
+struct PackedSpin {
+    orientation: [i16; 4],
+    // w is unused
+    angular_velocity: [u16; 4],
+}
+
+#[cfg(feature = "packed-instances")]
+impl From<Spin> for PackedSpin {
+    fn from(spin: Spin) -> Self {
+        Self {
+            // Rounded like `pack2x16snorm` in WGSL
+            orientation: spin
+                .orientation
+                .to_array()
+                .map(|component| (component.clamp(-1.0, 1.0) * 32767.0).round() as i16),
+            angular_velocity: spin
+                .angular_velocity
+                .extend(0.0)
+                .to_array()
+                .map(half_float::to_bits),
+        }
+    }
+}
+
+/// Declares `Spin`, the type of the elements of the spin buffer, and `load_orientation(u32)`,
+/// `load_angular_velocity(u32)` and `store_orientation(u32, vec4<f32>)` for those of `spins`.
+#[cfg(not(feature = "packed-instances"))]
+const SPIN_WGSL: &str = "
+struct Spin {
+    // Quaternion, xyz then w
+    orientation: vec4<f32>,
+    // Radians per second around the axis of its direction, w is unused
+    angular_velocity: vec4<f32>,
+};
+fn load_orientation(index: u32) -> vec4<f32> { return spins[index].orientation; }
+fn load_angular_velocity(index: u32) -> vec3<f32> { return spins[index].angular_velocity.xyz; }
+fn store_orientation(index: u32, orientation: vec4<f32>) {
+    spins[index].orientation = orientation;
+}
+";
+#[cfg(feature = "packed-instances")]
+const SPIN_WGSL: &str = "
+struct Spin {
+    orientation: vec2<u32>,
+    angular_velocity: vec2<u32>,
+};
+fn load_orientation(index: u32) -> vec4<f32> {
+    let orientation = spins[index].orientation;
+    return vec4(unpack2x16snorm(orientation.x), unpack2x16snorm(orientation.y));
+}
+fn load_angular_velocity(index: u32) -> vec3<f32> {
+    let angular_velocity = spins[index].angular_velocity;
+    return vec3(unpack2x16float(angular_velocity.x), unpack2x16float(angular_velocity.y).x);
+}
+fn store_orientation(index: u32, orientation: vec4<f32>) {
+    spins[index].orientation = vec2(pack2x16snorm(orientation.xy), pack2x16snorm(orientation.zw));
+}
+";
+
+/// The source of the spin pass, after the declarations of `Spin`.
+pub fn shader_source() -> String {
+    format!("{SPIN_WGSL}\n{}", include_str!("spinning.wgsl"))
+}
+
+// Must match SpinParams in spinning.wgsl
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Pod, Zeroable)]
+struct SpinParams {
+    dt: f32,
+    count: u32,
+    _padding: [u32; 2],
+}
+
+pub struct Spinning {
+    // Integrated on the CPU, or as uploaded last when the compute pass integrates them
+    spins: Vec<Spin>,
+    spin_buffer: wgpu::Buffer,
+    params_buffer: wgpu::Buffer,
+    bind_group_layout: wgpu::BindGroupLayout,
+    bind_group: wgpu::BindGroup,
+    compute_pipeline: wgpu::ComputePipeline,
+    // For each depth-stencil format of the particles pass
+    pipelines: Vec<(Option<wgpu::TextureFormat>, Arc<wgpu::RenderPipeline>)>,
+}
+
+impl Spinning {
+    pub fn new(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        capacity: usize,
+        pipelines: Vec<(Option<wgpu::TextureFormat>, Arc<wgpu::RenderPipeline>)>,
+    ) -> Self {
+        let params_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Spin Params Buffer"),
+            size: std::mem::size_of::<SpinParams>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let buffer_entry = |binding, ty| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let bind_group_layout_entries = [
+            buffer_entry(0, wgpu::BufferBindingType::Uniform),
+            buffer_entry(1, wgpu::BufferBindingType::Storage { read_only: false }),
+        ];
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Spin Bind Group Layout"),
+            entries: &bind_group_layout_entries,
+        });
+
+        let source = shader_source();
+        let shader = shader_check::create_module(device, "Spin Shader", "spinning.wgsl", &source);
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Spin Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let compute_pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Spin Pipeline"),
+            layout: Some(&pipeline_layout),
+            module: &shader,
+            entry_point: "main",
+        });
+
+        #[cfg(feature = "guardrails")]
+        {
+            let reflection = crate::guardrails::ShaderReflection::new("spinning.wgsl", &source);
+            reflection.check_bind_group_layout(0, &bind_group_layout_entries);
+            reflection.check_struct_size("SpinParams", std::mem::size_of::<SpinParams>());
+            reflection.check_struct_size("Spin", SPIN_SIZE as usize);
+        }
+
+        let spins = (0..capacity).map(Spin::new).collect::<Vec<_>>();
+        let spin_buffer = Self::create_spin_buffer(device, queue, &spins);
+        let bind_group =
+            Self::create_bind_group(device, &bind_group_layout, &params_buffer, &spin_buffer);
+        Self {
+            spins,
+            spin_buffer,
+            params_buffer,
+            bind_group_layout,
+            bind_group,
+            compute_pipeline,
+            pipelines,
+        }
+    }
+
+    fn create_spin_buffer(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        spins: &[Spin],
+    ) -> wgpu::Buffer {
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Spin Buffer"),
+            size: spins.len().max(1) as u64 * SPIN_SIZE,
+            usage: wgpu::BufferUsages::VERTEX
+                | wgpu::BufferUsages::STORAGE
+                | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        Self::write_spins(queue, &buffer, spins);
+        buffer
+    }
+
+    #[cfg(not(feature = "packed-instances"))]
+    fn write_spins(queue: &wgpu::Queue, spin_buffer: &wgpu::Buffer, spins: &[Spin]) {
+        queue.write_buffer(spin_buffer, 0, bytemuck::cast_slice(spins));
+    }
+
+    #[cfg(feature = "packed-instances")]
+    fn write_spins(queue: &wgpu::Queue, spin_buffer: &wgpu::Buffer, spins: &[Spin]) {
+        let packed = spins
+            .iter()
+            .copied()
+            .map(PackedSpin::from)
+            .collect::<Vec<_>>();
+        queue.write_buffer(spin_buffer, 0, bytemuck::cast_slice(&packed));
+    }
+
+    fn create_bind_group(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        params_buffer: &wgpu::Buffer,
+        spin_buffer: &wgpu::Buffer,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Spin Bind Group"),
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: params_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: spin_buffer.as_entire_binding(),
+                },
+            ],
+        })
+    }
+
+    #[cfg(not(feature = "packed-instances"))]
+    const ORIENTATION_FORMAT: wgpu::VertexFormat = wgpu::VertexFormat::Float32x4;
+    #[cfg(feature = "packed-instances")]
+    const ORIENTATION_FORMAT: wgpu::VertexFormat = wgpu::VertexFormat::Snorm16x4;
+
+    /// Orientation of each instance at location 5, the angular velocity is skipped.
+    pub fn descriptor() -> wgpu::VertexBufferLayout<'static> {
+        static ATTRIBUTES: &[wgpu::VertexAttribute] = &[wgpu::VertexAttribute {
+            offset: 0,
+            shader_location: 5,
+            format: Spinning::ORIENTATION_FORMAT,
+        }];
+
+        wgpu::VertexBufferLayout {
+            array_stride: SPIN_SIZE,
+            step_mode: wgpu::VertexStepMode::Instance,
+            attributes: ATTRIBUTES,
+        }
+    }
+
+    /// Grows the spins to `capacity` instances if needed. Turned by the compute pass, the particles
+    /// kept go back to the orientations last integrated on the CPU.
+    pub fn resize(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, capacity: usize) {
+        if capacity == self.spins.len() {
+            return;
+        }
+        let kept = self.spins.len().min(capacity);
+        self.spins.truncate(kept);
+        self.spins.extend((kept..capacity).map(Spin::new));
+        self.spin_buffer = Self::create_spin_buffer(device, queue, &self.spins);
+        self.bind_group = Self::create_bind_group(
+            device,
+            &self.bind_group_layout,
+            &self.params_buffer,
+            &self.spin_buffer,
+        );
+    }
+
+    /// Turns the particles by `dt` seconds on the CPU, and uploads their orientations.
+    pub fn update(&mut self, queue: &wgpu::Queue, dt: f32) {
+        for spin in &mut self.spins {
+            spin.integrate(dt);
+        }
+        Self::write_spins(queue, &self.spin_buffer, &self.spins);
+    }
+
+    /// Turns the particles by `dt` seconds in a compute pass, once `encoder` runs.
+    pub fn encode(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        dt: f32,
+    ) {
+        let count = self.spins.len() as u32;
+        let params = SpinParams {
+            dt,
+            count,
+            _padding: [0; 2],
+        };
+        queue.write_buffer(&self.params_buffer, 0, bytemuck::cast_slice(&[params]));
+        let groups = count.div_ceil(WORKGROUP_SIZE).max(1);
+        let max_groups = device.limits().max_compute_workgroups_per_dimension;
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Spin Pass"),
+        });
+        compute_pass.set_pipeline(&self.compute_pipeline);
+        compute_pass.set_bind_group(0, &self.bind_group, &[]);
+        compute_pass.dispatch_workgroups(groups.min(max_groups), groups.div_ceil(max_groups), 1);
+    }
+
+    /// Draws the `ranges` of instances with the quad bound to vertex buffer 0, and the positions
+    /// and colors to 1 and 2.
+    pub fn draw<'a>(
+        &'a self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        depth_format: Option<wgpu::TextureFormat>,
+        index_count: u32,
+        ranges: &[Range<usize>],
+    ) {
+        let Some((_, pipeline)) = self
+            .pipelines
+            .iter()
+            .find(|(format, _)| *format == depth_format)
+        else {
+            return;
+        };
+        render_pass.set_pipeline(pipeline);
+        render_pass.set_vertex_buffer(3, self.spin_buffer.slice(..));
+        for range in ranges {
+            render_pass.draw_indexed(0..index_count, 0, range.start as u32..range.end as u32);
+        }
+    }
+}
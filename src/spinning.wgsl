@@ -0,0 +1,30 @@
+// Spin and its accessors are declared by SPIN_WGSL in spinning.rs
+
+// Must match SpinParams in spinning.rs
+struct SpinParams {
+    dt: f32,
+    count: u32,
+    _padding0: u32,
+    _padding1: u32,
+};
+
+@group(0) @binding(0) var<uniform> params: SpinParams;
+@group(0) @binding(1) var<storage, read_write> spins: array<Spin>;
+
+// Must match WORKGROUP_SIZE in spinning.rs
+@compute @workgroup_size(64)
+fn main(
+    @builtin(global_invocation_id) id: vec3<u32>,
+    @builtin(num_workgroups) groups: vec3<u32>,
+) {
+    // Rows of workgroups past the dispatch limit
+    let index = id.x + id.y * groups.x * 64u;
+    if index >= params.count {
+        return;
+    }
+    // dq/dt = (w, 0) * q / 2, renormalized so the errors don't scale the quads
+    let q = load_orientation(index);
+    let w = load_angular_velocity(index);
+    let dq = vec4<f32>(w * q.w + cross(w, q.xyz), -dot(w, q.xyz)) * 0.5;
+    store_orientation(index, normalize(q + dq * params.dt));
+}
@@ -1,624 +1,7617 @@
-use std::{future, sync::Arc};
+use std::{
+    ops::Range,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 
 use bytemuck::{Pod, Zeroable};
-use glam::Vec4Swizzles;
-use rand::Rng;
+use glam::{Vec3A, Vec4Swizzles};
+use rand::{rngs::StdRng, Rng, SeedableRng};
 use rayon::prelude::{
     IndexedParallelIterator, IntoParallelRefIterator, IntoParallelRefMutIterator, ParallelIterator,
+    ParallelSliceMut,
 };
 use wgpu::util::DeviceExt;
 use winit::{
-    event::{MouseScrollDelta, VirtualKeyCode, WindowEvent},
-    window::Window,
+    dpi::PhysicalPosition,
+    event::{
+        ElementState, KeyboardInput, ModifiersState, MouseButton, VirtualKeyCode, WindowEvent,
+    },
+    window::{Fullscreen, Window, WindowId},
+};
+
+use crate::{
+    accessibility::{Accessibility, SafePalette},
+    adapters::{self, AdapterSelector, Backend},
+    adaptive::AdaptiveCount,
+    appearance::{Appearance, AppearanceWatcher},
+    arena::InstanceArena,
+    behavior::Behaviors,
+    bench::GpuFrameTime,
+    boids::{Boids, BoidsParams},
+    camera::{Camera, CameraUniform, DepthRange, ViewProjection, ZoomController},
+    camera_path::CameraPath,
+    camera_presets::CameraPresets,
+    capabilities::GpuCapabilities,
+    capture::{self, CaptureError, Image},
+    checkpoint::Checkpoint,
+    collisions::{self, Collisions},
+    compaction::Compaction,
+    debug_view::{DebugPipelines, DebugView},
+    dirty_ranges::{DirtyRanges, UploadStats},
+    emitter::{EmitterParams, EmitterShape, EMITTER_WGSL},
+    encoding::{self, Submission},
+    environment::{Environment, EnvironmentSource},
+    error::AppError,
+    explore::{self, ExploreRanges, Explorer},
+    frame_graph::{FrameGraph, Resource, TransientTextures},
+    frame_hash::FrameHasher,
+    frame_log::FrameLog,
+    frame_stats::FrameStats,
+    framing,
+    gizmos::Gizmos,
+    gradient::{self, ColorGradient},
+    grid::{CellRect, GridLayout},
+    groups::{self, GroupMask},
+    input::InputState,
+    inspector::Inspector,
+    instance_pool::{InstancePool, PoolFull},
+    keymap::{Action, Keymap},
+    lights::{self, Light},
+    lod::Lod,
+    memory_budget::{self, ByteSize, MemoryEstimate},
+    multi_draw::MultiDraw,
+    nbody::{Nbody, NbodyParams},
+    obstacles::{self, Obstacle, OBSTACLES_WGSL},
+    options::Options,
+    overlap::RenderSnapshot,
+    pacing::{self, FramePacer, PresentMode},
+    palette::SpawnPalette,
+    panorama, particle_init,
+    picking::{ParticleBuffers, Picker},
+    pipeline_cache::{BlendMode, ParticleShape, PipelineCache, RenderOptions},
+    point_cache::PointCache,
+    point_cloud::PointCloud,
+    readback::{Readback, ReadbackRing},
+    recording::{self, Recorder},
+    reduction::Reduction,
+    render_target::RenderTarget,
+    scene::{Scene, SceneWatcher},
+    schedule::{Keyframe, LightingUniform, Schedule},
+    screensaver::{ScrCommand, Screensaver},
+    settings::Settings,
+    shader_check,
+    sim_params::{CpuPath, SimMode, SimParams},
+    spatial_hash::SpatialHash,
+    speed_layout::SpeedLayout,
+    spinning::Spinning,
+    stereo::{self, StereoMode, StereoSettings},
+    stereo_view::{EyeCamera, StereoView},
+    stretched::Stretched,
+    surface_format::{self, SurfaceFormat},
+    surface_recovery::SurfaceRecovery,
+    touch::TouchGestures,
+    trace,
+    trails::Trails,
+    trajectories::TrajectoryWriter,
+    turbulence::TurbulenceParams,
+    vertex::{
+        self, DrawnPosition, Instance, InstanceColor, InstancePosition, Vertex,
+        INSTANCE_POSITION_WGSL,
+    },
+    vertex_pulling,
+    viewport::Viewport,
+    volume::VolumeView,
+    watchdog::{Failure, Watchdog},
+    wind::{self, Flow, WindField, WindSource, WindTexture, WIND_WGSL},
+};
+
+#[cfg(feature = "audio")]
+use crate::audio::{AudioInput, AudioLevels};
+#[cfg(feature = "gamepad")]
+use crate::input::GamepadAxes;
+#[cfg(feature = "metrics")]
+use crate::{
+    gpu_timer::{GpuTimer, Pass, PassTimes, PipelineStats},
+    metrics::{self, Metrics, MetricsExporter},
+    telemetry::{Stats, Telemetry, TelemetryCommand},
 };
+#[cfg(feature = "asset-loaders")]
+use crate::{mesh_emitter::TriangleMesh, particle_data};
 
+#[cfg(feature = "guardrails")]
+use crate::guardrails;
+#[cfg(feature = "packed-instances")]
+use crate::position_packing::PositionPacker;
+#[cfg(feature = "scripting")]
+use crate::scripting::Script;
+#[cfg(feature = "ui")]
+use crate::ui::{PanelValues, Ui};
+#[cfg(feature = "post-processing")]
 use crate::{
-    camera::{Camera, CameraUniform},
-    vertex::{Instance, InstanceRaw, Vertex},
+    checkerboard::{self, Checkerboard},
+    color_grading::{ColorGrading, Lut},
+    depth_of_field::DepthOfField,
+    half_resolution::HalfResolution,
+    motion_blur::MotionBlur,
+    obstacles::ObstacleView,
+    render_target::DEPTH_FORMAT,
+    soft_particles::SoftParticles,
+    temporal::TemporalAccumulation,
 };
 
 #[repr(C)]
 #[derive(Copy, Clone, Pod, Zeroable)]
 struct ParticleCpuData {
     speed: glam::Vec3,
-    _unused: f32,
+    // Only used by the compute passes, see CpuData in compute_kernel.wgsl
+    _step_dt: f32,
+}
+
+// Must match DispatchParams in compute_kernel.wgsl
+#[repr(C)]
+#[derive(Copy, Clone, Pod, Zeroable)]
+struct DispatchParams {
+    first_row: u32,
+    substep: u32,
+    _padding: [u32; 2],
 }
 
+// Entry points of compute_kernel.wgsl, each substep runs them in this order
+const COMPUTE_PASSES: [&str; 3] = ["accumulate_forces", "integrate", "apply_bounds"];
+
 struct ComputePipeline {
-    pipeline: wgpu::ComputePipeline,
-    bind_group: wgpu::BindGroup,
+    // One per entry of COMPUTE_PASSES, sharing the bind groups
+    passes: Vec<wgpu::ComputePipeline>,
+    // Invocations per workgroup of the passes
+    workgroup_size: u32,
+    // Of the passes, kept to build them again for another workgroup size
+    layout: wgpu::PipelineLayout,
+    source: String,
+    // One per chunk of particles, buffers too large for a single binding are bound in chunks
+    bind_groups: Vec<(Range<usize>, wgpu::BindGroup)>,
     cpu_data_buffer: wgpu::Buffer,
+    // Of the speeds in cpu_data_buffer, converted from and to ParticleCpuData
+    speed_layout: SpeedLayout,
+    turbulence_buffer: wgpu::Buffer,
+    dispatch_buffer: wgpu::Buffer,
+    sim_params_buffer: wgpu::Buffer,
+    // Kept alive for the bind groups
+    _obstacle_buffer: wgpu::Buffer,
+    _emitter_buffer: wgpu::Buffer,
+    _wind: WindTexture,
+}
+
+impl ComputePipeline {
+    /// Runs every pass of one substep on `rows` rows of the particles of `bind_group`.
+    fn dispatch<'a>(
+        &'a self,
+        compute_pass: &mut wgpu::ComputePass<'a>,
+        bind_group: &'a wgpu::BindGroup,
+        rows: u32,
+    ) {
+        compute_pass.set_bind_group(0, bind_group, &[]);
+        for pipeline in &self.passes {
+            compute_pass.set_pipeline(pipeline);
+            compute_pass.dispatch_workgroups(
+                COMPUTE_ROW_WIDTH.div_ceil(self.workgroup_size),
+                rows,
+                1,
+            );
+        }
+    }
+
+    /// Builds the passes again with `workgroup_size` invocations per workgroup.
+    fn set_workgroup_size(&mut self, device: &wgpu::Device, workgroup_size: u32) {
+        let source = self.source.replace(
+            COMPUTE_WORKGROUP_SIZE_ATTRIBUTE,
+            &format!("@workgroup_size({workgroup_size})"),
+        );
+        let module = shader_check::create_module(device, "3", "compute_kernel.wgsl", &source);
+        self.passes = COMPUTE_PASSES
+            .iter()
+            .map(|entry_point| {
+                device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                    label: Some(entry_point),
+                    layout: Some(&self.layout),
+                    module: &module,
+                    entry_point,
+                })
+            })
+            .collect();
+        self.workgroup_size = workgroup_size;
+    }
+
+    /// Particles the speeds buffer holds.
+    fn capacity(&self) -> usize {
+        self.cpu_data_buffer.size() as usize / self.speed_layout.stride()
+    }
+
+    /// Uploads the speeds of the particles from `first` on.
+    fn write_cpu_data(&self, queue: &wgpu::Queue, first: usize, cpu_data: &[ParticleCpuData]) {
+        queue.write_buffer(
+            &self.cpu_data_buffer,
+            (first * self.speed_layout.stride()) as u64,
+            &self.speed_layout.encode(bytemuck::cast_slice(cpu_data)),
+        );
+    }
+
+    /// Reads back the speeds of the first particles, as many as `cpu_data` holds.
+    fn read_cpu_data(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        cpu_data: &mut [ParticleCpuData],
+    ) {
+        let bytes = State::read_back_buffer::<u8>(
+            device,
+            queue,
+            &self.cpu_data_buffer,
+            cpu_data.len() * self.speed_layout.stride(),
+        );
+        self.speed_layout
+            .decode(&bytes, bytemuck::cast_slice_mut(cpu_data));
+    }
+}
+
+/// Depth-stencil attachment of the particles pass.
+#[cfg_attr(not(feature = "post-processing"), allow(dead_code))]
+enum DepthStencil<'a> {
+    /// Depth tested and written, for the passes reading the depth
+    Depth(&'a wgpu::TextureView),
+    /// Only draws the checkerboard quads of the parity, see checkerboard.rs
+    Checkerboard(&'a wgpu::TextureView, u32),
+}
+
+/// The compute kernel's step of a frame, encoded apart from the rest of the [`State`] so it can be
+/// on the encoding thread.
+struct KernelStep<'a> {
+    compute_pipeline: &'a ComputePipeline,
+    substeps: u32,
+    // Only the particles below are simulated
+    active_end: usize,
+    max_invocations_per_submit: Option<u32>,
+    #[cfg(feature = "metrics")]
+    gpu_timer: Option<&'a GpuTimer>,
+}
+
+impl<'a> KernelStep<'a> {
+    /// Large steps are split across submissions so a single one never runs long enough to trip
+    /// the OS GPU timeout.
+    fn encode(&self, device: &wgpu::Device) -> Vec<Submission<'a>> {
+        let mut dispatches = vec![];
+        for (range, bind_group) in &self.compute_pipeline.bind_groups {
+            let active = self.active_end.saturating_sub(range.start).min(range.len());
+            if active == 0 && !dispatches.is_empty() {
+                break;
+            }
+            let rows = (active as u32).div_ceil(COMPUTE_ROW_WIDTH).max(1);
+            #[cfg(feature = "guardrails")]
+            guardrails::check_dispatch_coverage(
+                [
+                    COMPUTE_ROW_WIDTH.div_ceil(self.compute_pipeline.workgroup_size),
+                    rows,
+                    1,
+                ],
+                [self.compute_pipeline.workgroup_size, 1, 1],
+                active,
+            );
+            let rows_per_submit = self
+                .max_invocations_per_submit
+                .map_or(rows, |max| (max / COMPUTE_ROW_WIDTH).max(1));
+            for first_row in (0..rows).step_by(rows_per_submit as usize) {
+                dispatches.push((bind_group, first_row, rows_per_submit.min(rows - first_row)));
+            }
+        }
+
+        // Particles in the region of interest take a substep on every one, the others only move
+        // on the first
+        #[cfg(feature = "metrics")]
+        let last_dispatch = dispatches.len() * self.substeps as usize - 1;
+        let mut submissions = vec![];
+        #[cfg_attr(not(feature = "metrics"), allow(unused_variables))]
+        for (index, (substep, &(bind_group, first_row, rows))) in (0..self.substeps)
+            .flat_map(|substep| dispatches.iter().map(move |dispatch| (substep, dispatch)))
+            .enumerate()
+        {
+            let dispatch = DispatchParams {
+                first_row,
+                substep,
+                _padding: [0; 2],
+            };
+            let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Compute Encoder"),
+            });
+
+            #[cfg(feature = "metrics")]
+            if let Some(gpu_timer) = self.gpu_timer.filter(|_| index == 0) {
+                gpu_timer.begin(&mut encoder, Pass::Compute);
+            }
+            {
+                let mut compute_pass = encoder.begin_compute_pass(&Default::default());
+                self.compute_pipeline
+                    .dispatch(&mut compute_pass, bind_group, rows);
+            }
+            #[cfg(feature = "metrics")]
+            if let Some(gpu_timer) = self.gpu_timer.filter(|_| index == last_dispatch) {
+                gpu_timer.end(&mut encoder, Pass::Compute);
+            }
+
+            submissions.push(Submission {
+                writes: vec![(
+                    &self.compute_pipeline.dispatch_buffer,
+                    bytemuck::bytes_of(&dispatch).to_vec(),
+                )],
+                command_buffer: encoder.finish(),
+            });
+        }
+        submissions
+    }
+}
+
+/// The CPU's step of a frame, on one particle at a time: the scalar path of the CPU simulation, and
+/// what the compute kernel is checked against.
+struct CpuStep<'a> {
+    sim_params: &'a SimParams,
+    flow: &'a Flow<'a>,
+    obstacles: &'a [Obstacle],
+    behaviors: &'a Behaviors,
+    // Where particles respawn at the end of their life
+    emitter: &'a EmitterShape,
+}
+
+impl CpuStep<'_> {
+    /// Ages particle `index` at `position` and moves it by a frame, updating its `speed`. Returns
+    /// its new position, with its group and age in w.
+    fn particle(&self, index: usize, position: glam::Vec4, speed: &mut glam::Vec3) -> glam::Vec4 {
+        let mut current = position.xyz();
+        let mut age = self
+            .sim_params
+            .age(index, current, groups::age_of(position.w));
+        if age >= 1.0 {
+            (current, *speed) = respawn(self.emitter, index, self.sim_params.frame);
+            age = 0.0;
+        }
+        self.sim_params
+            .step(
+                index,
+                current,
+                speed,
+                self.flow,
+                self.obstacles,
+                self.behaviors,
+            )
+            .extend(groups::with_age(position.w, age))
+    }
+
+    /// Same as [`CpuStep::particle`] with [`SimParams::step_simd`].
+    fn particle_simd(
+        &self,
+        index: usize,
+        position: glam::Vec4,
+        speed: &mut glam::Vec3,
+    ) -> glam::Vec4 {
+        // The translation is stepped where it is in the instance buffer
+        let mut current = Vec3A::from(position);
+        let mut speed_simd = Vec3A::from(*speed);
+        let mut age = self
+            .sim_params
+            .age(index, current.into(), groups::age_of(position.w));
+        if age >= 1.0 {
+            let (respawn_position, respawn_speed) =
+                respawn(self.emitter, index, self.sim_params.frame);
+            (current, speed_simd, age) = (respawn_position.into(), respawn_speed.into(), 0.0);
+        }
+        let stepped = self.sim_params.step_simd(
+            index,
+            current,
+            &mut speed_simd,
+            self.flow,
+            self.obstacles,
+            self.behaviors,
+        );
+        *speed = speed_simd.into();
+        stepped.extend(groups::with_age(position.w, age))
+    }
+}
+
+/// One of the independent particle systems of the grid view, always simulated on the GPU.
+pub struct GridCell {
+    scene_path: PathBuf,
+    particle_count: usize,
+    camera: Camera,
+    turbulence: TurbulenceParams,
+    sim_params: SimParams,
+    rect: CellRect,
+    // Of the instance pool
+    slot: Range<usize>,
+    compute_pipeline: ComputePipeline,
+    camera_buffer: wgpu::Buffer,
+    lighting_buffer: wgpu::Buffer,
+    camera_bind_group: wgpu::BindGroup,
+}
+
+impl GridCell {
+    /// Advances the simulation time by `dt` for the next step of the compute kernel.
+    pub fn advance(&mut self, queue: &wgpu::Queue, dt: f32) {
+        self.turbulence.time += dt;
+        self.sim_params.frame = self.sim_params.frame.wrapping_add(1);
+        queue.write_buffer(
+            &self.compute_pipeline.turbulence_buffer,
+            0,
+            bytemuck::cast_slice(&[self.turbulence]),
+        );
+        queue.write_buffer(
+            &self.compute_pipeline.sim_params_buffer,
+            0,
+            bytemuck::cast_slice(&[self.sim_params]),
+        );
+    }
+
+    /// Looks through `camera` from the next draw on, lit by `look`.
+    pub fn set_camera(&mut self, queue: &wgpu::Queue, camera: &Camera, look: &Keyframe) {
+        self.camera = camera.clone();
+        let mut camera_uniform = CameraUniform::new();
+        camera_uniform.update_view_proj(&self.camera);
+        queue.write_buffer(
+            &self.camera_buffer,
+            0,
+            bytemuck::cast_slice(&[camera_uniform]),
+        );
+        queue.write_buffer(
+            &self.lighting_buffer,
+            0,
+            bytemuck::cast_slice(&[LightingUniform::from(look)]),
+        );
+    }
+
+    /// Draws the particles with the quad mesh bound to the first vertex buffer slot, and the
+    /// instance pool to the others.
+    pub fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>, index_count: u32) {
+        render_pass.set_bind_group(0, &self.camera_bind_group, &[]);
+        render_pass.draw_indexed(
+            0..index_count,
+            0,
+            self.slot.start as u32..self.slot.end as u32,
+        );
+    }
+
+    /// Reads the positions of the particles back from `pool`, waiting for the GPU.
+    pub fn read_positions(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        pool: &InstancePool,
+    ) -> Vec<glam::Vec3> {
+        State::read_back_buffer::<InstancePosition>(
+            device,
+            queue,
+            pool.position_buffer(),
+            self.slot.end,
+        )[self.slot.clone()]
+        .iter()
+        .map(|instance| instance.position.xyz())
+        .collect()
+    }
 }
 
 pub struct State {
-    surface: wgpu::Surface,
+    // Kept to create the surfaces of extra windows
+    instance: wgpu::Instance,
+    gpu_adapter: wgpu::Adapter,
+    // What the device was created with of the optional features
+    capabilities: GpuCapabilities,
     device: wgpu::Device,
     queue: wgpu::Queue,
-    config: wgpu::SurfaceConfiguration,
-    size: winit::dpi::PhysicalSize<u32>,
-    window: Window,
-    render_pipeline: wgpu::RenderPipeline,
+    // The main window, closing it exits
+    viewport: Viewport,
+    // Retries of the main surface when it fails to hand out frames
+    surface_recovery: SurfaceRecovery,
+    // Opened with Ctrl+N, showing the same particles from their own cameras
+    extra_viewports: Vec<Viewport>,
+    window_requested: bool,
+    // Particle pipelines of every blend mode, shape and depth-stencil format used so far
+    pipeline_cache: PipelineCache,
+    // Of the scene, kept to build the pipelines again with the device
+    appearance: Option<Appearance>,
+    // Of the watched scene's appearance, if any
+    appearance_watcher: Option<AppearanceWatcher>,
+    // Of the last appearance that didn't compile, cleared by the next one that does
+    shader_error: Option<String>,
+    debug_pipelines: DebugPipelines,
+    debug_view: DebugView,
+    // Drawn behind the particles instead of clearing to the background color
+    environment: Option<Environment>,
+    picker: Picker,
+    render_target: RenderTarget,
+    // Textures of the frame graphs, reused across frames
+    transient_textures: TransientTextures,
+    // Built the first time they're enabled, some backends can't run them
+    #[cfg(feature = "post-processing")]
+    depth_of_field: Option<DepthOfField>,
+    #[cfg(feature = "post-processing")]
+    half_resolution: Option<HalfResolution>,
+    #[cfg(feature = "post-processing")]
+    motion_blur: Option<MotionBlur>,
+    #[cfg(feature = "post-processing")]
+    temporal: Option<TemporalAccumulation>,
+    #[cfg(feature = "post-processing")]
+    checkerboard: Checkerboard,
+    #[cfg(feature = "post-processing")]
+    color_grading: ColorGrading,
+    cursor_position: Option<PhysicalPosition<f64>>,
+    // While the middle button is held, the camera follows the cursor
+    panning: bool,
+    // Of an explosion or implosion, applied by the next step of the simulation
+    pending_impulse: Option<glam::Vec4>,
     vertex_buffer: wgpu::Buffer,
     index_buffer: wgpu::Buffer,
     index_count: u32,
     instances: Vec<Instance>,
-    instances_raw: Vec<InstanceRaw>,
+    instance_positions: Vec<InstancePosition>,
     instances_cpu_data: Vec<ParticleCpuData>,
-    instance_buffer: wgpu::Buffer,
-    camera: Camera,
-    camera_uniform: CameraUniform,
-    camera_buffer: wgpu::Buffer,
-    camera_bind_group: wgpu::BindGroup,
+    turbulence: TurbulenceParams,
+    // Of the scene, blowing on the CPU and in the compute kernel's texture
+    wind: Option<WindField>,
+    sim_params: SimParams,
+    sim_mode: SimMode,
+    cpu_path: CpuPath,
+    // Time spent moving the particles on the CPU last frame, None on the GPU
+    cpu_simulation_time: Option<std::time::Duration>,
+    // Records the compute kernel on the rayon pool while the render passes are, see encoding.rs
+    parallel_encoding: bool,
+    // Time spent recording the compute kernel and the render passes last frame
+    encode_time: std::time::Duration,
+    boids_params: BoidsParams,
+    boids_tile_size: u32,
+    // Steers the boids when simulating them on the GPU
+    boids: Option<Boids>,
+    nbody_params: NbodyParams,
+    nbody_grid_size: u32,
+    nbody_tile_size: u32,
+    // Applies the gravity when simulating n bodies on the GPU
+    nbody: Option<Nbody>,
+    // Collides the particles when simulating them on the GPU with a collision radius
+    collisions: Option<Collisions>,
+    obstacles: Vec<Obstacle>,
+    // Custom updates run after every step, on either backend
+    behaviors: Behaviors,
+    // Draws the obstacles over the scene
+    #[cfg(feature = "post-processing")]
+    obstacle_view: Option<ObstacleView>,
+    // Fades the particles near the obstacles while they're shown
+    #[cfg(feature = "post-processing")]
+    soft_particles: Option<SoftParticles>,
+    scene_watcher: Option<SceneWatcher>,
+    // Shown instead of the main particle system when not empty
+    grid_cells: Vec<GridCell>,
+    // Positions and colors of the grid cells, in slots handed out and freed as cells come and go
+    instance_pool: InstancePool,
+    target_fps: Option<f32>,
+    // Set on the command line or by the script, override the particle count, emitter and palette
+    // of scenes
+    particles: Option<usize>,
+    emitter: Option<EmitterShape>,
+    palette: Option<SpawnPalette>,
+    // Where and how particles respawn on reset, from the current scene
+    spawn_emitter: EmitterShape,
+    spawn_palette: SpawnPalette,
+    // Of the current scene, before the overrides
+    scene_palette: SpawnPalette,
+    // Palettes, heatmap colors and display adjustment, saved with the settings
+    accessibility: Accessibility,
+    spawn_seed: Option<u64>,
+    // Shown or imported instead of spawning the particles in the emitter
+    point_cloud: Option<PointCloud>,
+    // Where the particles are exported on shutdown
+    #[cfg(feature = "asset-loaders")]
+    export: Option<PathBuf>,
+    explorer: Explorer,
+    position_buffer: wgpu::Buffer,
+    color_buffer: wgpu::Buffer,
+    // The positions in halves, written by the compute kernel, with the `packed-instances` feature
+    drawn_position_buffer: Option<wgpu::Buffer>,
+    arena: InstanceArena,
+    adaptive: Option<AdaptiveCount>,
+    // Particles simulated and drawn, set with Ctrl - and Ctrl = or the panel. None for all of them
+    count_limit: Option<usize>,
+    dirty_instances: DirtyRanges,
+    // Mapped buffers reused from frame to frame for the uploads of the CPU path
+    staging_belt: wgpu::util::StagingBelt,
+    upload_stats: UploadStats,
+    schedule: Option<Schedule>,
+    // Current keyframe of the day cycle, neutral without a schedule
+    look: Keyframe,
+    // Point and directional lights of the scene, bound with the camera
+    lights: Vec<Light>,
+    lights_buffer: wgpu::Buffer,
+    // Color over lifetime of the scene, and the uniform it's written to every frame
+    gradient: Option<ColorGradient>,
+    gradient_buffer: wgpu::Buffer,
+    // Off colors the particles with their own colors even with a gradient
+    colors_by_age: bool,
+    blend_mode: BlendMode,
+    // Stops the simulation, the camera still moves
+    paused: bool,
+    // Multiplies the time the simulation advances by each frame, for slow motion or fast-forward
+    time_scale: f32,
+    camera_bind_group_layout: wgpu::BindGroupLayout,
+    trails: Option<Trails>,
+    // Draws only the alive particles, through an indirect draw
+    compaction: Option<Compaction>,
+    // Draws far particles with cheaper meshes, instead of compacting them
+    lod: Option<Lod>,
+    // Elongates the quads along the velocities or draws them as Gaussian splats, instead of
+    // compacting them or the level of detail
+    stretched: Option<Stretched>,
+    // Turns the quads around their own axes, instead of stretching them
+    spinning: Option<Spinning>,
+    // Axes, emitter and attractor drawn over the scene
+    gizmos: Option<Gizmos>,
+    // Positions drawn while the compute passes move the particles, a frame behind
+    overlap: Option<RenderSnapshot>,
+    // Packs the drawn positions in halves on the frames the compute kernel doesn't
+    #[cfg(feature = "packed-instances")]
+    position_packer: PositionPacker,
+    // Draws the live ranges through indirect draws, if the device can start them anywhere
+    multi_draw: Option<MultiDraw>,
+    // Generates the quads in the shader and reads the instances from storage, see vertex_pulling.rs
+    vertex_pulling: bool,
+    // Rebuilt every frame once enabled, for the kernels acting on nearby particles
+    spatial_hash: Option<SpatialHash>,
+    // Draws the density of the particles instead of the particles
+    volume: Option<VolumeView>,
+    stereo_view: Option<StereoView>,
+    // Summarizes the particles every frame for the title and the panel
+    reduction: Option<Reduction>,
+    stereo: StereoSettings,
     compute_pipeline: Option<ComputePipeline>,
-    frame_time_samples: [f32; 25],
-    frame_time_index: usize,
+    frame_stats: FrameStats,
+    frame_log: Option<FrameLog>,
+    // Shown in the window title after the frame stats, by the inspector or the explorer
+    title_detail: Option<String>,
+    last_frame: std::time::Instant,
+    pacer: FramePacer,
+    recorder: Option<Recorder>,
+    // Set when writing a recorded frame failed, the recording is then over
+    recording_failed: bool,
+    frame_hasher: Option<FrameHasher>,
+    // Recorded, hashed and compared frames have to be the same from one run to the next
+    deterministic: bool,
+    // Invocations per workgroup of the compute passes
+    workgroup_size: u32,
+    // Device memory the particles may take, see --max-memory
+    max_memory: Option<u64>,
+    frame_count: u64,
+    watchdog: Watchdog,
+    checkpoint: Option<Checkpoint>,
+    // Particles spawned on the GPU, on their way to the CPU copies
+    spawn_readback: Option<Checkpoint>,
+    // Kept to pick the same adapter and surface format again when recovering from a device loss
+    backend: Option<Backend>,
+    adapter: Option<AdapterSelector>,
+    surface_format: Option<SurfaceFormat>,
+    present_mode: PresentMode,
+    // Format everything is drawn in, the surface's unless it has no sRGB format
+    scene_format: wgpu::TextureFormat,
+    since_checkpoint: f32,
+    max_invocations_per_submit: Option<u32>,
+    camera_presets: CameraPresets,
+    screensaver: Option<Screensaver>,
+    // Flythrough moving the camera until it ends, if it doesn't loop
+    camera_path: Option<CameraPath>,
+    // Waiting for the particle stats to frame the particles, true if they were enabled only for it
+    framing: Option<bool>,
+    inspector: Option<Inspector>,
+    // Percentage of the particles streamed back every frame, and the ring reading them
+    sample_percent: Option<f32>,
+    sampler: Option<ReadbackRing>,
+    // Particles whose trajectories are written, and the ring reading them
+    tracked: Vec<usize>,
+    tracker: Option<(ReadbackRing, TrajectoryWriter)>,
+    // Samples the positions of the particles for offline rendering
+    point_cache: Option<PointCache>,
+    modifiers: ModifiersState,
+    // Actions of the keys, from the keymap file
+    keymap: Keymap,
+    // Flying controls held on the keyboard and gamepad
+    input_state: InputState,
+    #[cfg(feature = "metrics")]
+    metrics: Option<MetricsExporter>,
+    #[cfg(feature = "metrics")]
+    telemetry: Option<Telemetry>,
+    #[cfg(feature = "metrics")]
+    gpu_timer: Option<GpuTimer>,
+    // Latest read back, for the parameter panel
+    #[cfg(feature = "metrics")]
+    pass_times: Option<PassTimes>,
+    // Read back during the last frame, for `bench`
+    #[cfg(feature = "metrics")]
+    frame_pass_times: Option<PassTimes>,
+    #[cfg(feature = "metrics")]
+    pipeline_stats: Option<PipelineStats>,
+    #[cfg(feature = "ui")]
+    ui: Option<Ui>,
+    #[cfg(feature = "scripting")]
+    script: Option<Script>,
+    #[cfg(feature = "audio")]
+    audio: Option<AudioInput>,
+    // Of the last frame, scaling the simulation and the colors
+    #[cfg(feature = "audio")]
+    audio_levels: AudioLevels,
 }
 
 const VERTICES: &[Vertex] = &[
     Vertex {
         position: [-0.5, -0.5, 0.0],
         vertex_position: [0.0, 0.0],
+        normal: [0.0, 0.0, 1.0],
     },
     Vertex {
         position: [0.5, -0.5, 0.0],
         vertex_position: [1.0, 0.0],
+        normal: [0.0, 0.0, 1.0],
     },
     Vertex {
         position: [-0.5, 0.5, 0.0],
         vertex_position: [0.0, 1.0],
+        normal: [0.0, 0.0, 1.0],
     },
     Vertex {
         position: [0.5, 0.5, 0.0],
         vertex_position: [1.0, 1.0],
+        normal: [0.0, 0.0, 1.0],
     },
 ];
 
-const INDICES: &[u16] = &[0, 1, 2, 3, 2, 1];
+pub const INDICES: &[u16] = &[0, 1, 2, 3, 2, 1];
 
-impl State {
-    pub fn new(window: Window) -> Self {
-        let size = window.inner_size();
+// The camera, the lighting of the day cycle at binding 1, the scene lights at binding 2 and the
+// color gradient at binding 3
+pub const CAMERA_BIND_GROUP_LAYOUT_ENTRIES: [wgpu::BindGroupLayoutEntry; 4] = [
+    wgpu::BindGroupLayoutEntry {
+        binding: 0,
+        // The temporal accumulation reprojects every pixel with it
+        visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+        ty: wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Uniform,
+            has_dynamic_offset: false,
+            min_binding_size: None,
+        },
+        count: None,
+    },
+    wgpu::BindGroupLayoutEntry {
+        binding: 1,
+        visibility: wgpu::ShaderStages::FRAGMENT,
+        ty: wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Uniform,
+            has_dynamic_offset: false,
+            min_binding_size: None,
+        },
+        count: None,
+    },
+    wgpu::BindGroupLayoutEntry {
+        binding: 2,
+        visibility: wgpu::ShaderStages::FRAGMENT,
+        ty: wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Storage { read_only: true },
+            has_dynamic_offset: false,
+            min_binding_size: None,
+        },
+        count: None,
+    },
+    wgpu::BindGroupLayoutEntry {
+        binding: 3,
+        visibility: wgpu::ShaderStages::VERTEX,
+        ty: wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Uniform,
+            has_dynamic_offset: false,
+            min_binding_size: None,
+        },
+        count: None,
+    },
+];
 
-        let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
-            backends: wgpu::Backends::PRIMARY, // Vulkan, Metal, DX12, WebGPU
-            dx12_shader_compiler: Default::default(),
-        });
+const PARTICLE_COUNT: usize = 1_500_000;
+// Positions, colors and speeds of a particle are all 16 bytes
+const PARTICLE_SIZE: usize = std::mem::size_of::<InstancePosition>();
 
-        // # Safety
-        //
-        // The surface needs to live as long as the window that created it.
-        // State owns both the window and the surface so this is safe.
-        let surface = unsafe { instance.create_surface(&window) }.unwrap();
+// Number of instances grouped together when tracking which parts of the instance buffer changed
+const DIRTY_CHUNK_SIZE: usize = 4096;
 
-        let adapter = pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
-            power_preference: wgpu::PowerPreference::HighPerformance,
-            compatible_surface: Some(&surface),
-            force_fallback_adapter: false,
-        }))
-        .unwrap();
+// Size of the staging buffers the CPU path uploads the changed instances through, larger uploads
+// get a buffer of their own
+const UPLOAD_CHUNK_SIZE: wgpu::BufferAddress = (DIRTY_CHUNK_SIZE * 64 * PARTICLE_SIZE) as _;
 
-        let (device, queue) = pollster::block_on(adapter.request_device(
-            &wgpu::DeviceDescriptor {
-                features: wgpu::Features::empty(),
-                limits: wgpu::Limits::default(),
-                label: Some("4"),
-            },
-            None,
-        ))
-        .unwrap();
+// Past this many disjoint live ranges (and as many draw calls), the instances get compacted
+const MAX_LIVE_RANGES: usize = 4;
 
-        let surface_caps = surface.get_capabilities(&adapter);
+// Fraction of the instance buffer removed when pressing Delete
+const DELETE_BLOCK_FRACTION: f32 = 0.05;
 
-        let surface_format = surface_caps
-            .formats
-            .iter()
-            .copied()
-            .find(|texture_format| texture_format.is_srgb())
-            .expect("Did not find an sRGB texture to render to"); // Change here to render HDR
+// Size of the stills taken with P, independent of the window size
+const CAPTURE_SIZE: (u32, u32) = (7680, 4320);
+// Captures are rendered in tiles of at most this size
+const CAPTURE_TILE_SIZE: u32 = 2048;
+// Size of the equirectangular panoramas taken with C
+const PANORAMA_SIZE: (u32, u32) = (8192, 4096);
+// Size of each eye in the stereo captures taken with V (side-by-side) and B (anaglyph)
+const STEREO_EYE_SIZE: (u32, u32) = (1920, 1080);
 
-        let config = wgpu::SurfaceConfiguration {
-            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
-            format: surface_format,
-            width: size.width,
-            height: size.height,
-            present_mode: wgpu::PresentMode::Immediate,
-            alpha_mode: surface_caps.alpha_modes[0],
-            view_formats: vec![],
-        };
+pub const WINDOW_TITLE: &str = "Particles!";
+// Of the frames rendered headless, the main window's default size at a scale factor of 1
+const HEADLESS_SIZE: winit::dpi::PhysicalSize<u32> = winit::dpi::PhysicalSize::new(1500, 900);
+// Of the frames rendered headless without --surface-format
+const HEADLESS_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8UnormSrgb;
 
-        surface.configure(&device, &config);
+// What `State::create_device` creates, the surface None headless
+type CreatedDevice = (
+    wgpu::Instance,
+    wgpu::Adapter,
+    Option<wgpu::Surface>,
+    wgpu::Device,
+    wgpu::Queue,
+    wgpu::SurfaceConfiguration,
+);
 
-        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
-            label: Some("Shader"),
-            source: wgpu::ShaderSource::Wgsl(include_str!("shader.wgsl").into()),
-        });
+// Frames of sampled particles in flight before one is skipped
+const SAMPLE_SLOTS: usize = 3;
 
-        let camera = Camera {
-            // position the camera one unit up and 2 units back
-            // +z is out of the screen
-            eye: (0.0, 1.0, 5000.0).into(),
-            // have it look at the origin
-            target: (0.0, 0.0, -100.0).into(),
-            // which way is "up"
-            up: glam::Vec3::Y,
-            aspect: config.width as f32 / config.height as f32,
-            fovy: 20.0,
-            znear: 0.0,
-            zfar: 10000.0,
+// Seconds between two copies of the GPU simulation state back to the CPU
+const CHECKPOINT_INTERVAL: f32 = 2.0;
+
+// Camera presets stored with Ctrl+1-9 are kept here, in the working directory
+const CAMERA_PRESETS_PATH: &str = "camera_presets.txt";
+
+// Parameter sets bookmarked with Q in explore mode are appended here
+const EXPLORE_BOOKMARKS_PATH: &str = "explore_bookmarks.txt";
+
+// Width of the spatial hash cells, particles further apart than this don't interact
+const NEIGHBOR_CELL_SIZE: f32 = 50.0;
+// Factor of the active particle count for each press of Ctrl =, and its inverse for Ctrl -
+const COUNT_LIMIT_STEP: f32 = 1.25;
+// Change in turbulence amplitude for each press of - or =
+const TURBULENCE_AMPLITUDE_STEP: f32 = 0.05;
+// Change in damping for each press of J or K
+const DAMPING_STEP: f32 = 0.001;
+// Change in attractor strength for each press of G or H
+const ATTRACTOR_STRENGTH_STEP: f32 = 0.002;
+// Factor of the time scale for each press of Ctrl . and its inverse for Ctrl ,
+const TIME_SCALE_STEP: f32 = 2.0;
+// Change of speed of the particles at the center of an explosion or implosion, about ten times
+// the speed they spawn with, and the distance at which it's halved
+const IMPULSE_STRENGTH: f32 = 2.0;
+const IMPULSE_RADIUS: f32 = 250.0;
+// Factor of the particles the grid starts with the instance pool has room for, the rest is for the
+// scenes dropped on the window
+const POOL_HEADROOM: usize = 2;
+/// Slowest and fastest the simulation runs, relative to real time
+pub const MIN_TIME_SCALE: f32 = 1.0 / 16.0;
+pub const MAX_TIME_SCALE: f32 = 16.0;
+
+// Must match the row width used to compute the index in compute_kernel.wgsl
+pub const COMPUTE_ROW_WIDTH: u32 = 10_000;
+// Must match the entry points of compute_kernel.wgsl, replaced to set their workgroup size
+const COMPUTE_WORKGROUP_SIZE_ATTRIBUTE: &str = "@workgroup_size(64)";
+/// Invocations per workgroup of the compute passes without --workgroup-size
+pub const DEFAULT_WORKGROUP_SIZE: u32 = 64;
+/// Largest workgroup size, the most invocations per workgroup every device supports
+pub const MAX_WORKGROUP_SIZE: u32 = 256;
+
+impl State {
+    /// Sets up the simulation and renderer in `window`, or headless without one: the frames are
+    /// then only rendered offscreen, see [`State::render`].
+    pub fn new(
+        window: Option<Window>,
+        options: &Options,
+        settings: &Settings,
+    ) -> Result<Self, AppError> {
+        let size = window.as_ref().map_or(HEADLESS_SIZE, Window::inner_size);
+        let mut pacer = FramePacer::new(
+            window.as_ref().and_then(Window::current_monitor),
+            options.max_fps,
+        );
+        pacer.set_enabled(settings.paced);
+        if let Some(fps) = options.max_fps {
+            log::info!("Frame rate capped at {fps} FPS");
+        }
+        let mut watchdog = Watchdog::default();
+        let mut scene = match &options.watch {
+            Some(path) => Scene::load(path)?,
+            None => Scene::default(),
         };
+        let (particles, emitter) = (options.particles, options.emitter);
+        #[cfg(feature = "scripting")]
+        let script = options.script.as_deref().map(Script::load).transpose()?;
+        #[cfg(feature = "audio")]
+        let audio = options.audio.then(AudioInput::new).transpose()?;
+        #[cfg(feature = "scripting")]
+        let (particles, emitter) = match script.as_ref().map(Script::emission).transpose()? {
+            Some(Some(emission)) => emission.apply(particles, emitter),
+            _ => (particles, emitter),
+        };
+        if let Some(particles) = particles {
+            scene.particles = Some(particles);
+        }
+        if let Some(emitter) = emitter {
+            scene.emitter = emitter;
+        }
+        let scene_palette = scene.palette;
+        let accessibility = Accessibility {
+            display: settings.accessibility.display.clamped(),
+            ..settings.accessibility
+        };
+        let safe_palette = accessibility.palette.map(SafePalette::spawn_palette);
+        if let Some(palette) = options.palette.or(safe_palette) {
+            scene.palette = palette;
+        }
+
+        let (instance, gpu_adapter, surface, device, queue, config) = Self::create_device(
+            window.as_ref(),
+            size,
+            options.backend,
+            options.adapter.as_ref(),
+            options.surface_format,
+            options.present_mode,
+        )?;
+        let capabilities = GpuCapabilities::of(&gpu_adapter);
+        watchdog.watch(&device);
+        let scene_format = surface_format::scene_format(config.format);
+
+        let mut camera = initial_camera(
+            config.width as f32 / config.height as f32,
+            options.reverse_z,
+        );
+        settings.apply_camera(&mut camera);
+        scene.apply_camera(&mut camera);
 
         let mut camera_uniform = CameraUniform::new();
         camera_uniform.update_view_proj(&camera);
 
-        let camera_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("Camera Buffer"),
-            contents: bytemuck::cast_slice(&[camera_uniform]),
-            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
-        });
-
-        let camera_bind_group_layout =
-            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-                entries: &[wgpu::BindGroupLayoutEntry {
-                    binding: 0,
-                    visibility: wgpu::ShaderStages::VERTEX,
-                    ty: wgpu::BindingType::Buffer {
-                        ty: wgpu::BufferBindingType::Uniform,
-                        has_dynamic_offset: false,
-                        min_binding_size: None,
-                    },
-                    count: None,
-                }],
-                label: Some("camera_bind_group_layout"),
-            });
+        let schedule = match options.schedule {
+            Some(clock) => {
+                let keyframes = match &options.schedule_file {
+                    Some(path) => Schedule::load_keyframes(path)
+                        .map_err(|e| AppError::Schedule(path.clone(), e))?,
+                    None => vec![],
+                };
+                Some(Schedule::new(clock, keyframes))
+            }
+            None => None,
+        };
 
-        let camera_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            layout: &camera_bind_group_layout,
-            entries: &[wgpu::BindGroupEntry {
-                binding: 0,
-                resource: camera_buffer.as_entire_binding(),
-            }],
-            label: Some("camera_bind_group"),
-        });
+        let lights_buffer = lights::create_buffer(&device);
+        lights::write_buffer(&queue, &lights_buffer, &scene.lights);
+        let gradient_buffer = gradient::create_buffer(&device);
+        let (camera_buffer, lighting_buffer, camera_bind_group_layout, camera_bind_group) =
+            Self::create_camera_bindings(
+                &device,
+                &camera_uniform,
+                &Keyframe::NEUTRAL,
+                &lights_buffer,
+                &gradient_buffer,
+            );
 
-        let render_pipeline_layout =
-            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-                label: Some("Render Pipeline Layout"),
-                bind_group_layouts: &[&camera_bind_group_layout],
-                push_constant_ranges: &[],
-            });
+        let appearance = scene
+            .appearance
+            .as_deref()
+            .map(Appearance::load)
+            .transpose()?;
+        shader_check::validate_builtin()?;
+        let pipeline_cache = PipelineCache::new(
+            &device,
+            scene_format,
+            &camera_bind_group_layout,
+            &camera.depth,
+            appearance.as_ref(),
+        )?;
+        let appearance_watcher = options
+            .watch
+            .as_ref()
+            .and(appearance.as_ref())
+            .map(|appearance| AppearanceWatcher::new(appearance.path().to_owned()));
 
-        let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: Some("Render Pipeline"),
-            layout: Some(&render_pipeline_layout),
-            vertex: wgpu::VertexState {
-                module: &shader,
-                entry_point: "vs_main",
-                buffers: &[Vertex::descriptor(), InstanceRaw::descriptor()],
-            },
-            fragment: Some(wgpu::FragmentState {
-                module: &shader,
-                entry_point: "fs_main",
-                targets: &[Some(wgpu::ColorTargetState {
-                    format: config.format,
-                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
-                    write_mask: wgpu::ColorWrites::ALL,
-                })],
-            }),
-            primitive: wgpu::PrimitiveState {
-                topology: wgpu::PrimitiveTopology::TriangleList,
-                strip_index_format: None,
-                front_face: wgpu::FrontFace::Ccw,
-                cull_mode: Some(wgpu::Face::Back),
-                // Setting this to anything other than Fill requires Features::NON_FILL_POLYGON_MODE
-                polygon_mode: wgpu::PolygonMode::Fill,
-                // Requires Features::DEPTH_CLIP_CONTROL
-                unclipped_depth: false,
-                // Requires Features::CONSERVATIVE_RASTERIZATION
-                conservative: false,
-            },
-            depth_stencil: None, // 1.
-            multisample: wgpu::MultisampleState {
-                count: 1,
-                mask: !0,
-                alpha_to_coverage_enabled: false,
-            },
-            multiview: None, // 5.
-        });
+        let deterministic = options.record.is_some()
+            || options.frame_hash
+            || options.compare.is_some()
+            || options.record_input.is_some()
+            || options.replay.is_some()
+            || options.search.is_some();
+        let workgroup_size = options.workgroup_size.unwrap_or(DEFAULT_WORKGROUP_SIZE);
+        let (instance_pool, grid_cells) = Self::create_grid(
+            &device,
+            &queue,
+            &camera_bind_group_layout,
+            &lights_buffer,
+            &gradient_buffer,
+            &options.grid,
+            deterministic,
+            workgroup_size,
+            options.max_memory,
+            options.reverse_z,
+        )?;
+        if !grid_cells.is_empty() {
+            log::info!("Grid view of {} scenes", grid_cells.len());
+        }
 
-        let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("Vertex Buffer"),
-            contents: bytemuck::cast_slice(VERTICES),
-            usage: wgpu::BufferUsages::VERTEX,
-        });
+        let debug_pipelines = DebugPipelines::new(
+            &device,
+            &capabilities,
+            scene_format,
+            &camera_bind_group_layout,
+        );
+        let picker = Picker::new(
+            &device,
+            scene_format,
+            &camera_bind_group_layout,
+            &camera.depth,
+        );
+        let environment = Self::create_environment(
+            &device,
+            &queue,
+            scene_format,
+            &camera_bind_group_layout,
+            scene.environment.clone(),
+        );
 
-        let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("Index Buffer"),
-            contents: bytemuck::cast_slice(INDICES),
-            usage: wgpu::BufferUsages::INDEX,
+        let render_target = RenderTarget::new(
+            &device,
+            scene_format,
+            config.format,
+            size,
+            options.render_scale,
+        );
+        // The other effects are built once enabled, see State::depth_of_field
+        #[cfg(feature = "post-processing")]
+        let half_resolution = (options.half_res
+            && loads_depth(&capabilities, "Half resolution particles"))
+        .then(|| HalfResolution::new(&device, scene_format, true));
+        #[cfg(feature = "post-processing")]
+        let motion_blur = options.motion_blur.map(|shutter| {
+            MotionBlur::new(
+                &device,
+                scene_format,
+                &camera_bind_group_layout,
+                &camera.depth,
+                Some(shutter),
+            )
         });
-        let index_count = INDICES.len().try_into().unwrap();
+        #[cfg(feature = "post-processing")]
+        let temporal =
+            (options.taa && loads_depth(&capabilities, "Temporal accumulation")).then(|| {
+                TemporalAccumulation::new(&device, scene_format, &camera_bind_group_layout, true)
+            });
+        #[cfg(feature = "post-processing")]
+        let checkerboard = Checkerboard::new(&device, scene_format, options.checkerboard);
+        #[cfg(feature = "post-processing")]
+        let color_grading = {
+            // Enabled from the start if given a table
+            #[cfg(feature = "asset-loaders")]
+            let (lut, enabled) = (
+                Lut::load_or_neutral(options.lut.as_deref()),
+                options.lut.is_some(),
+            );
+            #[cfg(not(feature = "asset-loaders"))]
+            let (lut, enabled) = (Lut::neutral(), false);
+            ColorGrading::new(&device, &queue, scene_format, lut, enabled)
+        };
+        #[cfg(feature = "post-processing")]
+        let soft_particles = options
+            .soft_particles
+            .map(|fade_distance| SoftParticles::new(&device, fade_distance));
 
-        let mut rng = rand::thread_rng();
-        let max_particle_count = adapter
-            .limits()
-            .max_storage_buffer_binding_size
-            .min(adapter.limits().max_uniform_buffer_binding_size);
-
-        let instances = (0..1_500_000)
-            .map(|_| {
-                let x: f32 = (rng.gen::<f32>() - 0.5) * 850.0;
-                let y: f32 = (rng.gen::<f32>() - 0.5) * 820.0;
-                let z: f32 = (rng.gen::<f32>() - 0.1) * 1000.0;
-                let position = glam::Vec3::new(x, y, z);
-                let rotation = glam::Quat::from_axis_angle(glam::Vec3::Z, 0.0);
-                let color = glam::Vec4::new(
-                    0.12 + rng.gen::<f32>() / 4.0 + (x / 850.0 + 0.5) / 2.0,
-                    0.75 + rng.gen::<f32>() / 5.0,
-                    rng.gen(),
-                    1.0,
-                );
-                Instance {
-                    position,
-                    rotation,
-                    color,
-                }
+        let recorder = options
+            .record
+            .as_ref()
+            .map(|path| {
+                let (width, height) = render_target.size();
+                let frames = options.frames.expect("--record requires --frames");
+                Recorder::new(path, frames, width, height)
+                    .map_err(|e| AppError::Record(path.clone(), e))
             })
-            .collect::<Vec<_>>();
+            .transpose()?;
 
-        let instances_cpu_data = (0..instances.len())
-            .map(|_| ParticleCpuData {
-                speed: glam::Vec3::new(
-                    rng.gen::<f32>() - 0.5,
-                    rng.gen::<f32>() - 0.5,
-                    rng.gen::<f32>() - 0.5,
-                )
-                .normalize()
-                    / 5.0,
-                _unused: 0.0,
+        let frame_hasher = options.frame_hash.then(|| FrameHasher::new(&device));
+
+        let (vertex_buffer, index_buffer) = Self::create_mesh_buffers(&device);
+        let index_count = INDICES.len().try_into().unwrap();
+        let multi_draw = MultiDraw::new(&device, &capabilities, index_count);
+        let vertex_pulling = options.vertex_pulling && capabilities.vertex_storage;
+        if options.vertex_pulling && !vertex_pulling {
+            log::warn!(
+                "The adapter can't read storage buffers in vertex shaders, pulling disabled"
+            );
+        }
+
+        // Spawned straight into the buffers on the GPU if they can be bound at once, the CPU copies
+        // are read back later
+        #[cfg(feature = "asset-loaders")]
+        let loaded = Self::load_point_cloud(options, &scene, deterministic)?;
+        #[cfg(not(feature = "asset-loaders"))]
+        let loaded = None;
+        let point_cloud = loaded.or_else(|| {
+            options.distribution.map(|distribution| {
+                let mut rng = particle_rng(scene.seed, deterministic);
+                let count = scene.particles.unwrap_or(PARTICLE_COUNT);
+                let nbody = (options.sim == SimMode::Nbody).then_some(&scene.nbody);
+                distribution.generate(count, nbody, &mut rng)
             })
-            .collect::<Vec<_>>();
+        });
+        let particle_count =
+            Self::scene_particle_count(&device, &scene, point_cloud.as_ref(), options.max_memory);
+        let spawn_on_gpu = point_cloud.is_none() && particle_init::fits(&device, particle_count);
+        let (instances, instances_cpu_data) = if spawn_on_gpu {
+            Self::unspawned_particles(particle_count)
+        } else {
+            let mut rng = particle_rng(scene.seed, deterministic);
+            Self::generate_particles(
+                particle_count,
+                &scene.emitter,
+                &scene.palette,
+                point_cloud.as_ref(),
+                &mut rng,
+            )
+        };
 
-        let instances_raw = instances
+        let instance_positions = instances
             .par_iter()
-            .map(Instance::to_raw)
+            .map(Instance::to_position)
             .collect::<Vec<_>>();
 
-        let instance_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("Instance Buffer"),
-            contents: bytemuck::cast_slice(&instances_raw),
-            usage: wgpu::BufferUsages::VERTEX
-                | wgpu::BufferUsages::COPY_DST
-                | wgpu::BufferUsages::COPY_SRC
-                | wgpu::BufferUsages::STORAGE,
-        });
+        let (position_buffer, color_buffer, drawn_position_buffer) = if spawn_on_gpu {
+            Self::create_instance_buffers(&device, &queue, instances.len(), &[], &[])
+        } else {
+            let instance_colors = instances
+                .par_iter()
+                .map(Instance::to_color)
+                .collect::<Vec<_>>();
+            Self::create_instance_buffers(
+                &device,
+                &queue,
+                instances.len(),
+                &instance_positions,
+                &instance_colors,
+            )
+        };
 
+        let behaviors = Behaviors::new(&scene.behaviors);
+        let wind = Self::load_wind(scene.wind.as_ref());
         let compute_pipeline = Some(Self::create_compute_pipeline(
             &device,
             &instances_cpu_data,
-            &instance_buffer,
+            &position_buffer,
+            0,
+            drawn_position_buffer.as_ref(),
+            &scene.obstacles,
+            &behaviors,
+            &scene.emitter,
+            WindTexture::new(&device, &queue, wind.as_ref()),
+            workgroup_size,
+            SpeedLayout::of(&device),
         ));
 
-        Self {
-            window,
-            surface,
+        let spawn_readback =
+            compute_pipeline
+                .as_ref()
+                .filter(|_| spawn_on_gpu)
+                .map(|compute_pipeline| {
+                    Self::spawn_on_gpu(
+                        &device,
+                        &queue,
+                        &scene.emitter,
+                        &scene.palette,
+                        particle_seed(scene.seed, deterministic),
+                        particle_count,
+                        &position_buffer,
+                        &color_buffer,
+                        compute_pipeline,
+                    )
+                });
+
+        let boids = Self::create_boids(
+            &device,
+            options.sim,
+            &scene.boids,
+            options.boids_tile_size,
+            compute_pipeline.as_ref(),
+            &position_buffer,
+        );
+        let nbody = Self::create_nbody(
+            &device,
+            options.sim,
+            &scene.nbody,
+            options.nbody_grid,
+            options.nbody_tile_size,
+            compute_pipeline.as_ref(),
+            &position_buffer,
+        );
+        let collisions = Self::create_collisions(
+            &device,
+            &scene.sim_params,
+            compute_pipeline.as_ref(),
+            &position_buffer,
+        );
+
+        let instance_count = instances.len();
+
+        #[cfg(feature = "metrics")]
+        let metrics = (options.metrics_listen.is_some() || options.metrics_csv.is_some())
+            .then(|| MetricsExporter::new(options.metrics_listen, options.metrics_csv.as_deref()))
+            .transpose()
+            .map_err(AppError::Metrics)?;
+        #[cfg(feature = "metrics")]
+        let telemetry = options
+            .telemetry_listen
+            .map(Telemetry::new)
+            .transpose()
+            .map_err(AppError::Telemetry)?;
+        // The parameter panel shows the GPU times too
+        #[cfg(feature = "metrics")]
+        let gpu_timer = (metrics.is_some()
+            || telemetry.is_some()
+            || options.command == crate::options::Command::Bench
+            || options.frame_log.is_some()
+            || cfg!(feature = "ui"))
+        .then(|| Self::create_gpu_timer(&device, &queue, &capabilities))
+        .flatten();
+
+        let tracker = options
+            .track_csv
+            .as_ref()
+            .map(|path| {
+                let writer =
+                    TrajectoryWriter::new(path).map_err(|e| AppError::Write(path.clone(), e))?;
+                log::info!("Writing trajectories to {}", path.display());
+                Ok::<_, AppError>((Self::create_tracker(&options.track, instance_count), writer))
+            })
+            .transpose()?;
+        let point_cache = options
+            .point_cache
+            .as_ref()
+            .map(|path| {
+                let point_cache =
+                    PointCache::new(path, instance_count, options.point_cache_interval)
+                        .map_err(|e| AppError::Write(path.clone(), e))?;
+                log::info!(
+                    "Writing {instance_count} particles every {} frames to {}",
+                    options.point_cache_interval,
+                    path.display()
+                );
+                Ok::<_, AppError>(point_cache)
+            })
+            .transpose()?;
+        let camera_path = options
+            .camera_path
+            .as_ref()
+            .map(|path| CameraPath::load(path).map_err(|e| AppError::CameraPath(path.clone(), e)))
+            .transpose()?;
+        #[cfg(feature = "packed-instances")]
+        let position_packer = PositionPacker::new(&device);
+
+        let mut state = Self {
+            instance,
+            gpu_adapter,
+            capabilities,
             device,
             queue,
-            config,
-            size,
-            render_pipeline,
+            viewport: Viewport {
+                surface,
+                scale_factor: window.as_ref().map_or(1.0, Window::scale_factor),
+                minimized: false,
+                window,
+                config,
+                size,
+                zoom: ZoomController::default(),
+                gestures: TouchGestures::default(),
+                group_mask: GroupMask::default(),
+                camera,
+                camera_uniform,
+                camera_buffer,
+                lighting_buffer,
+                camera_bind_group,
+            },
+            surface_recovery: SurfaceRecovery::default(),
+            extra_viewports: vec![],
+            window_requested: false,
+            pipeline_cache,
+            appearance,
+            appearance_watcher,
+            shader_error: None,
+            debug_pipelines,
+            debug_view: DebugView::Off,
+            picker,
+            environment,
+            render_target,
+            transient_textures: TransientTextures::default(),
+            #[cfg(feature = "post-processing")]
+            depth_of_field: None,
+            #[cfg(feature = "post-processing")]
+            half_resolution,
+            #[cfg(feature = "post-processing")]
+            motion_blur,
+            #[cfg(feature = "post-processing")]
+            temporal,
+            #[cfg(feature = "post-processing")]
+            checkerboard,
+            #[cfg(feature = "post-processing")]
+            color_grading,
+            cursor_position: None,
+            panning: false,
+            pending_impulse: None,
             vertex_buffer,
             index_buffer,
             index_count,
             instances,
-            instances_raw,
-            instance_buffer,
+            instance_positions,
+            position_buffer,
+            color_buffer,
+            drawn_position_buffer,
             instances_cpu_data,
-            camera,
-            camera_bind_group,
-            camera_buffer,
-            camera_uniform,
+            turbulence: scene.turbulence,
+            wind,
+            sim_params: scene.sim_params,
+            sim_mode: options.sim,
+            cpu_path: options.cpu_sim.unwrap_or_default(),
+            cpu_simulation_time: None,
+            parallel_encoding: !options.serial_encoding,
+            encode_time: std::time::Duration::ZERO,
+            boids_params: scene.boids,
+            boids_tile_size: options.boids_tile_size,
+            boids,
+            nbody_params: scene.nbody,
+            nbody_grid_size: options.nbody_grid,
+            nbody_tile_size: options.nbody_tile_size,
+            nbody,
+            collisions,
+            obstacles: scene.obstacles.clone(),
+            behaviors,
+            #[cfg(feature = "post-processing")]
+            obstacle_view: None,
+            #[cfg(feature = "post-processing")]
+            soft_particles,
+            scene_watcher: options.watch.clone().map(SceneWatcher::new),
+            grid_cells,
+            instance_pool,
+            target_fps: options.target_fps,
+            particles,
+            emitter,
+            palette: options.palette,
+            spawn_emitter: scene.emitter,
+            spawn_palette: scene.palette,
+            scene_palette,
+            accessibility,
+            spawn_seed: scene.seed,
+            point_cloud,
+            #[cfg(feature = "asset-loaders")]
+            export: options.export.clone(),
+            explorer: Explorer::new(ExploreRanges::default(), EXPLORE_BOOKMARKS_PATH),
+            arena: InstanceArena::new(instance_count),
+            adaptive: options
+                .target_fps
+                .map(|fps| AdaptiveCount::new(fps, instance_count)),
+            count_limit: None,
+            dirty_instances: DirtyRanges::default(),
+            staging_belt: wgpu::util::StagingBelt::new(UPLOAD_CHUNK_SIZE),
+            upload_stats: UploadStats::default(),
+            lights: scene.lights.clone(),
+            lights_buffer,
+            gradient: scene.gradient.clone(),
+            gradient_buffer,
+            colors_by_age: true,
+            blend_mode: BlendMode::default(),
+            // Point clouds are meant to be looked at, the scene's forces would scatter them
+            #[cfg(feature = "asset-loaders")]
+            paused: options.points.is_some(),
+            #[cfg(not(feature = "asset-loaders"))]
+            paused: false,
+            time_scale: 1.0,
+            camera_bind_group_layout,
+            trails: None,
+            compaction: None,
+            lod: None,
+            stretched: None,
+            spinning: None,
+            gizmos: None,
+            overlap: None,
+            #[cfg(feature = "packed-instances")]
+            position_packer,
+            multi_draw,
+            vertex_pulling,
+            spatial_hash: None,
+            volume: None,
+            stereo_view: None,
+            reduction: None,
+            stereo: StereoSettings::default(),
+            schedule,
+            look: Keyframe::NEUTRAL,
             compute_pipeline,
-            frame_time_samples: Default::default(),
-            frame_time_index: 0,
+            frame_stats: FrameStats::new(
+                options
+                    .stats_interval
+                    .map(std::time::Duration::from_secs_f32),
+            ),
+            frame_log: options.frame_log.clone().map(FrameLog::new),
+            title_detail: None,
+            last_frame: std::time::Instant::now(),
+            pacer,
+            recorder,
+            recording_failed: false,
+            frame_hasher,
+            deterministic,
+            workgroup_size,
+            max_memory: options.max_memory,
+            frame_count: 0,
+            watchdog,
+            checkpoint: None,
+            spawn_readback,
+            backend: options.backend,
+            surface_format: options.surface_format,
+            present_mode: options.present_mode,
+            scene_format,
+            adapter: options.adapter.clone(),
+            since_checkpoint: 0.0,
+            max_invocations_per_submit: options.max_invocations_per_submit,
+            camera_presets: CameraPresets::load(CAMERA_PRESETS_PATH),
+            inspector: None,
+            sample_percent: options.sample,
+            sampler: options
+                .sample
+                .map(|percent| Self::create_sampler(instance_count, percent)),
+            tracked: options.track.clone(),
+            tracker,
+            point_cache,
+            screensaver: match (options.scr, options.idle) {
+                // Started by Windows once the system is idle, so it runs right away
+                (Some(ScrCommand::Run), _) => Some(Screensaver::new(0.0, true)),
+                (_, Some(idle)) => Some(Screensaver::new(idle, false)),
+                _ => None,
+            },
+            camera_path,
+            framing: None,
+            modifiers: ModifiersState::empty(),
+            keymap: Keymap::load(),
+            input_state: InputState::default(),
+            #[cfg(feature = "metrics")]
+            metrics,
+            #[cfg(feature = "metrics")]
+            telemetry,
+            #[cfg(feature = "metrics")]
+            gpu_timer,
+            #[cfg(feature = "metrics")]
+            pass_times: None,
+            #[cfg(feature = "metrics")]
+            frame_pass_times: None,
+            #[cfg(feature = "metrics")]
+            pipeline_stats: None,
+            #[cfg(feature = "ui")]
+            ui: None,
+            #[cfg(feature = "scripting")]
+            script,
+            #[cfg(feature = "audio")]
+            audio,
+            #[cfg(feature = "audio")]
+            audio_levels: AudioLevels::default(),
+        };
+        if options.cpu_sim.is_some() {
+            state.simulate_on_cpu();
+        }
+        if options.overlap {
+            state.toggle_overlap();
         }
+        state.apply_display(accessibility);
+        #[cfg(feature = "ui")]
+        if settings.ui_open {
+            state.toggle_ui();
+        }
+        Ok(state)
     }
 
-    pub fn window(&self) -> &Window {
-        &self.window
+    /// Remembers the main window, camera and toggles in `settings`.
+    pub fn store_settings(&self, settings: &mut Settings) {
+        if let Some(window) = &self.viewport.window {
+            settings.store_window(window);
+        }
+        settings.store_camera(&self.viewport.camera);
+        settings.paced = self.pacer.enabled();
+        settings.accessibility = self.accessibility;
+        #[cfg(feature = "ui")]
+        {
+            settings.ui_open = self.ui.is_some();
+        }
     }
 
-    pub fn size(&self) -> &winit::dpi::PhysicalSize<u32> {
-        &self.size
+    /// The main window, None headless.
+    pub fn window(&self) -> Option<&Window> {
+        self.viewport.window.as_ref()
     }
 
-    pub fn input(&mut self, event: &winit::event::WindowEvent) -> bool {
-        if let WindowEvent::MouseWheel { delta, .. } = event {
-            let MouseScrollDelta::PixelDelta(pos) = delta else {
-                return false;
-            };
-            self.camera.eye.z += pos.y as f32 / 50.0;
-            self.camera.target = self.camera.eye + glam::Vec3::new(0.0, 0.0, -1.0);
+    pub fn pacer(&mut self) -> &mut FramePacer {
+        &mut self.pacer
+    }
 
-            return true;
-        }
+    /// The parameters explored by [`crate::explore`] and the parameter search, as simulated now.
+    pub fn explored_params(&self) -> (TurbulenceParams, SimParams) {
+        (self.turbulence, self.sim_params)
+    }
 
-        if let WindowEvent::KeyboardInput { input, .. } = event {
-            if input.virtual_keycode == Some(VirtualKeyCode::R) {
-                let mut encoder =
-                    self.device
-                        .create_command_encoder(&wgpu::CommandEncoderDescriptor {
-                            label: Some("Compute Encoder"),
+    /// Respawns every particle and simulates them with `turbulence` and `sim_params` from then on,
+    /// so candidates of the parameter search start from the same particles.
+    pub fn restart_with(&mut self, turbulence: TurbulenceParams, sim_params: SimParams) {
+        self.turbulence = turbulence;
+        self.sim_params = sim_params;
+        self.reset_particles();
+    }
+
+    /// Positions of every simulated particle, read back once the GPU is done with the frames
+    /// submitted so far.
+    pub fn particle_positions(&self) -> Vec<glam::Vec3> {
+        Self::read_back_buffer::<InstancePosition>(
+            &self.device,
+            &self.queue,
+            &self.position_buffer,
+            self.instances.len(),
+        )
+        .iter()
+        .map(|instance| instance.position.xyz())
+        .collect()
+    }
+
+    /// The camera of the main window, or of the headless frames.
+    pub fn camera(&self) -> &Camera {
+        &self.viewport.camera
+    }
+
+    /// Focuses the depth of field on whatever is under the mouse cursor.
+    #[cfg(feature = "post-processing")]
+    fn focus_at_cursor(&mut self) {
+        let Some(position) = self.cursor_position else {
+            return;
+        };
+        let Some(depth_of_field) = self
+            .depth_of_field
+            .as_mut()
+            .filter(|depth_of_field| depth_of_field.enabled())
+        else {
+            return;
+        };
+        // The scene may be rendered at a different resolution than the window
+        let (width, height) = self.render_target.size();
+        let pixel = (
+            (position.x * width as f64 / self.viewport.size.width as f64) as u32,
+            (position.y * height as f64 / self.viewport.size.height as f64) as u32,
+        );
+        match depth_of_field.focus_at(&self.device, &self.queue, pixel) {
+            Some(distance) => log::info!("Focus distance: {distance}"),
+            None => log::info!("Nothing to focus on under the cursor"),
+        }
+    }
+
+    /// Depth of field, built the first time it's asked for, none if the GPU can't run it.
+    #[cfg(feature = "post-processing")]
+    fn depth_of_field(&mut self) -> Option<&mut DepthOfField> {
+        if self.depth_of_field.is_none() && loads_depth(&self.capabilities, "Depth of field") {
+            self.depth_of_field = Some(DepthOfField::new(
+                &self.device,
+                self.scene_format,
+                self.viewport
+                    .camera
+                    .eye
+                    .distance(self.viewport.camera.target),
+                self.viewport.camera.depth.reversed,
+            ));
+        }
+        self.depth_of_field.as_mut()
+    }
+
+    /// Half resolution particles, built disabled the first time they're asked for, none if the GPU
+    /// can't run them.
+    #[cfg(feature = "post-processing")]
+    fn half_resolution(&mut self) -> Option<&mut HalfResolution> {
+        if self.half_resolution.is_none()
+            && loads_depth(&self.capabilities, "Half resolution particles")
+        {
+            self.half_resolution =
+                Some(HalfResolution::new(&self.device, self.scene_format, false));
+        }
+        self.half_resolution.as_mut()
+    }
+
+    /// Motion blur, built disabled the first time it's asked for.
+    #[cfg(feature = "post-processing")]
+    fn motion_blur(&mut self) -> &mut MotionBlur {
+        self.motion_blur.get_or_insert_with(|| {
+            MotionBlur::new(
+                &self.device,
+                self.scene_format,
+                &self.camera_bind_group_layout,
+                &self.viewport.camera.depth,
+                None,
+            )
+        })
+    }
+
+    /// Temporal accumulation, built disabled the first time it's asked for, none if the GPU can't
+    /// run it.
+    #[cfg(feature = "post-processing")]
+    fn temporal(&mut self) -> Option<&mut TemporalAccumulation> {
+        if self.temporal.is_none() && loads_depth(&self.capabilities, "Temporal accumulation") {
+            self.temporal = Some(TemporalAccumulation::new(
+                &self.device,
+                self.scene_format,
+                &self.camera_bind_group_layout,
+                false,
+            ));
+        }
+        self.temporal.as_mut()
+    }
+
+    /// Picks up the refresh rate of the monitor the window is currently on.
+    pub fn update_monitor(&mut self) {
+        if self.pacer.set_monitor(
+            self.viewport
+                .window
+                .as_ref()
+                .and_then(Window::current_monitor),
+        ) {
+            match self.pacer.refresh_rate() {
+                Some(rate) => log::info!("Monitor refresh rate: {rate}Hz"),
+                None => log::warn!("Unknown monitor refresh rate, frames won't be paced"),
+            }
+        }
+    }
+
+    #[cfg(feature = "gamepad")]
+    pub fn set_gamepad(&mut self, axes: GamepadAxes) {
+        self.input_state.set_gamepad(axes);
+    }
+
+    /// Runs the compute passes with `workgroup_size` invocations per workgroup from the next
+    /// frame, see --workgroup-size.
+    pub fn set_workgroup_size(&mut self, workgroup_size: u32) {
+        log::info!("Workgroup size: {workgroup_size}");
+        self.workgroup_size = workgroup_size;
+        if let Some(compute_pipeline) = &mut self.compute_pipeline {
+            compute_pipeline.set_workgroup_size(&self.device, workgroup_size);
+        }
+        for cell in &mut self.grid_cells {
+            cell.compute_pipeline
+                .set_workgroup_size(&self.device, workgroup_size);
+        }
+    }
+
+    /// True once all the frames asked for with `--record` have been written, or writing one
+    /// failed.
+    pub fn recording_done(&self) -> bool {
+        self.recording_failed || self.recorder.as_ref().is_some_and(Recorder::is_done)
+    }
+
+    /// True if the recording stopped before all its frames were written.
+    pub fn recording_failed(&self) -> bool {
+        self.recording_failed
+    }
+
+    /// Waits for the work submitted to the GPU and finishes every output, so recordings,
+    /// trajectories and point caches aren't left truncated. Must be the last call before exiting.
+    pub fn shutdown(&mut self) {
+        log::info!("Shutting down");
+        // Nothing is presented to the extra windows anymore
+        self.extra_viewports.clear();
+        self.device.poll(wgpu::Maintain::Wait);
+
+        if let Some((mut tracker, mut writer)) = self.tracker.take() {
+            if let Some(readback) = tracker.try_take(&self.device) {
+                if let Err(e) = writer.write(&readback) {
+                    log::error!("Unable to write trajectories: {e}");
+                }
+            }
+        }
+        if let Some(point_cache) = self.point_cache.take() {
+            match point_cache.finish(&self.device) {
+                Ok(samples) => log::info!("Point cache closed after {samples} samples"),
+                Err(e) => log::error!("Unable to finish the point cache: {e}"),
+            }
+        }
+        if let Some(mut recorder) = self.recorder.take() {
+            let (written, frames) = recorder.progress();
+            if written < frames {
+                log::warn!("Recording stopped after {written} of {frames} frames");
+            }
+            if let Err(e) = recorder.finish() {
+                log::error!("Unable to finish the recording: {e}");
+            }
+        }
+        if self.frame_log.is_some() {
+            self.write_frame_log();
+        }
+        #[cfg(feature = "asset-loaders")]
+        if let Some(path) = self.export.take() {
+            self.export_particles(&path);
+        }
+        // Flushes the CSV
+        #[cfg(feature = "metrics")]
+        {
+            self.metrics = None;
+        }
+        self.sampler = None;
+        self.checkpoint = None;
+        self.spawn_readback = None;
+    }
+
+    /// True once input ended a screensaver started by Windows.
+    pub fn screensaver_dismissed(&self) -> bool {
+        self.screensaver
+            .as_ref()
+            .is_some_and(Screensaver::dismissed)
+    }
+
+    pub fn size(&self) -> &winit::dpi::PhysicalSize<u32> {
+        &self.viewport.size
+    }
+
+    pub fn input(&mut self, window_id: WindowId, event: &winit::event::WindowEvent) -> bool {
+        if !self.viewport.has_window(window_id) {
+            return self.extra_window_input(window_id, event);
+        }
+        self.main_window_input(event)
+    }
+
+    /// Feeds `event` replayed from a recording to the main window, or to the headless viewport.
+    pub fn replay_input(&mut self, event: &winit::event::WindowEvent) -> bool {
+        self.main_window_input(event)
+    }
+
+    fn main_window_input(&mut self, event: &winit::event::WindowEvent) -> bool {
+        // Before the camera, so dragging a slider doesn't move it
+        #[cfg(feature = "ui")]
+        if let Some(ui) = &mut self.ui {
+            if ui.input(event) {
+                return true;
+            }
+        }
+
+        if let Some(screensaver) = &mut self.screensaver {
+            if screensaver.input(event, &mut self.viewport.camera) {
+                self.viewport.zoom = ZoomController::default();
+                return true;
+            }
+        }
+
+        if let WindowEvent::MouseWheel { delta, .. } = event {
+            self.viewport.scroll(delta, self.cursor_position);
+            return true;
+        }
+
+        if self.viewport.gesture(event) {
+            return true;
+        }
+
+        if let WindowEvent::ModifiersChanged(modifiers) = event {
+            self.modifiers = *modifiers;
+        }
+
+        if let WindowEvent::Focused(false) = event {
+            self.input_state.release_keys();
+            self.panning = false;
+        }
+
+        if let WindowEvent::CursorMoved { position, .. } = event {
+            if let (true, Some(previous)) = (self.panning, self.cursor_position) {
+                let drag = glam::DVec2::new(position.x - previous.x, position.y - previous.y);
+                self.viewport.pan(drag.as_vec2());
+            }
+            self.cursor_position = Some(*position);
+        }
+
+        if let WindowEvent::MouseInput {
+            state,
+            button: MouseButton::Middle,
+            ..
+        } = event
+        {
+            self.panning = *state == ElementState::Pressed;
+            return true;
+        }
+
+        if let WindowEvent::DroppedFile(path) = event {
+            self.add_grid_cell(path);
+            return true;
+        }
+
+        if self.inspector.as_ref().is_some_and(Inspector::typing) && self.type_inspected(event) {
+            return true;
+        }
+
+        #[cfg(feature = "post-processing")]
+        if let WindowEvent::MouseInput {
+            state: ElementState::Pressed,
+            button: MouseButton::Left,
+            ..
+        } = event
+        {
+            self.focus_at_cursor();
+            return true;
+        }
+
+        if let WindowEvent::MouseInput {
+            state: ElementState::Pressed,
+            button: MouseButton::Right,
+            ..
+        } = event
+        {
+            self.inspect_at_cursor();
+            return true;
+        }
+
+        if let WindowEvent::KeyboardInput { input, .. } = event {
+            let Some(action) = input
+                .virtual_keycode
+                .and_then(|key| self.keymap.action(key, self.modifiers))
+            else {
+                return false;
+            };
+            let pressed = input.state == ElementState::Pressed;
+            if self.input_state.action(action, pressed) {
+                return true;
+            }
+            if let Action::CameraPreset(slot) = action {
+                if pressed {
+                    Self::camera_preset(
+                        &mut self.camera_presets,
+                        self.modifiers,
+                        slot,
+                        &mut self.viewport,
+                    );
+                }
+                return true;
+            }
+            if let Action::ToggleGroup(group) = action {
+                if pressed {
+                    self.viewport.toggle_group(group);
+                }
+                return true;
+            }
+            if pressed {
+                self.act(action);
+            }
+        }
+        false
+    }
+
+    /// Performs `action` on a key press. Held actions, camera presets and group toggles are
+    /// handled by the caller.
+    fn act(&mut self, action: Action) {
+        match action {
+            Action::TurbulenceFrequencyDown | Action::TurbulenceFrequencyUp => {
+                let factor = if action == Action::TurbulenceFrequencyUp {
+                    1.25
+                } else {
+                    0.8
+                };
+                self.turbulence.frequency *= factor;
+                log::info!("Turbulence frequency: {}", self.turbulence.frequency);
+            }
+            Action::ParticleCountDown | Action::ParticleCountUp => {
+                let factor = if action == Action::ParticleCountUp {
+                    COUNT_LIMIT_STEP
+                } else {
+                    1.0 / COUNT_LIMIT_STEP
+                };
+                let count = (self.active_count() as f32 * factor).round() as usize;
+                self.set_count_limit(count.max(1));
+            }
+            Action::TurbulenceAmplitudeDown | Action::TurbulenceAmplitudeUp => {
+                let step = if action == Action::TurbulenceAmplitudeUp {
+                    TURBULENCE_AMPLITUDE_STEP
+                } else {
+                    -TURBULENCE_AMPLITUDE_STEP
+                };
+                self.turbulence.amplitude = (self.turbulence.amplitude + step).max(0.0);
+                log::info!("Turbulence amplitude: {}", self.turbulence.amplitude);
+            }
+            Action::ResetParticles => self.reset_particles(),
+            Action::SimulateOnCpu => self.simulate_on_cpu(),
+            Action::DeleteBlock => self.delete_random_block(),
+            Action::NewWindow => {
+                if self.grid_cells.is_empty() {
+                    self.window_requested = true;
+                } else {
+                    log::warn!("Extra windows aren't supported in the grid view");
+                }
+            }
+            Action::CloseCell => self.close_cell_at_cursor(),
+            Action::SpeedDown | Action::SpeedUp => {
+                let factor = if action == Action::SpeedUp { 1.25 } else { 0.8 };
+                self.sim_params.speed_multiplier *= factor;
+                log::info!("Speed multiplier: {}", self.sim_params.speed_multiplier);
+            }
+            Action::StepDown | Action::StepUp => {
+                let factor = if action == Action::StepUp { 2.0 } else { 0.5 };
+                self.sim_params.dt *= factor;
+                log::info!("Simulation step: {} frames", self.sim_params.dt);
+            }
+            Action::TimeScaleDown | Action::TimeScaleUp => {
+                let factor = if action == Action::TimeScaleUp {
+                    TIME_SCALE_STEP
+                } else {
+                    1.0 / TIME_SCALE_STEP
+                };
+                self.set_time_scale(self.time_scale * factor);
+            }
+            Action::DampingDown | Action::DampingUp => {
+                let step = if action == Action::DampingUp {
+                    DAMPING_STEP
+                } else {
+                    -DAMPING_STEP
+                };
+                self.sim_params.damping = (self.sim_params.damping + step).clamp(0.0, 1.0);
+                log::info!("Damping: {}", self.sim_params.damping);
+            }
+            Action::AttractorDown | Action::AttractorUp => {
+                let step = if action == Action::AttractorUp {
+                    ATTRACTOR_STRENGTH_STEP
+                } else {
+                    -ATTRACTOR_STRENGTH_STEP
+                };
+                self.sim_params.attractor_strength += step;
+                log::info!("Attractor strength: {}", self.sim_params.attractor_strength);
+            }
+            Action::Explode | Action::Implode => {
+                let strength = if action == Action::Explode {
+                    IMPULSE_STRENGTH
+                } else {
+                    -IMPULSE_STRENGTH
+                };
+                let center = self.viewport.cursor_point(self.cursor_position);
+                self.pending_impulse = Some(center.extend(strength));
+                self.sim_params.impulse_radius = IMPULSE_RADIUS;
+                log::info!("{action:?} at {center}");
+            }
+            Action::ToggleBounds => {
+                self.sim_params.toggle_bounds();
+                log::info!("Bounds: {}", self.sim_params.bounded());
+            }
+            Action::ToggleExplore => {
+                self.explorer.toggle();
+                if self.explorer.enabled() {
+                    log::info!("Explore mode enabled");
+                    self.show_explored_params();
+                } else {
+                    log::info!("Explore mode disabled");
+                    self.title_detail = None;
+                    self.refresh_title();
+                }
+            }
+            Action::Bookmark => match self.explorer.bookmark(&self.turbulence, &self.sim_params) {
+                Ok(()) => log::info!(
+                    "Bookmarked {}",
+                    explore::describe(&self.turbulence, &self.sim_params)
+                ),
+                Err(e) => log::error!("Unable to save bookmark: {e}"),
+            },
+            Action::ToggleTrails => self.toggle_trails(),
+            Action::Pause => {
+                self.paused = !self.paused;
+                log::info!(
+                    "Simulation {}",
+                    if self.paused { "paused" } else { "resumed" }
+                );
+            }
+            Action::ToggleBlendMode => self.set_blend_mode(match self.blend_mode {
+                BlendMode::Alpha => BlendMode::Additive,
+                BlendMode::Additive => BlendMode::Alpha,
+            }),
+            Action::ToggleColorsByAge => self.toggle_colors_by_age(),
+            #[cfg(feature = "ui")]
+            Action::ToggleUi => self.toggle_ui(),
+            Action::ToggleLod => self.toggle_lod(),
+            Action::EyeSeparationDown | Action::EyeSeparationUp => {
+                let factor = if action == Action::EyeSeparationUp {
+                    1.25
+                } else {
+                    0.8
+                };
+                self.stereo.eye_separation *= factor;
+                log::info!("Stereo eye separation: {}", self.stereo.eye_separation);
+            }
+            Action::ConvergenceDown | Action::ConvergenceUp => {
+                let factor = if action == Action::ConvergenceUp {
+                    1.25
+                } else {
+                    0.8
+                };
+                self.stereo.convergence *= factor;
+                log::info!("Stereo convergence: {}", self.stereo.convergence);
+            }
+            Action::StereoSideBySide | Action::StereoAnaglyph => {
+                let mode = if action == Action::StereoSideBySide {
+                    StereoMode::SideBySide
+                } else {
+                    StereoMode::Anaglyph
+                };
+                let timestamp = std::time::SystemTime::now()
+                    .duration_since(std::time::UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_secs();
+                let path = format!("stereo-{mode}-{timestamp}.png");
+                let (width, height) = STEREO_EYE_SIZE;
+                match self.capture_stereo(mode, width, height, &path) {
+                    Ok(()) => log::info!("Saved {mode} stereo capture to {path}"),
+                    Err(e) => log::error!("{e}"),
+                }
+            }
+            Action::ToggleFullscreen => {
+                if let Some(window) = &self.viewport.window {
+                    toggle_fullscreen(window);
+                }
+            }
+            Action::TogglePacing => {
+                let enabled = !self.pacer.enabled();
+                self.pacer.set_enabled(enabled);
+                match (enabled, self.pacer.refresh_rate()) {
+                    (false, _) => log::info!("Frame pacing disabled"),
+                    (true, Some(rate)) => log::info!("Frame pacing at {rate}Hz"),
+                    (true, None) => {
+                        log::warn!("Unknown monitor refresh rate, frames won't be paced")
+                    }
+                }
+            }
+            #[cfg(feature = "post-processing")]
+            Action::ToggleDepthOfField => {
+                if let Some(depth_of_field) = self.depth_of_field() {
+                    depth_of_field.toggle();
+                    log::info!("Depth of field: {}", depth_of_field.enabled());
+                }
+            }
+            #[cfg(feature = "post-processing")]
+            Action::ToggleHalfResolution => {
+                if let Some(half_resolution) = self.half_resolution() {
+                    half_resolution.toggle();
+                    log::info!("Half resolution particles: {}", half_resolution.enabled());
+                }
+            }
+            #[cfg(feature = "post-processing")]
+            Action::ToggleMotionBlur => {
+                let motion_blur = self.motion_blur();
+                motion_blur.toggle();
+                log::info!("Motion blur: {}", motion_blur.enabled());
+            }
+            #[cfg(feature = "post-processing")]
+            Action::ToggleTemporal => {
+                if let Some(temporal) = self.temporal() {
+                    temporal.toggle();
+                    log::info!("Temporal accumulation: {}", temporal.enabled());
+                }
+            }
+            Action::WriteFrameLog => self.write_frame_log(),
+            Action::ToggleOverlap => self.toggle_overlap(),
+            Action::ToggleVolume => self.toggle_volume(),
+            Action::CycleStereoView => self.cycle_stereo_view(),
+            Action::ToggleParticleStats => self.toggle_reduction(),
+            Action::FrameParticles => self.frame_particles(),
+            #[cfg(feature = "post-processing")]
+            Action::ToggleCheckerboard => {
+                self.checkerboard.toggle();
+                log::info!("Checkerboard rendering: {}", self.checkerboard.enabled());
+            }
+            #[cfg(feature = "post-processing")]
+            Action::ToggleObstacleView => self.toggle_obstacle_view(),
+            #[cfg(feature = "post-processing")]
+            Action::ToggleColorGrading => {
+                self.color_grading.toggle();
+                log::info!("Color grading: {}", self.color_grading.enabled());
+            }
+            #[cfg(feature = "post-processing")]
+            Action::ApertureDown | Action::ApertureUp => {
+                let factor = if action == Action::ApertureUp {
+                    1.25
+                } else {
+                    0.8
+                };
+                if let Some(depth_of_field) = self.depth_of_field() {
+                    depth_of_field.scale_aperture(factor);
+                    log::info!("Depth of field aperture: {}", depth_of_field.aperture());
+                }
+            }
+            // Types the index of a particle to inspect, or closes the inspector while typing one
+            Action::Inspect => {
+                match &mut self.inspector {
+                    Some(inspector) if inspector.typing() => self.inspector = None,
+                    Some(inspector) => inspector.start_typing(),
+                    None => self.inspector = Some(Inspector::new(None)),
+                }
+                self.show_inspector();
+            }
+            Action::ToggleSpatialHash => self.toggle_spatial_hash(),
+            Action::ToggleCompaction => self.toggle_compaction(),
+            Action::ToggleStretched => self.toggle_stretched(ParticleShape::Stretched),
+            Action::ToggleSplats => self.toggle_stretched(ParticleShape::Splat),
+            Action::ToggleSpinning => self.toggle_spinning(),
+            Action::ToggleGizmos => self.toggle_gizmos(),
+            Action::CycleDebugView => {
+                self.debug_view = self.debug_pipelines.next(self.debug_view);
+                log::info!("Debug view: {}", self.debug_view);
+            }
+            Action::RenderResolutionDown | Action::RenderResolutionUp => {
+                let forward = action == Action::RenderResolutionUp;
+                self.render_target.cycle(&self.device, forward);
+                log::info!("Render resolution: {}", self.render_target.resolution());
+            }
+            Action::Capture => {
+                let timestamp = std::time::SystemTime::now()
+                    .duration_since(std::time::UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_secs();
+                let path = format!("capture-{timestamp}.png");
+                let (width, height) = CAPTURE_SIZE;
+                match self.capture_high_res(width, height, &path) {
+                    Ok(()) => log::info!("Saved {width}x{height} capture to {path}"),
+                    Err(e) => log::error!("{e}"),
+                }
+            }
+            Action::CapturePanorama => {
+                let timestamp = std::time::SystemTime::now()
+                    .duration_since(std::time::UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_secs();
+                let path = format!("panorama-{timestamp}.png");
+                let (width, height) = PANORAMA_SIZE;
+                match self.capture_panorama(width, height, &path) {
+                    Ok(()) => log::info!("Saved {width}x{height} panorama to {path}"),
+                    Err(e) => log::error!("{e}"),
+                }
+            }
+            Action::MoveForward
+            | Action::MoveBack
+            | Action::MoveLeft
+            | Action::MoveRight
+            | Action::LookUp
+            | Action::LookDown
+            | Action::LookLeft
+            | Action::LookRight
+            | Action::Boost
+            | Action::CameraPreset(_)
+            | Action::ToggleGroup(_) => {}
+        }
+    }
+
+    /// Moves the particles with rayon from now on, instead of the compute kernels.
+    fn simulate_on_cpu(&mut self) {
+        // The CPU path only uploads what it changes, so it has to start from what the GPU
+        // currently holds
+        self.read_back_positions();
+        self.compute_pipeline = None;
+        self.boids = None;
+        self.nbody = None;
+        self.collisions = None;
+        if self.overlap.take().is_some() {
+            log::info!("Overlapped rendering disabled");
+        }
+        if self.reduction.take().is_some() {
+            log::info!("Particle stats disabled");
+        }
+    }
+
+    /// Encodes the copies of the particles read back after the draws.
+    fn encode_readbacks(&mut self, encoder: &mut wgpu::CommandEncoder) {
+        let mut after_draws = FrameGraph::<State>::new();
+        // After the draws, for the next frame
+        after_draws.pass(
+            "snapshot",
+            &[
+                Resource::Positions,
+                #[cfg(feature = "packed-instances")]
+                Resource::PackedPositions,
+            ],
+            &[Resource::Snapshot],
+            |state, encoder, _| {
+                if let Some(overlap) = &mut state.overlap {
+                    overlap.copy(
+                        encoder,
+                        &state.position_buffer,
+                        state.drawn_position_buffer.as_ref(),
+                    );
+                }
+            },
+        );
+        after_draws.pass(
+            "inspector",
+            &[Resource::Positions, Resource::Colors, Resource::CpuData],
+            &[Resource::Readbacks],
+            |state, encoder, _| {
+                if let Some(inspector) = &mut state.inspector {
+                    inspector.copy(
+                        &state.device,
+                        encoder,
+                        &state.position_buffer,
+                        &state.color_buffer,
+                        state.compute_pipeline.as_ref().map(|compute_pipeline| {
+                            (
+                                &compute_pipeline.cpu_data_buffer,
+                                compute_pipeline.speed_layout,
+                            )
+                        }),
+                    );
+                }
+            },
+        );
+        after_draws.pass(
+            "reduction",
+            &[Resource::Positions, Resource::CpuData],
+            &[Resource::Readbacks],
+            |state, encoder, _| {
+                let active_end = state.active_ranges().last().map_or(0, |range| range.end);
+                let cpu_data_buffer = state
+                    .compute_pipeline
+                    .as_ref()
+                    .map(|compute_pipeline| &compute_pipeline.cpu_data_buffer);
+                if let (Some(reduction), Some(cpu_data_buffer)) =
+                    (&mut state.reduction, cpu_data_buffer)
+                {
+                    reduction.encode(
+                        &state.device,
+                        &state.queue,
+                        encoder,
+                        &state.position_buffer,
+                        cpu_data_buffer,
+                        active_end,
+                    );
+                }
+            },
+        );
+        after_draws.pass(
+            "sampler",
+            &[Resource::Positions, Resource::Colors, Resource::CpuData],
+            &[Resource::Readbacks],
+            |state, encoder, _| {
+                if let Some(sampler) = &mut state.sampler {
+                    sampler.copy(
+                        &state.device,
+                        encoder,
+                        &state.position_buffer,
+                        &state.color_buffer,
+                        state.compute_pipeline.as_ref().map(|compute_pipeline| {
+                            (
+                                &compute_pipeline.cpu_data_buffer,
+                                compute_pipeline.speed_layout,
+                            )
+                        }),
+                    );
+                }
+            },
+        );
+        after_draws.pass(
+            "tracker",
+            &[Resource::Positions, Resource::Colors, Resource::CpuData],
+            &[Resource::Readbacks],
+            |state, encoder, _| {
+                if let Some((tracker, _)) = &mut state.tracker {
+                    tracker.copy(
+                        &state.device,
+                        encoder,
+                        &state.position_buffer,
+                        &state.color_buffer,
+                        state.compute_pipeline.as_ref().map(|compute_pipeline| {
+                            (
+                                &compute_pipeline.cpu_data_buffer,
+                                compute_pipeline.speed_layout,
+                            )
+                        }),
+                    );
+                }
+            },
+        );
+        after_draws.pass(
+            "point cache",
+            &[Resource::Positions],
+            &[Resource::Readbacks],
+            |state, encoder, _| {
+                if let Some(point_cache) = &mut state.point_cache {
+                    point_cache.copy(&state.device, encoder, &state.position_buffer);
+                }
+            },
+        );
+        let transients = after_draws.allocate(&self.device, &mut self.transient_textures);
+        after_draws.execute(self, encoder, &transients);
+    }
+
+    /// Maps what [`State::encode_readbacks`] copied once submitted, and handles whatever was read
+    /// back by now.
+    fn take_readbacks(&mut self) {
+        if let Some(inspector) = &mut self.inspector {
+            inspector.map();
+            if inspector.try_take(&self.device) {
+                self.show_inspector();
+            }
+        }
+        if let Some(reduction) = &mut self.reduction {
+            reduction.map();
+            reduction.try_take(&self.device);
+        }
+        self.update_framing();
+        if let Some(sampler) = &mut self.sampler {
+            sampler.map();
+            if let Some(readback) = sampler.try_take(&self.device) {
+                log_sample(&readback);
+            }
+        }
+        if let Some((tracker, writer)) = &mut self.tracker {
+            tracker.map();
+            if let Some(readback) = tracker.try_take(&self.device) {
+                if let Err(e) = writer.write(&readback) {
+                    log::error!("Unable to write trajectories: {e}");
+                    self.tracker = None;
+                }
+            }
+        }
+        if let Some(point_cache) = &mut self.point_cache {
+            point_cache.map();
+            if let Err(e) = point_cache.write_ready(&self.device) {
+                log::error!("Unable to write the point cache: {e}");
+                self.point_cache = None;
+            }
+        }
+    }
+
+    /// Moves the cameras of every window by `dt`, along the screensaver, the flight or the camera
+    /// path.
+    fn update_cameras(&mut self, dt: f32) {
+        if let Some(screensaver) = &mut self.screensaver {
+            if screensaver.update(dt, &mut self.viewport.camera, &self.camera_presets) {
+                self.viewport.zoom = ZoomController::default();
+            }
+        }
+        if self.input_state.fly(&mut self.viewport.camera, dt) {
+            self.viewport.zoom = ZoomController::default();
+        }
+        if let Some(camera_path) = &mut self.camera_path {
+            camera_path.update(dt, &mut self.viewport.camera);
+            self.viewport.zoom = ZoomController::default();
+            if camera_path.finished() {
+                self.camera_path = None;
+            }
+        }
+        self.viewport.update_camera(&self.queue, dt);
+        for viewport in &mut self.extra_viewports {
+            viewport.update_camera(&self.queue, dt);
+        }
+    }
+
+    /// Time spent moving the particles on the CPU last frame, None when they move on the GPU.
+    pub fn cpu_simulation_time(&self) -> Option<std::time::Duration> {
+        self.cpu_simulation_time
+    }
+
+    /// GPU time of the passes of a frame read back during the last one, with the `metrics` feature.
+    pub fn gpu_frame_time(&self) -> Option<GpuFrameTime> {
+        #[cfg(feature = "metrics")]
+        return self.frame_pass_times.map(|times| GpuFrameTime {
+            busy: std::time::Duration::from_secs_f64(
+                (times.compute_ms + times.render_ms + times.neighbors_ms) / 1000.0,
+            ),
+            span: std::time::Duration::from_secs_f64(times.span_ms / 1000.0),
+        });
+        #[cfg(not(feature = "metrics"))]
+        None
+    }
+
+    /// Draws the particles from a snapshot of their positions while `enabled`, see [`RenderSnapshot`].
+    pub fn set_overlap(&mut self, enabled: bool) {
+        if self.overlap.is_some() != enabled {
+            self.toggle_overlap();
+        }
+    }
+
+    /// Handles `event` while the index of the inspected particle is typed. Returns true if it was
+    /// used, keys otherwise trigger their usual action.
+    fn type_inspected(&mut self, event: &WindowEvent) -> bool {
+        let end = self.arena.end();
+        let Some(inspector) = &mut self.inspector else {
+            return false;
+        };
+        match event {
+            WindowEvent::ReceivedCharacter(c) => inspector.type_char(*c),
+            WindowEvent::KeyboardInput { input, .. } => {
+                match (input.state, input.virtual_keycode) {
+                    (ElementState::Pressed, Some(VirtualKeyCode::Back)) => inspector.backspace(),
+                    (
+                        ElementState::Pressed,
+                        Some(VirtualKeyCode::Return | VirtualKeyCode::NumpadEnter),
+                    ) => inspector.confirm(end),
+                    (ElementState::Pressed, Some(VirtualKeyCode::Escape)) => self.inspector = None,
+                    // Closes the inspector as usual
+                    (_, Some(key))
+                        if self.keymap.action(key, self.modifiers) == Some(Action::Inspect) =>
+                    {
+                        return false
+                    }
+                    _ => {}
+                }
+            }
+            _ => return false,
+        }
+        self.show_inspector();
+        true
+    }
+
+    /// Inspects the particle closest to the mouse cursor on screen.
+    fn inspect_at_cursor(&mut self) {
+        let Some(cursor) = self.cursor_position else {
+            return;
+        };
+        if !self.grid_cells.is_empty() {
+            return;
+        }
+        let ranges = self.active_ranges();
+        let picked = self.picker.pick(
+            &self.device,
+            &self.queue,
+            &ParticleBuffers {
+                camera_bind_group: &self.viewport.camera_bind_group,
+                vertex_buffer: &self.vertex_buffer,
+                index_buffer: &self.index_buffer,
+                index_count: self.index_count,
+                position_buffer: match &self.overlap {
+                    Some(overlap) => overlap.buffer(),
+                    None => &self.position_buffer,
+                },
+            },
+            &ranges,
+            (self.viewport.size.width, self.viewport.size.height),
+            (cursor.x as u32, cursor.y as u32),
+        );
+
+        let Some(index) = picked else {
+            log::info!("No particle under the cursor");
+            return;
+        };
+        log::info!("Picked particle #{index}");
+        self.inspector
+            .get_or_insert_with(|| Inspector::new(Some(index)))
+            .select(index);
+        self.show_inspector();
+    }
+
+    fn show_inspector(&mut self) {
+        self.title_detail = self.inspector.as_ref().map(Inspector::describe);
+        self.refresh_title();
+    }
+
+    fn show_explored_params(&mut self) {
+        let params = explore::describe(&self.turbulence, &self.sim_params);
+        self.title_detail = Some(format!("explore | {params}"));
+        self.refresh_title();
+    }
+
+    /// Runs the simulation `time_scale` times as fast as real time, within
+    /// [`MIN_TIME_SCALE`, `MAX_TIME_SCALE`].
+    fn set_time_scale(&mut self, time_scale: f32) {
+        self.time_scale = time_scale.clamp(MIN_TIME_SCALE, MAX_TIME_SCALE);
+        log::info!("Time scale: {}", self.time_scale);
+        self.refresh_title();
+    }
+
+    fn refresh_title(&self) {
+        let mut title = WINDOW_TITLE.to_string();
+        let particle_stats = self
+            .reduction
+            .as_ref()
+            .and_then(Reduction::latest)
+            .map(|stats| stats.to_string());
+        // Shown while the simulation runs slower or faster than real time
+        let time_scale = (self.time_scale != 1.0).then(|| format!("{}x time", self.time_scale));
+        for part in [
+            self.frame_stats.describe(),
+            particle_stats,
+            time_scale,
+            self.title_detail.clone(),
+        ]
+        .into_iter()
+        .flatten()
+        {
+            title += " | ";
+            title += &part;
+        }
+        if let Some(window) = &self.viewport.window {
+            window.set_title(&title);
+        }
+    }
+
+    /// Jumps to the camera preset in `slot`, or stores the camera of `viewport` there with Ctrl
+    /// held.
+    fn camera_preset(
+        presets: &mut CameraPresets,
+        modifiers: ModifiersState,
+        slot: usize,
+        viewport: &mut Viewport,
+    ) {
+        if modifiers.ctrl() {
+            match presets.store(slot, &viewport.camera) {
+                Ok(()) => log::info!("Stored camera preset {}", slot + 1),
+                Err(e) => log::error!("Unable to save camera presets: {e}"),
+            }
+        } else if presets.apply(slot, &mut viewport.camera) {
+            viewport.zoom = ZoomController::default();
+            log::info!("Camera preset {}", slot + 1);
+        } else {
+            log::warn!(
+                "No camera preset {}, store one with Ctrl+{}",
+                slot + 1,
+                slot + 1
+            );
+        }
+    }
+
+    /// Writes the live particles to `path`, as simulated on the GPU.
+    #[cfg(feature = "asset-loaders")]
+    fn export_particles(&mut self, path: &Path) {
+        if !self.grid_cells.is_empty() {
+            log::warn!("Exporting isn't supported in the grid view");
+            return;
+        }
+        self.read_back_positions();
+        let particles = self
+            .arena
+            .live_ranges()
+            .iter()
+            .flat_map(|range| range.clone())
+            .map(|index| {
+                (
+                    self.instances[index].position,
+                    self.instances[index].color,
+                    self.instances_cpu_data[index].speed,
+                )
+            })
+            .collect::<Vec<_>>();
+        if let Err(e) = particle_data::export(path, particles.into_iter()) {
+            log::error!("{e}");
+        }
+    }
+
+    /// Reads the positions, and speeds the compute kernel may have changed, back from the GPU.
+    fn read_back_positions(&mut self) {
+        if self.instance_positions.is_empty() {
+            return;
+        }
+        // Colors aren't read back below
+        self.take_spawn_readback(true);
+
+        self.instance_positions = Self::read_back_buffer(
+            &self.device,
+            &self.queue,
+            &self.position_buffer,
+            self.instance_positions.len(),
+        );
+        if let Some(compute_pipeline) = &self.compute_pipeline {
+            compute_pipeline.read_cpu_data(&self.device, &self.queue, &mut self.instances_cpu_data);
+        }
+
+        for (instance, raw) in self.instances.iter_mut().zip(&self.instance_positions) {
+            instance.position = raw.position.xyz();
+        }
+    }
+
+    /// Copies the first `len` elements of `buffer` back to the CPU.
+    pub fn read_back_buffer<T: Pod + Send>(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        buffer: &wgpu::Buffer,
+        len: usize,
+    ) -> Vec<T> {
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Compute Encoder"),
+        });
+
+        let tmp_buffer = Arc::new(device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Gang!"),
+            mapped_at_creation: false,
+            size: (std::mem::size_of::<T>() * len) as _,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+        }));
+
+        encoder.copy_buffer_to_buffer(buffer, 0, &tmp_buffer, 0, tmp_buffer.size());
+        queue.submit(Some(encoder.finish()));
+
+        let tmp_clone = tmp_buffer.clone();
+        let (sender, receiver) = futures::channel::oneshot::channel::<Vec<T>>();
+        tmp_buffer
+            .slice(..)
+            .map_async(wgpu::MapMode::Read, move |x| {
+                x.unwrap();
+                let gpu_data_bytes = tmp_clone
+                    .slice(..)
+                    .get_mapped_range()
+                    .iter()
+                    .copied()
+                    .collect::<Vec<_>>();
+                let gpu_data: &[T] = bytemuck::cast_slice(&gpu_data_bytes);
+                // The receiver is blocked on below, it can't be gone
+                let _ = sender.send(gpu_data.to_vec());
+            });
+
+        device.poll(wgpu::Maintain::Wait);
+        futures::executor::block_on(receiver).unwrap()
+    }
+
+    pub fn resize(&mut self, window_id: WindowId, new_size: winit::dpi::PhysicalSize<u32>) {
+        let scale_factor = std::iter::once(&self.viewport)
+            .chain(&self.extra_viewports)
+            .find(|viewport| viewport.has_window(window_id))
+            .and_then(|viewport| viewport.window.as_ref())
+            .map(Window::scale_factor);
+        if let Some(scale_factor) = scale_factor {
+            self.rescale(window_id, scale_factor, new_size);
+        }
+    }
+
+    /// Resizes `window_id` after its scale factor changed, or its size.
+    pub fn rescale(
+        &mut self,
+        window_id: WindowId,
+        scale_factor: f64,
+        new_size: winit::dpi::PhysicalSize<u32>,
+    ) {
+        let is_main = self.viewport.has_window(window_id);
+        let Some(viewport) = std::iter::once(&mut self.viewport)
+            .chain(&mut self.extra_viewports)
+            .find(|viewport| viewport.has_window(window_id))
+        else {
+            return;
+        };
+        let was_minimized = viewport.minimized;
+        if !viewport.rescale(&self.device, scale_factor, new_size) || !is_main {
+            return;
+        }
+        self.render_target.resize(&self.device, self.viewport.size);
+        // The time spent minimized isn't simulated in one huge step
+        if was_minimized {
+            self.last_frame = std::time::Instant::now();
+        }
+    }
+
+    /// Reconfigures the main surface at the window's current size after it failed to hand out a
+    /// frame, unless the last attempt was too recent.
+    fn recover_surface(&mut self, error: wgpu::SurfaceError) {
+        let now = std::time::Instant::now();
+        if !self.surface_recovery.should_retry(now) {
+            return;
+        }
+        if self.surface_recovery.failed(now) {
+            log::warn!("{error}, reconfiguring the surface");
+        }
+        if matches!(
+            error,
+            wgpu::SurfaceError::Lost | wgpu::SurfaceError::Outdated
+        ) {
+            if let Some((window_id, size)) =
+                (self.viewport.window.as_ref()).map(|window| (window.id(), window.inner_size()))
+            {
+                self.resize(window_id, size);
+            }
+        }
+    }
+
+    /// True while the main window is minimized or the app suspended, frames aren't rendered then.
+    pub fn minimized(&self) -> bool {
+        self.viewport.minimized || self.viewport.surface.is_none()
+    }
+
+    /// Drops the surfaces while the app is suspended, by the OS on mobile or when the machine
+    /// sleeps. Nothing is simulated until [`State::resume`].
+    pub fn suspend(&mut self) {
+        log::info!("Suspended");
+        for viewport in std::iter::once(&mut self.viewport).chain(&mut self.extra_viewports) {
+            viewport.suspend();
+        }
+    }
+
+    /// Creates the surfaces again after [`State::suspend`].
+    pub fn resume(&mut self) {
+        // Also sent once at startup, when the surfaces already exist
+        if self.viewport.surface.is_some() {
+            return;
+        }
+        log::info!("Resumed");
+        for viewport in std::iter::once(&mut self.viewport).chain(&mut self.extra_viewports) {
+            if let Err(e) = viewport.resume(&self.instance, &self.device) {
+                log::error!("Unable to create a surface for the window again: {e}");
+            }
+        }
+        // The window may have been resized in the meantime
+        self.render_target.resize(&self.device, self.viewport.size);
+        // The time spent suspended isn't simulated in one huge step
+        self.last_frame = std::time::Instant::now();
+    }
+
+    /// True if `window_id` is the main window or one of the extra windows.
+    pub fn has_window(&self, window_id: WindowId) -> bool {
+        self.viewport.has_window(window_id)
+            || self
+                .extra_viewports
+                .iter()
+                .any(|viewport| viewport.has_window(window_id))
+    }
+
+    /// Closes `window_id`. Returns true if it was the main window, which should end the app.
+    pub fn close_window(&mut self, window_id: WindowId) -> bool {
+        if self.viewport.has_window(window_id) {
+            return true;
+        }
+        self.extra_viewports
+            .retain(|viewport| !viewport.has_window(window_id));
+        false
+    }
+
+    /// True once if a new window was asked for since the last call. Windows can only be created
+    /// from the event loop, which then hands them to [`State::add_window`].
+    pub fn take_window_request(&mut self) -> bool {
+        std::mem::take(&mut self.window_requested)
+    }
+
+    /// Shows the particles in `window` too, starting from the side of the main camera.
+    pub fn add_window(&mut self, window: Window) {
+        // # Safety
+        //
+        // The surface is stored in the Viewport owning the window, like the main one.
+        let surface = match unsafe { self.instance.create_surface(&window) } {
+            Ok(surface) => surface,
+            Err(e) => {
+                log::error!("Unable to create a surface for the new window: {e}");
+                return;
+            }
+        };
+        // The pipelines are shared, so the window has to take the same format
+        let formats = surface.get_capabilities(&self.gpu_adapter).formats;
+        if !formats.contains(&self.scene_format) {
+            // Drawn straight into, so it needs the format of the scene rather than the surface's
+            log::error!("The new window can't present {:?}", self.scene_format);
+            return;
+        }
+
+        let size = window.inner_size();
+        let config = wgpu::SurfaceConfiguration {
+            width: size.width.max(1),
+            height: size.height.max(1),
+            format: self.scene_format,
+            ..self.viewport.config.clone()
+        };
+        surface.configure(&self.device, &config);
+
+        let mut camera = self.viewport.camera.clone();
+        let axis = camera.up.try_normalize().unwrap_or(glam::Vec3::Y);
+        let rotation = glam::Quat::from_axis_angle(axis, std::f32::consts::FRAC_PI_2);
+        camera.eye = camera.target + rotation * (camera.eye - camera.target);
+        camera.aspect = config.width as f32 / config.height as f32;
+        let mut camera_uniform = CameraUniform::new();
+        camera_uniform.update_view_proj(&camera);
+        let (camera_buffer, lighting_buffer) =
+            Self::create_camera_buffers(&self.device, &camera_uniform, &self.look);
+        let camera_bind_group = Self::create_camera_bind_group(
+            &self.device,
+            &self.camera_bind_group_layout,
+            &camera_buffer,
+            &lighting_buffer,
+            &self.lights_buffer,
+            &self.gradient_buffer,
+        );
+
+        self.extra_viewports.push(Viewport {
+            surface: Some(surface),
+            scale_factor: window.scale_factor(),
+            minimized: false,
+            window: Some(window),
+            config,
+            size,
+            zoom: ZoomController::default(),
+            gestures: TouchGestures::default(),
+            group_mask: GroupMask::default(),
+            camera,
+            camera_uniform,
+            camera_buffer,
+            lighting_buffer,
+            camera_bind_group,
+        });
+        log::info!("Opened window {}", self.extra_viewports.len() + 1);
+    }
+
+    /// Input of an extra window moves its own camera, other input acts on the shared simulation.
+    fn extra_window_input(&mut self, window_id: WindowId, event: &WindowEvent) -> bool {
+        let presets = &mut self.camera_presets;
+        let modifiers = self.modifiers;
+        let keymap = &self.keymap;
+        let Some(viewport) = self
+            .extra_viewports
+            .iter_mut()
+            .find(|viewport| viewport.has_window(window_id))
+        else {
+            return false;
+        };
+        match event {
+            WindowEvent::MouseWheel { delta, .. } => viewport.scroll(delta, None),
+            WindowEvent::Touch(_) | WindowEvent::TouchpadMagnify { .. } => {
+                viewport.gesture(event);
+            }
+            // Focusing and picking work in the main window
+            WindowEvent::CursorMoved { .. } | WindowEvent::MouseInput { .. } => {}
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        state,
+                        virtual_keycode: Some(key),
+                        ..
+                    },
+                ..
+            } if matches!(
+                keymap.action(*key, modifiers),
+                Some(Action::CameraPreset(_) | Action::ToggleGroup(_) | Action::ToggleFullscreen)
+            ) =>
+            {
+                if *state == ElementState::Pressed {
+                    match keymap.action(*key, modifiers) {
+                        Some(Action::CameraPreset(slot)) => {
+                            Self::camera_preset(presets, modifiers, slot, viewport)
+                        }
+                        Some(Action::ToggleGroup(group)) => viewport.toggle_group(group),
+                        _ => {
+                            if let Some(window) = &viewport.window {
+                                toggle_fullscreen(window);
+                            }
+                        }
+                    }
+                }
+            }
+            _ => return self.main_window_input(event),
+        }
+        true
+    }
+
+    fn delete_random_block(&mut self) {
+        // Defragmenting leaves only deleted instances past the live ones
+        let end = self.arena.end();
+        if end == 0 {
+            log::info!("No particles left to delete");
+            return;
+        }
+        let len = ((end as f32 * DELETE_BLOCK_FRACTION) as usize).max(1);
+        let start = rand::thread_rng().gen_range(0..end.saturating_sub(len).max(1));
+        self.arena.free(start..start + len);
+        log::info!(
+            "Deleted instances {start}..{}, {} left in {} ranges",
+            start + len,
+            self.arena.live_count(),
+            self.arena.live_ranges().len()
+        );
+
+        if self.arena.live_ranges().len() > MAX_LIVE_RANGES {
+            self.defragment_instances();
+        }
+    }
+
+    fn defragment_instances(&mut self) {
+        if self.compute_pipeline.is_some() {
+            self.read_back_positions();
+        }
+
+        // Pending checkpoints have the old layout, as do the previous positions of the motion blur
+        self.checkpoint = None;
+        #[cfg(feature = "post-processing")]
+        if let Some(motion_blur) = &mut self.motion_blur {
+            motion_blur.reset();
+        }
+        let remap = self.arena.defragment();
+        remap.apply(&mut self.instances);
+        remap.apply(&mut self.instance_positions);
+        remap.apply(&mut self.instances_cpu_data);
+
+        let instance_colors = self
+            .instances
+            .iter()
+            .map(Instance::to_color)
+            .collect::<Vec<_>>();
+        self.queue.write_buffer(
+            &self.position_buffer,
+            0,
+            bytemuck::cast_slice(&self.instance_positions),
+        );
+        self.queue.write_buffer(
+            &self.color_buffer,
+            0,
+            bytemuck::cast_slice(&instance_colors),
+        );
+        if let Some(compute_pipeline) = &self.compute_pipeline {
+            compute_pipeline.write_cpu_data(&self.queue, 0, &self.instances_cpu_data);
+        }
+
+        log::info!(
+            "Defragmented instances with {} moves, {} live",
+            remap.moves.len(),
+            remap.live_count
+        );
+    }
+
+    /// Live instance ranges, limited to the particle count picked by `--target-fps` and by the
+    /// watchdog if any.
+    fn active_ranges(&self) -> Vec<Range<usize>> {
+        match self.particle_limit() {
+            None => self.arena.live_ranges().to_vec(),
+            Some(limit) => self.arena.first_live(limit),
+        }
+    }
+
+    /// Number of instances in [`Self::active_ranges`].
+    fn active_count(&self) -> usize {
+        self.particle_limit()
+            .map_or(self.arena.live_count(), |limit| {
+                limit.min(self.arena.live_count())
+            })
+    }
+
+    /// Lowest of the adaptive count, the watchdog's limit and the count limit, if any.
+    fn particle_limit(&self) -> Option<usize> {
+        let adaptive_count = self.adaptive.as_ref().map(AdaptiveCount::active);
+        [
+            adaptive_count,
+            self.watchdog.particle_limit(),
+            self.count_limit,
+        ]
+        .into_iter()
+        .flatten()
+        .min()
+    }
+
+    /// Number of particles that can be simulated without hanging the GPU, and within the count
+    /// limit.
+    fn available_particles(&self) -> usize {
+        [self.watchdog.particle_limit(), self.count_limit]
+            .into_iter()
+            .flatten()
+            .fold(self.arena.live_count(), usize::min)
+    }
+
+    /// Simulates and draws only the first `count` live particles, or spawns the missing ones if
+    /// there are fewer. The buffers keep every particle, so the count changes on the next frame.
+    fn set_count_limit(&mut self, count: usize) {
+        if count > self.arena.live_count() {
+            self.grow_particles(count);
+        }
+        let live = self.arena.live_count();
+        self.count_limit = (count < live).then_some(count);
+        log::info!("Active particles: {} of {live}", count.min(live));
+    }
+
+    /// Spawns particles until `count` are alive, as many as the device and `--max-memory` allow.
+    /// Past the capacity, the particle buffers are replaced by larger ones the live particles are
+    /// copied to on the GPU.
+    fn grow_particles(&mut self, count: usize) {
+        if !self.grid_cells.is_empty() {
+            log::warn!("Adding particles isn't supported in the grid view");
+            return;
+        }
+        if self.point_cloud.is_some() {
+            log::warn!("The point cloud has no more points to add as particles");
+            return;
+        }
+        self.take_spawn_readback(true);
+        // The new particles go after the live ones
+        if self.arena.live_ranges().len() > 1 || self.arena.live_count() != self.instances.len() {
+            self.defragment_instances();
+        }
+        let old_count = self.instances.len();
+        let count = Self::fit_particle_count(&self.device, count, true, self.max_memory);
+        if count <= old_count {
+            return;
+        }
+
+        let seed = particle_seed(self.spawn_seed, self.deterministic);
+        let mut rng = StdRng::seed_from_u64(seed.wrapping_add(old_count as u64));
+        let (mut instances, instances_cpu_data) = Self::generate_particles(
+            count - old_count,
+            &self.spawn_emitter,
+            &self.spawn_palette,
+            None,
+            &mut rng,
+        );
+        for (index, instance) in instances.iter_mut().enumerate() {
+            instance.group = groups::of(old_count + index);
+        }
+        self.instance_positions
+            .extend(instances.iter().map(Instance::to_position));
+        self.instances.extend(instances);
+        self.instances_cpu_data.extend(instances_cpu_data);
+
+        if count <= self.arena.capacity() {
+            let new = old_count..count;
+            let colors = self.instances[new.clone()]
+                .iter()
+                .map(Instance::to_color)
+                .collect::<Vec<_>>();
+            self.queue.write_buffer(
+                &self.position_buffer,
+                (old_count * PARTICLE_SIZE) as u64,
+                bytemuck::cast_slice(&self.instance_positions[new.clone()]),
+            );
+            self.queue.write_buffer(
+                &self.color_buffer,
+                (old_count * std::mem::size_of::<InstanceColor>()) as u64,
+                bytemuck::cast_slice(&colors),
+            );
+            if let Some(compute_pipeline) = &self.compute_pipeline {
+                compute_pipeline.write_cpu_data(
+                    &self.queue,
+                    old_count,
+                    &self.instances_cpu_data[new],
+                );
+            }
+        } else {
+            self.reallocate_particles(old_count, count);
+        }
+        self.arena.add(old_count..count);
+        log::info!("Spawned {} particles, {count} live", count - old_count);
+    }
+
+    /// Replaces the particle buffers by buffers of `capacity` particles holding the CPU copies,
+    /// then copies the first `kept` particles over from the old buffers, which the GPU may have
+    /// moved since.
+    fn reallocate_particles(&mut self, kept: usize, capacity: usize) {
+        let instance_colors = self
+            .instances
+            .iter()
+            .map(Instance::to_color)
+            .collect::<Vec<_>>();
+        let (position_buffer, color_buffer, drawn_position_buffer) = Self::create_instance_buffers(
+            &self.device,
+            &self.queue,
+            capacity,
+            &self.instance_positions,
+            &instance_colors,
+        );
+        let compute_pipeline = self.compute_pipeline.as_ref().map(|_| {
+            Self::create_compute_pipeline(
+                &self.device,
+                &self.instances_cpu_data,
+                &position_buffer,
+                0,
+                drawn_position_buffer.as_ref(),
+                &self.obstacles,
+                &self.behaviors,
+                &self.spawn_emitter,
+                WindTexture::new(&self.device, &self.queue, self.wind.as_ref()),
+                self.workgroup_size,
+                SpeedLayout::of(&self.device),
+            )
+        });
+
+        // Submitted after the writes above, so the copies land on top of them
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Grow Encoder"),
+            });
+        let mut copy = |from: &wgpu::Buffer, to: &wgpu::Buffer, size: usize| {
+            encoder.copy_buffer_to_buffer(from, 0, to, 0, (kept * size) as u64);
+        };
+        copy(&self.position_buffer, &position_buffer, PARTICLE_SIZE);
+        copy(
+            &self.color_buffer,
+            &color_buffer,
+            std::mem::size_of::<InstanceColor>(),
+        );
+        if let (Some(old), Some(new)) = (&self.drawn_position_buffer, &drawn_position_buffer) {
+            copy(old, new, std::mem::size_of::<DrawnPosition>());
+        }
+        if let (Some(old), Some(new)) = (&self.compute_pipeline, &compute_pipeline) {
+            copy(
+                &old.cpu_data_buffer,
+                &new.cpu_data_buffer,
+                old.speed_layout.stride(),
+            );
+        }
+        self.queue.submit(Some(encoder.finish()));
+
+        self.position_buffer = position_buffer;
+        self.color_buffer = color_buffer;
+        self.drawn_position_buffer = drawn_position_buffer;
+        self.compute_pipeline = compute_pipeline;
+        self.dirty_instances = DirtyRanges::default();
+        // Pending checkpoints and the previous positions of the motion blur have the old size
+        self.checkpoint = None;
+        #[cfg(feature = "post-processing")]
+        if let Some(motion_blur) = &mut self.motion_blur {
+            motion_blur.reset();
+        }
+        self.boids = Self::create_boids(
+            &self.device,
+            self.sim_mode,
+            &self.boids_params,
+            self.boids_tile_size,
+            self.compute_pipeline.as_ref(),
+            &self.position_buffer,
+        );
+        self.nbody = Self::create_nbody(
+            &self.device,
+            self.sim_mode,
+            &self.nbody_params,
+            self.nbody_grid_size,
+            self.nbody_tile_size,
+            self.compute_pipeline.as_ref(),
+            &self.position_buffer,
+        );
+        self.collisions = Self::create_collisions(
+            &self.device,
+            &self.sim_params,
+            self.compute_pipeline.as_ref(),
+            &self.position_buffer,
+        );
+        self.sampler = self
+            .sample_percent
+            .map(|percent| Self::create_sampler(capacity, percent));
+        if let Some((tracker, _)) = &mut self.tracker {
+            *tracker = Self::create_tracker(&self.tracked, capacity);
+        }
+        self.rebuild_particle_passes();
+    }
+
+    /// Moves the particles on the CPU, or runs the passes the compute kernel needs first on the
+    /// GPU. The kernel itself is encoded beside the render passes, see encoding.rs.
+    fn move_particles(&mut self) {
+        // Only the active particles are simulated, the rest stay where they are
+        let active_end = self.active_ranges().last().map_or(0, |range| range.end);
+        self.sim_params.frame = self.sim_params.frame.wrapping_add(1);
+        // Applied by this step only
+        self.sim_params.impulse = self.pending_impulse.take().unwrap_or(glam::Vec4::ZERO);
+
+        if let Some(compute_pipeline) = &self.compute_pipeline {
+            self.queue.write_buffer(
+                &compute_pipeline.turbulence_buffer,
+                0,
+                bytemuck::cast_slice(&[self.turbulence]),
+            );
+            self.queue.write_buffer(
+                &compute_pipeline.sim_params_buffer,
+                0,
+                bytemuck::cast_slice(&[self.sim_params]),
+            );
+
+            if self.boids.is_some() || self.nbody.is_some() || self.collisions.is_some() {
+                let mut encoder =
+                    self.device
+                        .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                            label: Some("Neighbors Encoder"),
+                        });
+                #[cfg(feature = "metrics")]
+                if let Some(gpu_timer) = &self.gpu_timer {
+                    gpu_timer.begin(&mut encoder, Pass::Neighbors);
+                }
+                if let Some(boids) = &mut self.boids {
+                    let params = BoidsParams {
+                        dt: self.sim_params.dt,
+                        count: active_end as u32,
+                        ..self.boids_params
+                    };
+                    boids.encode(
+                        &self.device,
+                        &self.queue,
+                        &mut encoder,
+                        &params,
+                        &compute_pipeline.cpu_data_buffer,
+                    );
+                }
+                if let Some(nbody) = &self.nbody {
+                    let params = NbodyParams {
+                        dt: self.sim_params.dt,
+                        count: active_end as u32,
+                        ..self.nbody_params
+                    };
+                    nbody.encode(&self.device, &self.queue, &mut encoder, &params);
+                }
+                // After steering, so the boids collide with the speeds they steered to
+                if let Some(collisions) = &mut self.collisions {
+                    collisions.encode(
+                        &self.device,
+                        &self.queue,
+                        &mut encoder,
+                        &self.sim_params,
+                        active_end,
+                        &compute_pipeline.cpu_data_buffer,
+                    );
+                }
+                #[cfg(feature = "metrics")]
+                if let Some(gpu_timer) = &self.gpu_timer {
+                    gpu_timer.end(&mut encoder, Pass::Neighbors);
+                }
+                self.queue.submit(Some(encoder.finish()));
+            }
+
+            // let tmp = Arc::new(self.device.create_buffer(&wgpu::BufferDescriptor {
+            //     label: Some("Gang!"),
+            //     mapped_at_creation: false,
+            //     size: (std::mem::size_of::<InstancePosition>() * self.instance_positions.len()) as _,
+            //     usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            // }));
+
+            // encoder.copy_buffer_to_buffer(&self.position_buffer, 0, &tmp, 0, tmp.size());
+
+            // let tmp_clone = tmp.clone();
+            // tmp.slice(..).map_async(wgpu::MapMode::Read, move |x| {
+            //     x.unwrap();
+            //
+            //     let a = tmp_clone.slice(..).get_mapped_range().iter().copied().collect::<Vec<_>>();
+            //     let b: &[InstancePosition] = bytemuck::cast_slice(&a);
+            //     for c in b {
+            //         // println!("gpu transformed: {c}");
+            //     }
+            // });
+            //
+            // let a = self.instances
+            //     .par_iter_mut()
+            //     .zip(&self.instances_cpu_data)
+            //     .map(|(instance, cpu_data)| {
+            //         instance.position += cpu_data.speed;
+            //         instance.to_position()
+            //     })
+            //     .collect::<Vec<_>>();
+            //
+            // for b in a {
+            //     // println!("cpu transformed: {b}");
+            // }
+        } else {
+            if self.sim_mode != SimMode::Turbulence || self.sim_params.collisions() {
+                let positions = self.instances[..active_end]
+                    .par_iter()
+                    .map(|instance| instance.position)
+                    .collect::<Vec<_>>();
+                let mut speeds = self.instances_cpu_data[..active_end]
+                    .par_iter()
+                    .map(|cpu_data| cpu_data.speed)
+                    .collect::<Vec<_>>();
+                if self.sim_mode == SimMode::Boids {
+                    let params = BoidsParams {
+                        dt: self.sim_params.dt,
+                        ..self.boids_params
+                    };
+                    speeds = params.steer(&positions, &speeds);
+                }
+                if self.sim_mode == SimMode::Nbody {
+                    let params = NbodyParams {
+                        dt: self.sim_params.dt,
+                        grid_size: self.nbody_grid_size,
+                        ..self.nbody_params
+                    };
+                    speeds = params.accelerate(&positions, &speeds);
+                }
+                if self.sim_params.collisions() {
+                    speeds = collisions::collide(&self.sim_params, &positions, &speeds);
+                }
+                self.instances_cpu_data[..active_end]
+                    .par_iter_mut()
+                    .zip(speeds)
+                    .for_each(|(cpu_data, speed)| cpu_data.speed = speed);
+            }
+
+            // Move particles, keeping track of which chunks actually changed
+            let start = std::time::Instant::now();
+            let turbulence = self.turbulence;
+            let flow = Flow {
+                turbulence: &turbulence,
+                wind: self.wind.as_ref(),
+            };
+            let sim_params = self.sim_params;
+            let cpu_path = self.cpu_path;
+            let cpu_step = CpuStep {
+                sim_params: &sim_params,
+                flow: &flow,
+                obstacles: &self.obstacles,
+                behaviors: &self.behaviors,
+                emitter: &self.spawn_emitter,
+            };
+            let changed_chunks = self.instances[..active_end]
+                .par_chunks_mut(DIRTY_CHUNK_SIZE)
+                .zip(self.instance_positions[..active_end].par_chunks_mut(DIRTY_CHUNK_SIZE))
+                .zip(self.instances_cpu_data[..active_end].par_chunks_mut(DIRTY_CHUNK_SIZE))
+                .enumerate()
+                .map(
+                    |(chunk_index, ((instances, instance_positions), instances_cpu_data))| {
+                        let mut changed = false;
+                        let particles = instances
+                            .iter_mut()
+                            .zip(instance_positions.iter_mut())
+                            .zip(instances_cpu_data)
+                            .enumerate();
+                        if cpu_path == CpuPath::Simd {
+                            // The translation is stepped where it is in the instance buffer
+                            for (offset, ((instance, raw), cpu_data)) in particles {
+                                let index = chunk_index * DIRTY_CHUNK_SIZE + offset;
+                                let before = raw.position;
+                                raw.position =
+                                    cpu_step.particle_simd(index, before, &mut cpu_data.speed);
+                                if raw.position != before {
+                                    instance.position = raw.position.xyz();
+                                    changed = true;
+                                }
+                            }
+                            return changed;
+                        }
+                        for (offset, ((instance, raw), cpu_data)) in particles {
+                            let index = chunk_index * DIRTY_CHUNK_SIZE + offset;
+                            let before = raw.position;
+                            raw.position = cpu_step.particle(index, before, &mut cpu_data.speed);
+                            instance.position = raw.position.xyz();
+                            if raw.position != before {
+                                changed = true;
+                            }
+                        }
+                        changed
+                    },
+                )
+                .collect::<Vec<_>>();
+            self.cpu_simulation_time = Some(start.elapsed());
+
+            for (chunk_index, _) in changed_chunks.iter().enumerate().filter(|(_, &c)| c) {
+                let start = chunk_index * DIRTY_CHUNK_SIZE;
+                let end = (start + DIRTY_CHUNK_SIZE).min(active_end);
+                self.dirty_instances.mark(start..end);
+            }
+
+            self.upload_dirty_instances();
+        }
+    }
+
+    /// Copies the changed instances through the staging belt, submitted right away so the passes
+    /// submitted before rendering see them too.
+    fn upload_dirty_instances(&mut self) {
+        let stride = std::mem::size_of::<InstancePosition>();
+        let mut upload_stats = UploadStats::default();
+        if self.dirty_instances.is_empty() {
+            self.upload_stats = upload_stats;
+            return;
+        }
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Upload Encoder"),
+            });
+        for range in self.dirty_instances.drain() {
+            let data: &[u8] = bytemuck::cast_slice(&self.instance_positions[range.clone()]);
+            let Some(size) = wgpu::BufferSize::new(data.len() as _) else {
+                continue;
+            };
+            #[cfg(feature = "guardrails")]
+            guardrails::check_buffer_write(
+                "Position Buffer",
+                &self.position_buffer,
+                (range.start * stride) as _,
+                data.len(),
+            );
+            self.staging_belt
+                .write_buffer(
+                    &mut encoder,
+                    &self.position_buffer,
+                    (range.start * stride) as _,
+                    size,
+                    &self.device,
+                )
+                .copy_from_slice(data);
+            upload_stats.record(data.len());
+        }
+        self.staging_belt.finish();
+        self.queue.submit(Some(encoder.finish()));
+        // Reused once the GPU is done copying from them
+        self.staging_belt.recall();
+        self.upload_stats = upload_stats;
+    }
+
+    pub fn render(&mut self) -> Result<(), wgpu::SurfaceError> {
+        // Runs the callbacks of the frames the GPU finished
+        self.device.poll(wgpu::Maintain::Poll);
+        if let Some(failure) = self.watchdog.check() {
+            self.recover(failure);
+            return Ok(());
+        }
+
+        let start = std::time::Instant::now();
+        // Deterministic runs advance by the frame time of the recordings
+        let dt = if self.deterministic {
+            recording::FRAME_TIME
+        } else {
+            (start - self.last_frame).as_secs_f32()
+        };
+        self.last_frame = start;
+        let _frame = tracing::info_span!("frame", index = self.frame_count).entered();
+        let simulation = tracing::info_span!("simulation").entered();
+        self.reload_scene();
+        self.reload_appearance();
+        #[cfg(feature = "scripting")]
+        self.run_script();
+        #[cfg(feature = "metrics")]
+        self.run_telemetry_commands();
+        if let Some(schedule) = &mut self.schedule {
+            self.look = schedule.update(dt);
+            let lighting = LightingUniform::from(&self.look);
+            for viewport in std::iter::once(&self.viewport).chain(&self.extra_viewports) {
+                self.queue.write_buffer(
+                    &viewport.lighting_buffer,
+                    0,
+                    bytemuck::cast_slice(&[lighting]),
+                );
+            }
+        }
+        #[cfg(feature = "audio")]
+        self.run_audio(dt);
+        #[cfg(feature = "ui")]
+        self.run_ui();
+        // Wall-clock time for the camera and the schedule, scaled for what the simulation moves
+        let sim_dt = dt * self.time_scale;
+        if !self.paused {
+            self.turbulence.time += sim_dt;
+        }
+        gradient::write_buffer(
+            &self.queue,
+            &self.gradient_buffer,
+            self.gradient.as_ref().filter(|_| self.colors_by_age),
+            self.turbulence.time,
+        );
+        if !self.paused
+            && self
+                .explorer
+                .update(dt, &mut self.turbulence, &mut self.sim_params)
+        {
+            self.show_explored_params();
+        }
+        if !self.paused {
+            if self.grid_cells.is_empty() {
+                // Both backends step by the scaled dt, the step set with PageUp stays as it is, and
+                // so do the parameters the audio scales
+                let unscaled = self.sim_params;
+                self.sim_params.dt *= self.time_scale;
+                #[cfg(feature = "audio")]
+                self.audio_levels.modulate(&mut self.sim_params);
+                self.move_particles();
+                self.sim_params.dt = unscaled.dt;
+                self.sim_params.speed_multiplier = unscaled.speed_multiplier;
+                self.sim_params.attractor_strength = unscaled.attractor_strength;
+            } else {
+                self.move_grid_cells(sim_dt);
+            }
+        }
+        drop(simulation);
+
+        let encoding = tracing::info_span!("encoding").entered();
+        // Without a frame to draw, the particles keep moving
+        let Some(surface) = &self.viewport.surface else {
+            encoding::submit(&self.queue, self.kernel_encoder()());
+            self.finish_simulation(dt);
+            // Headless, the frame is rendered offscreen only, and read back as if presented
+            if self.viewport.window.is_none() {
+                let mut encoder =
+                    self.device
+                        .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                            label: Some("Readback Encoder"),
+                        });
+                self.encode_readbacks(&mut encoder);
+                self.update_cameras(dt);
+                self.queue.submit(Some(encoder.finish()));
+                self.watchdog.submitted(&self.queue);
+                self.take_readbacks();
+                self.render_outputs();
+                self.transient_textures.end_frame();
+            }
+            return Ok(());
+        };
+        let output = match surface.get_current_texture() {
+            Ok(output) => {
+                let failures = self.surface_recovery.succeeded();
+                if failures > 0 {
+                    log::info!("Surface back after {failures} failed attempts");
+                }
+                output
+            }
+            Err(e) => {
+                encoding::submit(&self.queue, self.kernel_encoder()());
+                self.finish_simulation(dt);
+                if e == wgpu::SurfaceError::OutOfMemory {
+                    return Err(e);
+                }
+                self.recover_surface(e);
+                return Ok(());
+            }
+        };
+        let view = output
+            .texture
+            .create_view(&wgpu::TextureViewDescriptor::default());
+
+        let mut encoders = vec![];
+
+        let mut render_encoder =
+            self.device
+                .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                    label: Some("Render Encoder"),
+                });
+
+        let mut before_draws = FrameGraph::<State>::new();
+        #[cfg(feature = "packed-instances")]
+        before_draws.pass(
+            "pack positions",
+            &[Resource::Positions],
+            &[Resource::PackedPositions],
+            |state, encoder, _| state.pack_positions(encoder),
+        );
+        before_draws.pass(
+            "snapshot",
+            &[
+                Resource::Positions,
+                #[cfg(feature = "packed-instances")]
+                Resource::PackedPositions,
+            ],
+            &[Resource::Snapshot],
+            |state, encoder, _| {
+                if let Some(overlap) = &mut state.overlap {
+                    overlap.prepare(
+                        &state.device,
+                        encoder,
+                        &state.position_buffer,
+                        state.drawn_position_buffer.as_ref(),
+                    );
+                }
+            },
+        );
+        before_draws.pass(
+            "spin",
+            &[Resource::Positions],
+            &[Resource::Spins],
+            move |state, encoder, _| {
+                let Some(spinning) = &mut state.spinning else {
+                    return;
+                };
+                spinning.resize(&state.device, &state.queue, state.arena.capacity());
+                if !state.paused {
+                    // Along with the particles, on the GPU or the CPU
+                    if state.compute_pipeline.is_some() {
+                        spinning.encode(&state.device, &state.queue, encoder, sim_dt);
+                    } else {
+                        spinning.update(&state.queue, sim_dt);
+                    }
+                }
+            },
+        );
+        before_draws.pass(
+            "trails",
+            &[Resource::Positions, Resource::Snapshot],
+            &[Resource::Trails],
+            |state, encoder, _| {
+                if let Some(trails) = &mut state.trails {
+                    let position_buffer = match &state.overlap {
+                        Some(overlap) => overlap.buffer(),
+                        None => &state.position_buffer,
+                    };
+                    trails.record(encoder, &state.queue, position_buffer);
+                }
+            },
+        );
+        #[cfg(feature = "metrics")]
+        before_draws.pass(
+            "begin timer",
+            &[],
+            &[Resource::Timestamps],
+            |state, encoder, _| {
+                let Some(gpu_timer) = &state.gpu_timer else {
+                    return;
+                };
+                // Simulating on the CPU, the compute pass takes no GPU time
+                if state.compute_pipeline.is_none() {
+                    gpu_timer.begin(encoder, Pass::Compute);
+                    gpu_timer.end(encoder, Pass::Compute);
+                }
+                if state.boids.is_none() && state.nbody.is_none() && state.collisions.is_none() {
+                    gpu_timer.begin(encoder, Pass::Neighbors);
+                    gpu_timer.end(encoder, Pass::Neighbors);
+                }
+                gpu_timer.begin(encoder, Pass::Render);
+            },
+        );
+        let transients = before_draws.allocate(&self.device, &mut self.transient_textures);
+        before_draws.execute(self, &mut render_encoder, &transients);
+
+        self.prepare_scene(self.render_target.size());
+        let encode_start = std::time::Instant::now();
+        let (submissions, extra_outputs) =
+            encoding::join(self.parallel_encoding, self.kernel_encoder(), || {
+                // The compute passes are encoded alongside, borrowing the state
+                let mut draws = FrameGraph::<()>::new();
+                let target = self.render_target.view(&view);
+                draws.pass(
+                    "scene",
+                    &[
+                        Resource::Positions,
+                        Resource::Colors,
+                        Resource::CpuData,
+                        Resource::Snapshot,
+                        #[cfg(feature = "packed-instances")]
+                        Resource::PackedPositions,
+                        Resource::Trails,
+                        Resource::Spins,
+                    ],
+                    &[Resource::Scene],
+                    |_, encoder, _| self.encode_scene(encoder, target),
+                );
+                draws.pass(
+                    "highlight",
+                    &[Resource::Positions],
+                    &[Resource::Scene],
+                    |_, encoder, _| self.encode_highlight(encoder, target),
+                );
+                draws.pass("gizmos", &[], &[Resource::Scene], |_, encoder, _| {
+                    if let Some(gizmos) = &self.gizmos {
+                        gizmos.encode(encoder, target, &self.viewport.camera_bind_group);
+                    }
+                });
+                draws.pass(
+                    "blit",
+                    &[Resource::Scene],
+                    &[Resource::Surface],
+                    |_, encoder, _| self.render_target.blit(encoder, &view),
+                );
+                #[cfg(feature = "ui")]
+                draws.pass("ui", &[], &[Resource::Surface], |_, encoder, _| {
+                    if let Some(ui) = &self.ui {
+                        ui.encode(encoder, &view, self.viewport.size);
+                    }
+                });
+                draws.execute(&mut (), &mut render_encoder, &transients);
+                self.encode_extra_windows(&mut render_encoder)
+            });
+        let encode_time = encode_start.elapsed();
+        tracing::info_span!("submit compute")
+            .in_scope(|| encoding::submit(&self.queue, submissions));
+        self.encode_time = encode_time;
+        self.finish_simulation(dt);
+
+        #[cfg(feature = "metrics")]
+        if let Some(gpu_timer) = &mut self.gpu_timer {
+            gpu_timer.end(&mut render_encoder, Pass::Render);
+            gpu_timer.resolve(&mut render_encoder);
+        }
+        self.encode_readbacks(&mut render_encoder);
+
+        self.update_cameras(dt);
+
+        encoders.push(render_encoder.finish());
+        drop(encoding);
+        tracing::info_span!("submit").in_scope(|| self.queue.submit(encoders));
+        self.watchdog.submitted(&self.queue);
+        tracing::info_span!("present").in_scope(|| {
+            output.present();
+            for extra_output in extra_outputs {
+                extra_output.present();
+            }
+        });
+        #[cfg(feature = "metrics")]
+        if let Some(gpu_timer) = &mut self.gpu_timer {
+            gpu_timer.map();
+        }
+        self.take_readbacks();
+
+        self.render_outputs();
+        self.transient_textures.end_frame();
+
+        let end = std::time::Instant::now();
+        let delta = end - start;
+        let average_frame_time_ms = self.frame_stats.record(delta);
+        if self.frame_stats.update_title(end) {
+            self.refresh_title();
+        }
+        let available = self.available_particles();
+        if let Some(adaptive) = &mut self.adaptive {
+            if adaptive.update(average_frame_time_ms, available) {
+                log::info!("Adaptive particle count: {}", adaptive.active());
+            }
+        }
+        let active_count: usize = if self.grid_cells.is_empty() {
+            self.active_ranges().iter().map(|range| range.len()).sum()
+        } else {
+            self.grid_cells.iter().map(|cell| cell.particle_count).sum()
+        };
+        if self.frame_stats.log(end) {
+            tracing::info!(
+                target: trace::FRAME_STATS,
+                frame_time_ms = average_frame_time_ms,
+                particles = active_count,
+                width = self.viewport.size.width,
+                height = self.viewport.size.height,
+                render_width = self.render_target.size().0,
+                render_height = self.render_target.size().1,
+                uploaded_kb = self.upload_stats.bytes / 1024,
+                upload_writes = self.upload_stats.writes,
+            );
+            #[cfg(feature = "metrics")]
+            if let Some(stats) = &self.pipeline_stats {
+                let (width, height) = self.render_target.size();
+                tracing::info!(
+                    target: trace::FRAME_STATS,
+                    triangles = stats.clipper_invocations,
+                    culled_percent = stats.culled() * 100.0,
+                    fragments = stats.fragment_invocations,
+                    overdraw = stats.overdraw(width as u64 * height as u64),
+                );
+            }
+        }
+        #[cfg(feature = "metrics")]
+        let pass_times = self
+            .gpu_timer
+            .as_mut()
+            .and_then(|gpu_timer| gpu_timer.try_take(&self.device));
+        if let Some(frame_log) = &mut self.frame_log {
+            #[cfg(feature = "metrics")]
+            let gpu_ms = pass_times
+                .map(|times| (times.compute_ms + times.render_ms + times.neighbors_ms) as f32);
+            #[cfg(not(feature = "metrics"))]
+            let gpu_ms = None;
+            frame_log.record(
+                end,
+                delta,
+                self.encode_time,
+                gpu_ms,
+                active_count,
+                self.overlap.is_some(),
+            );
+        }
+        #[cfg(feature = "metrics")]
+        if let Some(gpu_timer) = &mut self.gpu_timer {
+            self.pass_times = pass_times.or(self.pass_times);
+            self.frame_pass_times = pass_times;
+            self.pipeline_stats = gpu_timer
+                .try_take_statistics(&self.device)
+                .or(self.pipeline_stats);
+        }
+        #[cfg(feature = "metrics")]
+        self.publish_metrics(average_frame_time_ms, active_count, pass_times);
+        Ok(())
+    }
+
+    fn write_frame_log(&self) {
+        let Some(frame_log) = &self.frame_log else {
+            log::warn!("No frame log, pass --frame-log to record one");
+            return;
+        };
+        match frame_log.write() {
+            Ok(()) => log::info!("Wrote frame log to {}", frame_log.path().display()),
+            Err(e) => log::error!("Unable to write the frame log: {e}"),
+        }
+    }
+
+    fn create_sampler(capacity: usize, percent: f32) -> ReadbackRing {
+        let ranges = ReadbackRing::sample(capacity, percent / 100.0);
+        log::info!(
+            "Streaming {} particles back every frame",
+            ranges.iter().map(Range::len).sum::<usize>()
+        );
+        ReadbackRing::new(SAMPLE_SLOTS, ranges)
+    }
+
+    /// Reads the `tracked` particles below `capacity`, the others are skipped.
+    fn create_tracker(tracked: &[usize], capacity: usize) -> ReadbackRing {
+        let (kept, skipped): (Vec<usize>, Vec<usize>) =
+            tracked.iter().partition(|&&index| index < capacity);
+        if !skipped.is_empty() {
+            log::warn!("Not tracking {skipped:?}, there are {capacity} particles");
+        }
+        ReadbackRing::new(SAMPLE_SLOTS, kept.into_iter().map(|index| index..index + 1))
+    }
+
+    #[cfg(feature = "metrics")]
+    fn create_gpu_timer(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        capabilities: &GpuCapabilities,
+    ) -> Option<GpuTimer> {
+        let gpu_timer = GpuTimer::new(device, queue, capabilities);
+        if gpu_timer.is_none() {
+            log::warn!("Timestamp queries aren't supported, GPU pass times won't be measured");
+        }
+        gpu_timer
+    }
+
+    #[cfg(feature = "metrics")]
+    fn publish_metrics(
+        &mut self,
+        frame_time_ms: f32,
+        active_particles: usize,
+        pass_times: Option<PassTimes>,
+    ) {
+        if self.metrics.is_none() && self.telemetry.is_none() {
+            return;
+        }
+        let mut gpu_buffer_bytes = self.position_buffer.size() + self.color_buffer.size();
+        if let Some(drawn_position_buffer) = &self.drawn_position_buffer {
+            gpu_buffer_bytes += drawn_position_buffer.size();
+        }
+        if let Some(compute_pipeline) = &self.compute_pipeline {
+            gpu_buffer_bytes += compute_pipeline.cpu_data_buffer.size();
+        }
+        let metrics = Metrics {
+            frame_time_ms,
+            compute_pass_ms: pass_times.map(|times| times.compute_ms),
+            render_pass_ms: pass_times.map(|times| times.render_ms),
+            neighbors_pass_ms: pass_times.map(|times| times.neighbors_ms),
+            active_particles,
+            total_particles: self.arena.live_count(),
+            gpu_buffer_bytes,
+            resident_bytes: metrics::resident_bytes(),
+        };
+        if let Some(telemetry) = &self.telemetry {
+            telemetry.publish(Stats {
+                metrics,
+                paused: self.paused,
+            });
+        }
+        if let Some(exporter) = &mut self.metrics {
+            exporter.publish(metrics);
+        }
+    }
+
+    /// Applies the commands received by the telemetry endpoint since the last frame.
+    #[cfg(feature = "metrics")]
+    fn run_telemetry_commands(&mut self) {
+        while let Some(command) = self.telemetry.as_ref().and_then(Telemetry::try_command) {
+            match command {
+                TelemetryCommand::Pause => self.paused = true,
+                TelemetryCommand::Resume => self.paused = false,
+                TelemetryCommand::Reset => self.reset_particles(),
+                TelemetryCommand::SetParticleCount(count) => self.set_count_limit(count),
+            }
+        }
+    }
+
+    /// Copies the GPU simulation state back every few seconds, and applies the copies once they
+    /// are readable.
+    fn update_checkpoint(&mut self, dt: f32) {
+        let Some(compute_pipeline) = &self.compute_pipeline else {
+            self.checkpoint = None;
+            return;
+        };
+
+        let checkpoint = self.checkpoint.get_or_insert_with(|| {
+            let sizes = vec![
+                std::mem::size_of_val(self.instance_positions.as_slice()) as u64,
+                (self.instances_cpu_data.len() * compute_pipeline.speed_layout.stride()) as u64,
+            ];
+            Checkpoint::new(&self.device, sizes)
+        });
+
+        if let Some(contents) = checkpoint.try_take(&self.device) {
+            bytemuck::cast_slice_mut(&mut self.instance_positions).copy_from_slice(&contents[0]);
+            compute_pipeline.speed_layout.decode(
+                &contents[1],
+                bytemuck::cast_slice_mut(&mut self.instances_cpu_data),
+            );
+            for (instance, raw) in self.instances.iter_mut().zip(&self.instance_positions) {
+                instance.position = raw.position.xyz();
+            }
+        }
+
+        self.since_checkpoint += dt;
+        if self.since_checkpoint >= CHECKPOINT_INTERVAL && !checkpoint.pending() {
+            self.since_checkpoint = 0.0;
+            checkpoint.start(
+                &self.device,
+                &self.queue,
+                &[&self.position_buffer, &compute_pipeline.cpu_data_buffer],
+            );
+        }
+    }
+
+    /// Rebuilds the device and every GPU resource from the CPU-side scene. After a hang, fewer
+    /// particles are simulated so the same work doesn't hang the new device.
+    ///
+    /// Positions simulated on the GPU since the last checkpoint are lost.
+    fn recover(&mut self, failure: Failure) {
+        match failure {
+            Failure::Hang => {
+                let active = self.active_ranges().iter().map(|range| range.len()).sum();
+                let limit = self.watchdog.reduce(active);
+                log::warn!("GPU hang detected, rebuilding the device with {limit} particles");
+            }
+            Failure::DeviceLost => log::warn!("GPU device lost, rebuilding it"),
+        }
+        self.checkpoint = None;
+        if self.spawn_readback.take().is_some() {
+            // Lost before the particles spawned on the GPU made it back, respawn them on the CPU
+            let mut rng = particle_rng(self.spawn_seed, self.deterministic);
+            let (instances, instances_cpu_data) = Self::generate_particles(
+                self.instances.len(),
+                &self.spawn_emitter,
+                &self.spawn_palette,
+                self.point_cloud.as_ref(),
+                &mut rng,
+            );
+            self.instance_positions = instances.par_iter().map(Instance::to_position).collect();
+            self.instances = instances;
+            self.instances_cpu_data = instances_cpu_data;
+        }
+        let resolution = self.render_target.resolution();
+
+        // Extra windows are closed rather than given surfaces of the new instance, device losses
+        // are rare enough
+        self.extra_viewports.clear();
+        let (instance, gpu_adapter, surface, device, queue, config) = Self::create_device(
+            self.viewport.window.as_ref(),
+            self.viewport.size,
+            self.backend,
+            self.adapter.as_ref(),
+            self.surface_format,
+            self.present_mode,
+        )
+        .unwrap_or_else(|e| panic!("Unable to rebuild the GPU device: {e}"));
+        let capabilities = GpuCapabilities::of(&gpu_adapter);
+        let scene_format = surface_format::scene_format(config.format);
+        self.scene_format = scene_format;
+        self.watchdog.watch(&device);
+
+        let lights_buffer = lights::create_buffer(&device);
+        lights::write_buffer(&queue, &lights_buffer, &self.lights);
+        let gradient_buffer = gradient::create_buffer(&device);
+        let (camera_buffer, lighting_buffer, camera_bind_group_layout, camera_bind_group) =
+            Self::create_camera_bindings(
+                &device,
+                &self.viewport.camera_uniform,
+                &self.look,
+                &lights_buffer,
+                &gradient_buffer,
+            );
+        self.pipeline_cache = PipelineCache::new(
+            &device,
+            scene_format,
+            &camera_bind_group_layout,
+            &self.viewport.camera.depth,
+            self.appearance.as_ref(),
+        )
+        .expect("the appearance compiled when the demo started");
+        self.debug_pipelines = DebugPipelines::new(
+            &device,
+            &capabilities,
+            scene_format,
+            &camera_bind_group_layout,
+        );
+        // A new egui context uploads its textures again
+        #[cfg(feature = "ui")]
+        if let (Some(_), Some(window)) = (&self.ui, &self.viewport.window) {
+            self.ui = Some(Ui::new(&device, window, config.format));
+        }
+        self.picker = Picker::new(
+            &device,
+            scene_format,
+            &camera_bind_group_layout,
+            &self.viewport.camera.depth,
+        );
+        self.environment = Self::create_environment(
+            &device,
+            &queue,
+            scene_format,
+            &camera_bind_group_layout,
+            self.environment
+                .as_ref()
+                .map(|environment| environment.source().clone()),
+        );
+        // The new device may not support the same debug views
+        if !self.debug_pipelines.supports(self.debug_view) {
+            self.debug_view = DebugView::Off;
+        }
+        self.render_target = RenderTarget::new(
+            &device,
+            scene_format,
+            config.format,
+            self.viewport.size,
+            self.render_target.scale(),
+        );
+        // Of the lost device
+        self.transient_textures = TransientTextures::default();
+        #[cfg(feature = "post-processing")]
+        {
+            // Only the effects built so far, those loading depth if the new adapter can
+            let loads_depth = capabilities.depth_texture_load;
+            self.depth_of_field =
+                self.depth_of_field
+                    .take()
+                    .filter(|_| loads_depth)
+                    .map(|previous| {
+                        let mut depth_of_field = DepthOfField::new(
+                            &device,
+                            scene_format,
+                            self.viewport
+                                .camera
+                                .eye
+                                .distance(self.viewport.camera.target),
+                            self.viewport.camera.depth.reversed,
+                        );
+                        if previous.enabled() {
+                            depth_of_field.toggle();
+                        }
+                        depth_of_field
+                    });
+            self.half_resolution = self
+                .half_resolution
+                .take()
+                .filter(|_| loads_depth)
+                .map(|previous| HalfResolution::new(&device, scene_format, previous.enabled()));
+            self.motion_blur = self.motion_blur.take().map(|previous| {
+                MotionBlur::new(
+                    &device,
+                    scene_format,
+                    &camera_bind_group_layout,
+                    &self.viewport.camera.depth,
+                    previous.shutter(),
+                )
+            });
+            self.temporal = self
+                .temporal
+                .take()
+                .filter(|_| loads_depth)
+                .map(|previous| {
+                    TemporalAccumulation::new(
+                        &device,
+                        scene_format,
+                        &camera_bind_group_layout,
+                        previous.enabled(),
+                    )
+                });
+            self.checkerboard =
+                Checkerboard::new(&device, scene_format, self.checkerboard.enabled());
+            self.color_grading = ColorGrading::new(
+                &device,
+                &queue,
+                scene_format,
+                self.color_grading.lut().clone(),
+                self.color_grading.enabled(),
+            );
+            self.soft_particles = self
+                .soft_particles
+                .as_ref()
+                .map(|soft_particles| SoftParticles::new(&device, soft_particles.fade_distance()));
+        }
+        self.render_target.set_resolution(&device, resolution);
+        (self.vertex_buffer, self.index_buffer) = Self::create_mesh_buffers(&device);
+
+        let instance_colors = self
+            .instances
+            .iter()
+            .map(Instance::to_color)
+            .collect::<Vec<_>>();
+        (
+            self.position_buffer,
+            self.color_buffer,
+            self.drawn_position_buffer,
+        ) = Self::create_instance_buffers(
+            &device,
+            &queue,
+            self.arena.capacity(),
+            &self.instance_positions,
+            &instance_colors,
+        );
+        self.dirty_instances = DirtyRanges::default();
+        self.multi_draw = MultiDraw::new(&device, &capabilities, self.index_count);
+
+        if self.compute_pipeline.is_some() {
+            self.compute_pipeline = Some(Self::create_compute_pipeline(
+                &device,
+                &self.instances_cpu_data,
+                &self.position_buffer,
+                0,
+                self.drawn_position_buffer.as_ref(),
+                &self.obstacles,
+                &self.behaviors,
+                &self.spawn_emitter,
+                WindTexture::new(&device, &self.queue, self.wind.as_ref()),
+                self.workgroup_size,
+                SpeedLayout::of(&device),
+            ));
+        }
+        self.boids = Self::create_boids(
+            &device,
+            self.sim_mode,
+            &self.boids_params,
+            self.boids_tile_size,
+            self.compute_pipeline.as_ref(),
+            &self.position_buffer,
+        );
+        self.nbody = Self::create_nbody(
+            &device,
+            self.sim_mode,
+            &self.nbody_params,
+            self.nbody_grid_size,
+            self.nbody_tile_size,
+            self.compute_pipeline.as_ref(),
+            &self.position_buffer,
+        );
+        self.collisions = Self::create_collisions(
+            &device,
+            &self.sim_params,
+            self.compute_pipeline.as_ref(),
+            &self.position_buffer,
+        );
+
+        self.viewport.surface = surface;
+        self.instance = instance;
+        self.vertex_pulling &= capabilities.vertex_storage;
+        self.gpu_adapter = gpu_adapter;
+        self.capabilities = capabilities;
+        self.device = device;
+        self.queue = queue;
+        self.staging_belt = wgpu::util::StagingBelt::new(UPLOAD_CHUNK_SIZE);
+        self.viewport.config = config;
+        self.viewport.camera_buffer = camera_buffer;
+        self.viewport.lighting_buffer = lighting_buffer;
+        self.lights_buffer = lights_buffer;
+        self.gradient_buffer = gradient_buffer;
+        self.camera_bind_group_layout = camera_bind_group_layout;
+        self.viewport.camera_bind_group = camera_bind_group;
+
+        if self.frame_hasher.is_some() {
+            self.frame_hasher = Some(FrameHasher::new(&self.device));
+        }
+        if let Some(inspector) = &self.inspector {
+            self.inspector = Some(Inspector::new(inspector.index()));
+        }
+        // The buffers of the ring belong to the lost device
+        self.sampler = self
+            .sample_percent
+            .map(|percent| Self::create_sampler(self.arena.capacity(), percent));
+        if let Some((tracker, _)) = &mut self.tracker {
+            *tracker = Self::create_tracker(&self.tracked, self.arena.capacity());
+        }
+        #[cfg(feature = "metrics")]
+        if self.gpu_timer.is_some() {
+            self.gpu_timer = Self::create_gpu_timer(&self.device, &self.queue, &self.capabilities);
+        }
+
+        // Grid scenes start over, their particles only ever lived on the GPU
+        let scene_paths = self
+            .grid_cells
+            .iter()
+            .map(|cell| cell.scene_path.clone())
+            .collect::<Vec<_>>();
+        (self.instance_pool, self.grid_cells) = Self::create_grid(
+            &self.device,
+            &self.queue,
+            &self.camera_bind_group_layout,
+            &self.lights_buffer,
+            &self.gradient_buffer,
+            &scene_paths,
+            self.deterministic,
+            self.workgroup_size,
+            self.max_memory,
+            self.viewport.camera.depth.reversed,
+        )
+        // Only fails if a scene was edited into an invalid one since it was loaded
+        .unwrap_or_else(|e| panic!("{e}"));
+
+        self.rebuild_particle_passes();
+        // The eye cameras belong to the lost device
+        if let Some(mode) = self.stereo_view.as_ref().map(StereoView::mode) {
+            self.set_stereo_view(Some(mode));
+        }
+        if self.reduction.take().is_some() {
+            self.toggle_reduction();
+        }
+        if self.gizmos.take().is_some() {
+            self.toggle_gizmos();
+        }
+        self.apply_display(self.accessibility);
+    }
+
+    /// Rebuilds the enabled passes holding on to the particle buffers, once they were replaced.
+    fn rebuild_particle_passes(&mut self) {
+        // Trails are rebuilt from the current positions rather than kept
+        if self.trails.take().is_some() {
+            self.toggle_trails();
+        }
+        #[cfg(feature = "post-processing")]
+        if self.obstacle_view.take().is_some() {
+            self.toggle_obstacle_view();
+        }
+        if self.compaction.take().is_some() {
+            self.toggle_compaction();
+        }
+        if self.lod.take().is_some() {
+            self.toggle_lod();
+        }
+        if let Some(stretched) = self.stretched.take() {
+            self.toggle_stretched(stretched.shape());
+        }
+        if self.spinning.take().is_some() {
+            self.toggle_spinning();
+        }
+        if self.overlap.take().is_some() {
+            self.toggle_overlap();
+        }
+        if self.spatial_hash.take().is_some() {
+            self.toggle_spatial_hash();
+        }
+        if self.volume.take().is_some() {
+            self.toggle_volume();
+        }
+    }
+
+    /// Must be called before [`State::encode_scene`] for a scene of `size`.
+    fn prepare_scene(&mut self, size: (u32, u32)) {
+        #[cfg(feature = "post-processing")]
+        self.color_grading.prepare(&self.device, size);
+        if !self.grid_cells.is_empty() {
+            self.prepare_grid(size);
+            return;
+        }
+        self.debug_pipelines
+            .prepare(&self.device, self.debug_view, size);
+        if let Some(stereo_view) = &mut self.stereo_view {
+            stereo_view.prepare(
+                &self.device,
+                &self.queue,
+                size,
+                &self.stereo,
+                &self.viewport.camera,
+            );
+        }
+        #[cfg(feature = "post-processing")]
+        {
+            if let Some(depth_of_field) = self
+                .depth_of_field
+                .as_mut()
+                .filter(|depth_of_field| depth_of_field.enabled())
+            {
+                depth_of_field.prepare(&self.device, &self.queue, size);
+            } else if let Some(motion_blur) = self
+                .motion_blur
+                .as_mut()
+                .filter(|motion_blur| motion_blur.enabled())
+            {
+                motion_blur.prepare(
+                    &self.device,
+                    &self.queue,
+                    size,
+                    self.viewport.camera.build_view_projection_matrix(),
+                    match &self.overlap {
+                        Some(overlap) => overlap.buffer(),
+                        None => &self.position_buffer,
+                    },
+                );
+            } else if let Some(temporal) =
+                self.temporal.as_mut().filter(|temporal| temporal.enabled())
+            {
+                temporal.prepare(&self.device, &self.queue, size);
+            } else if let Some(half_resolution) = self
+                .half_resolution
+                .as_mut()
+                .filter(|half_resolution| half_resolution.enabled())
+            {
+                half_resolution.prepare(&self.device, size);
+            } else if self.checkerboard.enabled() {
+                self.checkerboard.prepare(&self.device, &self.queue, size);
+            }
+            if let Some(obstacle_view) = &self.obstacle_view {
+                obstacle_view.prepare(&self.queue, &self.viewport.camera);
+                if let Some(soft_particles) = &mut self.soft_particles {
+                    let layout = self.pipeline_cache.soft_bind_group_layout();
+                    soft_particles.prepare(&self.device, layout, size);
+                }
+            }
+        }
+        if self.compaction.is_some() || self.lod.is_some() {
+            let ranges = self.active_ranges();
+            if let Some(compaction) = &mut self.compaction {
+                compaction.set_alive(&self.queue, &ranges);
+            }
+            if let Some(lod) = &mut self.lod {
+                lod.set_alive(&self.queue, &ranges);
+                lod.set_eye(&self.queue, self.viewport.camera.eye);
+            }
+        }
+        if let Some(stretched) = &mut self.stretched {
+            stretched.resize(&self.device, self.arena.capacity());
+            // The compute passes' speeds are copied in encode_scene
+            if self.compute_pipeline.is_none() {
+                stretched.upload(&self.queue, bytemuck::cast_slice(&self.instances_cpu_data));
+            }
+        }
+        if let Some(gizmos) = &mut self.gizmos {
+            let attracting = self.sim_params.attractor_strength != 0.0;
+            gizmos.update(&self.device, &self.spawn_emitter, attracting);
+        }
+        let active_count = self.active_count();
+        if let Some(multi_draw) = &mut self.multi_draw {
+            multi_draw.prepare(&self.queue, self.arena.live_ranges(), active_count);
+        }
+    }
+
+    /// Draws the scene into `view`, through the color grading pass if enabled.
+    fn encode_scene(&self, encoder: &mut wgpu::CommandEncoder, view: &wgpu::TextureView) {
+        #[cfg(feature = "post-processing")]
+        if let Some(graded_view) = self.color_grading.view() {
+            self.encode_ungraded_scene(encoder, graded_view);
+            self.color_grading.resolve(encoder, view);
+            return;
+        }
+        self.encode_ungraded_scene(encoder, view);
+    }
+
+    /// Draws the particles and trails into `view`, through the depth-of-field pass if enabled, or
+    /// at half resolution or in a checkerboard if enabled. The trails are always drawn at full
+    /// resolution.
+    fn encode_ungraded_scene(&self, encoder: &mut wgpu::CommandEncoder, view: &wgpu::TextureView) {
+        if !self.grid_cells.is_empty() {
+            self.encode_grid(encoder, view);
+            return;
+        }
+        // Counts every live particle in the main view, whatever else draws them
+        if let Some(heatmap) = self.debug_pipelines.heatmap(self.debug_view) {
+            let buffers = ParticleBuffers {
+                camera_bind_group: &self.viewport.camera_bind_group,
+                vertex_buffer: &self.vertex_buffer,
+                index_buffer: &self.index_buffer,
+                index_count: self.index_count,
+                position_buffer: self.drawn_positions(),
+            };
+            heatmap.encode(encoder, view, &buffers, &self.active_ranges());
+            return;
+        }
+        if let Some(compaction) = &self.compaction {
+            compaction.encode(&self.device, encoder);
+        } else if let Some(lod) = &self.lod {
+            lod.encode(&self.device, encoder);
+        } else if let Some(multi_draw) = &self.multi_draw {
+            multi_draw.encode(encoder);
+        }
+        if let (Some(stretched), Some(compute_pipeline)) = (&self.stretched, &self.compute_pipeline)
+        {
+            stretched.copy(encoder, &compute_pipeline.cpu_data_buffer);
+        }
+        if let Some(stereo_view) = &self.stereo_view {
+            for (eye_view, bind_group) in stereo_view.eyes() {
+                self.encode_particles_pass(encoder, eye_view, None, bind_group, None);
+                self.encode_trails_pass(encoder, eye_view, bind_group);
+            }
+            stereo_view.compose(encoder, view);
+            return;
+        }
+        // The volume replaces the particles and trails, post-processing included
+        let drawn = self.encode_volume(encoder, view);
+        #[cfg(feature = "post-processing")]
+        let soft = if drawn {
+            None
+        } else {
+            self.encode_obstacle_depth(encoder)
+        };
+        #[cfg(not(feature = "post-processing"))]
+        let soft = None;
+        // The debug views show the particles as they are, without post-processing
+        #[cfg(feature = "post-processing")]
+        let drawn = drawn
+            || self.debug_view == DebugView::Off && self.encode_post_processed(encoder, view, soft);
+        if !drawn {
+            let bind_group = &self.viewport.camera_bind_group;
+            self.encode_particles_pass(encoder, view, None, bind_group, soft);
+            self.encode_trails_pass(encoder, view, bind_group);
+        }
+        #[cfg(feature = "post-processing")]
+        if let Some(obstacle_view) = &self.obstacle_view {
+            obstacle_view.encode(encoder, view);
+        }
+    }
+
+    /// Draws the particles and trails into `view` through the enabled post-processing pass, if
+    /// any. Returns whether one was.
+    #[cfg(feature = "post-processing")]
+    fn encode_post_processed(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        view: &wgpu::TextureView,
+        soft: Option<&wgpu::BindGroup>,
+    ) -> bool {
+        let bind_group = &self.viewport.camera_bind_group;
+        // Each enabled effect with the views it was prepared with
+        let depth_of_field = self
+            .depth_of_field
+            .as_ref()
+            .filter(|effect| effect.enabled());
+        let motion_blur = self.motion_blur.as_ref().filter(|effect| effect.enabled());
+        let temporal = self.temporal.as_ref().filter(|effect| effect.enabled());
+        let half_resolution = self
+            .half_resolution
+            .as_ref()
+            .filter(|effect| effect.enabled());
+        match (
+            depth_of_field.zip(depth_of_field.and_then(DepthOfField::views)),
+            motion_blur.zip(motion_blur.and_then(MotionBlur::views)),
+            temporal.zip(temporal.and_then(TemporalAccumulation::views)),
+            half_resolution.zip(half_resolution.and_then(HalfResolution::views)),
+            self.checkerboard.views(),
+        ) {
+            (Some((depth_of_field, (color_view, depth_view))), ..) => {
+                let depth = Some(DepthStencil::Depth(depth_view));
+                self.encode_particles_pass(encoder, color_view, depth, bind_group, soft);
+                self.encode_trails_pass(encoder, color_view, bind_group);
+                depth_of_field.resolve(encoder, view);
+            }
+            (_, Some((motion_blur, (color_view, depth_view))), ..) => {
+                let depth = Some(DepthStencil::Depth(depth_view));
+                self.encode_particles_pass(encoder, color_view, depth, bind_group, soft);
+                self.encode_trails_pass(encoder, color_view, bind_group);
+                let buffers = ParticleBuffers {
+                    camera_bind_group: bind_group,
+                    vertex_buffer: &self.vertex_buffer,
+                    index_buffer: &self.index_buffer,
+                    index_count: self.index_count,
+                    position_buffer: self.drawn_positions(),
+                };
+                motion_blur.resolve(encoder, view, &buffers, &self.active_ranges());
+            }
+            (.., Some((temporal, (color_view, depth_view))), _, _) => {
+                let depth = Some(DepthStencil::Depth(depth_view));
+                self.encode_particles_pass(encoder, color_view, depth, bind_group, soft);
+                self.encode_trails_pass(encoder, color_view, bind_group);
+                temporal.resolve(encoder, view, bind_group);
+            }
+            (.., Some((half_resolution, (color_view, depth_view))), _) => {
+                let depth = Some(DepthStencil::Depth(depth_view));
+                self.encode_particles_pass(encoder, color_view, depth, bind_group, soft);
+                half_resolution.upsample(encoder, view);
+                self.encode_trails_pass(encoder, view, bind_group);
+            }
+            (.., Some((color_view, stencil_view, parity))) if self.checkerboard.enabled() => {
+                let stencil = Some(DepthStencil::Checkerboard(stencil_view, parity));
+                self.encode_particles_pass(encoder, color_view, stencil, bind_group, soft);
+                self.checkerboard.reconstruct(encoder, view);
+                self.encode_trails_pass(encoder, view, bind_group);
+            }
+            _ => return false,
+        }
+        true
+    }
+
+    /// Draws the obstacles' depth for the soft particles, if enabled and the obstacles are shown.
+    /// Returns the bind group the particles sample it with.
+    #[cfg(feature = "post-processing")]
+    fn encode_obstacle_depth(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+    ) -> Option<&wgpu::BindGroup> {
+        let obstacle_view = self.obstacle_view.as_ref()?;
+        let soft_particles = self.soft_particles.as_ref()?;
+        obstacle_view.encode_depth(encoder, soft_particles.depth_view()?);
+        soft_particles.bind_group()
+    }
+
+    /// Splats and ray-marches the particle densities into `view`, if the volume is enabled.
+    /// Returns whether it was.
+    fn encode_volume(&self, encoder: &mut wgpu::CommandEncoder, view: &wgpu::TextureView) -> bool {
+        let Some(volume) = &self.volume else {
+            return false;
+        };
+        let active_end = self.active_ranges().last().map_or(0, |range| range.end);
+        volume.encode(
+            &self.device,
+            &self.queue,
+            encoder,
+            self.drawn_positions(),
+            active_end,
+        );
+        let bind_group = &self.viewport.camera_bind_group;
+        let load = self.encode_background(encoder, view, bind_group);
+        volume.draw(encoder, view, bind_group, load);
+        true
+    }
+
+    /// Draws the environment into `view`, if any. Returns how the pass drawing over it should load
+    /// `view`, cleared to the background color without an environment.
+    fn encode_background(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        view: &wgpu::TextureView,
+        camera_bind_group: &wgpu::BindGroup,
+    ) -> wgpu::LoadOp<wgpu::Color> {
+        match &self.environment {
+            Some(environment) => {
+                environment.encode(encoder, view, camera_bind_group);
+                wgpu::LoadOp::Load
+            }
+            None => wgpu::LoadOp::Clear(wgpu::Color {
+                r: self.look.background.x as f64,
+                g: self.look.background.y as f64,
+                b: self.look.background.z as f64,
+                a: 1.0,
+            }),
+        }
+    }
+
+    fn encode_particles_pass(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        view: &wgpu::TextureView,
+        depth_stencil: Option<DepthStencil>,
+        camera_bind_group: &wgpu::BindGroup,
+        soft: Option<&wgpu::BindGroup>,
+    ) {
+        let depth_format = match depth_stencil {
+            #[cfg(feature = "post-processing")]
+            Some(DepthStencil::Depth(_)) => Some(DEPTH_FORMAT),
+            #[cfg(feature = "post-processing")]
+            Some(DepthStencil::Checkerboard(..)) => Some(checkerboard::STENCIL_FORMAT),
+            _ => None,
+        };
+        // The other draws bind their own vertex buffers
+        let pulled = self.vertex_pulling
+            && self.debug_view == DebugView::Off
+            && self.compaction.is_none()
+            && self.lod.is_none()
+            && self.stretched.is_none()
+            && self.spinning.is_none();
+        // Outlive the render pass
+        let pipeline = self.pipeline_cache.get(
+            &self.device,
+            RenderOptions {
+                shape: if pulled {
+                    ParticleShape::Pulled
+                } else {
+                    ParticleShape::Quad
+                },
+                blend_mode: self.blend_mode,
+                depth_format,
+                soft: soft.is_some(),
+            },
+        );
+        let pulled_bind_group = pulled.then(|| {
+            vertex_pulling::create_bind_group(
+                &self.device,
+                self.pipeline_cache.pulled_bind_group_layout(),
+                self.drawn_positions(),
+                &self.color_buffer,
+            )
+        });
+        let load = self.encode_background(encoder, view, camera_bind_group);
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Render Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view,
+                resolve_target: None,
+                ops: wgpu::Operations { load, store: true },
+            })],
+            depth_stencil_attachment: depth_stencil.as_ref().map(|depth_stencil| {
+                match *depth_stencil {
+                    DepthStencil::Depth(view) => wgpu::RenderPassDepthStencilAttachment {
+                        view,
+                        depth_ops: Some(wgpu::Operations {
+                            load: wgpu::LoadOp::Clear(self.viewport.camera.depth.far_depth()),
+                            store: true,
+                        }),
+                        stencil_ops: None,
+                    },
+                    DepthStencil::Checkerboard(view, _) => wgpu::RenderPassDepthStencilAttachment {
+                        view,
+                        depth_ops: None,
+                        stencil_ops: Some(wgpu::Operations {
+                            load: wgpu::LoadOp::Load,
+                            store: true,
+                        }),
+                    },
+                }
+            }),
+        });
+        if let Some(DepthStencil::Checkerboard(_, parity)) = depth_stencil {
+            render_pass.set_stencil_reference(parity);
+        }
+
+        render_pass.set_bind_group(0, camera_bind_group, &[]);
+        match soft {
+            Some(soft) => render_pass.set_bind_group(1, soft, &[]),
+            None if pulled => {
+                render_pass.set_bind_group(1, self.pipeline_cache.empty_bind_group(), &[])
+            }
+            None => {}
+        }
+        // Counted in the main view only, not in the stereo eyes or the extra windows
+        #[cfg(feature = "metrics")]
+        let gpu_timer = self
+            .gpu_timer
+            .as_ref()
+            .filter(|_| std::ptr::eq(camera_bind_group, &self.viewport.camera_bind_group));
+        #[cfg(feature = "metrics")]
+        if let Some(gpu_timer) = gpu_timer {
+            gpu_timer.begin_statistics(&mut render_pass);
+        }
+        self.draw_particles(
+            &mut render_pass,
+            &pipeline,
+            pulled_bind_group.as_ref(),
+            depth_format,
+        );
+        #[cfg(feature = "metrics")]
+        if let Some(gpu_timer) = gpu_timer {
+            gpu_timer.end_statistics(&mut render_pass);
+        }
+    }
+
+    /// Draws the particles with `pipeline` unless a debug view or another draw replaces it, the
+    /// camera and soft particle bind groups already set.
+    fn draw_particles<'a>(
+        &'a self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        pipeline: &'a wgpu::RenderPipeline,
+        pulled_bind_group: Option<&'a wgpu::BindGroup>,
+        depth_format: Option<wgpu::TextureFormat>,
+    ) {
+        if self.debug_view == DebugView::Points {
+            // One vertex per instance, no quad
+            render_pass.set_pipeline(self.debug_pipelines.pipeline(DebugView::Points).unwrap());
+            render_pass.set_vertex_buffer(0, self.vertex_positions().slice(..));
+            render_pass.set_vertex_buffer(1, self.color_buffer.slice(..));
+            for range in self.active_ranges() {
+                render_pass.draw(0..1, range.start as u32..range.end as u32);
+            }
+            return;
+        }
+
+        render_pass.set_pipeline(
+            self.debug_pipelines
+                .pipeline(self.debug_view)
+                .unwrap_or(pipeline),
+        );
+        if let Some(bind_group) = pulled_bind_group {
+            render_pass.set_bind_group(2, bind_group, &[]);
+            for range in self.active_ranges() {
+                render_pass.draw(0..self.index_count, range.start as u32..range.end as u32);
+            }
+            return;
+        }
+        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        render_pass.set_vertex_buffer(1, self.vertex_positions().slice(..));
+        render_pass.set_vertex_buffer(2, self.color_buffer.slice(..));
+        render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
+        if let Some(compaction) = &self.compaction {
+            compaction.draw(render_pass);
+            return;
+        }
+        if let (Some(stretched), DebugView::Off) = (&self.stretched, self.debug_view) {
+            let ranges = self.active_ranges();
+            stretched.draw(render_pass, depth_format, self.index_count, &ranges);
+            return;
+        }
+        if let (Some(spinning), DebugView::Off) = (&self.spinning, self.debug_view) {
+            let ranges = self.active_ranges();
+            spinning.draw(render_pass, depth_format, self.index_count, &ranges);
+            return;
+        }
+        if let (Some(lod), DebugView::Off) = (&self.lod, self.debug_view) {
+            lod.draw(render_pass, depth_format);
+            return;
+        }
+        if let Some(multi_draw) = &self.multi_draw {
+            if multi_draw.draw(render_pass) {
+                return;
+            }
+        }
+        for range in self.active_ranges() {
+            render_pass.draw_indexed(0..self.index_count, 0, range.start as u32..range.end as u32);
+        }
+    }
+
+    /// Rings the inspected particle, on screen only: captures and recordings leave it out.
+    fn encode_highlight(&self, encoder: &mut wgpu::CommandEncoder, view: &wgpu::TextureView) {
+        let Some(index) = self.inspector.as_ref().and_then(Inspector::index) else {
+            return;
+        };
+        if !self.grid_cells.is_empty() {
+            return;
+        }
+        self.picker.highlight(
+            encoder,
+            view,
+            &ParticleBuffers {
+                camera_bind_group: &self.viewport.camera_bind_group,
+                vertex_buffer: &self.vertex_buffer,
+                index_buffer: &self.index_buffer,
+                index_count: self.index_count,
+                position_buffer: self.drawn_positions(),
+            },
+            index,
+        );
+    }
+
+    /// Positions the particles are drawn from, a frame behind the simulation while the rendering
+    /// overlaps with it.
+    fn drawn_positions(&self) -> &wgpu::Buffer {
+        self.overlap
+            .as_ref()
+            .map_or(&self.position_buffer, RenderSnapshot::buffer)
+    }
+
+    /// Positions the particle pipelines fetch as vertices, the drawn ones packed in halves with
+    /// the `packed-instances` feature.
+    fn vertex_positions(&self) -> &wgpu::Buffer {
+        match (&self.overlap, &self.drawn_position_buffer) {
+            (Some(overlap), _) => overlap.drawn_buffer().unwrap_or(overlap.buffer()),
+            (None, Some(drawn_position_buffer)) => drawn_position_buffer,
+            (None, None) => &self.position_buffer,
+        }
+    }
+
+    /// Packs the positions, or those of the grid view, into the drawn positions on the frames the
+    /// compute kernel doesn't write them, paused or simulating on the CPU. Must run before the
+    /// scene is encoded.
+    #[cfg(feature = "packed-instances")]
+    fn pack_positions(&self, encoder: &mut wgpu::CommandEncoder) {
+        if !self.paused && (self.compute_pipeline.is_some() || !self.grid_cells.is_empty()) {
+            return;
+        }
+        let (position_buffer, drawn_position_buffer) = if self.grid_cells.is_empty() {
+            (&self.position_buffer, self.drawn_position_buffer.as_ref())
+        } else {
+            (
+                self.instance_pool.position_buffer(),
+                self.instance_pool.drawn_position_buffer(),
+            )
+        };
+        if let Some(drawn_position_buffer) = drawn_position_buffer {
+            self.position_packer.encode(
+                &self.device,
+                encoder,
+                position_buffer,
+                drawn_position_buffer,
+            );
+        }
+    }
+
+    /// Draws the particles and trails straight into the extra windows, at their native resolution
+    /// and without depth of field. Returns the textures to present once submitted.
+    fn encode_extra_windows(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+    ) -> Vec<wgpu::SurfaceTexture> {
+        let mut outputs = vec![];
+        for viewport in &self.extra_viewports {
+            let Some(surface) = &viewport.surface else {
+                continue;
+            };
+            let output = match surface.get_current_texture() {
+                Ok(output) => output,
+                Err(wgpu::SurfaceError::Lost | wgpu::SurfaceError::Outdated) => {
+                    surface.configure(&self.device, &viewport.config);
+                    continue;
+                }
+                Err(e) => {
+                    log::warn!("Skipping a frame of an extra window: {e}");
+                    continue;
+                }
+            };
+            let view = output
+                .texture
+                .create_view(&wgpu::TextureViewDescriptor::default());
+            self.encode_particles_pass(encoder, &view, None, &viewport.camera_bind_group, None);
+            self.encode_trails_pass(encoder, &view, &viewport.camera_bind_group);
+            outputs.push(output);
+        }
+        outputs
+    }
+
+    /// Fits the grid cells and their cameras to a scene of `size`.
+    fn prepare_grid(&mut self, size: (u32, u32)) {
+        let layout = GridLayout::new(self.grid_cells.len());
+        for (index, cell) in self.grid_cells.iter_mut().enumerate() {
+            cell.rect = layout.cell_rect(index, size);
+            let mut camera = cell.camera.clone();
+            camera.aspect = cell.rect.aspect();
+            cell.set_camera(&self.queue, &camera, &self.look);
+        }
+    }
+
+    /// Adds the scene at `path` to the grid, in a free slot of the instance pool.
+    fn add_grid_cell(&mut self, path: &Path) {
+        if self.grid_cells.is_empty() {
+            log::warn!("Scenes can only be dropped on the grid view, see --grid");
+            return;
+        }
+        let scene = match Scene::load(path) {
+            Ok(scene) => scene,
+            Err(e) => {
+                log::error!("{e}");
+                return;
+            }
+        };
+        // Shares the budget with the other scenes
+        let cell_memory = self
+            .max_memory
+            .map(|max_memory| max_memory / (self.grid_cells.len() + 1) as u64);
+        let cell = Self::create_scene_cell(
+            &self.device,
+            &self.queue,
+            &mut self.instance_pool,
+            &self.camera_bind_group_layout,
+            &self.lights_buffer,
+            &self.gradient_buffer,
+            &scene,
+            self.deterministic,
+            self.workgroup_size,
+            cell_memory,
+            self.viewport.camera.depth.reversed,
+        );
+        match cell {
+            Ok(cell) => {
+                self.grid_cells.push(GridCell {
+                    scene_path: path.to_owned(),
+                    ..cell
+                });
+                log::info!(
+                    "Added {} to the grid, {} of {} pooled instances free",
+                    path.display(),
+                    self.instance_pool.free_count(),
+                    self.instance_pool.capacity()
+                );
+            }
+            Err(e) => log::error!("Unable to add {}: {e}", path.display()),
+        }
+    }
+
+    /// Removes the grid cell under the cursor, its slot of the instance pool freed for the next
+    /// one. The last cell stays.
+    fn close_cell_at_cursor(&mut self) {
+        let Some(cursor) = self.cursor_position else {
+            return;
+        };
+        if self.grid_cells.len() < 2 {
+            return;
+        }
+        // The cells are laid out at the render resolution
+        let (width, height) = self.render_target.size();
+        let x = cursor.x * width as f64 / self.viewport.size.width.max(1) as f64;
+        let y = cursor.y * height as f64 / self.viewport.size.height.max(1) as f64;
+        let Some(index) = self
+            .grid_cells
+            .iter()
+            .position(|cell| cell.rect.contains(x as u32, y as u32))
+        else {
+            return;
+        };
+        let cell = self.grid_cells.remove(index);
+        self.instance_pool.free(cell.slot);
+        log::info!(
+            "Closed {}, {} of {} pooled instances free",
+            cell.scene_path.display(),
+            self.instance_pool.free_count(),
+            self.instance_pool.capacity()
+        );
+    }
+
+    fn encode_grid(&self, encoder: &mut wgpu::CommandEncoder, view: &wgpu::TextureView) {
+        let pipeline = self.pipeline_cache.get(
+            &self.device,
+            RenderOptions {
+                shape: ParticleShape::Quad,
+                blend_mode: self.blend_mode,
+                depth_format: None,
+                soft: false,
+            },
+        );
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Grid Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color {
+                        r: self.look.background.x as f64,
+                        g: self.look.background.y as f64,
+                        b: self.look.background.z as f64,
+                        a: 1.0,
+                    }),
+                    store: true,
+                },
+            })],
+            depth_stencil_attachment: None,
+        });
+
+        render_pass.set_pipeline(&pipeline);
+        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        self.instance_pool.set_vertex_buffers(&mut render_pass);
+        render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
+        for cell in &self.grid_cells {
+            let CellRect {
+                x,
+                y,
+                width,
+                height,
+            } = cell.rect;
+            // Quads straddling the edge of a cell would otherwise spill into its neighbours
+            render_pass.set_scissor_rect(x, y, width, height);
+            render_pass.set_viewport(x as f32, y as f32, width as f32, height as f32, 0.0, 1.0);
+            cell.draw(&mut render_pass, self.index_count);
+        }
+    }
+
+    fn move_grid_cells(&mut self, dt: f32) {
+        for cell in &mut self.grid_cells {
+            cell.advance(&self.queue, dt);
+        }
+    }
+
+    /// Records a submission per substep of the grid cells' compute kernels, so every cell sees the
+    /// substep in its dispatch buffer. Takes no `State`, to run on the encoding thread.
+    pub fn encode_grid_compute<'a>(
+        device: &wgpu::Device,
+        grid_cells: &'a [GridCell],
+    ) -> Vec<Submission<'a>> {
+        let substeps = grid_cells
+            .iter()
+            .map(|cell| cell.sim_params.roi_substeps)
+            .max()
+            .unwrap_or(0);
+        (0..substeps)
+            .map(|substep| {
+                let mut writes = vec![];
+                let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                    label: Some("Grid Compute Encoder"),
+                });
+                for cell in grid_cells {
+                    if substep >= cell.sim_params.roi_substeps {
+                        continue;
+                    }
+                    let compute_pipeline = &cell.compute_pipeline;
+                    let dispatch = DispatchParams {
+                        substep,
+                        ..DispatchParams::zeroed()
+                    };
+                    writes.push((
+                        &compute_pipeline.dispatch_buffer,
+                        bytemuck::bytes_of(&dispatch).to_vec(),
+                    ));
+
+                    let mut compute_pass =
+                        encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                            label: Some("Grid Compute Pass"),
                         });
+                    for (range, bind_group) in &compute_pipeline.bind_groups {
+                        let rows = (range.len() as u32).div_ceil(COMPUTE_ROW_WIDTH).max(1);
+                        compute_pipeline.dispatch(&mut compute_pass, bind_group, rows);
+                    }
+                }
+                Submission {
+                    writes,
+                    command_buffer: encoder.finish(),
+                }
+            })
+            .collect()
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn create_grid(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        camera_bind_group_layout: &wgpu::BindGroupLayout,
+        lights_buffer: &wgpu::Buffer,
+        gradient_buffer: &wgpu::Buffer,
+        scene_paths: &[PathBuf],
+        deterministic: bool,
+        workgroup_size: u32,
+        max_memory: Option<u64>,
+        reverse_z: bool,
+    ) -> Result<(InstancePool, Vec<GridCell>), AppError> {
+        // The scenes of the grid share the budget
+        let cell_memory = max_memory.map(|max_memory| max_memory / scene_paths.len().max(1) as u64);
+        let scenes = scene_paths
+            .iter()
+            .map(|path| Scene::load(path))
+            .collect::<Result<Vec<_>, _>>()?;
+        let capacity = scenes
+            .iter()
+            .map(|scene| {
+                let count = Self::scene_particle_count(device, scene, None, cell_memory);
+                InstancePool::slot_len(device, count)
+            })
+            .sum::<usize>()
+            * POOL_HEADROOM;
+        let mut pool = InstancePool::new(device, capacity);
+        let cells = scene_paths
+            .iter()
+            .zip(&scenes)
+            .map(|(scene_path, scene)| {
+                let cell = Self::create_scene_cell(
+                    device,
+                    queue,
+                    &mut pool,
+                    camera_bind_group_layout,
+                    lights_buffer,
+                    gradient_buffer,
+                    scene,
+                    deterministic,
+                    workgroup_size,
+                    cell_memory,
+                    reverse_z,
+                )?;
+                Ok(GridCell {
+                    scene_path: scene_path.clone(),
+                    ..cell
+                })
+            })
+            .collect::<Result<Vec<_>, PoolFull>>()?;
+        Ok((pool, cells))
+    }
+
+    /// Particle system of `scene` in a slot of `pool`, simulated on the GPU apart from the main
+    /// particles. Its scene path is left empty, for cells not loaded from a file.
+    #[allow(clippy::too_many_arguments)]
+    pub fn create_scene_cell(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        pool: &mut InstancePool,
+        camera_bind_group_layout: &wgpu::BindGroupLayout,
+        lights_buffer: &wgpu::Buffer,
+        gradient_buffer: &wgpu::Buffer,
+        scene: &Scene,
+        deterministic: bool,
+        workgroup_size: u32,
+        max_memory: Option<u64>,
+        reverse_z: bool,
+    ) -> Result<GridCell, PoolFull> {
+        let mut rng = particle_rng(scene.seed, deterministic);
+        let (instances, instances_cpu_data) = Self::generate_particles(
+            Self::scene_particle_count(device, scene, None, max_memory),
+            &scene.emitter,
+            &scene.palette,
+            None,
+            &mut rng,
+        );
+        let instance_positions = instances
+            .par_iter()
+            .map(Instance::to_position)
+            .collect::<Vec<_>>();
+        let instance_colors = instances
+            .par_iter()
+            .map(Instance::to_color)
+            .collect::<Vec<_>>();
+        let slot = pool.allocate(queue, &instance_positions, &instance_colors)?;
+        let wind = Self::load_wind(scene.wind.as_ref());
+        let compute_pipeline = Self::create_compute_pipeline(
+            device,
+            &instances_cpu_data,
+            pool.position_buffer(),
+            slot.start,
+            pool.drawn_position_buffer(),
+            &scene.obstacles,
+            &Behaviors::new(&scene.behaviors),
+            &scene.emitter,
+            WindTexture::new(device, queue, wind.as_ref()),
+            workgroup_size,
+            SpeedLayout::of(device),
+        );
+
+        // The aspect ratio is only known once the cell is laid out
+        let mut camera = initial_camera(1.0, reverse_z);
+        scene.apply_camera(&mut camera);
+        let (camera_buffer, lighting_buffer) =
+            Self::create_camera_buffers(device, &CameraUniform::new(), &Keyframe::NEUTRAL);
+        let camera_bind_group = Self::create_camera_bind_group(
+            device,
+            camera_bind_group_layout,
+            &camera_buffer,
+            &lighting_buffer,
+            lights_buffer,
+            gradient_buffer,
+        );
+
+        Ok(GridCell {
+            scene_path: PathBuf::new(),
+            particle_count: instances.len(),
+            camera,
+            turbulence: scene.turbulence,
+            sim_params: scene.sim_params,
+            rect: GridLayout::new(1).cell_rect(0, (1, 1)),
+            slot,
+            compute_pipeline,
+            camera_buffer,
+            lighting_buffer,
+            camera_bind_group,
+        })
+    }
+
+    fn encode_trails_pass(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        view: &wgpu::TextureView,
+        camera_bind_group: &wgpu::BindGroup,
+    ) {
+        let Some(trails) = &self.trails else {
+            return;
+        };
+
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Trail Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: true,
+                },
+            })],
+            depth_stencil_attachment: None,
+        });
+        trails.draw(
+            &mut render_pass,
+            camera_bind_group,
+            &self.color_buffer,
+            &self.active_ranges(),
+        );
+    }
+
+    /// Regenerates the particles and parameters if the watched scene file changed, keeping the
+    /// window and device.
+    fn reload_scene(&mut self) {
+        let Some(watcher) = &mut self.scene_watcher else {
+            return;
+        };
+        let scene = match watcher.poll() {
+            None => return,
+            Some(Ok(scene)) => scene,
+            Some(Err(e)) => {
+                log::error!("Keeping the current scene: {e}");
+                return;
+            }
+        };
+        log::info!("Reloaded {}", watcher.path().display());
+        let appearance = scene.appearance.clone();
+        self.load_scene(scene);
+
+        // A scene naming another snippet, or none anymore, is drawn with it right away
+        let watched = self
+            .appearance_watcher
+            .as_ref()
+            .map(AppearanceWatcher::path);
+        if appearance.as_deref() != watched {
+            self.appearance_watcher = appearance.clone().map(AppearanceWatcher::new);
+            match appearance.as_deref().map(Appearance::load).transpose() {
+                Ok(appearance) => self.set_appearance(appearance),
+                Err(e) => self.show_shader_error(e.to_string()),
+            }
+        }
+    }
+
+    /// Rebuilds the particle pipelines if the watched appearance snippet changed.
+    fn reload_appearance(&mut self) {
+        let Some(watcher) = &mut self.appearance_watcher else {
+            return;
+        };
+        match watcher.poll() {
+            None => {}
+            Some(Ok(appearance)) => {
+                log::info!("Reloaded {}", watcher.path().display());
+                self.set_appearance(Some(appearance));
+            }
+            Some(Err(e)) => self.show_shader_error(e.to_string()),
+        }
+    }
+
+    /// Draws the particles with `appearance`, or keeps the current pipelines and shows why if it
+    /// doesn't compile.
+    fn set_appearance(&mut self, appearance: Option<Appearance>) {
+        match PipelineCache::new(
+            &self.device,
+            self.scene_format,
+            &self.camera_bind_group_layout,
+            &self.viewport.camera.depth,
+            appearance.as_ref(),
+        ) {
+            Ok(pipeline_cache) => {
+                self.pipeline_cache = pipeline_cache;
+                self.appearance = appearance;
+                self.shader_error = None;
+            }
+            Err(e) => self.show_shader_error(e.to_string()),
+        }
+    }
+
+    /// Logs `error`, and opens the overlay on it with the `ui` feature.
+    fn show_shader_error(&mut self, error: String) {
+        log::error!("Keeping the current appearance: {error}");
+        #[cfg(feature = "ui")]
+        if self.ui.is_none() {
+            self.toggle_ui();
+        }
+        self.shader_error = Some(error);
+    }
+
+    /// Listens to the audio input, scaling the next step of the simulation and brightening the
+    /// particles by its levels.
+    #[cfg(feature = "audio")]
+    fn run_audio(&mut self, dt: f32) {
+        let Some(audio) = &mut self.audio else {
+            return;
+        };
+        self.audio_levels = audio.poll(dt);
+        let mut look = self.look;
+        look.tint *= self.audio_levels.brightness();
+        let lighting = LightingUniform::from(&look);
+        for viewport in std::iter::once(&self.viewport).chain(&self.extra_viewports) {
+            self.queue.write_buffer(
+                &viewport.lighting_buffer,
+                0,
+                bytemuck::cast_slice(&[lighting]),
+            );
+        }
+    }
+
+    /// Reloads the script if it changed, respawning the particles if its emission did, and applies
+    /// its forces.
+    #[cfg(feature = "scripting")]
+    fn run_script(&mut self) {
+        let Some(script) = &mut self.script else {
+            return;
+        };
+        let emission = if script.reload() {
+            script.emission()
+        } else {
+            Ok(None)
+        };
+        let forces = script.forces(self.turbulence.time);
+
+        match emission {
+            Ok(Some(emission)) => {
+                let (particles, emitter) = emission.apply(self.particles, self.emitter);
+                if (particles, emitter) != (self.particles, self.emitter) {
+                    (self.particles, self.emitter) = (particles, emitter);
+                    self.respawn_scene();
+                }
+            }
+            Ok(None) => {}
+            Err(e) => log::error!("Keeping the current particles: {e}"),
+        }
+        match forces {
+            Ok(Some(forces)) => forces.apply(&mut self.sim_params),
+            Ok(None) => {}
+            Err(e) => log::error!("{e}"),
+        }
+    }
+
+    /// Loads the scene again, from the watched file if any, respawning the particles with the
+    /// current particle count and emitter.
+    #[cfg(feature = "scripting")]
+    fn respawn_scene(&mut self) {
+        let scene = match &self.scene_watcher {
+            Some(watcher) => match Scene::load(watcher.path()) {
+                Ok(scene) => scene,
+                Err(e) => {
+                    log::error!("Keeping the current particles: {e}");
+                    return;
+                }
+            },
+            None => Scene::default(),
+        };
+        self.load_scene(scene);
+    }
+
+    /// Respawns the particles in `scene`, with the particle count, emitter and palette overrides.
+    fn load_scene(&mut self, mut scene: Scene) {
+        #[cfg(feature = "post-processing")]
+        if let Some(motion_blur) = &mut self.motion_blur {
+            motion_blur.reset();
+        }
+        if let Some(particles) = self.particles {
+            scene.particles = Some(particles);
+        }
+        if let Some(emitter) = self.emitter {
+            scene.emitter = emitter;
+        }
+        self.scene_palette = scene.palette;
+        if let Some(palette) = self.palette_override() {
+            scene.palette = palette;
+        }
+
+        let mut rng = particle_rng(scene.seed, self.deterministic);
+        let (instances, instances_cpu_data) = Self::generate_particles(
+            Self::scene_particle_count(
+                &self.device,
+                &scene,
+                self.point_cloud.as_ref(),
+                self.max_memory,
+            ),
+            &scene.emitter,
+            &scene.palette,
+            self.point_cloud.as_ref(),
+            &mut rng,
+        );
+        self.instance_positions = instances.par_iter().map(Instance::to_position).collect();
+        let instance_colors = instances
+            .par_iter()
+            .map(Instance::to_color)
+            .collect::<Vec<_>>();
+        (
+            self.position_buffer,
+            self.color_buffer,
+            self.drawn_position_buffer,
+        ) = Self::create_instance_buffers(
+            &self.device,
+            &self.queue,
+            instances.len(),
+            &self.instance_positions,
+            &instance_colors,
+        );
+        self.arena = InstanceArena::new(instances.len());
+        self.sampler = self
+            .sample_percent
+            .map(|percent| Self::create_sampler(instances.len(), percent));
+        if let Some((tracker, _)) = &mut self.tracker {
+            *tracker = Self::create_tracker(&self.tracked, instances.len());
+        }
+        if self
+            .inspector
+            .as_ref()
+            .and_then(Inspector::index)
+            .is_some_and(|index| index >= instances.len())
+        {
+            self.inspector = None;
+            self.show_inspector();
+        }
+        self.adaptive = self
+            .target_fps
+            .map(|fps| AdaptiveCount::new(fps, instances.len()));
+        self.instances = instances;
+        self.instances_cpu_data = instances_cpu_data;
+        self.dirty_instances = DirtyRanges::default();
+        self.checkpoint = None;
+        self.spawn_readback = None;
+        self.since_checkpoint = 0.0;
+        self.obstacles = scene.obstacles.clone();
+        self.lights = scene.lights.clone();
+        lights::write_buffer(&self.queue, &self.lights_buffer, &self.lights);
+        self.gradient = scene.gradient.clone();
+        self.spawn_emitter = scene.emitter;
+        self.spawn_palette = scene.palette;
+        self.spawn_seed = scene.seed;
+        self.behaviors = Behaviors::new(&scene.behaviors);
+        self.wind = Self::load_wind(scene.wind.as_ref());
+        if self.compute_pipeline.is_some() {
+            self.compute_pipeline = Some(Self::create_compute_pipeline(
+                &self.device,
+                &self.instances_cpu_data,
+                &self.position_buffer,
+                0,
+                self.drawn_position_buffer.as_ref(),
+                &self.obstacles,
+                &self.behaviors,
+                &self.spawn_emitter,
+                WindTexture::new(&self.device, &self.queue, self.wind.as_ref()),
+                self.workgroup_size,
+                SpeedLayout::of(&self.device),
+            ));
+        }
+        #[cfg(feature = "post-processing")]
+        if self.obstacle_view.take().is_some() {
+            self.toggle_obstacle_view();
+        }
+        if self.trails.take().is_some() {
+            self.toggle_trails();
+        }
+        if self.compaction.take().is_some() {
+            self.toggle_compaction();
+        }
+        if self.lod.take().is_some() {
+            self.toggle_lod();
+        }
+        if let Some(stretched) = self.stretched.take() {
+            self.toggle_stretched(stretched.shape());
+        }
+        if self.spinning.take().is_some() {
+            self.toggle_spinning();
+        }
+        if self.overlap.take().is_some() {
+            self.toggle_overlap();
+        }
+        if self.spatial_hash.take().is_some() {
+            self.toggle_spatial_hash();
+        }
+        if self.volume.take().is_some() {
+            self.toggle_volume();
+        }
+        if self.reduction.take().is_some() {
+            self.toggle_reduction();
+        }
+
+        self.turbulence = scene.turbulence;
+        self.sim_params = scene.sim_params;
+        self.boids_params = scene.boids;
+        self.boids = Self::create_boids(
+            &self.device,
+            self.sim_mode,
+            &self.boids_params,
+            self.boids_tile_size,
+            self.compute_pipeline.as_ref(),
+            &self.position_buffer,
+        );
+        self.nbody_params = scene.nbody;
+        self.nbody = Self::create_nbody(
+            &self.device,
+            self.sim_mode,
+            &self.nbody_params,
+            self.nbody_grid_size,
+            self.nbody_tile_size,
+            self.compute_pipeline.as_ref(),
+            &self.position_buffer,
+        );
+        self.collisions = Self::create_collisions(
+            &self.device,
+            &self.sim_params,
+            self.compute_pipeline.as_ref(),
+            &self.position_buffer,
+        );
+        self.viewport.camera = initial_camera(
+            self.viewport.camera.aspect,
+            self.viewport.camera.depth.reversed,
+        );
+        scene.apply_camera(&mut self.viewport.camera);
+        self.viewport.zoom = ZoomController::default();
+        self.environment = Self::create_environment(
+            &self.device,
+            &self.queue,
+            self.scene_format,
+            &self.camera_bind_group_layout,
+            scene.environment,
+        );
+    }
+
+    /// The palette particles spawn with instead of the scene's: the one of the command line or the
+    /// script, else the accessibility one.
+    fn palette_override(&self) -> Option<SpawnPalette> {
+        self.palette
+            .or(self.accessibility.palette.map(SafePalette::spawn_palette))
+    }
+
+    /// Applies `accessibility`, respawning the particles if it changes their palette.
+    #[cfg(feature = "ui")]
+    fn set_accessibility(&mut self, accessibility: Accessibility) {
+        let previous = std::mem::replace(&mut self.accessibility, accessibility);
+        if (accessibility.display, accessibility.heatmap) != (previous.display, previous.heatmap) {
+            self.apply_display(accessibility);
+        }
+        let spawn_palette = self.palette_override().unwrap_or(self.scene_palette);
+        if spawn_palette != self.spawn_palette {
+            self.spawn_palette = spawn_palette;
+            self.reset_particles();
+        }
+    }
+
+    /// Adjusts the frame and colors the heatmap the way of `accessibility`.
+    fn apply_display(&mut self, accessibility: Accessibility) {
+        self.render_target
+            .set_display(&self.device, &self.queue, accessibility.display);
+        self.debug_pipelines
+            .set_heatmap_colors(accessibility.heatmap);
+    }
+
+    /// Respawns every particle as when the scene was loaded, writing over the current buffers so
+    /// pipelines and bind groups stay as they are.
+    fn reset_particles(&mut self) {
+        if !self.grid_cells.is_empty() {
+            log::warn!("Resetting isn't supported in the grid view");
+            return;
+        }
+        let count = self.instances.len();
+        let deterministic = self.deterministic;
+        // Respawned the same way as at startup, so a seed gives back the same particles
+        let gpu_compute_pipeline = self
+            .compute_pipeline
+            .as_ref()
+            .filter(|_| self.point_cloud.is_none() && particle_init::fits(&self.device, count));
+        let (instances, instances_cpu_data) = match gpu_compute_pipeline {
+            Some(compute_pipeline) => {
+                self.spawn_readback = Some(Self::spawn_on_gpu(
+                    &self.device,
+                    &self.queue,
+                    &self.spawn_emitter,
+                    &self.spawn_palette,
+                    particle_seed(self.spawn_seed, deterministic),
+                    count,
+                    &self.position_buffer,
+                    &self.color_buffer,
+                    compute_pipeline,
+                ));
+                Self::unspawned_particles(count)
+            }
+            None => {
+                let mut rng = particle_rng(self.spawn_seed, deterministic);
+                let (instances, instances_cpu_data) = Self::generate_particles(
+                    count,
+                    &self.spawn_emitter,
+                    &self.spawn_palette,
+                    self.point_cloud.as_ref(),
+                    &mut rng,
+                );
+                let instance_positions = instances
+                    .par_iter()
+                    .map(Instance::to_position)
+                    .collect::<Vec<_>>();
+                let instance_colors = instances
+                    .par_iter()
+                    .map(Instance::to_color)
+                    .collect::<Vec<_>>();
+                self.queue.write_buffer(
+                    &self.position_buffer,
+                    0,
+                    bytemuck::cast_slice(&instance_positions),
+                );
+                self.queue.write_buffer(
+                    &self.color_buffer,
+                    0,
+                    bytemuck::cast_slice(&instance_colors),
+                );
+                if let Some(compute_pipeline) = &self.compute_pipeline {
+                    compute_pipeline.write_cpu_data(&self.queue, 0, &instances_cpu_data);
+                }
+                (instances, instances_cpu_data)
+            }
+        };
+        self.instance_positions = instances.par_iter().map(Instance::to_position).collect();
+        self.instances = instances;
+        self.instances_cpu_data = instances_cpu_data;
+        self.dirty_instances = DirtyRanges::default();
+        // A pending checkpoint holds the particles from before the reset
+        self.checkpoint = None;
+        self.since_checkpoint = 0.0;
+        #[cfg(feature = "post-processing")]
+        if let Some(motion_blur) = &mut self.motion_blur {
+            motion_blur.reset();
+        }
+        if let Some(trails) = &mut self.trails {
+            let mut encoder = self
+                .device
+                .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                    label: Some("Trail Reset Encoder"),
+                });
+            trails.reset(&mut encoder, &self.position_buffer);
+            self.queue.submit(Some(encoder.finish()));
+        }
+    }
+
+    /// Placeholders for `count` particles spawned on the GPU, until they are read back.
+    fn unspawned_particles(count: usize) -> (Vec<Instance>, Vec<ParticleCpuData>) {
+        let instances = (0..count)
+            .map(|index| Instance {
+                position: glam::Vec3::ZERO,
+                color: glam::Vec4::ZERO,
+                group: groups::of(index),
+            })
+            .collect();
+        (instances, vec![ParticleCpuData::zeroed(); count])
+    }
+
+    /// Spawns `count` particles of `emitter` colored from `palette` into the buffers with the init
+    /// kernel, returning the readback of the spawned particles.
+    #[allow(clippy::too_many_arguments)]
+    fn spawn_on_gpu(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        emitter: &EmitterShape,
+        palette: &SpawnPalette,
+        seed: u64,
+        count: usize,
+        position_buffer: &wgpu::Buffer,
+        color_buffer: &wgpu::Buffer,
+        compute_pipeline: &ComputePipeline,
+    ) -> Checkpoint {
+        log::info!("Spawning {count} particles in a {emitter} on the GPU");
+        let cpu_data_buffer = &compute_pipeline.cpu_data_buffer;
+        let speed_layout = compute_pipeline.speed_layout;
+        particle_init::spawn(
+            device,
+            queue,
+            emitter,
+            palette,
+            seed,
+            count,
+            position_buffer,
+            color_buffer,
+            cpu_data_buffer,
+            speed_layout,
+        );
+        let sizes = vec![
+            (count * std::mem::size_of::<InstancePosition>()) as u64,
+            (count * std::mem::size_of::<InstanceColor>()) as u64,
+            (count * speed_layout.stride()) as u64,
+        ];
+        let mut readback = Checkpoint::new(device, sizes);
+        readback.start(
+            device,
+            queue,
+            &[position_buffer, color_buffer, cpu_data_buffer],
+        );
+        readback
+    }
+
+    /// Copies the particles spawned on the GPU to the CPU once they are readable, waiting for them
+    /// if `wait`.
+    fn take_spawn_readback(&mut self, wait: bool) {
+        let Some(readback) = &mut self.spawn_readback else {
+            return;
+        };
+        if wait {
+            self.device.poll(wgpu::Maintain::Wait);
+        }
+        let Some(contents) = readback.try_take(&self.device) else {
+            return;
+        };
+        self.spawn_readback = None;
+        bytemuck::cast_slice_mut(&mut self.instance_positions).copy_from_slice(&contents[0]);
+        // Spawned by the compute pipeline, in its layout
+        SpeedLayout::of(&self.device).decode(
+            &contents[2],
+            bytemuck::cast_slice_mut(&mut self.instances_cpu_data),
+        );
+        let mut colors = vec![InstanceColor::zeroed(); self.instances.len()];
+        bytemuck::cast_slice_mut(&mut colors).copy_from_slice(&contents[1]);
+        for ((instance, raw), color) in self
+            .instances
+            .iter_mut()
+            .zip(&self.instance_positions)
+            .zip(colors)
+        {
+            instance.position = raw.position.xyz();
+            instance.color = color.color();
+        }
+    }
+
+    /// The particles of the files given with `--points`, `--import` or `--emit-mesh`, if any.
+    #[cfg(feature = "asset-loaders")]
+    fn load_point_cloud(
+        options: &Options,
+        scene: &Scene,
+        deterministic: bool,
+    ) -> Result<Option<PointCloud>, AppError> {
+        Ok(
+            match (&options.points, &options.import, &options.emit_mesh) {
+                (Some(path), ..) => Some(PointCloud::load(path)?),
+                (_, Some(path), _) => Some(particle_data::import(path)?),
+                (_, _, Some(path)) => {
+                    let mut rng = particle_rng(scene.seed, deterministic);
+                    let count = scene.particles.unwrap_or(PARTICLE_COUNT);
+                    Some(TriangleMesh::load(path)?.sample(count, &mut rng))
+                }
+                _ => None,
+            },
+        )
+    }
+
+    /// Spawns `count` particles in `emitter`, or takes the first `count` points of `point_cloud`,
+    /// standing still unless it has speeds.
+    fn generate_particles(
+        count: usize,
+        emitter: &EmitterShape,
+        palette: &SpawnPalette,
+        point_cloud: Option<&PointCloud>,
+        rng: &mut impl Rng,
+    ) -> (Vec<Instance>, Vec<ParticleCpuData>) {
+        if let Some(point_cloud) = point_cloud {
+            let instances = point_cloud
+                .positions
+                .iter()
+                .take(count)
+                .enumerate()
+                .map(|(index, &position)| Instance {
+                    position,
+                    color: match &point_cloud.colors {
+                        Some(colors) => colors[index],
+                        None => palette
+                            .color(position, glam::Vec3::new(rng.gen(), rng.gen(), rng.gen())),
+                    },
+                    group: groups::of(index),
+                })
+                .collect::<Vec<_>>();
+            let instances_cpu_data = (0..instances.len())
+                .map(|index| ParticleCpuData {
+                    speed: point_cloud
+                        .speeds
+                        .as_ref()
+                        .map_or(glam::Vec3::ZERO, |speeds| speeds[index]),
+                    _step_dt: 0.0,
+                })
+                .collect();
+            return (instances, instances_cpu_data);
+        }
+        log::info!("Spawning {count} particles in a {emitter}");
+        let instances = (0..count)
+            .map(|index| Instance {
+                group: groups::of(index),
+                ..random_instance(rng, emitter, palette)
+            })
+            .collect::<Vec<_>>();
+        let instances_cpu_data = (0..count)
+            .map(|_| ParticleCpuData {
+                speed: random_speed(rng),
+                _step_dt: 0.0,
+            })
+            .collect();
+        (instances, instances_cpu_data)
+    }
+
+    /// Loads the environment of `source`, if any, logging why it can't be drawn.
+    fn create_environment(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        format: wgpu::TextureFormat,
+        camera_bind_group_layout: &wgpu::BindGroupLayout,
+        source: Option<EnvironmentSource>,
+    ) -> Option<Environment> {
+        Environment::new(device, queue, format, camera_bind_group_layout, source?)
+            .map_err(|e| log::error!("Falling back to the clear color: {e}"))
+            .ok()
+    }
+
+    /// Loads the wind of `source`, if any, logging why it can't blow.
+    fn load_wind(source: Option<&WindSource>) -> Option<WindField> {
+        WindField::load(source?)
+            .map_err(|e| log::error!("Unable to load the wind: {e}"))
+            .ok()
+    }
+
+    fn toggle_lod(&mut self) {
+        if self.lod.take().is_some() {
+            log::info!("Level of detail disabled");
+            return;
+        }
+
+        self.lod = Lod::new(
+            &self.device,
+            &self.capabilities,
+            self.arena.capacity(),
+            self.index_count,
+            &self.position_buffer,
+            &self.color_buffer,
+            self.pipeline_cache.shape_pipelines(
+                &self.device,
+                ParticleShape::Point,
+                self.blend_mode,
+            ),
+        );
+        match self.lod {
+            Some(_) => {
+                // Both copy the alive instances, the level of detail drops them as well
+                if self.compaction.take().is_some() {
+                    log::info!("Compaction disabled");
+                }
+                if let Some(stretched) = self.stretched.take() {
+                    log::info!("{} disabled", stretched.name());
+                }
+                if self.spinning.take().is_some() {
+                    log::info!("Spinning particles disabled");
+                }
+                if self.overlap.take().is_some() {
+                    log::info!("Overlapped rendering disabled");
+                }
+                log::info!("Level of detail enabled");
+            }
+            None => log::warn!("Level of detail isn't supported by this device"),
+        }
+    }
+
+    fn toggle_overlap(&mut self) {
+        if self.overlap.take().is_some() {
+            log::info!("Overlapped rendering disabled");
+            return;
+        }
+        if self.compute_pipeline.is_none() || !self.grid_cells.is_empty() {
+            log::warn!("Overlapped rendering needs the particles simulated on the GPU");
+            return;
+        }
+        // Both draw from the instances they copied from the live positions
+        if self.compaction.take().is_some() {
+            log::info!("Compaction disabled");
+        }
+        if self.lod.take().is_some() {
+            log::info!("Level of detail disabled");
+        }
+
+        self.overlap = Some(RenderSnapshot::new(
+            &self.device,
+            self.position_buffer.size(),
+        ));
+        log::info!("Overlapped rendering enabled");
+    }
+
+    /// Draws the particles stretched along their velocities or as Gaussian splats, depending on
+    /// `shape`, or back as they were if they already are.
+    fn toggle_stretched(&mut self, shape: ParticleShape) {
+        if let Some(stretched) = self.stretched.take() {
+            log::info!("{} disabled", stretched.name());
+            if stretched.shape() == shape {
+                return;
+            }
+        }
+        // Both reorder the instances, the velocities would belong to other particles
+        if self.compaction.take().is_some() {
+            log::info!("Compaction disabled");
+        }
+        if self.lod.take().is_some() {
+            log::info!("Level of detail disabled");
+        }
+        // Both take vertex buffer 3
+        if self.spinning.take().is_some() {
+            log::info!("Spinning particles disabled");
+        }
+
+        // Splats always add up
+        let blend_mode = match shape {
+            ParticleShape::Splat => BlendMode::Additive,
+            _ => self.blend_mode,
+        };
+        let stretched = Stretched::new(
+            &self.device,
+            self.arena.capacity(),
+            shape,
+            self.pipeline_cache
+                .shape_pipelines(&self.device, shape, blend_mode),
+        );
+        log::info!("{} enabled", stretched.name());
+        self.stretched = Some(stretched);
+    }
+
+    fn toggle_gizmos(&mut self) {
+        if self.gizmos.take().is_some() {
+            log::info!("Gizmos disabled");
+            return;
+        }
+        self.gizmos = Some(Gizmos::new(
+            &self.device,
+            self.scene_format,
+            &self.camera_bind_group_layout,
+        ));
+        log::info!("Gizmos enabled");
+    }
+
+    fn toggle_spinning(&mut self) {
+        if self.spinning.take().is_some() {
+            log::info!("Spinning particles disabled");
+            return;
+        }
+        // Both reorder the instances, the orientations would belong to other particles
+        if self.compaction.take().is_some() {
+            log::info!("Compaction disabled");
+        }
+        if self.lod.take().is_some() {
+            log::info!("Level of detail disabled");
+        }
+        if let Some(stretched) = self.stretched.take() {
+            log::info!("{} disabled", stretched.name());
+        }
+
+        self.spinning = Some(Spinning::new(
+            &self.device,
+            &self.queue,
+            self.arena.capacity(),
+            self.pipeline_cache.shape_pipelines(
+                &self.device,
+                ParticleShape::Spinning,
+                self.blend_mode,
+            ),
+        ));
+        log::info!("Spinning particles enabled");
+    }
+
+    fn toggle_compaction(&mut self) {
+        if self.compaction.take().is_some() {
+            log::info!("Compaction disabled");
+            return;
+        }
+        if self.lod.take().is_some() {
+            log::info!("Level of detail disabled");
+        }
+        if let Some(stretched) = self.stretched.take() {
+            log::info!("{} disabled", stretched.name());
+        }
+        if self.spinning.take().is_some() {
+            log::info!("Spinning particles disabled");
+        }
+        if self.overlap.take().is_some() {
+            log::info!("Overlapped rendering disabled");
+        }
+
+        self.compaction = Compaction::new(
+            &self.device,
+            self.arena.capacity(),
+            self.index_count,
+            &self.position_buffer,
+            &self.color_buffer,
+        );
+        match self.compaction {
+            Some(_) => log::info!("Compaction enabled"),
+            None => log::warn!("Not enough buffer space for compaction"),
+        }
+    }
+
+    /// Returns the boid steering of `compute_pipeline`, if simulating boids on the GPU.
+    fn create_boids(
+        device: &wgpu::Device,
+        sim_mode: SimMode,
+        params: &BoidsParams,
+        tile_size: u32,
+        compute_pipeline: Option<&ComputePipeline>,
+        position_buffer: &wgpu::Buffer,
+    ) -> Option<Boids> {
+        let compute_pipeline = compute_pipeline.filter(|_| sim_mode == SimMode::Boids)?;
+        let boids = Boids::new(
+            device,
+            compute_pipeline.capacity(),
+            params,
+            tile_size,
+            position_buffer,
+            &compute_pipeline.cpu_data_buffer,
+            compute_pipeline.speed_layout,
+        );
+        if boids.is_none() {
+            log::warn!("Too many particles to bind at once, the boids won't flock");
+        }
+        boids
+    }
+
+    /// Returns the gravity of `compute_pipeline`, if simulating n bodies on the GPU.
+    fn create_nbody(
+        device: &wgpu::Device,
+        sim_mode: SimMode,
+        params: &NbodyParams,
+        grid_size: u32,
+        tile_size: u32,
+        compute_pipeline: Option<&ComputePipeline>,
+        position_buffer: &wgpu::Buffer,
+    ) -> Option<Nbody> {
+        let compute_pipeline = compute_pipeline.filter(|_| sim_mode == SimMode::Nbody)?;
+        let nbody = Nbody::new(
+            device,
+            compute_pipeline.capacity(),
+            params,
+            grid_size,
+            tile_size,
+            position_buffer,
+            &compute_pipeline.cpu_data_buffer,
+            compute_pipeline.speed_layout,
+        );
+        if nbody.is_none() {
+            log::warn!("Too many particles to bind at once, the particles won't attract");
+        }
+        nbody
+    }
+
+    /// Returns the collisions of `compute_pipeline`, if simulating on the GPU with a collision
+    /// radius.
+    fn create_collisions(
+        device: &wgpu::Device,
+        sim_params: &SimParams,
+        compute_pipeline: Option<&ComputePipeline>,
+        position_buffer: &wgpu::Buffer,
+    ) -> Option<Collisions> {
+        let compute_pipeline = compute_pipeline.filter(|_| sim_params.collisions())?;
+        let collisions = Collisions::new(
+            device,
+            compute_pipeline.capacity(),
+            sim_params,
+            &compute_pipeline.sim_params_buffer,
+            position_buffer,
+            &compute_pipeline.cpu_data_buffer,
+            compute_pipeline.speed_layout,
+        );
+        if collisions.is_none() {
+            log::warn!("Too many particles to bind at once, the particles won't collide");
+        }
+        collisions
+    }
+
+    fn toggle_spatial_hash(&mut self) {
+        if self.spatial_hash.take().is_some() {
+            log::info!("Spatial hash disabled");
+            return;
+        }
+
+        let Some(spatial_hash) = SpatialHash::new(
+            &self.device,
+            self.arena.capacity(),
+            NEIGHBOR_CELL_SIZE,
+            &self.position_buffer,
+        ) else {
+            log::warn!("Not enough buffer space for the spatial hash");
+            return;
+        };
+        self.spatial_hash = Some(spatial_hash);
+        self.build_spatial_hash();
+        if let Some(stats) = self
+            .spatial_hash
+            .as_ref()
+            .and_then(|spatial_hash| spatial_hash.stats(&self.device, &self.queue))
+        {
+            log::info!("Spatial hash enabled: {stats}");
+        }
+    }
+
+    fn toggle_reduction(&mut self) {
+        if self.reduction.take().is_some() {
+            log::info!("Particle stats disabled");
+            self.refresh_title();
+            return;
+        }
+        if self.compute_pipeline.is_none() || !self.grid_cells.is_empty() {
+            log::warn!("Particle stats need the particles simulated on the GPU");
+            return;
+        }
+        // The reduction binds every position and speed at once
+        if !particle_init::fits(&self.device, self.arena.capacity()) {
+            log::warn!("Too many particles for the particle stats");
+            return;
+        }
+        self.reduction = Some(Reduction::new(&self.device, SpeedLayout::of(&self.device)));
+        log::info!("Particle stats enabled");
+    }
+
+    /// Moves the camera over the whole cloud once the particle stats have its bounds.
+    fn frame_particles(&mut self) {
+        if self.framing.is_some() {
+            return;
+        }
+        let enabled = self.reduction.is_none();
+        if enabled {
+            self.toggle_reduction();
+            if self.reduction.is_none() {
+                return;
+            }
+        }
+        self.framing = Some(enabled);
+    }
+
+    fn update_framing(&mut self) {
+        let Some(enabled) = self.framing else {
+            return;
+        };
+        let Some(reduction) = &self.reduction else {
+            self.framing = None;
+            return;
+        };
+        let Some(stats) = reduction.latest() else {
+            return;
+        };
+        self.camera_path = Some(framing::fit(
+            &self.viewport.camera,
+            stats.bounds_min,
+            stats.bounds_max,
+        ));
+        self.framing = None;
+        if enabled {
+            self.toggle_reduction();
+        }
+    }
+
+    /// Switches the stereo view from off to side by side, then to anaglyph and back off.
+    fn cycle_stereo_view(&mut self) {
+        let mode = match self.stereo_view.as_ref().map(StereoView::mode) {
+            None => Some(StereoMode::SideBySide),
+            Some(StereoMode::SideBySide) => Some(StereoMode::Anaglyph),
+            Some(StereoMode::Anaglyph) => None,
+        };
+        self.set_stereo_view(mode);
+    }
+
+    fn set_stereo_view(&mut self, mode: Option<StereoMode>) {
+        self.stereo_view = None;
+        let Some(mode) = mode else {
+            log::info!("Stereo view disabled");
+            return;
+        };
+        if !self.grid_cells.is_empty() {
+            log::warn!("The stereo view only shows the main particle system");
+            return;
+        }
+        let eyes = [0, 1].map(|_| {
+            let camera_buffer = self
+                .device
+                .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                    label: Some("Eye Camera Buffer"),
+                    contents: bytemuck::cast_slice(&[CameraUniform::new()]),
+                    usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                });
+            // Lit like the main window, whose lighting buffer follows the schedule
+            let camera_bind_group = Self::create_camera_bind_group(
+                &self.device,
+                &self.camera_bind_group_layout,
+                &camera_buffer,
+                &self.viewport.lighting_buffer,
+                &self.lights_buffer,
+                &self.gradient_buffer,
+            );
+            EyeCamera {
+                camera_buffer,
+                camera_bind_group,
+            }
+        });
+        self.stereo_view = Some(StereoView::new(&self.device, self.scene_format, mode, eyes));
+        log::info!("Stereo view: {mode}");
+    }
+
+    fn toggle_volume(&mut self) {
+        if self.volume.take().is_some() {
+            log::info!("Volume view disabled");
+            return;
+        }
+        if !self.grid_cells.is_empty() {
+            log::warn!("The volume view only shows the main particle system");
+            return;
+        }
+        // The splat pass binds every position at once
+        if !particle_init::fits(&self.device, self.arena.capacity()) {
+            log::warn!("Too many particles for the volume view");
+            return;
+        }
+        self.volume = Some(VolumeView::new(
+            &self.device,
+            self.scene_format,
+            &self.camera_bind_group_layout,
+        ));
+        log::info!("Volume view enabled");
+    }
+
+    /// Returns a closure recording this frame's step of the compute kernel, of the grid cells' or
+    /// of the main particles', if simulating on the GPU. It borrows only what can be shared with
+    /// the encoding thread.
+    fn kernel_encoder<'a>(&'a self) -> impl FnOnce() -> Vec<Submission<'a>> + Send + 'a {
+        let device = &self.device;
+        let grid_cells: &[GridCell] = if self.paused { &[] } else { &self.grid_cells };
+        let kernel_step = self
+            .compute_pipeline
+            .as_ref()
+            .filter(|_| !self.paused && self.grid_cells.is_empty())
+            .map(|compute_pipeline| KernelStep {
+                compute_pipeline,
+                substeps: self.sim_params.roi_substeps,
+                active_end: self.active_ranges().last().map_or(0, |range| range.end),
+                max_invocations_per_submit: self.max_invocations_per_submit,
+                #[cfg(feature = "metrics")]
+                gpu_timer: self.gpu_timer.as_ref(),
+            });
+        move || {
+            let mut submissions = Self::encode_grid_compute(device, grid_cells);
+            if let Some(kernel_step) = kernel_step {
+                submissions.extend(kernel_step.encode(device));
+            }
+            submissions
+        }
+    }
+
+    /// What follows the compute kernel's step, once it's submitted.
+    fn finish_simulation(&mut self, dt: f32) {
+        if !self.paused && self.grid_cells.is_empty() {
+            self.build_spatial_hash();
+        }
+        self.take_spawn_readback(false);
+        self.update_checkpoint(dt);
+    }
+
+    fn build_spatial_hash(&mut self) {
+        let active_end = self.active_ranges().last().map_or(0, |range| range.end);
+        let Some(spatial_hash) = &mut self.spatial_hash else {
+            return;
+        };
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Spatial Hash Encoder"),
+            });
+        spatial_hash.encode(&self.device, &self.queue, &mut encoder, active_end);
+        self.queue.submit(Some(encoder.finish()));
+    }
+
+    #[cfg(feature = "post-processing")]
+    fn toggle_obstacle_view(&mut self) {
+        if self.obstacle_view.take().is_some() {
+            log::info!("Obstacle view disabled");
+            return;
+        }
+        if self.obstacles.is_empty() {
+            log::warn!("The scene has no obstacles to show");
+            return;
+        }
+        self.obstacle_view = Some(ObstacleView::new(
+            &self.device,
+            self.scene_format,
+            &self.obstacles,
+        ));
+        log::info!("Obstacle view enabled: {} obstacles", self.obstacles.len());
+    }
+
+    fn set_blend_mode(&mut self, blend_mode: BlendMode) {
+        self.blend_mode = blend_mode;
+        // Their pipelines are picked when they're enabled
+        if self.lod.take().is_some() {
+            self.toggle_lod();
+        }
+        if let Some(stretched) = self.stretched.take() {
+            self.toggle_stretched(stretched.shape());
+        }
+        if self.spinning.take().is_some() {
+            self.toggle_spinning();
+        }
+        log::info!("Blend mode: {blend_mode}");
+    }
+
+    fn toggle_colors_by_age(&mut self) {
+        if self.gradient.is_none() {
+            log::warn!("The scene has no gradient to color the particles by age with");
+            return;
+        }
+        self.colors_by_age = !self.colors_by_age;
+        log::info!(
+            "Colors by age {}",
+            if self.colors_by_age {
+                "enabled"
+            } else {
+                "disabled"
+            }
+        );
+    }
+
+    #[cfg(feature = "ui")]
+    fn toggle_ui(&mut self) {
+        let Some(window) = &self.viewport.window else {
+            return;
+        };
+        if self.ui.take().is_none() {
+            self.ui = Some(Ui::new(&self.device, window, self.viewport.config.format));
+        }
+    }
+
+    /// Lays out the parameter panel, and applies what changed in it.
+    #[cfg(feature = "ui")]
+    fn run_ui(&mut self) {
+        let active_particles = self.active_count();
+        let (Some(ui), Some(window)) = (&mut self.ui, &self.viewport.window) else {
+            return;
+        };
+        let current = PanelValues {
+            time_scale: self.time_scale,
+            speed_multiplier: self.sim_params.speed_multiplier,
+            attractor_strength: self.sim_params.attractor_strength,
+            fovy: self.viewport.camera.fovy,
+            colors_by_age: self.gradient.as_ref().map(|_| self.colors_by_age),
+            blend_mode: self.blend_mode,
+            paused: self.paused,
+            reset: false,
+            accessibility: self.accessibility,
+            active_particles,
+            live_particles: self.arena.live_count(),
+            particle_stats: self.reduction.as_ref().and_then(Reduction::latest),
+            #[cfg(feature = "metrics")]
+            pass_times: self.pass_times,
+            #[cfg(feature = "metrics")]
+            pipeline_stats: self.pipeline_stats,
+            #[cfg(feature = "metrics")]
+            scene_pixels: {
+                let (width, height) = self.render_target.size();
+                width as u64 * height as u64
+            },
+        };
+        let mut values = current;
+        ui.run(
+            &self.device,
+            &self.queue,
+            window,
+            &mut values,
+            self.shader_error.as_deref(),
+        );
+        if values == current {
+            return;
+        }
+
+        if values.time_scale != current.time_scale {
+            self.set_time_scale(values.time_scale);
+        }
+        self.sim_params.speed_multiplier = values.speed_multiplier;
+        self.sim_params.attractor_strength = values.attractor_strength;
+        self.viewport.camera.fovy = values.fovy;
+        if let Some(colors_by_age) = values.colors_by_age {
+            self.colors_by_age = colors_by_age;
+        }
+        self.paused = values.paused;
+        if values.blend_mode != self.blend_mode {
+            self.set_blend_mode(values.blend_mode);
+        }
+        if values.active_particles != current.active_particles {
+            self.set_count_limit(values.active_particles);
+        }
+        if values.accessibility != current.accessibility {
+            self.set_accessibility(values.accessibility);
+        }
+        if values.reset {
+            self.reset_particles();
+        }
+    }
+
+    fn toggle_trails(&mut self) {
+        if self.trails.take().is_some() {
+            log::info!("Trails disabled");
+            return;
+        }
+
+        let Some(mut trails) = Trails::new(
+            &self.device,
+            self.scene_format,
+            &self.camera_bind_group_layout,
+            self.arena.capacity(),
+        ) else {
+            log::warn!("Not enough buffer space for trails");
+            return;
+        };
+
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Trail Reset Encoder"),
+            });
+        trails.reset(&mut encoder, &self.position_buffer);
+        self.queue.submit(Some(encoder.finish()));
+        self.trails = Some(trails);
+        log::info!("Trails enabled");
+    }
+
+    /// Renders the current frame at `width`x`height` in tiles and saves it as a png.
+    pub fn capture_high_res(
+        &mut self,
+        width: u32,
+        height: u32,
+        path: impl AsRef<Path>,
+    ) -> Result<(), CaptureError> {
+        let tile_size = self
+            .device
+            .limits()
+            .max_texture_dimension_2d
+            .min(CAPTURE_TILE_SIZE);
+
+        let mut camera = self.viewport.camera.clone();
+        camera.aspect = width as f32 / height as f32;
+        let view_projection = camera.view_projection();
+
+        let mut image = Image::new(width, height);
+        for tile in capture::tiles(width, height, tile_size) {
+            let pixels = self.render_offscreen(
+                tile.width,
+                tile.height,
+                view_projection.then(tile.crop_matrix(width, height)),
+            )?;
+            image.blit(tile, &pixels);
+        }
+
+        image.save_png(path)
+    }
+
+    /// Renders the six cubemap faces around the camera and saves them as a `width`x`height`
+    /// equirectangular panorama, converted on the GPU.
+    pub fn capture_panorama(
+        &mut self,
+        width: u32,
+        height: u32,
+        path: impl AsRef<Path>,
+    ) -> Result<(), CaptureError> {
+        // A face covers a quarter of the panorama horizontally
+        let face_size = (width / 4)
+            .max(1)
+            .min(self.device.limits().max_texture_dimension_2d);
+
+        let faces = capture::CUBE_FACES.each_ref().map(|face| {
+            let view_projection =
+                face.view_projection(self.viewport.camera.eye, &self.viewport.camera.depth);
+            self.render_offscreen_texture(face_size, face_size, view_projection)
+        });
+        let panorama = panorama::from_cube(
+            &self.device,
+            &self.queue,
+            &faces.each_ref().map(|face| &**face),
+            width,
+            height,
+        );
+        let pixels = capture::read_texture_rgba(&self.device, &self.queue, &panorama)?;
+        Image {
+            width,
+            height,
+            pixels,
+        }
+        .save_png(path)
+    }
+
+    /// Renders the scene from both eyes at `width`x`height` each and saves them combined
+    /// according to `mode`.
+    pub fn capture_stereo(
+        &mut self,
+        mode: StereoMode,
+        width: u32,
+        height: u32,
+        path: impl AsRef<Path>,
+    ) -> Result<(), CaptureError> {
+        let [left, right] = self
+            .stereo
+            .eye_view_projections(&self.viewport.camera, width as f32 / height as f32);
+        let left = self.render_offscreen(width, height, left)?;
+        let right = self.render_offscreen(width, height, right)?;
+        stereo::compose(mode, &left, &right, width, height).save_png(path)
+    }
+
+    /// Renders the particles seen through `view_projection` in a new `width`x`height` texture and reads
+    /// it back as RGBA8.
+    fn hash_frame(&mut self) {
+        let (width, height) = self.render_target.size();
+        let mut camera = self.viewport.camera.clone();
+        camera.aspect = width as f32 / height as f32;
+        let texture = self.render_offscreen_texture(width, height, camera.view_projection());
+        let Some(frame_hasher) = &self.frame_hasher else {
+            return;
+        };
+        match frame_hasher.hash(&self.device, &self.queue, &texture) {
+            Ok(hash) => println!("Frame {} hash: {hash:08x}", self.frame_count),
+            Err(e) => log::error!("Unable to hash frame {}: {e}", self.frame_count),
+        }
+        self.frame_count += 1;
+    }
+
+    /// Renders the current frame in a new `width`x`height` image, as recorded.
+    pub fn render_frame(&mut self, width: u32, height: u32) -> Result<Image, CaptureError> {
+        let mut camera = self.viewport.camera.clone();
+        camera.aspect = width as f32 / height as f32;
+        let pixels = self.render_offscreen(width, height, camera.view_projection())?;
+        Ok(Image {
+            width,
+            height,
+            pixels,
+        })
+    }
+
+    /// Renders the frame offscreen for the recording and the frame hash, if enabled.
+    fn render_outputs(&mut self) {
+        if self.recorder.is_some() {
+            self.record_frame();
+        }
+        if self.frame_hasher.is_some() {
+            self.hash_frame();
+        }
+    }
+
+    /// Renders the next frame of the recording at its size and writes it, ending the recording
+    /// if that fails.
+    fn record_frame(&mut self) {
+        let Some((width, height)) = self.recorder.as_ref().map(Recorder::size) else {
+            return;
+        };
+        let mut camera = self.viewport.camera.clone();
+        camera.aspect = width as f32 / height as f32;
+        let result = self
+            .render_offscreen(width, height, camera.view_projection())
+            .and_then(|rgba| match &mut self.recorder {
+                Some(recorder) => recorder.write_frame(rgba),
+                None => Ok(()),
+            });
+        if let Err(e) = result {
+            log::error!("Recording failed: {e}");
+            self.recorder = None;
+            self.recording_failed = true;
+        }
+    }
+
+    /// Renders the particles seen through `view_projection` in a new `width`x`height` texture and
+    /// reads it back as RGBA8.
+    fn render_offscreen(
+        &mut self,
+        width: u32,
+        height: u32,
+        view_projection: ViewProjection,
+    ) -> Result<Vec<u8>, CaptureError> {
+        let texture = self.render_offscreen_texture(width, height, view_projection);
+        capture::read_texture_rgba(&self.device, &self.queue, &texture)
+    }
+
+    /// Renders the scene into a texture of the frame graph pool, usable as a copy source and as a
+    /// texture binding. It goes back to the pool once dropped.
+    fn render_offscreen_texture(
+        &mut self,
+        width: u32,
+        height: u32,
+        view_projection: ViewProjection,
+    ) -> Arc<wgpu::Texture> {
+        let mut graph = FrameGraph::<State>::new();
+        let target = graph.texture(&wgpu::TextureDescriptor {
+            label: Some("Capture Target"),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: self.scene_format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                | wgpu::TextureUsages::COPY_SRC
+                | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+
+        // The camera buffer is written again before the next frame is rendered
+        let mut camera_uniform =
+            CameraUniform::from_view_projection(&view_projection, &self.viewport.camera.depth);
+        camera_uniform.set_group_mask(self.viewport.group_mask);
+        self.queue.write_buffer(
+            &self.viewport.camera_buffer,
+            0,
+            bytemuck::cast_slice(&[camera_uniform]),
+        );
+
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Capture Encoder"),
+            });
+        graph.pass(
+            "scene",
+            &[Resource::Positions, Resource::Colors, Resource::CpuData],
+            &[Resource::Texture(target)],
+            move |state, encoder, transients| {
+                let view = transients
+                    .texture(target)
+                    .create_view(&wgpu::TextureViewDescriptor::default());
+                // Captures are seen through `view_projection` alone, even in the stereo view
+                let stereo_view = state.stereo_view.take();
+                #[cfg(feature = "packed-instances")]
+                state.pack_positions(encoder);
+                state.prepare_scene((width, height));
+                state.encode_scene(encoder, &view);
+                state.stereo_view = stereo_view;
+            },
+        );
+        let transients = graph.allocate(&self.device, &mut self.transient_textures);
+        graph.execute(self, &mut encoder, &transients);
+        self.queue.submit(Some(encoder.finish()));
+        transients.texture(target).clone()
+    }
+
+    /// Creates the device, and the surface of `window` configured at `size` if there's one.
+    fn create_device(
+        window: Option<&Window>,
+        size: winit::dpi::PhysicalSize<u32>,
+        backend: Option<Backend>,
+        adapter: Option<&AdapterSelector>,
+        surface_format: Option<SurfaceFormat>,
+        present_mode: PresentMode,
+    ) -> Result<CreatedDevice, AppError> {
+        let backends = Backend::backends(backend);
+        let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
+            backends,
+            dx12_shader_compiler: Default::default(),
+        });
+
+        // # Safety
+        //
+        // The surface needs to live as long as the window that created it.
+        // The surface is only ever stored in the Viewport owning the window, so this is safe.
+        let surface = window
+            .map(|window| unsafe { instance.create_surface(window) })
+            .transpose()?;
+
+        let adapter = match adapter {
+            Some(selector) => {
+                let adapter = adapters::select(&instance, backends, selector)
+                    .ok_or_else(|| AppError::AdapterNotFound(selector.clone()))?;
+                if surface
+                    .as_ref()
+                    .is_some_and(|surface| !adapter.is_surface_supported(surface))
+                {
+                    return Err(AppError::AdapterCantPresent(selector.clone()));
+                }
+                adapter
+            }
+            None => pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
+                power_preference: wgpu::PowerPreference::HighPerformance,
+                compatible_surface: surface.as_ref(),
+                force_fallback_adapter: false,
+            }))
+            .ok_or(AppError::NoAdapter(backend))?,
+        };
+        log::info!("Using adapter {:?}", adapter.get_info());
+
+        // Optional features, only used when the adapter supports them
+        let capabilities = GpuCapabilities::of(&adapter);
+        let unavailable = capabilities
+            .report()
+            .into_iter()
+            .filter(|(_, available)| !available)
+            .map(|(name, _)| name)
+            .collect::<Vec<_>>();
+        if !unavailable.is_empty() {
+            log::info!("Unavailable on this GPU: {}", unavailable.join(", "));
+        }
+        let features = capabilities.features();
+
+        // Buffers are sized from the device limits, so the adapter's are asked for where larger
+        let adapter_limits = adapter.limits();
+        let limits = wgpu::Limits {
+            max_storage_buffer_binding_size: adapter_limits.max_storage_buffer_binding_size,
+            max_buffer_size: adapter_limits.max_buffer_size,
+            ..wgpu::Limits::default()
+        };
+
+        let (device, queue) = pollster::block_on(adapter.request_device(
+            &wgpu::DeviceDescriptor {
+                features,
+                limits,
+                label: Some("4"),
+            },
+            None,
+        ))?;
+
+        // Headless, the frames are only rendered offscreen, in any format
+        let surface_caps = match &surface {
+            Some(surface) => surface.get_capabilities(&adapter),
+            None => wgpu::SurfaceCapabilities {
+                formats: vec![surface_format.map_or(HEADLESS_FORMAT, |format| format.0)],
+                present_modes: vec![wgpu::PresentMode::Fifo],
+                alpha_modes: vec![wgpu::CompositeAlphaMode::Opaque],
+                usages: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            },
+        };
+
+        let format = surface_format::choose(&surface_caps.formats, surface_format)
+            .ok_or(AppError::NoSurfaceFormat)?;
+
+        let config = wgpu::SurfaceConfiguration {
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            format,
+            width: size.width,
+            height: size.height,
+            present_mode: pacing::choose_present_mode(&surface_caps.present_modes, present_mode),
+            alpha_mode: surface_caps.alpha_modes[0],
+            view_formats: vec![],
+        };
+
+        if let Some(surface) = &surface {
+            surface.configure(&device, &config);
+        }
+        Ok((instance, adapter, surface, device, queue, config))
+    }
+
+    pub fn create_camera_bindings(
+        device: &wgpu::Device,
+        camera_uniform: &CameraUniform,
+        look: &Keyframe,
+        lights_buffer: &wgpu::Buffer,
+        gradient_buffer: &wgpu::Buffer,
+    ) -> (
+        wgpu::Buffer,
+        wgpu::Buffer,
+        wgpu::BindGroupLayout,
+        wgpu::BindGroup,
+    ) {
+        let (camera_buffer, lighting_buffer) =
+            Self::create_camera_buffers(device, camera_uniform, look);
+
+        let camera_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                entries: &CAMERA_BIND_GROUP_LAYOUT_ENTRIES,
+                label: Some("camera_bind_group_layout"),
+            });
+
+        let camera_bind_group = Self::create_camera_bind_group(
+            device,
+            &camera_bind_group_layout,
+            &camera_buffer,
+            &lighting_buffer,
+            lights_buffer,
+            gradient_buffer,
+        );
+
+        (
+            camera_buffer,
+            lighting_buffer,
+            camera_bind_group_layout,
+            camera_bind_group,
+        )
+    }
+
+    fn create_camera_buffers(
+        device: &wgpu::Device,
+        camera_uniform: &CameraUniform,
+        look: &Keyframe,
+    ) -> (wgpu::Buffer, wgpu::Buffer) {
+        let camera_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Camera Buffer"),
+            contents: bytemuck::cast_slice(&[*camera_uniform]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let lighting_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Lighting Buffer"),
+            contents: bytemuck::cast_slice(&[LightingUniform::from(look)]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        (camera_buffer, lighting_buffer)
+    }
+
+    fn create_camera_bind_group(
+        device: &wgpu::Device,
+        camera_bind_group_layout: &wgpu::BindGroupLayout,
+        camera_buffer: &wgpu::Buffer,
+        lighting_buffer: &wgpu::Buffer,
+        lights_buffer: &wgpu::Buffer,
+        gradient_buffer: &wgpu::Buffer,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: camera_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: camera_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: lighting_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: lights_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: gradient_buffer.as_entire_binding(),
+                },
+            ],
+            label: Some("camera_bind_group"),
+        })
+    }
+
+    pub fn create_mesh_buffers(device: &wgpu::Device) -> (wgpu::Buffer, wgpu::Buffer) {
+        let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Vertex Buffer"),
+            contents: bytemuck::cast_slice(VERTICES),
+            usage: wgpu::BufferUsages::VERTEX,
+        });
+
+        let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Index Buffer"),
+            contents: bytemuck::cast_slice(INDICES),
+            usage: wgpu::BufferUsages::INDEX,
+        });
 
-                let tmp_buffer = Arc::new(self.device.create_buffer(&wgpu::BufferDescriptor {
-                    label: Some("Gang!"),
-                    mapped_at_creation: false,
-                    size: (std::mem::size_of::<InstanceRaw>() * self.instances_raw.len()) as _,
-                    usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
-                }));
+        (vertex_buffer, index_buffer)
+    }
 
-                encoder.copy_buffer_to_buffer(
-                    &self.instance_buffer,
-                    0,
-                    &tmp_buffer,
-                    0,
-                    tmp_buffer.size(),
-                );
-                self.queue.submit(Some(encoder.finish()));
+    /// Position, color and drawn position buffers sized for `capacity` instances, filled with the
+    /// live instances. The drawn positions are only a buffer of their own with the
+    /// `packed-instances` feature.
+    fn create_instance_buffers(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        capacity: usize,
+        instance_positions: &[InstancePosition],
+        instance_colors: &[InstanceColor],
+    ) -> (wgpu::Buffer, wgpu::Buffer, Option<wgpu::Buffer>) {
+        let position_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Position Buffer"),
+            size: (capacity * std::mem::size_of::<InstancePosition>()) as u64,
+            usage: wgpu::BufferUsages::VERTEX
+                | wgpu::BufferUsages::COPY_DST
+                | wgpu::BufferUsages::COPY_SRC
+                | wgpu::BufferUsages::STORAGE,
+            mapped_at_creation: false,
+        });
+        queue.write_buffer(
+            &position_buffer,
+            0,
+            bytemuck::cast_slice(instance_positions),
+        );
 
-                let tmp_clone = tmp_buffer.clone();
-                // let (sender, receiver) = futures::channel::oneshot::channel::<Vec<InstanceRaw>>();
-                tmp_buffer
-                    .slice(..)
-                    .map_async(wgpu::MapMode::Read, move |x| {
-                        x.unwrap();
-                        let gpu_data_bytes = tmp_clone
-                            .slice(..)
-                            .get_mapped_range()
-                            .iter()
-                            .copied()
-                            .collect::<Vec<_>>();
-                        let gpu_data: &[InstanceRaw] = bytemuck::cast_slice(&gpu_data_bytes);
-                        let gpu_data_vec = gpu_data.to_vec();
-                        // println!("YESSIR");
-                        // sender.send(gpu_data_vec).unwrap();
-                    });
+        // Colors never change, they are only uploaded once. Left zeroed when empty, for particles
+        // spawned on the GPU
+        let color_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Color Buffer"),
+            size: (capacity * std::mem::size_of::<InstanceColor>()) as u64,
+            usage: wgpu::BufferUsages::VERTEX
+                | wgpu::BufferUsages::COPY_DST
+                | wgpu::BufferUsages::COPY_SRC
+                | wgpu::BufferUsages::STORAGE,
+            mapped_at_creation: false,
+        });
+        queue.write_buffer(&color_buffer, 0, bytemuck::cast_slice(instance_colors));
 
-                // println!("Waiting!");
-                // self.instances_raw = futures::executor::block_on(receiver).unwrap();
-                // println!("Done!");
+        let drawn_position_buffer = vertex::create_drawn_position_buffer(device, capacity);
+        if let Some(drawn_position_buffer) = &drawn_position_buffer {
+            vertex::write_drawn_positions(queue, drawn_position_buffer, 0, instance_positions);
+        }
 
-                for (instance, raw) in self.instances.iter_mut().zip(&self.instances_raw) {
-                    instance.position = raw.model.w_axis.xyz();
-                }
+        (position_buffer, color_buffer, drawn_position_buffer)
+    }
+
+    /// Most particles a single storage binding of the device holds, a multiple of the binding
+    /// offset alignment so chunks of that size can be bound back to back.
+    fn particles_per_binding(device: &wgpu::Device) -> usize {
+        let limits = device.limits();
+        let particles = limits.max_storage_buffer_binding_size as usize / PARTICLE_SIZE;
+        // Chunks start at aligned offsets of the packed speeds too, whose stride is smaller
+        let alignment = (limits.min_storage_buffer_offset_alignment as usize)
+            .div_ceil(SpeedLayout::F16.stride());
+        (particles - particles % alignment).max(alignment)
+    }
+
+    /// Particles `scene` spawns on `device`, or `point_cloud` when given, within `max_memory`.
+    pub fn scene_particle_count(
+        device: &wgpu::Device,
+        scene: &Scene,
+        point_cloud: Option<&PointCloud>,
+        max_memory: Option<u64>,
+    ) -> usize {
+        if let Some(point_cloud) = point_cloud {
+            return Self::fit_particle_count(device, point_cloud.len(), true, max_memory);
+        }
+        Self::fit_particle_count(
+            device,
+            scene.particles.unwrap_or(PARTICLE_COUNT),
+            scene.particles.is_some(),
+            max_memory,
+        )
+    }
 
-                self.compute_pipeline = None;
+    /// Clamps `requested` particles to what the device can hold and to `max_memory` bytes. Only
+    /// explicitly requested counts go past a single binding, they are then simulated in chunks.
+    fn fit_particle_count(
+        device: &wgpu::Device,
+        requested: usize,
+        explicit: bool,
+        max_memory: Option<u64>,
+    ) -> usize {
+        let per_binding = Self::particles_per_binding(device);
+        let per_buffer = (device.limits().max_buffer_size / PARTICLE_SIZE as u64) as usize;
+        let limit = if explicit { per_buffer } else { per_binding };
+        if requested > limit {
+            log::warn!("Only {limit} of the {requested} requested particles fit on this device");
+        }
+        let mut count = requested.min(limit);
+        if let Some(max_memory) = max_memory {
+            let within = memory_budget::particles_within(max_memory, count, Self::memory_estimate);
+            if within < count {
+                log::warn!(
+                    "Only {within} of the {count} particles fit in --max-memory {}, needing {}",
+                    ByteSize(max_memory),
+                    ByteSize(Self::memory_estimate(count).total())
+                );
+                count = within;
             }
         }
-        false
+        log::info!(
+            "Particle memory of {count} particles: {}",
+            Self::memory_estimate(count)
+        );
+        if count > per_binding {
+            log::info!(
+                "Simulating {count} particles in {} chunks",
+                count.div_ceil(per_binding)
+            );
+        }
+        count
     }
 
-    pub fn resize(&mut self, new_size: winit::dpi::PhysicalSize<u32>) {
-        if new_size.width > 0 && new_size.height > 0 {
-            self.size = new_size;
-            self.config.width = new_size.width;
-            self.config.height = new_size.height;
-            self.surface.configure(&self.device, &self.config);
-            self.camera.aspect = new_size.width as f32 / new_size.height as f32;
+    /// Device memory of the buffers of `particles` particles.
+    fn memory_estimate(particles: usize) -> MemoryEstimate {
+        let instances = (particles
+            * (std::mem::size_of::<InstancePosition>() + std::mem::size_of::<InstanceColor>()))
+            as u64;
+        // Along with the positions they are packed from
+        #[cfg(feature = "packed-instances")]
+        let instances = instances + (particles * std::mem::size_of::<DrawnPosition>()) as u64;
+        let velocities = (particles * std::mem::size_of::<ParticleCpuData>()) as u64;
+        MemoryEstimate {
+            instances,
+            velocities,
+            // The CPU path uploads through the staging belt, particles spawned on the GPU are
+            // read back whole
+            staging: UPLOAD_CHUNK_SIZE + instances + velocities,
         }
     }
 
-    fn move_particles(&mut self) {
-        if let Some(compute_pipeline) = &self.compute_pipeline {
-            let mut encoder = self
-                .device
-                .create_command_encoder(&wgpu::CommandEncoderDescriptor {
-                    label: Some("Compute Encoder"),
-                });
+    /// Compute kernel of the particles of `instances_cpu_data`, whose positions start at
+    /// `position_offset` in `position_buffer`, a multiple of the storage binding offset alignment.
+    /// It writes them as drawn at the same offset of `drawn_position_buffer`, when they are drawn
+    /// from a buffer of their own. Their speeds are stored in `speed_layout`.
+    #[allow(clippy::too_many_arguments)]
+    fn create_compute_pipeline(
+        device: &wgpu::Device,
+        instances_cpu_data: &[ParticleCpuData],
+        position_buffer: &wgpu::Buffer,
+        position_offset: usize,
+        drawn_position_buffer: Option<&wgpu::Buffer>,
+        obstacles: &[Obstacle],
+        behaviors: &Behaviors,
+        emitter: &EmitterShape,
+        wind: WindTexture,
+        workgroup_size: u32,
+        speed_layout: SpeedLayout,
+    ) -> ComputePipeline {
+        let cpu_data_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Cpu Data Buffer"),
+            usage: wgpu::BufferUsages::STORAGE
+                | wgpu::BufferUsages::COPY_DST
+                | wgpu::BufferUsages::COPY_SRC,
+            contents: &speed_layout.encode(bytemuck::cast_slice(instances_cpu_data)),
+        });
 
-            {
-                let mut raytracing_pass = encoder.begin_compute_pass(&Default::default());
-                raytracing_pass.set_pipeline(&compute_pipeline.pipeline);
-                raytracing_pass.set_bind_group(0, &compute_pipeline.bind_group, &[]);
-                raytracing_pass.dispatch_workgroups(10_000, 150, 1);
-            }
+        let turbulence_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Turbulence Buffer"),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            contents: bytemuck::cast_slice(&[TurbulenceParams::default()]),
+        });
 
-            // let tmp = Arc::new(self.device.create_buffer(&wgpu::BufferDescriptor {
-            //     label: Some("Gang!"),
-            //     mapped_at_creation: false,
-            //     size: (std::mem::size_of::<InstanceRaw>() * self.instances_raw.len()) as _,
-            //     usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
-            // }));
+        let sim_params_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Sim Params Buffer"),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            contents: bytemuck::cast_slice(&[SimParams::default()]),
+        });
 
-            // encoder.copy_buffer_to_buffer(&self.instance_buffer, 0, &tmp, 0, tmp.size());
+        let dispatch_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Dispatch Buffer"),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            contents: bytemuck::cast_slice(&[DispatchParams::zeroed()]),
+        });
 
-            self.queue.submit(Some(encoder.finish()));
+        let obstacle_buffer = obstacles::create_buffer(device, obstacles);
 
-            // let tmp_clone = tmp.clone();
-            // tmp.slice(..).map_async(wgpu::MapMode::Read, move |x| {
-            //     x.unwrap();
-            //
-            //     let a = tmp_clone.slice(..).get_mapped_range().iter().copied().collect::<Vec<_>>();
-            //     let b: &[InstanceRaw] = bytemuck::cast_slice(&a);
-            //     for c in b {
-            //         // println!("gpu transformed: {c}");
-            //     }
-            // });
-            //
-            // let a = self.instances
-            //     .par_iter_mut()
-            //     .zip(&self.instances_cpu_data)
-            //     .map(|(instance, cpu_data)| {
-            //         instance.position += cpu_data.speed;
-            //         instance.to_raw()
-            //     })
-            //     .collect::<Vec<_>>();
-            //
-            // for b in a {
-            //     // println!("cpu transformed: {b}");
-            // }
-        } else {
-            // Move particles
-            self.instances
-                .par_iter_mut()
-                .zip(&self.instances_cpu_data)
-                .map(|(instance, cpu_data)| {
-                    instance.position += cpu_data.speed;
-                    instance.to_raw()
-                })
-                .collect_into_vec(&mut self.instances_raw);
+        let emitter_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Emitter Buffer"),
+            usage: wgpu::BufferUsages::UNIFORM,
+            contents: bytemuck::bytes_of(&EmitterParams::new(emitter)),
+        });
 
-            self.queue.write_buffer(
-                &self.instance_buffer,
-                0,
-                bytemuck::cast_slice(&self.instances_raw),
+        let mut bind_group_layout_entries = vec![
+            wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Storage { read_only: false },
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 1,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Storage { read_only: false },
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 2,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 3,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 4,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 5,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Storage { read_only: true },
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+            wind::BIND_GROUP_LAYOUT_ENTRIES[0],
+            wind::BIND_GROUP_LAYOUT_ENTRIES[1],
+            wgpu::BindGroupLayoutEntry {
+                binding: 8,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+        ];
+        if drawn_position_buffer.is_some() {
+            bind_group_layout_entries.push(wgpu::BindGroupLayoutEntry {
+                binding: 9,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Storage { read_only: false },
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            });
+        }
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &bind_group_layout_entries,
+            label: Some("1"),
+        });
+
+        let chunk_len = Self::particles_per_binding(device);
+        let count = instances_cpu_data.len();
+        let bind_groups = (0..count)
+            .step_by(chunk_len)
+            .map(|start| {
+                let range = start..(start + chunk_len).min(count);
+                let binding = |buffer, first: usize, element_size: usize| {
+                    wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                        buffer,
+                        offset: ((first + range.start) * element_size) as u64,
+                        size: wgpu::BufferSize::new((range.len() * element_size) as u64),
+                    })
+                };
+                let [wind_field, wind_params] = wind.bind_group_entries();
+                let mut entries = vec![
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: binding(&cpu_data_buffer, 0, speed_layout.stride()),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: binding(
+                            position_buffer,
+                            position_offset,
+                            std::mem::size_of::<InstancePosition>(),
+                        ),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: turbulence_buffer.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 3,
+                        resource: dispatch_buffer.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 4,
+                        resource: sim_params_buffer.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 5,
+                        resource: obstacle_buffer.as_entire_binding(),
+                    },
+                    wind_field,
+                    wind_params,
+                    wgpu::BindGroupEntry {
+                        binding: 8,
+                        resource: emitter_buffer.as_entire_binding(),
+                    },
+                ];
+                if let Some(drawn_position_buffer) = drawn_position_buffer {
+                    entries.push(wgpu::BindGroupEntry {
+                        binding: 9,
+                        resource: binding(
+                            drawn_position_buffer,
+                            position_offset,
+                            std::mem::size_of::<DrawnPosition>(),
+                        ),
+                    });
+                }
+                let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                    layout: &bind_group_layout,
+                    label: Some("2"),
+                    entries: &entries,
+                });
+                (range, bind_group)
+            })
+            .collect();
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            bind_group_layouts: &[&bind_group_layout],
+            ..Default::default()
+        });
+
+        let source = compute_kernel_source(behaviors, speed_layout);
+        #[cfg(feature = "guardrails")]
+        {
+            let reflection = guardrails::ShaderReflection::new("compute_kernel.wgsl", &source);
+            reflection.check_struct_size("CpuData", std::mem::size_of::<ParticleCpuData>());
+            reflection
+                .check_struct_size("InstancePosition", std::mem::size_of::<InstancePosition>());
+            reflection
+                .check_struct_size("TurbulenceParams", std::mem::size_of::<TurbulenceParams>());
+            reflection.check_struct_size("DispatchParams", std::mem::size_of::<DispatchParams>());
+            reflection.check_struct_size("SimParams", std::mem::size_of::<SimParams>());
+            reflection.check_struct_size("Obstacle", std::mem::size_of::<Obstacle>());
+            reflection.check_struct_size("WindParams", std::mem::size_of::<wind::WindParams>());
+            reflection.check_struct_size("EmitterParams", std::mem::size_of::<EmitterParams>());
+            reflection.check_bind_group_layout(0, &bind_group_layout_entries);
+            guardrails::check_buffer_size(
+                "Cpu Data Buffer",
+                &cpu_data_buffer,
+                instances_cpu_data.len(),
+                speed_layout.stride(),
+            );
+            guardrails::check_buffer_size(
+                "Position Buffer",
+                position_buffer,
+                instances_cpu_data.len(),
+                std::mem::size_of::<InstancePosition>(),
             );
+            if let Some(drawn_position_buffer) = drawn_position_buffer {
+                guardrails::check_buffer_size(
+                    "Drawn Position Buffer",
+                    drawn_position_buffer,
+                    instances_cpu_data.len(),
+                    std::mem::size_of::<DrawnPosition>(),
+                );
+            }
         }
+
+        let mut compute_pipeline = ComputePipeline {
+            passes: vec![],
+            workgroup_size,
+            layout: pipeline_layout,
+            source,
+            bind_groups,
+            cpu_data_buffer,
+            speed_layout,
+            turbulence_buffer,
+            dispatch_buffer,
+            sim_params_buffer,
+            _obstacle_buffer: obstacle_buffer,
+            _emitter_buffer: emitter_buffer,
+            _wind: wind,
+        };
+        compute_pipeline.set_workgroup_size(device, workgroup_size);
+        compute_pipeline
     }
+}
 
-    pub fn render(&mut self) -> Result<(), wgpu::SurfaceError> {
-        let start = std::time::Instant::now();
-        self.move_particles();
+/// Declares `store_drawn_position(index: u32, position: vec4<f32>)`, for the compute kernel to
+/// write the positions it moves as drawn, to the buffer bound at 9 with the `packed-instances`
+/// feature. The particles are drawn from the positions it moves otherwise.
+#[cfg(not(feature = "packed-instances"))]
+const DRAWN_POSITION_WGSL: &str = "
+fn store_drawn_position(index: u32, position: vec4<f32>) {}
+";
+#[cfg(feature = "packed-instances")]
+const DRAWN_POSITION_WGSL: &str = "
+@group(0) @binding(9)
+var<storage, read_write> drawn_positions: array<PackedPosition>;
+fn store_drawn_position(index: u32, position: vec4<f32>) {
+    drawn_positions[index] = pack_position(position);
+}
+";
 
-        let output = self.surface.get_current_texture()?;
-        let view = output
-            .texture
-            .create_view(&wgpu::TextureViewDescriptor::default());
+/// The source of the compute kernel, after the pieces it uses, the WGSL of `behaviors` and the
+/// speeds in `speed_layout`.
+pub fn compute_kernel_source(behaviors: &Behaviors, speed_layout: SpeedLayout) -> String {
+    format!(
+        "{OBSTACLES_WGSL}\n{WIND_WGSL}\n{EMITTER_WGSL}\n{INSTANCE_POSITION_WGSL}\n\
+         {DRAWN_POSITION_WGSL}\n{}\n{}\n{}",
+        behaviors.wgsl(),
+        speed_layout.wgsl(),
+        include_str!("compute_kernel.wgsl")
+    )
+}
 
-        let mut encoders = vec![];
+fn toggle_fullscreen(window: &Window) {
+    let fullscreen = match window.fullscreen() {
+        Some(_) => None,
+        None => Some(Fullscreen::Borderless(None)),
+    };
+    window.set_fullscreen(fullscreen);
+}
 
-        let mut render_encoder =
-            self.device
-                .create_command_encoder(&wgpu::CommandEncoderDescriptor {
-                    label: Some("Render Encoder"),
-                });
+/// Logs a summary of the sampled particles, for a rough idea of how the forces act on them.
+fn log_sample(readback: &Readback) {
+    if readback.is_empty() {
+        return;
+    }
+    let count = readback.len() as f32;
+    let (position_sum, speed_sum) = readback.iter().fold(
+        (glam::Vec3::ZERO, 0.0),
+        |(position_sum, speed_sum), (_, values)| {
+            let speed = values.velocity.map_or(0.0, glam::Vec3::length);
+            (position_sum + values.position, speed_sum + speed)
+        },
+    );
+    let mean_position = position_sum / count;
+    log::debug!(
+        "Sample of frame {}: {} particles | mean position ({:.1}, {:.1}, {:.1}) | mean speed {:.3}",
+        readback.frame,
+        readback.len(),
+        mean_position.x,
+        mean_position.y,
+        mean_position.z,
+        speed_sum / count
+    );
+}
 
-        {
-            let mut render_pass = render_encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-                label: Some("Render Pass"),
-                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                    view: &view,
-                    resolve_target: None,
-                    ops: wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(wgpu::Color {
-                            r: 0.0,
-                            g: 0.0,
-                            b: 0.0,
-                            a: 1.0,
-                        }),
-                        store: true,
-                    },
-                })],
-                depth_stencil_attachment: None,
-            });
+/// The camera the scene starts with, depth reversed with `reverse_z`.
+pub fn initial_camera(aspect: f32, reverse_z: bool) -> Camera {
+    Camera {
+        // position the camera one unit up and 2 units back
+        // +z is out of the screen
+        eye: (0.0, 1.0, 5000.0).into(),
+        // have it look at the origin
+        target: (0.0, 0.0, -100.0).into(),
+        // which way is "up"
+        up: glam::Vec3::Y,
+        aspect,
+        fovy: 20.0,
+        depth: DepthRange {
+            reversed: reverse_z,
+            ..DepthRange::default()
+        },
+    }
+}
 
-            render_pass.set_pipeline(&self.render_pipeline);
-            render_pass.set_bind_group(0, &self.camera_bind_group, &[]);
-            render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
-            render_pass.set_vertex_buffer(1, self.instance_buffer.slice(..));
-            render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
-            render_pass.draw_indexed(0..self.index_count, 0, 0..self.instances.len() as _);
-        }
+/// Whether the GPU can run `effect`, which loads depth textures in its shader. Warns if it can't
+/// rather than failing to build the shader.
+#[cfg(feature = "post-processing")]
+fn loads_depth(capabilities: &GpuCapabilities, effect: &str) -> bool {
+    if !capabilities.depth_texture_load {
+        log::warn!("{effect} unavailable, this backend can't load depth textures in shaders");
+    }
+    capabilities.depth_texture_load
+}
 
-        self.camera_uniform.update_view_proj(&self.camera);
-        self.queue.write_buffer(
-            &self.camera_buffer,
-            0,
-            bytemuck::cast_slice(&[self.camera_uniform]),
-        );
+/// A particle placed somewhere in the initial cloud.
+/// Random generator for the particles of a scene. Recordings and hashed runs start from the same
+/// particles every time, even without a seed.
+fn particle_rng(seed: Option<u64>, deterministic: bool) -> StdRng {
+    match seed {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None if deterministic => StdRng::seed_from_u64(0),
+        None => StdRng::from_entropy(),
+    }
+}
 
-        encoders.push(render_encoder.finish());
-        self.queue.submit(encoders);
-        output.present();
+/// Seed of the particles spawned on the GPU, see [`particle_rng`].
+fn particle_seed(seed: Option<u64>, deterministic: bool) -> u64 {
+    match seed {
+        Some(seed) => seed,
+        None if deterministic => 0,
+        None => rand::random(),
+    }
+}
 
-        let end = std::time::Instant::now();
-        let delta = end - start;
-        self.frame_time_samples[self.frame_time_index] = delta.as_micros() as f32;
-        self.frame_time_index = (self.frame_time_index + 1) % self.frame_time_samples.len();
-        let average_frame_time_us: f32 =
-            self.frame_time_samples.iter().sum::<f32>() / self.frame_time_samples.len() as f32;
-        println!(
-            "Frame time: {}ms | res: {}x{}",
-            average_frame_time_us / 1000.0,
-            self.size.width,
-            self.size.height
+pub fn random_instance(
+    rng: &mut impl Rng,
+    emitter: &EmitterShape,
+    palette: &SpawnPalette,
+) -> Instance {
+    let position = emitter.sample(rng);
+    let random = glam::Vec3::new(rng.gen(), rng.gen(), rng.gen());
+    let color = palette.color(position, random);
+    Instance {
+        position,
+        color,
+        group: 0,
+    }
+}
+
+/// Position and speed particle `index` respawns with at the end of its life on `frame`. Respawns
+/// follow the distributions of the compute kernel's, not its random sequence.
+fn respawn(emitter: &EmitterShape, index: usize, frame: u32) -> (glam::Vec3, glam::Vec3) {
+    let mut rng = StdRng::seed_from_u64((u64::from(frame) << 32) | index as u64);
+    (emitter.sample(&mut rng), random_speed(&mut rng))
+}
+
+/// Initial speed of a particle, in a random direction.
+pub fn random_speed(rng: &mut impl Rng) -> glam::Vec3 {
+    glam::Vec3::new(
+        rng.gen::<f32>() - 0.5,
+        rng.gen::<f32>() - 0.5,
+        rng.gen::<f32>() - 0.5,
+    )
+    .normalize()
+        / 5.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{behavior::BuiltinBehavior, sim_params::Boundary, test_device::device};
+
+    // Particles stepped on both sides, a few rows of the compute dispatch
+    const COUNT: usize = 25_000;
+    // Positions are hundreds of units from the origin, the GPU's sin and cos differ in the last bits
+    const EPSILON: f32 = 1e-3;
+
+    /// Particles of the default emitter, moving in random directions.
+    fn particles() -> (Vec<InstancePosition>, Vec<ParticleCpuData>) {
+        let (instances, cpu_data) = State::generate_particles(
+            COUNT,
+            &EmitterShape::default(),
+            &SpawnPalette::default(),
+            None,
+            &mut StdRng::seed_from_u64(1),
         );
-        Ok(())
+        let positions = instances.iter().map(Instance::to_position).collect();
+        (positions, cpu_data)
     }
 
-    fn create_compute_pipeline(
+    /// Steps the particles by a frame of the compute kernel, their speeds in `speed_layout`,
+    /// reading them back.
+    #[allow(clippy::too_many_arguments)]
+    fn gpu_step(
         device: &wgpu::Device,
-        instances_cpu_data: &[ParticleCpuData],
-        instance_buffer: &wgpu::Buffer,
-    ) -> ComputePipeline {
-        let cpu_data_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("Cpu Data Buffer"),
-            usage: wgpu::BufferUsages::STORAGE,
-            contents: bytemuck::cast_slice(instances_cpu_data),
-        });
+        queue: &wgpu::Queue,
+        sim_params: &SimParams,
+        turbulence: &TurbulenceParams,
+        behaviors: &Behaviors,
+        positions: &[InstancePosition],
+        cpu_data: &[ParticleCpuData],
+        speed_layout: SpeedLayout,
+    ) -> (Vec<InstancePosition>, Vec<ParticleCpuData>) {
+        let colors = vec![InstanceColor::zeroed(); positions.len()];
+        let (position_buffer, _color_buffer, drawn_position_buffer) =
+            State::create_instance_buffers(device, queue, positions.len(), positions, &colors);
+        let compute_pipeline = State::create_compute_pipeline(
+            device,
+            cpu_data,
+            &position_buffer,
+            0,
+            drawn_position_buffer.as_ref(),
+            &[],
+            behaviors,
+            &EmitterShape::default(),
+            WindTexture::new(device, queue, None),
+            DEFAULT_WORKGROUP_SIZE,
+            speed_layout,
+        );
+        queue.write_buffer(
+            &compute_pipeline.turbulence_buffer,
+            0,
+            bytemuck::cast_slice(&[*turbulence]),
+        );
+        queue.write_buffer(
+            &compute_pipeline.sim_params_buffer,
+            0,
+            bytemuck::cast_slice(&[*sim_params]),
+        );
+        let kernel_step = KernelStep {
+            compute_pipeline: &compute_pipeline,
+            substeps: sim_params.roi_substeps,
+            active_end: positions.len(),
+            max_invocations_per_submit: None,
+            #[cfg(feature = "metrics")]
+            gpu_timer: None,
+        };
+        encoding::submit(queue, kernel_step.encode(device));
+        let mut gpu_cpu_data = vec![ParticleCpuData::zeroed(); cpu_data.len()];
+        compute_pipeline.read_cpu_data(device, queue, &mut gpu_cpu_data);
+        (
+            State::read_back_buffer(device, queue, &position_buffer, positions.len()),
+            gpu_cpu_data,
+        )
+    }
 
-        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-            entries: &[
-                wgpu::BindGroupLayoutEntry {
-                    binding: 0,
-                    visibility: wgpu::ShaderStages::COMPUTE,
-                    ty: wgpu::BindingType::Buffer {
-                        ty: wgpu::BufferBindingType::Storage { read_only: false },
-                        has_dynamic_offset: false,
-                        min_binding_size: None,
-                    },
-                    count: None,
+    /// Steps the particles by a frame on the rayon pool, along `cpu_path`.
+    fn cpu_step(
+        cpu_path: CpuPath,
+        sim_params: &SimParams,
+        turbulence: &TurbulenceParams,
+        behaviors: &Behaviors,
+        positions: &mut [InstancePosition],
+        cpu_data: &mut [ParticleCpuData],
+    ) {
+        let cpu_step = CpuStep {
+            sim_params,
+            flow: &Flow {
+                turbulence,
+                wind: None,
+            },
+            obstacles: &[],
+            behaviors,
+            emitter: &EmitterShape::default(),
+        };
+        positions
+            .par_iter_mut()
+            .zip(cpu_data)
+            .enumerate()
+            .for_each(|(index, (raw, cpu_data))| {
+                raw.position = match cpu_path {
+                    CpuPath::Simd => {
+                        cpu_step.particle_simd(index, raw.position, &mut cpu_data.speed)
+                    }
+                    CpuPath::Scalar => cpu_step.particle(index, raw.position, &mut cpu_data.speed),
+                };
+            });
+    }
+
+    /// Asserts each particle of `gpu` is within `EPSILON` of the same one of `cpu`, and within
+    /// `precision` of the distance it travelled from `before`.
+    fn assert_close(
+        mode: &str,
+        before: &[InstancePosition],
+        gpu: &[InstancePosition],
+        cpu: &[InstancePosition],
+        precision: f32,
+    ) {
+        assert_eq!(gpu.len(), cpu.len(), "{mode}");
+        let (index, error) = gpu
+            .iter()
+            .zip(cpu)
+            .zip(before)
+            .map(|((gpu, cpu), before)| {
+                gpu.position.distance(cpu.position)
+                    - cpu.position.distance(before.position) * precision
+            })
+            .enumerate()
+            .fold((0, 0.0), |worst, (index, error)| {
+                if error > worst.1 {
+                    (index, error)
+                } else {
+                    worst
+                }
+            });
+        assert!(
+            error < EPSILON,
+            "{mode}: particle {index} is at {} on the GPU and {} on the CPU",
+            gpu[index],
+            cpu[index]
+        );
+    }
+
+    /// Parameters of each force on its own, and bounds of each kind.
+    fn modes() -> Vec<(&'static str, SimParams, Behaviors)> {
+        let base = SimParams {
+            frame: 1,
+            ..SimParams::default()
+        };
+        let bounded = |boundary| {
+            let mut sim_params = SimParams {
+                // Far enough for many particles to leave the box
+                speed_multiplier: 2000.0,
+                ..base
+            };
+            sim_params.toggle_bounds();
+            for axis in 0..3 {
+                sim_params.set_boundary(axis, boundary);
+            }
+            sim_params
+        };
+        vec![
+            ("turbulence", base, Behaviors::default()),
+            (
+                "attractor",
+                SimParams {
+                    attractor_strength: 50.0,
+                    ..base
                 },
-                wgpu::BindGroupLayoutEntry {
-                    binding: 1,
-                    visibility: wgpu::ShaderStages::COMPUTE,
-                    ty: wgpu::BindingType::Buffer {
-                        ty: wgpu::BufferBindingType::Storage { read_only: false },
-                        has_dynamic_offset: false,
-                        min_binding_size: None,
-                    },
-                    count: None,
+                Behaviors::default(),
+            ),
+            (
+                "force and damping",
+                SimParams {
+                    force: glam::Vec4::new(0.0, -30.0, 5.0, 0.0),
+                    damping: 0.5,
+                    ..base
                 },
-            ],
-            label: Some("1"),
-        });
-
-        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            layout: &bind_group_layout,
-            label: Some("2"),
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: cpu_data_buffer.as_entire_binding(),
+                Behaviors::default(),
+            ),
+            (
+                "explosion",
+                SimParams {
+                    impulse: glam::Vec4::new(100.0, -50.0, 0.0, 20.0),
+                    impulse_radius: 300.0,
+                    ..base
                 },
-                wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: instance_buffer.as_entire_binding(),
+                Behaviors::default(),
+            ),
+            (
+                "implosion",
+                SimParams {
+                    impulse: glam::Vec4::new(0.0, 0.0, 0.0, -20.0),
+                    impulse_radius: 300.0,
+                    ..base
                 },
-            ],
-        });
-
-        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-            bind_group_layouts: &[&bind_group_layout],
-            ..Default::default()
-        });
-
-        let cs_module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
-            label: Some("3"),
-            source: wgpu::ShaderSource::Wgsl(include_str!("compute_kernel.wgsl").into()),
-        });
-
-        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
-            label: Some("Compute Pipeline"),
-            layout: Some(&pipeline_layout),
-            module: &cs_module,
-            entry_point: "main",
-        });
+                Behaviors::default(),
+            ),
+            ("bounce", bounded(Boundary::Bounce), Behaviors::default()),
+            ("clamp", bounded(Boundary::Clamp), Behaviors::default()),
+            ("wrap", bounded(Boundary::Wrap), Behaviors::default()),
+            (
+                "region of interest",
+                SimParams {
+                    roi_min: glam::Vec4::new(-200.0, -200.0, -200.0, 0.0),
+                    roi_max: glam::Vec4::new(200.0, 200.0, 200.0, 0.0),
+                    roi_substeps: 4,
+                    coarse_interval: 3,
+                    ..base
+                },
+                Behaviors::default(),
+            ),
+            (
+                "spiral",
+                base,
+                Behaviors::new(&[BuiltinBehavior::Spiral {
+                    axis: glam::Vec3::Y,
+                    angular_speed: 0.5,
+                    climb: 10.0,
+                }]),
+            ),
+        ]
+    }
 
-        ComputePipeline {
-            pipeline,
-            bind_group,
-            cpu_data_buffer,
+    #[test]
+    fn gpu_step_matches_the_cpu_step() {
+        let Some((device, queue)) = device() else {
+            eprintln!("No adapter, skipping the compute kernel");
+            return;
+        };
+        let mut turbulence = TurbulenceParams::default();
+        turbulence.time = 1.5;
+        let (positions, cpu_data) = particles();
+        for speed_layout in [SpeedLayout::F32, SpeedLayout::F16] {
+            // Both sides start from the speeds as stored, halves are only rounded again when stored
+            let mut cpu_data = cpu_data.clone();
+            let speeds = bytemuck::cast_slice_mut(&mut cpu_data);
+            let stored = speed_layout.encode(speeds).to_vec();
+            speed_layout.decode(&stored, speeds);
+            let speed_precision = match speed_layout {
+                SpeedLayout::F32 => 0.0,
+                SpeedLayout::F16 => 1.0 / 1024.0,
+            };
+            for (mode, sim_params, behaviors) in modes() {
+                let (gpu_positions, gpu_cpu_data) = gpu_step(
+                    &device,
+                    &queue,
+                    &sim_params,
+                    &turbulence,
+                    &behaviors,
+                    &positions,
+                    &cpu_data,
+                    speed_layout,
+                );
+                for cpu_path in [CpuPath::Scalar, CpuPath::Simd] {
+                    let mode = format!("{mode} ({cpu_path:?}, {speed_layout:?} speeds)");
+                    let (mut cpu_positions, mut cpu_cpu_data) =
+                        (positions.clone(), cpu_data.clone());
+                    cpu_step(
+                        cpu_path,
+                        &sim_params,
+                        &turbulence,
+                        &behaviors,
+                        &mut cpu_positions,
+                        &mut cpu_cpu_data,
+                    );
+                    assert_close(
+                        &mode,
+                        &positions,
+                        &gpu_positions,
+                        &cpu_positions,
+                        speed_precision,
+                    );
+                    let max_speed_error = gpu_cpu_data
+                        .iter()
+                        .zip(&cpu_cpu_data)
+                        .map(|(gpu, cpu)| {
+                            gpu.speed.distance(cpu.speed) - cpu.speed.length() * speed_precision
+                        })
+                        .fold(0.0, f32::max);
+                    assert!(
+                        max_speed_error < EPSILON,
+                        "{mode}: speeds off by {max_speed_error}"
+                    );
+                }
+            }
         }
     }
 }
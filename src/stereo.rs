@@ -0,0 +1,99 @@
+//! Stereo pairs for quick 3D previews without a headset.
+
+use std::fmt::Display;
+
+use crate::{
+    camera::{Camera, ViewProjection},
+    capture::Image,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StereoMode {
+    /// Left eye on the left half, right eye on the right half
+    SideBySide,
+    /// Red channel from the left eye, green and blue from the right eye
+    Anaglyph,
+}
+
+impl Display for StereoMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StereoMode::SideBySide => write!(f, "side-by-side"),
+            StereoMode::Anaglyph => write!(f, "anaglyph"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct StereoSettings {
+    /// Distance between the two eyes, in world units
+    pub eye_separation: f32,
+    /// Distance from the camera at which both eyes see the same image, i.e. the depth of the
+    /// screen plane
+    pub convergence: f32,
+}
+
+impl Default for StereoSettings {
+    fn default() -> Self {
+        Self {
+            eye_separation: 40.0,
+            convergence: 4500.0,
+        }
+    }
+}
+
+impl StereoSettings {
+    /// View projections of the left and right eyes, with `aspect` the aspect ratio of each eye's
+    /// image.
+    ///
+    /// Both eyes look in the same direction and converge by shifting their frustum rather than by
+    /// rotating towards each other, which would introduce vertical parallax.
+    pub fn eye_view_projections(&self, camera: &Camera, aspect: f32) -> [ViewProjection; 2] {
+        let mut camera = camera.clone();
+        camera.aspect = aspect;
+        let right = (camera.target - camera.eye).cross(camera.up).normalize();
+        let focal_length = 1.0 / (camera.fovy.to_radians() / 2.0).tan() / aspect;
+
+        [-0.5, 0.5].map(|side| {
+            let offset = side * self.eye_separation;
+            let mut eye = camera.clone();
+            eye.eye += right * offset;
+            eye.target += right * offset;
+            // Points on the convergence plane straight ahead of the camera land in the middle of
+            // both images
+            let shift = focal_length * offset / self.convergence;
+            eye.view_projection()
+                .then(glam::Mat4::from_translation(glam::Vec3::new(
+                    shift, 0.0, 0.0,
+                )))
+        })
+    }
+}
+
+/// Combines the RGBA8 images of both eyes, each `width`x`height`.
+pub fn compose(mode: StereoMode, left: &[u8], right: &[u8], width: u32, height: u32) -> Image {
+    match mode {
+        StereoMode::SideBySide => {
+            let mut image = Image::new(width * 2, height);
+            let row_len = width as usize * 4;
+            for (row, out) in image.pixels.chunks_exact_mut(row_len * 2).enumerate() {
+                let src = row * row_len..(row + 1) * row_len;
+                out[..row_len].copy_from_slice(&left[src.clone()]);
+                out[row_len..].copy_from_slice(&right[src]);
+            }
+            image
+        }
+        StereoMode::Anaglyph => {
+            let mut image = Image::new(width, height);
+            for ((out, l), r) in image
+                .pixels
+                .chunks_exact_mut(4)
+                .zip(left.chunks_exact(4))
+                .zip(right.chunks_exact(4))
+            {
+                out.copy_from_slice(&[l[0], r[1], r[2], 255]);
+            }
+            image
+        }
+    }
+}
@@ -0,0 +1,241 @@
+//! Live stereo view, the scene drawn once per eye and combined on screen like the stereo
+//! captures.
+//!
+//! Each eye has its own camera buffer and texture, the particles and trails are drawn into both
+//! and a fullscreen pass puts them side by side or into the channels of an anaglyph. The volume
+//! and post-processing passes are left out.
+
+use crate::{
+    camera::{Camera, CameraUniform},
+    render_target::fullscreen_shader,
+    stereo::{StereoMode, StereoSettings},
+};
+
+/// Camera buffer and bind group of an eye, bound like a viewport's.
+pub struct EyeCamera {
+    pub camera_buffer: wgpu::Buffer,
+    pub camera_bind_group: wgpu::BindGroup,
+}
+
+struct EyeTextures {
+    size: (u32, u32),
+    // Kept alive for the views
+    _textures: [wgpu::Texture; 2],
+    views: [wgpu::TextureView; 2],
+    bind_group: wgpu::BindGroup,
+}
+
+pub struct StereoView {
+    mode: StereoMode,
+    format: wgpu::TextureFormat,
+    eyes: [EyeCamera; 2],
+    textures: Option<EyeTextures>,
+    bind_group_layout: wgpu::BindGroupLayout,
+    sampler: wgpu::Sampler,
+    pipeline: wgpu::RenderPipeline,
+}
+
+impl StereoView {
+    /// Draws the eyes in textures of `format`, the format of the scene, through the cameras of
+    /// `eyes`, left then right.
+    pub fn new(
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+        mode: StereoMode,
+        eyes: [EyeCamera; 2],
+    ) -> Self {
+        let texture_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                view_dimension: wgpu::TextureViewDimension::D2,
+                multisampled: false,
+            },
+            count: None,
+        };
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Stereo Bind Group Layout"),
+            entries: &[
+                texture_entry(0),
+                texture_entry(1),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        });
+
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Stereo Sampler"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        let source = fullscreen_shader(include_str!("stereo_view.wgsl"));
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Stereo Shader"),
+            source: wgpu::ShaderSource::Wgsl(source.as_str().into()),
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Stereo Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Stereo Pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_fullscreen",
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: match mode {
+                    StereoMode::SideBySide => "fs_side_by_side",
+                    StereoMode::Anaglyph => "fs_anaglyph",
+                },
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: None,
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+
+        Self {
+            mode,
+            format,
+            eyes,
+            textures: None,
+            bind_group_layout,
+            sampler,
+            pipeline,
+        }
+    }
+
+    pub fn mode(&self) -> StereoMode {
+        self.mode
+    }
+
+    /// Sizes the eye textures for a scene of `size` and writes the eye cameras of `camera`. Must
+    /// be called before [`StereoView::eyes`] every frame.
+    pub fn prepare(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        size: (u32, u32),
+        settings: &StereoSettings,
+        camera: &Camera,
+    ) {
+        let (width, height) = size;
+        let eye_size = match self.mode {
+            StereoMode::SideBySide => ((width / 2).max(1), height),
+            StereoMode::Anaglyph => size,
+        };
+        if self.textures.as_ref().map(|textures| textures.size) != Some(eye_size) {
+            self.textures = Some(self.create_textures(device, eye_size));
+        }
+
+        let aspect = eye_size.0 as f32 / eye_size.1 as f32;
+        let view_projections = settings.eye_view_projections(camera, aspect);
+        for (eye, view_projection) in self.eyes.iter().zip(view_projections) {
+            let camera_uniform =
+                CameraUniform::from_view_projection(&view_projection, &camera.depth);
+            queue.write_buffer(
+                &eye.camera_buffer,
+                0,
+                bytemuck::cast_slice(&[camera_uniform]),
+            );
+        }
+    }
+
+    /// Texture and camera bind group of the left then the right eye, to draw the scene with.
+    pub fn eyes(&self) -> impl Iterator<Item = (&wgpu::TextureView, &wgpu::BindGroup)> {
+        let textures = self
+            .textures
+            .as_ref()
+            .expect("StereoView::prepare wasn't called");
+        textures
+            .views
+            .iter()
+            .zip(self.eyes.iter().map(|eye| &eye.camera_bind_group))
+    }
+
+    /// Combines the eyes drawn this frame into `view`.
+    pub fn compose(&self, encoder: &mut wgpu::CommandEncoder, view: &wgpu::TextureView) {
+        let Some(textures) = &self.textures else {
+            return;
+        };
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Stereo Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                    store: true,
+                },
+            })],
+            depth_stencil_attachment: None,
+        });
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &textures.bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+
+    fn create_textures(&self, device: &wgpu::Device, (width, height): (u32, u32)) -> EyeTextures {
+        let textures = ["Left Eye Texture", "Right Eye Texture"].map(|label| {
+            device.create_texture(&wgpu::TextureDescriptor {
+                label: Some(label),
+                size: wgpu::Extent3d {
+                    width,
+                    height,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: self.format,
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                    | wgpu::TextureUsages::TEXTURE_BINDING,
+                view_formats: &[],
+            })
+        });
+        let views = textures
+            .each_ref()
+            .map(|texture| texture.create_view(&wgpu::TextureViewDescriptor::default()));
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Stereo Bind Group"),
+            layout: &self.bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&views[0]),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&views[1]),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::Sampler(&self.sampler),
+                },
+            ],
+        });
+        EyeTextures {
+            size: (width, height),
+            _textures: textures,
+            views,
+            bind_group,
+        }
+    }
+}
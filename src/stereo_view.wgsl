@@ -0,0 +1,26 @@
+// Combines the images of both eyes into the scene, side by side or as an anaglyph
+
+@group(0) @binding(0)
+var left_eye: texture_2d<f32>;
+@group(0) @binding(1)
+var right_eye: texture_2d<f32>;
+@group(0) @binding(2)
+var eye_sampler: sampler;
+
+// Left eye on the left half, right eye on the right half
+@fragment
+fn fs_side_by_side(in: FullscreenOutput) -> @location(0) vec4<f32> {
+    let uv = vec2<f32>(fract(in.uv.x * 2.0), in.uv.y);
+    // Both sampled outside of the branch, which must be in uniform control flow
+    let left = textureSample(left_eye, eye_sampler, uv);
+    let right = textureSample(right_eye, eye_sampler, uv);
+    return select(right, left, in.uv.x < 0.5);
+}
+
+// Red channel from the left eye, green and blue from the right eye
+@fragment
+fn fs_anaglyph(in: FullscreenOutput) -> @location(0) vec4<f32> {
+    let left = textureSample(left_eye, eye_sampler, in.uv);
+    let right = textureSample(right_eye, eye_sampler, in.uv);
+    return vec4<f32>(left.r, right.g, right.b, 1.0);
+}
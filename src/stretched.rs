@@ -0,0 +1,129 @@
+//! Stretched billboards: each quad faces the camera and is elongated along the particle's velocity
+//! as seen on screen, for motion streaks in fast simulations like n-body and attractors.
+//!
+//! The velocities are an extra per-instance vertex buffer, copied from the speeds of the compute
+//! passes or uploaded from the CPU simulation every frame, in the layout of the compute passes'
+//! speeds. Compaction and the level of detail
+//! reorder the instances, so they can't be combined with it.
+//!
+//! The same velocities shape Gaussian splats: each particle is a screen-space Gaussian, its 3D
+//! covariance elongated along the velocity and projected in the vertex shader, its falloff
+//! evaluated in the fragment shader and added up, for smooth point clouds.
+
+use std::{ops::Range, sync::Arc};
+
+use crate::{pipeline_cache::ParticleShape, speed_layout::SpeedLayout};
+
+pub struct Stretched {
+    // ParticleShape::Stretched or ParticleShape::Splat
+    shape: ParticleShape,
+    capacity: usize,
+    speed_layout: SpeedLayout,
+    velocity_buffer: wgpu::Buffer,
+    // For each depth-stencil format of the particles pass
+    pipelines: Vec<(Option<wgpu::TextureFormat>, Arc<wgpu::RenderPipeline>)>,
+}
+
+impl Stretched {
+    pub fn new(
+        device: &wgpu::Device,
+        capacity: usize,
+        shape: ParticleShape,
+        pipelines: Vec<(Option<wgpu::TextureFormat>, Arc<wgpu::RenderPipeline>)>,
+    ) -> Self {
+        let speed_layout = SpeedLayout::of(device);
+        Self {
+            shape,
+            capacity,
+            speed_layout,
+            velocity_buffer: Self::create_velocity_buffer(device, capacity, speed_layout),
+            pipelines,
+        }
+    }
+
+    pub fn shape(&self) -> ParticleShape {
+        self.shape
+    }
+
+    /// What the particles are drawn as, for the logs.
+    pub fn name(&self) -> &'static str {
+        match self.shape {
+            ParticleShape::Splat => "Gaussian splats",
+            _ => "Stretched particles",
+        }
+    }
+
+    fn create_velocity_buffer(
+        device: &wgpu::Device,
+        capacity: usize,
+        speed_layout: SpeedLayout,
+    ) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Velocity Buffer"),
+            size: (capacity.max(1) * speed_layout.stride()) as u64,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    /// Speed of each instance at location 5, with the step dt of the compute passes in w.
+    pub fn descriptor(speed_layout: SpeedLayout) -> wgpu::VertexBufferLayout<'static> {
+        static ATTRIBUTES: [[wgpu::VertexAttribute; 1]; 2] = [
+            wgpu::vertex_attr_array![5 => Float32x4],
+            wgpu::vertex_attr_array![5 => Float16x4],
+        ];
+
+        wgpu::VertexBufferLayout {
+            array_stride: speed_layout.stride() as u64,
+            step_mode: wgpu::VertexStepMode::Instance,
+            attributes: match speed_layout {
+                SpeedLayout::F32 => &ATTRIBUTES[0],
+                SpeedLayout::F16 => &ATTRIBUTES[1],
+            },
+        }
+    }
+
+    /// Grows the velocity buffer to `capacity` instances if needed.
+    pub fn resize(&mut self, device: &wgpu::Device, capacity: usize) {
+        if capacity != self.capacity {
+            self.capacity = capacity;
+            self.velocity_buffer =
+                Self::create_velocity_buffer(device, capacity, self.speed_layout);
+        }
+    }
+
+    /// Uploads the speeds of the CPU simulation.
+    pub fn upload(&self, queue: &wgpu::Queue, speeds: &[[f32; 4]]) {
+        let speeds = &speeds[..self.capacity.min(speeds.len())];
+        queue.write_buffer(&self.velocity_buffer, 0, &self.speed_layout.encode(speeds));
+    }
+
+    /// Copies the speeds of the compute passes.
+    pub fn copy(&self, encoder: &mut wgpu::CommandEncoder, speed_buffer: &wgpu::Buffer) {
+        let size = ((self.capacity * self.speed_layout.stride()) as u64).min(speed_buffer.size());
+        encoder.copy_buffer_to_buffer(speed_buffer, 0, &self.velocity_buffer, 0, size);
+    }
+
+    /// Draws the `ranges` of instances with the quad bound to vertex buffer 0, and the positions
+    /// and colors to 1 and 2.
+    pub fn draw<'a>(
+        &'a self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        depth_format: Option<wgpu::TextureFormat>,
+        index_count: u32,
+        ranges: &[Range<usize>],
+    ) {
+        let Some((_, pipeline)) = self
+            .pipelines
+            .iter()
+            .find(|(format, _)| *format == depth_format)
+        else {
+            return;
+        };
+        render_pass.set_pipeline(pipeline);
+        render_pass.set_vertex_buffer(3, self.velocity_buffer.slice(..));
+        for range in ranges {
+            render_pass.draw_indexed(0..index_count, 0, range.start as u32..range.end as u32);
+        }
+    }
+}
@@ -0,0 +1,88 @@
+//! Picks the format of the window surface, and the format the scene is drawn in.
+//!
+//! sRGB surfaces are drawn into directly. Some adapters and compositors only expose linear
+//! formats, the scene is then drawn into an sRGB offscreen target and the blit to the surface
+//! encodes the gamma in the shader.
+
+use std::{fmt::Display, str::FromStr};
+
+use wgpu::TextureFormat;
+
+/// Scene format on surfaces without an sRGB format. Captures read it as 8 bits per channel.
+const LINEAR_SURFACE_SCENE_FORMAT: TextureFormat = TextureFormat::Rgba8UnormSrgb;
+
+/// Surface format given with `--surface-format`, named as in WebGPU.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SurfaceFormat(pub TextureFormat);
+
+const NAMES: &[(&str, TextureFormat)] = &[
+    ("bgra8unorm-srgb", TextureFormat::Bgra8UnormSrgb),
+    ("rgba8unorm-srgb", TextureFormat::Rgba8UnormSrgb),
+    ("bgra8unorm", TextureFormat::Bgra8Unorm),
+    ("rgba8unorm", TextureFormat::Rgba8Unorm),
+    ("rgb10a2unorm", TextureFormat::Rgb10a2Unorm),
+    ("rgba16float", TextureFormat::Rgba16Float),
+];
+
+impl FromStr for SurfaceFormat {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        NAMES
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(s))
+            .map(|&(_, format)| SurfaceFormat(format))
+            .ok_or(())
+    }
+}
+
+impl Display for SurfaceFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match NAMES.iter().find(|(_, format)| *format == self.0) {
+            Some((name, _)) => write!(f, "{name}"),
+            None => write!(f, "{:?}", self.0),
+        }
+    }
+}
+
+/// The requested format if the surface supports it, else its first sRGB format, else its first
+/// format.
+pub fn choose(
+    supported: &[TextureFormat],
+    requested: Option<SurfaceFormat>,
+) -> Option<TextureFormat> {
+    if let Some(requested) = requested {
+        if supported.contains(&requested.0) {
+            return Some(requested.0);
+        }
+        log::warn!("The surface doesn't support {requested}, supported: {supported:?}");
+    }
+    let format = supported
+        .iter()
+        .copied()
+        .find(TextureFormat::is_srgb)
+        .or_else(|| supported.first().copied())?;
+    if !format.is_srgb() {
+        log::info!("No sRGB surface format, encoding the gamma of {format:?} in the shader");
+    }
+    Some(format)
+}
+
+/// Format the scene is drawn in on a surface of `surface_format`.
+pub fn scene_format(surface_format: TextureFormat) -> TextureFormat {
+    if surface_format.is_srgb() {
+        surface_format
+    } else {
+        LINEAR_SURFACE_SCENE_FORMAT
+    }
+}
+
+/// True if the blit to a surface of `surface_format` has to encode the gamma. Float surfaces are
+/// presented as linear.
+pub fn encodes_gamma(surface_format: TextureFormat) -> bool {
+    !surface_format.is_srgb()
+        && !matches!(
+            surface_format,
+            TextureFormat::Rgba16Float | TextureFormat::Rgba32Float
+        )
+}
@@ -0,0 +1,40 @@
+//! Backoff for a main window surface that fails to hand out frames. A lost or outdated surface is
+//! reconfigured at the window's current size, which usually fixes it on the next frame; when it
+//! doesn't, the retries and the warnings get further and further apart.
+
+use std::time::{Duration, Instant};
+
+const FIRST_DELAY: Duration = Duration::from_millis(10);
+const MAX_DELAY: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Default)]
+pub struct SurfaceRecovery {
+    // Frames failed in a row
+    failures: u32,
+    // No reconfiguration before then
+    retry_at: Option<Instant>,
+}
+
+impl SurfaceRecovery {
+    /// True if it's time to try reconfiguring the surface again.
+    pub fn should_retry(&self, now: Instant) -> bool {
+        self.retry_at.is_none_or(|retry_at| now >= retry_at)
+    }
+
+    /// Records a failed attempt, and pushes the next retry back. Returns true if it should be
+    /// logged, on the first failure and then each time their count doubles.
+    pub fn failed(&mut self, now: Instant) -> bool {
+        self.failures += 1;
+        let delay = FIRST_DELAY
+            .saturating_mul(1 << (self.failures - 1).min(16))
+            .min(MAX_DELAY);
+        self.retry_at = Some(now + delay);
+        self.failures.is_power_of_two()
+    }
+
+    /// Records a frame presented, returns how many failed before it.
+    pub fn succeeded(&mut self) -> u32 {
+        self.retry_at = None;
+        std::mem::take(&mut self.failures)
+    }
+}
@@ -0,0 +1,163 @@
+//! Telemetry endpoint, to monitor and steer headless runs on remote machines over HTTP.
+//!
+//! `GET /stats` returns the latest stats as JSON. `POST /pause`, `POST /resume` and `POST /reset`
+//! pause, resume and respawn the simulation, and `POST /particles?count=N` sets how many of the
+//! spawned particles are simulated.
+//!
+//! `--telemetry-port` only listens on the loopback interface, anything else has to be given
+//! explicitly with `--telemetry-listen`, e.g. `0.0.0.0:9000` for every interface.
+
+use std::{
+    fmt::Write as _,
+    io::{self, BufRead, BufReader, Write},
+    net::{SocketAddr, TcpListener, TcpStream},
+    sync::{
+        mpsc::{self, Receiver, Sender},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use crate::metrics::Metrics;
+
+// How long a client gets to send its request, so a stalled one doesn't block the others.
+const READ_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// What the endpoint asks the simulation to do.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TelemetryCommand {
+    Pause,
+    Resume,
+    Reset,
+    SetParticleCount(usize),
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Stats {
+    pub metrics: Metrics,
+    pub paused: bool,
+}
+
+pub struct Telemetry {
+    latest: Arc<Mutex<Stats>>,
+    commands: Receiver<TelemetryCommand>,
+}
+
+impl Telemetry {
+    /// Serves the endpoint on `address`.
+    pub fn new(address: SocketAddr) -> io::Result<Self> {
+        let latest = Arc::new(Mutex::new(Stats::default()));
+        let (sender, commands) = mpsc::channel();
+
+        let listener = TcpListener::bind(address)?;
+        log::info!(
+            "Serving telemetry on http://{}/stats",
+            listener.local_addr()?
+        );
+        let served = latest.clone();
+        std::thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                if let Err(e) = respond(stream, &served, &sender) {
+                    log::warn!("Unable to serve telemetry: {e}");
+                }
+            }
+        });
+
+        Ok(Self { latest, commands })
+    }
+
+    pub fn publish(&self, stats: Stats) {
+        *self.latest.lock().unwrap() = stats;
+    }
+
+    /// The next command received since the last call, if any.
+    pub fn try_command(&self) -> Option<TelemetryCommand> {
+        self.commands.try_recv().ok()
+    }
+}
+
+fn respond(
+    stream: TcpStream,
+    latest: &Mutex<Stats>,
+    commands: &Sender<TelemetryCommand>,
+) -> io::Result<()> {
+    stream.set_read_timeout(Some(READ_TIMEOUT))?;
+    // Only the request line matters, the rest is read up to its blank line
+    let mut reader = BufReader::new(&stream);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    let mut line = String::new();
+    while reader.read_line(&mut line)? > 0 && line.trim_end() != "" {
+        line.clear();
+    }
+
+    let mut parts = request_line.split_whitespace();
+    let (method, target) = (parts.next().unwrap_or(""), parts.next().unwrap_or(""));
+    let (status, body) = match route(method, target) {
+        Ok(None) => ("200 OK", stats_json(&latest.lock().unwrap())),
+        Ok(Some(command)) => {
+            log::info!("Telemetry command: {command:?}");
+            // The simulation only stops receiving when it exits
+            let _ = commands.send(command);
+            ("200 OK", "{\"ok\":true}".to_string())
+        }
+        Err(status) => (status, format!("{{\"error\":\"{status}\"}}")),
+    };
+
+    let mut stream = &stream;
+    write!(
+        stream,
+        "HTTP/1.1 {status}\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\
+        Connection: close\r\n\r\n{body}",
+        body.len()
+    )
+}
+
+/// The command of a request, `None` for the stats, or the status of the error response.
+fn route(method: &str, target: &str) -> Result<Option<TelemetryCommand>, &'static str> {
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    match (method, path) {
+        ("GET", "/stats") => Ok(None),
+        ("POST", "/pause") => Ok(Some(TelemetryCommand::Pause)),
+        ("POST", "/resume") => Ok(Some(TelemetryCommand::Resume)),
+        ("POST", "/reset") => Ok(Some(TelemetryCommand::Reset)),
+        ("POST", "/particles") => query
+            .split('&')
+            .find_map(|pair| pair.strip_prefix("count="))
+            .and_then(|count| count.parse().ok())
+            .filter(|&count| count > 0)
+            .map(|count| Some(TelemetryCommand::SetParticleCount(count)))
+            .ok_or("400 Bad Request"),
+        (_, "/stats" | "/pause" | "/resume" | "/reset" | "/particles") => {
+            Err("405 Method Not Allowed")
+        }
+        _ => Err("404 Not Found"),
+    }
+}
+
+fn stats_json(stats: &Stats) -> String {
+    let metrics = &stats.metrics;
+    let optional = |value: Option<f64>| value.map_or("null".to_string(), |value| value.to_string());
+    let fps = if metrics.frame_time_ms > 0.0 {
+        1000.0 / metrics.frame_time_ms
+    } else {
+        0.0
+    };
+    let mut json = String::new();
+    let _ = write!(
+        json,
+        "{{\"fps\":{fps},\"frame_time_ms\":{},\"paused\":{},\"active_particles\":{},\
+        \"total_particles\":{},\"gpu_ms\":{{\"compute\":{},\"render\":{},\"neighbors\":{}}},\
+        \"gpu_buffer_bytes\":{},\"resident_bytes\":{}}}",
+        metrics.frame_time_ms,
+        stats.paused,
+        metrics.active_particles,
+        metrics.total_particles,
+        optional(metrics.compute_pass_ms),
+        optional(metrics.render_pass_ms),
+        optional(metrics.neighbors_pass_ms),
+        metrics.gpu_buffer_bytes,
+        optional(metrics.resident_bytes.map(|bytes| bytes as f64)),
+    );
+    json
+}
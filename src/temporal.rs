@@ -0,0 +1,321 @@
+//! Temporal accumulation, smoothing the shimmer of small particles over a few frames.
+//!
+//! While enabled, the scene is drawn into textures owned here with a depth buffer, then blended
+//! with a history of the previous frames. Each pixel finds where it was in the history with the
+//! previous view projection of the camera, and the history is clamped to the colors around the
+//! pixel so moving particles don't leave ghosts behind. Two history textures take turns being read
+//! and written.
+
+use bytemuck::{Pod, Zeroable};
+
+use crate::render_target::{fullscreen_shader, DEPTH_FORMAT};
+
+// Must match `DEPTH_SCALE` in depth_of_field.rs
+const DEPTH_SCALE: f32 = 1000.0;
+// Weight of the history in every frame, the higher the smoother and the more it lags
+const HISTORY_WEIGHT: f32 = 0.9;
+
+// Must match TemporalParams in temporal.wgsl
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Pod, Zeroable)]
+struct TemporalParams {
+    history_weight: f32,
+    depth_scale: f32,
+    _padding: [f32; 2],
+}
+
+struct Targets {
+    size: (u32, u32),
+    // Kept alive for the views
+    _color_texture: wgpu::Texture,
+    color_view: wgpu::TextureView,
+    _depth_texture: wgpu::Texture,
+    depth_view: wgpu::TextureView,
+    _history_textures: [wgpu::Texture; 2],
+    history_views: [wgpu::TextureView; 2],
+    // Reading each history texture
+    bind_groups: [wgpu::BindGroup; 2],
+    // History written this frame, the other one is read
+    written: usize,
+    // No history until a frame was written
+    has_history: bool,
+}
+
+pub struct TemporalAccumulation {
+    enabled: bool,
+    format: wgpu::TextureFormat,
+    pipeline: wgpu::RenderPipeline,
+    bind_group_layout: wgpu::BindGroupLayout,
+    sampler: wgpu::Sampler,
+    params_buffer: wgpu::Buffer,
+    targets: Option<Targets>,
+}
+
+impl TemporalAccumulation {
+    pub fn new(
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+        camera_bind_group_layout: &wgpu::BindGroupLayout,
+        enabled: bool,
+    ) -> Self {
+        let texture_entry = |binding, sample_type| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                sample_type,
+                view_dimension: wgpu::TextureViewDimension::D2,
+                multisampled: false,
+            },
+            count: None,
+        };
+        let bind_group_layout_entries = [
+            texture_entry(0, wgpu::TextureSampleType::Float { filterable: false }),
+            texture_entry(1, wgpu::TextureSampleType::Depth),
+            texture_entry(2, wgpu::TextureSampleType::Float { filterable: true }),
+            wgpu::BindGroupLayoutEntry {
+                binding: 3,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 4,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+        ];
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Temporal Accumulation Bind Group Layout"),
+            entries: &bind_group_layout_entries,
+        });
+
+        let source = fullscreen_shader(include_str!("temporal.wgsl"));
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Temporal Accumulation Shader"),
+            source: wgpu::ShaderSource::Wgsl(source.as_str().into()),
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Temporal Accumulation Pipeline Layout"),
+            bind_group_layouts: &[camera_bind_group_layout, &bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let target = Some(wgpu::ColorTargetState {
+            format,
+            blend: None,
+            write_mask: wgpu::ColorWrites::ALL,
+        });
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Temporal Accumulation Pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_fullscreen",
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                // The frame, and the history of the next one
+                targets: &[target.clone(), target],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+
+        #[cfg(feature = "guardrails")]
+        {
+            let reflection = crate::guardrails::ShaderReflection::new("temporal.wgsl", &source);
+            reflection.check_bind_group_layout(1, &bind_group_layout_entries);
+            reflection.check_struct_size("TemporalParams", std::mem::size_of::<TemporalParams>());
+            reflection.check_struct_size(
+                "CameraUniform",
+                std::mem::size_of::<crate::camera::CameraUniform>(),
+            );
+        }
+
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Temporal Accumulation Sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        let params_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Temporal Accumulation Params Buffer"),
+            size: std::mem::size_of::<TemporalParams>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        Self {
+            enabled,
+            format,
+            pipeline,
+            bind_group_layout,
+            sampler,
+            params_buffer,
+            targets: None,
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn toggle(&mut self) {
+        self.enabled = !self.enabled;
+        if !self.enabled {
+            self.targets = None;
+        }
+    }
+
+    /// Makes sure the scene textures are `size`, and swaps the history textures. Must be called
+    /// before [`TemporalAccumulation::views`] and [`TemporalAccumulation::resolve`] every time the
+    /// scene is drawn.
+    pub fn prepare(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, size: (u32, u32)) {
+        let targets = match &mut self.targets {
+            Some(targets) if targets.size == size => {
+                targets.written = 1 - targets.written;
+                targets.has_history = true;
+                targets
+            }
+            _ => self.targets.insert(self.create_targets(device, size)),
+        };
+        let params = TemporalParams {
+            history_weight: if targets.has_history {
+                HISTORY_WEIGHT
+            } else {
+                0.0
+            },
+            depth_scale: DEPTH_SCALE,
+            _padding: [0.0; 2],
+        };
+        queue.write_buffer(&self.params_buffer, 0, bytemuck::cast_slice(&[params]));
+    }
+
+    /// Color and depth views the scene should be drawn into, once prepared.
+    pub fn views(&self) -> Option<(&wgpu::TextureView, &wgpu::TextureView)> {
+        let targets = self.targets.as_ref()?;
+        Some((&targets.color_view, &targets.depth_view))
+    }
+
+    /// Blends the scene drawn into [`TemporalAccumulation::views`] with the history into `view`.
+    pub fn resolve(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        view: &wgpu::TextureView,
+        camera_bind_group: &wgpu::BindGroup,
+    ) {
+        let Some(targets) = &self.targets else {
+            return;
+        };
+
+        let attachment = |view| {
+            Some(wgpu::RenderPassColorAttachment {
+                view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                    store: true,
+                },
+            })
+        };
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Temporal Accumulation Pass"),
+            color_attachments: &[
+                attachment(view),
+                attachment(&targets.history_views[targets.written]),
+            ],
+            depth_stencil_attachment: None,
+        });
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, camera_bind_group, &[]);
+        render_pass.set_bind_group(1, &targets.bind_groups[1 - targets.written], &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+
+    fn create_targets(&self, device: &wgpu::Device, size: (u32, u32)) -> Targets {
+        let create_texture = |label, format| {
+            device.create_texture(&wgpu::TextureDescriptor {
+                label: Some(label),
+                size: wgpu::Extent3d {
+                    width: size.0,
+                    height: size.1,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format,
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                    | wgpu::TextureUsages::TEXTURE_BINDING,
+                view_formats: &[],
+            })
+        };
+        let color_texture = create_texture("Temporal Accumulation Color Texture", self.format);
+        let depth_texture = create_texture("Temporal Accumulation Depth Texture", DEPTH_FORMAT);
+        let history_textures = [
+            create_texture("Temporal Accumulation History Texture", self.format),
+            create_texture("Temporal Accumulation History Texture", self.format),
+        ];
+        let color_view = color_texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let depth_view = depth_texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let history_views = history_textures
+            .each_ref()
+            .map(|texture| texture.create_view(&wgpu::TextureViewDescriptor::default()));
+
+        let bind_groups = history_views.each_ref().map(|history_view| {
+            device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("Temporal Accumulation Bind Group"),
+                layout: &self.bind_group_layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::TextureView(&color_view),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::TextureView(&depth_view),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: wgpu::BindingResource::TextureView(history_view),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 3,
+                        resource: wgpu::BindingResource::Sampler(&self.sampler),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 4,
+                        resource: self.params_buffer.as_entire_binding(),
+                    },
+                ],
+            })
+        });
+
+        Targets {
+            size,
+            _color_texture: color_texture,
+            color_view,
+            _depth_texture: depth_texture,
+            depth_view,
+            _history_textures: history_textures,
+            history_views,
+            bind_groups,
+            written: 0,
+            has_history: false,
+        }
+    }
+}
@@ -0,0 +1,100 @@
+// Temporal accumulation: blends every pixel with where it was in the history of the previous
+// frames, clamped to the colors around it this frame so moving particles don't leave ghosts
+
+struct CameraUniform {
+    view_proj: mat4x4<f32>,
+    eye: vec4<f32>,
+    inv_view_proj: mat4x4<f32>,
+    previous_view_proj: mat4x4<f32>,
+    view: mat4x4<f32>,
+    proj: mat4x4<f32>,
+    group_mask: u32,
+    reverse_z: u32,
+};
+@group(0) @binding(0)
+var<uniform> camera: CameraUniform;
+
+// Must match TemporalParams in temporal.rs
+struct TemporalParams {
+    // Weight of the history, 0 while there's none
+    history_weight: f32,
+    depth_scale: f32,
+    _padding0: f32,
+    _padding1: f32,
+};
+
+@group(1) @binding(0)
+var scene: texture_2d<f32>;
+@group(1) @binding(1)
+var scene_depth: texture_depth_2d;
+@group(1) @binding(2)
+var history: texture_2d<f32>;
+@group(1) @binding(3)
+var history_sampler: sampler;
+@group(1) @binding(4)
+var<uniform> params: TemporalParams;
+
+// Direction of the view ray through `ndc`, unprojected on the near plane since the far one may be
+// at infinity
+fn view_ray(ndc: vec2<f32>) -> vec3<f32> {
+    let near_depth = select(0.0, 1.0, camera.reverse_z != 0u);
+    let near = camera.inv_view_proj * vec4<f32>(ndc, near_depth, 1.0);
+    return normalize(near.xyz / near.w - camera.eye.xyz);
+}
+
+// Where the pixel at `ndc` was in the previous frame, in texture coordinates. Its view-space depth
+// comes from the depth written by `fs_depth` in shader.wgsl, the background is infinitely far
+fn reproject(ndc: vec2<f32>, stored_depth: f32) -> vec2<f32> {
+    let ray = view_ray(ndc);
+    // A direction, only the rotation of the camera moves the background
+    var position = vec4<f32>(ray, 0.0);
+    // Reverse-Z stores 1 minus the depth, the background at 0
+    let depth = select(stored_depth, 1.0 - stored_depth, camera.reverse_z != 0u);
+    if depth < 1.0 {
+        let view_depth = params.depth_scale * depth / (1.0 - depth);
+        // The view matrix maps the forward direction to -z, its third row
+        let forward = -vec3<f32>(camera.view[0].z, camera.view[1].z, camera.view[2].z);
+        position = vec4<f32>(camera.eye.xyz + ray * view_depth / dot(ray, forward), 1.0);
+    }
+    let previous = camera.previous_view_proj * position;
+    return previous.xy / previous.w * vec2<f32>(0.5, -0.5) + 0.5;
+}
+
+struct TemporalOutput {
+    @location(0) color: vec4<f32>,
+    // Next frame's history
+    @location(1) history: vec4<f32>,
+};
+
+@fragment
+fn fs_main(in: FullscreenOutput) -> TemporalOutput {
+    let size = vec2<i32>(textureDimensions(scene));
+    let center = vec2<i32>(in.clip_position.xy);
+    let current = textureLoad(scene, center, 0);
+
+    // Range of the colors around the pixel, the history is clamped to it
+    var low = current;
+    var high = current;
+    for (var y = -1; y <= 1; y++) {
+        for (var x = -1; x <= 1; x++) {
+            let coords = clamp(center + vec2<i32>(x, y), vec2<i32>(0), size - 1);
+            let color = textureLoad(scene, coords, 0);
+            low = min(low, color);
+            high = max(high, color);
+        }
+    }
+
+    let uv = in.clip_position.xy / vec2<f32>(size);
+    let previous_uv = reproject(fullscreen_ndc(uv), textureLoad(scene_depth, center, 0));
+    var weight = params.history_weight;
+    // Pixels coming from off screen have no history
+    if any(previous_uv < vec2<f32>(0.0)) || any(previous_uv > vec2<f32>(1.0)) {
+        weight = 0.0;
+    }
+    let previous = clamp(textureSampleLevel(history, history_sampler, previous_uv, 0.0), low, high);
+
+    var out: TemporalOutput;
+    out.color = mix(current, previous, weight);
+    out.history = out.color;
+    return out;
+}
@@ -0,0 +1,9 @@
+//! The device the GPU unit tests run on, the default adapter, a software one in CI.
+
+/// None when there's no adapter, the tests are then skipped.
+pub fn device() -> Option<(wgpu::Device, wgpu::Queue)> {
+    let instance = wgpu::Instance::default();
+    let adapter =
+        pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions::default()))?;
+    pollster::block_on(adapter.request_device(&wgpu::DeviceDescriptor::default(), None)).ok()
+}
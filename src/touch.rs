@@ -0,0 +1,96 @@
+//! Touch screen and touchpad gestures, for machines without a mouse wheel.
+//!
+//! Dragging one finger on a touch screen orbits the camera around its target, pinching two fingers
+//! zooms like a touchpad pinch does.
+
+use std::collections::HashMap;
+
+use glam::{DVec2, Quat, Vec3};
+use winit::event::{Touch, TouchPhase, WindowEvent};
+
+use crate::camera::{Camera, ZoomController};
+
+// Radians the camera orbits per pixel dragged
+const ORBIT_PER_PIXEL: f32 = 0.005;
+// Orbiting closer than this to straight up or down would flip the camera, in cosine of the angle
+const MAX_PITCH_COS: f32 = 0.99;
+
+/// Fingers on a touch screen, by touch id.
+#[derive(Debug, Default)]
+pub struct TouchGestures {
+    fingers: HashMap<u64, DVec2>,
+}
+
+impl TouchGestures {
+    /// Moves `camera` and `zoom` from a touch or touchpad pinch `event`. Returns true if it was
+    /// one.
+    pub fn input(
+        &mut self,
+        event: &WindowEvent,
+        camera: &mut Camera,
+        zoom: &mut ZoomController,
+    ) -> bool {
+        match event {
+            WindowEvent::TouchpadMagnify { delta, .. } => zoom.magnify(*delta as f32),
+            WindowEvent::Touch(touch) => self.touch(touch, camera, zoom),
+            _ => return false,
+        }
+        true
+    }
+
+    fn touch(&mut self, touch: &Touch, camera: &mut Camera, zoom: &mut ZoomController) {
+        let position = DVec2::new(touch.location.x, touch.location.y);
+        match touch.phase {
+            TouchPhase::Started => {
+                self.fingers.insert(touch.id, position);
+            }
+            TouchPhase::Ended | TouchPhase::Cancelled => {
+                self.fingers.remove(&touch.id);
+            }
+            TouchPhase::Moved => {
+                let Some(&previous) = self.fingers.get(&touch.id) else {
+                    return;
+                };
+                match self.fingers.len() {
+                    1 => {
+                        orbit(camera, (position - previous).as_vec2());
+                        *zoom = ZoomController::default();
+                    }
+                    2 => {
+                        let other = self
+                            .fingers
+                            .iter()
+                            .find(|(&id, _)| id != touch.id)
+                            .map(|(_, &other)| other)
+                            .unwrap();
+                        let before = previous.distance(other);
+                        if before > 0.0 {
+                            zoom.magnify((position.distance(other) / before - 1.0) as f32);
+                        }
+                    }
+                    // Nothing for more fingers than that
+                    _ => {}
+                }
+                self.fingers.insert(touch.id, position);
+            }
+        }
+    }
+}
+
+/// Turns `camera` around its target by `drag` pixels, right and down.
+fn orbit(camera: &mut Camera, drag: glam::Vec2) {
+    let up = camera.up.try_normalize().unwrap_or(Vec3::Y);
+    let offset = camera.eye - camera.target;
+    let right = up.cross(offset).normalize_or_zero();
+
+    let yaw = Quat::from_axis_angle(up, -drag.x * ORBIT_PER_PIXEL);
+    let pitch = Quat::from_axis_angle(right, drag.y * ORBIT_PER_PIXEL);
+    let turned = yaw * pitch * offset;
+    // Stops pitching short of the up axis rather than flipping over it
+    let offset = if turned.normalize_or_zero().dot(up).abs() < MAX_PITCH_COS {
+        turned
+    } else {
+        yaw * offset
+    };
+    camera.eye = camera.target + offset;
+}
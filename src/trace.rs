@@ -0,0 +1,36 @@
+//! Logging and tracing. `log` records and `tracing` events are printed to stderr, filtered by
+//! `RUST_LOG`, and spans around the phases of each frame can be written to a Chrome trace, to be
+//! opened in chrome://tracing or Perfetto.
+
+use std::path::Path;
+
+use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt, EnvFilter, Layer};
+
+/// Target of the frame stats, shown without `RUST_LOG`.
+pub const FRAME_STATS: &str = "frame";
+
+/// Installs the subscriber, writing a Chrome trace to `trace_json` if given. The trace is only
+/// complete once the returned guard is dropped or flushed.
+pub fn init(trace_json: Option<&Path>) -> Option<tracing_chrome::FlushGuard> {
+    let filter = EnvFilter::try_from_default_env()
+        .unwrap_or_else(|_| EnvFilter::new(format!("error,{FRAME_STATS}=info")));
+    let fmt = tracing_subscriber::fmt::layer()
+        .with_writer(std::io::stderr)
+        .with_filter(filter);
+    let (chrome, guard) = match trace_json {
+        Some(path) => {
+            let (layer, guard) = tracing_chrome::ChromeLayerBuilder::new()
+                .file(path)
+                .include_args(true)
+                .build();
+            // Only the app's own spans, the log records of wgpu would drown them
+            (
+                Some(layer.with_filter(EnvFilter::new("particles=info"))),
+                Some(guard),
+            )
+        }
+        None => (None, None),
+    };
+    tracing_subscriber::registry().with(fmt).with(chrome).init();
+    guard
+}
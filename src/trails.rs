@@ -0,0 +1,230 @@
+//! Trails behind the particles, drawn from a ring buffer of their last positions.
+
+use std::ops::Range;
+
+use bytemuck::{Pod, Zeroable};
+use wgpu::util::DeviceExt;
+
+use crate::vertex::{InstanceColor, InstancePosition};
+
+// Number of past positions kept per particle
+const TRAIL_LENGTH: u32 = 5;
+// A new position is recorded every this many frames, so trails span more than a few frames
+const FRAMES_PER_SAMPLE: u32 = 4;
+
+// Must match TrailParams in trails.wgsl
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Pod, Zeroable)]
+struct TrailParams {
+    head: u32,
+    length: u32,
+    capacity: u32,
+    _padding: u32,
+}
+
+pub struct Trails {
+    params: TrailParams,
+    frame: u32,
+    history_buffer: wgpu::Buffer,
+    params_buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+    pipeline: wgpu::RenderPipeline,
+}
+
+impl Trails {
+    /// Allocates the history for `capacity` particles, or returns `None` if the device can't hold
+    /// even two positions per particle.
+    pub fn new(
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+        camera_bind_group_layout: &wgpu::BindGroupLayout,
+        capacity: usize,
+    ) -> Option<Self> {
+        let slot_size = (capacity * std::mem::size_of::<InstancePosition>()) as u64;
+        let limits = device.limits();
+        let max_size = (limits.max_storage_buffer_binding_size as u64).min(limits.max_buffer_size);
+        let length = TRAIL_LENGTH.min((max_size / slot_size.max(1)) as u32);
+        if length < 2 {
+            return None;
+        }
+
+        let history_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Trail History Buffer"),
+            size: slot_size * length as u64,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let params = TrailParams {
+            head: 0,
+            length,
+            capacity: capacity as u32,
+            _padding: 0,
+        };
+        let params_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Trail Params Buffer"),
+            contents: bytemuck::cast_slice(&[params]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let bind_group_layout_entries = [
+            wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::VERTEX,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Storage { read_only: true },
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 1,
+                visibility: wgpu::ShaderStages::VERTEX,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+        ];
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Trail Bind Group Layout"),
+            entries: &bind_group_layout_entries,
+        });
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Trail Bind Group"),
+            layout: &bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: history_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: params_buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Trail Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("trails.wgsl").into()),
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Trail Pipeline Layout"),
+            bind_group_layouts: &[camera_bind_group_layout, &bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Trail Pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[InstanceColor::descriptor()],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::LineList,
+                ..Default::default()
+            },
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+
+        #[cfg(feature = "guardrails")]
+        {
+            let reflection = crate::guardrails::ShaderReflection::new(
+                "trails.wgsl",
+                include_str!("trails.wgsl"),
+            );
+            reflection.check_vertex_buffers("vs_main", &[InstanceColor::descriptor()]);
+            reflection.check_bind_group_layout(1, &bind_group_layout_entries);
+            reflection.check_struct_size("TrailParams", std::mem::size_of::<TrailParams>());
+        }
+
+        Some(Self {
+            params,
+            frame: 0,
+            history_buffer,
+            params_buffer,
+            bind_group,
+            pipeline,
+        })
+    }
+
+    /// Fills the whole history with the current positions, so trails grow from the particles
+    /// instead of from wherever they were when trails were last enabled.
+    pub fn reset(&mut self, encoder: &mut wgpu::CommandEncoder, position_buffer: &wgpu::Buffer) {
+        for slot in 0..self.params.length {
+            self.copy_slot(encoder, position_buffer, slot);
+        }
+        self.frame = 0;
+    }
+
+    /// Records the current positions in the history every few frames.
+    pub fn record(
+        &mut self,
+        encoder: &mut wgpu::CommandEncoder,
+        queue: &wgpu::Queue,
+        position_buffer: &wgpu::Buffer,
+    ) {
+        self.frame += 1;
+        if !self.frame.is_multiple_of(FRAMES_PER_SAMPLE) {
+            return;
+        }
+
+        self.params.head = (self.params.head + 1) % self.params.length;
+        self.copy_slot(encoder, position_buffer, self.params.head);
+        queue.write_buffer(&self.params_buffer, 0, bytemuck::cast_slice(&[self.params]));
+    }
+
+    pub fn draw<'a>(
+        &'a self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        camera_bind_group: &'a wgpu::BindGroup,
+        color_buffer: &'a wgpu::Buffer,
+        instance_ranges: &[Range<usize>],
+    ) {
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, camera_bind_group, &[]);
+        render_pass.set_bind_group(1, &self.bind_group, &[]);
+        render_pass.set_vertex_buffer(0, color_buffer.slice(..));
+        let vertex_count = (self.params.length - 1) * 2;
+        for range in instance_ranges {
+            render_pass.draw(0..vertex_count, range.start as u32..range.end as u32);
+        }
+    }
+
+    fn copy_slot(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        position_buffer: &wgpu::Buffer,
+        slot: u32,
+    ) {
+        let slot_size =
+            self.params.capacity as u64 * std::mem::size_of::<InstancePosition>() as u64;
+        encoder.copy_buffer_to_buffer(
+            position_buffer,
+            0,
+            &self.history_buffer,
+            slot as u64 * slot_size,
+            slot_size,
+        );
+    }
+}
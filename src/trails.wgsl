@@ -0,0 +1,48 @@
+// Fading lines following the last positions of every particle
+
+struct CameraUniform {
+    view_proj: mat4x4<f32>,
+};
+@group(0) @binding(0)
+var<uniform> camera: CameraUniform;
+
+// Must match TrailParams in trails.rs
+struct TrailParams {
+    head: u32,
+    length: u32,
+    capacity: u32,
+    _padding: u32,
+};
+
+// `length` slots of `capacity` positions, slot `head` holds the most recent positions
+@group(1) @binding(0)
+var<storage, read> history: array<vec4<f32>>;
+@group(1) @binding(1)
+var<uniform> trail: TrailParams;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) color: vec4<f32>,
+};
+
+@vertex
+fn vs_main(
+    @builtin(vertex_index) vertex_index: u32,
+    @builtin(instance_index) instance_index: u32,
+    @location(3) color: vec4<f32>,
+) -> VertexOutput {
+    // Segment n joins the positions recorded n and n + 1 steps ago
+    let age = vertex_index / 2u + vertex_index % 2u;
+    let slot = (trail.head + trail.length - age) % trail.length;
+    let position = history[slot * trail.capacity + instance_index];
+
+    var out: VertexOutput;
+    out.clip_position = camera.view_proj * vec4<f32>(position.xyz, 1.0);
+    out.color = vec4<f32>(color.rgb, color.a * (1.0 - f32(age) / f32(trail.length)));
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    return in.color;
+}
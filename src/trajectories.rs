@@ -0,0 +1,55 @@
+//! CSV export of the trajectories of a few tracked particles, streamed back from the GPU, to plot
+//! how their position and speed respond to changes of the forces.
+
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+    time::Instant,
+};
+
+use crate::readback::Readback;
+
+const CSV_HEADER: &str = "time_s,frame,particle,x,y,z,vx,vy,vz,speed";
+
+pub struct TrajectoryWriter {
+    csv: BufWriter<File>,
+    start: Instant,
+}
+
+impl TrajectoryWriter {
+    /// Writes to `path`, replacing any previous trajectories.
+    pub fn new(path: &Path) -> io::Result<Self> {
+        let mut csv = BufWriter::new(File::create(path)?);
+        writeln!(csv, "{CSV_HEADER}")?;
+        Ok(Self {
+            csv,
+            start: Instant::now(),
+        })
+    }
+
+    /// Adds a row per tracked particle. Velocities are left empty when simulating on the CPU.
+    pub fn write(&mut self, readback: &Readback) -> io::Result<()> {
+        let time = (readback.copied_at - self.start).as_secs_f64();
+        for (index, values) in readback.iter() {
+            let position = values.position;
+            write!(
+                self.csv,
+                "{time:.4},{},{index},{},{},{}",
+                readback.frame, position.x, position.y, position.z
+            )?;
+            match values.velocity {
+                Some(velocity) => writeln!(
+                    self.csv,
+                    ",{},{},{},{}",
+                    velocity.x,
+                    velocity.y,
+                    velocity.z,
+                    velocity.length()
+                )?,
+                None => writeln!(self.csv, ",,,,")?,
+            }
+        }
+        self.csv.flush()
+    }
+}
@@ -0,0 +1,76 @@
+use bytemuck::{Pod, Zeroable};
+
+/// Parameters of the turbulence velocity field, shared with compute_kernel.wgsl.
+///
+/// The field is the curl of a sum of sine potentials, which makes it divergence free: particles
+/// swirl around without bunching up or spreading out. [`TurbulenceParams::velocity`] must stay in
+/// sync with `turbulence_velocity` in the compute kernel so both backends move particles the same
+/// way.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Pod, Zeroable)]
+pub struct TurbulenceParams {
+    pub time: f32,
+    pub frequency: f32,
+    pub amplitude: f32,
+    _padding: f32,
+}
+
+impl Default for TurbulenceParams {
+    fn default() -> Self {
+        Self {
+            time: 0.0,
+            frequency: 0.01,
+            amplitude: 0.15,
+            _padding: 0.0,
+        }
+    }
+}
+
+impl TurbulenceParams {
+    pub fn velocity(&self, position: glam::Vec3) -> glam::Vec3 {
+        let t = self.time;
+        let base =
+            curl_octave(position * self.frequency + glam::Vec3::new(t * 0.31, t * 0.23, t * 0.17));
+        let detail = curl_octave(
+            position * self.frequency * 2.13
+                + glam::Vec3::new(1.7 - t * 0.19, 4.1 + t * 0.29, 2.3 + t * 0.37),
+        ) * 0.5;
+        (base + detail) * self.amplitude
+    }
+
+    /// Same as [`TurbulenceParams::velocity`] on SIMD vectors.
+    pub fn velocity_simd(&self, position: glam::Vec3A) -> glam::Vec3A {
+        let t = self.time;
+        let base = curl_octave_simd(
+            position * self.frequency + glam::Vec3A::new(t * 0.31, t * 0.23, t * 0.17),
+        );
+        let detail = curl_octave_simd(
+            position * self.frequency * 2.13
+                + glam::Vec3A::new(1.7 - t * 0.19, 4.1 + t * 0.29, 2.3 + t * 0.37),
+        ) * 0.5;
+        (base + detail) * self.amplitude
+    }
+}
+
+// Curl of (cos(a.y) sin(a.z), cos(a.z) sin(a.x), cos(a.x) sin(a.y)), without the frequency factor
+fn curl_octave(a: glam::Vec3) -> glam::Vec3 {
+    let (s, c) = (
+        glam::Vec3::new(a.x.sin(), a.y.sin(), a.z.sin()),
+        glam::Vec3::new(a.x.cos(), a.y.cos(), a.z.cos()),
+    );
+    glam::Vec3::new(
+        c.x * c.y + s.z * s.x,
+        c.y * c.z + s.x * s.y,
+        c.z * c.x + s.y * s.z,
+    )
+}
+
+// The products of `curl_octave` as two SIMD multiply-adds over swizzled lanes
+fn curl_octave_simd(a: glam::Vec3A) -> glam::Vec3A {
+    use glam::Vec3Swizzles;
+    let (s, c) = (
+        glam::Vec3A::new(a.x.sin(), a.y.sin(), a.z.sin()),
+        glam::Vec3A::new(a.x.cos(), a.y.cos(), a.z.cos()),
+    );
+    c * c.yzx() + s.zxy() * s
+}
@@ -0,0 +1,600 @@
+//! Parameter panel drawn over the scene with egui, toggled with F1.
+//!
+//! egui-wgpu needs a newer wgpu, so the panel is painted here: the meshes egui tessellates go in
+//! one vertex and index buffer each frame, its textures are kept by id until it frees them.
+
+use std::collections::HashMap;
+
+use bytemuck::{Pod, Zeroable};
+use wgpu::util::DeviceExt;
+use winit::{event::WindowEvent, window::Window};
+
+use crate::{
+    accessibility::{Accessibility, HeatmapColors, SafePalette, MAX_ADJUSTMENT, MIN_ADJUSTMENT},
+    pipeline_cache::BlendMode,
+    reduction::ParticleStats,
+    state::{MAX_TIME_SCALE, MIN_TIME_SCALE},
+    surface_format,
+};
+
+#[cfg(feature = "metrics")]
+use crate::gpu_timer::{PassTimes, PipelineStats};
+
+/// What the panel shows and edits, read from the state before each frame and applied back after.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PanelValues {
+    /// Of the simulation time, between [`MIN_TIME_SCALE`] and [`MAX_TIME_SCALE`]
+    pub time_scale: f32,
+    pub speed_multiplier: f32,
+    pub attractor_strength: f32,
+    pub fovy: f32,
+    /// None without a gradient in the scene
+    pub colors_by_age: Option<bool>,
+    pub blend_mode: BlendMode,
+    pub paused: bool,
+    /// Set when the reset button was clicked
+    pub reset: bool,
+    pub accessibility: Accessibility,
+    /// Particles simulated and drawn, out of `live_particles`
+    pub active_particles: usize,
+    pub live_particles: usize,
+    /// Shown while the particle stats are enabled, read only
+    pub particle_stats: Option<ParticleStats>,
+    /// Latest GPU measurements, read only, a couple of frames old
+    #[cfg(feature = "metrics")]
+    pub pass_times: Option<PassTimes>,
+    #[cfg(feature = "metrics")]
+    pub pipeline_stats: Option<PipelineStats>,
+    /// Pixels the scene is drawn at, to show the overdraw. Half resolution and checkerboard
+    /// rendering shade fewer
+    #[cfg(feature = "metrics")]
+    pub scene_pixels: u64,
+}
+
+// Must match Screen in ui.wgsl
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Pod, Zeroable)]
+struct ScreenUniform {
+    size: [f32; 2],
+    _padding: [f32; 2],
+}
+
+struct Texture {
+    texture: wgpu::Texture,
+    bind_group: wgpu::BindGroup,
+}
+
+// Meshes of the last frame, in the buffers of `Frame`
+struct Draw {
+    clip_rect: egui::Rect,
+    texture_id: egui::TextureId,
+    indices: std::ops::Range<u32>,
+    base_vertex: i32,
+}
+
+struct Frame {
+    vertex_buffer: wgpu::Buffer,
+    index_buffer: wgpu::Buffer,
+    draws: Vec<Draw>,
+    pixels_per_point: f32,
+}
+
+pub struct Ui {
+    context: egui::Context,
+    input: egui_winit::State,
+    pipeline: wgpu::RenderPipeline,
+    screen_buffer: wgpu::Buffer,
+    screen_bind_group: wgpu::BindGroup,
+    texture_bind_group_layout: wgpu::BindGroupLayout,
+    textures: HashMap<egui::TextureId, Texture>,
+    frame: Option<Frame>,
+}
+
+impl Ui {
+    /// Paints the panel on surfaces of `surface_format`.
+    pub fn new(
+        device: &wgpu::Device,
+        window: &Window,
+        surface_format: wgpu::TextureFormat,
+    ) -> Self {
+        let mut input = egui_winit::State::new(window);
+        input.set_pixels_per_point(window.scale_factor() as f32);
+        input.set_max_texture_side(device.limits().max_texture_dimension_2d as usize);
+
+        let screen_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Ui Screen Buffer"),
+            size: std::mem::size_of::<ScreenUniform>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let screen_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("Ui Screen Bind Group Layout"),
+                entries: &[wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::VERTEX,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                }],
+            });
+        let screen_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Ui Screen Bind Group"),
+            layout: &screen_bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: screen_buffer.as_entire_binding(),
+            }],
+        });
+        let texture_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("Ui Texture Bind Group Layout"),
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Texture {
+                            multisampled: false,
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                        count: None,
+                    },
+                ],
+            });
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Ui Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("ui.wgsl").into()),
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Ui Pipeline Layout"),
+            bind_group_layouts: &[&screen_bind_group_layout, &texture_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Ui Pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[wgpu::VertexBufferLayout {
+                    array_stride: std::mem::size_of::<egui::epaint::Vertex>() as u64,
+                    step_mode: wgpu::VertexStepMode::Vertex,
+                    attributes: &wgpu::vertex_attr_array![
+                        0 => Float32x2,
+                        1 => Float32x2,
+                        2 => Unorm8x4,
+                    ],
+                }],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: if surface_format::encodes_gamma(surface_format) {
+                    "fs_encode_srgb"
+                } else {
+                    "fs_main"
+                },
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: surface_format,
+                    blend: Some(wgpu::BlendState::PREMULTIPLIED_ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+
+        Self {
+            context: egui::Context::default(),
+            input,
+            pipeline,
+            screen_buffer,
+            screen_bind_group,
+            texture_bind_group_layout,
+            textures: HashMap::new(),
+            frame: None,
+        }
+    }
+
+    /// Passes `event` to the panel. Returns true if the panel used it, and the camera shouldn't.
+    pub fn input(&mut self, event: &WindowEvent) -> bool {
+        self.input.on_event(&self.context, event).consumed
+    }
+
+    /// Lays out the panel editing `values`, next to `shader_error` if any, and uploads what `encode`
+    /// paints.
+    pub fn run(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        window: &Window,
+        values: &mut PanelValues,
+        shader_error: Option<&str>,
+    ) {
+        let raw_input = self.input.take_egui_input(window);
+        let output = self.context.run(raw_input, |context| {
+            panel(context, values);
+            if let Some(error) = shader_error {
+                error_window(context, error);
+            }
+        });
+        self.input
+            .handle_platform_output(window, &self.context, output.platform_output);
+
+        for (id, delta) in &output.textures_delta.set {
+            self.update_texture(device, queue, *id, delta);
+        }
+        let primitives = self.context.tessellate(output.shapes);
+        self.frame = self.upload(device, queue, window, primitives);
+        for id in &output.textures_delta.free {
+            self.textures.remove(id);
+        }
+    }
+
+    /// Paints the panel laid out by the last [`Ui::run`] over `view`.
+    pub fn encode(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        view: &wgpu::TextureView,
+        size: winit::dpi::PhysicalSize<u32>,
+    ) {
+        let Some(frame) = &self.frame else {
+            return;
+        };
+
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Ui Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: true,
+                },
+            })],
+            depth_stencil_attachment: None,
+        });
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &self.screen_bind_group, &[]);
+        render_pass.set_vertex_buffer(0, frame.vertex_buffer.slice(..));
+        render_pass.set_index_buffer(frame.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+        for draw in &frame.draws {
+            let Some(texture) = self.textures.get(&draw.texture_id) else {
+                continue;
+            };
+            // Clip rectangles are in points, and may reach past the surface
+            let min = (draw.clip_rect.min.to_vec2() * frame.pixels_per_point).round();
+            let max = (draw.clip_rect.max.to_vec2() * frame.pixels_per_point).round();
+            let x = (min.x.max(0.0) as u32).min(size.width);
+            let y = (min.y.max(0.0) as u32).min(size.height);
+            let width = (max.x.max(0.0) as u32).min(size.width).saturating_sub(x);
+            let height = (max.y.max(0.0) as u32).min(size.height).saturating_sub(y);
+            if width == 0 || height == 0 {
+                continue;
+            }
+            render_pass.set_scissor_rect(x, y, width, height);
+            render_pass.set_bind_group(1, &texture.bind_group, &[]);
+            render_pass.draw_indexed(draw.indices.clone(), draw.base_vertex, 0..1);
+        }
+    }
+
+    fn upload(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        window: &Window,
+        primitives: Vec<egui::ClippedPrimitive>,
+    ) -> Option<Frame> {
+        let mut vertices = vec![];
+        let mut indices = vec![];
+        let mut draws = vec![];
+        for egui::ClippedPrimitive {
+            clip_rect,
+            primitive,
+        } in primitives
+        {
+            // Only the custom painting callbacks aren't meshes, the panel doesn't use any
+            let egui::epaint::Primitive::Mesh(mesh) = primitive else {
+                continue;
+            };
+            draws.push(Draw {
+                clip_rect,
+                texture_id: mesh.texture_id,
+                indices: indices.len() as u32..(indices.len() + mesh.indices.len()) as u32,
+                base_vertex: vertices.len() as i32,
+            });
+            vertices.extend_from_slice(&mesh.vertices);
+            indices.extend_from_slice(&mesh.indices);
+        }
+        if draws.is_empty() {
+            return None;
+        }
+
+        let pixels_per_point = self.context.pixels_per_point();
+        let size = window.inner_size();
+        queue.write_buffer(
+            &self.screen_buffer,
+            0,
+            bytemuck::bytes_of(&ScreenUniform {
+                size: [
+                    size.width as f32 / pixels_per_point,
+                    size.height as f32 / pixels_per_point,
+                ],
+                _padding: [0.0; 2],
+            }),
+        );
+        Some(Frame {
+            vertex_buffer: device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("Ui Vertex Buffer"),
+                contents: bytemuck::cast_slice(&vertices),
+                usage: wgpu::BufferUsages::VERTEX,
+            }),
+            index_buffer: device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("Ui Index Buffer"),
+                contents: bytemuck::cast_slice(&indices),
+                usage: wgpu::BufferUsages::INDEX,
+            }),
+            draws,
+            pixels_per_point,
+        })
+    }
+
+    /// Creates the texture `id`, or replaces part of it when the delta has a position.
+    fn update_texture(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        id: egui::TextureId,
+        delta: &egui::epaint::ImageDelta,
+    ) {
+        let pixels: Vec<egui::Color32> = match &delta.image {
+            egui::ImageData::Color(image) => image.pixels.clone(),
+            egui::ImageData::Font(image) => image.srgba_pixels(None).collect(),
+        };
+        let [width, height] = delta.image.size();
+        let size = wgpu::Extent3d {
+            width: width as u32,
+            height: height as u32,
+            depth_or_array_layers: 1,
+        };
+
+        if delta.pos.is_none() {
+            let texture = device.create_texture(&wgpu::TextureDescriptor {
+                label: Some("Ui Texture"),
+                size,
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: wgpu::TextureFormat::Rgba8UnormSrgb,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+                view_formats: &[],
+            });
+            let filter = |filter| match filter {
+                egui::TextureFilter::Nearest => wgpu::FilterMode::Nearest,
+                egui::TextureFilter::Linear => wgpu::FilterMode::Linear,
+            };
+            let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+                label: Some("Ui Sampler"),
+                mag_filter: filter(delta.options.magnification),
+                min_filter: filter(delta.options.minification),
+                ..Default::default()
+            });
+            let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("Ui Texture Bind Group"),
+                layout: &self.texture_bind_group_layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::TextureView(
+                            &texture.create_view(&wgpu::TextureViewDescriptor::default()),
+                        ),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::Sampler(&sampler),
+                    },
+                ],
+            });
+            self.textures.insert(
+                id,
+                Texture {
+                    texture,
+                    bind_group,
+                },
+            );
+        }
+
+        let Some(texture) = self.textures.get(&id) else {
+            log::warn!("Partial update of the unknown ui texture {id:?}");
+            return;
+        };
+        let [x, y] = delta.pos.unwrap_or([0, 0]);
+        queue.write_texture(
+            wgpu::ImageCopyTexture {
+                texture: &texture.texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d {
+                    x: x as u32,
+                    y: y as u32,
+                    z: 0,
+                },
+                aspect: wgpu::TextureAspect::All,
+            },
+            bytemuck::cast_slice(&pixels),
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(4 * width as u32),
+                rows_per_image: Some(height as u32),
+            },
+            size,
+        );
+    }
+}
+
+fn panel(context: &egui::Context, values: &mut PanelValues) {
+    egui::Window::new("Parameters")
+        .resizable(false)
+        .show(context, |ui| {
+            ui.add(
+                egui::Slider::new(&mut values.time_scale, MIN_TIME_SCALE..=MAX_TIME_SCALE)
+                    .logarithmic(true)
+                    .text("Time scale"),
+            );
+            ui.add(
+                egui::Slider::new(&mut values.speed_multiplier, 0.0..=10.0)
+                    .logarithmic(true)
+                    .text("Speed multiplier"),
+            );
+            ui.add(
+                egui::Slider::new(&mut values.attractor_strength, 0.0..=1.0)
+                    .logarithmic(true)
+                    .text("Attractor strength"),
+            );
+            ui.add(egui::Slider::new(&mut values.fovy, 1.0..=120.0).text("Field of view"));
+            ui.add(
+                egui::Slider::new(
+                    &mut values.active_particles,
+                    1..=values.live_particles.max(1),
+                )
+                .logarithmic(true)
+                .text("Particles"),
+            );
+
+            if let Some(stats) = &values.particle_stats {
+                ui.label(format!(
+                    "Speed {:.3} to {:.3}, average {:.3}",
+                    stats.min_speed, stats.max_speed, stats.average_speed
+                ));
+                let (min, max) = (stats.bounds_min, stats.bounds_max);
+                ui.label(format!(
+                    "Bounds ({:.0}, {:.0}, {:.0}) to ({:.0}, {:.0}, {:.0})",
+                    min.x, min.y, min.z, max.x, max.y, max.z
+                ));
+            }
+
+            #[cfg(feature = "metrics")]
+            gpu_section(ui, values);
+
+            ui.horizontal(|ui| {
+                ui.label("Colors");
+                match &mut values.colors_by_age {
+                    Some(colors_by_age) => {
+                        ui.radio_value(colors_by_age, false, "Particles");
+                        ui.radio_value(colors_by_age, true, "Gradient by age");
+                    }
+                    None => {
+                        ui.label("Particles, the scene has no gradient");
+                    }
+                }
+            });
+            ui.horizontal(|ui| {
+                ui.label("Blending");
+                ui.radio_value(&mut values.blend_mode, BlendMode::Alpha, "Alpha");
+                ui.radio_value(&mut values.blend_mode, BlendMode::Additive, "Additive");
+            });
+
+            accessibility_section(ui, &mut values.accessibility);
+
+            ui.horizontal(|ui| {
+                if ui.button("Reset").clicked() {
+                    values.reset = true;
+                }
+                let pause = if values.paused { "Resume" } else { "Pause" };
+                if ui.button(pause).clicked() {
+                    values.paused = !values.paused;
+                }
+            });
+        });
+}
+
+fn accessibility_section(ui: &mut egui::Ui, accessibility: &mut Accessibility) {
+    ui.collapsing("Accessibility", |ui| {
+        egui::ComboBox::from_label("Palette")
+            .selected_text(match accessibility.palette {
+                Some(palette) => palette.to_string(),
+                None => "scene".to_owned(),
+            })
+            .show_ui(ui, |ui| {
+                ui.selectable_value(&mut accessibility.palette, None, "scene");
+                for palette in SafePalette::ALL {
+                    ui.selectable_value(
+                        &mut accessibility.palette,
+                        Some(palette),
+                        palette.to_string(),
+                    );
+                }
+            });
+        egui::ComboBox::from_label("Heatmap colors")
+            .selected_text(accessibility.heatmap.to_string())
+            .show_ui(ui, |ui| {
+                for colors in HeatmapColors::ALL {
+                    ui.selectable_value(&mut accessibility.heatmap, colors, colors.to_string());
+                }
+            });
+        let display = &mut accessibility.display;
+        ui.add(
+            egui::Slider::new(&mut display.gamma, MIN_ADJUSTMENT..=MAX_ADJUSTMENT).text("Gamma"),
+        );
+        ui.add(
+            egui::Slider::new(&mut display.brightness, MIN_ADJUSTMENT..=MAX_ADJUSTMENT)
+                .text("Brightness"),
+        );
+        if ui.button("Default display").clicked() {
+            *display = Default::default();
+        }
+    });
+}
+
+/// The annotated error of a shader that doesn't compile.
+fn error_window(context: &egui::Context, error: &str) {
+    egui::Window::new("Shader error")
+        .default_pos([320.0, 16.0])
+        .show(context, |ui| {
+            egui::ScrollArea::vertical().show(ui, |ui| {
+                ui.label(
+                    egui::RichText::new(error)
+                        .monospace()
+                        .color(egui::Color32::LIGHT_RED),
+                );
+            });
+        });
+}
+
+#[cfg(feature = "metrics")]
+fn gpu_section(ui: &mut egui::Ui, values: &PanelValues) {
+    if let Some(times) = &values.pass_times {
+        ui.label(format!(
+            "GPU {:.2} ms: compute {:.2}, neighbors {:.2}, render {:.2}",
+            times.compute_ms + times.neighbors_ms + times.render_ms,
+            times.compute_ms,
+            times.neighbors_ms,
+            times.render_ms
+        ));
+    }
+    if let Some(stats) = &values.pipeline_stats {
+        ui.label(format!(
+            "{} vertices, {} of {} triangles drawn ({:.0}% culled)",
+            stats.vertex_invocations,
+            stats.primitives,
+            stats.clipper_invocations,
+            stats.culled() * 100.0
+        ));
+        ui.label(format!(
+            "{} fragments, overdraw {:.2}x",
+            stats.fragment_invocations,
+            stats.overdraw(values.scene_pixels)
+        ));
+    }
+}
@@ -0,0 +1,65 @@
+// Paints the meshes of the egui panel, see ui.rs
+
+// Must match ScreenUniform in ui.rs
+struct Screen {
+    // In egui points
+    size: vec2<f32>,
+    _padding: vec2<f32>,
+};
+
+@group(0) @binding(0)
+var<uniform> screen: Screen;
+
+@group(1) @binding(0)
+var panel_texture: texture_2d<f32>;
+@group(1) @binding(1)
+var panel_sampler: sampler;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+    @location(1) color: vec4<f32>,
+};
+
+fn srgb_to_linear(srgb: vec3<f32>) -> vec3<f32> {
+    let low = srgb / 12.92;
+    let high = pow((srgb + 0.055) / 1.055, vec3<f32>(2.4));
+    return select(high, low, srgb <= vec3<f32>(0.04045));
+}
+
+fn linear_to_srgb(linear: vec3<f32>) -> vec3<f32> {
+    let low = linear * 12.92;
+    let high = 1.055 * pow(linear, vec3<f32>(1.0 / 2.4)) - 0.055;
+    return select(high, low, linear <= vec3<f32>(0.0031308));
+}
+
+// egui vertex colors are premultiplied sRGB
+@vertex
+fn vs_main(
+    @location(0) position: vec2<f32>,
+    @location(1) uv: vec2<f32>,
+    @location(2) color: vec4<f32>,
+) -> VertexOutput {
+    var out: VertexOutput;
+    out.clip_position = vec4<f32>(
+        2.0 * position.x / screen.size.x - 1.0,
+        1.0 - 2.0 * position.y / screen.size.y,
+        0.0,
+        1.0,
+    );
+    out.uv = uv;
+    out.color = vec4<f32>(srgb_to_linear(color.rgb), color.a);
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    return in.color * textureSample(panel_texture, panel_sampler, in.uv);
+}
+
+// Surfaces without an sRGB format, as in blit.wgsl
+@fragment
+fn fs_encode_srgb(in: VertexOutput) -> @location(0) vec4<f32> {
+    let color = in.color * textureSample(panel_texture, panel_sampler, in.uv);
+    return vec4<f32>(linear_to_srgb(clamp(color.rgb, vec3<f32>(0.0), vec3<f32>(1.0))), color.a);
+}
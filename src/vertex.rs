@@ -2,11 +2,15 @@ use std::fmt::Display;
 
 use bytemuck::{Pod, Zeroable};
 
+#[cfg(feature = "packed-instances")]
+use crate::half_float;
+
 #[repr(C)]
 #[derive(Copy, Clone, Debug, Pod, Zeroable)]
 pub struct Vertex {
     pub position: [f32; 3],
     pub vertex_position: [f32; 2],
+    pub normal: [f32; 3],
 }
 
 impl Vertex {
@@ -22,6 +26,11 @@ impl Vertex {
                 shader_location: 1,
                 format: wgpu::VertexFormat::Float32x2,
             },
+            wgpu::VertexAttribute {
+                offset: memoffset::offset_of!(Vertex, normal) as u64,
+                shader_location: 4,
+                format: wgpu::VertexFormat::Float32x3,
+            },
         ];
 
         wgpu::VertexBufferLayout {
@@ -32,52 +41,240 @@ impl Vertex {
     }
 }
 
+// Instance data is split in a structure-of-arrays layout: positions change every frame while
+// colors are uploaded once, so they live in separate vertex buffers.
+
 #[repr(C)]
 #[derive(Copy, Clone, Pod, Zeroable, Debug)]
-pub struct InstanceRaw {
-    // 4x4 transform matrix
-    pub model: glam::Mat4,
-    pub color: glam::Vec4,
+pub struct InstancePosition {
+    // w is the particle's group, see groups.rs
+    pub position: glam::Vec4,
 }
 
-impl Display for InstanceRaw {
+impl Display for InstancePosition {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        self.model.fmt(f)
+        self.position.fmt(f)
     }
 }
 
-impl InstanceRaw {
+impl InstancePosition {
     pub fn descriptor() -> wgpu::VertexBufferLayout<'static> {
-        static ATTRIBUTES: &[wgpu::VertexAttribute] = &[
-            wgpu::VertexAttribute {
-                offset: 0,
-                shader_location: 2,
-                format: wgpu::VertexFormat::Float32x4,
-            },
-            wgpu::VertexAttribute {
-                offset: std::mem::size_of::<[f32; 4]>() as wgpu::BufferAddress,
-                shader_location: 3,
-                format: wgpu::VertexFormat::Float32x4,
-            },
-            wgpu::VertexAttribute {
-                offset: std::mem::size_of::<[f32; 8]>() as wgpu::BufferAddress,
-                shader_location: 4,
-                format: wgpu::VertexFormat::Float32x4,
-            },
-            wgpu::VertexAttribute {
-                offset: std::mem::size_of::<[f32; 12]>() as wgpu::BufferAddress,
-                shader_location: 5,
-                format: wgpu::VertexFormat::Float32x4,
-            },
-            wgpu::VertexAttribute {
-                offset: std::mem::size_of::<[f32; 16]>() as wgpu::BufferAddress,
-                shader_location: 6,
-                format: wgpu::VertexFormat::Float32x4,
-            },
-        ];
+        static ATTRIBUTES: &[wgpu::VertexAttribute] = &[wgpu::VertexAttribute {
+            offset: 0,
+            shader_location: 2,
+            format: wgpu::VertexFormat::Float32x4,
+        }];
+
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<InstancePosition>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Instance,
+            attributes: ATTRIBUTES,
+        }
+    }
+}
+
+// With the `packed-instances` feature the particle pipelines draw positions packed in halves, half
+// the size. The positions the compute kernel integrates stay in f32, half floats lose the small
+// steps of a frame: the kernel writes the drawn ones along with them, see position_packing.rs for
+// the frames it doesn't run.
+
+/// Positions as drawn, in halves with the `packed-instances` feature.
+#[cfg(not(feature = "packed-instances"))]
+pub type DrawnPosition = InstancePosition;
+#[cfg(feature = "packed-instances")]
+pub type DrawnPosition = PackedPosition;
+
+#[cfg(feature = "packed-instances")]
+#[repr(C)]
+#[derive(Copy, Clone, Pod, Zeroable, Debug)]
+pub struct PackedPosition {
+    // Half floats, with the group in w
+    position: [u16; 4],
+}
+
+#[cfg(feature = "packed-instances")]
+impl From<InstancePosition> for PackedPosition {
+    fn from(position: InstancePosition) -> Self {
+        Self::new(position.position)
+    }
+}
+
+/// Buffer of `capacity` drawn positions, with the `packed-instances` feature only: the particles
+/// are drawn from the positions the compute kernel integrates otherwise.
+#[cfg(not(feature = "packed-instances"))]
+pub fn create_drawn_position_buffer(
+    _device: &wgpu::Device,
+    _capacity: usize,
+) -> Option<wgpu::Buffer> {
+    None
+}
+#[cfg(feature = "packed-instances")]
+pub fn create_drawn_position_buffer(
+    device: &wgpu::Device,
+    capacity: usize,
+) -> Option<wgpu::Buffer> {
+    Some(device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Drawn Position Buffer"),
+        size: (capacity.max(1) * std::mem::size_of::<PackedPosition>()) as u64,
+        usage: wgpu::BufferUsages::VERTEX
+            | wgpu::BufferUsages::COPY_DST
+            | wgpu::BufferUsages::COPY_SRC
+            | wgpu::BufferUsages::STORAGE,
+        mapped_at_creation: false,
+    }))
+}
+
+/// Writes `positions` as drawn to `drawn_position_buffer`, from the position at `first` on.
+#[cfg(not(feature = "packed-instances"))]
+pub fn write_drawn_positions(
+    queue: &wgpu::Queue,
+    drawn_position_buffer: &wgpu::Buffer,
+    first: usize,
+    positions: &[InstancePosition],
+) {
+    queue.write_buffer(
+        drawn_position_buffer,
+        (first * std::mem::size_of::<DrawnPosition>()) as u64,
+        bytemuck::cast_slice(positions),
+    );
+}
+#[cfg(feature = "packed-instances")]
+pub fn write_drawn_positions(
+    queue: &wgpu::Queue,
+    drawn_position_buffer: &wgpu::Buffer,
+    first: usize,
+    positions: &[InstancePosition],
+) {
+    let drawn = positions
+        .iter()
+        .copied()
+        .map(PackedPosition::from)
+        .collect::<Vec<_>>();
+    queue.write_buffer(
+        drawn_position_buffer,
+        (first * std::mem::size_of::<DrawnPosition>()) as u64,
+        bytemuck::cast_slice(&drawn),
+    );
+}
+
+#[cfg(feature = "packed-instances")]
+impl PackedPosition {
+    pub fn new(position: glam::Vec4) -> Self {
+        Self {
+            position: position.to_array().map(half_float::to_bits),
+        }
+    }
+
+    #[cfg_attr(not(test), allow(dead_code))]
+    pub fn position(&self) -> glam::Vec4 {
+        glam::Vec4::from_array(self.position.map(half_float::from_bits))
+    }
+
+    pub fn descriptor() -> wgpu::VertexBufferLayout<'static> {
+        static ATTRIBUTES: &[wgpu::VertexAttribute] = &[wgpu::VertexAttribute {
+            offset: 0,
+            shader_location: 2,
+            format: wgpu::VertexFormat::Float16x4,
+        }];
+
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<PackedPosition>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Instance,
+            attributes: ATTRIBUTES,
+        }
+    }
+}
+
+/// Declares `PackedPosition`, the type of the elements of the drawn position buffers, and
+/// `pack_position(vec4<f32>) -> PackedPosition`, for the shaders writing them.
+#[cfg(not(feature = "packed-instances"))]
+pub const INSTANCE_POSITION_WGSL: &str = "
+alias PackedPosition = vec4<f32>;
+fn pack_position(position: vec4<f32>) -> PackedPosition { return position; }
+";
+#[cfg(feature = "packed-instances")]
+pub const INSTANCE_POSITION_WGSL: &str = "
+alias PackedPosition = vec2<u32>;
+fn pack_position(position: vec4<f32>) -> PackedPosition {
+    return vec2(pack2x16float(position.xy), pack2x16float(position.zw));
+}
+";
+
+// With the `packed-instances` feature colors are stored as 8 bit RGBA, a quarter of the size, and
+// unpacked by the vertex fetch.
+
+#[cfg(not(feature = "packed-instances"))]
+#[repr(C)]
+#[derive(Copy, Clone, Pod, Zeroable, Debug)]
+pub struct InstanceColor {
+    color: glam::Vec4,
+}
+
+#[cfg(feature = "packed-instances")]
+#[repr(C)]
+#[derive(Copy, Clone, Pod, Zeroable, Debug)]
+pub struct InstanceColor {
+    // RGBA, 8 bits each with red in the lowest byte
+    color: u32,
+}
+
+/// Declares `PackedColor`, the type of the elements of the color buffer,
+/// `pack_color(vec4<f32>) -> PackedColor` and `unpack_color(PackedColor) -> vec4<f32>`, for the
+/// shaders binding the color buffer as storage.
+#[cfg(not(feature = "packed-instances"))]
+pub const INSTANCE_COLOR_WGSL: &str = "
+alias PackedColor = vec4<f32>;
+fn pack_color(color: vec4<f32>) -> PackedColor { return color; }
+fn unpack_color(color: PackedColor) -> vec4<f32> { return color; }
+";
+#[cfg(feature = "packed-instances")]
+pub const INSTANCE_COLOR_WGSL: &str = "
+alias PackedColor = u32;
+fn pack_color(color: vec4<f32>) -> PackedColor { return pack4x8unorm(color); }
+fn unpack_color(color: PackedColor) -> vec4<f32> { return unpack4x8unorm(color); }
+";
+
+impl InstanceColor {
+    #[cfg(not(feature = "packed-instances"))]
+    const FORMAT: wgpu::VertexFormat = wgpu::VertexFormat::Float32x4;
+    #[cfg(feature = "packed-instances")]
+    const FORMAT: wgpu::VertexFormat = wgpu::VertexFormat::Unorm8x4;
+
+    #[cfg(not(feature = "packed-instances"))]
+    pub fn new(color: glam::Vec4) -> Self {
+        Self { color }
+    }
+
+    #[cfg(feature = "packed-instances")]
+    pub fn new(color: glam::Vec4) -> Self {
+        let bytes = (color.clamp(glam::Vec4::ZERO, glam::Vec4::ONE) * 255.0)
+            .round()
+            .to_array()
+            .map(|channel| channel as u8);
+        Self {
+            color: u32::from_le_bytes(bytes),
+        }
+    }
+
+    #[cfg(not(feature = "packed-instances"))]
+    pub fn color(&self) -> glam::Vec4 {
+        self.color
+    }
+
+    #[cfg(feature = "packed-instances")]
+    pub fn color(&self) -> glam::Vec4 {
+        glam::Vec4::from_array(self.color.to_le_bytes().map(f32::from)) / 255.0
+    }
+
+    pub fn descriptor() -> wgpu::VertexBufferLayout<'static> {
+        static ATTRIBUTES: &[wgpu::VertexAttribute] = &[wgpu::VertexAttribute {
+            offset: 0,
+            shader_location: 3,
+            format: InstanceColor::FORMAT,
+        }];
 
         wgpu::VertexBufferLayout {
-            array_stride: std::mem::size_of::<InstanceRaw>() as wgpu::BufferAddress,
+            array_stride: std::mem::size_of::<InstanceColor>() as wgpu::BufferAddress,
             step_mode: wgpu::VertexStepMode::Instance,
             attributes: ATTRIBUTES,
         }
@@ -86,16 +283,25 @@ impl InstanceRaw {
 
 pub struct Instance {
     pub position: glam::Vec3,
-    pub rotation: glam::Quat,
     pub color: glam::Vec4,
+    pub group: u32,
 }
 
 impl Instance {
-    pub fn to_raw(&self) -> InstanceRaw {
-        InstanceRaw {
-            model: (glam::Mat4::from_translation(self.position)
-                * glam::Mat4::from_quat(self.rotation)),
-            color: self.color,
+    pub fn to_position(&self) -> InstancePosition {
+        InstancePosition {
+            position: self.position.extend(self.group as f32),
         }
     }
+
+    /// The position as drawn, rounded to halves with the `packed-instances` feature.
+    #[cfg(feature = "packed-instances")]
+    #[cfg_attr(not(test), allow(dead_code))]
+    pub fn to_drawn_position(&self) -> DrawnPosition {
+        PackedPosition::new(self.to_position().position)
+    }
+
+    pub fn to_color(&self) -> InstanceColor {
+        InstanceColor::new(self.color)
+    }
 }
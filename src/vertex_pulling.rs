@@ -0,0 +1,50 @@
+//! Vertex pulling render path, selected with `--vertex-pulling` to compare against the classic
+//! instancing.
+//!
+//! No vertex or index buffer is bound: the particle quad is generated from the vertex index and
+//! each instance read from the position and color buffers, bound as storage, at the instance
+//! index. Compaction, the level of detail and the stretched particles keep their own vertex
+//! buffers, and the live ranges are drawn one by one rather than through multi-draw.
+
+/// Layout of the bind group at index 2 of the [`ParticleShape::Pulled`] pipelines.
+///
+/// [`ParticleShape::Pulled`]: crate::pipeline_cache::ParticleShape::Pulled
+pub const BIND_GROUP_LAYOUT_ENTRIES: [wgpu::BindGroupLayoutEntry; 2] =
+    [storage_entry(0), storage_entry(1)];
+
+const fn storage_entry(binding: u32) -> wgpu::BindGroupLayoutEntry {
+    wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility: wgpu::ShaderStages::VERTEX,
+        ty: wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Storage { read_only: true },
+            has_dynamic_offset: false,
+            min_binding_size: None,
+        },
+        count: None,
+    }
+}
+
+/// Binds `positions` and `colors`, with `layout` of [`BIND_GROUP_LAYOUT_ENTRIES`]. They change
+/// when the instance buffers grow or the drawing overlaps, so this is called every frame.
+pub fn create_bind_group(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    positions: &wgpu::Buffer,
+    colors: &wgpu::Buffer,
+) -> wgpu::BindGroup {
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("Vertex Pulling Bind Group"),
+        layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: positions.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: colors.as_entire_binding(),
+            },
+        ],
+    })
+}
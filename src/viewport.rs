@@ -0,0 +1,161 @@
+//! Windows showing the simulation, each from its own camera.
+//!
+//! A viewport owns everything that differs between windows: the surface and its configuration, and
+//! the camera with its uniform buffers. The device, pipelines and particle buffers are shared.
+//! Rendering headless, the main viewport has neither window nor surface and is only rendered
+//! offscreen.
+
+use winit::{
+    dpi::{LogicalSize, PhysicalPosition, PhysicalSize},
+    event::{MouseScrollDelta, WindowEvent},
+    window::{Window, WindowId},
+};
+
+use crate::{
+    camera::{Camera, CameraUniform, ZoomController},
+    groups::GroupMask,
+    touch::TouchGestures,
+};
+
+/// Windows can't be made smaller than this, the grid cells and overlays get unreadable.
+pub const MIN_WINDOW_SIZE: LogicalSize<u32> = LogicalSize::new(320, 240);
+
+pub struct Viewport {
+    // Declared before the window, so the surface is dropped first. None while the app is
+    // suspended, the window can't be drawn to then
+    pub surface: Option<wgpu::Surface>,
+    // None rendering headless
+    pub window: Option<Window>,
+    pub config: wgpu::SurfaceConfiguration,
+    pub size: PhysicalSize<u32>,
+    /// Physical pixels per logical pixel of the monitor the window is on
+    pub scale_factor: f64,
+    /// Nothing is rendered while minimized, the surface keeps its last size
+    pub minimized: bool,
+    pub camera: Camera,
+    pub zoom: ZoomController,
+    /// Fingers on the window's touch screen
+    pub gestures: TouchGestures,
+    /// Particle groups drawn in this window
+    pub group_mask: GroupMask,
+    pub camera_uniform: CameraUniform,
+    pub camera_buffer: wgpu::Buffer,
+    pub lighting_buffer: wgpu::Buffer,
+    pub camera_bind_group: wgpu::BindGroup,
+}
+
+impl Viewport {
+    /// Whether this viewport shows in the window `window_id`.
+    pub fn has_window(&self, window_id: WindowId) -> bool {
+        self.window
+            .as_ref()
+            .is_some_and(|window| window.id() == window_id)
+    }
+
+    /// Moves the camera from a touch or touchpad pinch `event`. Returns true if it was one.
+    pub fn gesture(&mut self, event: &WindowEvent) -> bool {
+        self.gestures.input(event, &mut self.camera, &mut self.zoom)
+    }
+
+    /// Zooms along the ray under `cursor`, or through the middle of the window without one.
+    pub fn scroll(&mut self, delta: &MouseScrollDelta, cursor: Option<PhysicalPosition<f64>>) {
+        let ray = self.camera_uniform.ray(self.ndc(cursor));
+        self.zoom.scroll(delta, ray);
+    }
+
+    /// The point under `cursor`, or in the middle of the window without one, as far from the eye
+    /// as the camera's target.
+    pub fn cursor_point(&self, cursor: Option<PhysicalPosition<f64>>) -> glam::Vec3 {
+        let ray = self.camera_uniform.ray(self.ndc(cursor));
+        self.camera.eye + ray * self.camera.eye.distance(self.camera.target)
+    }
+
+    fn ndc(&self, cursor: Option<PhysicalPosition<f64>>) -> glam::Vec2 {
+        cursor.map_or(glam::Vec2::ZERO, |cursor| {
+            glam::Vec2::new(
+                (2.0 * cursor.x / self.size.width.max(1) as f64 - 1.0) as f32,
+                (1.0 - 2.0 * cursor.y / self.size.height.max(1) as f64) as f32,
+            )
+        })
+    }
+
+    /// Slides the camera by `drag` pixels of the window, right and down.
+    pub fn pan(&mut self, drag: glam::Vec2) {
+        ZoomController::pan(&mut self.camera, drag, self.size.height as f32);
+    }
+
+    /// Reconfigures the surface for `size`, within the device's texture limits. Returns false for a
+    /// minimized window, which keeps its previous size.
+    pub fn resize(&mut self, device: &wgpu::Device, size: PhysicalSize<u32>) -> bool {
+        if size.width == 0 || size.height == 0 {
+            self.minimized = true;
+            return false;
+        }
+        self.minimized = false;
+        let max = device.limits().max_texture_dimension_2d;
+        let size = PhysicalSize::new(size.width.min(max), size.height.min(max));
+        self.size = size;
+        self.config.width = size.width;
+        self.config.height = size.height;
+        if let Some(surface) = &self.surface {
+            surface.configure(device, &self.config);
+        }
+        self.camera.aspect = size.width as f32 / size.height as f32;
+        true
+    }
+
+    /// Drops the surface when the app is suspended.
+    pub fn suspend(&mut self) {
+        self.surface = None;
+    }
+
+    /// Creates the surface again once the app is resumed, at the window's current size.
+    pub fn resume(
+        &mut self,
+        instance: &wgpu::Instance,
+        device: &wgpu::Device,
+    ) -> Result<(), wgpu::CreateSurfaceError> {
+        let Some(window) = &self.window else {
+            return Ok(());
+        };
+        // # Safety
+        //
+        // The surface is stored next to the window that created it and dropped before it.
+        self.surface = Some(unsafe { instance.create_surface(window) }?);
+        self.resize(device, window.inner_size());
+        Ok(())
+    }
+
+    /// Resizes the surface to `size` after the window moved to a monitor with another scale
+    /// factor.
+    pub fn rescale(
+        &mut self,
+        device: &wgpu::Device,
+        scale_factor: f64,
+        size: PhysicalSize<u32>,
+    ) -> bool {
+        if scale_factor != self.scale_factor {
+            log::info!("Scale factor: {scale_factor}");
+            self.scale_factor = scale_factor;
+        }
+        self.resize(device, size)
+    }
+
+    /// Shows or hides the particles of `group` in this window.
+    pub fn toggle_group(&mut self, group: usize) {
+        self.group_mask.toggle(group);
+        log::info!("Groups shown: {}", self.group_mask);
+    }
+
+    /// Moves the camera towards its zoom target and uploads it.
+    pub fn update_camera(&mut self, queue: &wgpu::Queue, dt: f32) {
+        self.zoom.update(&mut self.camera, dt);
+        self.camera_uniform.update_view_proj(&self.camera);
+        self.camera_uniform.set_group_mask(self.group_mask);
+        queue.write_buffer(
+            &self.camera_buffer,
+            0,
+            bytemuck::cast_slice(&[self.camera_uniform]),
+        );
+    }
+}
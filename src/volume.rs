@@ -0,0 +1,366 @@
+//! Volumetric view of the particles, showing the cloud as a density field rather than quads.
+//!
+//! Every frame the particles are counted into the voxels of a grid around the bounds box, the
+//! counts turned into densities in a 3D texture, and a fullscreen pass ray-marches the texture,
+//! coloring each view ray with a transfer function of the densities it crosses. Particles outside
+//! the grid don't show.
+
+use bytemuck::{Pod, Zeroable};
+use glam::Vec4;
+use wgpu::util::DeviceExt;
+
+// Voxels along each axis of the grid
+const RESOLUTION: u32 = 64;
+// Samples along each view ray
+const STEPS: u32 = 128;
+// Half size of the grid, that of the bounds box in sim_params.rs
+const HALF_SIZE: f32 = 1000.0;
+// Must match `@workgroup_size` of splat in volume_splat.wgsl
+const WORKGROUP_SIZE: u32 = 64;
+// Must match `@workgroup_size` of resolve in volume_splat.wgsl, along each axis
+const RESOLVE_WORKGROUP_SIZE: u32 = 4;
+const DENSITY_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+
+// Must match VolumeParams in volume.wgsl and volume_splat.wgsl
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Pod, Zeroable)]
+struct VolumeParams {
+    bounds_min: Vec4,
+    bounds_max: Vec4,
+    density_scale: f32,
+    count: u32,
+    resolution: u32,
+    steps: u32,
+}
+
+impl VolumeParams {
+    fn new(count: u32) -> Self {
+        let voxels = RESOLUTION.pow(3) as f32;
+        Self {
+            bounds_min: Vec4::new(-HALF_SIZE, -HALF_SIZE, -HALF_SIZE, 0.0),
+            bounds_max: Vec4::new(HALF_SIZE, HALF_SIZE, HALF_SIZE, 0.0),
+            density_scale: voxels / count.max(1) as f32,
+            count,
+            resolution: RESOLUTION,
+            steps: STEPS,
+        }
+    }
+}
+
+pub struct VolumeView {
+    params_buffer: wgpu::Buffer,
+    counts_buffer: wgpu::Buffer,
+    // Kept alive for the view
+    _density_texture: wgpu::Texture,
+    density_view: wgpu::TextureView,
+    splat_bind_group_layout: wgpu::BindGroupLayout,
+    splat_pipeline: wgpu::ComputePipeline,
+    resolve_pipeline: wgpu::ComputePipeline,
+    render_pipeline: wgpu::RenderPipeline,
+    render_bind_group: wgpu::BindGroup,
+}
+
+impl VolumeView {
+    pub fn new(
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+        camera_bind_group_layout: &wgpu::BindGroupLayout,
+    ) -> Self {
+        let params_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Volume Params Buffer"),
+            contents: bytemuck::cast_slice(&[VolumeParams::new(0)]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let counts_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Volume Counts Buffer"),
+            size: RESOLUTION.pow(3) as u64 * std::mem::size_of::<u32>() as u64,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let density_texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Volume Density Texture"),
+            size: wgpu::Extent3d {
+                width: RESOLUTION,
+                height: RESOLUTION,
+                depth_or_array_layers: RESOLUTION,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D3,
+            format: DENSITY_FORMAT,
+            usage: wgpu::TextureUsages::STORAGE_BINDING | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+        let density_view = density_texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        let buffer_entry = |binding, visibility, ty| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility,
+            ty: wgpu::BindingType::Buffer {
+                ty,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let splat_bind_group_layout_entries = [
+            buffer_entry(
+                0,
+                wgpu::ShaderStages::COMPUTE,
+                wgpu::BufferBindingType::Uniform,
+            ),
+            buffer_entry(
+                1,
+                wgpu::ShaderStages::COMPUTE,
+                wgpu::BufferBindingType::Storage { read_only: true },
+            ),
+            buffer_entry(
+                2,
+                wgpu::ShaderStages::COMPUTE,
+                wgpu::BufferBindingType::Storage { read_only: false },
+            ),
+            wgpu::BindGroupLayoutEntry {
+                binding: 3,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::StorageTexture {
+                    access: wgpu::StorageTextureAccess::WriteOnly,
+                    format: DENSITY_FORMAT,
+                    view_dimension: wgpu::TextureViewDimension::D3,
+                },
+                count: None,
+            },
+        ];
+        let render_bind_group_layout_entries = [
+            buffer_entry(
+                0,
+                wgpu::ShaderStages::FRAGMENT,
+                wgpu::BufferBindingType::Uniform,
+            ),
+            wgpu::BindGroupLayoutEntry {
+                binding: 1,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    view_dimension: wgpu::TextureViewDimension::D3,
+                    multisampled: false,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 2,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                count: None,
+            },
+        ];
+
+        let splat_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("Volume Splat Bind Group Layout"),
+                entries: &splat_bind_group_layout_entries,
+            });
+        let render_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("Volume Bind Group Layout"),
+                entries: &render_bind_group_layout_entries,
+            });
+
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Volume Sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        let render_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Volume Bind Group"),
+            layout: &render_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: params_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&density_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::Sampler(&sampler),
+                },
+            ],
+        });
+
+        let splat_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Volume Splat Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("volume_splat.wgsl").into()),
+        });
+        let splat_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Volume Splat Pipeline Layout"),
+                bind_group_layouts: &[&splat_bind_group_layout],
+                push_constant_ranges: &[],
+            });
+        let create_compute_pipeline = |label, entry_point| {
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some(label),
+                layout: Some(&splat_pipeline_layout),
+                module: &splat_shader,
+                entry_point,
+            })
+        };
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Volume Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("volume.wgsl").into()),
+        });
+        let render_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Volume Pipeline Layout"),
+                bind_group_layouts: &[camera_bind_group_layout, &render_bind_group_layout],
+                push_constant_ranges: &[],
+            });
+        let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Volume Pipeline"),
+            layout: Some(&render_pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    // The colors are premultiplied by their alpha, over the background
+                    blend: Some(wgpu::BlendState::PREMULTIPLIED_ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+
+        #[cfg(feature = "guardrails")]
+        {
+            let reflection = crate::guardrails::ShaderReflection::new(
+                "volume_splat.wgsl",
+                include_str!("volume_splat.wgsl"),
+            );
+            reflection.check_bind_group_layout(0, &splat_bind_group_layout_entries);
+            reflection.check_struct_size("VolumeParams", std::mem::size_of::<VolumeParams>());
+            let reflection = crate::guardrails::ShaderReflection::new(
+                "volume.wgsl",
+                include_str!("volume.wgsl"),
+            );
+            reflection.check_bind_group_layout(1, &render_bind_group_layout_entries);
+            reflection.check_struct_size("VolumeParams", std::mem::size_of::<VolumeParams>());
+            reflection.check_struct_size(
+                "CameraUniform",
+                std::mem::size_of::<crate::camera::CameraUniform>(),
+            );
+        }
+
+        Self {
+            params_buffer,
+            counts_buffer,
+            _density_texture: density_texture,
+            density_view,
+            splat_bind_group_layout,
+            splat_pipeline: create_compute_pipeline("Volume Splat Pipeline", "splat"),
+            resolve_pipeline: create_compute_pipeline("Volume Resolve Pipeline", "resolve"),
+            render_pipeline,
+            render_bind_group,
+        }
+    }
+
+    /// Splats the first `count` particles of `position_buffer` into the densities drawn next.
+    pub fn encode(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        position_buffer: &wgpu::Buffer,
+        count: usize,
+    ) {
+        let count = count as u32;
+        queue.write_buffer(
+            &self.params_buffer,
+            0,
+            bytemuck::cast_slice(&[VolumeParams::new(count)]),
+        );
+        encoder.clear_buffer(&self.counts_buffer, 0, None);
+
+        // The position buffer changes with the particle count, bound every frame
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Volume Splat Bind Group"),
+            layout: &self.splat_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: self.params_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: position_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: self.counts_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::TextureView(&self.density_view),
+                },
+            ],
+        });
+
+        let groups = count.div_ceil(WORKGROUP_SIZE).max(1);
+        let max_groups = device.limits().max_compute_workgroups_per_dimension;
+        let (x, y) = (groups.min(max_groups), groups.div_ceil(max_groups));
+        #[cfg(feature = "guardrails")]
+        crate::guardrails::check_dispatch_coverage(
+            [x, y, 1],
+            [WORKGROUP_SIZE, 1, 1],
+            count as usize,
+        );
+        let resolve_groups = RESOLUTION.div_ceil(RESOLVE_WORKGROUP_SIZE);
+
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Volume Splat Pass"),
+        });
+        compute_pass.set_bind_group(0, &bind_group, &[]);
+        compute_pass.set_pipeline(&self.splat_pipeline);
+        compute_pass.dispatch_workgroups(x, y, 1);
+        compute_pass.set_pipeline(&self.resolve_pipeline);
+        compute_pass.dispatch_workgroups(resolve_groups, resolve_groups, resolve_groups);
+    }
+
+    /// Ray-marches the densities into `view`, over what `load` leaves in it.
+    pub fn draw(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        view: &wgpu::TextureView,
+        camera_bind_group: &wgpu::BindGroup,
+        load: wgpu::LoadOp<wgpu::Color>,
+    ) {
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Volume Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view,
+                resolve_target: None,
+                ops: wgpu::Operations { load, store: true },
+            })],
+            depth_stencil_attachment: None,
+        });
+        render_pass.set_pipeline(&self.render_pipeline);
+        render_pass.set_bind_group(0, camera_bind_group, &[]);
+        render_pass.set_bind_group(1, &self.render_bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
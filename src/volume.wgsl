@@ -0,0 +1,100 @@
+// Ray-marches the particle densities of volume_splat.wgsl, a fullscreen triangle coloring each
+// view ray with a transfer function of the densities it crosses
+
+// Must match CameraUniform in camera.rs
+struct CameraUniform {
+    view_proj: mat4x4<f32>,
+    eye: vec4<f32>,
+    inv_view_proj: mat4x4<f32>,
+    previous_view_proj: mat4x4<f32>,
+    view: mat4x4<f32>,
+    proj: mat4x4<f32>,
+    group_mask: u32,
+    reverse_z: u32,
+};
+@group(0) @binding(0)
+var<uniform> camera: CameraUniform;
+
+// Must match VolumeParams in volume.rs and volume_splat.wgsl
+struct VolumeParams {
+    bounds_min: vec4<f32>,
+    bounds_max: vec4<f32>,
+    density_scale: f32,
+    count: u32,
+    resolution: u32,
+    steps: u32,
+}
+
+@group(1) @binding(0) var<uniform> params: VolumeParams;
+@group(1) @binding(1) var density: texture_3d<f32>;
+@group(1) @binding(2) var density_sampler: sampler;
+
+// Opacity of a voxel at the average density
+const ABSORPTION: f32 = 0.05;
+// Past this much opacity the rest of the ray barely shows
+const OPAQUE: f32 = 0.99;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    // View ray in homogeneous coordinates, divided by w once interpolated
+    @location(0) ray: vec4<f32>,
+};
+
+// Same as environment.wgsl
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    // Covers the screen with (-1, -1), (3, -1) and (-1, 3)
+    let ndc = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u)) * 2.0 - 1.0;
+    let near_depth = select(0.0, 1.0, camera.reverse_z != 0u);
+    let near = camera.inv_view_proj * vec4<f32>(ndc, near_depth, 1.0);
+    var out: VertexOutput;
+    out.clip_position = vec4<f32>(ndc, 0.0, 1.0);
+    out.ray = vec4<f32>(near.xyz - camera.eye.xyz * near.w, near.w);
+    return out;
+}
+
+// Dark blue for sparse voxels to cyan, yellow and white for the densest
+fn transfer(density: f32) -> vec3<f32> {
+    let t = clamp(log2(1.0 + density) / 5.0, 0.0, 1.0) * 3.0;
+    let sparse = mix(vec3<f32>(0.05, 0.1, 0.4), vec3<f32>(0.1, 0.7, 0.9), clamp(t, 0.0, 1.0));
+    let dense = mix(vec3<f32>(1.0, 0.8, 0.2), vec3<f32>(1.0), clamp(t - 2.0, 0.0, 1.0));
+    return mix(sparse, dense, clamp(t - 1.0, 0.0, 1.0));
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let direction = normalize(in.ray.xyz / in.ray.w);
+    let origin = camera.eye.xyz;
+
+    // Where the ray enters and leaves the bounds of the volume
+    let inverse = 1.0 / direction;
+    let to_min = (params.bounds_min.xyz - origin) * inverse;
+    let to_max = (params.bounds_max.xyz - origin) * inverse;
+    let near = max(max(min(to_min.x, to_max.x), min(to_min.y, to_max.y)), min(to_min.z, to_max.z));
+    let far = min(min(max(to_min.x, to_max.x), max(to_min.y, to_max.y)), max(to_min.z, to_max.z));
+    let start = max(near, 0.0);
+    if far <= start {
+        return vec4<f32>(0.0);
+    }
+
+    let size = params.bounds_max.xyz - params.bounds_min.xyz;
+    let step = (far - start) / f32(params.steps);
+    // Steps are measured in voxels so the opacity doesn't depend on the step count
+    let voxels_per_step = step * f32(params.resolution) / max(max(size.x, size.y), size.z);
+
+    // Front to back, premultiplied by alpha
+    var color = vec3<f32>(0.0);
+    var alpha = 0.0;
+    for (var i = 0u; i < params.steps; i++) {
+        let position = origin + direction * (start + (f32(i) + 0.5) * step);
+        let uvw = (position - params.bounds_min.xyz) / size;
+        let value = textureSampleLevel(density, density_sampler, uvw, 0.0).r;
+        let opacity = 1.0 - exp(-value * ABSORPTION * voxels_per_step);
+        color += (1.0 - alpha) * opacity * transfer(value);
+        alpha += (1.0 - alpha) * opacity;
+        if alpha > OPAQUE {
+            break;
+        }
+    }
+    return vec4<f32>(color, alpha);
+}
@@ -0,0 +1,53 @@
+// Counts the particles in each voxel of the volume, then turns the counts into densities
+
+// Must match VolumeParams in volume.rs and volume.wgsl
+struct VolumeParams {
+    bounds_min: vec4<f32>,
+    bounds_max: vec4<f32>,
+    // Turns a count into a density, 1 for as many particles as an average voxel
+    density_scale: f32,
+    count: u32,
+    resolution: u32,
+    steps: u32,
+}
+
+@group(0) @binding(0) var<uniform> params: VolumeParams;
+@group(0) @binding(1) var<storage, read> positions: array<vec4<f32>>;
+@group(0) @binding(2) var<storage, read_write> counts: array<atomic<u32>>;
+@group(0) @binding(3) var density: texture_storage_3d<rgba16float, write>;
+
+fn voxel_index(voxel: vec3<u32>) -> u32 {
+    return (voxel.z * params.resolution + voxel.y) * params.resolution + voxel.x;
+}
+
+// Must match `WORKGROUP_SIZE` in volume.rs
+@compute @workgroup_size(64)
+fn splat(
+    @builtin(global_invocation_id) id: vec3<u32>,
+    @builtin(num_workgroups) workgroups: vec3<u32>,
+) {
+    // Large counts are dispatched in rows, a single dimension is limited to 65535 workgroups
+    let index = id.x + id.y * workgroups.x * 64u;
+    if index >= params.count {
+        return;
+    }
+    let uvw = (positions[index].xyz - params.bounds_min.xyz)
+        / (params.bounds_max.xyz - params.bounds_min.xyz);
+    // Particles outside the volume aren't drawn
+    if any(uvw < vec3<f32>(0.0)) || any(uvw >= vec3<f32>(1.0)) {
+        return;
+    }
+    let voxel = vec3<u32>(uvw * f32(params.resolution));
+    atomicAdd(&counts[voxel_index(voxel)], 1u);
+}
+
+// Must match `RESOLVE_WORKGROUP_SIZE` in volume.rs
+@compute @workgroup_size(4, 4, 4)
+fn resolve(@builtin(global_invocation_id) id: vec3<u32>) {
+    if any(id >= vec3<u32>(params.resolution)) {
+        return;
+    }
+    let count = atomicLoad(&counts[voxel_index(id)]);
+    let value = f32(count) * params.density_scale;
+    textureStore(density, vec3<i32>(id), vec4<f32>(value, 0.0, 0.0, 0.0));
+}
@@ -0,0 +1,131 @@
+//! Detection of GPU hangs and device loss, so the renderer can rebuild its device instead of
+//! crashing.
+//!
+//! Long compute dispatches can trip the driver's timeout detection and reset the device. When the
+//! GPU takes suspiciously long to finish a frame, the particle count is halved before the device is
+//! recreated so the same dispatch doesn't hang it again. Other device losses, like driver updates,
+//! keep it.
+//!
+//! Only the GPU's work counts: a frame is timed from its submission to the callback the queue calls
+//! once it's done, so a window blocking on the surface or a readback waiting for the GPU doesn't
+//! look like a hang.
+
+use std::{
+    collections::VecDeque,
+    error::Error,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use wgpu_core::device::{
+    queue::{QueueSubmitError, QueueWriteError},
+    DeviceError,
+};
+
+// Frames the GPU is still working on after this long are treated as a GPU hang
+const HANG_GPU_TIME: Duration = Duration::from_secs(2);
+// The particle count is never reduced below this
+const MIN_PARTICLES: usize = 1_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Failure {
+    DeviceLost,
+    Hang,
+}
+
+#[derive(Default)]
+pub struct Watchdog {
+    device_lost: Arc<AtomicBool>,
+    // Submission times of the frames the GPU hasn't finished, oldest first
+    in_flight: Arc<Mutex<VecDeque<Instant>>>,
+    particle_limit: Option<usize>,
+}
+
+impl Watchdog {
+    /// Flags the device as lost when it reports running out of memory or being lost. Other errors
+    /// still panic, like they do without an error handler.
+    pub fn watch(&mut self, device: &wgpu::Device) {
+        // The frames of the previous device are never finished
+        self.in_flight = Arc::default();
+        self.device_lost.store(false, Ordering::Relaxed);
+        let device_lost = self.device_lost.clone();
+        device.on_uncaptured_error(Box::new(move |error| {
+            let lost = match &error {
+                wgpu::Error::OutOfMemory { .. } => true,
+                wgpu::Error::Validation { source, .. } => is_device_lost(source.as_ref()),
+            };
+            if !lost {
+                panic!("wgpu error: {error}");
+            }
+            log::error!("{error}");
+            device_lost.store(true, Ordering::Relaxed);
+        }));
+    }
+
+    /// Starts timing the work submitted to `queue` so far, as a frame. Must be called after the
+    /// last submission of each frame.
+    pub fn submitted(&self, queue: &wgpu::Queue) {
+        self.in_flight.lock().unwrap().push_back(Instant::now());
+        let in_flight = self.in_flight.clone();
+        // Called in order of submission
+        queue.on_submitted_work_done(move || {
+            in_flight.lock().unwrap().pop_front();
+        });
+    }
+
+    /// Returns why the device has to be rebuilt, if it was lost or if the GPU has been working on
+    /// a frame for long enough to suspect a hang. `device` must have been polled just before, so
+    /// the frames it finished are accounted for.
+    pub fn check(&self) -> Option<Failure> {
+        if self.device_lost.load(Ordering::Relaxed) {
+            Some(Failure::DeviceLost)
+        } else if self
+            .in_flight
+            .lock()
+            .unwrap()
+            .front()
+            .is_some_and(|submitted| submitted.elapsed() > HANG_GPU_TIME)
+        {
+            Some(Failure::Hang)
+        } else {
+            None
+        }
+    }
+
+    /// Maximum number of particles to simulate, if a recovery had to lower it.
+    pub fn particle_limit(&self) -> Option<usize> {
+        self.particle_limit
+    }
+
+    /// Halves the number of particles after a hang with `active` particles. Returns the new limit.
+    pub fn reduce(&mut self, active: usize) -> usize {
+        let limit = (active / 2).max(MIN_PARTICLES);
+        self.particle_limit = Some(limit);
+        limit
+    }
+}
+
+/// True if `error` or one of its sources is the device being lost. Most errors wrap the device's
+/// transparently, hiding it from the sources, so the ones raised while rendering are matched too.
+fn is_device_lost(error: &(dyn Error + 'static)) -> bool {
+    let mut source = Some(error);
+    while let Some(error) = source {
+        let lost = matches!(error.downcast_ref(), Some(DeviceError::Lost))
+            || matches!(
+                error.downcast_ref(),
+                Some(QueueSubmitError::Queue(DeviceError::Lost))
+            )
+            || matches!(
+                error.downcast_ref(),
+                Some(QueueWriteError::Queue(DeviceError::Lost))
+            );
+        if lost {
+            return true;
+        }
+        source = error.source();
+    }
+    false
+}
@@ -0,0 +1,287 @@
+//! Wind: a velocity field over a box of the scene, carrying the particles along like the
+//! turbulence, for flow visualization.
+//!
+//! The field is a grid of velocities, read from a raw file such as a 3D array exported from a VDB
+//! grid, or generated. The compute kernel reads it from a 3D texture and the CPU backend from the
+//! same grid, both interpolating trilinearly. Outside its box, the wind doesn't blow.
+
+use std::path::{Path, PathBuf};
+
+use bytemuck::{Pod, Zeroable};
+use glam::{UVec3, Vec3, Vec3A, Vec4};
+use wgpu::util::DeviceExt;
+
+use crate::turbulence::TurbulenceParams;
+
+/// Included at the start of the compute kernel, which binds the field and its parameters.
+pub const WIND_WGSL: &str = include_str!("wind.wgsl");
+
+/// Layout of the wind bindings of the compute kernel, at 6 and 7 of its group 0.
+pub const BIND_GROUP_LAYOUT_ENTRIES: [wgpu::BindGroupLayoutEntry; 2] = [
+    wgpu::BindGroupLayoutEntry {
+        binding: 6,
+        visibility: wgpu::ShaderStages::COMPUTE,
+        ty: wgpu::BindingType::Texture {
+            sample_type: wgpu::TextureSampleType::Float { filterable: false },
+            view_dimension: wgpu::TextureViewDimension::D3,
+            multisampled: false,
+        },
+        count: None,
+    },
+    wgpu::BindGroupLayoutEntry {
+        binding: 7,
+        visibility: wgpu::ShaderStages::COMPUTE,
+        ty: wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Uniform,
+            has_dynamic_offset: false,
+            min_binding_size: None,
+        },
+        count: None,
+    },
+];
+
+#[derive(Debug, thiserror::Error)]
+pub enum WindError {
+    #[error("unable to read {0}: {1}")]
+    Io(PathBuf, std::io::Error),
+    #[error("{0} holds {1} bytes, a {2}x{3}x{4} field takes {5}")]
+    Size(PathBuf, usize, u32, u32, u32, usize),
+    #[error("the wind box is empty, `max` must be above `min` on every axis")]
+    EmptyBox,
+}
+
+/// Where the velocities come from.
+#[derive(Debug, Clone, PartialEq)]
+pub enum WindData {
+    /// Little-endian `f32` x, y, z triples, x varying fastest, then y, then z
+    File(PathBuf),
+    /// A column of air turning around the vertical axis through the center of the box and rising
+    /// near it
+    Vortex,
+}
+
+/// How a scene describes its wind.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WindSource {
+    pub data: WindData,
+    /// Samples along each axis
+    pub size: UVec3,
+    pub min: Vec3,
+    pub max: Vec3,
+    /// Scales the velocities
+    pub strength: f32,
+}
+
+impl Default for WindSource {
+    fn default() -> Self {
+        Self {
+            data: WindData::Vortex,
+            size: UVec3::splat(32),
+            min: Vec3::splat(-1000.0),
+            max: Vec3::splat(1000.0),
+            strength: 1.0,
+        }
+    }
+}
+
+impl WindSource {
+    /// Makes a relative file path relative to `dir`, the directory of the scene file.
+    pub fn resolve(&mut self, dir: &Path) {
+        if let WindData::File(path) = &mut self.data {
+            if path.is_relative() {
+                *path = dir.join(&path);
+            }
+        }
+    }
+}
+
+// Must match WindParams in wind.wgsl
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Pod, Zeroable)]
+pub struct WindParams {
+    min: Vec4,
+    max: Vec4,
+    strength: f32,
+    _padding: [f32; 3],
+}
+
+#[derive(Debug, Clone)]
+pub struct WindField {
+    size: UVec3,
+    min: Vec3,
+    max: Vec3,
+    strength: f32,
+    // The w of each is unused, kept for the texture
+    velocities: Vec<Vec4>,
+}
+
+impl WindField {
+    pub fn load(source: &WindSource) -> Result<Self, WindError> {
+        if source.max.cmple(source.min).any() {
+            return Err(WindError::EmptyBox);
+        }
+        let size = source.size.max(UVec3::ONE);
+        let count = (size.x * size.y * size.z) as usize;
+        let velocities = match &source.data {
+            WindData::File(path) => {
+                let bytes = std::fs::read(path).map_err(|e| WindError::Io(path.to_owned(), e))?;
+                let expected = count * 3 * std::mem::size_of::<f32>();
+                if bytes.len() != expected {
+                    return Err(WindError::Size(
+                        path.to_owned(),
+                        bytes.len(),
+                        size.x,
+                        size.y,
+                        size.z,
+                        expected,
+                    ));
+                }
+                bytes
+                    .chunks_exact(3 * std::mem::size_of::<f32>())
+                    .map(|sample| {
+                        let [x, y, z] = [0, 4, 8].map(|offset| {
+                            f32::from_le_bytes(sample[offset..offset + 4].try_into().unwrap())
+                        });
+                        Vec4::new(x, y, z, 0.0)
+                    })
+                    .collect()
+            }
+            WindData::Vortex => (0..count as u32)
+                .map(|index| {
+                    let cell = UVec3::new(
+                        index % size.x,
+                        index / size.x % size.y,
+                        index / (size.x * size.y),
+                    );
+                    // From -1 to 1 across the box
+                    let p = cell.as_vec3() / (size - 1).max(UVec3::ONE).as_vec3() * 2.0 - 1.0;
+                    let radius = p.x.hypot(p.z);
+                    let swirl = Vec3::new(-p.z, 0.0, p.x) * (-2.0 * radius * radius).exp();
+                    let rise = (1.0 - radius).max(0.0) * 0.5;
+                    (swirl + Vec3::Y * rise).extend(0.0)
+                })
+                .collect(),
+        };
+
+        Ok(Self {
+            size,
+            min: source.min,
+            max: source.max,
+            strength: source.strength,
+            velocities,
+        })
+    }
+
+    /// Must match `wind_velocity` in wind.wgsl.
+    pub fn velocity(&self, position: Vec3) -> Vec3 {
+        if self.strength == 0.0 || position.cmplt(self.min).any() || position.cmpgt(self.max).any()
+        {
+            return Vec3::ZERO;
+        }
+        let last = self.size - 1;
+        let cell = (position - self.min) / (self.max - self.min) * last.as_vec3();
+        let base = cell.as_uvec3().min(last);
+        let next = (base + 1).min(last);
+        let t = cell - base.as_vec3();
+
+        let load = |x: u32, y: u32, z: u32| {
+            self.velocities[(x + (y + z * self.size.y) * self.size.x) as usize].truncate()
+        };
+        let along_x = |y, z| load(base.x, y, z).lerp(load(next.x, y, z), t.x);
+        let along_y = |z| along_x(base.y, z).lerp(along_x(next.y, z), t.y);
+        along_y(base.z).lerp(along_y(next.z), t.z) * self.strength
+    }
+
+    fn params(&self) -> WindParams {
+        WindParams {
+            min: self.min.extend(0.0),
+            max: self.max.extend(0.0),
+            strength: self.strength,
+            _padding: [0.0; 3],
+        }
+    }
+}
+
+/// Velocity of the air the particles move in: the turbulence, and the wind where there is some.
+#[derive(Debug, Clone, Copy)]
+pub struct Flow<'a> {
+    pub turbulence: &'a TurbulenceParams,
+    pub wind: Option<&'a WindField>,
+}
+
+impl Flow<'_> {
+    pub fn velocity(&self, position: Vec3) -> Vec3 {
+        let wind = self.wind.map_or(Vec3::ZERO, |wind| wind.velocity(position));
+        self.turbulence.velocity(position) + wind
+    }
+
+    pub fn velocity_simd(&self, position: Vec3A) -> Vec3A {
+        let wind = self
+            .wind
+            .map_or(Vec3A::ZERO, |wind| wind.velocity(position.into()).into());
+        self.turbulence.velocity_simd(position) + wind
+    }
+}
+
+/// The field in a 3D texture, with its parameters, for the compute kernel.
+pub struct WindTexture {
+    // Kept alive for the view
+    _texture: wgpu::Texture,
+    view: wgpu::TextureView,
+    params_buffer: wgpu::Buffer,
+}
+
+impl WindTexture {
+    /// Without a field, a single still sample the kernel skips.
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue, field: Option<&WindField>) -> Self {
+        let still = [Vec4::ZERO];
+        let (size, velocities, params) = match field {
+            Some(field) => (field.size, &field.velocities[..], field.params()),
+            None => (UVec3::ONE, &still[..], WindParams::zeroed()),
+        };
+        let texture = device.create_texture_with_data(
+            queue,
+            &wgpu::TextureDescriptor {
+                label: Some("Wind Texture"),
+                size: wgpu::Extent3d {
+                    width: size.x,
+                    height: size.y,
+                    depth_or_array_layers: size.z,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D3,
+                format: wgpu::TextureFormat::Rgba32Float,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+                view_formats: &[],
+            },
+            bytemuck::cast_slice(velocities),
+        );
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let params_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Wind Params Buffer"),
+            contents: bytemuck::bytes_of(&params),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+
+        Self {
+            _texture: texture,
+            view,
+            params_buffer,
+        }
+    }
+
+    /// Entries of [`BIND_GROUP_LAYOUT_ENTRIES`].
+    pub fn bind_group_entries(&self) -> [wgpu::BindGroupEntry<'_>; 2] {
+        [
+            wgpu::BindGroupEntry {
+                binding: 6,
+                resource: wgpu::BindingResource::TextureView(&self.view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 7,
+                resource: self.params_buffer.as_entire_binding(),
+            },
+        ]
+    }
+}
@@ -0,0 +1,35 @@
+// Velocity of the wind of wind.rs, included at the start of the compute kernel, which binds
+// `wind_field` and `wind`
+
+// Must match WindParams in wind.rs
+struct WindParams {
+    min: vec4<f32>,
+    max: vec4<f32>,
+    // 0 without a wind field
+    strength: f32,
+    _padding0: f32,
+    _padding1: f32,
+    _padding2: f32,
+};
+
+// Must match WindField::velocity in wind.rs
+fn wind_velocity(position: vec3<f32>) -> vec3<f32> {
+    if wind.strength == 0.0 || any(position < wind.min.xyz) || any(position > wind.max.xyz) {
+        return vec3<f32>(0.0);
+    }
+    let last = textureDimensions(wind_field) - 1u;
+    let cell = (position - wind.min.xyz) / (wind.max.xyz - wind.min.xyz) * vec3<f32>(last);
+    let base = min(vec3<u32>(cell), last);
+    let next = min(base + 1u, last);
+    let t = cell - vec3<f32>(base);
+
+    let x00 = mix(wind_sample(base.x, base.y, base.z), wind_sample(next.x, base.y, base.z), t.x);
+    let x10 = mix(wind_sample(base.x, next.y, base.z), wind_sample(next.x, next.y, base.z), t.x);
+    let x01 = mix(wind_sample(base.x, base.y, next.z), wind_sample(next.x, base.y, next.z), t.x);
+    let x11 = mix(wind_sample(base.x, next.y, next.z), wind_sample(next.x, next.y, next.z), t.x);
+    return mix(mix(x00, x10, t.y), mix(x01, x11, t.y), t.z) * wind.strength;
+}
+
+fn wind_sample(x: u32, y: u32, z: u32) -> vec3<f32> {
+    return textureLoad(wind_field, vec3<u32>(x, y, z), 0).xyz;
+}
@@ -0,0 +1,226 @@
+//! Smoke tests of the shaders and pipelines on a real GPU, without a window: the particles are
+//! stepped by the compute kernel, read back, and drawn into an offscreen texture, by the library
+//! and by the binary's headless mode. Skipped when no adapter is available.
+
+use std::sync::Arc;
+
+use particles::{
+    internal::{error::AppError, options::Options, settings::Settings, state::State},
+    Camera, DepthRange, ParticleRenderer,
+};
+
+const SIZE: u32 = 256;
+const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8Unorm;
+// Bytes per row of the read back image, already a multiple of COPY_BYTES_PER_ROW_ALIGNMENT
+const BYTES_PER_ROW: u32 = SIZE * 4;
+// Farther than any particle of one step can get, at the default speeds and turbulence
+const MAX_STEP: f32 = 10.0;
+
+fn device() -> Option<(Arc<wgpu::Device>, Arc<wgpu::Queue>)> {
+    let instance = wgpu::Instance::default();
+    let adapter =
+        pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions::default()))?;
+    let (device, queue) =
+        pollster::block_on(adapter.request_device(&wgpu::DeviceDescriptor::default(), None))
+            .ok()?;
+    Some((Arc::new(device), Arc::new(queue)))
+}
+
+/// Looking at the origin from far enough to see the whole default scene, or away from it.
+fn camera(towards_the_scene: bool) -> Camera {
+    let eye = glam::Vec3::new(0.0, 1.0, 5000.0);
+    Camera {
+        eye,
+        target: if towards_the_scene {
+            glam::Vec3::ZERO
+        } else {
+            eye * 2.0
+        },
+        up: glam::Vec3::Y,
+        aspect: 1.0,
+        fovy: 20.0,
+        depth: DepthRange::default(),
+    }
+}
+
+/// Draws the particles seen through `camera` over black, returning the RGBA8 pixels.
+fn render(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    renderer: &mut ParticleRenderer,
+    camera: &Camera,
+) -> Vec<u8> {
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: None,
+        size: wgpu::Extent3d {
+            width: SIZE,
+            height: SIZE,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: FORMAT,
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+        view_formats: &[],
+    });
+    let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+    let readback_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: None,
+        size: (BYTES_PER_ROW * SIZE) as u64,
+        usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+        mapped_at_creation: false,
+    });
+
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
+    encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+        label: None,
+        color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+            view: &view,
+            resolve_target: None,
+            ops: wgpu::Operations {
+                load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                store: true,
+            },
+        })],
+        depth_stencil_attachment: None,
+    });
+    renderer.render(&mut encoder, &view, camera);
+    encoder.copy_texture_to_buffer(
+        texture.as_image_copy(),
+        wgpu::ImageCopyBuffer {
+            buffer: &readback_buffer,
+            layout: wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(BYTES_PER_ROW),
+                rows_per_image: None,
+            },
+        },
+        texture.size(),
+    );
+    queue.submit(Some(encoder.finish()));
+
+    readback_buffer
+        .slice(..)
+        .map_async(wgpu::MapMode::Read, |result| result.unwrap());
+    device.poll(wgpu::Maintain::Wait);
+    let pixels = readback_buffer.slice(..).get_mapped_range().to_vec();
+    pixels
+}
+
+/// The binary's state, headless with the options of `args`. Falls back to GL where the primary
+/// backends have no adapter.
+fn headless_state(args: &[&str]) -> Option<State> {
+    let options = |backend: &[&str]| {
+        let args = ["headless", "--frames", "1", "--particles", "20000"]
+            .iter()
+            .chain(args)
+            .chain(backend);
+        Options::from_args(args.map(|arg| arg.to_string())).unwrap()
+    };
+    let settings = Settings::default();
+    match State::new(None, &options(&[]), &settings) {
+        Ok(state) => Some(state),
+        Err(AppError::NoAdapter(_)) => State::new(None, &options(&["--backend", "gl"]), &settings)
+            .map_err(|e| eprintln!("{e}"))
+            .ok(),
+        Err(e) => panic!("{e}"),
+    }
+}
+
+fn lit_pixels(pixels: &[u8]) -> usize {
+    pixels
+        .chunks_exact(4)
+        .filter(|pixel| pixel[..3].iter().any(|&channel| channel > 0))
+        .count()
+}
+
+#[test]
+fn one_step_moves_the_particles() {
+    let Some((device, queue)) = device() else {
+        eprintln!("No adapter, skipping the simulation step");
+        return;
+    };
+    let mut renderer = ParticleRenderer::new(device, queue, FORMAT).unwrap();
+    let before = renderer.positions();
+    assert!(!before.is_empty());
+
+    renderer.update(1.0 / 60.0);
+    let after = renderer.positions();
+    assert_eq!(before.len(), after.len());
+
+    let steps = before
+        .iter()
+        .zip(&after)
+        .map(|(before, after)| after.distance(*before))
+        .collect::<Vec<_>>();
+    assert!(
+        after.iter().all(|position| position.is_finite()),
+        "particles went to infinity or NaN"
+    );
+    let moved = steps.iter().filter(|&&step| step > 0.0).count();
+    assert!(
+        moved > before.len() / 2,
+        "only {moved} of {} particles moved",
+        before.len()
+    );
+    let longest = steps.iter().copied().fold(0.0, f32::max);
+    assert!(
+        longest < MAX_STEP,
+        "a particle moved by {longest} in a step"
+    );
+}
+
+#[test]
+fn renders_the_particles_offscreen() {
+    let Some((device, queue)) = device() else {
+        eprintln!("No adapter, skipping the offscreen render");
+        return;
+    };
+    let mut renderer = ParticleRenderer::new(device.clone(), queue.clone(), FORMAT).unwrap();
+    renderer.update(1.0 / 60.0);
+
+    let pixels = render(&device, &queue, &mut renderer, &camera(true));
+    assert!(lit_pixels(&pixels) > 0, "no particle was drawn");
+
+    // Nothing is behind the camera, so nothing should be drawn looking away
+    let pixels = render(&device, &queue, &mut renderer, &camera(false));
+    assert_eq!(lit_pixels(&pixels), 0, "particles drawn behind the camera");
+}
+
+#[test]
+fn renders_pulled_vertices_without_soft_particles() {
+    let Some(mut state) = headless_state(&["--frame-hash", "--vertex-pulling"]) else {
+        eprintln!("No adapter, skipping the vertex pulling frame");
+        return;
+    };
+    // Validation errors panic
+    state.render().unwrap();
+    state.shutdown();
+}
+
+#[test]
+fn writes_a_point_cache_sample_per_frame() {
+    const FRAMES: u32 = 5;
+    let path = std::env::temp_dir().join(format!("particles-test-{}.pc2", std::process::id()));
+    let Some(mut state) =
+        headless_state(&["--frame-hash", "--point-cache", path.to_str().unwrap()])
+    else {
+        eprintln!("No adapter, skipping the point cache");
+        return;
+    };
+    for _ in 0..FRAMES {
+        state.render().unwrap();
+    }
+    // Writes the samples left and their count
+    state.shutdown();
+
+    let cache = std::fs::read(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    let header_i32 =
+        |offset: usize| i32::from_le_bytes(cache[offset..offset + 4].try_into().unwrap());
+    let points = header_i32(16) as usize;
+    assert!(points > 0);
+    assert_eq!(header_i32(28), FRAMES as i32);
+    assert_eq!(cache.len(), 32 + FRAMES as usize * points * 12);
+}